@@ -0,0 +1,288 @@
+//! A stable API for embedding Cackle in other tools. Everything else in this crate is internal and
+//! may change at any time.
+//!
+//! Cackle works by running `cargo build` with itself standing in for rustc, the linker and any
+//! binaries that get run, such as build scripts. When embedded, "itself" is your binary, so
+//! [`handle_subprocess_invocation`] must be called at the very start of your `main` function.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     cackle::analysis::handle_subprocess_invocation()?;
+//!     let workspace = Path::new("/path/to/workspace");
+//!     let config = cackle::analysis::Config::load(workspace, &workspace.join("cackle.toml"))?;
+//!     let report = cackle::analysis::analyze(workspace, &config)?;
+//!     for crate_report in &report.crates {
+//!         for (api, api_report) in &crate_report.apis {
+//!             println!("{} {api} {:?}", crate_report.selector, api_report.status);
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::cli::Args;
+use crate::cli::Cackle;
+use crate::config;
+use crate::config::permissions::PermSel;
+use crate::crate_index::CrateIndex;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A validated `cackle.toml`, together with any baseline and workspace member configs that it
+/// includes.
+#[derive(Debug, Clone)]
+pub struct Config {
+    path: PathBuf,
+    inner: Arc<config::Config>,
+}
+
+/// Builds a workspace and checks what each crate in it and its dependencies does against a config.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    workspace: PathBuf,
+    config: Config,
+    features: Option<Vec<String>>,
+    target: Option<String>,
+}
+
+/// What was observed when a workspace was analysed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Report {
+    /// One entry for each permission selector that was compiled, or that used or was granted at
+    /// least one API, sorted by selector.
+    pub crates: Vec<CrateReport>,
+    /// Problems that were found. Analysis stops at the first error, so if there are errors, then
+    /// not everything will have been analysed.
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CrateReport {
+    /// The permission selector, as used in `cackle.toml`. e.g. `foo`, `foo.build` or `foo.test`.
+    pub selector: String,
+    /// The name of the package.
+    pub package: String,
+    /// Keyed by API name.
+    pub apis: BTreeMap<String, ApiReport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ApiReport {
+    pub status: ApiStatus,
+    /// Where the API was used, sorted by location.
+    pub usages: Vec<ApiUsage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ApiStatus {
+    /// The config permits the usages.
+    Granted,
+    /// At least some of the usages aren't permitted by the config.
+    New,
+    /// The config permits the API, but it wasn't used.
+    Unused,
+}
+
+/// A place where a crate references a name that belongs to an API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ApiUsage {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: Option<u32>,
+    /// The function or variable containing the usage.
+    pub from: String,
+    /// The name that was referenced.
+    pub to: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Problem {
+    pub severity: Severity,
+    /// The package that the problem relates to, if any.
+    pub package: Option<String>,
+    /// A human-readable description of the problem. The format isn't stable.
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Handles the cases where we were invoked by cargo as rustc or the linker, or invoked ourselves in
+/// order to run a binary in a sandbox. In those cases, this doesn't return. Must be called at the
+/// start of `main` by any binary that uses [`analyze`].
+pub fn handle_subprocess_invocation() -> Result<()> {
+    crate::sandbox::handle_helper_invocation();
+    crate::proxy::subprocess::handle_wrapped_binaries()?;
+
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == crate::proxy::subprocess::PROXY_BIN_ARG)
+    {
+        // If we get here and the call to handle_wrapped_binaries above didn't diverge, then either
+        // a user invoked a bin wrapper directly, or we've been invoked when we're already inside a
+        // cackle sandbox. In either case, we just run the original binary directly.
+        crate::cli::invoke_wrapped_binary()?;
+    }
+    Ok(())
+}
+
+/// Builds `workspace` and checks it against `config`. Equivalent to `cargo acl --ci`, so the
+/// workspace is cleaned first in order that everything gets analysed.
+pub fn analyze(workspace: &Path, config: &Config) -> Result<Report> {
+    Checker::new(workspace, config.clone()).analyze()
+}
+
+impl Config {
+    /// Loads and validates the config at `path` for the workspace in the directory `workspace`.
+    pub fn load(workspace: &Path, path: &Path) -> Result<Self> {
+        let features = config::configured_features(path).context(config::InvalidConfig)?;
+        let crate_index = CrateIndex::new(workspace, &features)?;
+        let inner = config::parse_file(path, &crate_index).context(config::InvalidConfig)?;
+        Ok(Self {
+            path: path.to_owned(),
+            inner,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the names of all APIs that are defined, including those imported from the standard
+    /// library.
+    pub fn apis(&self) -> Vec<String> {
+        self.inner
+            .raw
+            .apis
+            .keys()
+            .map(|api| api.to_string())
+            .collect()
+    }
+
+    /// Returns the APIs that the crate with `selector` is permitted to use. e.g. `foo` or
+    /// `foo.build`.
+    pub fn allowed_apis(&self, selector: &str) -> Vec<String> {
+        let Ok(perm_sel) = selector.parse::<PermSel>() else {
+            return Vec::new();
+        };
+        let mut apis: Vec<String> = self
+            .inner
+            .permissions
+            .get(&perm_sel)
+            .map(|pkg_config| {
+                pkg_config
+                    .allow_apis
+                    .iter()
+                    .map(|api| api.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        apis.sort();
+        apis
+    }
+}
+
+impl Checker {
+    pub fn new(workspace: &Path, config: Config) -> Self {
+        Self {
+            workspace: workspace.to_owned(),
+            config,
+            features: None,
+            target: None,
+        }
+    }
+
+    /// Sets the features to build with, overriding `common.features` in the config.
+    pub fn features(mut self, features: Vec<String>) -> Self {
+        self.features = Some(features);
+        self
+    }
+
+    /// Sets the target to build for. e.g. "x86_64-unknown-linux-gnu".
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_owned());
+        self
+    }
+
+    /// Builds the workspace and checks it. Nothing is printed, other than output from cargo.
+    pub fn analyze(&self) -> Result<Report> {
+        let args = Args {
+            path: Some(self.workspace.clone()),
+            cackle_path: Some(self.config.path.clone()),
+            features: self.features.as_ref().map(|features| features.join(",")),
+            target: self.target.clone(),
+            ci: true,
+            quiet: true,
+            colour: crate::colour::Colour::Never,
+            output_format: crate::sarif::OutputFormat::Json,
+            ..Args::default()
+        };
+        let (abort_sender, abort_recv) = std::sync::mpsc::channel();
+        Cackle::new(args, abort_sender)?.analyze(abort_recv)
+    }
+}
+
+impl From<crate::problem::Severity> for Severity {
+    fn from(severity: crate::problem::Severity) -> Self {
+        match severity {
+            crate::problem::Severity::Info => Severity::Info,
+            crate::problem::Severity::Warning => Severity::Warning,
+            crate::problem::Severity::Error => Severity::Error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiStatus;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::ApiUsages;
+    use crate::problem::Problem;
+    use crate::problem::Severity;
+    use crate::report::Report;
+
+    #[test]
+    fn report_conversion() {
+        let mut report = Report::default();
+        report.api_used(
+            &ApiUsages {
+                pkg_id: pkg_id("crab1"),
+                scope: PermissionScope::Build,
+                api_name: ApiName::from("fs"),
+                usages: Vec::new(),
+                severity: Severity::Error,
+            },
+            false,
+        );
+        report.api_unused(&PermSel::for_primary("crab2"), &ApiName::from("net"));
+        let problem = Problem::UsesBuildScript(pkg_id("crab1"));
+        let report = report.into_analysis(&[(Severity::Warning, &problem)]);
+        assert_eq!(report.crates.len(), 2);
+        assert_eq!(report.crates[0].selector, "crab1.build");
+        assert_eq!(report.crates[0].package, "crab1");
+        assert_eq!(report.crates[0].apis["fs"].status, ApiStatus::New);
+        assert_eq!(report.crates[1].apis["net"].status, ApiStatus::Unused);
+        assert_eq!(report.problems[0].severity, super::Severity::Warning);
+        assert_eq!(report.problems[0].package.as_deref(), Some("crab1"));
+    }
+}
@@ -0,0 +1,326 @@
+//! Signed records of a reviewer having granted a package its permissions. Attestations are written
+//! as TOML alongside an SSH signature made with `ssh-keygen -Y sign`, so that they can be committed
+//! and verified on later runs against an allowed signers file, in the same way that git verifies
+//! SSH-signed commits.
+
+use crate::config::ApiName;
+use crate::config::Config;
+use crate::crate_index::PackageId;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use cargo_metadata::semver::Version;
+use clap::Parser;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+/// The namespace in which signatures are made, so that signatures made for other purposes, e.g.
+/// git commits, can't be passed off as attestations.
+const NAMESPACE: &str = "cackle-attestation";
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct AttestOptions {
+    /// Packages to attest, e.g. `foo`, or `rand@0.8` if there are multiple versions.
+    #[clap(required = true)]
+    pub(crate) packages: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Attestation {
+    pub(crate) package: String,
+    pub(crate) version: Version,
+    pub(crate) rlib_sha256: String,
+    pub(crate) reviewer: String,
+    /// What the package was granted, keyed by selector, e.g. `foo` or `foo.build`.
+    #[serde(default)]
+    pub(crate) permissions: BTreeMap<String, AttestedPermissions>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AttestedPermissions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) allow_apis: Vec<ApiName>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) allow_unsafe: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) allow_proc_macro: bool,
+}
+
+impl Attestation {
+    pub(crate) fn new(
+        pkg_id: &PackageId,
+        rlib_sha256: String,
+        reviewer: String,
+        config: &Config,
+    ) -> Self {
+        Self {
+            package: pkg_id.name_str().to_owned(),
+            version: pkg_id.version().clone(),
+            rlib_sha256,
+            reviewer,
+            permissions: granted_permissions(pkg_id, config),
+        }
+    }
+
+    /// Returns whether what `config` grants `pkg_id` differs from what was attested.
+    pub(crate) fn permissions_changed(&self, pkg_id: &PackageId, config: &Config) -> bool {
+        granted_permissions(pkg_id, config) != self.permissions
+    }
+}
+
+/// Returns the permissions that `config` grants to `pkg_id`, keyed by selector.
+pub(crate) fn granted_permissions(
+    pkg_id: &PackageId,
+    config: &Config,
+) -> BTreeMap<String, AttestedPermissions> {
+    config
+        .permissions_no_inheritance
+        .packages
+        .iter()
+        .filter(|(perm_sel, _)| pkg_id.is_selected_by(&perm_sel.package_name))
+        .map(|(perm_sel, pkg_config)| {
+            let mut allow_apis = pkg_config.allow_apis.clone();
+            allow_apis.sort();
+            (
+                perm_sel.to_string(),
+                AttestedPermissions {
+                    allow_apis,
+                    allow_unsafe: pkg_config.allow_unsafe,
+                    allow_proc_macro: pkg_config.allow_proc_macro,
+                },
+            )
+        })
+        .filter(|(_, permissions)| *permissions != AttestedPermissions::default())
+        .collect()
+}
+
+/// Returns the path of the attestation for `pkg_id` within `dir`. The signature is in the same
+/// place with `.sig` appended.
+pub(crate) fn path(dir: &Path, pkg_id: &PackageId) -> PathBuf {
+    dir.join(format!("{}-{}.toml", pkg_id.name_str(), pkg_id.version()))
+}
+
+fn signature_path(path: &Path) -> PathBuf {
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".sig");
+    PathBuf::from(signature_path)
+}
+
+/// Reads the attestation at `path`, if there is one.
+pub(crate) fn read(path: &Path) -> Result<Option<Attestation>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = crate::fs::read_to_string(path)?;
+    let attestation = toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+    Ok(Some(attestation))
+}
+
+/// Checks that the attestation at `path` was signed by its reviewer according to
+/// `allowed_signers`.
+pub(crate) fn verify(path: &Path, attestation: &Attestation, allowed_signers: &Path) -> Result<()> {
+    if !allowed_signers.exists() {
+        bail!("`{}` doesn't exist", allowed_signers.display());
+    }
+    let signature_path = signature_path(path);
+    if !signature_path.exists() {
+        bail!("`{}` doesn't exist", signature_path.display());
+    }
+    let mut child = Command::new("ssh-keygen")
+        .args([
+            "-Y",
+            "verify",
+            "-n",
+            NAMESPACE,
+            "-I",
+            &attestation.reviewer,
+            "-f",
+        ])
+        .arg(allowed_signers)
+        .arg("-s")
+        .arg(&signature_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run ssh-keygen")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&std::fs::read(path)?)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Writes `attestation` to `path` and signs it with `signing_key`.
+pub(crate) fn write_signed(
+    path: &Path,
+    attestation: &Attestation,
+    signing_key: &Path,
+) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    }
+    crate::fs::write_atomic(path, &toml::to_string(attestation)?)?;
+    let signature_path = signature_path(path);
+    // ssh-keygen asks before overwriting an existing signature.
+    if signature_path.exists() {
+        std::fs::remove_file(&signature_path)
+            .with_context(|| format!("Failed to remove `{}`", signature_path.display()))?;
+    }
+    let output = Command::new("ssh-keygen")
+        .args(["-Y", "sign", "-n", NAMESPACE, "-f"])
+        .arg(signing_key)
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run ssh-keygen")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to sign `{}`: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Returns the reviewer identity to record when none is configured, which is the email address
+/// that git is configured to use in `dir`.
+pub(crate) fn default_reviewer(dir: &Path) -> Result<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["config", "user.email"])
+        .output()
+        .context("Failed to run git")?;
+    let email = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if email.is_empty() {
+        bail!("`attestations.reviewer` isn't set and git doesn't have a `user.email`");
+    }
+    Ok(email)
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    /// Generates a signing key in `dir` for `me@example.com` and an allowed signers file that trusts
+    /// it. Returns the paths of the key and the allowed signers file, or None if ssh-keygen isn't
+    /// available.
+    pub(crate) fn signing_key(dir: &Path) -> Option<(PathBuf, PathBuf)> {
+        let key = dir.join("key");
+        let status = Command::new("ssh-keygen")
+            .args([
+                "-q",
+                "-t",
+                "ed25519",
+                "-N",
+                "",
+                "-C",
+                "me@example.com",
+                "-f",
+            ])
+            .arg(&key)
+            .status();
+        if !status.is_ok_and(|status| status.success()) {
+            return None;
+        }
+        let public_key = std::fs::read_to_string(dir.join("key.pub")).unwrap();
+        let allowed_signers = dir.join("allowed_signers");
+        std::fs::write(&allowed_signers, format!("me@example.com {public_key}")).unwrap();
+        Some((key, allowed_signers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Attestation;
+    use crate::config::testing::parse;
+    use crate::crate_index::testing::pkg_id;
+    use std::path::Path;
+
+    #[test]
+    fn permissions() {
+        let config = parse(
+            r#"
+            [api.fs]
+            [api.net]
+            [pkg.crab1]
+            allow_apis = ["net", "fs"]
+            [pkg.crab1.build]
+            allow_unsafe = true
+            [pkg.crab2]
+            allow_apis = ["net"]
+            "#,
+        )
+        .unwrap();
+        let crab1 = pkg_id("crab1");
+        let attestation = Attestation::new(&crab1, "abc".to_owned(), "me".to_owned(), &config);
+        assert_eq!(
+            toml::to_string(&attestation).unwrap(),
+            indoc::indoc! {r#"
+                package = "crab1"
+                version = "0.0.0"
+                rlib_sha256 = "abc"
+                reviewer = "me"
+
+                [permissions.crab1]
+                allow_apis = ["fs", "net"]
+
+                [permissions."crab1.build"]
+                allow_unsafe = true
+            "#}
+        );
+        assert!(!attestation.permissions_changed(&crab1, &config));
+        let config = parse(
+            r#"
+            [api.fs]
+            [api.net]
+            [pkg.crab1]
+            allow_apis = ["net"]
+            "#,
+        )
+        .unwrap();
+        assert!(attestation.permissions_changed(&crab1, &config));
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let Some((key, allowed_signers)) = super::testing::signing_key(dir.path()) else {
+            return;
+        };
+
+        let config = parse("").unwrap();
+        let crab1 = pkg_id("crab1");
+        let attestation = Attestation::new(
+            &crab1,
+            "abc".to_owned(),
+            "me@example.com".to_owned(),
+            &config,
+        );
+        let path = super::path(&dir.path().join("attestations"), &crab1);
+        super::write_signed(&path, &attestation, &key).unwrap();
+        assert_eq!(super::read(&path).unwrap(), Some(attestation.clone()));
+        super::verify(&path, &attestation, &allowed_signers).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, contents.replace("abc", "def")).unwrap();
+        assert!(super::verify(&path, &attestation, &allowed_signers).is_err());
+        assert!(super::verify(&path, &attestation, Path::new("/does/not/exist")).is_err());
+    }
+}
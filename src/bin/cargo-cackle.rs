@@ -0,0 +1,18 @@
+//! Lets Cackle be invoked as `cargo cackle`. Cargo runs us as `cargo-cackle cackle <args>`, so we
+//! just replace ourselves with `cargo-acl`, which accepts `cackle` as an alias for `acl`.
+
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+fn main() {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe.with_file_name("cargo-acl"),
+        Err(error) => {
+            eprintln!("Failed to determine path to cargo-cackle: {error}");
+            std::process::exit(1);
+        }
+    };
+    let error = Command::new(&exe).args(std::env::args_os().skip(1)).exec();
+    eprintln!("Failed to run `{}`: {error}", exe.display());
+    std::process::exit(1);
+}
@@ -0,0 +1,143 @@
+//! Output from cargo and whatever it runs. While the full terminal UI is active, this output is held
+//! back from the terminal, so we also keep a copy that the UI can show.
+
+use crate::events::AppEvent;
+use std::collections::VecDeque;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// The maximum number of lines that we keep. Older lines are discarded.
+const MAX_LINES: usize = 10_000;
+
+#[derive(Default)]
+pub(crate) struct BuildOutput {
+    /// Complete lines of output with terminal escape sequences removed, oldest first.
+    lines: VecDeque<String>,
+    /// The total number of lines received, including any that have been discarded.
+    total_lines: usize,
+    /// If set, sent `AppEvent::ProgressChanged` each time lines are added.
+    listener: Option<Sender<AppEvent>>,
+}
+
+/// Splits the output of a single stream, such as cargo's stderr, into lines and adds them to a
+/// shared `BuildOutput`.
+pub(crate) struct StreamCapture {
+    output: Arc<Mutex<BuildOutput>>,
+    /// Output received since the last newline.
+    partial: Vec<u8>,
+}
+
+impl BuildOutput {
+    fn add_lines(&mut self, lines: Vec<String>) {
+        if lines.is_empty() {
+            return;
+        }
+        self.total_lines += lines.len();
+        self.lines.extend(lines);
+        if self.lines.len() > MAX_LINES {
+            self.lines.drain(..self.lines.len() - MAX_LINES);
+        }
+        if let Some(listener) = &self.listener {
+            let _ = listener.send(AppEvent::ProgressChanged);
+        }
+    }
+
+    pub(crate) fn lines(&self) -> &VecDeque<String> {
+        &self.lines
+    }
+
+    /// Returns the number of lines received so far. Unlike `lines().len()`, this keeps increasing
+    /// once old lines start being discarded, so it can be used to tell when there's new output.
+    pub(crate) fn total_lines(&self) -> usize {
+        self.total_lines
+    }
+
+    /// Arranges for `listener` to be notified whenever output is added.
+    pub(crate) fn set_listener(&mut self, listener: Sender<AppEvent>) {
+        self.listener = Some(listener);
+    }
+}
+
+impl StreamCapture {
+    pub(crate) fn new(output: Arc<Mutex<BuildOutput>>) -> Self {
+        Self {
+            output,
+            partial: Vec::new(),
+        }
+    }
+
+    pub(crate) fn append(&mut self, mut bytes: &[u8]) {
+        let mut lines = Vec::new();
+        while let Some(newline) = bytes.iter().position(|b| *b == b'\n') {
+            self.partial.extend_from_slice(&bytes[..newline]);
+            lines.push(strip_escapes(&String::from_utf8_lossy(&self.partial)));
+            self.partial.clear();
+            bytes = &bytes[newline + 1..];
+        }
+        self.partial.extend_from_slice(bytes);
+        self.output.lock().unwrap().add_lines(lines);
+    }
+}
+
+/// Removes terminal escape sequences, such as those that set colours, and carriage returns.
+fn strip_escapes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => {
+                // A control sequence is `ESC [` then parameters, ending with a byte in the range
+                // `@` to `~`. Other escapes are just `ESC` and one more character.
+                if chars.next() == Some('[') {
+                    for ch in chars.by_ref() {
+                        if ('@'..='~').contains(&ch) {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\r' => {}
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BuildOutput;
+    use super::StreamCapture;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn append() {
+        let output = Arc::new(Mutex::new(BuildOutput::default()));
+        let mut stdout = StreamCapture::new(output.clone());
+        let mut stderr = StreamCapture::new(output.clone());
+        stderr.append(b"\x1b[1m\x1b[92m   Compiling\x1b[0m foo\r\nwar");
+        stdout.append(b"running 1 test");
+        stderr.append(b"ning: unused\n");
+        stdout.append(b"\n");
+        let output = output.lock().unwrap();
+        assert_eq!(
+            output.lines(),
+            &["   Compiling foo", "warning: unused", "running 1 test"]
+        );
+        assert_eq!(output.total_lines(), 3);
+    }
+
+    #[test]
+    fn discards_old_lines() {
+        let output = Arc::new(Mutex::new(BuildOutput::default()));
+        let mut capture = StreamCapture::new(output.clone());
+        for i in 0..super::MAX_LINES + 5 {
+            capture.append(format!("{i}\n").as_bytes());
+        }
+        let output = output.lock().unwrap();
+        assert_eq!(output.lines().len(), super::MAX_LINES);
+        assert_eq!(output.lines()[0], "5");
+        assert_eq!(output.total_lines(), super::MAX_LINES + 5);
+    }
+}
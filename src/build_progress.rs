@@ -0,0 +1,177 @@
+//! Tracks what the build is currently doing, based on progress updates sent by our proxy
+//! subprocesses. Used by the UI to show which crates are being compiled while the build runs.
+
+use crate::crate_index::CrateSel;
+use crate::crate_index::PackageId;
+use crate::events::AppEvent;
+use crate::proxy::rpc::BuildPhase;
+use crate::proxy::rpc::ProgressUpdate;
+use fxhash::FxHashSet;
+use std::fmt::Display;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use std::time::Instant;
+
+#[derive(Default)]
+pub(crate) struct BuildProgress {
+    /// Crates that are currently being worked on, oldest first.
+    active: Vec<Activity>,
+    /// The number of crates that have finished compiling.
+    compiled: usize,
+    /// Packages for which at least one crate has finished compiling.
+    compiled_packages: FxHashSet<PackageId>,
+    /// When we received the first update.
+    started: Option<Instant>,
+    /// Incremented each time something changes. Lets the UI know when it needs to redraw.
+    generation: u64,
+    /// If set, sent `AppEvent::ProgressChanged` each time something changes.
+    listener: Option<Sender<AppEvent>>,
+}
+
+struct Activity {
+    crate_sel: CrateSel,
+    phase: BuildPhase,
+    percent: Option<u8>,
+    started: Instant,
+}
+
+impl BuildProgress {
+    pub(crate) fn update(&mut self, update: &ProgressUpdate) {
+        self.started.get_or_insert_with(Instant::now);
+        let existing = self
+            .active
+            .iter()
+            .position(|activity| activity.crate_sel == update.crate_sel);
+        if update.phase == BuildPhase::Finished {
+            if let Some(index) = existing {
+                let activity = self.active.remove(index);
+                if activity.phase != BuildPhase::Running {
+                    self.compiled += 1;
+                    self.compiled_packages
+                        .insert(activity.crate_sel.pkg_id().clone());
+                }
+            }
+        } else if let Some(index) = existing {
+            let activity = &mut self.active[index];
+            if update.phase < activity.phase {
+                // The linker is invoked by rustc, so the rustc wrapper may continue to report that
+                // it's compiling after we've been told that linking has started.
+                return;
+            }
+            if activity.phase != update.phase {
+                activity.started = Instant::now();
+            }
+            activity.phase = update.phase;
+            activity.percent = update.percent;
+        } else {
+            self.active.push(Activity {
+                crate_sel: update.crate_sel.clone(),
+                phase: update.phase,
+                percent: update.percent,
+                started: Instant::now(),
+            });
+        }
+        self.generation += 1;
+        if let Some(listener) = &self.listener {
+            let _ = listener.send(AppEvent::ProgressChanged);
+        }
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the number of packages for which at least one crate has finished compiling.
+    pub(crate) fn packages_compiled(&self) -> usize {
+        self.compiled_packages.len()
+    }
+
+    /// Returns the number of crates that are currently being compiled, linked or run.
+    pub(crate) fn num_active(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Returns how long it's been since the build started, or None if it hasn't started yet.
+    pub(crate) fn elapsed(&self) -> Option<Duration> {
+        self.started.map(|started| started.elapsed())
+    }
+
+    /// Arranges for `listener` to be notified whenever the progress changes.
+    pub(crate) fn set_listener(&mut self, listener: Sender<AppEvent>) {
+        self.listener = Some(listener);
+    }
+}
+
+impl Display for BuildProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.active.is_empty() && self.compiled == 0 {
+            return write!(f, "Build in progress...");
+        }
+        writeln!(f, "Crates compiled: {}", self.compiled)?;
+        for activity in &self.active {
+            write!(
+                f,
+                "{} {} ({}s)",
+                activity.phase,
+                activity.crate_sel,
+                activity.started.elapsed().as_secs()
+            )?;
+            if let Some(percent) = activity.percent {
+                write!(f, " {percent}%")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for BuildPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildPhase::Compiling => write!(f, "Compiling"),
+            BuildPhase::Linking => write!(f, "Linking"),
+            BuildPhase::Running => write!(f, "Running"),
+            BuildPhase::Finished => write!(f, "Finished"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BuildProgress;
+    use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::CrateSel;
+    use crate::proxy::rpc::BuildPhase;
+    use crate::proxy::rpc::ProgressUpdate;
+
+    fn update(name: &str, phase: BuildPhase, percent: Option<u8>) -> ProgressUpdate {
+        ProgressUpdate {
+            crate_sel: CrateSel::primary(pkg_id(name)),
+            phase,
+            percent,
+        }
+    }
+
+    #[test]
+    fn progress() {
+        let mut progress = BuildProgress::default();
+        assert_eq!(progress.to_string(), "Build in progress...");
+        assert!(progress.elapsed().is_none());
+        let (sender, receiver) = std::sync::mpsc::channel();
+        progress.set_listener(sender);
+        progress.update(&update("foo", BuildPhase::Compiling, None));
+        progress.update(&update("bar", BuildPhase::Compiling, None));
+        progress.update(&update("foo", BuildPhase::Linking, Some(50)));
+        progress.update(&update("foo", BuildPhase::Compiling, None));
+        progress.update(&update("bar", BuildPhase::Finished, None));
+        assert_eq!(progress.generation(), 4);
+        assert_eq!(progress.packages_compiled(), 1);
+        assert_eq!(progress.num_active(), 1);
+        assert!(progress.elapsed().is_some());
+        assert_eq!(receiver.try_iter().count(), 4);
+        assert_eq!(
+            progress.to_string(),
+            "Crates compiled: 1\nLinking foo (0s) 50%\n"
+        );
+    }
+}
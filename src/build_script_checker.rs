@@ -2,8 +2,11 @@ use crate::config::permissions::PermSel;
 use crate::config::Config;
 use crate::crate_index::PackageId;
 use crate::problem::DisallowedBuildInstruction;
+use crate::problem::DisallowedLinkLib;
+use crate::problem::DisallowedRustcDirective;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
+use crate::problem::RustcDirectiveKind;
 use crate::proxy::rpc::BinExecutionOutput;
 use anyhow::Result;
 
@@ -20,12 +23,25 @@ impl BuildScriptReport {
     ) -> Result<BuildScriptReport> {
         let mut report = BuildScriptReport::default();
         let crate_sel = &outputs.crate_sel;
-        let perm_sel = PermSel::for_build_script(crate_sel.pkg_name());
+        let perm_sel = PermSel::for_build_script(crate_sel.pkg_id.perm_name());
         let allow_build_instructions = config
             .permissions
             .get(&perm_sel)
             .map(|cfg| cfg.allow_build_instructions.as_slice())
             .unwrap_or(&[]);
+        // Native libraries, cfgs and env vars all affect the package itself, not its build script,
+        // so they're granted at the package level.
+        let pkg_config = config
+            .permissions
+            .get(&PermSel::for_primary(crate_sel.pkg_id.perm_name()));
+        let allow_link_libs = pkg_config
+            .map(|cfg| cfg.allow_link_libs.as_slice())
+            .unwrap_or(&[]);
+        let allow_rustc_env = pkg_config
+            .map(|cfg| cfg.allow_rustc_env.as_slice())
+            .unwrap_or(&[]);
+        // If no cfgs are listed, then any are permitted.
+        let allow_rustc_cfg = pkg_config.and_then(|cfg| cfg.allow_rustc_cfg.as_deref());
         let Ok(stdout) = std::str::from_utf8(&outputs.stdout) else {
             report.problems.push(Problem::new(format!(
                 "The build script `{}` emitted invalid UTF-8",
@@ -34,6 +50,43 @@ impl BuildScriptReport {
             return Ok(report);
         };
         for line in stdout.lines() {
+            if let Some(lib) = link_lib_name(line) {
+                if !allow_link_libs.iter().any(|allowed| allowed == lib)
+                    && !allow_build_instructions.iter().any(|i| matches(line, i))
+                {
+                    report
+                        .problems
+                        .push(Problem::DisallowedLinkLib(DisallowedLinkLib {
+                            pkg_id: crate_sel.pkg_id.clone(),
+                            lib: lib.to_owned(),
+                            instruction: line.to_owned(),
+                        }));
+                }
+                continue;
+            }
+            if let Some((kind, name)) = rustc_directive(line) {
+                let permitted = match kind {
+                    RustcDirectiveKind::Env => {
+                        allow_rustc_env.iter().any(|rule| matches(name, rule))
+                    }
+                    RustcDirectiveKind::Cfg => allow_rustc_cfg
+                        .map_or(true, |rules| rules.iter().any(|rule| matches(name, rule))),
+                };
+                if !permitted && !allow_build_instructions.iter().any(|i| matches(line, i)) {
+                    report.problems.push(Problem::DisallowedRustcDirective(
+                        DisallowedRustcDirective {
+                            pkg_id: crate_sel.pkg_id.clone(),
+                            kind,
+                            name: name.to_owned(),
+                            instruction: line.to_owned(),
+                        },
+                    ));
+                }
+                if kind == RustcDirectiveKind::Env {
+                    report.env_vars.push(name.to_owned());
+                }
+                continue;
+            }
             if line.starts_with("cargo:") {
                 report.problems.merge(check_directive(
                     line,
@@ -41,11 +94,6 @@ impl BuildScriptReport {
                     allow_build_instructions,
                 ));
             }
-            if let Some(rest) = line.strip_prefix("cargo:rustc-env=") {
-                if let Some((var_name, _value)) = rest.split_once('=') {
-                    report.env_vars.push(var_name.to_owned());
-                }
-            }
         }
         Ok(report)
     }
@@ -53,12 +101,7 @@ impl BuildScriptReport {
 
 /// Cargo instructions that should be harmless, so would just add noise if we were required to
 /// explicitly allow them.
-const ALWAYS_PERMITTED: &[&str] = &[
-    "cargo:rerun-if-",
-    "cargo:warning",
-    "cargo:rustc-cfg=",
-    "cargo:rustc-check-cfg=",
-];
+const ALWAYS_PERMITTED: &[&str] = &["cargo:rerun-if-", "cargo:warning", "cargo:rustc-check-cfg="];
 
 fn check_directive(
     instruction: &str,
@@ -84,6 +127,37 @@ fn check_directive(
     .into()
 }
 
+/// If `line` is a `cargo:rustc-link-lib` instruction, returns the name of the library that it links.
+/// The instruction has the form `cargo:rustc-link-lib=[KIND[:MODIFIERS]=]NAME[:RENAME]`.
+fn link_lib_name(line: &str) -> Option<&str> {
+    let value = directive_value(line, "rustc-link-lib")?;
+    let name = value.rsplit_once('=').map_or(value, |(_, name)| name);
+    let name = name.split_once(':').map_or(name, |(name, _)| name);
+    Some(name)
+}
+
+/// If `line` is a `cargo:rustc-env` or `cargo:rustc-cfg` instruction, returns which it is together
+/// with the name of the variable or cfg that it sets. For `cargo:rustc-env=VAR=VALUE` the name is
+/// `VAR` and for `cargo:rustc-cfg=KEY[="VALUE"]` it's `KEY`.
+fn rustc_directive(line: &str) -> Option<(RustcDirectiveKind, &str)> {
+    if let Some(value) = directive_value(line, "rustc-env") {
+        let (name, _value) = value.split_once('=')?;
+        return Some((RustcDirectiveKind::Env, name));
+    }
+    let value = directive_value(line, "rustc-cfg")?;
+    let name = value.split_once('=').map_or(value, |(name, _)| name);
+    Some((RustcDirectiveKind::Cfg, name))
+}
+
+/// Returns the value of `line` if it's the cargo instruction `key`, using either the `cargo:` or the
+/// `cargo::` syntax.
+fn directive_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let rest = line
+        .strip_prefix("cargo::")
+        .or_else(|| line.strip_prefix("cargo:"))?;
+    rest.strip_prefix(key)?.strip_prefix('=')
+}
+
 fn matches(instruction: &str, rule: &str) -> bool {
     if let Some(prefix) = rule.strip_suffix('*') {
         instruction.starts_with(prefix)
@@ -99,8 +173,11 @@ mod tests {
     use crate::crate_index::testing::pkg_id;
     use crate::crate_index::CrateSel;
     use crate::problem::DisallowedBuildInstruction;
+    use crate::problem::DisallowedLinkLib;
+    use crate::problem::DisallowedRustcDirective;
     use crate::problem::Problem;
     use crate::problem::ProblemList;
+    use crate::problem::RustcDirectiveKind;
     use crate::proxy::rpc::BinExecutionOutput;
     use std::path::PathBuf;
 
@@ -115,6 +192,10 @@ mod tests {
             sandbox_config: SandboxConfig::default(),
             binary_path: PathBuf::new(),
             sandbox_config_display: None,
+            network_requests: Vec::new(),
+            denied_accesses: Vec::new(),
+            limit_exceeded: None,
+            captured_writes: Vec::new(),
         };
         super::BuildScriptReport::build(&outputs, &config)
             .unwrap()
@@ -165,4 +246,121 @@ mod tests {
             ProblemList::default()
         );
     }
+
+    #[test]
+    fn test_link_lib() {
+        let disallowed = |lib: &str, instruction: &str| -> ProblemList {
+            Problem::DisallowedLinkLib(DisallowedLinkLib {
+                pkg_id: pkg_id("my_pkg"),
+                lib: lib.to_owned(),
+                instruction: instruction.to_owned(),
+            })
+            .into()
+        };
+        assert_eq!(
+            check("cargo:rustc-link-lib=static:+whole-archive=ssl", ""),
+            disallowed("ssl", "cargo:rustc-link-lib=static:+whole-archive=ssl")
+        );
+        assert_eq!(
+            check("cargo::rustc-link-lib=foo:bar", ""),
+            disallowed("foo", "cargo::rustc-link-lib=foo:bar")
+        );
+        let config = r#"
+            [pkg.my_pkg]
+            allow_link_libs = ["ssl"]
+        "#;
+        assert_eq!(
+            check("cargo:rustc-link-lib=dylib=ssl", config),
+            ProblemList::default()
+        );
+        assert_eq!(
+            check("cargo:rustc-link-lib=crypto", config),
+            disallowed("crypto", "cargo:rustc-link-lib=crypto")
+        );
+        // Instructions that were previously allowed via `allow_build_instructions` remain allowed.
+        assert_eq!(
+            check(
+                "cargo:rustc-link-lib=crypto",
+                r#"
+                [pkg.my_pkg.build]
+                allow_build_instructions = [ "cargo:rustc-link-*" ]
+                "#
+            ),
+            ProblemList::default()
+        );
+    }
+
+    #[test]
+    fn test_rustc_env() {
+        let disallowed = |name: &str, instruction: &str| -> ProblemList {
+            Problem::DisallowedRustcDirective(DisallowedRustcDirective {
+                pkg_id: pkg_id("my_pkg"),
+                kind: RustcDirectiveKind::Env,
+                name: name.to_owned(),
+                instruction: instruction.to_owned(),
+            })
+            .into()
+        };
+        assert_eq!(
+            check("cargo:rustc-env=GIT_HASH=abc", ""),
+            disallowed("GIT_HASH", "cargo:rustc-env=GIT_HASH=abc")
+        );
+        assert_eq!(
+            check("cargo::rustc-env=OUT=x=y", ""),
+            disallowed("OUT", "cargo::rustc-env=OUT=x=y")
+        );
+        let config = r#"
+            [pkg.my_pkg]
+            allow_rustc_env = ["GIT_HASH", "BUILD_*"]
+        "#;
+        assert_eq!(
+            check("cargo:rustc-env=GIT_HASH=abc", config),
+            ProblemList::default()
+        );
+        assert_eq!(
+            check("cargo:rustc-env=BUILD_TIME=123", config),
+            ProblemList::default()
+        );
+        assert_eq!(
+            check("cargo:rustc-env=PATH=/bin", config),
+            disallowed("PATH", "cargo:rustc-env=PATH=/bin")
+        );
+        assert_eq!(
+            check(
+                "cargo:rustc-env=PATH=/bin",
+                r#"
+                [pkg.my_pkg.build]
+                allow_build_instructions = [ "cargo:rustc-env=*" ]
+                "#
+            ),
+            ProblemList::default()
+        );
+    }
+
+    #[test]
+    fn test_rustc_cfg() {
+        // Without an allowlist, any cfg is permitted.
+        assert_eq!(
+            check("cargo:rustc-cfg=has_foo\ncargo::rustc-cfg=bar=\"x\"", ""),
+            ProblemList::default()
+        );
+        let config = r#"
+            [pkg.my_pkg]
+            allow_rustc_cfg = ["has_foo"]
+        "#;
+        assert_eq!(
+            check("cargo:rustc-cfg=has_foo", config),
+            ProblemList::default()
+        );
+        assert_eq!(
+            check("cargo:rustc-cfg=feature=\"evil\"", config),
+            Problem::DisallowedRustcDirective(DisallowedRustcDirective {
+                pkg_id: pkg_id("my_pkg"),
+                kind: RustcDirectiveKind::Cfg,
+                name: "feature".to_owned(),
+                instruction: "cargo:rustc-cfg=feature=\"evil\"".to_owned(),
+            })
+            .into()
+        );
+    }
 }
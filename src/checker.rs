@@ -1,32 +1,54 @@
+use self::proc_macro_attribution::ContainingFunction;
+use crate::attestation;
+use crate::build_output::BuildOutput;
+use crate::build_progress::BuildProgress;
 use crate::build_script_checker;
+use crate::cli::Args;
+use crate::cli::CheckState;
 use crate::config::permissions::PermSel;
 use crate::config::permissions::PermissionScope;
+use crate::config::permissions::Permissions;
+use crate::config::ApiMode;
 use crate::config::ApiName;
 use crate::config::Config;
+use crate::config::PackageName;
+use crate::config::ProcMacroAttribution;
+use crate::config::SandboxKind;
 use crate::crate_index::CrateIndex;
 use crate::crate_index::CrateKind;
 use crate::crate_index::PackageId;
+use crate::crate_index::RUST_STD_PKG_NAME;
+use crate::graph::Graph;
 use crate::link_info::LinkInfo;
 use crate::location::SourceLocation;
 use crate::names::Name;
 use crate::names::SymbolOrDebugName;
+use crate::problem::AdvisoryWithSensitiveApis;
 use crate::problem::ApiUsages;
+use crate::problem::FfiExport;
 use crate::problem::OffTreeApiUsage;
 use crate::problem::PossibleExportedApi;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
+use crate::problem::SandboxDegraded;
+use crate::problem::Severity;
+use crate::problem::StaleAttestation;
+use crate::problem::StaleAttestationReason;
+use crate::problem::UndeclaredApis;
 use crate::problem::UnusedAllowApi;
+use crate::problem::VersionMismatch;
 use crate::proxy::cargo::profile_name;
 use crate::proxy::rpc;
 use crate::proxy::rpc::UnsafeUsage;
 use crate::proxy::subprocess::SubprocessConfig;
+use crate::report::Report;
+use crate::rust_std::RustStd;
+use crate::sarif::OutputFormat;
 use crate::symbol_graph::backtrace::Backtracer;
 use crate::symbol_graph::NameSource;
 use crate::symbol_graph::UsageDebugData;
 use crate::timing::TimingCollector;
 use crate::tmpdir::TempDir;
-use crate::Args;
-use crate::CheckState;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
@@ -38,9 +60,11 @@ use std::borrow::Cow;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 mod api_map;
 pub(crate) mod common_prefix;
+pub(crate) mod proc_macro_attribution;
 
 pub(crate) struct Checker {
     /// For each name, the set of APIs active for that name and all names that have this name as a
@@ -53,7 +77,7 @@ pub(crate) struct Checker {
     tmpdir: Arc<TempDir>,
     pub(crate) args: Arc<Args>,
     pub(crate) crate_index: Arc<CrateIndex>,
-    pub(crate) sysroot: Arc<Path>,
+    pub(crate) rust_std: RustStd,
 
     /// Mapping from Rust source paths to the packages that contains them. Generally a source path
     /// will map to a single package, but in rare cases multiple packages could reference the same
@@ -64,10 +88,49 @@ pub(crate) struct Checker {
 
     backtracers: FxHashMap<Arc<Path>, Backtracer>,
 
+    /// The graph of references that we've observed. Only populated when we've been asked to print
+    /// it.
+    pub(crate) graph: Option<Graph>,
+
+    /// What we've observed for `--output-format json`, the `report` and `diff` subcommands and
+    /// `summary --table`. Only populated when one of those was requested.
+    pub(crate) report: Option<Report>,
+
+    /// Permissions granted by the baseline written by `cargo acl baseline`. These are already merged
+    /// into `config`, but we keep track of them so that we can report how much of the baseline is
+    /// no longer needed.
+    baseline: Permissions,
+
+    /// The baseline that gets merged into `config`. While `cargo acl baseline` is regenerating the
+    /// baseline, this is the pending baseline, so that the old one doesn't affect the result.
+    baseline_path: PathBuf,
+
+    /// APIs granted by the baseline that turned out to be unused. Populated by `check_unused`.
+    pub(crate) unused_baseline_apis: Vec<(PermSel, ApiName)>,
+
+    /// Problems that were downgraded to warnings by `--warn`. These are reported once we're done.
+    pub(crate) downgraded_problems: ProblemList,
+
     /// Information obtained when the linker was invoked, but for which we haven't yet received a
     /// corresponding notification that rustc has completed. We defer processing of these until
     /// rustc completes because we need information from the .deps file that rustc writes.
     outstanding_linker_invocations: Vec<LinkInfo>,
+
+    /// What the build is currently doing. Shared with the UI.
+    pub(crate) progress: Arc<Mutex<BuildProgress>>,
+
+    /// Output from cargo, if we're capturing it. Shared with the UI.
+    pub(crate) build_output: Arc<Mutex<BuildOutput>>,
+
+    /// The SHA-256 of each package's rlib. Only populated if attestations are in use.
+    rlib_hashes: FxHashMap<PackageId, String>,
+
+    /// Packages that have been reviewed during this run, either by granting them permissions in the
+    /// UI or by naming them to `cargo acl attest`. These are attested once the build succeeds.
+    packages_to_attest: FxHashSet<PackageId>,
+
+    /// The APIs that each selector has been observed to use, whether or not it was permitted to.
+    observed_apis: FxHashMap<PermSel, FxHashSet<ApiName>>,
 }
 
 #[derive(Default, Debug)]
@@ -93,6 +156,13 @@ pub(crate) struct ApiUsage {
     pub(crate) to_name: Name,
     pub(crate) to_source: NameSource<'static>,
     pub(crate) debug_data: Option<UsageDebugData>,
+    /// The functions through which the usage can be reached, starting with an entry point to the
+    /// package and ending with the function containing the usage. Only populated if
+    /// `common.report_call_chains` is set.
+    pub(crate) call_chain: Vec<SymbolOrDebugName>,
+    /// The number of other usages of the same name in the same file that were merged into this
+    /// one. See `common.max_usages_per_problem`.
+    pub(crate) similar_usages: usize,
 }
 
 /// A location within a bin file (executable or shared object).
@@ -108,11 +178,29 @@ impl Checker {
         tmpdir: Arc<TempDir>,
         target_dir: PathBuf,
         args: Arc<Args>,
-        sysroot: Arc<Path>,
+        rust_std: RustStd,
         crate_index: Arc<CrateIndex>,
         config_path: PathBuf,
     ) -> Self {
         let timings = TimingCollector::new(args.print_timing);
+        let graph =
+            matches!(args.command, Some(crate::cli::Command::Graph(..))).then(Graph::default);
+        let report = (args.output_format == OutputFormat::Json
+            || matches!(
+                &args.command,
+                Some(
+                    crate::cli::Command::Report(..)
+                        | crate::cli::Command::Diff(..)
+                        | crate::cli::Command::Export(..)
+                )
+            )
+            || matches!(&args.command, Some(crate::cli::Command::Summary(options)) if options.table))
+        .then(Report::default);
+        let baseline_path = if matches!(args.command, Some(crate::cli::Command::Baseline)) {
+            crate::config::pending_baseline_path(&config_path)
+        } else {
+            crate::config::baseline_path(&config_path)
+        };
         Self {
             apis_by_prefix: Default::default(),
             crate_infos: Default::default(),
@@ -125,14 +213,32 @@ impl Checker {
             path_to_pkg_ids: Default::default(),
             timings,
             backtracers: Default::default(),
+            graph,
+            report,
+            baseline: Default::default(),
+            baseline_path,
+            unused_baseline_apis: Default::default(),
+            downgraded_problems: Default::default(),
             outstanding_linker_invocations: Default::default(),
-            sysroot,
+            rust_std,
+            progress: Default::default(),
+            build_output: Default::default(),
+            rlib_hashes: Default::default(),
+            packages_to_attest: Default::default(),
+            observed_apis: Default::default(),
         }
     }
 
     /// Load (or reload) config. Note in the case of reloading, APIs are only ever additive.
     pub(crate) fn load_config(&mut self) -> Result<()> {
-        let config = crate::config::parse_file(&self.config_path, &self.crate_index)?;
+        let config = crate::config::parse_file_with_baseline(
+            &self.config_path,
+            &self.baseline_path,
+            &self.crate_index,
+        )
+        .context(crate::config::InvalidConfig)?;
+        self.baseline = crate::config::parse_baseline(&self.baseline_path)
+            .context(crate::config::InvalidConfig)?;
         // Every time we reload our configuration, we rewrite the flattened configuration. The
         // flattened configuration is used by subprocesses rather than using the original
         // configuration since using the original would require each subprocess to run `cargo
@@ -171,24 +277,23 @@ impl Checker {
                     .create_entry(crate::names::split_simple(&path.prefix).parts())
             }
         }
+        self.apis_by_prefix.expand_wildcards();
         for (api_name, api) in &config.raw.apis {
             for path in &api.include {
                 let name = &crate::names::split_simple(&path.prefix);
-                self.apis_by_prefix
-                    .mut_tree(name.parts())
-                    .update_subtree(&|apis| {
-                        apis.insert(api_name.clone());
-                    });
+                let parts: Vec<&str> = name.parts().collect();
+                self.apis_by_prefix.update_matching(&parts, &|apis| {
+                    apis.insert(api_name.clone());
+                });
             }
         }
         for (api_name, api_config) in &config.raw.apis {
             for path in &api_config.exclude {
                 let name = &crate::names::split_simple(&path.prefix);
-                self.apis_by_prefix
-                    .mut_tree(name.parts())
-                    .update_subtree(&|apis| {
-                        apis.remove(api_name);
-                    });
+                let parts: Vec<&str> = name.parts().collect();
+                self.apis_by_prefix.update_matching(&parts, &|apis| {
+                    apis.remove(api_name);
+                });
             }
         }
         // First apply permissions without inheritance, updating our unused_allow_apis records for
@@ -219,7 +324,7 @@ impl Checker {
             if !self
                 .config
                 .permissions
-                .get(&PermSel::for_primary(pkg_id.pkg_name()))
+                .get(&PermSel::for_primary(pkg_id.perm_name()))
                 .is_some_and(|pkg_config| pkg_config.allow_proc_macro)
             {
                 problems.push(Problem::IsProcMacro(pkg_id.clone()));
@@ -228,6 +333,28 @@ impl Checker {
         problems
     }
 
+    /// Returns errors for packages whose version doesn't match the version pinned in the config.
+    pub(crate) fn check_version_mismatches(&self) -> ProblemList {
+        let mut problems = ProblemList::default();
+        for pkg_id in self.crate_index.package_ids() {
+            let Some(version_req) = self
+                .config
+                .permissions_no_inheritance
+                .get(&PermSel::for_primary(pkg_id.pkg_name()))
+                .and_then(|pkg_config| pkg_config.version.as_ref())
+            else {
+                continue;
+            };
+            if !version_req.matches(pkg_id.version()) {
+                problems.push(Problem::VersionMismatch(VersionMismatch {
+                    pkg_id: pkg_id.clone(),
+                    version_req: version_req.clone(),
+                }));
+            }
+        }
+        problems
+    }
+
     pub(crate) fn handle_request(
         &mut self,
         request: &Option<rpc::Request>,
@@ -243,7 +370,24 @@ impl Checker {
                 Ok(ProblemList::default())
             }
             rpc::Request::BinExecutionComplete(output) => {
-                if output.exit_code != 0 {
+                if let Some(report) = self.report.as_mut() {
+                    report.bin_executed(output);
+                }
+                crate::sandbox::write_network_audit_log(
+                    &self.target_dir,
+                    profile_name(&self.args, &self.config.raw.common),
+                    &output.crate_sel,
+                    &output.network_requests,
+                )?;
+                if output.limit_exceeded.is_some() {
+                    Ok(
+                        Problem::ResourceLimitExceeded(crate::problem::BinExecutionFailed {
+                            output: output.clone(),
+                            crate_sel: output.crate_sel.clone(),
+                        })
+                        .into(),
+                    )
+                } else if output.exit_code != 0 {
                     Ok(
                         Problem::ExecutionFailed(crate::problem::BinExecutionFailed {
                             output: output.clone(),
@@ -251,6 +395,15 @@ impl Checker {
                         })
                         .into(),
                     )
+                } else if !output.captured_writes.is_empty() {
+                    // Any other problems will be reported when the binary is rerun.
+                    Ok(
+                        Problem::UnexpectedWrites(crate::problem::BinExecutionFailed {
+                            output: output.clone(),
+                            crate_sel: output.crate_sel.clone(),
+                        })
+                        .into(),
+                    )
                 } else if output.crate_sel.kind == CrateKind::BuildScript {
                     let report =
                         build_script_checker::BuildScriptReport::build(output, &self.config)?;
@@ -267,22 +420,33 @@ impl Checker {
             }
             rpc::Request::RustcComplete(info) => {
                 self.record_crate_paths(info)?;
-                if let Some(link_info) = self.get_link_info(info) {
-                    let problems = self.check_linker_invocation(&link_info, check_state)?;
-                    if !problems.is_empty() {
+                self.record_rlib_hash(info)?;
+                if let Some(report) = self.report.as_mut() {
+                    report.crate_compiled(info)?;
+                }
+                let mut problems = ProblemList::default();
+                for link_info in self.take_link_infos(info) {
+                    let mut link_problems =
+                        self.check_linker_invocation(&link_info, check_state)?;
+                    let downgraded = self.args.take_downgraded_problems(&mut link_problems);
+                    self.downgraded_problems.merge(downgraded);
+                    if !link_problems.is_empty() {
                         // Since we found some problems, add our LinkInfo back so that if we fix the
                         // problems via the UI we can recheck once we have fixes.
                         self.outstanding_linker_invocations.push(link_info);
                     }
-                    return Ok(problems);
+                    problems.merge(link_problems);
                 }
-
-                Ok(ProblemList::default())
+                Ok(problems)
             }
             rpc::Request::RustcStarted(crate_sel) => {
                 info!("Rustc started compiling {crate_sel}");
                 Ok(ProblemList::default())
             }
+            rpc::Request::Progress(update) => {
+                self.progress.lock().unwrap().update(update);
+                Ok(ProblemList::default())
+            }
         }
     }
 
@@ -303,8 +467,9 @@ impl Checker {
         )?);
         self.timings.add_timing(start, "Total object processing");
         info!(
-            "Checking linker args for {} with {} objects. {} problems",
+            "Checking linker args for {} ({}) with {} objects. {} problems",
             info.crate_sel,
+            info.output_file.display(),
             info.object_paths.len(),
             problems.len(),
         );
@@ -317,24 +482,27 @@ impl Checker {
         link_info: &LinkInfo,
         check_state: &mut CheckState,
     ) -> Result<ProblemList> {
+        let output_file = &link_info.output_file;
         if check_state
             .graph_outputs
-            .as_ref()
+            .get(output_file)
             .is_some_and(|outputs| outputs.apis != self.config.raw.apis)
         {
             // APIs have changed, invalidate cache.
-            check_state.graph_outputs = None;
+            check_state.graph_outputs.remove(output_file);
         }
-        if check_state.graph_outputs.is_none() {
+        if !check_state.graph_outputs.contains_key(output_file) {
             let (mut graph_outputs, backtracer) =
                 crate::symbol_graph::scan_objects(paths, link_info, self)?;
             graph_outputs.apis = self.config.raw.apis.clone();
-            check_state.graph_outputs = Some(graph_outputs);
+            check_state
+                .graph_outputs
+                .insert(output_file.clone(), graph_outputs);
             if let Some(b) = backtracer {
-                self.backtracers.insert(link_info.output_file.clone(), b);
+                self.backtracers.insert(output_file.clone(), b);
             }
         }
-        let graph_outputs = check_state.graph_outputs.as_ref().unwrap();
+        let graph_outputs = &check_state.graph_outputs[output_file];
         let problems = graph_outputs.problems(self)?;
         Ok(problems)
     }
@@ -349,7 +517,7 @@ impl Checker {
         }
         if self
             .crate_infos
-            .contains_key(&PermSel::for_build_script(pkg_id.name_str()))
+            .contains_key(&PermSel::for_build_script(pkg_id.perm_name()))
         {
             return ProblemList::default();
         }
@@ -359,41 +527,99 @@ impl Checker {
     pub(crate) fn pkg_ids_from_source_path(
         &self,
         source_path: &Path,
-    ) -> Result<Cow<Vec<PackageId>>> {
+    ) -> Result<Cow<'_, [PackageId]>> {
         self.opt_pkg_ids_from_source_path(source_path)
             .ok_or_else(|| anyhow!("Couldn't find crate name for {}", source_path.display(),))
     }
 
+    /// Returns whether API usages in code generated by proc macros should be attributed to the
+    /// proc macro.
+    pub(crate) fn attributes_to_proc_macros(&self) -> bool {
+        self.config.raw.common.proc_macro_attribution == ProcMacroAttribution::ProcMacro
+    }
+
+    /// Returns the packages that should be held responsible for an API usage at `location`. This is
+    /// normally the packages that contain `location`, but if `function` is supplied, which it
+    /// should be if `attributes_to_proc_macros` returns true, then usages in code generated by
+    /// proc macros are attributed to the proc macro.
+    pub(crate) fn pkg_ids_for_usage(
+        &self,
+        location: &SourceLocation,
+        function: Option<&ContainingFunction>,
+    ) -> Result<Cow<'_, [PackageId]>> {
+        let pkg_ids = self.pkg_ids_from_source_path(location.filename())?;
+        let Some(function) = function else {
+            return Ok(pkg_ids);
+        };
+        let mut changed = false;
+        let attributed: Vec<PackageId> = pkg_ids
+            .iter()
+            .map(|pkg_id| {
+                match proc_macro_attribution::generating_proc_macro(
+                    &self.crate_index,
+                    pkg_id,
+                    location,
+                    function,
+                ) {
+                    Some(proc_macro) => {
+                        changed = true;
+                        proc_macro.clone()
+                    }
+                    None => pkg_id.clone(),
+                }
+            })
+            .collect();
+        if changed {
+            Ok(Cow::Owned(attributed))
+        } else {
+            Ok(pkg_ids)
+        }
+    }
+
     pub(crate) fn opt_pkg_ids_from_source_path(
         &self,
         source_path: &Path,
-    ) -> Option<Cow<Vec<PackageId>>> {
+    ) -> Option<Cow<'_, [PackageId]>> {
         self.path_to_pkg_ids
             .get(source_path)
-            .map(Cow::Borrowed)
+            .map(|pkg_ids| Cow::Borrowed(pkg_ids.as_slice()))
             .or_else(|| {
-                // If the source path is from the rust standard library, or from one of the
-                // precompiled crates that comes with the standard library, then report no crates.
-                if self.is_in_rust_std(source_path) {
-                    return Some(Cow::Owned(vec![]));
+                if self.rust_std.is_in_sysroot(source_path) {
+                    return Some(self.rust_std_pkg_ids());
                 }
 
-                // Fall-back to just finding the package that contains the source path.
-                self.crate_index
-                    .package_id_for_path(source_path)
-                    .map(|pkg_id| Cow::Owned(vec![pkg_id.clone()]))
+                // Fall-back to just finding the package that contains the source path. We do this
+                // before checking other paths that the standard library may have been built from,
+                // since `/cargo/registry` is also where packages live if CARGO_HOME is `/cargo`,
+                // as it is in some docker images.
+                if let Some(pkg_id) = self.crate_index.package_id_for_path(source_path) {
+                    return Some(Cow::Owned(vec![pkg_id.clone()]));
+                }
+
+                // If the source path is from the rust standard library, or from one of the
+                // precompiled crates that comes with the standard library, then report no crates,
+                // unless we've been asked to audit the standard library.
+                if self.rust_std.contains(source_path) {
+                    return Some(self.rust_std_pkg_ids());
+                }
+                None
             })
     }
 
-    // Returns whether `source_path` is from the rust standard library or precompiled crates that are
-    // bundled with the standard library (e.g. hashbrown).
-    pub(crate) fn is_in_rust_std(&self, source_path: &Path) -> bool {
-        // Pre 2023-10-26
-        source_path.starts_with("/rustc/")
-            || source_path.starts_with("/cargo/registry")
-            // Post 2023-10-26
-            || source_path.starts_with("/rust/")
-            || source_path.starts_with(&self.sysroot)
+    fn rust_std_pkg_ids(&self) -> Cow<'_, [PackageId]> {
+        if self.config.raw.common.audit_std {
+            Cow::Owned(vec![PackageId::rust_std()])
+        } else {
+            Cow::Owned(vec![])
+        }
+    }
+
+    /// Returns whether the compilation unit with `compdir` is part of the rust standard library and
+    /// should be skipped because we're not auditing it.
+    pub(crate) fn should_skip_std_unit(&self, compdir: &Path) -> bool {
+        !self.config.raw.common.audit_std
+            && self.crate_index.package_id_for_path(compdir).is_none()
+            && self.rust_std.contains(compdir)
     }
 
     /// Returns all APIs that are matched by `name`. e.g. The name `["std", "fs", "write"]` might
@@ -411,8 +637,21 @@ impl Checker {
         api_usage: &ApiUsages,
         problems: &mut ProblemList,
     ) -> Result<()> {
+        let problem_count = problems.len();
+        self.check_api_usage(api_usage, problems)?;
+        if let Some(report) = self.report.as_mut() {
+            report.api_used(api_usage, problems.len() == problem_count);
+        }
+        Ok(())
+    }
+
+    fn check_api_usage(&mut self, api_usage: &ApiUsages, problems: &mut ProblemList) -> Result<()> {
         let api = &api_usage.api_name;
         let perm_sel = api_usage.perm_sel();
+        self.observed_apis
+            .entry(perm_sel.clone())
+            .or_default()
+            .insert(api.clone());
         if let Some(crate_info) = self.crate_infos.get_mut(&perm_sel) {
             if crate_info.allowed_apis.contains(api) {
                 crate_info.unused_allowed_apis.remove(api);
@@ -421,6 +660,45 @@ impl Checker {
             }
         }
 
+        // APIs in allow mode are permitted unless the package has explicitly been denied them.
+        if self
+            .config
+            .raw
+            .apis
+            .get(api)
+            .is_some_and(|api_config| api_config.mode == ApiMode::Allow)
+            && !self
+                .config
+                .permissions
+                .get(&perm_sel)
+                .is_some_and(|pkg_config| pkg_config.deny_apis.contains(api))
+        {
+            return Ok(());
+        }
+
+        // Drop any usages of functions that have been individually permitted.
+        let allowed_fns: Vec<Name> = self
+            .config
+            .permissions
+            .get(&perm_sel)
+            .map(|pkg_config| {
+                pkg_config
+                    .allow_fn
+                    .iter()
+                    .map(|path| crate::names::split_simple(&path.prefix))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let usages: Vec<&ApiUsage> = api_usage
+            .usages
+            .iter()
+            .filter(|usage| !allowed_fns.iter().any(|f| usage.to_name.is_within(f)))
+            .filter(|usage| !self.is_ignored(api_usage, usage))
+            .collect();
+        if usages.is_empty() {
+            return Ok(());
+        }
+
         // Partition all usages into on-tree and off-tree usages. On-tree are those usages that are
         // referencing a name from one of our dependencies. Off-tree are those that reference names
         // from packages not in our package's dependency tree.
@@ -429,7 +707,7 @@ impl Checker {
 
         let all_deps = self.crate_index.name_prefix_to_pkg_id();
         if let Some(crate_deps) = self.crate_index.transitive_deps(&api_usage.pkg_id) {
-            for usage in &api_usage.usages {
+            for usage in usages {
                 if let Some(first_name_part) = usage.to_name.parts.first() {
                     if !crate_deps.contains(first_name_part) {
                         if let Some(pkg_id) = all_deps.get(first_name_part) {
@@ -456,7 +734,7 @@ impl Checker {
             // If we don't know the transitive dependencies of our crate, then just classify
             // everything as on-tree. This currently happens for transitive dependencies of
             // proc-macros due to the arguments we pass to `cargo tree`.
-            on_tree.extend(api_usage.usages.iter().cloned());
+            on_tree.extend(usages.into_iter().cloned());
         }
 
         // Report off-tree problems for each off-tree package that we appear to reference.
@@ -475,6 +753,48 @@ impl Checker {
         Ok(())
     }
 
+    /// Discards state left over from a build that has finished, so that it doesn't affect the next
+    /// build. Returns problems that were downgraded during the build, which have yet to be reported.
+    /// Used by `watch`.
+    pub(crate) fn finish_build(&mut self) -> ProblemList {
+        self.outstanding_linker_invocations.clear();
+        std::mem::take(&mut self.downgraded_problems)
+    }
+
+    /// Returns the severity with which to report usages of `api` by packages that aren't permitted
+    /// to use it.
+    pub(crate) fn api_severity(&self, api: &ApiName) -> Severity {
+        self.config
+            .raw
+            .apis
+            .get(api)
+            .map(|api_config| api_config.severity.problem_severity())
+            .unwrap_or(Severity::Error)
+    }
+
+    /// Returns whether `usage` is from a source file that matches an ignore rule for the package
+    /// and API of `api_usage`.
+    fn is_ignored(&self, api_usage: &ApiUsages, usage: &ApiUsage) -> bool {
+        let pkg_name = api_usage.pkg_id.pkg_name();
+        let mut rules = self
+            .config
+            .raw
+            .ignore
+            .iter()
+            .filter(|rule| rule.pkg.as_ref() == pkg_name.as_ref() && rule.api == api_usage.api_name)
+            .peekable();
+        if rules.peek().is_none() {
+            return false;
+        }
+        let Some(pkg_dir) = self.crate_index.pkg_dir(&api_usage.pkg_id) else {
+            return false;
+        };
+        let Ok(relative_path) = usage.source_location.filename().strip_prefix(pkg_dir) else {
+            return false;
+        };
+        rules.any(|rule| crate::glob::matches(&rule.path, relative_path))
+    }
+
     /// Returns whether the to-name of `usage` starts with a crate name that matches the package
     /// that defined the outer location of the usage.
     fn is_to_name_from_outer_location(&self, usage: &ApiUsage) -> Result<bool> {
@@ -492,7 +812,16 @@ impl Checker {
         Ok(false)
     }
 
-    pub(crate) fn check_unused(&self) -> Result<ProblemList> {
+    /// Returns the number of APIs granted by the baseline.
+    pub(crate) fn baseline_api_count(&self) -> usize {
+        self.baseline
+            .packages
+            .values()
+            .map(|config| config.allow_apis.len())
+            .sum()
+    }
+
+    pub(crate) fn check_unused(&mut self) -> Result<ProblemList> {
         if !self.outstanding_linker_invocations.is_empty() {
             bail!(
                 "Linker invocations with no matching rustc completion: {}",
@@ -502,14 +831,34 @@ impl Checker {
 
         let mut problems = ProblemList::default();
         let perm_sels_in_index = &self.crate_index.permission_selectors;
+        self.unused_baseline_apis.clear();
         for (perm_sel, crate_info) in &self.crate_infos {
-            if !perm_sels_in_index.contains(perm_sel) {
+            let baseline_config = self.baseline.get(perm_sel);
+            let is_std = self.config.raw.common.audit_std
+                && perm_sel.package_name.name() == RUST_STD_PKG_NAME;
+            if !perm_sels_in_index.contains(perm_sel) && baseline_config.is_none() && !is_std {
                 problems.push(Problem::UnusedPackageConfig(perm_sel.clone()));
             }
-            if !crate_info.unused_allowed_apis.is_empty() {
+            if let Some(report) = self.report.as_mut() {
+                for api in &crate_info.unused_allowed_apis {
+                    report.api_unused(perm_sel, api);
+                }
+            }
+            // Unused APIs that were granted by the baseline have been burned down rather than being
+            // config that needs removing.
+            let mut unused_apis = Vec::new();
+            for api in &crate_info.unused_allowed_apis {
+                if baseline_config.is_some_and(|config| config.allow_apis.contains(api)) {
+                    self.unused_baseline_apis
+                        .push((perm_sel.clone(), api.clone()));
+                } else {
+                    unused_apis.push(api.clone());
+                }
+            }
+            if !unused_apis.is_empty() {
                 problems.push(Problem::UnusedAllowApi(UnusedAllowApi {
                     perm_sel: perm_sel.clone(),
-                    apis: crate_info.unused_allowed_apis.iter().cloned().collect(),
+                    apis: unused_apis,
                 }));
             }
         }
@@ -526,6 +875,56 @@ impl Checker {
         Ok(problems)
     }
 
+    /// Returns warnings for packages that were observed to use APIs that they don't declare in their
+    /// `cackle-manifest.toml`. Like `check_unused`, this relies on having seen a complete build.
+    pub(crate) fn check_declarations(&self) -> ProblemList {
+        let mut problems = ProblemList::default();
+        for (pkg_name, declaration) in &self.config.raw.declarations.declared {
+            for (scope, declared) in [
+                (PermissionScope::All, &declaration.allow_apis),
+                (PermissionScope::Build, &declaration.build.allow_apis),
+            ] {
+                // Usages from anywhere other than the build script are of the package's library.
+                let mut observed: Vec<&ApiName> = self
+                    .observed_apis
+                    .iter()
+                    .filter(|(perm_sel, _)| {
+                        perm_sel.package_name == *pkg_name
+                            && (perm_sel.scope == PermissionScope::Build)
+                                == (scope == PermissionScope::Build)
+                            && perm_sel.scope != PermissionScope::Test
+                    })
+                    .flat_map(|(_, apis)| apis)
+                    .collect();
+                observed.sort();
+                observed.dedup();
+                let undeclared: Vec<ApiName> = observed
+                    .iter()
+                    .filter(|api| !declared.contains(api))
+                    .map(|api| (*api).clone())
+                    .collect();
+                if undeclared.is_empty() {
+                    continue;
+                }
+                let mut unobserved: Vec<ApiName> = declared
+                    .iter()
+                    .filter(|api| !observed.contains(api))
+                    .cloned()
+                    .collect();
+                unobserved.sort();
+                problems.push(Problem::UndeclaredApis(UndeclaredApis {
+                    perm_sel: PermSel {
+                        package_name: pkg_name.clone(),
+                        scope,
+                    },
+                    undeclared,
+                    unobserved,
+                }));
+            }
+        }
+        problems
+    }
+
     pub(crate) fn check_for_new_config_version(&self) -> ProblemList {
         let version = self.config.raw.common.version;
         if version < crate::config::MAX_VERSION {
@@ -534,6 +933,211 @@ impl Checker {
         ProblemList::default()
     }
 
+    /// Returns a warning if any sandbox is configured as `Auto` and the strongest sandbox available
+    /// on this system doesn't provide all protections.
+    pub(crate) fn check_sandbox_capabilities(&self) -> ProblemList {
+        let config = &self.config;
+        let uses_auto = [
+            &config.raw.sandbox,
+            &config.raw.rustc.sandbox,
+            &config.raw.rustc.proc_macro_sandbox,
+        ]
+        .into_iter()
+        .chain(config.permissions.packages.values().map(|pkg| &pkg.sandbox))
+        .any(|sandbox| sandbox.kind == Some(SandboxKind::Auto));
+        if !uses_auto {
+            return ProblemList::default();
+        }
+        let kind = crate::sandbox::available_kind();
+        let skipped = crate::sandbox::skipped_protections(kind);
+        if skipped.is_empty() {
+            return ProblemList::default();
+        }
+        Problem::SandboxDegraded(SandboxDegraded { kind, skipped }).into()
+    }
+
+    /// Returns warnings for packages that have outstanding advisories and are granted any of the
+    /// configured sensitive APIs.
+    pub(crate) fn check_advisories(&self) -> ProblemList {
+        let mut problems = ProblemList::default();
+        let sensitive_apis = &self.config.raw.advisories.sensitive_apis;
+        for (pkg_id, advisories) in &self.config.raw.advisories.outstanding {
+            let perm_name = pkg_id.perm_name();
+            let mut apis: Vec<ApiName> = self
+                .config
+                .permissions
+                .packages
+                .iter()
+                .filter(|(perm_sel, _)| *perm_sel.package_name.as_ref() == *perm_name)
+                .flat_map(|(_, pkg_config)| &pkg_config.allow_apis)
+                .filter(|api| sensitive_apis.contains(api))
+                .cloned()
+                .collect();
+            apis.sort();
+            apis.dedup();
+            if !apis.is_empty() {
+                problems.push(Problem::AdvisoryWithSensitiveApis(
+                    AdvisoryWithSensitiveApis {
+                        pkg_id: pkg_id.clone(),
+                        advisories: advisories.clone(),
+                        apis,
+                    },
+                ));
+            }
+        }
+        problems
+    }
+
+    fn workspace_root(&self) -> &Path {
+        self.crate_index
+            .manifest_path
+            .parent()
+            .unwrap_or(Path::new("."))
+    }
+
+    fn attestations_in_use(&self) -> bool {
+        let config = &self.config.raw.attestations;
+        config.signing_key.is_some() || config.dir(self.workspace_root()).is_dir()
+    }
+
+    fn record_rlib_hash(&mut self, info: &rpc::RustcOutput) -> Result<()> {
+        let Some(rlib_path) = &info.rlib_path else {
+            return Ok(());
+        };
+        if info.crate_sel.kind != CrateKind::Primary
+            || self.crate_index.is_workspace_member(&info.crate_sel.pkg_id)
+            || !self.attestations_in_use()
+        {
+            return Ok(());
+        }
+        self.rlib_hashes
+            .insert(info.crate_sel.pkg_id.clone(), crate::fs::sha256(rlib_path)?);
+        Ok(())
+    }
+
+    /// Records which of `pkg_ids` were granted different permissions by fixes that were just
+    /// accepted in the UI, given the config from before they were applied.
+    pub(crate) fn record_reviewed(&mut self, pkg_ids: &[PackageId], old_config: &Config) {
+        if !self.args.has_interactive_ui() || self.config.raw.attestations.signing_key.is_none() {
+            return;
+        }
+        for pkg_id in pkg_ids {
+            if attestation::granted_permissions(pkg_id, old_config)
+                != attestation::granted_permissions(pkg_id, &self.config)
+            {
+                self.packages_to_attest.insert(pkg_id.clone());
+            }
+        }
+    }
+
+    /// Marks the named packages as reviewed, so that they're attested once the build succeeds.
+    /// Used by `cargo acl attest`.
+    pub(crate) fn attest_packages(&mut self, names: &[String]) -> Result<()> {
+        if self.config.raw.attestations.signing_key.is_none() {
+            bail!("`attestations.signing_key` must be set in order to write attestations");
+        }
+        for name in names {
+            let pkg_name = PackageName::from(name.as_str());
+            let mut found = false;
+            for pkg_id in self.crate_index.package_ids() {
+                if pkg_id.is_selected_by(&pkg_name) {
+                    self.packages_to_attest.insert(pkg_id.clone());
+                    found = true;
+                }
+            }
+            if !found {
+                bail!("No package named `{name}` in the dependency tree");
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns warnings for packages whose attestations no longer hold, because the package was
+    /// built differently, is granted different permissions or the signature can't be verified.
+    /// Packages that are about to be attested again aren't checked.
+    pub(crate) fn check_attestations(&self) -> Result<ProblemList> {
+        let mut problems = ProblemList::default();
+        if self.rlib_hashes.is_empty() {
+            return Ok(problems);
+        }
+        let config = &self.config.raw.attestations;
+        let dir = config.dir(self.workspace_root());
+        let allowed_signers = config.allowed_signers(self.workspace_root());
+        let mut pkg_ids: Vec<&PackageId> = self
+            .rlib_hashes
+            .keys()
+            .filter(|pkg_id| !self.packages_to_attest.contains(*pkg_id))
+            .collect();
+        pkg_ids.sort_by(|a, b| (a.name_str(), a.version()).cmp(&(b.name_str(), b.version())));
+        for pkg_id in pkg_ids {
+            let path = attestation::path(&dir, pkg_id);
+            let Some(attestation) = attestation::read(&path)? else {
+                continue;
+            };
+            let reason =
+                if let Err(error) = attestation::verify(&path, &attestation, &allowed_signers) {
+                    StaleAttestationReason::InvalidSignature(format!("{error:#}"))
+                } else if attestation.package != pkg_id.name_str()
+                    || attestation.version != *pkg_id.version()
+                {
+                    StaleAttestationReason::InvalidSignature(format!(
+                        "it's for `{} v{}`",
+                        attestation.package, attestation.version
+                    ))
+                } else if attestation.rlib_sha256 != self.rlib_hashes[pkg_id] {
+                    StaleAttestationReason::ContentsChanged
+                } else if attestation.permissions_changed(pkg_id, &self.config) {
+                    StaleAttestationReason::PermissionsChanged
+                } else {
+                    continue;
+                };
+            problems.push(Problem::StaleAttestation(StaleAttestation {
+                pkg_id: pkg_id.clone(),
+                reviewer: attestation.reviewer,
+                reason,
+            }));
+        }
+        Ok(problems)
+    }
+
+    /// Signs and writes attestations for packages that were reviewed during this run. Returns the
+    /// packages that were attested.
+    pub(crate) fn write_attestations(&self) -> Result<Vec<PackageId>> {
+        let config = &self.config.raw.attestations;
+        let Some(signing_key) = &config.signing_key else {
+            return Ok(Vec::new());
+        };
+        let mut pkg_ids: Vec<&PackageId> = self
+            .packages_to_attest
+            .iter()
+            .filter(|pkg_id| self.rlib_hashes.contains_key(*pkg_id))
+            .collect();
+        if pkg_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        pkg_ids.sort_by(|a, b| (a.name_str(), a.version()).cmp(&(b.name_str(), b.version())));
+        let root = self.workspace_root();
+        let reviewer = match &config.reviewer {
+            Some(reviewer) => reviewer.clone(),
+            None => attestation::default_reviewer(root)?,
+        };
+        let dir = config.dir(root);
+        for pkg_id in &pkg_ids {
+            let attestation = attestation::Attestation::new(
+                pkg_id,
+                self.rlib_hashes[*pkg_id].clone(),
+                reviewer.clone(),
+                &self.config,
+            );
+            attestation::write_signed(
+                &attestation::path(&dir, pkg_id),
+                &attestation,
+                &root.join(signing_key),
+            )?;
+        }
+        Ok(pkg_ids.into_iter().cloned().collect())
+    }
+
     fn record_crate_paths(&mut self, info: &rpc::RustcOutput) -> Result<()> {
         for path in &info.source_paths {
             let selectors = &mut self.path_to_pkg_ids.entry(path.to_owned()).or_default();
@@ -552,6 +1156,23 @@ impl Checker {
         }
     }
 
+    pub(crate) fn ffi_export_problems(
+        &self,
+        ffi_exports: &[FfiExport],
+        problems: &mut ProblemList,
+    ) {
+        for export in ffi_exports {
+            if !self
+                .config
+                .permissions
+                .get(&export.perm_sel())
+                .is_some_and(|pkg_config| pkg_config.allow_ffi_export)
+            {
+                problems.push(Problem::DisallowedFfiExport(export.clone()));
+            }
+        }
+    }
+
     pub(crate) fn possible_exported_api_problems(
         &self,
         possible_exported_apis: &[PossibleExportedApi],
@@ -579,15 +1200,33 @@ impl Checker {
 
     /// Returns the outstanding LinkInfo for when the linker was invoked corresponding to the
     /// supplied rustc completion event.
-    fn get_link_info(&mut self, info: &rpc::RustcOutput) -> Option<LinkInfo> {
-        let index = self
-            .outstanding_linker_invocations
-            .iter()
-            .position(|link_info| link_info.crate_sel == info.crate_sel)?;
-        Some(self.outstanding_linker_invocations.remove(index))
+    /// Removes and returns the linker invocations that were made by the rustc invocation that
+    /// produced `info`. If the same output was linked more than once, e.g. because rustc was rerun,
+    /// only the most recent is returned.
+    fn take_link_infos(&mut self, info: &rpc::RustcOutput) -> Vec<LinkInfo> {
+        let (matching, others) = std::mem::take(&mut self.outstanding_linker_invocations)
+            .into_iter()
+            .partition(|link_info| {
+                link_info.crate_sel == info.crate_sel
+                    && link_info.platform == info.platform
+                    && link_info.rustc_invocation == info.rustc_invocation
+            });
+        self.outstanding_linker_invocations = others;
+        let mut link_infos: Vec<LinkInfo> = Vec::new();
+        for link_info in matching {
+            link_infos.retain(|existing| existing.output_file != link_info.output_file);
+            link_infos.push(link_info);
+        }
+        link_infos
     }
 
     fn mark_parent_allow_apis_used(&mut self, api: &ApiName, perm_sel: &PermSel) {
+        for equivalent in perm_sel.equivalents(&self.crate_index) {
+            if let Some(info) = self.crate_infos.get_mut(&equivalent) {
+                info.unused_allowed_apis.remove(api);
+            }
+            self.mark_parent_allow_apis_used(api, &equivalent);
+        }
         let Some(parent) = perm_sel.parent() else {
             return;
         };
@@ -598,6 +1237,34 @@ impl Checker {
     }
 }
 
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::Checker;
+    use crate::config::Config;
+    use std::sync::Arc;
+
+    /// Returns the names of the APIs that `config` classifies `path` as, sorted.
+    pub(crate) fn apis_for_path(config: Arc<Config>, path: &str) -> Vec<String> {
+        let mut checker = Checker::new(
+            Arc::new(crate::tmpdir::TempDir::new(None).unwrap()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        );
+        checker.update_config(config);
+        let name = crate::names::split_simple(path);
+        let mut apis: Vec<String> = checker
+            .apis_for_name_iterator(name.parts())
+            .iter()
+            .map(|api| api.to_string())
+            .collect();
+        apis.sort();
+        apis
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -609,12 +1276,51 @@ mod tests {
             Arc::new(TempDir::new(None).unwrap()),
             PathBuf::default(),
             Arc::new(Args::default()),
-            Arc::from(Path::new("")),
+            RustStd::default(),
             Arc::new(CrateIndex::default()),
             PathBuf::default(),
         )
     }
 
+    #[test]
+    fn regenerating_baseline_ignores_old_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("cackle.toml");
+        let baseline_path = crate::config::baseline_path(&config_path);
+        std::fs::write(
+            &config_path,
+            "[common]\nversion = 2\n[api.fs]\ninclude = [\"std::fs\"]\n",
+        )
+        .unwrap();
+        std::fs::write(&baseline_path, "[pkg.foo]\nallow_apis = [\"fs\"]\n").unwrap();
+        let load = |command| {
+            let mut checker = Checker::new(
+                Arc::new(TempDir::new(None).unwrap()),
+                PathBuf::default(),
+                Arc::new(Args {
+                    command,
+                    ..Args::default()
+                }),
+                RustStd::default(),
+                Arc::new(CrateIndex::default()),
+                config_path.clone(),
+            );
+            checker.load_config().unwrap();
+            checker
+        };
+        assert_eq!(load(None).baseline_api_count(), 1);
+
+        let checker = load(Some(crate::cli::Command::Baseline));
+        assert_eq!(checker.baseline_api_count(), 0);
+        assert!(checker
+            .config
+            .permissions
+            .get(&PermSel::for_primary("foo"))
+            .is_none());
+        // The old baseline is only replaced once the run succeeds.
+        assert!(baseline_path.exists());
+    }
+
     #[track_caller]
     fn assert_apis(config: &str, path: &[&str], expected: &[&str]) {
         let mut checker = checker_for_testing();
@@ -626,6 +1332,50 @@ mod tests {
         assert_eq!(api_names, expected);
     }
 
+    #[test]
+    fn take_link_infos() {
+        use crate::crate_index::testing::pkg_id;
+
+        fn link_info(output: &str, invocation: u64) -> LinkInfo {
+            serde_json::from_value(serde_json::json!({
+                "crate_sel": crate::crate_index::CrateSel::primary(pkg_id("foo")),
+                "object_paths": [],
+                "output_file": output,
+                "is_shared": false,
+                "rustc_invocation": invocation,
+            }))
+            .unwrap()
+        }
+
+        let mut checker = checker_for_testing();
+        for info in [
+            link_info("a", 1),
+            link_info("b", 2),
+            link_info("c", 1),
+            link_info("a", 1),
+        ] {
+            checker.outstanding_linker_invocations.push(info);
+        }
+        let complete = |invocation| {
+            serde_json::from_value::<rpc::RustcOutput>(serde_json::json!({
+                "crate_sel": crate::crate_index::CrateSel::primary(pkg_id("foo")),
+                "source_paths": [],
+                "platform": "Target",
+                "rustc_invocation": invocation,
+            }))
+            .unwrap()
+        };
+        let outputs = |infos: Vec<LinkInfo>| {
+            infos
+                .iter()
+                .map(|info| info.output_file.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(outputs(checker.take_link_infos(&complete(1))), ["c", "a"]);
+        assert_eq!(outputs(checker.take_link_infos(&complete(2))), ["b"]);
+        assert!(checker.outstanding_linker_invocations.is_empty());
+    }
+
     #[test]
     fn test_apis_for_path() {
         let config = r#"
@@ -647,6 +1397,240 @@ mod tests {
         assert_apis(config, &["std", "env", "exe"], &["env", "env2", "fs"]);
     }
 
+    #[test]
+    fn test_apis_for_path_with_wildcards() {
+        let config = r#"
+                [api.net]
+                include = [
+                    "std::net",
+                    "std::os::*::net",
+                ]
+                exclude = [
+                    "std::os::*::net::SocketAddr",
+                ]
+
+                [api.fs]
+                include = [
+                    "std::os::unix::fs",
+                    "std::os::*::fs",
+                ]
+                exclude = [
+                    "std::os::unix::*::Metadata",
+                ]
+                "#;
+        assert_apis(
+            config,
+            &["std", "os", "unix", "net", "UnixStream"],
+            &["net"],
+        );
+        assert_apis(config, &["std", "os", "wasi", "net", "TcpStream"], &["net"]);
+        assert_apis(config, &["std", "os", "unix", "net", "SocketAddr"], &[]);
+        assert_apis(config, &["std", "os", "windows", "fs", "symlink"], &["fs"]);
+        assert_apis(config, &["std", "os", "unix", "fs", "Metadata"], &[]);
+        assert_apis(config, &["std", "os", "unix", "process"], &[]);
+        assert_apis(config, &["std", "os"], &[]);
+    }
+
+    #[test]
+    fn audit_std() {
+        let std_path = Path::new("/build/rustc-1.79.0-src/library/std/src/fs.rs");
+        let config = "[pkg.std]\nallow_unsafe = true\n";
+        let mut checker = checker_for_testing();
+        checker.update_config(parse(config).unwrap());
+        assert_eq!(
+            checker.opt_pkg_ids_from_source_path(std_path).as_deref(),
+            Some([].as_slice())
+        );
+        assert!(checker.should_skip_std_unit(Path::new("/build/rustc-1.79.0-src/library/std")));
+        assert!(checker
+            .check_unused()
+            .unwrap()
+            .take()
+            .contains(&Problem::UnusedPackageConfig(PermSel::for_primary("std"))));
+
+        let mut config = Arc::into_inner(parse(config).unwrap()).unwrap();
+        config.raw.common.audit_std = true;
+        checker.update_config(Arc::new(config));
+        assert_eq!(
+            checker.opt_pkg_ids_from_source_path(std_path).as_deref(),
+            Some([PackageId::rust_std()].as_slice())
+        );
+        assert!(!checker.should_skip_std_unit(Path::new("/build/rustc-1.79.0-src/library/std")));
+        assert!(checker.check_unused().unwrap().is_empty());
+    }
+
+    #[test]
+    fn advisory_with_sensitive_apis() {
+        use crate::config::advisories::Advisory;
+        use crate::crate_index::testing::pkg_id;
+
+        let advisory = Advisory {
+            id: "RUSTSEC-2024-0001".to_owned(),
+            title: "Something bad".to_owned(),
+            informational: None,
+        };
+        let checker = |config: &str| {
+            let mut checker = Checker {
+                crate_index: crate::crate_index::testing::index_with_package_names(&["crab1"]),
+                ..checker_for_testing()
+            };
+            let mut config = Arc::into_inner(parse(config).unwrap()).unwrap();
+            config.raw.advisories.sensitive_apis = vec![ApiName::from("net")];
+            config.raw.advisories.outstanding = vec![(pkg_id("crab1"), vec![advisory.clone()])];
+            checker.update_config(Arc::new(config));
+            checker.check_advisories()
+        };
+        let config =
+            |apis: &str| format!("[api.fs]\n[api.net]\n[pkg.crab1]\nallow_apis = [{apis}]\n");
+        assert!(checker(&config("\"fs\"")).is_empty());
+        assert_eq!(
+            checker(&config("\"fs\", \"net\"")).take(),
+            vec![Problem::AdvisoryWithSensitiveApis(
+                AdvisoryWithSensitiveApis {
+                    pkg_id: pkg_id("crab1"),
+                    advisories: vec![advisory.clone()],
+                    apis: vec![ApiName::from("net")],
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn attestations() {
+        use crate::crate_index::testing::pkg_id;
+
+        let dir = tempfile::tempdir().unwrap();
+        let Some((key, allowed_signers)) = crate::attestation::testing::signing_key(dir.path())
+        else {
+            return;
+        };
+        let mut checker = Checker {
+            crate_index: crate::crate_index::testing::index_with_package_names(&["crab1"]),
+            ..checker_for_testing()
+        };
+        let with_config = |checker: &mut Checker, config: &str| {
+            let mut config = Arc::into_inner(parse(config).unwrap()).unwrap();
+            config.raw.attestations.dir = Some(dir.path().join("attestations"));
+            config.raw.attestations.signing_key = Some(key.clone());
+            config.raw.attestations.reviewer = Some("me@example.com".to_owned());
+            config.raw.attestations.allowed_signers = Some(allowed_signers.clone());
+            checker.update_config(Arc::new(config));
+        };
+        let crab1 = pkg_id("crab1");
+        let stale = |reason| {
+            vec![Problem::StaleAttestation(StaleAttestation {
+                pkg_id: crab1.clone(),
+                reviewer: "me@example.com".to_owned(),
+                reason,
+            })]
+        };
+        with_config(
+            &mut checker,
+            "[api.fs]\n[api.net]\n[pkg.crab1]\nallow_apis = [\"fs\"]\n",
+        );
+        checker.rlib_hashes.insert(crab1.clone(), "abc".to_owned());
+        checker.attest_packages(&["crab1".to_owned()]).unwrap();
+        assert_eq!(checker.write_attestations().unwrap(), vec![crab1.clone()]);
+        checker.packages_to_attest.clear();
+        assert!(checker.check_attestations().unwrap().is_empty());
+
+        checker.rlib_hashes.insert(crab1.clone(), "def".to_owned());
+        assert_eq!(
+            checker.check_attestations().unwrap().take(),
+            stale(StaleAttestationReason::ContentsChanged)
+        );
+
+        checker.rlib_hashes.insert(crab1.clone(), "abc".to_owned());
+        with_config(
+            &mut checker,
+            "[api.fs]\n[api.net]\n[pkg.crab1]\nallow_apis = [\"fs\", \"net\"]\n",
+        );
+        assert_eq!(
+            checker.check_attestations().unwrap().take(),
+            stale(StaleAttestationReason::PermissionsChanged)
+        );
+
+        std::fs::write(&allowed_signers, "").unwrap();
+        assert!(matches!(
+            checker.check_attestations().unwrap().take().as_slice(),
+            [Problem::StaleAttestation(StaleAttestation {
+                reason: StaleAttestationReason::InvalidSignature(..),
+                ..
+            })]
+        ));
+    }
+
+    #[test]
+    fn undeclared_apis() {
+        use crate::config::declarations::Declaration;
+
+        let mut checker = Checker {
+            crate_index: crate::crate_index::testing::index_with_package_names(&["foo"]),
+            ..checker_for_testing()
+        };
+        let mut config = Arc::into_inner(
+            parse(
+                "[api.fs]
+[api.net]
+[api.process]
+[pkg.foo]
+allow_apis = [\"fs\", \"net\"]
+",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        config.raw.declarations.declared.insert(
+            PackageName::from("foo"),
+            Declaration {
+                allow_apis: vec![ApiName::from("fs"), ApiName::from("process")],
+                ..Default::default()
+            },
+        );
+        checker.update_config(Arc::new(config));
+
+        let mut problems = ProblemList::default();
+        checker
+            .api_used(&api_usages("foo", "fs", &["std::fs::read"]), &mut problems)
+            .unwrap();
+        assert!(checker.check_declarations().is_empty());
+
+        checker
+            .api_used(
+                &api_usages("foo", "net", &["std::net::TcpStream"]),
+                &mut problems,
+            )
+            .unwrap();
+        assert_eq!(
+            checker.check_declarations().take(),
+            vec![Problem::UndeclaredApis(UndeclaredApis {
+                perm_sel: PermSel::for_primary(PackageName::from("foo").0),
+                undeclared: vec![ApiName::from("net")],
+                unobserved: vec![ApiName::from("process")],
+            })]
+        );
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let checker = |config: &str| {
+            let mut checker = Checker {
+                crate_index: crate::crate_index::testing::index_with_package_names(&["foo"]),
+                ..checker_for_testing()
+            };
+            checker.update_config(parse(config).unwrap());
+            checker.check_version_mismatches()
+        };
+        assert!(checker("[pkg.foo]\nversion = \"0.*\"").is_empty());
+        assert_eq!(
+            checker("[pkg.foo]\nversion = \"1.*\"").take(),
+            vec![Problem::VersionMismatch(VersionMismatch {
+                pkg_id: crate::crate_index::testing::pkg_id("foo"),
+                version_req: "1.*".parse().unwrap(),
+            })]
+        );
+    }
+
     #[test]
     fn reload_config() {
         let config = parse(
@@ -689,12 +1673,15 @@ mod tests {
                     permission_scope: PermissionScope::All,
                     source_location: SourceLocation::new(Path::new("lib.rs"), 1, None),
                     outer_location: None,
+                    call_chain: Vec::new(),
+                    similar_usages: 0,
                     from: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
                     to_name: crate::names::split_simple("foo::bar"),
                     to: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
                     to_source: NameSource::Symbol(Symbol::borrowed(b"foo::bar")),
                     debug_data: None,
                 }],
+                severity: Severity::Error,
             };
             checker.api_used(&api_usage, &mut problems).unwrap();
         }
@@ -706,4 +1693,210 @@ mod tests {
         checker.update_config(config);
         assert!(checker.check_unused().unwrap().is_empty());
     }
+
+    fn usage_of(to: &str) -> ApiUsage {
+        ApiUsage {
+            bin_location: BinLocation {
+                address: 0,
+                symbol_start: 0,
+            },
+            bin_path: Arc::from(Path::new("bin")),
+            permission_scope: PermissionScope::All,
+            source_location: SourceLocation::new(Path::new("lib.rs"), 1, None),
+            outer_location: None,
+            call_chain: Vec::new(),
+            similar_usages: 0,
+            from: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+            to_name: crate::names::split_simple(to),
+            to: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+            to_source: NameSource::Symbol(Symbol::borrowed(b"")),
+            debug_data: None,
+        }
+    }
+
+    fn api_usages(pkg_name: &str, api: &'static str, to: &[&str]) -> ApiUsages {
+        ApiUsages {
+            pkg_id: crate::crate_index::testing::pkg_id(pkg_name),
+            scope: PermissionScope::All,
+            api_name: ApiName::from(api),
+            usages: to.iter().map(|to| usage_of(to)).collect(),
+            severity: Severity::Error,
+        }
+    }
+
+    #[test]
+    fn allow_mode_api() {
+        let config = parse(
+            r#"
+            [api.fs]
+            include = ["std::fs"]
+            mode = "Allow"
+
+            [api.net]
+            include = ["std::net"]
+
+            [pkg.bar]
+            deny_apis = ["fs"]
+        "#,
+        )
+        .unwrap();
+        let mut checker = Checker {
+            crate_index: crate::crate_index::testing::index_with_package_names(&["foo", "bar"]),
+            ..checker_for_testing()
+        };
+        checker.update_config(config);
+
+        let mut problems = ProblemList::default();
+        checker
+            .api_used(&api_usages("foo", "fs", &["std::fs::read"]), &mut problems)
+            .unwrap();
+        assert!(problems.is_empty());
+
+        checker
+            .api_used(
+                &api_usages("foo", "net", &["std::net::TcpStream"]),
+                &mut problems,
+            )
+            .unwrap();
+        assert_eq!(
+            problems,
+            Problem::DisallowedApiUsage(api_usages("foo", "net", &["std::net::TcpStream"])).into()
+        );
+
+        let mut problems = ProblemList::default();
+        checker
+            .api_used(&api_usages("bar", "fs", &["std::fs::read"]), &mut problems)
+            .unwrap();
+        assert_eq!(
+            problems,
+            Problem::DisallowedApiUsage(api_usages("bar", "fs", &["std::fs::read"])).into()
+        );
+    }
+
+    #[test]
+    fn api_severity() {
+        let config = parse(
+            r#"
+            [api.terminate]
+            include = ["std::process::exit"]
+            severity = "info"
+
+            [api.process]
+            include = ["std::process"]
+            exclude = ["std::process::exit"]
+            severity = "deny"
+
+            [api.env]
+            include = ["std::env"]
+            severity = "warn"
+        "#,
+        )
+        .unwrap();
+        let mut checker = checker_for_testing();
+        checker.update_config(config);
+        assert_eq!(
+            checker.api_severity(&ApiName::from("terminate")),
+            Severity::Info
+        );
+        assert_eq!(
+            checker.api_severity(&ApiName::from("process")),
+            Severity::Error
+        );
+        assert_eq!(
+            checker.api_severity(&ApiName::from("env")),
+            Severity::Warning
+        );
+        assert_eq!(checker.api_severity(&ApiName::from("fs")), Severity::Error);
+    }
+
+    #[test]
+    fn ignore_rules() {
+        let config = parse(
+            r#"
+            [api.fs]
+            include = ["std::fs"]
+
+            [[ignore]]
+            pkg = "foo"
+            api = "fs"
+            path = "src/testing/**"
+        "#,
+        )
+        .unwrap();
+        let mut checker = Checker {
+            crate_index: crate::crate_index::testing::index_with_package_names(&["foo", "bar"]),
+            ..checker_for_testing()
+        };
+        checker.update_config(config);
+
+        let from_file = |pkg_name: &str, filename: &str| {
+            let mut usages = api_usages(pkg_name, "fs", &["std::fs::read"]);
+            usages.usages[0].source_location = SourceLocation::new(Path::new(filename), 1, None);
+            usages
+        };
+
+        let mut problems = ProblemList::default();
+        checker
+            .api_used(&from_file("foo", "src/testing/helpers.rs"), &mut problems)
+            .unwrap();
+        assert!(problems.is_empty());
+
+        checker
+            .api_used(&from_file("foo", "src/lib.rs"), &mut problems)
+            .unwrap();
+        assert_eq!(
+            problems,
+            Problem::DisallowedApiUsage(from_file("foo", "src/lib.rs")).into()
+        );
+
+        let mut problems = ProblemList::default();
+        checker
+            .api_used(&from_file("bar", "src/testing/helpers.rs"), &mut problems)
+            .unwrap();
+        assert_eq!(
+            problems,
+            Problem::DisallowedApiUsage(from_file("bar", "src/testing/helpers.rs")).into()
+        );
+    }
+
+    #[test]
+    fn allow_fn() {
+        let config = parse(
+            r#"
+            [api.process]
+            include = [
+                "std::process",
+            ]
+            [pkg.foo]
+            allow_fn = [
+                "std::process::Command::new",
+            ]
+        "#,
+        )
+        .unwrap();
+        let mut checker = Checker {
+            crate_index: crate::crate_index::testing::index_with_package_names(&["foo"]),
+            ..checker_for_testing()
+        };
+        checker.update_config(config);
+
+        let api_usages = |to: &[&str]| api_usages("foo", "process", to);
+
+        let mut problems = ProblemList::default();
+        checker
+            .api_used(&api_usages(&["std::process::Command::new"]), &mut problems)
+            .unwrap();
+        assert!(problems.is_empty());
+
+        checker
+            .api_used(
+                &api_usages(&["std::process::Command::new", "std::process::Command::spawn"]),
+                &mut problems,
+            )
+            .unwrap();
+        assert_eq!(
+            problems,
+            Problem::DisallowedApiUsage(api_usages(&["std::process::Command::spawn"])).into()
+        );
+    }
 }
@@ -1,4 +1,5 @@
 use crate::config::ApiName;
+use crate::names::WILDCARD;
 use fxhash::FxHashMap;
 use fxhash::FxHashSet;
 
@@ -12,10 +13,16 @@ use fxhash::FxHashSet;
 ///
 /// Lookups are done using iterators, which allows us to efficiently find the permissions for a path
 /// without heap allocation.
+///
+/// A path part of `*` matches any single part. These are stored as a separate wildcard child, which
+/// is used when there's no child for the exact part. So that lookup never needs to consider more
+/// than one child, `expand_wildcards` copies the structure of each wildcard child into its exact
+/// siblings and `update_matching` applies updates to all nodes that a pattern matches.
 #[derive(Default)]
 pub(super) struct ApiMap {
     apis: FxHashSet<ApiName>,
     map: FxHashMap<String, Box<ApiMap>>,
+    wildcard: Option<Box<ApiMap>>,
 }
 
 impl ApiMap {
@@ -25,42 +32,79 @@ impl ApiMap {
     pub(super) fn get<'a>(&self, mut key_it: impl Iterator<Item = &'a str>) -> &FxHashSet<ApiName> {
         key_it
             .next()
-            .and_then(|key| self.map.get(key))
+            .and_then(|key| self.map.get(key).or(self.wildcard.as_ref()))
             .map(|sub| sub.get(key_it))
             .unwrap_or(&self.apis) as _
     }
 
     /// Creates nodes to represent the name produced by `key_it`. This should be called for all path
-    /// prefixes that we care about before calling `mut_tree` on those names path prefixes.
+    /// prefixes that we care about, followed by `expand_wildcards`, before calling
+    /// `update_matching` on those path prefixes.
     pub(super) fn create_entry<'a>(&mut self, mut key_it: impl Iterator<Item = &'a str>) {
         if let Some(key) = key_it.next() {
+            if key == WILDCARD {
+                self.wildcard.get_or_insert_with(Default::default)
+            } else {
+                self.map.entry(key.to_owned()).or_default()
+            }
+            .create_entry(key_it)
+        }
+    }
+
+    /// Copies the structure of each wildcard child into its exact siblings, so that a path that
+    /// matches an exact child finds nodes for all the patterns that it matches.
+    pub(super) fn expand_wildcards(&mut self) {
+        if let Some(wildcard) = self.wildcard.as_ref() {
+            for child in self.map.values_mut() {
+                child.merge_structure(wildcard);
+            }
+        }
+        for child in self.map.values_mut().chain(self.wildcard.as_mut()) {
+            child.expand_wildcards();
+        }
+    }
+
+    fn merge_structure(&mut self, other: &ApiMap) {
+        for (key, other_child) in &other.map {
             self.map
-                .entry(key.to_owned())
+                .entry(key.clone())
                 .or_default()
-                .create_entry(key_it)
+                .merge_structure(other_child);
+        }
+        if let Some(other_wildcard) = other.wildcard.as_ref() {
+            self.wildcard
+                .get_or_insert_with(Default::default)
+                .merge_structure(other_wildcard);
         }
     }
 
-    /// Returns the mutable tree rooted at the path indicated by `key_it`. Panics if such a tree
-    /// doesn't exit. i.e. you must have previously called `create_entry` for `key_it`.
-    pub(super) fn mut_tree<'a>(
+    /// Calls `update_subtree` on every node matched by `key`. Panics if `key` has no node. i.e. you
+    /// must have previously called `create_entry` for `key`.
+    pub(super) fn update_matching(
         &mut self,
-        mut key_it: impl Iterator<Item = &'a str>,
-    ) -> &mut ApiMap {
-        match key_it.next() {
-            Some(key) => self
-                .map
-                .get_mut(key)
-                .expect("mut_tree called without calling create_entry")
-                .mut_tree(key_it),
-            _ => self,
+        key: &[&str],
+        mutator: &impl Fn(&mut FxHashSet<ApiName>),
+    ) {
+        let Some((first, rest)) = key.split_first() else {
+            self.update_subtree(mutator);
+            return;
+        };
+        if *first == WILDCARD {
+            for child in self.map.values_mut().chain(self.wildcard.as_mut()) {
+                child.update_matching(rest, mutator);
+            }
+        } else {
+            self.map
+                .get_mut(*first)
+                .expect("update_matching called without calling create_entry")
+                .update_matching(rest, mutator);
         }
     }
 
     /// Modifies the APIs for this node in the subtree and all child nodes.
     pub(super) fn update_subtree(&mut self, mutator: &impl Fn(&mut FxHashSet<ApiName>)) {
         (mutator)(&mut self.apis);
-        for subtree in self.map.values_mut() {
+        for subtree in self.map.values_mut().chain(self.wildcard.as_mut()) {
             subtree.update_subtree(mutator);
         }
     }
@@ -68,5 +112,6 @@ impl ApiMap {
     pub(crate) fn clear(&mut self) {
         self.apis.clear();
         self.map.clear();
+        self.wildcard = None;
     }
 }
@@ -20,10 +20,13 @@ pub(crate) fn common_to_prefixes(usages: &ApiUsages) -> Result<Vec<String>> {
 }
 
 pub(crate) fn common_prefixes(usages: &ApiUsages, from: bool) -> Result<Vec<String>> {
+    // Buffers for any symbols that need to be demangled. These need to outlive `checker`, since
+    // it holds references into them.
+    let mut buffers = vec![String::new(); usages.usages.len()];
     let mut checker = CommonPrefixChecker::default();
 
-    for usage in &usages.usages {
-        checker.check_usage(usage, from)?;
+    for (usage, buffer) in usages.usages.iter().zip(buffers.iter_mut()) {
+        checker.check_usage(usage, from, buffer)?;
     }
     let mut prefixes: Vec<String> = checker.common.into_iter().map(|s| s.join("::")).collect();
     prefixes.sort();
@@ -37,11 +40,16 @@ struct CommonPrefixChecker<'input> {
 }
 
 impl<'input> CommonPrefixChecker<'input> {
-    fn check_usage(&mut self, usage: &'input ApiUsage, from: bool) -> Result<()> {
+    fn check_usage(
+        &mut self,
+        usage: &'input ApiUsage,
+        from: bool,
+        buffer: &'input mut String,
+    ) -> Result<()> {
         let name = if from { &usage.from } else { &usage.to };
         match name {
             SymbolOrDebugName::Symbol(symbol) => {
-                self.check_names(symbol.names()?)?;
+                self.check_names(symbol.names(buffer)?)?;
             }
             SymbolOrDebugName::DebugName(debug_name) => {
                 self.check_names(debug_name.names_iterator())?;
@@ -0,0 +1,148 @@
+//! Code generated by a proc macro usually has debug info that points at the location where the
+//! macro was invoked. This means that, by default, API usages in generated code are attributed to
+//! the crate that invoked the macro. This module works out from debug info when an API usage is in
+//! code generated by a proc macro and, using the crate index, which proc macro crate was most likely
+//! responsible, so that such usages can optionally be attributed to the proc macro instead.
+//!
+//! Tokens produced by a proc macro get the span of the macro invocation. So when a proc macro
+//! generates a function, as derives do, the function is declared at the invocation and so is all the
+//! code within it. A usage on the same line as the declaration of its containing function is
+//! therefore a candidate. We then look at the names of the containing function for a path that
+//! belongs to one of the proc macros that the invoking package depends on, such as the trait in
+//! `<Foo as serde::ser::Serialize>::serialize`, or for the anonymous `const _` that derives
+//! generally wrap their impls in.
+
+use crate::crate_index::CrateIndex;
+use crate::crate_index::PackageId;
+use crate::location::SourceLocation;
+use crate::names::Name;
+
+/// What debug info tells us about the function that contains an API usage.
+pub(crate) struct ContainingFunction {
+    /// The names obtained from the function's debug name and symbol. For a trait impl, these
+    /// include the path of the trait.
+    pub(crate) names: Vec<Name>,
+    /// Where the function is declared, if known.
+    pub(crate) declared_at: Option<SourceLocation>,
+}
+
+/// The name of the namespace for items within `const _: () = { ... }`.
+const ANONYMOUS_CONST: &str = "_";
+
+/// Returns the proc macro that most likely generated the code at `location`, which is within
+/// `function` in `pkg_id`. Returns `None` if the code doesn't look like it was generated by a proc
+/// macro, or we can't determine which proc macro is responsible.
+pub(crate) fn generating_proc_macro<'a>(
+    crate_index: &'a CrateIndex,
+    pkg_id: &PackageId,
+    location: &SourceLocation,
+    function: &ContainingFunction,
+) -> Option<&'a PackageId> {
+    pick_proc_macro(
+        pkg_id,
+        location,
+        function,
+        crate_index.proc_macro_deps(pkg_id),
+    )
+}
+
+fn pick_proc_macro<'a>(
+    pkg_id: &PackageId,
+    location: &SourceLocation,
+    function: &ContainingFunction,
+    candidates: &'a [PackageId],
+) -> Option<&'a PackageId> {
+    if candidates.is_empty() {
+        return None;
+    }
+    let declared_at = function.declared_at.as_ref()?;
+    if declared_at.filename() != location.filename() || declared_at.line() != location.line() {
+        return None;
+    }
+    let own_crate_name = pkg_id.crate_name();
+    let mut in_anonymous_const = false;
+    for name in &function.names {
+        let Some(first) = name.parts().next() else {
+            continue;
+        };
+        in_anonymous_const |= name.parts().any(|part| part == ANONYMOUS_CONST);
+        if first == own_crate_name {
+            continue;
+        }
+        // The macro's crate is often re-exported by a crate with a similar name, e.g. `serde`
+        // for `serde_derive` or `tokio` for `tokio_macros`.
+        if let Some(pkg_id) = candidates.iter().find(|pkg_id| {
+            let name = pkg_id.crate_name();
+            name == first
+                || name
+                    .strip_prefix(first)
+                    .is_some_and(|rest| rest.starts_with('_'))
+        }) {
+            return Some(pkg_id);
+        }
+    }
+    // If the function is in an anonymous const, but implements a trait that we can't tie to a
+    // particular proc macro, then only guess if there's a single proc macro that could have
+    // generated it.
+    if in_anonymous_const && candidates.len() == 1 {
+        return candidates.first();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crate_index::testing::pkg_id;
+    use crate::names::split_simple;
+
+    fn function(names: &[&str], line: u32) -> ContainingFunction {
+        ContainingFunction {
+            names: names.iter().map(|name| split_simple(name)).collect(),
+            declared_at: Some(SourceLocation::new(
+                std::path::Path::new("src/lib.rs"),
+                line,
+                Some(10),
+            )),
+        }
+    }
+
+    #[test]
+    fn pick() {
+        let invoker = pkg_id("my-app");
+        let serde_derive = pkg_id("serde_derive");
+        let tokio_macros = pkg_id("tokio-macros");
+        let both = [serde_derive.clone(), tokio_macros.clone()];
+        let location = SourceLocation::new(std::path::Path::new("src/lib.rs"), 5, Some(17));
+        let pick = |function: &ContainingFunction, candidates| {
+            pick_proc_macro(&invoker, &location, function, candidates)
+        };
+
+        // `<my_app::Foo as serde::ser::Serialize>::serialize`, generated by a derive.
+        let serialize = function(&["my_app::Foo", "serde::ser::Serialize::serialize"], 5);
+        assert_eq!(pick(&serialize, &both), Some(&serde_derive));
+        assert_eq!(pick(&serialize, &[]), None);
+
+        // The same impl, but written by hand, so the usage isn't on the line where the function
+        // is declared.
+        let hand_written = function(&["my_app::Foo", "serde::ser::Serialize::serialize"], 3);
+        assert_eq!(pick(&hand_written, &both), None);
+
+        // A derive whose trait we can't tie to a proc macro, but which is in an anonymous const.
+        let anonymous = function(&["my_app::_::Foo", "other::Trait::method"], 5);
+        assert_eq!(pick(&anonymous, &both), None);
+        assert_eq!(
+            pick(&anonymous, std::slice::from_ref(&serde_derive)),
+            Some(&serde_derive)
+        );
+
+        // A one-line function in the invoking crate that doesn't involve any proc macro.
+        let plain = function(&["my_app::read_config"], 5);
+        assert_eq!(pick(&plain, std::slice::from_ref(&serde_derive)), None);
+        let no_decl = ContainingFunction {
+            declared_at: None,
+            ..function(&["serde::ser::Serialize::serialize"], 5)
+        };
+        assert_eq!(pick(&no_decl, &both), None);
+    }
+}
@@ -0,0 +1,211 @@
+//! Support for `--ci`, which runs without any UI, reports problems grouped by crate and exits with a
+//! code that indicates why it failed.
+
+use crate::cli::Args;
+use crate::config::InvalidConfig;
+use crate::lint_config;
+use crate::outcome::ExitCode;
+use crate::problem::Problem;
+use crate::problem::ProblemList;
+use crate::problem::Severity;
+use crate::proxy::BuildFailed;
+use clap::ValueEnum;
+use colored::Colorize;
+use std::collections::BTreeMap;
+
+/// Exit code when problems were found.
+pub(crate) const PROBLEMS_FOUND: ExitCode = ExitCode(1);
+
+/// Exit code when the config couldn't be loaded.
+pub(crate) const CONFIG_ERROR: ExitCode = ExitCode(2);
+
+/// Exit code when cargo failed for reasons other than problems that we found.
+pub(crate) const BUILD_FAILED: ExitCode = ExitCode(3);
+
+/// Kinds of problem whose severity can be changed with `--deny` and `--warn`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ProblemKind {
+    /// Use of APIs that aren't permitted by the config. Denied by default.
+    NewUsages,
+    /// Config that isn't needed, such as allowed APIs that aren't used. Warned by default.
+    Unused,
+}
+
+impl ProblemKind {
+    fn matches(self, problem: &Problem) -> bool {
+        match self {
+            ProblemKind::NewUsages => matches!(
+                problem,
+                Problem::DisallowedApiUsage(..) | Problem::OffTreeApiUsage(..)
+            ),
+            ProblemKind::Unused => lint_config::is_lint(problem),
+        }
+    }
+}
+
+impl Args {
+    /// Returns the severity of `problem`, taking into account `--deny` and `--warn`. If a kind of
+    /// problem is both denied and warned, then it's denied. `--warn` never raises the severity of a
+    /// problem, so usages of APIs configured with `severity = "info"` stay informational.
+    pub(crate) fn ci_severity(&self, problem: &Problem) -> Severity {
+        if self.deny.iter().any(|kind| kind.matches(problem)) {
+            Severity::Error
+        } else if self.warn.iter().any(|kind| kind.matches(problem)) {
+            problem.severity().min(Severity::Warning)
+        } else {
+            problem.severity()
+        }
+    }
+
+    /// Removes from `problems` and returns those that won't fail the build, but which the UI has no
+    /// way to resolve. These are problems that are normally errors, but which were downgraded by
+    /// `--warn`, and usages of APIs whose configured severity is `warn` or `info`. If left to the
+    /// UI, they'd just be found again when we recheck, so they're reported once we're done. When
+    /// running as a language server, all API usages are taken, since they're published as
+    /// diagnostics rather than stopping the build.
+    pub(crate) fn take_downgraded_problems(&self, problems: &mut ProblemList) -> ProblemList {
+        let mut downgraded = ProblemList::default();
+        let mut kept = ProblemList::default();
+        for problem in std::mem::take(problems).take() {
+            let severity = self.ci_severity(&problem);
+            let fails_build = severity == Severity::Error
+                || (severity == Severity::Warning && self.fail_on_warnings);
+            let is_lsp_diagnostic = matches!(self.command, Some(crate::cli::Command::Lsp))
+                && ProblemKind::NewUsages.matches(&problem);
+            if is_lsp_diagnostic
+                || (!fails_build
+                    && (problem.severity() == Severity::Error
+                        || ProblemKind::NewUsages.matches(&problem)))
+            {
+                downgraded.push(problem);
+            } else {
+                kept.push(problem);
+            }
+        }
+        *problems = kept;
+        downgraded
+    }
+}
+
+/// Returns the exit code to use in CI mode when we failed with `error`.
+pub(crate) fn exit_code_for_error(error: &anyhow::Error) -> ExitCode {
+    if error.is::<InvalidConfig>() {
+        CONFIG_ERROR
+    } else if error.is::<BuildFailed>() {
+        BUILD_FAILED
+    } else {
+        crate::outcome::FAILURE
+    }
+}
+
+/// Prints `problems` grouped by the crate that they relate to. Problems that don't relate to a
+/// particular crate are printed first.
+pub(crate) fn print_grouped(problems: &[(Severity, &Problem)]) {
+    let mut groups: BTreeMap<Option<String>, Vec<(Severity, &Problem)>> = BTreeMap::new();
+    for &(severity, problem) in problems {
+        let pkg = problem.pkg_id().map(|pkg_id| pkg_id.to_string());
+        groups.entry(pkg).or_default().push((severity, problem));
+    }
+    for (pkg, mut group) in groups {
+        group.sort_by_key(|(severity, _)| std::cmp::Reverse(*severity));
+        if let Some(pkg) = pkg {
+            println!("{}", pkg.bold());
+        }
+        for (severity, problem) in group {
+            let label = match severity {
+                Severity::Info => "INFO:".cyan(),
+                Severity::Warning => "WARNING:".yellow(),
+                Severity::Error => "ERROR:".red(),
+            };
+            let text = format!("{problem:#}");
+            println!("  {label} {}", text.trim_end().replace('\n', "\n    "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProblemKind;
+    use crate::cli::Args;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::ApiUsages;
+    use crate::problem::Problem;
+    use crate::problem::ProblemList;
+    use crate::problem::Severity;
+
+    #[test]
+    fn severity_overrides() {
+        let unused = Problem::UnusedPackageConfig(PermSel::for_primary("crab1"));
+        let message = Problem::new("Something went wrong");
+        let mut args = Args::default();
+        assert_eq!(args.ci_severity(&unused), Severity::Warning);
+
+        args.deny = vec![ProblemKind::Unused];
+        assert_eq!(args.ci_severity(&unused), Severity::Error);
+        assert_eq!(args.ci_severity(&message), Severity::Error);
+
+        // Only problems that would otherwise be errors get taken.
+        let usage = Problem::DisallowedApiUsage(ApiUsages {
+            pkg_id: pkg_id("crab1"),
+            scope: PermissionScope::All,
+            api_name: ApiName::from("fs"),
+            usages: Vec::new(),
+            severity: Severity::Error,
+        });
+        args.deny.clear();
+        args.warn = vec![ProblemKind::Unused, ProblemKind::NewUsages];
+        let mut problems = ProblemList::default();
+        problems.push(unused.clone());
+        problems.push(usage.clone());
+        problems.push(message.clone());
+        let downgraded = args.take_downgraded_problems(&mut problems);
+        assert_eq!(downgraded.take(), vec![usage]);
+        assert_eq!(problems.take(), vec![unused, message]);
+    }
+
+    #[test]
+    fn configured_api_severity() {
+        let usage = |api: &'static str, severity| {
+            Problem::DisallowedApiUsage(ApiUsages {
+                pkg_id: pkg_id("crab1"),
+                scope: PermissionScope::All,
+                api_name: ApiName::from(api),
+                usages: Vec::new(),
+                severity,
+            })
+        };
+        let info = usage("terminate", Severity::Info);
+        let warning = usage("env", Severity::Warning);
+        let error = usage("process", Severity::Error);
+        let mut args = Args {
+            warn: vec![ProblemKind::NewUsages],
+            ..Args::default()
+        };
+
+        // `--warn` doesn't make informational usages more severe.
+        assert_eq!(args.ci_severity(&info), Severity::Info);
+        args.warn.clear();
+
+        let mut problems = ProblemList::default();
+        problems.push(info.clone());
+        problems.push(warning.clone());
+        problems.push(error.clone());
+        let downgraded = args.take_downgraded_problems(&mut problems.clone());
+        assert_eq!(downgraded.take(), vec![info.clone(), warning.clone()]);
+
+        args.fail_on_warnings = true;
+        let downgraded = args.take_downgraded_problems(&mut problems.clone());
+        assert_eq!(downgraded.take(), vec![info]);
+
+        args.deny = vec![ProblemKind::NewUsages];
+        let downgraded = args.take_downgraded_problems(&mut problems);
+        assert!(downgraded.is_empty());
+        assert_eq!(
+            problems.take(),
+            vec![usage("terminate", Severity::Info), warning, error]
+        );
+    }
+}
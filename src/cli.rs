@@ -0,0 +1,1356 @@
+//! The `cargo acl` command line: argument parsing and the top-level flow of a run, which builds
+//! the crate, feeds requests from our proxy subprocesses to the checker and reports the outcome.
+
+use crate::analysis;
+use crate::attestation::AttestOptions;
+use crate::checker::Checker;
+use crate::ci;
+use crate::colour;
+use crate::config;
+use crate::crate_index::CrateIndex;
+use crate::edit_command::EditCommand;
+use crate::events::AppEvent;
+use crate::graph::GraphOptions;
+use crate::lint_config;
+use crate::lint_config::LintConfigOptions;
+use crate::logging;
+use crate::lsp;
+use crate::outcome;
+use crate::outcome::ExitCode;
+use crate::outcome::Outcome;
+use crate::problem;
+use crate::problem::Problem;
+use crate::problem_store::ProblemStoreRef;
+use crate::proxy;
+use crate::proxy::cargo::profile_name;
+use crate::proxy::cargo::CargoOptions;
+use crate::proxy::rpc::Request;
+use crate::proxy::subprocess::PROXY_BIN_ARG;
+use crate::proxy::CargoOutputWaiter;
+use crate::replay;
+use crate::replay::ReplayOptions;
+use crate::report::diff::Baseline;
+use crate::report::diff::DiffOptions;
+use crate::report::export::ExportOptions;
+use crate::report::ReportOptions;
+use crate::rust_std::RustStd;
+use crate::sandbox;
+use crate::sarif;
+use crate::summary;
+use crate::summary::SummaryOptions;
+use crate::symbol_graph::ScanOutputs;
+use crate::tmpdir::TempDir;
+use crate::ui;
+use crate::watch;
+use crate::watch::WatchOptions;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use clap::Subcommand;
+use fxhash::FxHashMap;
+use log::info;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+#[derive(Parser, Debug, Clone)]
+#[clap()]
+pub(crate) struct OuterArgs {
+    #[command(subcommand)]
+    pub(crate) command: OuterCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum OuterCommand {
+    // `cackle` is the subcommand name when invoked as `cargo cackle`.
+    #[command(alias = "cackle")]
+    Acl(Args),
+}
+
+#[derive(Parser, Debug, Clone, Default)]
+#[clap(version, about)]
+pub(crate) struct Args {
+    /// Directory containing crate to analyze. Defaults to current working directory.
+    #[clap(long)]
+    pub(crate) path: Option<PathBuf>,
+
+    /// Path to cackle.toml. Defaults to cackle.toml in the directory containing Cargo.toml.
+    #[clap(short, long)]
+    pub(crate) cackle_path: Option<PathBuf>,
+
+    /// Print the mapping from paths to crate names. Useful for debugging.
+    #[clap(long, hide = true)]
+    pub(crate) print_path_to_crate_map: bool,
+
+    /// Promotes warnings (e.g. due to unused permissions) to errors.
+    #[clap(long)]
+    pub(crate) fail_on_warnings: bool,
+
+    /// Ignore newer config versions.
+    #[clap(long)]
+    pub(crate) ignore_newer_config_versions: bool,
+
+    /// Whether to use coloured output.
+    #[clap(long, alias = "color", default_value = "auto")]
+    pub(crate) colour: colour::Colour,
+
+    /// Don't print anything on success.
+    #[clap(long)]
+    pub(crate) quiet: bool,
+
+    /// Override the target used when compiling. e.g. "x86_64-unknown-linux-gnu".
+    #[clap(long)]
+    pub(crate) target: Option<String>,
+
+    /// Override build profile.
+    #[clap(long)]
+    pub(crate) profile: Option<String>,
+
+    /// Features to pass to cargo. Overrides common.features in config.
+    #[clap(long)]
+    pub(crate) features: Option<String>,
+
+    /// Print how long various things take to run.
+    #[clap(long)]
+    pub(crate) print_timing: bool,
+
+    /// Print additional information that's probably only useful for debugging.
+    #[clap(long)]
+    pub(crate) debug: bool,
+
+    /// Output file for logs that might be useful for diagnosing problems.
+    #[clap(long)]
+    pub(crate) log_file: Option<PathBuf>,
+
+    /// How detailed the logs should be.
+    #[clap(long, default_value = "info")]
+    pub(crate) log_level: logging::LevelFilter,
+
+    /// Record the information needed to rerun the checks into the specified file. The checks can
+    /// then be rerun later without rebuilding, via the `replay` subcommand.
+    #[clap(long)]
+    pub(crate) record: Option<PathBuf>,
+
+    /// When specified, writes all requests into a subdirectory of the target directory. For
+    /// debugging use.
+    #[clap(long, hide = true)]
+    pub(crate) save_requests: bool,
+
+    /// Instead of running `cargo build`, replay requests saved by a previous run where
+    /// --write-requests was specified. For debugging use.
+    #[clap(long, hide = true)]
+    pub(crate) replay_requests: bool,
+
+    /// How proxy subprocesses communicate with us. Defaults to a Unix domain socket where available.
+    #[clap(long, hide = true)]
+    pub(crate) ipc_transport: Option<proxy::transport::TransportKind>,
+
+    /// Temporary directory for Cackle to use. This is intended for testing purposes.
+    #[clap(long, hide = true)]
+    pub(crate) tmpdir: Option<PathBuf>,
+
+    /// What kind of user interface to use.
+    #[clap(long)]
+    pub(crate) ui: Option<ui::Kind>,
+
+    /// Disable interactive UI.
+    #[clap(long, short)]
+    pub(crate) no_ui: bool,
+
+    /// Command that the interactive UI uses to open a source file when `e` is pressed. `{file}`,
+    /// `{line}` and `{column}` are replaced with the location to open and `{editor}` with `$VISUAL`
+    /// or `$EDITOR`. The command is split on whitespace. Defaults to "{editor} +{line} {file}".
+    #[clap(long)]
+    pub(crate) editor: Option<String>,
+
+    /// Run non-interactively for use in CI. Problems are printed grouped by crate and the exit code
+    /// is 1 if problems were found, 2 if the config couldn't be loaded and 3 if the build failed.
+    #[clap(long)]
+    pub(crate) ci: bool,
+
+    /// In CI mode, fail if the specified kind of problem is found.
+    #[clap(long, value_enum, requires = "ci")]
+    pub(crate) deny: Vec<ci::ProblemKind>,
+
+    /// In CI mode, report the specified kind of problem as a warning rather than failing.
+    #[clap(long, value_enum, requires = "ci")]
+    pub(crate) warn: Vec<ci::ProblemKind>,
+
+    /// The format in which to report problems. Formats other than text imply `--no-ui`.
+    #[clap(long, value_enum, default_value_t)]
+    pub(crate) output_format: sarif::OutputFormat,
+
+    /// File to write problems to when `--output-format` is something other than text. Defaults to
+    /// stdout, in which case nothing else is printed to stdout on success.
+    #[clap(long)]
+    pub(crate) output_file: Option<PathBuf>,
+
+    /// Disable backtraces (may reduce peak memory consumption).
+    #[clap(long)]
+    pub(crate) no_backtrace: bool,
+
+    /// Run sandboxed binaries (e.g. build scripts) under strace and report any file or network
+    /// accesses that were denied. Useful for figuring out why something failed in the sandbox.
+    #[clap(long)]
+    pub(crate) trace_sandbox: bool,
+
+    /// Report API usages even if they're unreachable from the binary's entry points. Overrides
+    /// common.prune_unreachable.
+    #[clap(long)]
+    pub(crate) no_prune_unreachable: bool,
+
+    // We may at some point allow this to be a short flag, but should probably wait a few releases.
+    // -p was previously accepted for --path.
+    /// Packages to build and analyse.
+    #[clap(long)]
+    pub(crate) package: Vec<String>,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum Command {
+    /// Print summary of permissions used.
+    Summary(SummaryOptions),
+
+    /// Run `cargo test`, analysing whatever gets built.
+    Test(CargoOptions),
+
+    /// Run `cargo run`, analysing whatever gets built.
+    Run(CargoOptions),
+
+    /// Build and analyse, then print the graph of references between crates or symbols.
+    Graph(GraphOptions),
+
+    /// Build and analyse, then write a report of the permissions used by each crate, where they're
+    /// used, use of unsafe and results of running sandboxed build scripts and tests.
+    Report(ReportOptions),
+
+    /// Build and analyse, then print which crates have started or stopped using which APIs
+    /// compared to a report written previously with `--output-format json`. Fails if any crate
+    /// uses an API that it didn't previously.
+    Diff(DiffOptions),
+
+    /// Build and analyse, then write the APIs used by each package in the format of another tool.
+    /// e.g. `--format cargo-vet` writes audit entries for cargo-vet's `audits.toml` and `--format
+    /// cyclonedx` writes an SBOM, each saying what each package was observed to use.
+    Export(ExportOptions),
+
+    /// Build and analyse, then sign and write attestations that the named packages have been
+    /// reviewed, recording what they're granted and a hash of what was built. Requires
+    /// `attestations.signing_key` to be configured.
+    Attest(AttestOptions),
+
+    /// Build and analyse, then write cackle-baseline.toml, granting whatever permissions are needed
+    /// to resolve current problems. Subsequent runs then only fail on new problems.
+    Baseline,
+
+    /// Build and analyse, then write a draft cackle.toml that grants whatever permissions are
+    /// currently used. Entries are marked for review.
+    Init,
+
+    /// Apply an edit to cackle.toml without building. Edits are made in the same way as when
+    /// accepting fixes in the interactive UI.
+    #[command(subcommand)]
+    Edit(EditCommand),
+
+    /// Build and analyse, then report config that is no longer needed, such as allowed APIs that
+    /// aren't used and config for packages that are no longer in the dependency tree.
+    LintConfig(LintConfigOptions),
+
+    /// Print which kinds of sandbox are available on this system and which protections the
+    /// strongest of them provides.
+    SandboxInfo,
+
+    /// Build and analyse, then keep rebuilding and reanalysing whenever files in the workspace
+    /// change, until stopped with control-c.
+    Watch(WatchOptions),
+
+    /// Run a language server on stdin and stdout, so that editors can show where crates use APIs
+    /// that they aren't permitted to use. Rechecks whenever a file is saved.
+    Lsp,
+
+    /// Build and analyse, serving a web page on localhost from which problems can be reviewed and
+    /// fixes accepted. Useful when building on a remote machine, with the port forwarded over SSH.
+    #[cfg(feature = "ui")]
+    Serve(ui::ServeOptions),
+
+    /// Rerun the checks against a recording made with `--record`, using the current config,
+    /// without rebuilding.
+    Replay(ReplayOptions),
+
+    /// Any other cargo subcommand, e.g. `check` or `bench`. It and all following arguments are
+    /// passed through to cargo and whatever gets built is analysed.
+    #[command(external_subcommand)]
+    Cargo(Vec<String>),
+
+    #[clap(hide = true, name = PROXY_BIN_ARG)]
+    ProxyBin(ProxyBinOptions),
+}
+
+impl Args {
+    /// Returns whether we're building and analysing all targets, as opposed to running a specific
+    /// cargo command like `test` or `run`.
+    pub(crate) fn is_full_build(&self) -> bool {
+        matches!(
+            self.command,
+            None | Some(Command::Graph(..))
+                | Some(Command::Report(..))
+                | Some(Command::Diff(..))
+                | Some(Command::Export(..))
+                | Some(Command::Attest(..))
+                | Some(Command::Init)
+                | Some(Command::Baseline)
+                | Some(Command::LintConfig(..))
+                | Some(Command::Lsp)
+        ) || self.is_serve()
+            || matches!(&self.command, Some(Command::Summary(options)) if options.table)
+    }
+
+    /// Returns whether problems are written to stdout in a machine-readable format, in which case
+    /// we shouldn't print them as text.
+    pub(crate) fn writes_report_to_stdout(&self) -> bool {
+        self.output_format != sarif::OutputFormat::Text && self.output_file.is_none()
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct ProxyBinOptions {
+    #[clap(allow_hyphen_values = true)]
+    remaining: Vec<String>,
+}
+
+/// Runs `cargo acl` with the arguments that we were invoked with. This is the entry point of the
+/// `cargo-acl` binary.
+pub fn cli_main() -> Result<()> {
+    analysis::handle_subprocess_invocation()?;
+
+    let outer = OuterArgs::parse();
+    let OuterCommand::Acl(mut args) = outer.command;
+    args.take_cargo_flags()?;
+    if args.writes_report_to_stdout() {
+        args.quiet = true;
+    }
+    if matches!(args.command, Some(Command::SandboxInfo)) {
+        print!("{}", sandbox::Capabilities::get().report());
+        return Ok(());
+    }
+    args.colour = args.colour.detect();
+    if let Some(log_file) = &args.log_file {
+        logging::init(log_file, args.log_level)?;
+    }
+    let (abort_send, abort_recv) = std::sync::mpsc::channel();
+    let is_ci = args.ci;
+    let cackle = match Cackle::new(args, abort_send) {
+        Ok(cackle) => cackle,
+        Err(error) if is_ci => {
+            println!("Error: {error:#}");
+            std::process::exit(ci::exit_code_for_error(&error).code());
+        }
+        Err(error) => return Err(error),
+    };
+    let exit_code = cackle.run_and_report_errors(abort_recv);
+    info!("Shutdown with exit code {}", exit_code);
+    std::process::exit(exit_code.code());
+}
+
+pub(crate) struct Cackle {
+    problem_store: ProblemStoreRef,
+    root_path: PathBuf,
+    config_path: PathBuf,
+    checker: Arc<Mutex<Checker>>,
+    tmpdir: Arc<TempDir>,
+    target_dir: PathBuf,
+    args: Arc<Args>,
+    event_sender: Sender<AppEvent>,
+    ui_join_handle: JoinHandle<Result<()>>,
+    cargo_output_waiter: Option<CargoOutputWaiter>,
+    crate_index: Arc<CrateIndex>,
+    abort_sender: Sender<()>,
+    lsp_server: Option<lsp::Server>,
+}
+
+impl Cackle {
+    pub(crate) fn new(args: Args, abort_sender: Sender<()>) -> Result<Self> {
+        // This needs to happen before anything gets printed, since the server takes over stdout.
+        let lsp_server = matches!(args.command, Some(Command::Lsp))
+            .then(lsp::Server::start)
+            .transpose()?;
+        let args = Arc::new(args);
+        let root_path = root_path(&args)?;
+        let root_path = Path::new(&root_path)
+            .canonicalize()
+            .with_context(|| format!("Failed to read directory `{}`", root_path.display()))?;
+
+        let config_path = args
+            .cackle_path
+            .clone()
+            .unwrap_or_else(|| root_path.join("cackle.toml"));
+
+        if config_path.exists() {
+            if let Some(migrated) =
+                config::schema::migrate_file(&config_path).context(config::InvalidConfig)?
+            {
+                println!(
+                    "Migrated {} from schema version {} to {}. The original was saved as {}",
+                    config_path.display(),
+                    migrated.from,
+                    migrated.to,
+                    migrated.backup_path.display()
+                );
+            }
+        }
+
+        let features = match &args.features {
+            Some(features) => features.split(',').map(str::to_owned).collect(),
+            None => config::configured_features(&config_path).context(config::InvalidConfig)?,
+        };
+        let crate_index = Arc::new(CrateIndex::new(&root_path, &features)?);
+        let target_dir = root_path.join(
+            std::env::var("CARGO_TARGET_DIR")
+                .as_deref()
+                .unwrap_or("target"),
+        );
+        let tmpdir = Arc::new(TempDir::new(args.tmpdir.as_deref())?);
+        let checker = Arc::new(Mutex::new(Checker::new(
+            tmpdir.clone(),
+            target_dir.clone(),
+            args.clone(),
+            RustStd::detect(&root_path)?,
+            crate_index.clone(),
+            config_path.clone(),
+        )));
+        let (event_sender, event_receiver) = std::sync::mpsc::channel();
+        let problem_store = crate::problem_store::create(event_sender.clone());
+        let ui_join_handle = ui::start_ui(
+            &args,
+            &config_path,
+            &checker,
+            problem_store.clone(),
+            event_receiver,
+            &event_sender,
+            abort_sender.clone(),
+        )?;
+        Ok(Self {
+            problem_store,
+            root_path,
+            config_path,
+            checker,
+            args,
+            event_sender,
+            ui_join_handle,
+            crate_index,
+            tmpdir,
+            target_dir,
+            abort_sender,
+            cargo_output_waiter: None,
+            lsp_server,
+        })
+    }
+
+    /// Runs, reports any error and returns the exit code. Takes self by value so that it's dropped
+    /// before we return. That way the user interface will be cleaned up before we exit.
+    fn run_and_report_errors(mut self, abort_recv: Receiver<()>) -> ExitCode {
+        if let Some(Command::Summary(options)) = &self.args.command {
+            if !options.table {
+                return self.print_summary(options);
+            }
+        }
+        if let Some(Command::Edit(edit_command)) = &self.args.command {
+            if let Err(error) = edit_command.apply(&self.config_path, &self.crate_index) {
+                println!("Error: {error:#}");
+                return outcome::FAILURE;
+            }
+            return outcome::SUCCESS;
+        }
+        // Load the baseline before building, so that if it's invalid, we fail early.
+        let baseline = match &self.args.command {
+            Some(Command::Diff(options)) => match Baseline::load(options) {
+                Ok(baseline) => Some(baseline),
+                Err(error) => {
+                    println!("Error: {error:#}");
+                    return outcome::FAILURE;
+                }
+            },
+            _ => None,
+        };
+        // Whether unused config, including any unused parts of the baseline, will be checked.
+        let checks_unused = self.should_run_cargo_clean();
+        let baseline_path = config::baseline_path(&self.config_path);
+        let previous_baseline_entries = if matches!(self.args.command, Some(Command::Baseline)) {
+            match config::count_baseline_entries(&baseline_path) {
+                Ok(count) => count,
+                Err(error) => {
+                    println!("Error: {error:#}");
+                    return outcome::FAILURE;
+                }
+            }
+        } else {
+            0
+        };
+        let mut error = None;
+        let mut exit_code = match self.run(abort_recv) {
+            Err(e) => {
+                let exit_code = if self.args.ci {
+                    ci::exit_code_for_error(&e)
+                } else {
+                    outcome::FAILURE
+                };
+                error = Some(e);
+                exit_code
+            }
+            Ok(outcome::FAILURE) if self.args.ci => ci::PROBLEMS_FOUND,
+            Ok(exit_code) => exit_code,
+        };
+        let _ = self.event_sender.send(AppEvent::Shutdown);
+        if let Ok(Err(error)) = self.ui_join_handle.join() {
+            println!("UI error: {error}");
+            return outcome::FAILURE;
+        }
+        if let Some(mut output_waiter) = self.cargo_output_waiter.take() {
+            output_waiter.wait_for_output();
+        }
+        // Now that the UI (if any) has shut down, print any errors.
+        if let Some(error) = error {
+            if self.args.writes_report_to_stdout() {
+                // Keep stdout for the report.
+                eprintln!("Error: {error:#}");
+            } else {
+                println!();
+                println!("Error: {error:#}");
+            }
+        }
+
+        let checker = self.checker.lock().unwrap();
+        if let Err(error) = write_report(&self.args, &self.problem_store, &self.root_path, &checker)
+        {
+            println!("Failed to write report: {error:#}");
+            return outcome::FAILURE;
+        }
+        if !checker.downgraded_problems.is_empty() && !self.args.writes_report_to_stdout() {
+            println!();
+            let problems: Vec<_> = checker
+                .downgraded_problems
+                .into_iter()
+                .map(|problem| (self.args.ci_severity(problem), problem))
+                .collect();
+            ci::print_grouped(&problems);
+        }
+        if exit_code == outcome::SUCCESS {
+            if let (Some(Command::Graph(options)), Some(graph)) =
+                (&self.args.command, checker.graph.as_ref())
+            {
+                graph.print(options);
+            }
+            if let (Some(Command::Summary(options)), Some(report)) =
+                (&self.args.command, checker.report.as_ref())
+            {
+                match report.table(options.output_format, options.sort) {
+                    Ok(table) => print!("{table}"),
+                    Err(error) => println!("Failed to print table: {error:#}"),
+                }
+            }
+        }
+        if let (Some(Command::Report(options)), Some(report)) =
+            (&self.args.command, checker.report.as_ref())
+        {
+            match report.write_html(options) {
+                Ok(path) => {
+                    if !self.args.quiet {
+                        println!("Wrote report to {}", path.display());
+                    }
+                }
+                Err(error) => {
+                    println!("Failed to write report: {error:#}");
+                    return outcome::FAILURE;
+                }
+            }
+        }
+        if let (Some(Command::Export(options)), Some(report), true) = (
+            &self.args.command,
+            checker.report.as_ref(),
+            exit_code == outcome::SUCCESS,
+        ) {
+            if let Err(error) = report.export(options, &checker.crate_index) {
+                println!("Failed to export: {error:#}");
+                return outcome::FAILURE;
+            }
+        }
+        if exit_code == outcome::SUCCESS {
+            match checker.write_attestations() {
+                Ok(attested) => {
+                    if !self.args.quiet && !self.args.writes_report_to_stdout() {
+                        for pkg_id in attested {
+                            println!("Attested `{pkg_id}`");
+                        }
+                    }
+                }
+                Err(error) => {
+                    println!("Failed to write attestations: {error:#}");
+                    return outcome::FAILURE;
+                }
+            }
+        }
+        if let (Some(baseline), Some(report), true) = (
+            &baseline,
+            checker.report.as_ref(),
+            exit_code == outcome::SUCCESS,
+        ) {
+            let diff = report.diff(baseline);
+            diff.print();
+            if diff.has_additions() {
+                exit_code = ci::PROBLEMS_FOUND;
+            }
+        }
+        if self.args.print_path_to_crate_map {
+            checker.print_path_to_crate_map();
+        }
+        if self.args.print_timing {
+            checker.print_timing();
+        }
+        if exit_code == outcome::SUCCESS
+            && !self.args.quiet
+            && matches!(self.args.command, Some(Command::Init))
+        {
+            println!(
+                "Wrote draft configuration to {}. Review entries marked TODO before relying on it.",
+                self.config_path.display()
+            );
+        }
+        if matches!(self.args.command, Some(Command::Baseline)) {
+            let result = if exit_code == outcome::SUCCESS {
+                config::replace_baseline(&self.config_path)
+            } else {
+                config::discard_pending_baseline(&self.config_path)
+            };
+            if let Err(error) = result {
+                println!("Error: {error:#}");
+                return outcome::FAILURE;
+            }
+        }
+        if exit_code == outcome::SUCCESS
+            && !self.args.quiet
+            && matches!(self.args.command, Some(Command::Baseline))
+        {
+            match config::count_baseline_entries(&baseline_path) {
+                Ok(count) => println!(
+                    "Wrote baseline with {count} entries to {} (previously {previous_baseline_entries})",
+                    baseline_path.display()
+                ),
+                Err(error) => println!("Error: {error:#}"),
+            }
+        }
+        let baseline_api_count = checker.baseline_api_count();
+        if exit_code == outcome::SUCCESS
+            && baseline_api_count > 0
+            && checks_unused
+            && !matches!(self.args.command, Some(Command::Baseline))
+            && !self.args.quiet
+            && !self.args.writes_report_to_stdout()
+        {
+            println!(
+                "Baseline: {} of {baseline_api_count} grandfathered API permissions are no longer \
+                 used",
+                checker.unused_baseline_apis.len()
+            );
+            if !checker.unused_baseline_apis.is_empty() {
+                println!("Run `cargo acl baseline` to remove them from the baseline");
+            }
+        }
+        if exit_code == outcome::SUCCESS
+            && !self.args.quiet
+            && matches!(self.args.command, Some(Command::LintConfig(..)))
+        {
+            println!(
+                "Lint of configuration {} completed",
+                self.config_path.display()
+            );
+        }
+        if exit_code == outcome::SUCCESS
+            && !self.args.quiet
+            && matches!(self.args.command, None | Some(Command::Replay(..)))
+        {
+            println!(
+                "Completed successfully for configuration {}",
+                self.config_path.display()
+            );
+            let summary = summary::Summary::new(&self.crate_index, &checker.config);
+            println!("{summary}");
+        }
+        exit_code
+    }
+
+    /// Runs the checks and returns what was observed. Used when we're embedded in another tool via
+    /// `analysis`.
+    pub(crate) fn analyze(mut self, abort_recv: Receiver<()>) -> Result<analysis::Report> {
+        let result = self.run(abort_recv);
+        let _ = self.event_sender.send(AppEvent::Shutdown);
+        if let Ok(Err(error)) = self.ui_join_handle.join() {
+            return Err(error);
+        }
+        if let Some(mut output_waiter) = self.cargo_output_waiter.take() {
+            output_waiter.wait_for_output();
+        }
+        result?;
+        let mut checker = self.checker.lock().unwrap();
+        let report = checker.report.take().unwrap_or_default();
+        let pstore = self.problem_store.lock();
+        let downgraded = checker
+            .downgraded_problems
+            .into_iter()
+            .map(|problem| (self.args.ci_severity(problem), problem));
+        let problems: Vec<_> = pstore
+            .reported
+            .iter()
+            .map(|(severity, problem)| (*severity, problem))
+            .chain(downgraded)
+            .collect();
+        Ok(report.into_analysis(&problems))
+    }
+
+    fn print_summary(&self, options: &SummaryOptions) -> ExitCode {
+        let mut checker = self.checker.lock().unwrap();
+        if let Err(error) = checker.load_config() {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        let summary = summary::Summary::new(&self.crate_index, &checker.config);
+        if let Err(error) = summary.print(options) {
+            println!("{error:#}");
+            return outcome::FAILURE;
+        }
+        outcome::SUCCESS
+    }
+
+    fn run(&mut self, abort_recv: Receiver<()>) -> Result<ExitCode> {
+        if matches!(self.args.command, Some(Command::Baseline)) {
+            if !self.config_path.exists() {
+                bail!(
+                    "{} doesn't exist. Create it first, e.g. with `cargo acl init`",
+                    self.config_path.display()
+                );
+            }
+            // The baseline is regenerated from scratch, so that anything that's been fixed gets
+            // removed from it. We build the new baseline separately and only replace the old one
+            // once we succeed. Anything left over from an earlier run that didn't finish is
+            // discarded.
+            config::discard_pending_baseline(&self.config_path)?;
+        }
+        if matches!(self.args.command, Some(Command::Init)) && self.config_path.exists() {
+            bail!(
+                "{} already exists. Remove it first if you'd like to generate a new one",
+                self.config_path.display()
+            );
+        }
+        if self.maybe_create_config()? == Outcome::GiveUp {
+            info!("Gave up creating initial configuration");
+            return Ok(outcome::FAILURE);
+        }
+        self.checker.lock().unwrap().load_config()?;
+        if let Some(Command::Attest(options)) = &self.args.command {
+            self.checker
+                .lock()
+                .unwrap()
+                .attest_packages(&options.packages)?;
+        }
+        // This needs to happen before we clean, since cleaning removes what the session recorded.
+        if self.resume_session()? == Outcome::GiveUp {
+            return Ok(outcome::FAILURE);
+        }
+        if self.should_run_cargo_clean() {
+            let checker = self.checker.lock().unwrap();
+            proxy::clean(&self.root_path, &self.args, &checker.config.raw.common)?;
+        }
+        if !self.args.ignore_newer_config_versions {
+            let update_problems = self.checker.lock().unwrap().check_for_new_config_version();
+            if !update_problems.is_empty() {
+                self.problem_store.fix_problems(update_problems);
+            }
+        }
+        let sandbox_problems = self.checker.lock().unwrap().check_sandbox_capabilities();
+        if !sandbox_problems.is_empty() {
+            self.problem_store.fix_problems(sandbox_problems);
+        }
+        let advisory_problems = self.checker.lock().unwrap().check_advisories();
+        if !advisory_problems.is_empty() {
+            self.problem_store.fix_problems(advisory_problems);
+        }
+        // Unlike the warnings above, a version mismatch is an error, so we stop if it isn't fixed.
+        let version_problems = self.checker.lock().unwrap().check_version_mismatches();
+        if self.problem_store.fix_problems(version_problems) == Outcome::GiveUp {
+            return Ok(outcome::FAILURE);
+        }
+
+        let mut initial_outcome = self.new_request_handler(None).handle_request()?;
+        let config = self.checker.lock().unwrap().config.clone();
+        let crate_index = self.checker.lock().unwrap().crate_index.clone();
+        initial_outcome = initial_outcome.and(
+            self.problem_store
+                .fix_problems(config.raw.unused_imports(&crate_index)),
+        );
+
+        {
+            let mut checker = self.checker.lock().unwrap();
+
+            // The following call to load_config is only really necessary if we fixed unused-import
+            // problems above. It might be worthwhile at some point refactoring so that we don't do an
+            // unnecessary reload here.
+            checker.load_config()?;
+        }
+
+        let build_result = if initial_outcome == Outcome::Continue {
+            self.build(&abort_recv, &config, &crate_index)
+        } else {
+            // We've already detected problems before running cargo, don't run cargo.
+            Ok(())
+        };
+
+        let args = self.args.clone();
+        if let (Some(Command::Watch(options)), Outcome::Continue) = (&args.command, initial_outcome)
+        {
+            return self.watch(options, &abort_recv, build_result);
+        }
+        if let (Some(server), Outcome::Continue) = (self.lsp_server.take(), initial_outcome) {
+            return self.serve_lsp(server, &abort_recv, build_result);
+        }
+
+        if self.problem_store.lock().has_aborted {
+            return Ok(outcome::FAILURE);
+        }
+
+        // We only check if the build failed if there were no ACL check errors.
+        build_result?;
+
+        // If we didn't run `cargo clean` when we started, then our records of what is an isn't used
+        // won't be complete, so we shouldn't emit unused warnings.
+        if self.should_run_cargo_clean() {
+            let unused_problems = self.checker.lock().unwrap().check_unused()?;
+            if let Some(Command::LintConfig(options)) = &self.args.command {
+                if options.autofix {
+                    let config = self.checker.lock().unwrap().config.clone();
+                    let applied =
+                        lint_config::autofix(&self.config_path, &config, &unused_problems)?;
+                    for title in &applied {
+                        println!("{title}");
+                    }
+                    return Ok(outcome::SUCCESS);
+                }
+            }
+            let resolution = self.problem_store.fix_problems(unused_problems);
+            if resolution != Outcome::Continue {
+                return Ok(outcome::FAILURE);
+            }
+            let declaration_problems = self.checker.lock().unwrap().check_declarations();
+            if self.problem_store.fix_problems(declaration_problems) != Outcome::Continue {
+                return Ok(outcome::FAILURE);
+            }
+        }
+
+        // If edits were undone, then the problems that they resolved need resolving again.
+        if self.problem_store.wait_for_reinstated() != Outcome::Continue {
+            return Ok(outcome::FAILURE);
+        }
+
+        let attestation_problems = self.checker.lock().unwrap().check_attestations()?;
+        if self.problem_store.fix_problems(attestation_problems) != Outcome::Continue {
+            return Ok(outcome::FAILURE);
+        }
+
+        // Everything has been resolved, so there's nothing to resume.
+        let session_path = self.session_path();
+        if self.records_session() && session_path.exists() {
+            std::fs::remove_file(&session_path)
+                .with_context(|| format!("Failed to remove `{}`", session_path.display()))?;
+        }
+
+        Ok(outcome::SUCCESS)
+    }
+
+    /// Builds, or replays a previous build, checking whatever gets built.
+    fn build(
+        &mut self,
+        abort_recv: &Receiver<()>,
+        config: &Arc<config::Config>,
+        crate_index: &Arc<CrateIndex>,
+    ) -> Result<()> {
+        let root_path = self.root_path.clone();
+        let args = self.args.clone();
+        if let Some(Command::Replay(options)) = &self.args.command {
+            self.replay_recording(options.path())
+        } else if self.args.replay_requests {
+            self.replay_requests()
+        } else {
+            let session_path = self.records_session().then(|| self.session_path());
+            if let Some(dir) = session_path.as_deref().and_then(Path::parent) {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create `{}`", dir.display()))?;
+            }
+            let recorders = self
+                .args
+                .record
+                .iter()
+                .chain(session_path.iter())
+                .map(|path| replay::Recorder::create(path).map(Mutex::new))
+                .collect::<Result<Vec<_>>>()?;
+            let build_output = self.checker.lock().unwrap().build_output.clone();
+            let cargo_runner = proxy::CargoRunner {
+                manifest_dir: &root_path,
+                tmpdir: self.tmpdir.path(),
+                target_dir: &self.target_dir,
+                config,
+                args: &args,
+                crate_index,
+                build_output: &build_output,
+            };
+            let invoke = || {
+                cargo_runner.invoke_cargo_build(abort_recv, self.abort_sender.clone(), |request| {
+                    // Progress updates aren't needed in order to replay the build.
+                    if self.args.save_requests && !matches!(request, Request::Progress(..)) {
+                        if let Err(error) = self.save_request(&request) {
+                            println!("Failed to save request: {error}");
+                        }
+                    }
+                    for recorder in &recorders {
+                        if let Err(error) = recorder.lock().unwrap().record(&request) {
+                            println!("Failed to record request: {error:#}");
+                        }
+                    }
+                    self.new_request_handler(Some(request))
+                })
+            };
+            let mut r = invoke();
+            if let Some(mismatch) = r
+                .as_ref()
+                .err()
+                .and_then(|error| error.downcast_ref::<proxy::rpc::VersionMismatch>())
+            {
+                // A stale wrapper from a different version of cackle was left in the target
+                // directory. Cleaning will cause it to be rebuilt, after which we can retry.
+                info!("{mismatch}. Cleaning and retrying");
+                proxy::clean(&root_path, &args, &config.raw.common)?;
+                r = invoke();
+            }
+            match r {
+                Ok(output_waiter) => {
+                    self.cargo_output_waiter = Some(output_waiter);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    /// Reports the outcome of the build that just finished, then rebuilds and rechecks whenever
+    /// files in the workspace change. Only returns if we fail to watch for changes, since we expect
+    /// to be stopped with control-c.
+    fn watch(
+        &mut self,
+        options: &WatchOptions,
+        abort_recv: &Receiver<()>,
+        mut build_result: Result<()>,
+    ) -> Result<ExitCode> {
+        let mut watcher = watch::Watcher::new(
+            &self.root_path,
+            vec![self.target_dir.clone(), self.root_path.join(".git")],
+        )?;
+        loop {
+            self.finish_watched_build(build_result);
+            println!(
+                "Watching {} for changes. Press control-c to stop.",
+                self.root_path.display()
+            );
+            let changed = watcher.wait_for_changes(options.debounce())?;
+            match changed.as_slice() {
+                [path] => println!("{} changed, rechecking", path.display()),
+                _ => println!("{} files changed, rechecking", changed.len()),
+            }
+            // If the previous build was aborted after cargo had already finished, then the abort
+            // will still be pending and would stop the next build as soon as it started.
+            while abort_recv.try_recv().is_ok() {}
+            build_result = self.checker.lock().unwrap().load_config();
+            if build_result.is_ok() {
+                let (config, crate_index) = {
+                    let checker = self.checker.lock().unwrap();
+                    (checker.config.clone(), checker.crate_index.clone())
+                };
+                build_result = self.build(abort_recv, &config, &crate_index);
+            }
+        }
+    }
+
+    /// Waits for output from a build run by `watch`, then reports how it went and resets our state
+    /// ready for the next build.
+    fn finish_watched_build(&mut self, build_result: Result<()>) {
+        if let Some(mut output_waiter) = self.cargo_output_waiter.take() {
+            output_waiter.wait_for_output();
+        }
+        let aborted = {
+            let mut pstore = self.problem_store.lock();
+            let aborted = pstore.has_aborted;
+            pstore.reset();
+            aborted
+        };
+        let downgraded = self.checker.lock().unwrap().finish_build();
+        if !downgraded.is_empty() {
+            println!();
+            let problems: Vec<_> = downgraded
+                .into_iter()
+                .map(|problem| (self.args.ci_severity(problem), problem))
+                .collect();
+            ci::print_grouped(&problems);
+        }
+        match build_result {
+            // If we aborted due to problems, then those problems have already been reported and
+            // cargo most likely failed because we killed it.
+            _ if aborted => {}
+            Ok(()) => println!("No problems found"),
+            Err(error) => println!("Error: {error:#}"),
+        }
+    }
+
+    /// Publishes diagnostics for the build that just finished, then rebuilds and rechecks whenever
+    /// the editor saves a file. Returns once the editor asks us to exit.
+    fn serve_lsp(
+        &mut self,
+        mut server: lsp::Server,
+        abort_recv: &Receiver<()>,
+        mut build_result: Result<()>,
+    ) -> Result<ExitCode> {
+        let mut rechecked = lsp::Rechecked::Everything;
+        loop {
+            self.finish_lsp_build(&mut server, build_result, &rechecked)?;
+            let Some(saved) = server.wait_for_saves(lsp::DEBOUNCE) else {
+                return Ok(outcome::SUCCESS);
+            };
+            rechecked = lsp::Rechecked::new(&saved, &self.config_path, &self.crate_index);
+            while abort_recv.try_recv().is_ok() {}
+            build_result = self.checker.lock().unwrap().load_config();
+            if build_result.is_ok() {
+                let (config, crate_index) = {
+                    let checker = self.checker.lock().unwrap();
+                    (checker.config.clone(), checker.crate_index.clone())
+                };
+                if matches!(rechecked, lsp::Rechecked::Everything) {
+                    // Changing the config doesn't cause cargo to rebuild anything, so we need to
+                    // clean in order for everything to be checked against the new config.
+                    build_result = proxy::clean(&self.root_path, &self.args, &config.raw.common);
+                }
+                if build_result.is_ok() {
+                    build_result = self.build(abort_recv, &config, &crate_index);
+                }
+            }
+        }
+    }
+
+    /// Waits for output from a build run by `serve_lsp`, then publishes whatever it found and
+    /// resets our state ready for the next build.
+    fn finish_lsp_build(
+        &mut self,
+        server: &mut lsp::Server,
+        build_result: Result<()>,
+        rechecked: &lsp::Rechecked,
+    ) -> Result<()> {
+        if let Some(mut output_waiter) = self.cargo_output_waiter.take() {
+            output_waiter.wait_for_output();
+        }
+        let (aborted, reported) = {
+            let mut pstore = self.problem_store.lock();
+            let aborted = pstore.has_aborted;
+            let reported = std::mem::take(&mut pstore.reported);
+            pstore.reset();
+            (aborted, reported)
+        };
+        let downgraded = self.checker.lock().unwrap().finish_build();
+        let problems: Vec<_> = reported
+            .iter()
+            .map(|(severity, problem)| (*severity, problem))
+            .chain(
+                downgraded
+                    .into_iter()
+                    .map(|problem| (self.args.ci_severity(problem), problem)),
+            )
+            .collect();
+        server.publish(&problems, &self.root_path, rechecked)?;
+        if let (Err(error), false) = (build_result, aborted) {
+            server.show_message(problem::Severity::Error, &format!("{error:#}"))?;
+        }
+        Ok(())
+    }
+
+    fn should_run_cargo_clean(&mut self) -> bool {
+        !self.args.replay_requests && self.args.is_full_build()
+    }
+
+    fn new_request_handler(&self, request: Option<Request>) -> RequestHandler {
+        RequestHandler {
+            check_state: CheckState::default(),
+            checker: self.checker.clone(),
+            problem_store: self.problem_store.clone(),
+            request,
+            config_changed: false,
+        }
+    }
+
+    fn maybe_create_config(&mut self) -> Result<Outcome> {
+        if !self.config_path.exists() && self.args.ci {
+            return Err(anyhow!("{} doesn't exist", self.config_path.display())
+                .context(config::InvalidConfig));
+        }
+        if !self.config_path.exists() {
+            return Ok(self
+                .problem_store
+                .fix_problems(Problem::MissingConfiguration(self.config_path.clone()).into()));
+        }
+        Ok(Outcome::Continue)
+    }
+
+    /// Returns whether we record the requests made during the build, so that if we exit before the
+    /// user has resolved all problems, the next run can resume where they left off.
+    fn records_session(&self) -> bool {
+        self.args.has_interactive_ui()
+            && !self.args.replay_requests
+            && !matches!(
+                self.args.command,
+                Some(Command::Replay(..)) | Some(Command::Watch(..))
+            )
+    }
+
+    fn session_path(&self) -> PathBuf {
+        self.target_dir
+            .join(profile_name(
+                &self.args,
+                &self.checker.lock().unwrap().config.raw.common,
+            ))
+            .join("review-session.jsonl")
+    }
+
+    /// If a previous interactive run exited before all problems were resolved, restores those
+    /// problems by rechecking what that run recorded against the current config. Problems that were
+    /// resolved by editing the config won't be found again.
+    fn resume_session(&self) -> Result<Outcome> {
+        let path = self.session_path();
+        if !self.records_session() || !path.exists() {
+            return Ok(Outcome::Continue);
+        }
+        let requests = match replay::read(&path) {
+            Ok(requests) => requests,
+            Err(error) => {
+                info!("Discarding previous review session: {error:#}");
+                return Ok(Outcome::Continue);
+            }
+        };
+        if !self.args.quiet {
+            println!("Resuming review of problems found by the previous run");
+        }
+        for request in requests {
+            if self.new_request_handler(Some(request)).handle_request()? == Outcome::GiveUp {
+                return Ok(Outcome::GiveUp);
+            }
+        }
+        Ok(Outcome::Continue)
+    }
+
+    fn saved_request_path(&self) -> PathBuf {
+        self.target_dir
+            .join(profile_name(
+                &self.args,
+                &self.checker.lock().unwrap().config.raw.common,
+            ))
+            .join("saved-cackle-rpcs")
+    }
+
+    fn replay_requests(&self) -> Result<()> {
+        let rpcs_dir = &self.saved_request_path();
+        let mut rpc_paths: Vec<PathBuf> = rpcs_dir
+            .read_dir()
+            .with_context(|| format!("Failed to read saved RPCs dir `{}`", rpcs_dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        rpc_paths.sort();
+        for path in rpc_paths {
+            info!("Replaying RPC `{}`", path.display());
+            if self
+                .replay_request(&path)
+                .with_context(|| format!("Replay of request `{}` failed", path.display()))?
+                == Outcome::GiveUp
+            {
+                bail!("Request gave error");
+            }
+        }
+        Ok(())
+    }
+
+    fn replay_recording(&self, path: &Path) -> Result<()> {
+        for request in replay::read(path)? {
+            if self.new_request_handler(Some(request)).handle_request()? == Outcome::GiveUp {
+                bail!("Request gave error");
+            }
+        }
+        Ok(())
+    }
+
+    fn replay_request(&self, path: &Path) -> Result<Outcome> {
+        let request_str = crate::fs::read_to_string(path)?;
+        let request: Request = serde_json::from_str(&request_str)?;
+        let mut handler = self.new_request_handler(Some(request));
+        handler.handle_request()
+    }
+
+    fn save_request(&self, request: &Request) -> Result<()> {
+        let rpcs_dir = self.saved_request_path();
+        std::fs::create_dir_all(&rpcs_dir)?;
+        let num_entries = rpcs_dir.read_dir()?.count();
+        let serialized = serde_json::to_string(request)?;
+        crate::fs::write(
+            rpcs_dir.join(format!("{num_entries:03}.cackle-rpc")),
+            serialized,
+        )?;
+        Ok(())
+    }
+}
+
+/// Writes problems that were reported in the format selected with `--output-format`, if that's
+/// something other than text.
+fn write_report(
+    args: &Args,
+    problem_store: &ProblemStoreRef,
+    root_path: &Path,
+    checker: &Checker,
+) -> Result<()> {
+    if args.output_format == sarif::OutputFormat::Text {
+        return Ok(());
+    }
+    let pstore = problem_store.lock();
+    let downgraded = checker
+        .downgraded_problems
+        .into_iter()
+        .map(|problem| (args.ci_severity(problem), problem));
+    let problems: Vec<_> = pstore
+        .reported
+        .iter()
+        .map(|(severity, problem)| (*severity, problem))
+        .chain(downgraded)
+        .collect();
+    let mut out: Box<dyn std::io::Write> = if let Some(path) = &args.output_file {
+        Box::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("Failed to create `{}`", path.display()))?,
+        )
+    } else {
+        Box::new(std::io::stdout().lock())
+    };
+    match args.output_format {
+        sarif::OutputFormat::Text => Ok(()),
+        sarif::OutputFormat::Sarif => sarif::write(&problems, root_path, &mut out),
+        sarif::OutputFormat::Json => checker
+            .report
+            .as_ref()
+            .ok_or_else(|| anyhow!("Report wasn't collected"))?
+            .write(&problems, &mut out),
+    }
+}
+
+fn root_path(args: &Arc<Args>) -> Result<PathBuf> {
+    if let Some(path) = args.path.clone() {
+        return Ok(path);
+    }
+    let current_dir = std::env::current_dir()
+        .ok()
+        .ok_or_else(|| anyhow!("Failed to get current working directory"))?;
+    let mut dir = current_dir.as_path();
+    loop {
+        if dir.join("Cargo.toml").exists() {
+            return Ok(dir.to_owned());
+        }
+        if let Some(parent) = dir.parent() {
+            dir = parent;
+        } else {
+            bail!(
+                "No Cargo.toml found in `{}` or any parent directory",
+                current_dir.display()
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct CheckState {
+    /// The results of scanning each linker output, keyed by the output file.
+    pub(crate) graph_outputs: FxHashMap<Arc<Path>, ScanOutputs>,
+}
+
+pub(crate) struct RequestHandler {
+    check_state: CheckState,
+    checker: Arc<Mutex<Checker>>,
+    problem_store: ProblemStoreRef,
+    request: Option<proxy::rpc::Request>,
+    /// Whether the config was reloaded while handling the request.
+    config_changed: bool,
+}
+
+impl RequestHandler {
+    pub(crate) fn handle_request(&mut self) -> Result<Outcome> {
+        loop {
+            let problems = self
+                .checker
+                .lock()
+                .unwrap()
+                .handle_request(&self.request, &mut self.check_state)?;
+            let return_on_retry = problems.should_send_retry_to_subprocess();
+            if problems.is_empty() {
+                return Ok(Outcome::Continue);
+            }
+            let mut pkg_ids = Vec::new();
+            for problem in &problems {
+                pkg_ids.extend(problem.pkg_id().cloned());
+            }
+            match self.problem_store.fix_problems(problems) {
+                Outcome::Continue => {
+                    let mut checker = self.checker.lock().unwrap();
+                    let old_config = checker.config.clone();
+                    checker.load_config()?;
+                    checker.record_reviewed(&pkg_ids, &old_config);
+                    drop(checker);
+                    self.config_changed = true;
+                    if return_on_retry {
+                        // If the only problem is that something in a subprocess failed, we return
+                        // an empty error set. This signals the subprocess that it should proceed,
+                        // which since something failed means that it should reload the config and
+                        // retry whatever failed.
+                        return Ok(Outcome::Continue);
+                    }
+                }
+                Outcome::GiveUp => {
+                    return Ok(Outcome::GiveUp);
+                }
+            }
+        }
+    }
+
+    /// Returns whether the config was changed while handling the request, e.g. because the user
+    /// accepted a fix.
+    pub(crate) fn config_changed(&self) -> bool {
+        self.config_changed
+    }
+}
+
+/// Directly invokes a wrapped binary, where the binary and arguments were passed to us by the
+/// wrapper shell script.
+pub(crate) fn invoke_wrapped_binary() -> Result<()> {
+    let mut args = std::env::args_os().skip(3);
+    let program = args
+        .next()
+        .ok_or_else(|| anyhow!("Missing proxy-bin program"))?;
+    let status = std::process::Command::new(&program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to invoke `{}`", program.to_string_lossy()))?;
+    std::process::exit(status.code().unwrap_or(-1));
+}
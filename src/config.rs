@@ -4,10 +4,12 @@ use crate::crate_index::PackageId;
 use crate::problem::AvailableApi;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
+use crate::problem::Severity;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use cargo_metadata::semver::VersionReq;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::BTreeMap;
@@ -17,8 +19,14 @@ use std::path::PathBuf;
 use std::sync::Arc;
 pub(crate) use versions::MAX_VERSION;
 
+pub(crate) mod advisories;
+pub(crate) mod api_packs;
 pub(crate) mod built_in;
+pub(crate) mod cargo_vet;
+pub(crate) mod cfg_expr;
+pub(crate) mod declarations;
 pub(crate) mod permissions;
+pub(crate) mod schema;
 pub(crate) mod versions;
 
 #[derive(Default, Debug)]
@@ -50,9 +58,43 @@ pub(crate) struct RawConfig {
 
     #[serde(default)]
     pub(crate) rustc: RustcConfig,
+
+    #[serde(default)]
+    pub(crate) wrapper: WrapperConfig,
+
+    #[serde(default)]
+    pub(crate) ui: UiConfig,
+
+    #[serde(default)]
+    pub(crate) ignore: Vec<IgnoreRule>,
+
+    #[serde(default)]
+    pub(crate) cargo_vet: cargo_vet::CargoVetConfig,
+
+    #[serde(default)]
+    pub(crate) advisories: advisories::AdvisoriesConfig,
+
+    #[serde(default)]
+    pub(crate) attestations: AttestationsConfig,
+
+    #[serde(default)]
+    pub(crate) declarations: declarations::DeclarationsConfig,
 }
 
-/// The name of a package. Doesn't include any version information.
+/// Suppresses reporting of a package's usages of an API from source files that match a glob. e.g.
+/// to permit use of `fs` from a package's test helpers without permitting it for the whole package.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct IgnoreRule {
+    pub(crate) pkg: PackageName,
+    pub(crate) api: ApiName,
+    /// A glob, relative to the package's directory, e.g. `src/testing/**`.
+    pub(crate) path: String,
+}
+
+/// The name of a package, optionally followed by `@` and the semver-compatible part of a version,
+/// e.g. `rand@0.8`. The version is only needed when there are multiple versions of a package in
+/// the dependency tree and we want to select just one of them.
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Serialize, Deserialize, PartialOrd, Ord)]
 #[serde(transparent)]
 pub(crate) struct PackageName(pub(crate) Arc<str>);
@@ -62,6 +104,10 @@ pub(crate) struct PackageName(pub(crate) Arc<str>);
 pub(crate) struct CommonConfig {
     pub(crate) version: i64,
 
+    /// The version of the layout of the config file. See `schema.rs`.
+    #[serde(default)]
+    pub(crate) schema_version: Option<i64>,
+
     #[serde(default)]
     pub(crate) explicit_build_scripts: bool,
 
@@ -71,11 +117,44 @@ pub(crate) struct CommonConfig {
     #[serde(default)]
     pub(crate) import_std: Vec<String>,
 
+    /// Files containing additional API definitions to merge into ours.
+    #[serde(default)]
+    pub(crate) api_packs: Vec<api_packs::ApiPackSource>,
+
     #[serde(default)]
     pub(crate) features: Vec<String>,
 
     #[serde(default)]
     pub(crate) profile: Option<String>,
+
+    /// Whether to only report API usages that are reachable from the entry points of the binary
+    /// in which they were found.
+    #[serde(default)]
+    pub(crate) prune_unreachable: bool,
+
+    /// Which crate API usages in code generated by proc macros should be attributed to.
+    #[serde(default)]
+    pub(crate) proc_macro_attribution: ProcMacroAttribution,
+
+    /// Whether to read the symbol tables of object files that contain LLVM bitcode, as produced by
+    /// linker-plugin LTO, rather than reporting an error.
+    #[serde(default)]
+    pub(crate) scan_lto_bitcode: bool,
+
+    /// Whether to record, for each API usage, the chain of calls from an entry point of the
+    /// package through to the usage.
+    #[serde(default)]
+    pub(crate) report_call_chains: bool,
+
+    /// The number of usages to retain for each package and API before further usages are merged
+    /// into a retained usage of the same name in the same file.
+    #[serde(default)]
+    pub(crate) max_usages_per_problem: Option<usize>,
+
+    /// Whether to attribute API usages in the standard library to a package named `std` rather
+    /// than ignoring them.
+    #[serde(default)]
+    pub(crate) audit_std: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
@@ -89,6 +168,11 @@ pub(crate) struct SandboxConfig {
 
     pub(crate) allow_network: Option<bool>,
 
+    /// Domains that may be accessed via a filtering HTTP(S) proxy. Ignored if `allow_network` is
+    /// set.
+    #[serde(default)]
+    pub(crate) allow_domains: Vec<String>,
+
     #[serde(default)]
     pub(crate) bind_writable: Vec<PathBuf>,
 
@@ -97,6 +181,45 @@ pub(crate) struct SandboxConfig {
 
     #[serde(default)]
     pub(crate) pass_env: Vec<String>,
+
+    /// Environment variables to set to fixed values. These take precedence over any variables that
+    /// are passed through.
+    #[serde(default)]
+    pub(crate) set_env: BTreeMap<String, String>,
+
+    /// Environment variables that must never be passed through, even if they'd otherwise be passed
+    /// through by default or via `pass_env`. `*` matches any sequence of characters.
+    #[serde(default)]
+    pub(crate) deny_env: Vec<String>,
+
+    /// Whether to let the sandboxed process write to the package's source directory and the
+    /// workspace, capturing the writes rather than applying them, so that they can be reported.
+    pub(crate) capture_writes: Option<bool>,
+
+    /// Whether to relax the sandbox according to the APIs that the package is permitted to use.
+    /// Currently this permits network access if the package is allowed to use the `net` API.
+    pub(crate) map_permissions: Option<bool>,
+
+    /// The maximum CPU time in seconds that each process in the sandbox may use.
+    pub(crate) max_cpu_seconds: Option<u64>,
+
+    /// The maximum time in seconds that the sandboxed process may run for.
+    pub(crate) max_wall_seconds: Option<u64>,
+
+    /// The maximum size in MiB of the address space of each process in the sandbox.
+    pub(crate) max_memory_mb: Option<u64>,
+
+    /// The maximum number of processes that the user may have while the sandbox is running.
+    pub(crate) max_processes: Option<u64>,
+
+    /// The name of a profile from `sandbox.profiles` that this config extends.
+    #[serde(default)]
+    pub(crate) profile: Option<String>,
+
+    /// Named sandbox configurations that can be referenced via `profile`. Only permitted in the
+    /// top-level `[sandbox]`.
+    #[serde(default)]
+    pub(crate) profiles: BTreeMap<String, SandboxConfig>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
@@ -104,6 +227,88 @@ pub(crate) struct SandboxConfig {
 pub(crate) struct RustcConfig {
     #[serde(default)]
     pub(crate) sandbox: SandboxConfig,
+
+    /// The sandbox used when running rustc for crates that use proc macros. Anything not set
+    /// here is inherited from `sandbox`.
+    #[serde(default)]
+    pub(crate) proc_macro_sandbox: SandboxConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WrapperConfig {
+    /// Whether to chain to a rustc wrapper such as sccache if one was configured before we took
+    /// over `RUSTC_WRAPPER`. Defaults to true, unless rustc is sandboxed.
+    #[serde(default)]
+    pub(crate) passthrough: Option<bool>,
+}
+
+impl WrapperConfig {
+    /// Returns whether to chain to a configured rustc wrapper. A wrapper runs wherever rustc does,
+    /// and wrappers like sccache can't reach their server or cache from within the rustc sandbox,
+    /// so by default we only chain to them when rustc isn't sandboxed.
+    pub(crate) fn passthrough(&self, rustc_sandboxed: bool) -> bool {
+        self.passthrough.unwrap_or(!rustc_sandboxed)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct UiConfig {
+    /// Keys for actions in the full-screen UI, keyed by action name. Actions that aren't listed
+    /// keep their default keys.
+    #[serde(default)]
+    pub(crate) keys: BTreeMap<String, Vec<String>>,
+
+    /// Colours for the full-screen UI. `preset` selects a preset, while other entries override
+    /// individual colours.
+    #[serde(default)]
+    pub(crate) theme: BTreeMap<String, String>,
+
+    /// Whether to send a desktop notification when problems are found after the user has been
+    /// idle for a while.
+    #[serde(default)]
+    pub(crate) notifications: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AttestationsConfig {
+    /// Where attestations are written and read from, relative to the workspace root. Defaults to
+    /// `cackle-attestations`.
+    #[serde(default)]
+    pub(crate) dir: Option<PathBuf>,
+
+    /// The SSH key to sign attestations with, as passed to `ssh-keygen -Y sign -f`. Attestations are
+    /// only written if this is set.
+    #[serde(default)]
+    pub(crate) signing_key: Option<PathBuf>,
+
+    /// Who is doing the reviewing. Defaults to git's `user.email`.
+    #[serde(default)]
+    pub(crate) reviewer: Option<String>,
+
+    /// The file listing whose signatures are trusted, in the format used by `ssh-keygen`. Relative
+    /// to the workspace root. Defaults to `allowed_signers` in `dir`.
+    #[serde(default)]
+    pub(crate) allowed_signers: Option<PathBuf>,
+}
+
+impl AttestationsConfig {
+    pub(crate) fn dir(&self, workspace_root: &Path) -> PathBuf {
+        workspace_root.join(
+            self.dir
+                .as_deref()
+                .unwrap_or(Path::new("cackle-attestations")),
+        )
+    }
+
+    pub(crate) fn allowed_signers(&self, workspace_root: &Path) -> PathBuf {
+        match &self.allowed_signers {
+            Some(path) => workspace_root.join(path),
+            None => self.dir(workspace_root).join("allowed_signers"),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default, Hash)]
@@ -117,6 +322,53 @@ pub(crate) struct ApiConfig {
 
     #[serde(default)]
     pub(crate) no_auto_detect: Vec<PackageName>,
+
+    /// Other APIs whose includes and excludes should be merged into this API.
+    #[serde(default)]
+    pub(crate) include_apis: Vec<ApiName>,
+
+    /// Other APIs whose includes should be excluded from this API.
+    #[serde(default)]
+    pub(crate) exclude_apis: Vec<ApiName>,
+
+    #[serde(default)]
+    pub(crate) mode: ApiMode,
+
+    #[serde(default)]
+    pub(crate) severity: ApiSeverity,
+}
+
+/// Determines whether use of an API needs to be explicitly permitted.
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub(crate) enum ApiMode {
+    /// Packages may only use the API if it's in their `allow_apis`.
+    #[default]
+    Deny,
+    /// Packages may use the API unless it's in their `deny_apis`.
+    Allow,
+}
+
+/// How use of an API by a package that hasn't been permitted to use it is reported.
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ApiSeverity {
+    /// Reported as an error, which fails the build.
+    #[default]
+    Deny,
+    /// Reported as a warning, which doesn't fail the build unless `--fail-on-warnings` is set.
+    Warn,
+    /// Reported for information only.
+    Info,
+}
+
+impl ApiSeverity {
+    pub(crate) fn problem_severity(self) -> Severity {
+        match self {
+            ApiSeverity::Deny => Severity::Error,
+            ApiSeverity::Warn => Severity::Warning,
+            ApiSeverity::Info => Severity::Info,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -132,31 +384,93 @@ pub(crate) struct ApiPath {
     pub(crate) prefix: Arc<str>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash, clap::ValueEnum)]
 pub(crate) enum SandboxKind {
     Disabled,
     Bubblewrap,
+    Landlock,
+    Namespaces,
+    Seatbelt,
+    /// Use the strongest kind of sandbox that's available on the system at the time of running.
+    Auto,
 }
 
-pub(crate) const SANDBOX_KINDS: &[SandboxKind] = &[SandboxKind::Disabled, SandboxKind::Bubblewrap];
+pub(crate) const SANDBOX_KINDS: &[SandboxKind] = &[
+    SandboxKind::Disabled,
+    SandboxKind::Bubblewrap,
+    SandboxKind::Landlock,
+    SandboxKind::Namespaces,
+    SandboxKind::Seatbelt,
+    SandboxKind::Auto,
+];
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub(crate) enum ProcMacroAttribution {
+    /// Attribute usages to the crate that invoked the proc macro.
+    #[default]
+    Invoker,
+    /// Attribute usages to the proc macro crate that generated the code.
+    ProcMacro,
+}
 
+// Unknown fields are rejected by `conditional`, since any key that isn't a field gets parsed as a cfg
+// expression. `deny_unknown_fields` doesn't work in combination with `flatten`.
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
-#[serde(deny_unknown_fields)]
 pub(crate) struct PackageConfig {
+    /// A requirement on the version of the package, e.g. "1.*". If a version of the package that
+    /// doesn't match is in the dependency tree, then that's reported as a problem, since the
+    /// permissions granted here may no longer be appropriate.
+    #[serde(default)]
+    pub(crate) version: Option<VersionReq>,
+
     #[serde(default)]
     pub(crate) allow_unsafe: bool,
 
+    /// Source files in which unsafe code is permitted, even though `allow_unsafe` isn't set. Each
+    /// is a glob relative to the package's directory, e.g. `src/ffi/**`.
+    #[serde(default)]
+    pub(crate) allow_unsafe_in: Vec<String>,
+
     #[serde(default)]
     pub(crate) allow_build_instructions: Vec<String>,
 
+    /// Native libraries that the package's build script may ask cargo to link, via
+    /// `cargo:rustc-link-lib`. e.g. `ssl`.
+    #[serde(default)]
+    pub(crate) allow_link_libs: Vec<String>,
+
+    /// Environment variables that the package's build script may set for the compilation of the
+    /// package, via `cargo:rustc-env`.
+    #[serde(default)]
+    pub(crate) allow_rustc_env: Vec<String>,
+
+    /// Cfgs that the package's build script may set, via `cargo:rustc-cfg`. If unset, any cfg is
+    /// permitted.
+    #[serde(default)]
+    pub(crate) allow_rustc_cfg: Option<Vec<String>>,
+
     #[serde(default)]
     pub(crate) allow_apis: Vec<ApiName>,
 
+    /// APIs with `mode = "Allow"` that this package is nonetheless not permitted to use.
+    #[serde(default)]
+    pub(crate) deny_apis: Vec<ApiName>,
+
+    /// Individual functions that are permitted even if they belong to an API that isn't in
+    /// `allow_apis`. e.g. `std::process::Command::new`.
+    #[serde(default)]
+    pub(crate) allow_fn: Vec<ApiPath>,
+
     #[serde(default)]
     pub(crate) allow_proc_macro: bool,
 
+    /// Whether the package is permitted to export unmangled symbols, e.g. via `#[no_mangle]`.
+    #[serde(default)]
+    pub(crate) allow_ffi_export: bool,
+
     pub(crate) build: Option<Box<PackageConfig>>,
     pub(crate) test: Option<Box<PackageConfig>>,
+    pub(crate) dev: Option<Box<PackageConfig>>,
 
     #[serde()]
     pub(crate) from: Option<FromConfig>,
@@ -166,6 +480,11 @@ pub(crate) struct PackageConfig {
 
     #[serde(default)]
     pub(crate) import: Option<Vec<String>>,
+
+    /// Permissions that only apply when the package is built with particular features, keyed by a
+    /// cfg expression. e.g. `[pkg.foo.'cfg(feature = "tls")']`.
+    #[serde(flatten)]
+    pub(crate) conditional: BTreeMap<cfg_expr::CfgExpr, PackageConfig>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
@@ -173,11 +492,52 @@ pub(crate) struct PackageConfig {
 pub(crate) struct FromConfig {
     pub(crate) build: Option<Box<PackageConfig>>,
     pub(crate) test: Option<Box<PackageConfig>>,
+    /// Permissions that apply when the package is used from proc macros.
+    pub(crate) host: Option<Box<PackageConfig>>,
+}
+
+/// Context attached to errors that occur while loading the config, so that they can be told apart
+/// from other errors, e.g. to pick an exit code.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InvalidConfig;
+
+impl Display for InvalidConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration")
+    }
 }
 
 pub(crate) fn parse_file(cackle_path: &Path, crate_index: &CrateIndex) -> Result<Arc<Config>> {
-    let mut raw_config = parse_file_raw(cackle_path)?;
+    parse_file_with_baseline(cackle_path, &baseline_path(cackle_path), crate_index)
+}
+
+/// Like `parse_file`, but merges in the baseline at `baseline_path` rather than the one alongside
+/// the config.
+pub(crate) fn parse_file_with_baseline(
+    cackle_path: &Path,
+    baseline_path: &Path,
+    crate_index: &CrateIndex,
+) -> Result<Arc<Config>> {
+    let mut table = read_config_table(cackle_path, false, &mut Vec::new())?;
+    layer_member_configs(&mut table, cackle_path, crate_index.workspace_member_dirs())?;
+    if let Some(baseline) = read_baseline_table(baseline_path)? {
+        merge_toml_tables(&mut table, baseline);
+    }
+    let mut raw_config = parse_raw_table(table)
+        .with_context(|| format!("Failed to parse {}", cackle_path.display()))?;
     raw_config.load_imports(crate_index)?;
+    api_packs::load(&mut raw_config, crate_index.manifest_path.parent())?;
+    cargo_vet::load(
+        &mut raw_config,
+        crate_index,
+        crate_index.manifest_path.parent(),
+    )?;
+    advisories::load(
+        &mut raw_config,
+        crate_index,
+        crate_index.manifest_path.parent(),
+    )?;
+    declarations::load(&mut raw_config, crate_index);
     raw_config.make_paths_absolute(crate_index.manifest_path.parent())?;
     let config = Config::from_raw(raw_config, crate_index)?;
     crate::config_validation::validate(&config, cackle_path)?;
@@ -185,7 +545,8 @@ pub(crate) fn parse_file(cackle_path: &Path, crate_index: &CrateIndex) -> Result
 }
 
 impl Config {
-    fn from_raw(raw_config: RawConfig, crate_index: &CrateIndex) -> Result<Arc<Config>> {
+    fn from_raw(mut raw_config: RawConfig, crate_index: &CrateIndex) -> Result<Arc<Config>> {
+        raw_config.resolve_composed_apis()?;
         let permissions_no_inheritance = Permissions::from_config(&raw_config);
         let permissions = Permissions::from_config_with_inheritance(&raw_config, crate_index);
         let config = Config {
@@ -198,21 +559,453 @@ impl Config {
 }
 
 fn parse_file_raw(cackle_path: &Path) -> Result<RawConfig> {
-    let cackle: String = std::fs::read_to_string(cackle_path)
-        .with_context(|| format!("Failed to open {}", cackle_path.display()))?;
-    let raw_config =
-        parse_raw(&cackle).with_context(|| format!("Failed to parse {}", cackle_path.display()))?;
+    let table = read_config_table(cackle_path, false, &mut Vec::new())?;
+    let raw_config = parse_raw_table(table)
+        .with_context(|| format!("Failed to parse {}", cackle_path.display()))?;
     Ok(raw_config)
 }
 
+/// Returns `common.features` from the config file at `cackle_path`, if it exists. This is needed
+/// before the config can be fully parsed, since the features affect how cargo resolves the
+/// dependency tree.
+pub(crate) fn configured_features(cackle_path: &Path) -> Result<Vec<String>> {
+    if !cackle_path.exists() {
+        return Ok(Vec::new());
+    }
+    let table = read_config_table(cackle_path, false, &mut Vec::new())?;
+    let Some(features) = table
+        .get("common")
+        .and_then(|common| common.get("features"))
+        .cloned()
+    else {
+        return Ok(Vec::new());
+    };
+    features
+        .try_into()
+        .with_context(|| format!("Invalid `common.features` in {}", cackle_path.display()))
+}
+
+/// Reads the config file at `path` and merges in any config files listed in `common.import`.
+/// `fetched` should be set if the file was fetched from a URL, in which case it may only import
+/// other URLs. `importers` is the chain of files that led to `path` being imported and is used to
+/// detect cycles.
+fn read_config_table(
+    path: &Path,
+    fetched: bool,
+    importers: &mut Vec<PathBuf>,
+) -> Result<toml::Table> {
+    let cackle: String = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut table: toml::Table =
+        toml::from_str(&cackle).with_context(|| format!("Failed to parse {}", path.display()))?;
+    schema::check_not_from_future(&table).with_context(|| format!("In {}", path.display()))?;
+    let imports = take_imports(&mut table)
+        .with_context(|| format!("Invalid `common.import` in {}", path.display()))?;
+    if imports.is_empty() {
+        return Ok(table);
+    }
+    let canonical_path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize {}", path.display()))?;
+    if importers.contains(&canonical_path) {
+        bail!(
+            "Config import cycle: {} -> {}",
+            importers
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> "),
+            canonical_path.display()
+        );
+    }
+    importers.push(canonical_path);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    // Imports are merged with the importing file taking precedence, so by going in reverse, later
+    // imports take precedence over earlier ones.
+    for import in imports.iter().rev() {
+        let imported = import
+            .fetch_or_resolve(dir, fetched)
+            .and_then(|import_path| read_config_table(&import_path, import.is_url(), importers))
+            .with_context(|| format!("Failed to import `{import}` from {}", path.display()))?;
+        merge_toml_tables(&mut table, imported);
+    }
+    importers.pop();
+    Ok(table)
+}
+
+/// The top-level tables that may be present in the config files of workspace members.
+const MEMBER_CONFIG_TABLES: &[&str] = &["api", "pkg"];
+
+/// Merges `cackle.toml` files found in `member_dirs` into `table`, which should be the workspace
+/// config loaded from `cackle_path`. Values set by members take precedence over values set by the
+/// workspace config, while lists such as `allow_apis` are combined.
+fn layer_member_configs(
+    table: &mut toml::Table,
+    cackle_path: &Path,
+    member_dirs: &[PathBuf],
+) -> Result<()> {
+    let workspace_config_path = cackle_path.canonicalize().ok();
+    for dir in member_dirs {
+        let member_path = dir.join("cackle.toml");
+        if !member_path.exists() || member_path.canonicalize().ok() == workspace_config_path {
+            continue;
+        }
+        let mut member_table = read_config_table(&member_path, false, &mut Vec::new())?;
+        // `common.import` has already been removed, so if nothing else was in `common`, we're fine.
+        if member_table
+            .get("common")
+            .and_then(|common| common.as_table())
+            .is_some_and(|common| common.is_empty())
+        {
+            member_table.remove("common");
+        }
+        if let Some(key) = member_table
+            .keys()
+            .find(|key| !MEMBER_CONFIG_TABLES.contains(&key.as_str()))
+        {
+            bail!(
+                "{}: `{key}` can only be configured in the workspace config {}",
+                member_path.display(),
+                cackle_path.display()
+            );
+        }
+        merge_toml_tables(&mut member_table, std::mem::take(table));
+        *table = member_table;
+    }
+    Ok(())
+}
+
+/// The name of the file, alongside the config file, that holds permissions written by `cargo acl
+/// baseline`. These are granted in addition to those in the config, so that existing problems
+/// don't cause failures while they're being worked through.
+const BASELINE_FILENAME: &str = "cackle-baseline.toml";
+
+/// Tables that may appear in the baseline.
+pub(crate) const BASELINE_TABLES: &[&str] = &["pkg"];
+
+/// The name of the file that `cargo acl baseline` writes the new baseline to while it's running. It
+/// only replaces the baseline once the run succeeds, so that an aborted or failed run leaves the old
+/// baseline in place.
+const PENDING_BASELINE_FILENAME: &str = "cackle-baseline.toml.new";
+
+pub(crate) fn baseline_path(cackle_path: &Path) -> PathBuf {
+    cackle_path.with_file_name(BASELINE_FILENAME)
+}
+
+pub(crate) fn pending_baseline_path(cackle_path: &Path) -> PathBuf {
+    cackle_path.with_file_name(PENDING_BASELINE_FILENAME)
+}
+
+/// Replaces the baseline that goes with the config at `cackle_path` with the pending baseline. If
+/// there's no pending baseline, then nothing needed grandfathering, so the baseline is removed.
+pub(crate) fn replace_baseline(cackle_path: &Path) -> Result<()> {
+    let path = baseline_path(cackle_path);
+    let pending_path = pending_baseline_path(cackle_path);
+    if !pending_path.exists() {
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        return Ok(());
+    }
+    crate::fs::write_atomic(&path, &crate::fs::read_to_string(&pending_path)?)?;
+    discard_pending_baseline(cackle_path)
+}
+
+/// Removes the pending baseline, if any, leaving the existing baseline untouched.
+pub(crate) fn discard_pending_baseline(cackle_path: &Path) -> Result<()> {
+    let pending_path = pending_baseline_path(cackle_path);
+    if pending_path.exists() {
+        std::fs::remove_file(&pending_path)
+            .with_context(|| format!("Failed to remove {}", pending_path.display()))?;
+    }
+    Ok(())
+}
+
+/// Reads the baseline at `path`, if there is one.
+fn read_baseline_table(path: &Path) -> Result<Option<toml::Table>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let table: toml::Table =
+        toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+    if let Some(key) = table
+        .keys()
+        .find(|key| !BASELINE_TABLES.contains(&key.as_str()))
+    {
+        bail!("{}: `{key}` can't be set in a baseline", path.display());
+    }
+    Ok(Some(table))
+}
+
+/// Returns the permissions granted by the baseline at `path`. Empty if there's no baseline.
+pub(crate) fn parse_baseline(path: &Path) -> Result<Permissions> {
+    let Some(table) = read_baseline_table(path)? else {
+        return Ok(Permissions::default());
+    };
+    let packages = table
+        .get("pkg")
+        .cloned()
+        .map(|pkg| pkg.try_into())
+        .transpose()
+        .with_context(|| format!("Failed to parse {}", path.display()))?
+        .unwrap_or_default();
+    Ok(Permissions::from_config(&RawConfig {
+        packages,
+        ..RawConfig::default()
+    }))
+}
+
+/// Returns the number of permissions granted by the baseline at `path`. Each API, flag or other
+/// value granted counts as one. Returns 0 if the file doesn't exist.
+pub(crate) fn count_baseline_entries(path: &Path) -> Result<usize> {
+    fn count(value: &toml::Value) -> usize {
+        match value {
+            toml::Value::Table(table) => table.values().map(count).sum(),
+            toml::Value::Array(array) => array.len(),
+            toml::Value::Boolean(value) => usize::from(*value),
+            _ => 1,
+        }
+    }
+    if !path.exists() {
+        return Ok(0);
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let table: toml::Table =
+        toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(table.values().map(count).sum())
+}
+
+/// Removes and returns `common.import` from `table`. We remove it so that imports don't get
+/// written into the flattened config that gets read by subprocesses.
+fn take_imports(table: &mut toml::Table) -> Result<Vec<ConfigImport>> {
+    let Some(imports) = table
+        .get_mut("common")
+        .and_then(|common| common.as_table_mut())
+        .and_then(|common| common.remove("import"))
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(imports.try_into()?)
+}
+
+/// An entry in `common.import`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+enum ConfigImport {
+    /// A path relative to the importing file.
+    Path(String),
+    Url(UrlImport),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+struct UrlImport {
+    url: String,
+    /// The expected SHA-256 of the file, as lowercase hex. Required so that what we import can't
+    /// change underneath us.
+    sha256: Option<String>,
+}
+
+impl ConfigImport {
+    fn is_url(&self) -> bool {
+        matches!(self, ConfigImport::Url(_))
+    }
+
+    /// Returns the path from which to read this import, fetching it first if it's a URL. `dir` is
+    /// the directory of the importing file and `fetched` is whether that file was itself fetched.
+    fn fetch_or_resolve(&self, dir: &Path, fetched: bool) -> Result<PathBuf> {
+        match self {
+            ConfigImport::Path(path) => {
+                if path.contains("://") {
+                    bail!("URLs must be imported as `{{ url = \"{path}\", sha256 = \"...\" }}`");
+                }
+                if fetched {
+                    // There's nothing for the path to be relative to.
+                    bail!("Config fetched from a URL can only import other URLs");
+                }
+                Ok(dir.join(path))
+            }
+            ConfigImport::Url(UrlImport { url, sha256 }) => {
+                let sha256 = sha256
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Config imported from a URL must specify `sha256`"))?;
+                api_packs::fetch(url, sha256, "imports")
+            }
+        }
+    }
+}
+
+impl Display for ConfigImport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigImport::Path(path) => path.fmt(f),
+            ConfigImport::Url(import) => import.url.fmt(f),
+        }
+    }
+}
+
+/// Merges `imported` into `table`. Tables are merged recursively and arrays are combined. For
+/// other values, those already in `table` take precedence.
+fn merge_toml_tables(table: &mut toml::Table, imported: toml::Table) {
+    for (key, imported_value) in imported {
+        match (table.get_mut(&key), imported_value) {
+            (None, imported_value) => {
+                table.insert(key, imported_value);
+            }
+            (Some(toml::Value::Table(existing)), toml::Value::Table(imported)) => {
+                merge_toml_tables(existing, imported);
+            }
+            (Some(toml::Value::Array(existing)), toml::Value::Array(imported)) => {
+                for value in imported {
+                    if !existing.contains(&value) {
+                        existing.push(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
 fn parse_raw(cackle: &str) -> Result<RawConfig> {
-    let mut config = toml::from_str(cackle)?;
+    parse_raw_table(toml::from_str(cackle)?)
+}
+
+fn parse_raw_table(table: toml::Table) -> Result<RawConfig> {
+    let mut config: RawConfig = table.try_into()?;
     merge_built_ins(&mut config)?;
     versions::apply_runtime_patches(&mut config);
+    config.resolve_sandbox_profiles()?;
     config.rustc.sandbox.inherit(&config.sandbox);
+    config
+        .rustc
+        .proc_macro_sandbox
+        .inherit(&config.rustc.sandbox);
     Ok(config)
 }
 
+fn resolve_api(
+    api_name: &ApiName,
+    apis: &BTreeMap<ApiName, ApiConfig>,
+    resolved: &mut BTreeMap<ApiName, ApiConfig>,
+    stack: &mut Vec<ApiName>,
+) -> Result<ApiConfig> {
+    if let Some(api_config) = resolved.get(api_name) {
+        return Ok(api_config.clone());
+    }
+    if stack.contains(api_name) {
+        bail!("API `{api_name}` is composed from itself");
+    }
+    let Some(api_config) = apis.get(api_name) else {
+        let referenced_from = stack.last().map(|a| a.to_string()).unwrap_or_default();
+        bail!("Unknown API `{api_name}` referenced from API `{referenced_from}`");
+    };
+    stack.push(api_name.clone());
+    let mut result = ApiConfig {
+        include_apis: Vec::new(),
+        exclude_apis: Vec::new(),
+        ..api_config.clone()
+    };
+    for other in &api_config.include_apis {
+        let other = resolve_api(other, apis, resolved, stack)?;
+        result.include.extend(other.include);
+        result.exclude.extend(other.exclude);
+    }
+    for other in &api_config.exclude_apis {
+        let other = resolve_api(other, apis, resolved, stack)?;
+        result.exclude.extend(other.include);
+    }
+    stack.pop();
+    result.include.sort();
+    result.include.dedup();
+    result.exclude.sort();
+    result.exclude.dedup();
+    resolved.insert(api_name.clone(), result.clone());
+    Ok(result)
+}
+
+fn resolve_sandbox_profile(
+    name: &str,
+    profiles: &BTreeMap<String, SandboxConfig>,
+    resolved: &mut BTreeMap<String, SandboxConfig>,
+    stack: &mut Vec<String>,
+) -> Result<SandboxConfig> {
+    if let Some(profile) = resolved.get(name) {
+        return Ok(profile.clone());
+    }
+    if stack.iter().any(|n| n == name) {
+        bail!("Sandbox profile `{name}` extends itself");
+    }
+    let Some(profile) = profiles.get(name) else {
+        bail!("Unknown sandbox profile `{name}`");
+    };
+    if !profile.profiles.is_empty() {
+        bail!("Sandbox profile `{name}` can't define further profiles");
+    }
+    let mut result = profile.clone();
+    if let Some(parent) = result.profile.take() {
+        stack.push(name.to_owned());
+        let parent = resolve_sandbox_profile(&parent, profiles, resolved, stack)?;
+        stack.pop();
+        result.extend(&parent);
+    }
+    resolved.insert(name.to_owned(), result.clone());
+    Ok(result)
+}
+
+impl SandboxConfig {
+    fn apply_profile(&mut self, resolved: &BTreeMap<String, SandboxConfig>) -> Result<()> {
+        if !self.profiles.is_empty() {
+            bail!("Sandbox profiles can only be defined in the top-level `[sandbox]`");
+        }
+        if let Some(name) = self.profile.take() {
+            let profile = resolved
+                .get(&name)
+                .ok_or_else(|| anyhow!("Unknown sandbox profile `{name}`"))?;
+            self.extend(profile);
+        }
+        Ok(())
+    }
+
+    /// Fills in anything not set in this config from `base`. Unlike `inherit`, this also includes
+    /// `pass_env`, since a profile should be usable in place of writing out the config in full.
+    fn extend(&mut self, base: &SandboxConfig) {
+        self.inherit(base);
+        self.pass_env.extend(base.pass_env.iter().cloned());
+        self.pass_env.sort();
+        self.pass_env.dedup();
+    }
+}
+
+impl PackageConfig {
+    fn for_each_sandbox_mut(
+        &mut self,
+        f: &mut dyn FnMut(&mut SandboxConfig) -> Result<()>,
+    ) -> Result<()> {
+        f(&mut self.sandbox)?;
+        let from = self.from.as_mut();
+        let sub_configs = [self.build.as_mut(), self.test.as_mut(), self.dev.as_mut()]
+            .into_iter()
+            .flatten()
+            .chain(
+                from.into_iter()
+                    .flat_map(|from| [from.build.as_mut(), from.test.as_mut(), from.host.as_mut()])
+                    .flatten(),
+            )
+            .map(|config| config.as_mut())
+            .chain(self.conditional.values_mut());
+        for config in sub_configs {
+            config.for_each_sandbox_mut(f)?;
+        }
+        Ok(())
+    }
+}
+
 fn merge_built_ins(config: &mut RawConfig) -> Result<()> {
     if config.common.import_std.is_empty() {
         return Ok(());
@@ -235,6 +1028,43 @@ fn merge_built_ins(config: &mut RawConfig) -> Result<()> {
 }
 
 impl RawConfig {
+    /// Applies the sandbox profile referenced by each sandbox config. Profiles are removed
+    /// afterwards, since they've served their purpose.
+    fn resolve_sandbox_profiles(&mut self) -> Result<()> {
+        let profiles = std::mem::take(&mut self.sandbox.profiles);
+        let mut resolved = BTreeMap::new();
+        for name in profiles.keys() {
+            resolve_sandbox_profile(name, &profiles, &mut resolved, &mut Vec::new())?;
+        }
+        let mut apply = |sandbox: &mut SandboxConfig| sandbox.apply_profile(&resolved);
+        apply(&mut self.sandbox)?;
+        apply(&mut self.rustc.sandbox)?;
+        apply(&mut self.rustc.proc_macro_sandbox)?;
+        for pkg_config in self.packages.values_mut() {
+            pkg_config.for_each_sandbox_mut(&mut apply)?;
+        }
+        Ok(())
+    }
+
+    /// Expands `include_apis` and `exclude_apis` of each API into plain path prefixes. The lists
+    /// are left empty afterwards, so that the flattened config read by subprocesses doesn't get
+    /// expanded a second time.
+    fn resolve_composed_apis(&mut self) -> Result<()> {
+        if self
+            .apis
+            .values()
+            .all(|api| api.include_apis.is_empty() && api.exclude_apis.is_empty())
+        {
+            return Ok(());
+        }
+        let mut resolved = BTreeMap::new();
+        for api_name in self.apis.keys() {
+            resolve_api(api_name, &self.apis, &mut resolved, &mut Vec::new())?;
+        }
+        self.apis = resolved;
+        Ok(())
+    }
+
     fn load_imports(&mut self, crate_index: &CrateIndex) -> Result<()> {
         for (pkg_name, pkg_config) in &mut self.packages {
             // If imports are specified, then we leave an empty list of imports. This ensures that
@@ -423,6 +1253,23 @@ impl From<&str> for PackageName {
     }
 }
 
+impl PackageName {
+    /// Returns the name of the package without any version.
+    pub(crate) fn name(&self) -> &str {
+        self.0.split_once('@').map_or(&self.0, |(name, _)| name)
+    }
+
+    /// Returns the version, if any. e.g. `0.8` for `rand@0.8`.
+    pub(crate) fn version(&self) -> Option<&str> {
+        self.0.split_once('@').map(|(_, version)| version)
+    }
+
+    /// Returns this package name with the version removed, or None if it doesn't have a version.
+    pub(crate) fn without_version(&self) -> Option<PackageName> {
+        self.version().map(|_| PackageName::from(self.name()))
+    }
+}
+
 impl AsRef<str> for PackageName {
     fn as_ref(&self) -> &str {
         &self.0
@@ -456,7 +1303,7 @@ pub(crate) mod testing {
         let raw = super::parse_raw(&cackle_with_header)?;
         let package_names: Vec<_> = raw.packages.keys().map(|k| k.as_ref()).collect();
         let crate_index = crate::crate_index::testing::index_with_package_names(&package_names);
-        let config = Config::from_raw(raw, &crate_index).unwrap();
+        let config = Config::from_raw(raw, &crate_index)?;
         validate(&config, std::path::Path::new("/dev/null"))?;
         Ok(config)
     }
@@ -466,6 +1313,9 @@ pub(crate) mod testing {
 mod tests {
     use super::testing::parse;
     use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::config::ApiPath;
     use crate::config::SandboxKind;
 
     #[test]
@@ -506,6 +1356,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unknown_ui_field() {
+        check_unknown_field(
+            r#"
+            [ui.keys]
+            up = ["k", "up"]
+
+            [ui]
+        "#,
+        );
+    }
+
     #[test]
     fn unknown_api() {
         let result = parse(
@@ -523,6 +1385,356 @@ mod tests {
         "#,
         );
         assert!(result.is_err());
+
+        let result = parse(
+            r#"
+            [pkg.foo]
+            deny_apis = ["typo"]
+        "#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn invalid_package_version() {
+        for version in ["0.8.5", "1.2", "0", "0.0", "x"] {
+            let result = parse(&format!("[pkg.'foo@{version}']\nallow_unsafe = true\n"));
+            assert!(result.is_err(), "{version}");
+        }
+        assert!(parse("[pkg.'foo@0.8']\nallow_unsafe = true\n").is_ok());
+    }
+
+    #[test]
+    fn composed_api() {
+        let config = parse(
+            r#"
+            [api.net]
+            include = ["std::net"]
+            exclude = ["std::net::Ipv4Addr"]
+
+            [api.listen]
+            include = ["std::net::TcpListener", "std::net::UdpSocket::bind"]
+
+            [api.net-client]
+            include_apis = ["net"]
+            exclude_apis = ["listen"]
+
+            [api.net-client-and-process]
+            include = ["std::process"]
+            include_apis = ["net-client"]
+        "#,
+        )
+        .unwrap();
+        let api = config
+            .raw
+            .apis
+            .get(&ApiName::new("net-client-and-process"))
+            .unwrap();
+        let paths = |paths: &[ApiPath]| {
+            paths
+                .iter()
+                .map(|p| p.prefix.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(&api.include), vec!["std::net", "std::process"]);
+        assert_eq!(
+            paths(&api.exclude),
+            vec![
+                "std::net::Ipv4Addr",
+                "std::net::TcpListener",
+                "std::net::UdpSocket::bind"
+            ]
+        );
+        assert!(api.include_apis.is_empty());
+        assert!(api.exclude_apis.is_empty());
+    }
+
+    #[test]
+    fn composed_api_errors() {
+        let result = parse(
+            r#"
+            [api.a]
+            include_apis = ["typo"]
+        "#,
+        );
+        assert!(result.is_err());
+
+        let result = parse(
+            r#"
+            [api.a]
+            include_apis = ["b"]
+
+            [api.b]
+            exclude_apis = ["a"]
+        "#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn import_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &str| {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+        };
+        write(
+            "base.toml",
+            r#"
+            [common]
+            import_std = ["fs"]
+            features = ["base"]
+
+            [sandbox]
+            kind = "Bubblewrap"
+            allow_network = false
+
+            [pkg.foo]
+            allow_apis = ["fs"]
+        "#,
+        );
+        std::fs::create_dir(dir.path().join("policy")).unwrap();
+        write(
+            "policy/net.toml",
+            r#"
+            [common]
+            import = ["../base.toml"]
+            features = ["net"]
+
+            [api.net]
+            include = ["std::net"]
+
+            [sandbox]
+            allow_network = true
+        "#,
+        );
+        write(
+            "cackle.toml",
+            r#"
+            [common]
+            version = 2
+            import = ["base.toml", "policy/net.toml"]
+
+            [pkg.foo]
+            allow_apis = ["net"]
+            allow_unsafe = true
+        "#,
+        );
+        let config = super::parse_file_raw(&dir.path().join("cackle.toml")).unwrap();
+        assert_eq!(config.common.version, 2);
+        assert_eq!(config.common.features, &["net", "base"]);
+        assert!(config.apis.contains_key(&ApiName::new("fs")));
+        assert!(config.apis.contains_key(&ApiName::new("net")));
+        assert_eq!(config.sandbox.kind, Some(SandboxKind::Bubblewrap));
+        assert_eq!(config.sandbox.allow_network, Some(true));
+        let foo = config
+            .packages
+            .get(&super::PackageName("foo".into()))
+            .unwrap();
+        assert_eq!(foo.allow_apis, &[ApiName::new("net"), ApiName::new("fs")]);
+        assert!(foo.allow_unsafe);
+    }
+
+    #[test]
+    fn member_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for member in ["a", "b"] {
+            std::fs::create_dir(root.join(member)).unwrap();
+        }
+        std::fs::write(
+            root.join("cackle.toml"),
+            indoc::indoc! {r#"
+                [common]
+                version = 2
+                import_std = ["fs", "net"]
+
+                [pkg.foo]
+                allow_apis = ["fs"]
+                allow_unsafe = true
+            "#},
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("a").join("cackle.toml"),
+            indoc::indoc! {r#"
+                [pkg.foo]
+                allow_apis = ["net"]
+                allow_unsafe = false
+            "#},
+        )
+        .unwrap();
+        let member_dirs = vec![root.to_owned(), root.join("a"), root.join("b")];
+        let cackle_path = root.join("cackle.toml");
+        let mut table = super::read_config_table(&cackle_path, false, &mut Vec::new()).unwrap();
+        super::layer_member_configs(&mut table, &cackle_path, &member_dirs).unwrap();
+        let config = super::parse_raw_table(table).unwrap();
+        let foo = config
+            .packages
+            .get(&super::PackageName("foo".into()))
+            .unwrap();
+        assert_eq!(foo.allow_apis, &[ApiName::new("net"), ApiName::new("fs")]);
+        assert!(!foo.allow_unsafe);
+
+        // Members can't change workspace-wide settings.
+        std::fs::write(
+            root.join("b").join("cackle.toml"),
+            "[sandbox]\nkind = \"Disabled\"\n",
+        )
+        .unwrap();
+        let mut table = super::read_config_table(&cackle_path, false, &mut Vec::new()).unwrap();
+        assert!(super::layer_member_configs(&mut table, &cackle_path, &member_dirs).is_err());
+    }
+
+    #[test]
+    fn baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let cackle_path = dir.path().join("cackle.toml");
+        let baseline_path = super::baseline_path(&cackle_path);
+        assert_eq!(super::count_baseline_entries(&baseline_path).unwrap(), 0);
+        assert!(super::parse_baseline(&baseline_path)
+            .unwrap()
+            .packages
+            .is_empty());
+        std::fs::write(
+            &baseline_path,
+            indoc::indoc! {r#"
+                [pkg.foo]
+                allow_apis = ["fs", "net"]
+                allow_unsafe = true
+
+                [pkg.foo.build]
+                allow_build_instructions = ["cargo:rustc-link-search=*"]
+            "#},
+        )
+        .unwrap();
+        assert_eq!(super::count_baseline_entries(&baseline_path).unwrap(), 4);
+        let baseline = super::parse_baseline(&baseline_path).unwrap();
+        let foo = baseline.get(&PermSel::for_primary("foo")).unwrap();
+        assert_eq!(foo.allow_apis, &[ApiName::new("fs"), ApiName::new("net")]);
+        assert!(baseline.get(&PermSel::for_build_script("foo")).is_some());
+
+        // A baseline can only grant permissions.
+        std::fs::write(&baseline_path, "[sandbox]\nkind = \"Disabled\"\n").unwrap();
+        assert!(super::read_baseline_table(&baseline_path).is_err());
+    }
+
+    #[test]
+    fn replace_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let cackle_path = dir.path().join("cackle.toml");
+        let baseline_path = super::baseline_path(&cackle_path);
+        let pending_path = super::pending_baseline_path(&cackle_path);
+        let old = "[pkg.foo]\nallow_apis = [\"fs\"]\n";
+        let new = "[pkg.bar]\nallow_apis = [\"net\"]\n";
+        std::fs::write(&baseline_path, old).unwrap();
+
+        // A failed or aborted run discards what it had written so far.
+        std::fs::write(&pending_path, new).unwrap();
+        super::discard_pending_baseline(&cackle_path).unwrap();
+        assert_eq!(std::fs::read_to_string(&baseline_path).unwrap(), old);
+        assert!(!pending_path.exists());
+
+        // A successful run replaces the old baseline.
+        std::fs::write(&pending_path, new).unwrap();
+        super::replace_baseline(&cackle_path).unwrap();
+        assert_eq!(std::fs::read_to_string(&baseline_path).unwrap(), new);
+        assert!(!pending_path.exists());
+
+        // A successful run that didn't need to grandfather anything removes the baseline.
+        super::replace_baseline(&cackle_path).unwrap();
+        assert!(!baseline_path.exists());
+    }
+
+    #[test]
+    fn import_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a.toml"),
+            "[common]\nversion = 2\nimport = [\"b.toml\"]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.toml"),
+            "[common]\nimport = [\"a.toml\"]\n",
+        )
+        .unwrap();
+        let error = super::parse_file_raw(&dir.path().join("a.toml")).unwrap_err();
+        assert!(format!("{error:#}").contains("import cycle"));
+
+        // Config imported from a URL is pinned by its checksum, so can't import anything that
+        // imports it in turn. The only way it could get back to `a.toml` is via a path, which isn't
+        // allowed.
+        let url_import = |name: &str| {
+            let path = dir.path().join(name);
+            format!(
+                "{{ url = \"file://{}\", sha256 = \"{}\" }}",
+                path.display(),
+                crate::fs::sha256(&path).unwrap()
+            )
+        };
+        std::fs::write(
+            dir.path().join("b.toml"),
+            "[common]\nimport = [\"a.toml\"]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("a.toml"),
+            format!(
+                "[common]\nversion = 2\nimport = [{}]\n",
+                url_import("b.toml")
+            ),
+        )
+        .unwrap();
+        let error = super::parse_file_raw(&dir.path().join("a.toml")).unwrap_err();
+        assert!(format!("{error:#}").contains("can only import other URLs"));
+
+        std::fs::write(
+            dir.path().join("c.toml"),
+            "[api.net]\ninclude = [\"std::net\"]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.toml"),
+            format!("[common]\nimport = [{}]\n", url_import("c.toml")),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("a.toml"),
+            format!(
+                "[common]\nversion = 2\nimport = [{}]\n",
+                url_import("b.toml")
+            ),
+        )
+        .unwrap();
+        let config = super::parse_file_raw(&dir.path().join("a.toml")).unwrap();
+        assert!(config.apis.contains_key(&ApiName::from("net")));
+
+        // The checksum is required and must match.
+        std::fs::write(
+            dir.path().join("a.toml"),
+            format!(
+                "[common]\nversion = 2\nimport = [{{ url = \"file://{}\" }}]\n",
+                dir.path().join("c.toml").display()
+            ),
+        )
+        .unwrap();
+        let error = super::parse_file_raw(&dir.path().join("a.toml")).unwrap_err();
+        assert!(format!("{error:#}").contains("must specify `sha256`"));
+        // Content that's unique to this test, so that it can't have been fetched and cached.
+        std::fs::write(
+            dir.path().join("c.toml"),
+            format!("# {}\n", dir.path().display()),
+        )
+        .unwrap();
+        let import = url_import("c.toml");
+        std::fs::write(dir.path().join("c.toml"), "").unwrap();
+        std::fs::write(
+            dir.path().join("a.toml"),
+            format!("[common]\nversion = 2\nimport = [{import}]\n"),
+        )
+        .unwrap();
+        let error = super::parse_file_raw(&dir.path().join("a.toml")).unwrap_err();
+        assert!(format!("{error:#}").contains("Checksum mismatch"));
     }
 
     #[test]
@@ -548,11 +1760,23 @@ mod tests {
                 extra_args = [
                     "--extra1",
                 ]
+                max_wall_seconds = 300
+                max_memory_mb = 2048
+                deny_env = [
+                    "AWS_*",
+                ]
+                set_env.TZ = "UTC"
+                set_env.LANG = "C"
 
                 [pkg.a.build.sandbox]
                 extra_args = [
                     "--extra2",
                 ]
+                max_wall_seconds = 900
+                deny_env = [
+                    "NPM_TOKEN",
+                ]
+                set_env.TZ = "Europe/Paris"
 
                 [pkg.b.build.sandbox]
                 kind = "Disabled"
@@ -565,11 +1789,165 @@ mod tests {
             .sandbox_config_for_package(&PermSel::for_build_script("a"));
         assert_eq!(sandbox_a.kind, Some(SandboxKind::Bubblewrap));
         assert_eq!(sandbox_a.extra_args, vec!["--extra1", "--extra2"]);
+        assert_eq!(sandbox_a.max_wall_seconds, Some(900));
+        assert_eq!(sandbox_a.max_memory_mb, Some(2048));
+        assert_eq!(sandbox_a.max_cpu_seconds, None);
+        assert_eq!(sandbox_a.deny_env, vec!["AWS_*", "NPM_TOKEN"]);
+        assert_eq!(
+            sandbox_a.set_env.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("LANG".to_owned(), "C".to_owned()),
+                ("TZ".to_owned(), "Europe/Paris".to_owned())
+            ]
+        );
+
+        let sandbox_b = config
+            .permissions
+            .sandbox_config_for_package(&PermSel::for_build_script("b"));
+        assert_eq!(sandbox_b.kind, Some(SandboxKind::Disabled));
+    }
+
+    #[test]
+    fn sandbox_profiles() {
+        let config = parse(
+            r#"
+                [sandbox]
+                kind = "Bubblewrap"
+
+                [sandbox.profiles.networkless]
+                extra_args = [
+                    "--unshare-net",
+                ]
+                pass_env = [
+                    "HOME",
+                ]
+
+                [sandbox.profiles.codegen]
+                profile = "networkless"
+                make_writable = [
+                    "generated",
+                ]
+
+                [pkg.a.build.sandbox]
+                profile = "codegen"
+                pass_env = [
+                    "PROTOC",
+                ]
+
+                [pkg.b.build.sandbox]
+                profile = "networkless"
+                kind = "Disabled"
+            "#,
+        )
+        .unwrap();
+        assert!(config.raw.sandbox.profiles.is_empty());
+
+        let sandbox_a = config
+            .permissions
+            .sandbox_config_for_package(&PermSel::for_build_script("a"));
+        assert_eq!(sandbox_a.kind, Some(SandboxKind::Bubblewrap));
+        assert_eq!(sandbox_a.extra_args, vec!["--unshare-net"]);
+        assert_eq!(sandbox_a.pass_env, vec!["HOME", "PROTOC"]);
+        assert!(sandbox_a.make_writable[0].ends_with("generated"));
+        assert_eq!(sandbox_a.profile, None);
 
         let sandbox_b = config
             .permissions
             .sandbox_config_for_package(&PermSel::for_build_script("b"));
         assert_eq!(sandbox_b.kind, Some(SandboxKind::Disabled));
+        assert_eq!(sandbox_b.extra_args, vec!["--unshare-net"]);
+    }
+
+    #[test]
+    fn sandbox_map_permissions() {
+        let config = parse(
+            r#"
+                [api.net]
+                include = ["std::net"]
+
+                [sandbox]
+                kind = "Bubblewrap"
+
+                [pkg.a]
+                allow_apis = ["net"]
+                test.sandbox.map_permissions = true
+
+                [pkg.b]
+                allow_apis = ["net"]
+                test.sandbox.map_permissions = true
+                test.sandbox.allow_network = false
+
+                [pkg.c]
+                test.sandbox.map_permissions = true
+            "#,
+        )
+        .unwrap();
+        let allow_network = |pkg: &str| {
+            config
+                .permissions
+                .sandbox_config_for_package(&PermSel::with_scope(
+                    &crate::crate_index::testing::pkg_id(pkg),
+                    PermissionScope::Test,
+                ))
+                .allow_network
+        };
+        assert_eq!(allow_network("a"), Some(true));
+        assert_eq!(allow_network("b"), Some(false));
+        assert_eq!(allow_network("c"), None);
+    }
+
+    #[test]
+    fn proc_macro_sandbox() {
+        // With config version 1, rustc isn't sandboxed by default, but crates that use proc macros
+        // can opt in.
+        let config = parse(
+            r#"
+                [sandbox]
+                kind = "Bubblewrap"
+
+                [rustc.proc_macro_sandbox]
+                kind = "Bubblewrap"
+                allow_network = true
+            "#,
+        )
+        .unwrap();
+        let rustc = &config.raw.rustc;
+        assert_eq!(rustc.sandbox.kind, Some(SandboxKind::Disabled));
+        assert_eq!(rustc.proc_macro_sandbox.kind, Some(SandboxKind::Bubblewrap));
+        assert_eq!(rustc.proc_macro_sandbox.allow_network, Some(true));
+
+        // Otherwise, the proc macro sandbox inherits from the rustc sandbox.
+        let config = parse(
+            r#"
+                [rustc.sandbox]
+                kind = "Namespaces"
+            "#,
+        )
+        .unwrap();
+        let rustc = &config.raw.rustc;
+        assert_eq!(rustc.proc_macro_sandbox.kind, Some(SandboxKind::Namespaces));
+    }
+
+    #[test]
+    fn wrapper_passthrough() {
+        let wrapper = |cackle: &str| parse(cackle).unwrap().raw.wrapper.clone();
+        assert!(wrapper("").passthrough(false));
+        assert!(!wrapper("").passthrough(true));
+        let disabled = wrapper("[wrapper]\npassthrough = false");
+        assert!(!disabled.passthrough(false));
+        let enabled = wrapper("[wrapper]\npassthrough = true");
+        assert!(enabled.passthrough(true));
+    }
+
+    #[test]
+    fn invalid_sandbox_profiles() {
+        for cackle in [
+            "[pkg.a.build.sandbox]\nprofile = \"missing\"\n",
+            "[sandbox.profiles.a]\nprofile = \"b\"\n[sandbox.profiles.b]\nprofile = \"a\"\n",
+            "[pkg.a.sandbox.profiles.x]\nkind = \"Disabled\"\n",
+        ] {
+            assert!(parse(cackle).is_err(), "{cackle}");
+        }
     }
 
     #[test]
@@ -598,5 +1976,8 @@ mod tests {
         assert!(parse("[pkg.x.build.build]").is_err());
         assert!(parse("[pkg.x.test.dep]").is_err());
         assert!(parse("[pkg.x.test.test]").is_err());
+        assert!(parse("[pkg.x.dev.dev]").is_err());
+        assert!(parse("[pkg.x.build]\nversion = \"1.*\"").is_err());
+        assert!(parse("[pkg.x]\nversion = \"1.*\"").is_ok());
     }
 }
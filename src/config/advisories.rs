@@ -0,0 +1,296 @@
+//! Cross-checks the packages in the dependency tree against the RustSec advisory database, so that
+//! packages that have outstanding advisories and are also granted sensitive APIs, such as `net` or
+//! `process`, can be reported and prioritised for review.
+
+use super::ApiName;
+use super::RawConfig;
+use crate::crate_index::CrateIndex;
+use crate::crate_index::PackageId;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use cargo_metadata::semver::Version;
+use cargo_metadata::semver::VersionReq;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// Where the advisory database is fetched from when `fetch` is set.
+const DB_URL: &str = "https://github.com/rustsec/advisory-db";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AdvisoriesConfig {
+    /// APIs that packages with outstanding advisories get reported for being granted. Advisories
+    /// aren't checked if this is empty.
+    #[serde(default)]
+    pub(crate) sensitive_apis: Vec<ApiName>,
+
+    /// A checkout of the advisory database, relative to the workspace root. Defaults to
+    /// `$CARGO_HOME/advisory-db`, which is where cargo-audit keeps it.
+    #[serde(default)]
+    pub(crate) db: Option<PathBuf>,
+
+    /// Whether to clone or update the advisory database before checking.
+    #[serde(default)]
+    pub(crate) fetch: bool,
+
+    /// IDs of advisories that have been reviewed and shouldn't be reported again.
+    #[serde(default)]
+    pub(crate) ignore: Vec<String>,
+
+    /// Advisories that affect the versions of packages in the dependency tree, excluding those in
+    /// `ignore`. Populated by `load`.
+    #[serde(skip)]
+    pub(crate) outstanding: Vec<(PackageId, Vec<Advisory>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Advisory {
+    pub(crate) id: String,
+    pub(crate) title: String,
+    /// Set for advisories that aren't about a vulnerability, e.g. `unmaintained` or `unsound`.
+    pub(crate) informational: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMetadata,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryMetadata {
+    id: String,
+    #[serde(default)]
+    informational: Option<String>,
+    #[serde(default)]
+    withdrawn: Option<toml::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct AdvisoryVersions {
+    #[serde(default)]
+    patched: Vec<VersionReq>,
+    #[serde(default)]
+    unaffected: Vec<VersionReq>,
+}
+
+/// Reads the advisories for each package in the dependency tree, fetching the database first if
+/// requested, and records those that are outstanding.
+pub(crate) fn load(
+    config: &mut RawConfig,
+    crate_index: &CrateIndex,
+    workspace_root: Option<&Path>,
+) -> Result<()> {
+    if config.advisories.sensitive_apis.is_empty() {
+        return Ok(());
+    }
+    let db = match (&config.advisories.db, workspace_root) {
+        (Some(db), Some(root)) => root.join(db),
+        (Some(db), None) => db.clone(),
+        (None, _) => crate::proxy::cargo::cargo_home()
+            .context("Couldn't determine CARGO_HOME, so `advisories.db` must be set")?
+            .join("advisory-db"),
+    };
+    if config.advisories.fetch {
+        fetch(&db)?;
+    }
+    if !db.join("crates").is_dir() {
+        bail!(
+            "Advisory database not found at `{}`. Set `advisories.fetch = true` to fetch it",
+            db.display()
+        );
+    }
+    let mut outstanding = Vec::new();
+    let mut pkg_ids: Vec<&PackageId> = crate_index.package_ids().collect();
+    pkg_ids.sort_by(|a, b| (a.name_str(), a.version()).cmp(&(b.name_str(), b.version())));
+    for pkg_id in pkg_ids {
+        let advisories: Vec<Advisory> = read_advisories(&db, pkg_id.name_str())?
+            .into_iter()
+            .filter(|(advisory, versions)| {
+                versions.affects(pkg_id.version())
+                    && !config.advisories.ignore.contains(&advisory.id)
+            })
+            .map(|(advisory, _)| advisory)
+            .collect();
+        if !advisories.is_empty() {
+            outstanding.push((pkg_id.clone(), advisories));
+        }
+    }
+    config.advisories.outstanding = outstanding;
+    Ok(())
+}
+
+/// Returns the advisories for `pkg_name` that haven't been withdrawn, sorted by ID.
+fn read_advisories(db: &Path, pkg_name: &str) -> Result<Vec<(Advisory, AdvisoryVersions)>> {
+    let dir = db.join("crates").join(pkg_name);
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut advisories = Vec::new();
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("Failed to read directory `{}`", dir.display()))?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "md") {
+            let contents = crate::fs::read_to_string(&path)?;
+            if let Some(advisory) = parse_advisory(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+            {
+                advisories.push(advisory);
+            }
+        }
+    }
+    advisories.sort_by(|a, b| a.0.id.cmp(&b.0.id));
+    Ok(advisories)
+}
+
+/// Parses an advisory, which is markdown starting with a fenced block of TOML metadata, followed by
+/// a heading with the title. Returns None if the advisory has been withdrawn.
+fn parse_advisory(contents: &str) -> Result<Option<(Advisory, AdvisoryVersions)>> {
+    let Some((metadata, rest)) = contents
+        .trim_start()
+        .strip_prefix("```toml")
+        .and_then(|rest| rest.split_once("```"))
+    else {
+        bail!("Advisory doesn't start with TOML metadata");
+    };
+    let file: AdvisoryFile = toml::from_str(metadata)?;
+    if file.advisory.withdrawn.is_some() {
+        return Ok(None);
+    }
+    let title = rest
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .unwrap_or_default()
+        .trim()
+        .to_owned();
+    Ok(Some((
+        Advisory {
+            id: file.advisory.id,
+            title,
+            informational: file.advisory.informational,
+        },
+        file.versions,
+    )))
+}
+
+impl AdvisoryVersions {
+    fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(&self.unaffected)
+            .any(|req| req.matches(version))
+    }
+}
+
+/// Clones the advisory database into `db`, or updates it if it's already there. Only done once per
+/// run, since the config gets reloaded each time a fix is applied.
+fn fetch(db: &Path) -> Result<()> {
+    static FETCHED: AtomicBool = AtomicBool::new(false);
+    if FETCHED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let mut command = Command::new("git");
+    if db.join(".git").exists() {
+        command
+            .arg("-C")
+            .arg(db)
+            .args(["pull", "--ff-only", "--quiet"]);
+    } else {
+        command
+            .args(["clone", "--depth", "1", "--quiet", DB_URL])
+            .arg(db);
+    }
+    let output = command.output().context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to fetch advisory database into `{}`: {}",
+            db.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for Advisory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)?;
+        if let Some(informational) = &self.informational {
+            write!(f, " ({informational})")?;
+        }
+        if !self.title.is_empty() {
+            write!(f, ": {}", self.title)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crate_index::testing::index_with_package_names;
+    use crate::crate_index::testing::pkg_id;
+    use indoc::indoc;
+
+    #[test]
+    fn outstanding_advisories() {
+        let dir = tempfile::tempdir().unwrap();
+        let crab1 = dir.path().join("crates").join("crab1");
+        std::fs::create_dir_all(&crab1).unwrap();
+        let advisory = |id: &str, extra: &str| {
+            format!(
+                "```toml\n[advisory]\nid = \"{id}\"\npackage = \"crab1\"\n\
+                date = \"2024-01-01\"\n{extra}\n```\n\n# Problem in {id}\n\nDetails.\n"
+            )
+        };
+        let advisories = [
+            (
+                "RUSTSEC-2024-0001",
+                "[versions]\nunaffected = [\"< 0.1.0\"]",
+            ),
+            ("RUSTSEC-2024-0002", "informational = \"unmaintained\""),
+            ("RUSTSEC-2024-0003", "withdrawn = \"2024-02-01\""),
+            ("RUSTSEC-2024-0004", ""),
+            ("RUSTSEC-2024-0005", ""),
+        ];
+        for (id, extra) in advisories {
+            std::fs::write(crab1.join(format!("{id}.md")), advisory(id, extra)).unwrap();
+        }
+        let mut config = crate::config::parse_raw(&format!(
+            indoc! {r#"
+                [common]
+                version = 1
+
+                [api.net]
+                include = ["std::net"]
+
+                [advisories]
+                sensitive_apis = ["net"]
+                db = "{}"
+                ignore = ["RUSTSEC-2024-0005"]
+            "#},
+            dir.path().display()
+        ))
+        .unwrap();
+        let crate_index = index_with_package_names(&["crab1", "crab2"]);
+        super::load(&mut config, &crate_index, None).unwrap();
+        let outstanding = &config.advisories.outstanding;
+        assert_eq!(outstanding.len(), 1);
+        assert_eq!(outstanding[0].0, pkg_id("crab1"));
+        let advisories: Vec<String> = outstanding[0].1.iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            advisories,
+            [
+                "RUSTSEC-2024-0002 (unmaintained): Problem in RUSTSEC-2024-0002",
+                "RUSTSEC-2024-0004: Problem in RUSTSEC-2024-0004"
+            ]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! API packs are TOML files that contain API definitions for a particular ecosystem, e.g. mapping
+//! parts of tokio's public API to the `net` and `fs` APIs. They can be loaded from a local file or
+//! fetched from a URL. Fetched packs must have a pinned checksum and are cached once verified. The
+//! same fetching is used for config files imported from a URL.
+
+use super::ApiConfig;
+use super::ApiName;
+use super::RawConfig;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Where to load an API pack from. Exactly one of `path` and `url` should be set.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ApiPackSource {
+    /// A local file, relative to the workspace root.
+    pub(crate) path: Option<PathBuf>,
+
+    pub(crate) url: Option<String>,
+
+    /// The expected SHA-256 of the pack, as lowercase hex. Required when `url` is set.
+    pub(crate) sha256: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ApiPack {
+    #[serde(default, rename = "api")]
+    apis: BTreeMap<ApiName, ApiConfig>,
+}
+
+/// Loads all API packs listed in `config` and merges their API definitions into `config`. The list
+/// of packs is cleared, so that subprocesses reading the flattened config don't load them again.
+pub(crate) fn load(config: &mut RawConfig, workspace_root: Option<&Path>) -> Result<()> {
+    for source in std::mem::take(&mut config.common.api_packs) {
+        let pack = read_pack(&source, workspace_root)
+            .with_context(|| format!("Failed to load API pack {}", source.describe()))?;
+        for (api_name, pack_api) in pack.apis {
+            let api_config = config.apis.entry(api_name).or_default();
+            api_config.include.extend(pack_api.include);
+            api_config.exclude.extend(pack_api.exclude);
+            api_config.no_auto_detect.extend(pack_api.no_auto_detect);
+            api_config.include_apis.extend(pack_api.include_apis);
+            api_config.exclude_apis.extend(pack_api.exclude_apis);
+        }
+    }
+    Ok(())
+}
+
+fn read_pack(source: &ApiPackSource, workspace_root: Option<&Path>) -> Result<ApiPack> {
+    let path = match (&source.path, &source.url) {
+        (Some(path), None) => {
+            let path = match workspace_root {
+                Some(root) => root.join(path),
+                None => path.clone(),
+            };
+            if let Some(expected) = &source.sha256 {
+                verify_checksum(&path, expected)?;
+            }
+            path
+        }
+        (None, Some(url)) => {
+            let expected = source
+                .sha256
+                .as_ref()
+                .ok_or_else(|| anyhow!("API packs fetched from a URL must specify `sha256`"))?;
+            fetch(url, expected, "api-packs")?
+        }
+        _ => bail!("API pack must specify exactly one of `path` or `url`"),
+    };
+    let contents = crate::fs::read_to_string(&path)?;
+    toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Downloads `url` unless we already have a cached copy with the expected checksum. Returns the
+/// path of the verified file. `kind` is the subdirectory of our cache directory to use.
+pub(crate) fn fetch(url: &str, expected_sha256: &str, kind: &str) -> Result<PathBuf> {
+    if !is_sha256(expected_sha256) {
+        bail!("`{expected_sha256}` isn't a valid SHA-256");
+    }
+    let dir = cache_dir().join(kind);
+    let cached = dir.join(format!("{expected_sha256}.toml"));
+    if cached.exists() && verify_checksum(&cached, expected_sha256).is_ok() {
+        return Ok(cached);
+    }
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    let download = dir.join(format!("{expected_sha256}.download"));
+    let output = Command::new("curl")
+        .arg("--fail")
+        .arg("--silent")
+        .arg("--show-error")
+        .arg("--location")
+        .arg("--output")
+        .arg(&download)
+        .arg(url)
+        .output()
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to fetch {url}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    verify_checksum(&download, expected_sha256)?;
+    std::fs::rename(&download, &cached)
+        .with_context(|| format!("Failed to rename `{}`", download.display()))?;
+    Ok(cached)
+}
+
+fn verify_checksum(path: &Path, expected: &str) -> Result<()> {
+    let actual = crate::fs::sha256(path)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!(
+            "Checksum mismatch for {}. Expected {expected}, got {actual}",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn is_sha256(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+fn cache_dir() -> PathBuf {
+    // Keep files fetched by tests out of the user's cache.
+    if cfg!(test) {
+        return std::env::temp_dir().join("cackle-test-cache");
+    }
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("cackle")
+}
+
+impl ApiPackSource {
+    fn describe(&self) -> String {
+        match (&self.path, &self.url) {
+            (Some(path), _) => format!("`{}`", path.display()),
+            (None, Some(url)) => format!("`{url}`"),
+            (None, None) => "with no source".to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::ApiName;
+    use crate::config::ApiPath;
+    use indoc::indoc;
+
+    #[test]
+    fn load_local_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack = indoc! {r#"
+            [api.net]
+            include = ["tokio::net"]
+            exclude = ["tokio::net::unix"]
+        "#};
+        std::fs::write(dir.path().join("tokio.toml"), pack).unwrap();
+        let mut config = crate::config::parse_raw(indoc! {r#"
+            [common]
+            version = 2
+            api_packs = [
+                { path = "tokio.toml" },
+            ]
+
+            [api.net]
+            include = ["std::net"]
+        "#})
+        .unwrap();
+        super::load(&mut config, Some(dir.path())).unwrap();
+        assert!(config.common.api_packs.is_empty());
+        let net = &config.apis[&ApiName::from("net")];
+        assert_eq!(
+            net.include,
+            vec![
+                ApiPath::from_str("std::net"),
+                ApiPath::from_str("tokio::net")
+            ]
+        );
+        assert_eq!(net.exclude, vec![ApiPath::from_str("tokio::net::unix")]);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("pack.toml"), "").unwrap();
+        let mut config = crate::config::parse_raw(indoc! {r#"
+            [common]
+            version = 2
+            api_packs = [
+                { path = "pack.toml", sha256 = "0000000000000000000000000000000000000000000000000000000000000000" },
+            ]
+        "#})
+        .unwrap();
+        assert!(super::load(&mut config, Some(dir.path())).is_err());
+
+        // The SHA-256 of an empty file.
+        let mut config = crate::config::parse_raw(indoc! {r#"
+            [common]
+            version = 2
+            api_packs = [
+                { path = "pack.toml", sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855" },
+            ]
+        "#})
+        .unwrap();
+        super::load(&mut config, Some(dir.path())).unwrap();
+    }
+
+    #[test]
+    fn url_requires_checksum() {
+        let mut config = crate::config::parse_raw(indoc! {r#"
+            [common]
+            version = 2
+            api_packs = [
+                { url = "https://example.com/pack.toml" },
+            ]
+        "#})
+        .unwrap();
+        assert!(super::load(&mut config, None).is_err());
+    }
+}
@@ -18,15 +18,66 @@ pub(crate) fn get_built_ins() -> BTreeMap<ApiName, ApiConfig> {
                 "std::os::windows::fs",
                 "std::os::windows::io",
                 "std::path",
+                // WebAssembly imports from WASI.
+                "wasi_snapshot_preview1::fd_advise",
+                "wasi_snapshot_preview1::fd_allocate",
+                "wasi_snapshot_preview1::fd_close",
+                "wasi_snapshot_preview1::fd_datasync",
+                "wasi_snapshot_preview1::fd_fdstat_get",
+                "wasi_snapshot_preview1::fd_fdstat_set_flags",
+                "wasi_snapshot_preview1::fd_filestat_get",
+                "wasi_snapshot_preview1::fd_filestat_set_size",
+                "wasi_snapshot_preview1::fd_filestat_set_times",
+                "wasi_snapshot_preview1::fd_pread",
+                "wasi_snapshot_preview1::fd_prestat_dir_name",
+                "wasi_snapshot_preview1::fd_prestat_get",
+                "wasi_snapshot_preview1::fd_pwrite",
+                "wasi_snapshot_preview1::fd_read",
+                "wasi_snapshot_preview1::fd_readdir",
+                "wasi_snapshot_preview1::fd_renumber",
+                "wasi_snapshot_preview1::fd_seek",
+                "wasi_snapshot_preview1::fd_sync",
+                "wasi_snapshot_preview1::fd_tell",
+                "wasi_snapshot_preview1::fd_write",
+                "wasi_snapshot_preview1::path_create_directory",
+                "wasi_snapshot_preview1::path_filestat_get",
+                "wasi_snapshot_preview1::path_filestat_set_times",
+                "wasi_snapshot_preview1::path_link",
+                "wasi_snapshot_preview1::path_open",
+                "wasi_snapshot_preview1::path_readlink",
+                "wasi_snapshot_preview1::path_remove_directory",
+                "wasi_snapshot_preview1::path_rename",
+                "wasi_snapshot_preview1::path_symlink",
+                "wasi_snapshot_preview1::path_unlink_file",
+            ],
+            &[],
+        ),
+    );
+    result.insert(
+        ApiName::from("env"),
+        perm(
+            &[
+                "std::env",
+                "wasi_snapshot_preview1::args_get",
+                "wasi_snapshot_preview1::args_sizes_get",
+                "wasi_snapshot_preview1::environ_get",
+                "wasi_snapshot_preview1::environ_sizes_get",
             ],
             &[],
         ),
     );
-    result.insert(ApiName::from("env"), perm(&["std::env"], &[]));
     result.insert(
         ApiName::from("net"),
         perm(
-            &["std::net", "std::os::wasi::net", "std::os::windows::net"],
+            &[
+                "std::net",
+                "std::os::wasi::net",
+                "std::os::windows::net",
+                "wasi_snapshot_preview1::sock_accept",
+                "wasi_snapshot_preview1::sock_recv",
+                "wasi_snapshot_preview1::sock_send",
+                "wasi_snapshot_preview1::sock_shutdown",
+            ],
             &[],
         ),
     );
@@ -47,7 +98,134 @@ pub(crate) fn get_built_ins() -> BTreeMap<ApiName, ApiConfig> {
     );
     result.insert(
         ApiName::from("terminate"),
-        perm(&["std::process::abort", "std::process::exit"], &[]),
+        perm(
+            &[
+                "std::process::abort",
+                "std::process::exit",
+                "wasi_snapshot_preview1::proc_exit",
+            ],
+            &[],
+        ),
+    );
+    // Spawning threads. Things like sleeping and thread-locals don't create threads, so are
+    // excluded. Newer versions of std define the free functions in `std::thread::functions`.
+    result.insert(
+        ApiName::from("thread"),
+        perm(
+            &[
+                "std::thread",
+                "pthread_create",
+                "thrd_create",
+                "CreateThread",
+                "_beginthreadex",
+                "__imp_CreateThread",
+            ],
+            &[
+                "std::thread::AccessError",
+                "std::thread::LocalKey",
+                "std::thread::Thread",
+                "std::thread::ThreadId",
+                "std::thread::available_parallelism",
+                "std::thread::current",
+                "std::thread::functions::available_parallelism",
+                "std::thread::functions::panicking",
+                "std::thread::functions::park",
+                "std::thread::functions::park_timeout",
+                "std::thread::functions::sleep",
+                "std::thread::functions::sleep_ms",
+                "std::thread::functions::sleep_until",
+                "std::thread::functions::yield_now",
+                "std::thread::id",
+                "std::thread::local",
+                "std::thread::panicking",
+                "std::thread::park",
+                "std::thread::park_timeout",
+                "std::thread::sleep",
+                "std::thread::sleep_ms",
+                "std::thread::sleep_until",
+                "std::thread::thread",
+                "std::thread::yield_now",
+            ],
+        ),
+    );
+    // Reading the wall clock or a monotonic clock. Arithmetic on durations and times that have
+    // already been read doesn't access a clock, so isn't included.
+    result.insert(
+        ApiName::from("time"),
+        perm(
+            &[
+                "std::time::Instant::now",
+                "std::time::Instant::elapsed",
+                "std::time::SystemTime::now",
+                "std::time::SystemTime::elapsed",
+                "chrono::offset::local::Local::now",
+                "chrono::offset::utc::Utc::now",
+                "time::offset_date_time::OffsetDateTime::now_local",
+                "time::offset_date_time::OffsetDateTime::now_utc",
+                "clock_gettime",
+                "clock_gettime64",
+                "gettimeofday",
+                "GetSystemTimeAsFileTime",
+                "GetSystemTimePreciseAsFileTime",
+                "QueryPerformanceCounter",
+                "wasi_snapshot_preview1::clock_time_get",
+            ],
+            &[],
+        ),
+    );
+    // Sources of entropy. Opening `/dev/urandom` or `/dev/random` directly is detected via the
+    // string literal naming the device.
+    result.insert(
+        ApiName::from("random"),
+        perm(
+            &[
+                "std::collections::hash_map::RandomState",
+                "std::hash::random::RandomState",
+                "getrandom",
+                "getentropy",
+                "arc4random",
+                "arc4random_buf",
+                "BCryptGenRandom",
+                "ProcessPrng",
+                "SecRandomCopyBytes",
+                "rand::random",
+                "rand::rngs::os::OsRng",
+                "rand::rngs::thread::thread_rng",
+                "rand_core::os::OsRng",
+                "wasi_snapshot_preview1::random_get",
+                "__path::dev::random",
+                "__path::dev::urandom",
+            ],
+            &[],
+        ),
+    );
+    // Loading code at runtime bypasses everything that we can see statically. These are mostly raw
+    // symbol names, since crates like `libc` just declare them, so references go directly to the
+    // unmangled symbol.
+    result.insert(
+        ApiName::from("dynamic-loading"),
+        perm(
+            &[
+                "dlopen",
+                "dlmopen",
+                "dlsym",
+                "dlvsym",
+                "_dlopen",
+                "_dlsym",
+                "LoadLibraryA",
+                "LoadLibraryW",
+                "LoadLibraryExA",
+                "LoadLibraryExW",
+                "GetProcAddress",
+                "__imp_LoadLibraryA",
+                "__imp_LoadLibraryW",
+                "__imp_LoadLibraryExA",
+                "__imp_LoadLibraryExW",
+                "__imp_GetProcAddress",
+                "libloading",
+            ],
+            &[],
+        ),
     );
     result
 }
@@ -56,6 +234,90 @@ fn perm(include: &[&str], exclude: &[&str]) -> ApiConfig {
     ApiConfig {
         include: include.iter().map(|s| ApiPath::from_str(s)).collect(),
         exclude: exclude.iter().map(|s| ApiPath::from_str(s)).collect(),
-        no_auto_detect: Vec::new(),
+        ..ApiConfig::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checker::testing::apis_for_path;
+    use crate::config::testing::parse;
+
+    #[test]
+    fn classify_built_ins() {
+        let config = parse(r#"import_std = ["thread", "time", "random"]"#).unwrap();
+        let cases: &[(&str, &[&str])] = &[
+            ("std::thread::spawn", &["thread"]),
+            ("std::thread::Builder::spawn", &["thread"]),
+            ("std::thread::sleep", &[]),
+            ("std::thread::functions::sleep", &[]),
+            ("std::time::Instant::now", &["time"]),
+            ("std::time::SystemTime::now", &["time"]),
+            ("std::time::Duration::from_secs", &[]),
+            ("std::collections::hash_map::RandomState::new", &["random"]),
+            ("rand::rngs::os::OsRng", &["random"]),
+            ("rand::rngs::std::StdRng::seed_from_u64", &[]),
+            ("__path::dev::urandom", &["random"]),
+            ("__path::dev::random", &["random"]),
+            ("__path::dev::null", &[]),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(apis_for_path(config.clone(), path), *expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn thread_api() {
+        let config = parse(r#"import_std = ["thread"]"#).unwrap();
+        let cases: &[(&str, &[&str])] = &[
+            ("std::thread::spawn", &["thread"]),
+            ("std::thread::scope", &["thread"]),
+            ("std::thread::Builder::spawn_scoped", &["thread"]),
+            ("std::thread::functions::spawn", &["thread"]),
+            ("pthread_create", &["thread"]),
+            ("thrd_create", &["thread"]),
+            ("CreateThread", &["thread"]),
+            ("_beginthreadex", &["thread"]),
+            ("std::thread::current", &[]),
+            ("std::thread::Thread::unpark", &[]),
+            ("std::thread::ThreadId::as_u64", &[]),
+            ("std::thread::local::LocalKey::with", &[]),
+            ("std::thread::available_parallelism", &[]),
+            ("std::thread::functions::yield_now", &[]),
+            ("std::thread::park_timeout", &[]),
+            ("pthread_self", &[]),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(apis_for_path(config.clone(), path), *expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn time_api() {
+        let config = parse(r#"import_std = ["time"]"#).unwrap();
+        let cases: &[(&str, &[&str])] = &[
+            ("std::time::Instant::now", &["time"]),
+            ("std::time::Instant::elapsed", &["time"]),
+            ("std::time::SystemTime::now", &["time"]),
+            ("std::time::SystemTime::elapsed", &["time"]),
+            ("chrono::offset::utc::Utc::now", &["time"]),
+            ("chrono::offset::local::Local::now", &["time"]),
+            ("time::offset_date_time::OffsetDateTime::now_utc", &["time"]),
+            ("clock_gettime", &["time"]),
+            ("gettimeofday", &["time"]),
+            ("QueryPerformanceCounter", &["time"]),
+            ("wasi_snapshot_preview1::clock_time_get", &["time"]),
+            ("std::time::Instant::duration_since", &[]),
+            ("std::time::SystemTime::duration_since", &[]),
+            ("std::time::Duration::from_millis", &[]),
+            ("chrono::naive::date::NaiveDate::from_ymd_opt", &[]),
+            (
+                "time::offset_date_time::OffsetDateTime::from_unix_timestamp",
+                &[],
+            ),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(apis_for_path(config.clone(), path), *expected, "{path}");
+        }
     }
 }
@@ -0,0 +1,383 @@
+//! Reads the audits recorded by cargo-vet, so that packages that have been audited against
+//! particular criteria can be granted the APIs configured for those criteria in the `cargo_vet`
+//! section of cackle.toml.
+
+use super::ApiName;
+use super::PackageName;
+use super::RawConfig;
+use crate::crate_index::CrateIndex;
+use anyhow::Context;
+use anyhow::Result;
+use cargo_metadata::semver::Version;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Where cargo-vet keeps its files, relative to the workspace root, unless configured otherwise.
+const DEFAULT_STORE: &str = "supply-chain";
+
+/// Criteria that cargo-vet defines without them needing to be declared in `audits.toml`, together
+/// with the criteria that they imply.
+const BUILT_IN_CRITERIA: &[(&str, &[&str])] =
+    &[("safe-to-deploy", &["safe-to-run"]), ("safe-to-run", &[])];
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CargoVetConfig {
+    /// The directory containing `audits.toml`, relative to the workspace root.
+    #[serde(default)]
+    pub(crate) store: Option<PathBuf>,
+
+    /// APIs to grant to packages that have been audited, keyed by cargo-vet criteria.
+    #[serde(default)]
+    pub(crate) grant: BTreeMap<String, Vec<ApiName>>,
+
+    /// The APIs granted to each package as a result of `grant`. Populated by `load`.
+    #[serde(skip)]
+    pub(crate) granted: BTreeMap<PackageName, Vec<ApiName>>,
+}
+
+#[derive(Deserialize, Default)]
+struct AuditsFile {
+    #[serde(default)]
+    criteria: BTreeMap<String, CriteriaEntry>,
+    #[serde(default)]
+    audits: BTreeMap<String, Vec<AuditEntry>>,
+}
+
+/// The audits that other organisations publish and that cargo-vet has imported.
+#[derive(Deserialize, Default)]
+struct ImportsLock {
+    #[serde(default)]
+    audits: BTreeMap<String, AuditsFile>,
+}
+
+#[derive(Deserialize, Default)]
+struct CriteriaEntry {
+    #[serde(default)]
+    implies: StringOrVec,
+}
+
+/// An audit of a package. Entries with neither `version` nor `delta`, such as violations, don't
+/// certify anything.
+#[derive(Deserialize)]
+struct AuditEntry {
+    #[serde(default)]
+    criteria: StringOrVec,
+    version: Option<String>,
+    /// An audit of the changes between two versions, written as `1.0.0 -> 1.1.0`.
+    delta: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+enum StringOrVec {
+    #[default]
+    None,
+    String(String),
+    Vec(Vec<String>),
+}
+
+/// All audits that we know of, together with the criteria that each criteria implies.
+#[derive(Default)]
+struct Audits {
+    implies: BTreeMap<String, Vec<String>>,
+    audits: BTreeMap<String, Vec<AuditEntry>>,
+}
+
+/// Reads the cargo-vet audits, then for each package that has been audited against criteria listed
+/// in `cargo_vet.grant`, records the APIs that should be granted to it.
+pub(crate) fn load(
+    config: &mut RawConfig,
+    crate_index: &CrateIndex,
+    workspace_root: Option<&Path>,
+) -> Result<()> {
+    if config.cargo_vet.grant.is_empty() {
+        return Ok(());
+    }
+    let store = config
+        .cargo_vet
+        .store
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_STORE));
+    let store = match workspace_root {
+        Some(root) => root.join(store),
+        None => store,
+    };
+    let audits = Audits::read(&store)?;
+    // A selector may refer to several versions of a package, in which case we only grant what all
+    // of them have been audited for.
+    let mut granted: BTreeMap<PackageName, BTreeSet<ApiName>> = BTreeMap::new();
+    for pkg_id in crate_index.package_ids() {
+        if crate_index.is_workspace_member(pkg_id) {
+            continue;
+        }
+        let apis: BTreeSet<ApiName> = config
+            .cargo_vet
+            .grant
+            .iter()
+            .filter(|(criteria, _)| {
+                audits.is_audited(pkg_id.name_str(), pkg_id.version(), criteria)
+            })
+            .flat_map(|(_, apis)| apis.iter().cloned())
+            .collect();
+        granted
+            .entry(PackageName(pkg_id.perm_name()))
+            .and_modify(|existing| existing.retain(|api| apis.contains(api)))
+            .or_insert(apis);
+    }
+    config.cargo_vet.granted = granted
+        .into_iter()
+        .filter(|(_, apis)| !apis.is_empty())
+        .map(|(pkg_name, apis)| (pkg_name, apis.into_iter().collect()))
+        .collect();
+    Ok(())
+}
+
+impl Audits {
+    /// Reads `audits.toml` and, if present, `imports.lock` from `store`.
+    fn read(store: &Path) -> Result<Self> {
+        let mut audits = Audits::with_built_in_criteria();
+        audits.add(parse_file::<AuditsFile>(&store.join("audits.toml"))?);
+        let imports_path = store.join("imports.lock");
+        if imports_path.exists() {
+            for imported in parse_file::<ImportsLock>(&imports_path)?
+                .audits
+                .into_values()
+            {
+                audits.add(imported);
+            }
+        }
+        Ok(audits)
+    }
+
+    fn with_built_in_criteria() -> Self {
+        let mut audits = Audits::default();
+        for (name, implies) in BUILT_IN_CRITERIA {
+            audits.implies.insert(
+                name.to_string(),
+                implies.iter().map(|c| c.to_string()).collect(),
+            );
+        }
+        audits
+    }
+
+    fn add(&mut self, file: AuditsFile) {
+        for (name, entry) in file.criteria {
+            self.implies
+                .entry(name)
+                .or_default()
+                .extend(entry.implies.into_vec());
+        }
+        for (pkg_name, entries) in file.audits {
+            self.audits.entry(pkg_name).or_default().extend(entries);
+        }
+    }
+
+    /// Returns whether `version` of `pkg_name` has been audited against `criteria`, either with a
+    /// full audit or via a chain of delta audits from a fully audited version.
+    fn is_audited(&self, pkg_name: &str, version: &Version, criteria: &str) -> bool {
+        let Some(entries) = self.audits.get(pkg_name) else {
+            return false;
+        };
+        let entries: Vec<&AuditEntry> = entries
+            .iter()
+            .filter(|entry| self.certifies(entry, criteria))
+            .collect();
+        let mut audited: BTreeSet<Version> = entries
+            .iter()
+            .filter_map(|entry| parse_version(entry.version.as_deref()?))
+            .collect();
+        let deltas: Vec<(Version, Version)> = entries
+            .iter()
+            .filter_map(|entry| {
+                let (from, to) = entry.delta.as_deref()?.split_once("->")?;
+                Some((parse_version(from)?, parse_version(to)?))
+            })
+            .collect();
+        loop {
+            let reachable: Vec<Version> = deltas
+                .iter()
+                .filter(|(from, to)| audited.contains(from) && !audited.contains(to))
+                .map(|(_, to)| to.clone())
+                .collect();
+            if reachable.is_empty() {
+                break;
+            }
+            audited.extend(reachable);
+        }
+        audited.contains(version)
+    }
+
+    /// Returns whether `entry` certifies `criteria`, either directly or via criteria that imply it.
+    fn certifies(&self, entry: &AuditEntry, criteria: &str) -> bool {
+        let mut pending: Vec<&str> = entry.criteria.names();
+        let mut seen = BTreeSet::new();
+        while let Some(name) = pending.pop() {
+            if name == criteria {
+                return true;
+            }
+            if seen.insert(name) {
+                pending.extend(
+                    self.implies
+                        .get(name)
+                        .into_iter()
+                        .flatten()
+                        .map(String::as_str),
+                );
+            }
+        }
+        false
+    }
+}
+
+impl StringOrVec {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            StringOrVec::None => Vec::new(),
+            StringOrVec::String(value) => vec![value],
+            StringOrVec::Vec(values) => values,
+        }
+    }
+
+    fn names(&self) -> Vec<&str> {
+        match self {
+            StringOrVec::None => Vec::new(),
+            StringOrVec::String(value) => vec![value.as_str()],
+            StringOrVec::Vec(values) => values.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+fn parse_version(version: &str) -> Option<Version> {
+    Version::parse(version.trim()).ok()
+}
+
+fn parse_file<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents = crate::fs::read_to_string(path)?;
+    toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Audits;
+    use super::AuditsFile;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::Permissions;
+    use crate::config::ApiName;
+    use crate::config::PackageName;
+    use crate::crate_index::testing::index_with_package_names;
+    use cargo_metadata::semver::Version;
+    use std::collections::BTreeMap;
+
+    fn audits(toml: &str) -> Audits {
+        let mut audits = Audits::with_built_in_criteria();
+        audits.add(toml::from_str::<AuditsFile>(toml).unwrap());
+        audits
+    }
+
+    #[test]
+    fn full_and_delta_audits() {
+        let audits = audits(
+            r#"
+            [criteria.crypto-reviewed]
+            description = "Cryptography has been reviewed"
+            implies = "safe-to-deploy"
+
+            [[audits.crab1]]
+            who = "Someone <someone@example.com>"
+            criteria = "safe-to-deploy"
+            version = "1.0.0"
+
+            [[audits.crab1]]
+            who = "Someone <someone@example.com>"
+            criteria = "safe-to-deploy"
+            delta = "1.0.0 -> 1.1.0"
+
+            [[audits.crab1]]
+            who = "Someone <someone@example.com>"
+            criteria = "safe-to-run"
+            delta = "1.1.0 -> 1.2.0"
+
+            [[audits.crab2]]
+            who = "Someone <someone@example.com>"
+            criteria = ["crypto-reviewed"]
+            version = "0.3.1"
+            "#,
+        );
+        let audited = |name: &str, version: &str, criteria: &str| {
+            audits.is_audited(name, &Version::parse(version).unwrap(), criteria)
+        };
+        assert!(audited("crab1", "1.0.0", "safe-to-deploy"));
+        assert!(audited("crab1", "1.1.0", "safe-to-deploy"));
+        assert!(audited("crab1", "1.1.0", "safe-to-run"));
+        assert!(!audited("crab1", "1.2.0", "safe-to-deploy"));
+        assert!(audited("crab1", "1.2.0", "safe-to-run"));
+        assert!(!audited("crab1", "2.0.0", "safe-to-run"));
+        assert!(audited("crab2", "0.3.1", "safe-to-run"));
+        assert!(!audited("crab2", "0.3.1", "other"));
+        assert!(!audited("crab3", "1.0.0", "safe-to-run"));
+    }
+
+    #[test]
+    fn grant_apis_to_audited_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join("supply-chain");
+        std::fs::create_dir(&store).unwrap();
+        std::fs::write(
+            store.join("audits.toml"),
+            indoc::indoc! {r#"
+                [[audits.crab1]]
+                criteria = "safe-to-deploy"
+                version = "0.0.0"
+            "#},
+        )
+        .unwrap();
+        std::fs::write(
+            store.join("imports.lock"),
+            indoc::indoc! {r#"
+                [[audits.peer.audits.crab2]]
+                criteria = "safe-to-run"
+                version = "0.0.0"
+            "#},
+        )
+        .unwrap();
+        let mut config = crate::config::parse_raw(indoc::indoc! {r#"
+            [common]
+            version = 1
+
+            [api.fs]
+            include = ["std::fs"]
+
+            [api.net]
+            include = ["std::net"]
+
+            [cargo_vet.grant]
+            safe-to-deploy = ["fs"]
+            safe-to-run = ["net"]
+        "#})
+        .unwrap();
+        let crate_index = index_with_package_names(&["crab1", "crab2", "crab3"]);
+        super::load(&mut config, &crate_index, Some(dir.path())).unwrap();
+        let api = |name: &'static str| ApiName::from(name);
+        assert_eq!(
+            config.cargo_vet.granted,
+            BTreeMap::from([
+                (PackageName::from("crab1"), vec![api("fs"), api("net")]),
+                (PackageName::from("crab2"), vec![api("net")]),
+            ])
+        );
+
+        // Granted APIs are permitted, but aren't part of the config that's checked for being
+        // unused.
+        let permissions = Permissions::from_config_with_inheritance(&config, &crate_index);
+        let crab1 = permissions.get(&PermSel::for_primary("crab1")).unwrap();
+        assert_eq!(crab1.allow_apis, vec![api("fs"), api("net")]);
+        assert!(Permissions::from_config(&config)
+            .get(&PermSel::for_primary("crab1"))
+            .is_none());
+    }
+}
@@ -0,0 +1,220 @@
+//! Parsing and evaluation of the `cfg(...)` expressions used to make package permissions
+//! conditional on which of the package's cargo features are enabled. e.g.
+//! `[pkg.foo.'cfg(feature = "tls")']`. Only `feature` predicates are supported, optionally combined
+//! with `all`, `any` and `not`.
+
+use anyhow::bail;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct CfgExpr {
+    /// The expression as written by the user. Retained so that we can write it back out unchanged.
+    source: String,
+    predicate: Predicate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Predicate {
+    Feature(String),
+    All(Vec<Predicate>),
+    Any(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl CfgExpr {
+    /// Returns whether the expression holds given a function that reports whether a feature is
+    /// enabled.
+    pub(crate) fn eval(&self, feature_enabled: &dyn Fn(&str) -> bool) -> bool {
+        self.predicate.eval(feature_enabled)
+    }
+}
+
+impl Predicate {
+    fn eval(&self, feature_enabled: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Predicate::Feature(feature) => feature_enabled(feature),
+            Predicate::All(predicates) => predicates.iter().all(|p| p.eval(feature_enabled)),
+            Predicate::Any(predicates) => predicates.iter().any(|p| p.eval(feature_enabled)),
+            Predicate::Not(predicate) => !predicate.eval(feature_enabled),
+        }
+    }
+}
+
+impl TryFrom<String> for CfgExpr {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenise(&source)?,
+            pos: 0,
+        };
+        if parser.next() != Some(Token::Ident("cfg")) {
+            bail!("Unknown key `{source}`. Conditional permissions should look like `cfg(feature = \"x\")`");
+        }
+        parser.expect(Token::OpenParen)?;
+        let predicate = parser.predicate()?;
+        parser.expect(Token::CloseParen)?;
+        if parser.next().is_some() {
+            bail!("Unexpected trailing input in `{source}`");
+        }
+        Ok(CfgExpr { source, predicate })
+    }
+}
+
+impl From<CfgExpr> for String {
+    fn from(value: CfgExpr) -> Self {
+        value.source
+    }
+}
+
+impl Display for CfgExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Str(&'a str),
+    OpenParen,
+    CloseParen,
+    Comma,
+    Equals,
+}
+
+fn tokenise(input: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(ch) = rest.chars().next() {
+        let (token, len) = match ch {
+            '(' => (Token::OpenParen, 1),
+            ')' => (Token::CloseParen, 1),
+            ',' => (Token::Comma, 1),
+            '=' => (Token::Equals, 1),
+            '"' => {
+                let Some(end) = rest[1..].find('"') else {
+                    bail!("Unterminated string in `{input}`");
+                };
+                (Token::Str(&rest[1..end + 1]), end + 2)
+            }
+            ch if ch.is_alphanumeric() || ch == '_' => {
+                let len = rest
+                    .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                    .unwrap_or(rest.len());
+                (Token::Ident(&rest[..len]), len)
+            }
+            other => bail!("Unexpected character `{other}` in `{input}`"),
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let actual = self.next();
+        if actual != Some(expected) {
+            bail!("Expected {expected:?}, found {actual:?}");
+        }
+        Ok(())
+    }
+
+    fn predicate(&mut self) -> Result<Predicate> {
+        match self.next() {
+            Some(Token::Ident("feature")) => {
+                self.expect(Token::Equals)?;
+                match self.next() {
+                    Some(Token::Str(feature)) => Ok(Predicate::Feature(feature.to_owned())),
+                    other => bail!("Expected a feature name in quotes, found {other:?}"),
+                }
+            }
+            Some(Token::Ident(op @ ("all" | "any" | "not"))) => {
+                self.expect(Token::OpenParen)?;
+                let mut predicates = Vec::new();
+                while self.peek() != Some(Token::CloseParen) {
+                    predicates.push(self.predicate()?);
+                    if self.peek() == Some(Token::Comma) {
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                self.expect(Token::CloseParen)?;
+                match op {
+                    "all" => Ok(Predicate::All(predicates)),
+                    "any" => Ok(Predicate::Any(predicates)),
+                    _ => {
+                        let [predicate] = <[Predicate; 1]>::try_from(predicates)
+                            .map_err(|_| anyhow::anyhow!("`not` takes exactly one predicate"))?;
+                        Ok(Predicate::Not(Box::new(predicate)))
+                    }
+                }
+            }
+            Some(Token::Ident(other)) => {
+                bail!("Unsupported cfg predicate `{other}`. Only `feature` is supported")
+            }
+            other => bail!("Expected a cfg predicate, found {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CfgExpr;
+
+    #[track_caller]
+    fn check(expr: &str, enabled: &[&str], expected: bool) {
+        let expr = CfgExpr::try_from(expr.to_owned()).unwrap();
+        assert_eq!(expr.eval(&|feature| enabled.contains(&feature)), expected);
+    }
+
+    #[test]
+    fn eval_cfg() {
+        check(r#"cfg(feature = "tls")"#, &["tls"], true);
+        check(r#"cfg(feature = "tls")"#, &["net"], false);
+        check(
+            r#"cfg(all(feature = "a", feature = "b"))"#,
+            &["a", "b"],
+            true,
+        );
+        check(r#"cfg(all(feature = "a", feature = "b"))"#, &["a"], false);
+        check(r#"cfg(any(feature = "a", feature = "b",))"#, &["b"], true);
+        check(r#"cfg(not(feature = "a"))"#, &[], true);
+        check(r#"cfg(not(feature = "a"))"#, &["a"], false);
+    }
+
+    #[test]
+    fn invalid_cfg() {
+        for expr in [
+            "no_such_field",
+            r#"cfg(target_os = "linux")"#,
+            r#"cfg(feature = tls)"#,
+            r#"cfg(feature = "tls""#,
+            r#"cfg(not(feature = "a", feature = "b"))"#,
+            r#"cfg(feature = "a") x"#,
+        ] {
+            assert!(CfgExpr::try_from(expr.to_owned()).is_err(), "{expr}");
+        }
+    }
+}
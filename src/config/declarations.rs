@@ -0,0 +1,212 @@
+//! Reads the permissions that packages declare that they need, in a `cackle-manifest.toml` shipped
+//! in the package. Declarations can be compared with what packages are observed to use and, for
+//! packages that are trusted in the `declarations` section of cackle.toml, granted.
+
+use super::ApiName;
+use super::PackageName;
+use super::RawConfig;
+use crate::crate_index::CrateIndex;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The name of the file, in the root of a package, in which it declares its permissions.
+pub(crate) const MANIFEST_FILENAME: &str = "cackle-manifest.toml";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DeclarationsConfig {
+    /// Packages whose declared permissions should be granted.
+    #[serde(default)]
+    pub(crate) trust: Vec<PackageName>,
+
+    /// Whether to grant all packages the permissions that they declare.
+    #[serde(default)]
+    pub(crate) trust_all: bool,
+
+    /// What each package declares. Populated by `load`.
+    #[serde(skip)]
+    pub(crate) declared: BTreeMap<PackageName, Declaration>,
+}
+
+/// The contents of a package's `cackle-manifest.toml`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Declaration {
+    #[serde(default)]
+    pub(crate) allow_apis: Vec<ApiName>,
+
+    #[serde(default)]
+    pub(crate) allow_unsafe: bool,
+
+    /// What the package's build script needs.
+    #[serde(default)]
+    pub(crate) build: DeclaredBuild,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DeclaredBuild {
+    #[serde(default)]
+    pub(crate) allow_apis: Vec<ApiName>,
+
+    #[serde(default)]
+    pub(crate) allow_unsafe: bool,
+}
+
+impl DeclarationsConfig {
+    /// Returns the declarations of the packages whose declared permissions should be granted.
+    pub(crate) fn trusted(&self) -> impl Iterator<Item = (&PackageName, &Declaration)> {
+        self.declared.iter().filter(|(pkg_name, _)| {
+            self.trust_all
+                || self.trust.iter().any(|trusted| {
+                    trusted == *pkg_name
+                        || (trusted.version().is_none() && trusted.name() == pkg_name.name())
+                })
+        })
+    }
+}
+
+impl Declaration {
+    /// Reduces this declaration to what it has in common with `other`.
+    fn intersect(&mut self, other: &Declaration) {
+        self.allow_apis.retain(|api| other.allow_apis.contains(api));
+        self.allow_unsafe &= other.allow_unsafe;
+        self.build
+            .allow_apis
+            .retain(|api| other.build.allow_apis.contains(api));
+        self.build.allow_unsafe &= other.build.allow_unsafe;
+    }
+}
+
+/// Reads the declarations of each package that isn't part of the workspace. A declaration that
+/// can't be parsed is ignored, since it's outside the control of whoever is running us.
+pub(crate) fn load(config: &mut RawConfig, crate_index: &CrateIndex) {
+    let mut declared: BTreeMap<PackageName, Declaration> = BTreeMap::new();
+    for pkg_id in crate_index.package_ids() {
+        if crate_index.is_workspace_member(pkg_id) {
+            continue;
+        }
+        let Some(path) = crate_index
+            .pkg_dir(pkg_id)
+            .map(|dir| dir.join(MANIFEST_FILENAME))
+        else {
+            continue;
+        };
+        if !path.exists() {
+            continue;
+        }
+        let declaration = match crate::fs::read_to_string(&path)
+            .and_then(|contents| Ok(toml::from_str::<Declaration>(&contents)?))
+        {
+            Ok(declaration) => declaration,
+            Err(error) => {
+                log::warn!("Ignoring `{}`: {error:#}", path.display());
+                continue;
+            }
+        };
+        // A selector may refer to several versions of a package, in which case we only use what
+        // all of them declare.
+        declared
+            .entry(PackageName(pkg_id.perm_name()))
+            .and_modify(|existing| existing.intersect(&declaration))
+            .or_insert(declaration);
+    }
+    config.declarations.declared = declared;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::Permissions;
+    use crate::config::ApiName;
+    use crate::config::PackageName;
+    use crate::config::RawConfig;
+    use crate::crate_index::testing::index_with_package_dirs;
+    use crate::crate_index::CrateIndex;
+    use std::sync::Arc;
+
+    fn load(config: &str, declarations: &[(&str, &str)]) -> (RawConfig, Arc<CrateIndex>) {
+        let dir = tempfile::tempdir().unwrap();
+        let names: Vec<&str> = declarations.iter().map(|(name, _)| *name).collect();
+        let crate_index = index_with_package_dirs(&names, dir.path());
+        for (name, contents) in declarations {
+            std::fs::write(
+                dir.path().join(name).join(super::MANIFEST_FILENAME),
+                contents,
+            )
+            .unwrap();
+        }
+        let mut config = crate::config::parse_raw(config).unwrap();
+        super::load(&mut config, &crate_index);
+        (config, crate_index)
+    }
+
+    #[test]
+    fn trusted_declarations() {
+        let (mut config, _) = load(
+            indoc::indoc! {r#"
+                [common]
+                version = 1
+
+                [declarations]
+                trust = ["crab2"]
+            "#},
+            &[
+                (
+                    "crab1",
+                    "allow_apis = [\"fs\"]\n[build]\nallow_apis = [\"process\"]\n",
+                ),
+                ("crab2", "allow_apis = [\"net\"]\nallow_unsafe = true\n"),
+                ("crab3", "this isn't valid"),
+            ],
+        );
+        let declared: Vec<&str> = config
+            .declarations
+            .declared
+            .keys()
+            .map(|name| name.name())
+            .collect();
+        assert_eq!(declared, ["crab1", "crab2"]);
+        let trusted: Vec<&PackageName> = config
+            .declarations
+            .trusted()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(trusted, [&PackageName::from("crab2")]);
+
+        config.declarations.trust_all = true;
+        assert_eq!(config.declarations.trusted().count(), 2);
+    }
+
+    #[test]
+    fn grant_trusted_declarations() {
+        let (config, crate_index) = load(
+            indoc::indoc! {r#"
+                [common]
+                version = 1
+
+                [api.fs]
+                [api.net]
+
+                [declarations]
+                trust_all = true
+            "#},
+            &[(
+                "crab1",
+                "allow_apis = [\"net\", \"undefined\"]\nallow_unsafe = true\n\
+                [build]\nallow_apis = [\"fs\"]\n",
+            )],
+        );
+        let permissions = Permissions::from_config_with_inheritance(&config, &crate_index);
+        let crab1 = permissions
+            .get(&PermSel::for_primary(PackageName::from("crab1").0))
+            .unwrap();
+        assert_eq!(crab1.allow_apis, [ApiName::from("net")]);
+        assert!(crab1.allow_unsafe);
+        let build = permissions
+            .get(&PermSel::for_build_script("crab1"))
+            .unwrap();
+        assert!(build.allow_apis.contains(&ApiName::from("fs")));
+    }
+}
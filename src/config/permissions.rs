@@ -6,13 +6,20 @@ use crate::crate_index::CrateIndex;
 use crate::crate_index::CrateKind;
 use crate::crate_index::CrateSel;
 use crate::crate_index::PackageId;
+use crate::link_info::Platform;
+use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Result;
 use fxhash::FxHashMap;
 use serde::Deserialize;
 use serde::Serialize;
 use std::fmt::Display;
+use std::str::FromStr;
 use std::sync::Arc;
 
+/// The API that, when `map_permissions` is set, results in the sandbox permitting network access.
+const NET_API: &str = "net";
+
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct Permissions {
@@ -40,6 +47,12 @@ pub(crate) enum PermissionScope {
     FromBuild,
     /// Permission is granted to the package, but only when used via tests of other packages.
     FromTest,
+    /// Permission is granted to the package, but only when used via proc macros of other
+    /// packages. Like build scripts, proc macros are built for and run on the host.
+    FromHost,
+    /// Permission is granted to the package, but only when used via binaries that can link
+    /// dev-dependencies. i.e. tests, benchmarks and examples.
+    Dev,
 }
 
 impl Permissions {
@@ -65,6 +78,15 @@ impl Permissions {
                     *sub_cfg,
                 );
             }
+            if let Some(sub_cfg) = pkg_config.dev.take() {
+                packages.insert(
+                    PermSel {
+                        package_name: name.clone(),
+                        scope: PermissionScope::Dev,
+                    },
+                    *sub_cfg,
+                );
+            }
             if let Some(mut dep) = pkg_config.from.take() {
                 if let Some(sub_cfg) = dep.build.take() {
                     packages.insert(
@@ -84,6 +106,15 @@ impl Permissions {
                         *sub_cfg,
                     );
                 }
+                if let Some(sub_cfg) = dep.host.take() {
+                    packages.insert(
+                        PermSel {
+                            package_name: name.clone(),
+                            scope: PermissionScope::FromHost,
+                        },
+                        *sub_cfg,
+                    );
+                }
             }
             packages.insert(
                 PermSel {
@@ -104,15 +135,74 @@ impl Permissions {
         for sel in &crate_index.permission_selectors {
             new.packages.entry(sel.clone()).or_default();
         }
-        apply_inheritance(&mut new.packages, config);
+        // APIs granted because of cargo-vet audits only get added here, so that they're never
+        // reported as unused.
+        for (pkg_name, apis) in &config.cargo_vet.granted {
+            let pkg_config = new
+                .packages
+                .entry(PermSel {
+                    package_name: pkg_name.clone(),
+                    scope: PermissionScope::All,
+                })
+                .or_default();
+            for api in apis {
+                if !pkg_config.allow_apis.contains(api) {
+                    pkg_config.allow_apis.push(api.clone());
+                }
+            }
+        }
+        // Likewise for what trusted packages declare that they need. Declarations come from the
+        // packages themselves, so may name APIs that we don't define, which we skip.
+        for (pkg_name, declaration) in config.declarations.trusted() {
+            for (scope, apis, allow_unsafe) in [
+                (
+                    PermissionScope::All,
+                    &declaration.allow_apis,
+                    declaration.allow_unsafe,
+                ),
+                (
+                    PermissionScope::Build,
+                    &declaration.build.allow_apis,
+                    declaration.build.allow_unsafe,
+                ),
+            ] {
+                if apis.is_empty() && !allow_unsafe {
+                    continue;
+                }
+                let pkg_config = new
+                    .packages
+                    .entry(PermSel {
+                        package_name: pkg_name.clone(),
+                        scope,
+                    })
+                    .or_default();
+                for api in apis {
+                    if config.apis.contains_key(api) && !pkg_config.allow_apis.contains(api) {
+                        pkg_config.allow_apis.push(api.clone());
+                    }
+                }
+                pkg_config.allow_unsafe |= allow_unsafe;
+            }
+        }
+        for (perm_sel, pkg_config) in &mut new.packages {
+            pkg_config.apply_conditional(&perm_sel.package_name, crate_index);
+        }
+        apply_inheritance(&mut new.packages, config, crate_index);
         new
     }
 
     pub(crate) fn sandbox_config_for_package(&self, perm_sel: &PermSel) -> SandboxConfig {
-        self.packages
-            .get(perm_sel)
-            .map(|c| c.sandbox.clone())
-            .unwrap_or_default()
+        let Some(pkg_config) = self.packages.get(perm_sel) else {
+            return SandboxConfig::default();
+        };
+        let mut sandbox = pkg_config.sandbox.clone();
+        if sandbox.map_permissions.unwrap_or(false)
+            && sandbox.allow_network.is_none()
+            && pkg_config.allow_apis.iter().any(|api| *api == NET_API)
+        {
+            sandbox.allow_network = Some(true);
+        }
+        sandbox
     }
 
     pub(crate) fn unsafe_permitted_for_crate(&self, crate_sel: &CrateSel) -> bool {
@@ -121,12 +211,25 @@ impl Permissions {
             .is_some_and(|crate_config| crate_config.allow_unsafe)
     }
 
+    /// Returns globs matching source files in which unsafe code is permitted for the specified
+    /// crate. Not relevant if `unsafe_permitted_for_crate` returns true.
+    pub(crate) fn unsafe_permitted_paths(&self, crate_sel: &CrateSel) -> &[String] {
+        self.packages
+            .get(&PermSel::for_non_build_output(crate_sel))
+            .map(|crate_config| crate_config.allow_unsafe_in.as_slice())
+            .unwrap_or_default()
+    }
+
     pub(crate) fn get(&self, perm_sel: &PermSel) -> Option<&PackageConfig> {
         self.packages.get(perm_sel)
     }
 }
 
-fn apply_inheritance(packages: &mut FxHashMap<PermSel, PackageConfig>, config: &RawConfig) {
+fn apply_inheritance(
+    packages: &mut FxHashMap<PermSel, PackageConfig>,
+    config: &RawConfig,
+    crate_index: &CrateIndex,
+) {
     // Determine a global config. We may eventually make this an actual thing in our configuration
     // file.
     let global_config = PackageConfig {
@@ -134,9 +237,29 @@ fn apply_inheritance(packages: &mut FxHashMap<PermSel, PackageConfig>, config: &
         ..Default::default()
     };
 
+    // Config for a package without a version applies to all versions of that package and config
+    // written against a name that the package was renamed to applies to the package.
+    let equivalents: Vec<(PermSel, PackageConfig)> = packages
+        .keys()
+        .flat_map(|perm_sel| {
+            perm_sel
+                .equivalents(crate_index)
+                .into_iter()
+                .filter_map(|equivalent| {
+                    Some((perm_sel.clone(), packages.get(&equivalent)?.clone()))
+                })
+        })
+        .collect();
+    for (perm_sel, equivalent_config) in equivalents {
+        if let Some(config) = packages.get_mut(&perm_sel) {
+            config.inherit(&equivalent_config);
+        }
+    }
+
     // Separate out the configs into a map per layer. Note, we move everything out of `packages`,
     // then put them back later.
     let mut all = FxHashMap::default();
+    let mut dev = FxHashMap::default();
     let mut dep = FxHashMap::default();
     let mut local = FxHashMap::default();
     for (perm_sel, config) in std::mem::take(packages) {
@@ -146,6 +269,8 @@ fn apply_inheritance(packages: &mut FxHashMap<PermSel, PackageConfig>, config: &
             PermissionScope::Test => local.insert(perm_sel, config),
             PermissionScope::FromBuild => dep.insert(perm_sel, config),
             PermissionScope::FromTest => dep.insert(perm_sel, config),
+            PermissionScope::FromHost => dep.insert(perm_sel, config),
+            PermissionScope::Dev => dev.insert(perm_sel, config),
         };
     }
 
@@ -153,11 +278,26 @@ fn apply_inheritance(packages: &mut FxHashMap<PermSel, PackageConfig>, config: &
     for config in all.values_mut() {
         config.inherit(&global_config);
     }
-    for (perm_sel, config) in dep.iter_mut() {
+    for (perm_sel, config) in dev.iter_mut() {
         if let Some(parent) = all.get(&perm_sel.clone_with_scope(PermissionScope::All)) {
             config.inherit(parent);
         }
     }
+    for (perm_sel, config) in dep.iter_mut() {
+        let parent = match perm_sel.scope {
+            PermissionScope::FromBuild | PermissionScope::FromHost => {
+                all.get(&perm_sel.clone_with_scope(PermissionScope::All))
+            }
+            // If there's no dev layer for this package, then it would have been the same as `All`.
+            PermissionScope::FromTest => dev
+                .get(&perm_sel.clone_with_scope(PermissionScope::Dev))
+                .or_else(|| all.get(&perm_sel.clone_with_scope(PermissionScope::All))),
+            _ => unreachable!(),
+        };
+        if let Some(parent) = parent {
+            config.inherit(parent);
+        }
+    }
     for (perm_sel, config) in local.iter_mut() {
         let parent_scope = match perm_sel.scope {
             PermissionScope::Build => PermissionScope::FromBuild,
@@ -171,19 +311,42 @@ fn apply_inheritance(packages: &mut FxHashMap<PermSel, PackageConfig>, config: &
 
     // Recombine the layers back into the original map.
     packages.extend(all);
+    packages.extend(dev);
     packages.extend(dep);
     packages.extend(local);
 }
 
 impl PackageConfig {
+    /// Merges in any conditional permissions whose conditions are satisfied by the features that
+    /// cargo resolved for the package. Conditional permissions are removed either way.
+    fn apply_conditional(&mut self, pkg_name: &PackageName, crate_index: &CrateIndex) {
+        for (cfg, conditional) in std::mem::take(&mut self.conditional) {
+            if cfg.eval(&|feature| crate_index.is_feature_enabled(pkg_name, feature)) {
+                self.inherit(&conditional);
+            }
+        }
+    }
+
     fn inherit(&mut self, other: &PackageConfig) {
         merge_string_vec(&mut self.allow_apis, &other.allow_apis);
+        merge_string_vec(&mut self.deny_apis, &other.deny_apis);
+        merge_string_vec(&mut self.allow_fn, &other.allow_fn);
         merge_string_vec(
             &mut self.allow_build_instructions,
             &other.allow_build_instructions,
         );
+        merge_string_vec(&mut self.allow_link_libs, &other.allow_link_libs);
+        merge_string_vec(&mut self.allow_rustc_env, &other.allow_rustc_env);
+        if let Some(other_cfgs) = &other.allow_rustc_cfg {
+            merge_string_vec(
+                self.allow_rustc_cfg.get_or_insert_with(Vec::new),
+                other_cfgs,
+            );
+        }
         self.allow_proc_macro |= other.allow_proc_macro;
+        self.allow_ffi_export |= other.allow_ffi_export;
         self.allow_unsafe |= other.allow_unsafe;
+        merge_string_vec(&mut self.allow_unsafe_in, &other.allow_unsafe_in);
         self.sandbox.inherit(&other.sandbox);
     }
 }
@@ -196,9 +359,22 @@ impl SandboxConfig {
         merge_string_vec(&mut self.extra_args, &other.extra_args);
         merge_string_vec(&mut self.bind_writable, &other.bind_writable);
         merge_string_vec(&mut self.make_writable, &other.make_writable);
+        merge_string_vec(&mut self.allow_domains, &other.allow_domains);
+        merge_string_vec(&mut self.deny_env, &other.deny_env);
+        for (var, value) in &other.set_env {
+            self.set_env
+                .entry(var.clone())
+                .or_insert_with(|| value.clone());
+        }
         if self.allow_network.is_none() {
             self.allow_network = other.allow_network;
         }
+        self.map_permissions = self.map_permissions.or(other.map_permissions);
+        self.capture_writes = self.capture_writes.or(other.capture_writes);
+        self.max_cpu_seconds = self.max_cpu_seconds.or(other.max_cpu_seconds);
+        self.max_wall_seconds = self.max_wall_seconds.or(other.max_wall_seconds);
+        self.max_memory_mb = self.max_memory_mb.or(other.max_memory_mb);
+        self.max_processes = self.max_processes.or(other.max_processes);
     }
 }
 
@@ -211,7 +387,7 @@ fn merge_string_vec<T: Ord + Clone>(add_to: &mut Vec<T>, add: &[T]) {
 impl PermSel {
     pub(crate) fn with_scope(use_package: &PackageId, scope: PermissionScope) -> Self {
         Self {
-            package_name: PackageName(use_package.pkg_name()),
+            package_name: PackageName(use_package.perm_name()),
             scope,
         }
     }
@@ -225,6 +401,7 @@ impl PermSel {
             CrateKind::Primary => PermissionScope::All,
             CrateKind::BuildScript => PermissionScope::Build,
             CrateKind::Test => PermissionScope::Test,
+            CrateKind::Example => PermissionScope::Dev,
         };
         Self::with_scope(&crate_sel.pkg_id, scope)
     }
@@ -250,6 +427,31 @@ impl PermSel {
         }
     }
 
+    /// Returns selectors that aren't ancestors of this one, but whose permissions also apply to it.
+    /// That's the selector for all versions of the package and selectors that use names that
+    /// dependents gave the package by renaming it in their Cargo.toml.
+    pub(crate) fn equivalents(&self, crate_index: &CrateIndex) -> Vec<PermSel> {
+        let mut equivalents: Vec<PermSel> = self.without_version().into_iter().collect();
+        equivalents.extend(
+            crate_index
+                .renamed_as(&self.package_name)
+                .map(|rename| PermSel {
+                    package_name: PackageName(rename.clone()),
+                    scope: self.scope,
+                }),
+        );
+        equivalents
+    }
+
+    /// Returns the equivalent selector for all versions of the package, or None if this selector
+    /// doesn't specify a version.
+    pub(crate) fn without_version(&self) -> Option<PermSel> {
+        Some(PermSel {
+            package_name: self.package_name.without_version()?,
+            scope: self.scope,
+        })
+    }
+
     pub(crate) fn parent(&self) -> Option<PermSel> {
         Some(self.clone_with_scope(self.scope.parent_scope()?))
     }
@@ -262,15 +464,8 @@ impl PermSel {
             .iter()
             .map(|s| self.clone_with_scope(*s))
             .collect();
-        let mut next_level: Vec<PermSel> = scopes
-            .iter()
-            .flat_map(|sel| {
-                sel.scope
-                    .child_scopes()
-                    .iter()
-                    .map(|s| self.clone_with_scope(*s))
-            })
-            .collect();
+        let mut next_level: Vec<PermSel> =
+            scopes.iter().flat_map(|sel| sel.descendants()).collect();
         scopes.append(&mut next_level);
         scopes
     }
@@ -295,21 +490,31 @@ impl PermissionScope {
             Self::Test => Some("test"),
             Self::FromBuild => Some("from.build"),
             Self::FromTest => Some("from.test"),
+            Self::FromHost => Some("from.host"),
+            Self::Dev => Some("dev"),
         }
     }
 
-    pub(crate) fn determine(use_pkg: &PackageId, bin_selector: &CrateSel) -> PermissionScope {
+    pub(crate) fn determine(
+        use_pkg: &PackageId,
+        bin_selector: &CrateSel,
+        platform: Platform,
+    ) -> PermissionScope {
         if use_pkg == &bin_selector.pkg_id {
             match bin_selector.kind {
                 CrateKind::Primary => PermissionScope::All,
                 CrateKind::BuildScript => PermissionScope::Build,
                 CrateKind::Test => PermissionScope::Test,
+                CrateKind::Example => PermissionScope::Dev,
             }
         } else {
             match bin_selector.kind {
+                // A primary crate built for the host is a proc macro.
+                CrateKind::Primary if platform == Platform::Host => PermissionScope::FromHost,
                 CrateKind::Primary => PermissionScope::All,
                 CrateKind::BuildScript => PermissionScope::FromBuild,
                 CrateKind::Test => PermissionScope::FromTest,
+                CrateKind::Example => PermissionScope::Dev,
             }
         }
     }
@@ -320,21 +525,93 @@ impl PermissionScope {
             PermissionScope::Build => Some(PermissionScope::FromBuild),
             PermissionScope::Test => Some(PermissionScope::FromTest),
             PermissionScope::FromBuild => Some(PermissionScope::All),
-            PermissionScope::FromTest => Some(PermissionScope::All),
+            PermissionScope::FromTest => Some(PermissionScope::Dev),
+            PermissionScope::FromHost => Some(PermissionScope::All),
+            PermissionScope::Dev => Some(PermissionScope::All),
         }
     }
 
     fn child_scopes(self) -> &'static [PermissionScope] {
         match self {
-            PermissionScope::All => &[PermissionScope::FromBuild, PermissionScope::FromTest],
+            PermissionScope::All => &[
+                PermissionScope::FromBuild,
+                PermissionScope::FromHost,
+                PermissionScope::Dev,
+            ],
             PermissionScope::Build => &[],
             PermissionScope::Test => &[],
             PermissionScope::FromBuild => &[PermissionScope::Build],
             PermissionScope::FromTest => &[PermissionScope::Test],
+            PermissionScope::FromHost => &[],
+            PermissionScope::Dev => &[PermissionScope::FromTest],
         }
     }
 }
 
+/// Parses a selector written the same way as in the config file, minus the leading `pkg.`. e.g.
+/// `foo`, `foo.build`, `foo.from.test` or `foo@0.8.build`.
+impl FromStr for PermSel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (package_name, selector) = match s.split_once('@') {
+            Some((name, rest)) => {
+                // The version is made up of digits and dots, so the selector starts at the first
+                // character that's neither.
+                let version_end = rest
+                    .find(|c: char| !c.is_ascii_digit() && c != '.')
+                    .unwrap_or(rest.len());
+                let version = rest[..version_end].trim_end_matches('.');
+                if !is_valid_version(version) {
+                    bail!("Invalid version `{version}` in `{s}`");
+                }
+                (&s[..name.len() + 1 + version.len()], &rest[version_end..])
+            }
+            None => s.split_once('.').unwrap_or((s, "")),
+        };
+        if package_name.is_empty() || package_name.starts_with('@') {
+            bail!("Missing package name in `{s}`");
+        }
+        let scope = if selector.is_empty() {
+            PermissionScope::All
+        } else {
+            [
+                PermissionScope::Build,
+                PermissionScope::Test,
+                PermissionScope::FromBuild,
+                PermissionScope::FromTest,
+                PermissionScope::FromHost,
+                PermissionScope::Dev,
+            ]
+            .into_iter()
+            .find(|scope| scope.config_selector() == Some(selector))
+            .ok_or_else(|| anyhow!("Unknown selector `{selector}` in `{s}`"))?
+        };
+        Ok(PermSel {
+            package_name: PackageName(package_name.into()),
+            scope,
+        })
+    }
+}
+
+/// Returns whether `version` is the semver-compatible part of a version, as used in package names
+/// like `rand@0.8`. e.g. `1`, `0.8` or `0.0.3`.
+pub(crate) fn is_valid_version(version: &str) -> bool {
+    let parts: Vec<&str> = version.split('.').collect();
+    if parts
+        .iter()
+        .any(|part| part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return false;
+    }
+    match parts.as_slice() {
+        [major] => *major != "0",
+        ["0", minor] => *minor != "0",
+        ["0", "0", _] => true,
+        _ => false,
+    }
+}
+
 /// A manual implementation of Serialize for PermSel so that we can use it as keys in a hashmap that
 /// gets serialised.
 impl Serialize for PermSel {
@@ -426,3 +703,219 @@ fn test_inheritance() {
     assert!(bar1_test_config.allow_unsafe);
     assert_eq!(bar1_test_config.allow_apis, &["fs", "process"])
 }
+
+#[test]
+fn test_dev_inheritance() {
+    let bar1 = PermSel::for_primary("bar1");
+    let mut crate_index = CrateIndex::default();
+    for scope in [
+        PermissionScope::FromBuild,
+        PermissionScope::FromTest,
+        PermissionScope::Dev,
+        PermissionScope::Test,
+    ] {
+        crate_index
+            .permission_selectors
+            .insert(bar1.clone_with_scope(scope));
+    }
+
+    let raw = super::parse_raw(
+        r#"
+        [common]
+        version = 1
+        import_std = ["fs", "process"]
+
+        [pkg.bar1]
+        allow_apis = [
+            "fs",
+        ]
+
+        [pkg.bar1.dev]
+        allow_apis = [
+            "process",
+        ]
+    "#,
+    )
+    .unwrap();
+    let config = crate::config::Config::from_raw(raw, &crate_index).unwrap();
+
+    let allowed = |scope| {
+        config
+            .permissions
+            .get(&bar1.clone_with_scope(scope))
+            .unwrap()
+            .allow_apis
+            .clone()
+    };
+    assert_eq!(allowed(PermissionScope::All), &["fs"]);
+    assert_eq!(allowed(PermissionScope::FromBuild), &["fs"]);
+    assert_eq!(allowed(PermissionScope::Dev), &["fs", "process"]);
+    assert_eq!(allowed(PermissionScope::FromTest), &["fs", "process"]);
+    assert_eq!(allowed(PermissionScope::Test), &["fs", "process"]);
+}
+
+#[test]
+fn test_versioned_inheritance() {
+    let bar1 = PermSel::for_primary("bar1@0.8");
+    let mut crate_index = CrateIndex::default();
+    for perm_sel in [PermSel::for_primary("bar1"), bar1.clone()] {
+        crate_index.permission_selectors.insert(perm_sel.clone());
+        crate_index
+            .permission_selectors
+            .insert(perm_sel.clone_with_scope(PermissionScope::FromBuild));
+        crate_index
+            .permission_selectors
+            .insert(perm_sel.clone_with_scope(PermissionScope::Build));
+    }
+
+    let raw = super::parse_raw(
+        r#"
+        [common]
+        version = 1
+        import_std = ["fs", "net", "process"]
+
+        [pkg.bar1]
+        allow_apis = [
+            "fs",
+        ]
+        build.allow_apis = [
+            "process",
+        ]
+
+        [pkg.'bar1@0.8']
+        allow_apis = [
+            "net",
+        ]
+    "#,
+    )
+    .unwrap();
+    let config = crate::config::Config::from_raw(raw, &crate_index).unwrap();
+
+    let allowed = |perm_sel: &PermSel| config.permissions.get(perm_sel).unwrap().allow_apis.clone();
+    assert_eq!(allowed(&PermSel::for_primary("bar1")), &["fs"]);
+    assert_eq!(allowed(&bar1), &["fs", "net"]);
+    assert_eq!(
+        allowed(&bar1.clone_with_scope(PermissionScope::Build)),
+        &["fs", "net", "process"]
+    );
+    assert_eq!(bar1.without_version(), Some(PermSel::for_primary("bar1")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PermSel;
+    use super::PermissionScope;
+
+    #[test]
+    fn parse_perm_sel() {
+        for (input, scope) in [
+            ("foo", PermissionScope::All),
+            ("foo.build", PermissionScope::Build),
+            ("foo.test", PermissionScope::Test),
+            ("foo.from.build", PermissionScope::FromBuild),
+            ("foo.from.test", PermissionScope::FromTest),
+            ("foo.from.host", PermissionScope::FromHost),
+            ("foo.dev", PermissionScope::Dev),
+        ] {
+            let perm_sel: PermSel = input.parse().unwrap();
+            assert_eq!(perm_sel.scope, scope);
+            assert_eq!(perm_sel.package_name.as_ref(), "foo");
+            assert_eq!(perm_sel.to_string(), input);
+        }
+        assert!("foo.bar".parse::<PermSel>().is_err());
+        assert!(".build".parse::<PermSel>().is_err());
+
+        for (input, name, scope) in [
+            ("foo@1", "foo@1", PermissionScope::All),
+            ("foo@0.8.build", "foo@0.8", PermissionScope::Build),
+            (
+                "foo@0.0.3.from.test",
+                "foo@0.0.3",
+                PermissionScope::FromTest,
+            ),
+        ] {
+            let perm_sel: PermSel = input.parse().unwrap();
+            assert_eq!(perm_sel.scope, scope);
+            assert_eq!(perm_sel.package_name.as_ref(), name);
+            assert_eq!(perm_sel.to_string(), input);
+        }
+        assert!("foo@0.8.5".parse::<PermSel>().is_err());
+        assert!("foo@.build".parse::<PermSel>().is_err());
+        assert!("@1".parse::<PermSel>().is_err());
+    }
+
+    #[test]
+    fn determine_host_scope() {
+        use crate::crate_index::testing::pkg_id;
+        use crate::crate_index::CrateSel;
+        use crate::link_info::Platform;
+
+        let foo = pkg_id("foo");
+        let proc_macro = CrateSel::primary(pkg_id("macro"));
+        assert_eq!(
+            PermissionScope::determine(&foo, &proc_macro, Platform::Host),
+            PermissionScope::FromHost
+        );
+        assert_eq!(
+            PermissionScope::determine(&foo, &proc_macro, Platform::Target),
+            PermissionScope::All
+        );
+        // A proc macro's own code isn't restricted to host permissions.
+        assert_eq!(
+            PermissionScope::determine(&pkg_id("macro"), &proc_macro, Platform::Host),
+            PermissionScope::All
+        );
+        assert_eq!(
+            PermSel::for_primary("foo")
+                .clone_with_scope(PermissionScope::FromHost)
+                .parent(),
+            Some(PermSel::for_primary("foo"))
+        );
+    }
+}
+
+#[test]
+fn test_conditional_permissions() {
+    let crate_index =
+        crate::crate_index::testing::index_with_features(&[("foo", &["tls"]), ("bar", &[])]);
+    let raw = super::parse_raw(
+        r#"
+        [common]
+        version = 1
+        import_std = ["fs", "net"]
+
+        [pkg.foo.'cfg(feature = "tls")']
+        allow_apis = [
+            "net",
+        ]
+
+        [pkg.foo.'cfg(feature = "compression")']
+        allow_apis = [
+            "fs",
+        ]
+
+        [pkg.bar]
+        allow_apis = [
+            "fs",
+        ]
+
+        [pkg.bar.'cfg(feature = "tls")']
+        allow_apis = [
+            "net",
+        ]
+    "#,
+    )
+    .unwrap();
+    let config = crate::config::Config::from_raw(raw, &crate_index).unwrap();
+
+    let allowed = |pkg| {
+        config
+            .permissions
+            .get(&PermSel::for_primary(pkg))
+            .unwrap()
+            .allow_apis
+            .clone()
+    };
+    assert_eq!(allowed("foo"), &["net"]);
+    assert_eq!(allowed("bar"), &["fs"]);
+}
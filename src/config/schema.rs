@@ -0,0 +1,200 @@
+//! Versioning of the config file format. This is separate from `common.version`, which selects
+//! between different behaviours and which users opt into. The schema version on the other hand
+//! describes how the file is laid out. Older layouts are migrated automatically when Cackle starts,
+//! since there's no choice for the user to make.
+
+use crate::config_editor::ConfigEditor;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The schema version assumed for config files that don't specify `common.schema_version`. All
+/// config files written before the schema version was introduced use this layout.
+const IMPLICIT_SCHEMA_VERSION: i64 = 1;
+
+/// The schema version of config files that we write and that we understand.
+pub(crate) const CURRENT_SCHEMA_VERSION: i64 = IMPLICIT_SCHEMA_VERSION + MIGRATIONS.len() as i64;
+
+pub(crate) struct Migration {
+    /// A description of what the migration changes.
+    pub(crate) description: &'static str,
+
+    /// Transforms a config from the previous schema version to this one.
+    migrate_fn: fn(&mut ConfigEditor) -> Result<()>,
+}
+
+/// Migrations to each schema version after `IMPLICIT_SCHEMA_VERSION`. The first entry migrates to
+/// schema version 2, the second to 3 etc. To change the layout of the config file, add a migration
+/// here, then update the config structs to match the new layout.
+const MIGRATIONS: &[Migration] = &[];
+
+/// The outcome of a successful migration.
+#[derive(Debug)]
+pub(crate) struct Migrated {
+    pub(crate) from: i64,
+    pub(crate) to: i64,
+    pub(crate) backup_path: PathBuf,
+}
+
+/// Upgrades the config file at `path` to the current schema version if it's using an older version.
+/// The original file is preserved as a backup alongside the config. Returns `None` if no migration
+/// was necessary.
+pub(crate) fn migrate_file(path: &Path) -> Result<Option<Migrated>> {
+    let original = crate::fs::read_to_string(path)?;
+    let mut editor = ConfigEditor::from_toml_string(&original)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    let from = schema_version(&editor).with_context(|| format!("In {}", path.display()))?;
+    check_supported(from).with_context(|| format!("In {}", path.display()))?;
+    if !migrate(&mut editor, from, MIGRATIONS)? {
+        return Ok(None);
+    }
+    let backup_path = path.with_extension(format!("toml.schema-{from}.bak"));
+    crate::fs::write(&backup_path, &original)?;
+    editor.write(path)?;
+    Ok(Some(Migrated {
+        from,
+        to: CURRENT_SCHEMA_VERSION,
+        backup_path,
+    }))
+}
+
+/// Returns an error if `table`, a parsed config file, declares a schema version newer than what we
+/// understand.
+pub(crate) fn check_not_from_future(table: &toml::Table) -> Result<()> {
+    let version = table
+        .get("common")
+        .and_then(|common| common.get("schema_version"))
+        .and_then(|version| version.as_integer());
+    if let Some(version) = version {
+        check_supported(version)?;
+    }
+    Ok(())
+}
+
+fn check_supported(version: i64) -> Result<()> {
+    if version > CURRENT_SCHEMA_VERSION {
+        bail!(
+            "Config schema version {version} is newer than the latest supported version \
+             ({CURRENT_SCHEMA_VERSION}). Please upgrade cargo-acl"
+        );
+    }
+    if version < IMPLICIT_SCHEMA_VERSION {
+        bail!("Invalid config schema version {version}");
+    }
+    Ok(())
+}
+
+fn schema_version(editor: &ConfigEditor) -> Result<i64> {
+    let Some(version) = editor.common_value("schema_version") else {
+        return Ok(IMPLICIT_SCHEMA_VERSION);
+    };
+    version
+        .as_integer()
+        .ok_or_else(|| anyhow!("common.schema_version should be an integer"))
+}
+
+/// Applies whatever of `migrations` are needed to get from schema version `from` to the latest.
+/// Returns whether any were applied.
+fn migrate(editor: &mut ConfigEditor, from: i64, migrations: &[Migration]) -> Result<bool> {
+    let already_applied = (from - IMPLICIT_SCHEMA_VERSION) as usize;
+    let Some(pending) = migrations.get(already_applied..).filter(|m| !m.is_empty()) else {
+        return Ok(false);
+    };
+    for (index, migration) in pending.iter().enumerate() {
+        let to = from + 1 + index as i64;
+        (migration.migrate_fn)(editor).with_context(|| {
+            format!(
+                "Failed to migrate config to schema version {to} ({})",
+                migration.description
+            )
+        })?;
+    }
+    editor.set_schema_version(from + pending.len() as i64)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Migration;
+    use crate::config_editor::ConfigEditor;
+    use indoc::indoc;
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            description: "Rename common.old to common.new",
+            migrate_fn: |editor| {
+                let table = editor.table(["common"].into_iter())?;
+                if let Some(value) = table.remove("old") {
+                    table.insert("new", value);
+                }
+                Ok(())
+            },
+        },
+        Migration {
+            description: "Remove common.obsolete",
+            migrate_fn: |editor| {
+                editor.table(["common"].into_iter())?.remove("obsolete");
+                Ok(())
+            },
+        },
+    ];
+
+    #[test]
+    fn migrate_from_implicit_version() {
+        let mut editor = ConfigEditor::from_toml_string(indoc! {r#"
+            [common]
+            version = 2
+            old = true
+            obsolete = 1
+        "#})
+        .unwrap();
+        let from = super::schema_version(&editor).unwrap();
+        assert_eq!(from, 1);
+        assert!(super::migrate(&mut editor, from, TEST_MIGRATIONS).unwrap());
+        assert_eq!(
+            editor.to_toml(),
+            indoc! {r#"
+                [common]
+                version = 2
+                new = true
+                schema_version = 3
+            "#}
+        );
+
+        // Now that we're up-to-date, there's nothing more to do.
+        assert!(!super::migrate(&mut editor, 3, TEST_MIGRATIONS).unwrap());
+    }
+
+    #[test]
+    fn migrate_from_explicit_version() {
+        let mut editor = ConfigEditor::from_toml_string(indoc! {r#"
+            [common]
+            schema_version = 2
+            old = true
+            obsolete = 1
+        "#})
+        .unwrap();
+        let from = super::schema_version(&editor).unwrap();
+        assert_eq!(from, 2);
+        assert!(super::migrate(&mut editor, from, TEST_MIGRATIONS).unwrap());
+        assert_eq!(
+            editor.to_toml(),
+            indoc! {r#"
+                [common]
+                schema_version = 3
+                old = true
+            "#}
+        );
+    }
+
+    #[test]
+    fn future_version() {
+        let table: toml::Table = toml::from_str("[common]\nschema_version = 1000\n").unwrap();
+        assert!(super::check_not_from_future(&table).is_err());
+        let table: toml::Table = toml::from_str("[common]\nversion = 2\n").unwrap();
+        assert!(super::check_not_from_future(&table).is_ok());
+    }
+}
@@ -1,4 +1,7 @@
-//! This module is responsible for applying automatic edits to cackle.toml.
+//! This module is responsible for applying automatic edits to cackle.toml. Edits are made to the
+//! parsed document rather than reserialising the config, so that the user's comments, whitespace
+//! and key order are preserved. All code that writes cackle.toml, whether from the UI or the
+//! command line, should go via `ConfigEditor`.
 
 use crate::checker::common_prefix::common_to_prefixes;
 use crate::config::permissions::PermSel;
@@ -8,18 +11,22 @@ use crate::config::ApiPath;
 use crate::config::Config;
 use crate::config::PackageName;
 use crate::config::SandboxKind;
+use crate::crate_index::PackageId;
 use crate::problem::ApiUsages;
 use crate::problem::AvailableApi;
 use crate::problem::PossibleExportedApi;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
+use crate::problem::RustcDirectiveKind;
 use crate::problem::UnusedAllowApi;
+use crate::sandbox::ResourceLimit;
 use anyhow::anyhow;
 use anyhow::Result;
 use std::borrow::Borrow;
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::path::Path;
+use std::path::PathBuf;
 use toml_edit::Array;
 use toml_edit::Document;
 use toml_edit::Formatted;
@@ -91,23 +98,86 @@ pub(crate) fn fixes_for_problem(problem: &Problem, config: &Config) -> Vec<Box<d
         }
         Problem::IsProcMacro(pkg_id) => {
             edits.push(Box::new(AllowProcMacro {
-                perm_sel: PermSel::for_primary(pkg_id.pkg_name()),
+                perm_sel: PermSel::for_primary(pkg_id.perm_name()),
             }));
         }
-        Problem::ExecutionFailed(failure) => {
-            if failure.output.sandbox_config.kind != Some(SandboxKind::Disabled) {
-                let perm_sel = PermSel::for_build_script(failure.crate_sel.pkg_name());
-                if !failure.output.sandbox_config.allow_network.unwrap_or(false) {
-                    edits.push(Box::new(SandboxAllowNetwork {
-                        perm_sel: perm_sel.clone(),
-                    }));
-                }
-                edits.push(Box::new(DisableSandbox { perm_sel }));
+        Problem::VersionMismatch(info) => {
+            edits.push(Box::new(UpdatePinnedVersion {
+                pkg_id: info.pkg_id.clone(),
+            }));
+        }
+        Problem::AdvisoryWithSensitiveApis(info) => {
+            edits.push(Box::new(IgnoreAdvisories {
+                ids: info
+                    .advisories
+                    .iter()
+                    .map(|advisory| advisory.id.clone())
+                    .collect(),
+            }));
+        }
+        Problem::DisallowedFfiExport(export) => {
+            edits.push(Box::new(AllowFfiExport {
+                perm_sel: export.perm_sel(),
+            }));
+        }
+        Problem::ExecutionFailed(failure)
+            if failure.output.sandbox_config.kind != Some(SandboxKind::Disabled) =>
+        {
+            let perm_sel = PermSel::for_build_script(failure.crate_sel.pkg_id.perm_name());
+            for dir in failure.output.denied_write_dirs() {
+                edits.push(Box::new(SandboxBindWritable {
+                    perm_sel: perm_sel.clone(),
+                    dir: dir.to_owned(),
+                }));
+            }
+            for host in failure.output.denied_hosts() {
+                edits.push(Box::new(SandboxAllowDomain {
+                    perm_sel: perm_sel.clone(),
+                    domain: host.to_owned(),
+                }));
+            }
+            if !failure.output.sandbox_config.allow_network.unwrap_or(false) {
+                edits.push(Box::new(SandboxAllowNetwork {
+                    perm_sel: perm_sel.clone(),
+                }));
+            }
+            edits.push(Box::new(DisableSandbox { perm_sel }));
+        }
+        Problem::UnexpectedWrites(failure) => {
+            let perm_sel = PermSel::for_non_build_output(&failure.crate_sel);
+            for dir in failure.output.captured_write_dirs() {
+                edits.push(Box::new(SandboxBindWritable {
+                    perm_sel: perm_sel.clone(),
+                    dir: dir.to_owned(),
+                }));
             }
         }
+        Problem::ResourceLimitExceeded(failure) => {
+            let perm_sel = PermSel::for_non_build_output(&failure.crate_sel);
+            if let Some(limit) = failure.output.limit_exceeded {
+                edits.push(Box::new(SandboxIncreaseLimit {
+                    perm_sel: perm_sel.clone(),
+                    limit,
+                }));
+            }
+            edits.push(Box::new(DisableSandbox { perm_sel }));
+        }
         Problem::DisallowedBuildInstruction(failure) => {
             edits.append(&mut edits_for_build_instruction(failure));
         }
+        Problem::DisallowedLinkLib(failure) => {
+            edits.push(Box::new(AllowLinkLib {
+                perm_sel: PermSel::for_primary(failure.pkg_id.perm_name()),
+                lib: failure.lib.clone(),
+            }));
+        }
+        Problem::DisallowedRustcDirective(failure) => {
+            edits.push(Box::new(AllowRustcDirective {
+                perm_sel: PermSel::for_primary(failure.pkg_id.perm_name()),
+                kind: failure.kind,
+                name: failure.name.clone(),
+            }));
+        }
         Problem::DisallowedUnsafe(failure) => edits.push(Box::new(AllowUnsafe {
             perm_sel: PermSel::for_non_build_output(&failure.crate_sel),
         })),
@@ -117,6 +187,11 @@ pub(crate) fn fixes_for_problem(problem: &Problem, config: &Config) -> Vec<Box<d
         Problem::UnusedPackageConfig(crate_name) => edits.push(Box::new(RemoveUnusedPkgConfig {
             perm_sel: crate_name.clone(),
         })),
+        Problem::UnusedSandboxConfiguration(perm_sel) => {
+            edits.push(Box::new(RemoveUnusedSandboxConfig {
+                perm_sel: perm_sel.clone(),
+            }))
+        }
         Problem::PossibleExportedApi(info) => {
             edits.push(Box::new(ExtendApi {
                 api: info.api.clone(),
@@ -240,9 +315,20 @@ impl ConfigEditor {
         Ok(Some(table))
     }
 
+    pub(crate) fn set_schema_version(&mut self, version: i64) -> Result<()> {
+        let table = self.table(["common"].into_iter())?;
+        replace_item(table, "schema_version", toml_edit::value(version));
+        Ok(())
+    }
+
+    /// Returns the value of `key` in the common table, if present.
+    pub(crate) fn common_value(&self, key: &str) -> Option<&Item> {
+        self.document.get("common")?.get(key)
+    }
+
     pub(crate) fn set_version(&mut self, version: i64) -> Result<()> {
         let table = self.table(["common"].into_iter())?;
-        table.insert("version", toml_edit::value(version));
+        replace_item(table, "version", toml_edit::value(version));
         Ok(())
     }
 
@@ -261,9 +347,9 @@ impl ConfigEditor {
             .enumerate()
             .find(|(_, item)| item.as_str() == Some(api));
         if let Some((index, _)) = existing {
-            imports.remove(index);
+            remove_from_array(imports, index);
         } else {
-            imports.push_formatted(create_string(api.to_string(), None));
+            insert_into_array(imports, imports.len(), api.to_string(), None);
         }
         Ok(())
     }
@@ -273,9 +359,13 @@ impl ConfigEditor {
         let sandbox_kind = match sandbox_kind {
             SandboxKind::Disabled => "Disabled",
             SandboxKind::Bubblewrap => "Bubblewrap",
+            SandboxKind::Landlock => "Landlock",
+            SandboxKind::Namespaces => "Namespaces",
+            SandboxKind::Seatbelt => "Seatbelt",
+            SandboxKind::Auto => "Auto",
         };
-        self.table(["sandbox"].into_iter())?
-            .insert("kind", toml_edit::value(sandbox_kind));
+        let table = self.table(["sandbox"].into_iter())?;
+        replace_item(table, "kind", toml_edit::value(sandbox_kind));
         Ok(())
     }
 }
@@ -383,7 +473,7 @@ fn edits_for_build_instruction(
     let mut suffix = "";
     loop {
         out.push(Box::new(AllowBuildInstruction {
-            perm_sel: PermSel::for_build_script(failure.pkg_id.name_str()),
+            perm_sel: PermSel::for_build_script(failure.pkg_id.perm_name()),
             instruction: format!("{instruction}{suffix}"),
         }));
         suffix = "*";
@@ -494,7 +584,8 @@ impl Edit for SelectSandbox {
     fn help(&self) -> Cow<'static, str> {
         "Select what kind of sandbox you'd like to use. This is used when running build scripts \
          (build.rs), tests and when running rustc. To use Bubblewrap, it must be installed. On \
-         Debian-based systems you can `sudo apt install bubblewrap`"
+         Debian-based systems you can `sudo apt install bubblewrap`. Auto uses the strongest \
+         sandbox available each time cackle runs"
             .into()
     }
 
@@ -633,7 +724,7 @@ fn add_to_array<S: AsRef<str>>(
             // Value is already present in the array.
             continue;
         }
-        array.insert_formatted(index, create_string(value, comment));
+        insert_into_array(array, index, value, comment);
     }
     Ok(())
 }
@@ -796,6 +887,12 @@ impl Edit for AllowApiUsage {
             crate::config::permissions::PermissionScope::FromTest => {
                 format!("Allow `{pkg}` to use `{api}` API when building tests")
             }
+            crate::config::permissions::PermissionScope::FromHost => {
+                format!("Allow `{pkg}` to use `{api}` API when building proc macros")
+            }
+            crate::config::permissions::PermissionScope::Dev => {
+                format!("Allow `{pkg}` to use `{api}` API when building tests or examples")
+            }
         }
     }
 
@@ -818,25 +915,54 @@ impl Edit for AllowApiUsage {
             crate::config::permissions::PermissionScope::FromTest => {
                 format!("Allow `{pkg}` to use `{api}` API, but only from tests")
             }
+            crate::config::permissions::PermissionScope::FromHost => {
+                format!("Allow `{pkg}` to use `{api}` API, but only from proc macros")
+            }
+            crate::config::permissions::PermissionScope::Dev => {
+                format!(
+                    "Allow `{pkg}` to use `{api}` API, but only from tests, benchmarks and examples"
+                )
+            }
         }
         .into()
     }
 
     fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
-        let table = editor.pkg_table(&self.usage.perm_sel())?;
-        add_to_array(
-            table,
-            "allow_apis",
-            &[&self.usage.api_name],
-            opts.comment.as_deref(),
-        )?;
-        // Remove the API from any selectors that inherit from the one that we just added to, since
+        AllowApis {
+            perm_sel: self.usage.perm_sel(),
+            apis: vec![self.usage.api_name.clone()],
+        }
+        .apply(editor, opts)
+    }
+}
+
+/// Grants some APIs to a permission selector. Unlike `AllowApiUsage`, this isn't tied to a
+/// particular problem, so can be used for edits requested via the command line.
+pub(crate) struct AllowApis {
+    pub(crate) perm_sel: PermSel,
+    pub(crate) apis: Vec<ApiName>,
+}
+
+impl Edit for AllowApis {
+    fn title(&self) -> String {
+        let apis: Vec<String> = self.apis.iter().map(|api| format!("`{api}`")).collect();
+        format!("Allow `{}` to use {} API", self.perm_sel, apis.join(", "))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Add these APIs to the list of APIs that this package is allowed to use.".into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_table(&self.perm_sel)?;
+        add_to_array(table, "allow_apis", &self.apis, opts.comment.as_deref())?;
+        // Remove the APIs from any selectors that inherit from the one that we just added to, since
         // they're now redundant.
-        for perm_sel in self.usage.perm_sel().descendants() {
+        for perm_sel in self.perm_sel.descendants() {
             RemoveUnusedAllowApis {
                 unused: UnusedAllowApi {
                     perm_sel,
-                    apis: vec![self.usage.api_name.clone()],
+                    apis: self.apis.clone(),
                 },
             }
             .apply(editor, opts)?;
@@ -871,7 +997,7 @@ impl Edit for RemoveUnusedAllowApis {
                 .enumerate()
                 .find(|(_, allowed)| allowed.as_str() == Some(api.to_string().as_str()));
             if let Some((index, _)) = index_and_entry {
-                allow_apis.remove(index);
+                remove_from_array(allow_apis, index);
             }
         }
         if allow_apis.is_empty() {
@@ -904,6 +1030,29 @@ impl Edit for RemoveUnusedPkgConfig {
     }
 }
 
+struct RemoveUnusedSandboxConfig {
+    perm_sel: PermSel,
+}
+
+impl Edit for RemoveUnusedSandboxConfig {
+    fn title(&self) -> String {
+        "Remove unused sandbox configuration".to_owned()
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Remove the sandbox configuration for this package. Sandbox configuration only applies to \
+         build scripts and tests."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, _opts: &EditOpts) -> Result<()> {
+        if let Some(table) = editor.opt_pkg_table(&self.perm_sel)? {
+            table.remove("sandbox");
+        }
+        Ok(())
+    }
+}
+
 struct UpdateConfigVersion {
     version: Version,
 }
@@ -973,6 +1122,49 @@ fn get_or_create_array<'table>(
     Ok(array)
 }
 
+/// Inserts `value` into `array` at `index`, matching the existing layout of the array. Comments
+/// are only added if the array has one element per line, since otherwise the comment would end up
+/// swallowing the rest of the array.
+fn insert_into_array(array: &mut Array, index: usize, value: String, comment: Option<&str>) {
+    if is_multiline(array) {
+        array.insert_formatted(index, create_string(value, comment));
+        return;
+    }
+    let prefix = if index == 0 {
+        if let Some(first) = array.get_mut(0) {
+            first.decor_mut().set_prefix(" ");
+        }
+        ""
+    } else {
+        " "
+    };
+    array.insert_formatted(index, Value::from(value).decorated(prefix, ""));
+}
+
+/// Removes the element at `index` from `array`, keeping the layout of the remaining elements.
+fn remove_from_array(array: &mut Array, index: usize) {
+    let multiline = is_multiline(array);
+    array.remove(index);
+    if index == 0 && !multiline {
+        if let Some(first) = array.get_mut(0) {
+            first.decor_mut().set_prefix("");
+        }
+    }
+}
+
+/// Returns whether `array` has its elements on separate lines. Empty arrays are treated as
+/// multi-line, since that's how we lay out arrays that we create.
+fn is_multiline(array: &Array) -> bool {
+    array.is_empty()
+        || array.iter().any(|value| {
+            value
+                .decor()
+                .prefix()
+                .and_then(|prefix| prefix.as_str())
+                .is_some_and(|prefix| prefix.contains('\n'))
+        })
+}
+
 fn create_string(value: String, comment: Option<&str>) -> Value {
     let string = Value::String(Formatted::new(value));
     if let Some(comment) = comment {
@@ -1004,6 +1196,89 @@ impl Edit for AllowProcMacro {
     }
 }
 
+struct AllowFfiExport {
+    perm_sel: PermSel,
+}
+
+impl Edit for AllowFfiExport {
+    fn title(&self) -> String {
+        format!("Allow FFI exports from `{}`", self.perm_sel)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Allow this crate to export unmangled symbols. Such symbols can be called directly from C \
+         or from other languages, bypassing the crate's Rust API."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_table(&self.perm_sel)?;
+        set_table_value(table, "allow_ffi_export", toml_edit::value(true), opts);
+        Ok(())
+    }
+}
+
+struct IgnoreAdvisories {
+    ids: Vec<String>,
+}
+
+impl Edit for IgnoreAdvisories {
+    fn title(&self) -> String {
+        format!("Ignore advisories {}", self.ids.join(", "))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Stop reporting these advisories. Select this once you've reviewed them and are satisfied \
+         that the package's permissions are still appropriate, e.g. because the advisories don't \
+         affect how you use it."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.table(["advisories"].into_iter())?;
+        add_to_array(table, "ignore", &self.ids, opts.comment.as_deref())
+    }
+}
+
+struct UpdatePinnedVersion {
+    pkg_id: PackageId,
+}
+
+impl UpdatePinnedVersion {
+    /// Returns a version requirement that matches semver-compatible versions of our package.
+    fn version_req(&self) -> String {
+        let version = self.pkg_id.version();
+        if version.major == 0 {
+            format!("0.{}.*", version.minor)
+        } else {
+            format!("{}.*", version.major)
+        }
+    }
+}
+
+impl Edit for UpdatePinnedVersion {
+    fn title(&self) -> String {
+        format!(
+            "Pin `{}` to version `{}`",
+            self.pkg_id.pkg_name(),
+            self.version_req()
+        )
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Update the version requirement for this package to match the version that's now in use. \
+        Select this once you're satisfied that the permissions granted to the package are still \
+        appropriate for the new version."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_table(&PermSel::for_primary(self.pkg_id.pkg_name()))?;
+        set_table_value(table, "version", toml_edit::value(self.version_req()), opts);
+        Ok(())
+    }
+}
+
 struct AllowBuildInstruction {
     perm_sel: PermSel,
     instruction: String,
@@ -1035,6 +1310,73 @@ impl Edit for AllowBuildInstruction {
     }
 }
 
+struct AllowLinkLib {
+    perm_sel: PermSel,
+    lib: String,
+}
+
+impl Edit for AllowLinkLib {
+    fn title(&self) -> String {
+        format!(
+            "Allow `{}` to link native library `{}`",
+            self.perm_sel, self.lib
+        )
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Allow this crate's build.rs to ask cargo to link the specified native library. Code in \
+         native libraries isn't analysed, so this effectively grants the library whatever \
+         permissions it needs."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_table(&self.perm_sel)?;
+        add_to_array(
+            table,
+            "allow_link_libs",
+            &[&self.lib],
+            opts.comment.as_deref(),
+        )
+    }
+}
+
+struct AllowRustcDirective {
+    perm_sel: PermSel,
+    kind: RustcDirectiveKind,
+    name: String,
+}
+
+impl Edit for AllowRustcDirective {
+    fn title(&self) -> String {
+        match self.kind {
+            RustcDirectiveKind::Env => format!(
+                "Allow `{}` to set environment variable `{}`",
+                self.perm_sel, self.name
+            ),
+            RustcDirectiveKind::Cfg => {
+                format!("Allow `{}` to set cfg `{}`", self.perm_sel, self.name)
+            }
+        }
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Allow this crate's build.rs to set the specified environment variable or cfg when \
+         compiling the crate. These can change what code gets compiled, or values that it sees \
+         via `env!`."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_table(&self.perm_sel)?;
+        let key = match self.kind {
+            RustcDirectiveKind::Env => "allow_rustc_env",
+            RustcDirectiveKind::Cfg => "allow_rustc_cfg",
+        };
+        add_to_array(table, key, &[&self.name], opts.comment.as_deref())
+    }
+}
+
 struct DisableSandbox {
     perm_sel: PermSel,
 }
@@ -1058,8 +1400,8 @@ impl Edit for DisableSandbox {
     }
 }
 
-struct AllowUnsafe {
-    perm_sel: PermSel,
+pub(crate) struct AllowUnsafe {
+    pub(crate) perm_sel: PermSel,
 }
 
 impl Edit for AllowUnsafe {
@@ -1083,8 +1425,8 @@ impl Edit for AllowUnsafe {
     }
 }
 
-struct SandboxAllowNetwork {
-    perm_sel: PermSel,
+pub(crate) struct SandboxAllowNetwork {
+    pub(crate) perm_sel: PermSel,
 }
 
 impl Edit for SandboxAllowNetwork {
@@ -1105,6 +1447,132 @@ impl Edit for SandboxAllowNetwork {
     }
 }
 
+pub(crate) struct SandboxAllowDomain {
+    pub(crate) perm_sel: PermSel,
+    pub(crate) domain: String,
+}
+
+impl Edit for SandboxAllowDomain {
+    fn title(&self) -> String {
+        format!(
+            "Permit network access to `{}` from sandbox for `{}`",
+            self.domain, self.perm_sel
+        )
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Allow this crate's build script (build.rs) to access this domain via the sandbox's \
+         filtering proxy. Access to other domains remains blocked."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_sandbox_table(&self.perm_sel)?;
+        add_to_array(
+            table,
+            "allow_domains",
+            &[&self.domain],
+            opts.comment.as_deref(),
+        )
+    }
+}
+
+pub(crate) struct SandboxBindWritable {
+    pub(crate) perm_sel: PermSel,
+    pub(crate) dir: PathBuf,
+}
+
+impl Edit for SandboxBindWritable {
+    fn title(&self) -> String {
+        format!(
+            "Make `{}` writable from sandbox for `{}`",
+            self.dir.display(),
+            self.perm_sel
+        )
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Allow this crate's build script (build.rs) to write to this directory. The build script \
+         tried to write to something in this directory, but was denied by the sandbox."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_sandbox_table(&self.perm_sel)?;
+        add_to_array(
+            table,
+            "bind_writable",
+            &[self.dir.to_string_lossy()],
+            opts.comment.as_deref(),
+        )
+    }
+}
+
+pub(crate) struct SandboxIncreaseLimit {
+    pub(crate) perm_sel: PermSel,
+    pub(crate) limit: ResourceLimit,
+}
+
+impl SandboxIncreaseLimit {
+    /// Returns the config key for the limit and the doubled value that we'll set it to.
+    fn key_and_new_value(&self) -> (&'static str, u64) {
+        match self.limit {
+            ResourceLimit::CpuTime(seconds) => ("max_cpu_seconds", seconds.saturating_mul(2)),
+            ResourceLimit::WallTime(seconds) => ("max_wall_seconds", seconds.saturating_mul(2)),
+            ResourceLimit::Memory(mb) => ("max_memory_mb", mb.saturating_mul(2)),
+            ResourceLimit::Processes(count) => ("max_processes", count.saturating_mul(2)),
+        }
+    }
+}
+
+impl Edit for SandboxIncreaseLimit {
+    fn title(&self) -> String {
+        let (key, value) = self.key_and_new_value();
+        format!("Set `{key}` to {value} for `{}`", self.perm_sel)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Double the limit that this crate's build script (build.rs) or test exceeded. Some build \
+        scripts, e.g. those that compile large C libraries, legitimately need a lot of time or \
+        memory."
+            .into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let (key, value) = self.key_and_new_value();
+        let table = editor.pkg_sandbox_table(&self.perm_sel)?;
+        set_table_value(table, key, toml_edit::value(value as i64), opts);
+        Ok(())
+    }
+}
+
+/// Sets what kind of sandbox to use for a particular package's build script.
+pub(crate) struct SetPkgSandboxKind {
+    pub(crate) perm_sel: PermSel,
+    pub(crate) kind: SandboxKind,
+}
+
+impl Edit for SetPkgSandboxKind {
+    fn title(&self) -> String {
+        format!("Use sandbox `{:?}` for `{}`", self.kind, self.perm_sel)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        "Override the sandbox kind used when running this crate's build script (build.rs).".into()
+    }
+
+    fn apply(&self, editor: &mut ConfigEditor, opts: &EditOpts) -> Result<()> {
+        let table = editor.pkg_sandbox_table(&self.perm_sel)?;
+        set_table_value(
+            table,
+            "kind",
+            toml_edit::value(format!("{:?}", self.kind)),
+            opts,
+        );
+        Ok(())
+    }
+}
+
 impl Display for dyn Edit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.title())
@@ -1117,32 +1585,73 @@ fn set_table_value(
     item: toml_edit::Item,
     opts: &EditOpts,
 ) {
-    table[key] = item;
-    if let Some(comment) = &opts.comment {
+    replace_item(table, key, item);
+    // A comment before a key in a dotted table would end up between the dotted parts of the key,
+    // which isn't valid.
+    if let Some(comment) = opts.comment.as_ref().filter(|_| !table.is_dotted()) {
         if let Some(decor) = table.key_decor_mut(key) {
-            *decor = toml_edit::Decor::new(format!("# {comment}\n"), " ");
+            add_comment(decor, comment);
         }
     }
 }
 
+/// Sets `key` in `table` to `item`. If `key` is already present, then comments attached to the key
+/// and to its existing value are kept.
+fn replace_item(table: &mut toml_edit::Table, key: &str, mut item: Item) {
+    let Some(existing) = table.get_mut(key) else {
+        table.insert(key, item);
+        return;
+    };
+    if let (Some(existing_value), Some(value)) = (existing.as_value(), item.as_value_mut()) {
+        *value.decor_mut() = existing_value.decor().clone();
+    }
+    *existing = item;
+}
+
+/// Adds a comment line before a key, after any comments that are already there.
+fn add_comment(decor: &mut toml_edit::Decor, comment: &str) {
+    let line = format!("# {comment}\n");
+    let existing = decor
+        .prefix()
+        .and_then(|prefix| prefix.as_str())
+        .unwrap_or("");
+    if existing.contains(&line) {
+        return;
+    }
+    let suffix = decor
+        .suffix()
+        .and_then(|suffix| suffix.as_str())
+        .unwrap_or(" ")
+        .to_owned();
+    *decor = toml_edit::Decor::new(format!("{existing}{line}"), suffix);
+}
+
 #[cfg(test)]
 mod tests {
+    use super::AllowApis;
+    use super::AllowUnsafe;
     use super::ConfigEditor;
     use super::Edit;
+    use super::EditOpts;
     use super::InlineStdApi;
+    use super::SetPkgSandboxKind;
     use crate::config::permissions::PermSel;
     use crate::config::permissions::PermissionScope;
     use crate::config::ApiName;
     use crate::config::Config;
     use crate::config::SandboxConfig;
+    use crate::config::SandboxKind;
     use crate::config_editor::fixes_for_problem;
     use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::testing::pkg_id_with_version;
     use crate::crate_index::CrateSel;
     use crate::crate_index::PackageId;
     use crate::location::SourceLocation;
     use crate::problem::ApiUsages;
     use crate::problem::DisallowedBuildInstruction;
     use crate::problem::Problem;
+    use crate::problem::RustcDirectiveKind;
+    use crate::problem::Severity;
     use crate::proxy::rpc::BinExecutionOutput;
     use indoc::indoc;
     use std::path::Path;
@@ -1155,6 +1664,7 @@ mod tests {
             scope,
             api_name: ApiName::from(api),
             usages: Vec::new(),
+            severity: Severity::Error,
         })
     }
 
@@ -1189,6 +1699,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fix_missing_api_versioned() {
+        check(
+            indoc! {r#"
+                [pkg.crab1]
+                allow_unsafe = true
+            "#},
+            &disallowed_api(
+                pkg_id_with_version("crab1", "0.8.2"),
+                PermissionScope::All,
+                "net",
+            ),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                allow_unsafe = true
+
+                [pkg."crab1@0.8"]
+                allow_apis = [
+                    "net",
+                ]
+            "#,
+            },
+        );
+    }
+
     #[test]
     fn fix_missing_api_build_script() {
         check(
@@ -1317,6 +1853,176 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fix_allow_ffi_export() {
+        check(
+            "",
+            &Problem::DisallowedFfiExport(crate::problem::FfiExport {
+                pkg_id: pkg_id("crab1"),
+                scope: PermissionScope::All,
+                symbols: vec!["crab1_init".to_owned()],
+            }),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                allow_ffi_export = true
+            "#,
+            },
+        );
+        check(
+            "",
+            &Problem::DisallowedFfiExport(crate::problem::FfiExport {
+                pkg_id: pkg_id("crab1"),
+                scope: PermissionScope::Build,
+                symbols: vec!["crab1_init".to_owned()],
+            }),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                build.allow_ffi_export = true
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn fix_link_lib() {
+        check(
+            "",
+            &Problem::DisallowedLinkLib(crate::problem::DisallowedLinkLib {
+                pkg_id: pkg_id("crab1"),
+                lib: "ssl".to_owned(),
+                instruction: "cargo:rustc-link-lib=dylib=ssl".to_owned(),
+            }),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                allow_link_libs = [
+                    "ssl",
+                ]
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn fix_rustc_directive() {
+        check(
+            "",
+            &Problem::DisallowedRustcDirective(crate::problem::DisallowedRustcDirective {
+                pkg_id: pkg_id("crab1"),
+                kind: RustcDirectiveKind::Env,
+                name: "GIT_HASH".to_owned(),
+                instruction: "cargo:rustc-env=GIT_HASH=abc123".to_owned(),
+            }),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                allow_rustc_env = [
+                    "GIT_HASH",
+                ]
+            "#,
+            },
+        );
+        check(
+            indoc! {r#"
+                [pkg.crab1]
+                allow_rustc_cfg = [
+                    "has_bar",
+                ]
+            "#},
+            &Problem::DisallowedRustcDirective(crate::problem::DisallowedRustcDirective {
+                pkg_id: pkg_id("crab1"),
+                kind: RustcDirectiveKind::Cfg,
+                name: "has_foo".to_owned(),
+                instruction: "cargo:rustc-cfg=has_foo".to_owned(),
+            }),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                allow_rustc_cfg = [
+                    "has_bar",
+                    "has_foo",
+                ]
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn fix_version_mismatch() {
+        check(
+            indoc! {r#"
+                [pkg.crab1]
+                version = "1.*"
+                allow_unsafe = true
+            "#},
+            &Problem::VersionMismatch(crate::problem::VersionMismatch {
+                pkg_id: pkg_id("crab1"),
+                version_req: "1.*".parse().unwrap(),
+            }),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                version = "0.0.*"
+                allow_unsafe = true
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn fix_advisory() {
+        check(
+            indoc! {r#"
+                [api.net]
+
+                [pkg.crab1]
+                allow_apis = ["net"]
+            "#},
+            &Problem::AdvisoryWithSensitiveApis(crate::problem::AdvisoryWithSensitiveApis {
+                pkg_id: pkg_id("crab1"),
+                advisories: vec![crate::config::advisories::Advisory {
+                    id: "RUSTSEC-2024-0001".to_owned(),
+                    title: "Something bad".to_owned(),
+                    informational: None,
+                }],
+                apis: vec![ApiName::from("net")],
+            }),
+            0,
+            indoc! {r#"
+                [api.net]
+
+                [pkg.crab1]
+                allow_apis = ["net"]
+
+                [advisories]
+                ignore = [
+                    "RUSTSEC-2024-0001",
+                ]
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn fix_unused_sandbox_config() {
+        check(
+            indoc! {r#"
+                [pkg.crab1]
+                allow_unsafe = true
+                sandbox.kind = "Disabled"
+            "#},
+            &Problem::UnusedSandboxConfiguration(PermSel::for_primary("crab1")),
+            0,
+            indoc! {r#"
+                [pkg.crab1]
+                allow_unsafe = true
+            "#,
+            },
+        );
+    }
+
     #[test]
     fn fix_allow_unsafe() {
         check(
@@ -1335,21 +2041,326 @@ mod tests {
     }
 
     #[test]
-    fn build_script_failed() {
+    fn command_line_edits() {
+        let mut editor = ConfigEditor::from_toml_string(indoc! {r#"
+            [pkg.crab1]
+            test.allow_apis = [
+                "fs",
+                "net",
+            ]
+        "#})
+        .unwrap();
+        let edits: Vec<Box<dyn Edit>> = vec![
+            Box::new(AllowApis {
+                perm_sel: "crab1".parse().unwrap(),
+                apis: vec![ApiName::from("fs"), ApiName::from("process")],
+            }),
+            Box::new(SetPkgSandboxKind {
+                perm_sel: "crab1.build".parse().unwrap(),
+                kind: SandboxKind::Bubblewrap,
+            }),
+        ];
+        for edit in &edits {
+            edit.apply(&mut editor, &Default::default()).unwrap();
+        }
+        assert_eq!(
+            editor.to_toml(),
+            indoc! {r#"
+                [pkg.crab1]
+                test.allow_apis = [
+                    "net",
+                ]
+                allow_apis = [
+                    "fs",
+                    "process",
+                ]
+
+                [pkg.crab1.build.sandbox]
+                kind = "Bubblewrap"
+            "#}
+        );
+    }
+
+    #[test]
+    fn edits_preserve_formatting() {
+        let mut editor = ConfigEditor::from_toml_string(indoc! {r#"
+            # Top comment
+
+            [common]
+            # Pinned on purpose
+            version = 1 # trailing
+            import_std = ["fs", "process"] # not net
+
+            [sandbox]
+            # Which sandbox
+            kind = "Bubblewrap" # for CI
+
+            [pkg.crab1]
+            # Note about unsafe
+            allow_unsafe = false # hmm
+            allow_apis = ["fs", "process"]
+            build.sandbox.kind = "Bubblewrap" # bw
+        "#})
+        .unwrap();
+        editor.set_version(2).unwrap();
+        editor.set_sandbox_kind(SandboxKind::Disabled).unwrap();
+        editor.toggle_std_import("env").unwrap();
+        editor.toggle_std_import("process").unwrap();
+        let review = EditOpts {
+            comment: Some("review".to_owned()),
+        };
+        let edits: Vec<Box<dyn Edit>> = vec![
+            Box::new(AllowUnsafe {
+                perm_sel: PermSel::for_primary("crab1"),
+            }),
+            Box::new(SetPkgSandboxKind {
+                perm_sel: PermSel::for_build_script("crab1"),
+                kind: SandboxKind::Disabled,
+            }),
+            Box::new(AllowApis {
+                perm_sel: PermSel::for_primary("crab1"),
+                apis: vec![ApiName::from("env")],
+            }),
+        ];
+        for edit in &edits {
+            edit.apply(&mut editor, &review).unwrap();
+            // Applying again shouldn't add a second comment.
+            edit.apply(&mut editor, &review).unwrap();
+        }
+        assert_eq!(
+            editor.to_toml(),
+            indoc! {r#"
+                # Top comment
+
+                [common]
+                # Pinned on purpose
+                version = 2 # trailing
+                import_std = ["fs", "env"] # not net
+
+                [sandbox]
+                # Which sandbox
+                kind = "Disabled" # for CI
+
+                [pkg.crab1]
+                # Note about unsafe
+                # review
+                allow_unsafe = true # hmm
+                allow_apis = ["env", "fs", "process"]
+                build.sandbox.kind = "Disabled" # bw
+            "#}
+        );
+    }
+
+    #[test]
+    fn comment_in_dotted_table() {
+        let mut editor = ConfigEditor::from_toml_string(indoc! {r#"
+            [pkg.crab1]
+            test.allow_build_instructions = ["cargo:rustc-cfg=*"]
+        "#})
+        .unwrap();
+        let edit = &fixes_for_problem(
+            &Problem::DisallowedUnsafe(crate::proxy::rpc::UnsafeUsage {
+                crate_sel: CrateSel {
+                    pkg_id: pkg_id("crab1"),
+                    kind: crate::crate_index::CrateKind::Test,
+                },
+                locations: vec![SourceLocation::new(Path::new("main.rs"), 10, None)],
+            }),
+            &Config::default(),
+        )[0];
+        edit.apply(
+            &mut editor,
+            &EditOpts {
+                comment: Some("review".to_owned()),
+            },
+        )
+        .unwrap();
+        let updated_toml = editor.to_toml();
+        crate::config::testing::parse(&updated_toml).unwrap();
+        assert!(updated_toml.contains("test.allow_unsafe = true"));
+    }
+
+    #[test]
+    fn build_script_network_denied() {
         let crate_sel = CrateSel::build_script(pkg_id("crab1"));
         let failure = Problem::ExecutionFailed(crate::problem::BinExecutionFailed {
-            output: BinExecutionOutput {
+            output: Box::new(BinExecutionOutput {
                 exit_code: 1,
                 stdout: Vec::new(),
                 stderr: Vec::new(),
                 crate_sel: crate_sel.clone(),
                 sandbox_config: SandboxConfig {
                     kind: Some(crate::config::SandboxKind::Bubblewrap),
+                    allow_domains: vec!["crates.io".to_owned()],
+                    ..Default::default()
+                },
+                binary_path: PathBuf::new(),
+                sandbox_config_display: None,
+                network_requests: vec![crate::sandbox::NetworkRequest {
+                    method: "CONNECT".to_owned(),
+                    host: "github.com".to_owned(),
+                    port: 443,
+                    allowed: false,
+                }],
+                denied_accesses: Vec::new(),
+                limit_exceeded: None,
+                captured_writes: Vec::new(),
+            }),
+            crate_sel,
+        });
+        check(
+            indoc! {r#"
+                [pkg.crab1.build.sandbox]
+                allow_domains = ["crates.io"]
+            "#,
+            },
+            &failure,
+            0,
+            indoc! {r#"
+                [pkg.crab1.build.sandbox]
+                allow_domains = ["crates.io", "github.com"]
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn build_script_write_denied() {
+        let crate_sel = CrateSel::build_script(pkg_id("crab1"));
+        let failure = Problem::ExecutionFailed(crate::problem::BinExecutionFailed {
+            output: Box::new(BinExecutionOutput {
+                exit_code: 1,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+                crate_sel: crate_sel.clone(),
+                sandbox_config: SandboxConfig {
+                    kind: Some(crate::config::SandboxKind::Bubblewrap),
+                    ..Default::default()
+                },
+                binary_path: PathBuf::new(),
+                sandbox_config_display: None,
+                network_requests: Vec::new(),
+                denied_accesses: vec![crate::sandbox::DeniedAccess {
+                    kind: crate::sandbox::AccessKind::Write,
+                    target: "/work/generated/out.rs".to_owned(),
+                    error: "EROFS".to_owned(),
+                }],
+                limit_exceeded: None,
+                captured_writes: Vec::new(),
+            }),
+            crate_sel,
+        });
+        check(
+            "",
+            &failure,
+            0,
+            indoc! {r#"
+                [pkg.crab1.build.sandbox]
+                bind_writable = [
+                    "/work/generated",
+                ]
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn build_script_limit_exceeded() {
+        let crate_sel = CrateSel::build_script(pkg_id("crab1"));
+        let failure = Problem::ResourceLimitExceeded(crate::problem::BinExecutionFailed {
+            output: Box::new(BinExecutionOutput {
+                exit_code: -1,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+                crate_sel: crate_sel.clone(),
+                sandbox_config: SandboxConfig {
+                    kind: Some(crate::config::SandboxKind::Bubblewrap),
+                    max_wall_seconds: Some(60),
                     ..Default::default()
                 },
                 binary_path: PathBuf::new(),
                 sandbox_config_display: None,
+                network_requests: Vec::new(),
+                denied_accesses: Vec::new(),
+                limit_exceeded: Some(crate::sandbox::ResourceLimit::WallTime(60)),
+                captured_writes: Vec::new(),
+            }),
+            crate_sel,
+        });
+        check(
+            indoc! {r#"
+                [pkg.crab1.build.sandbox]
+                max_wall_seconds = 60
+            "#,
+            },
+            &failure,
+            0,
+            indoc! {r#"
+                [pkg.crab1.build.sandbox]
+                max_wall_seconds = 120
+            "#,
             },
+        );
+    }
+
+    #[test]
+    fn test_memory_limit_exceeded() {
+        let crate_sel = CrateSel {
+            pkg_id: pkg_id("crab1"),
+            kind: crate::crate_index::CrateKind::Test,
+        };
+        let failure = Problem::ResourceLimitExceeded(crate::problem::BinExecutionFailed {
+            output: Box::new(BinExecutionOutput {
+                exit_code: -1,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+                crate_sel: crate_sel.clone(),
+                sandbox_config: SandboxConfig {
+                    kind: Some(crate::config::SandboxKind::Bubblewrap),
+                    max_memory_mb: Some(512),
+                    ..Default::default()
+                },
+                binary_path: PathBuf::new(),
+                sandbox_config_display: None,
+                network_requests: Vec::new(),
+                denied_accesses: Vec::new(),
+                limit_exceeded: Some(crate::sandbox::ResourceLimit::Memory(512)),
+                captured_writes: Vec::new(),
+            }),
+            crate_sel,
+        });
+        check(
+            "",
+            &failure,
+            0,
+            indoc! {r#"
+                [pkg.crab1.test.sandbox]
+                max_memory_mb = 1024
+            "#,
+            },
+        );
+    }
+
+    #[test]
+    fn build_script_failed() {
+        let crate_sel = CrateSel::build_script(pkg_id("crab1"));
+        let failure = Problem::ExecutionFailed(crate::problem::BinExecutionFailed {
+            output: Box::new(BinExecutionOutput {
+                exit_code: 1,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+                crate_sel: crate_sel.clone(),
+                sandbox_config: SandboxConfig {
+                    kind: Some(crate::config::SandboxKind::Bubblewrap),
+                    ..Default::default()
+                },
+                binary_path: PathBuf::new(),
+                sandbox_config_display: None,
+                network_requests: Vec::new(),
+                denied_accesses: Vec::new(),
+                limit_exceeded: None,
+                captured_writes: Vec::new(),
+            }),
             crate_sel,
         });
         check(
@@ -1,3 +1,6 @@
+use crate::config::permissions::is_valid_version;
+use crate::config::permissions::PermSel;
+use crate::config::permissions::PermissionScope;
 use crate::config::ApiName;
 use crate::config::Config;
 use crate::config::MAX_VERSION;
@@ -18,6 +21,9 @@ enum Problem {
     DuplicateAllowedApi(ApiName),
     UnsupportedVersion(i64),
     InvalidPkgSelector(String),
+    InvalidPkgVersion(PermSel),
+    VersionInSubSelector(PermSel),
+    InvalidConditional(String),
 }
 
 pub(crate) fn validate(config: &Config, config_path: &Path) -> Result<(), InvalidConfig> {
@@ -36,15 +42,66 @@ pub(crate) fn validate(config: &Config, config_path: &Path) -> Result<(), Invali
                 problems.push(Problem::DuplicateAllowedApi(permission_name.clone()))
             }
         }
+        for permission_name in &crate_config.deny_apis {
+            if !permission_names.contains(permission_name) {
+                problems.push(Problem::UnknownPermission(permission_name.clone()));
+            }
+        }
+        if perm_sel
+            .package_name
+            .version()
+            .is_some_and(|version| !is_valid_version(version))
+        {
+            problems.push(Problem::InvalidPkgVersion(perm_sel.clone()));
+        }
         if crate_config.build.is_some() {
             problems.push(Problem::InvalidPkgSelector(format!("{perm_sel}.build")));
         }
         if crate_config.test.is_some() {
             problems.push(Problem::InvalidPkgSelector(format!("{perm_sel}.test")));
         }
+        if crate_config.version.is_some() && perm_sel.scope != PermissionScope::All {
+            problems.push(Problem::VersionInSubSelector(perm_sel.clone()));
+        }
+        if crate_config.dev.is_some() {
+            problems.push(Problem::InvalidPkgSelector(format!("{perm_sel}.dev")));
+        }
         if crate_config.from.is_some() {
             problems.push(Problem::InvalidPkgSelector(format!("{perm_sel}.dep")));
         }
+        for (cfg, conditional) in &crate_config.conditional {
+            for permission_name in conditional.allow_apis.iter().chain(&conditional.deny_apis) {
+                if !permission_names.contains(permission_name) {
+                    problems.push(Problem::UnknownPermission(permission_name.clone()));
+                }
+            }
+            if conditional.version.is_some()
+                || conditional.build.is_some()
+                || conditional.test.is_some()
+                || conditional.dev.is_some()
+                || conditional.from.is_some()
+                || !conditional.conditional.is_empty()
+            {
+                problems.push(Problem::InvalidConditional(format!("{perm_sel}.'{cfg}'")));
+            }
+        }
+    }
+    for api in config
+        .raw
+        .cargo_vet
+        .grant
+        .values()
+        .flatten()
+        .chain(&config.raw.advisories.sensitive_apis)
+    {
+        if !permission_names.contains(api) {
+            problems.push(Problem::UnknownPermission(api.clone()));
+        }
+    }
+    for rule in &config.raw.ignore {
+        if !permission_names.contains(&rule.api) {
+            problems.push(Problem::UnknownPermission(rule.api.clone()));
+        }
     }
     if problems.is_empty() {
         Ok(())
@@ -71,6 +128,21 @@ impl Display for InvalidConfig {
                 Problem::InvalidPkgSelector(sel) => {
                     write!(f, "  Unsupported package selector `pkg.{sel}`")?
                 }
+                Problem::InvalidPkgVersion(perm_sel) => write!(
+                    f,
+                    "  Invalid version in `pkg.{perm_sel}`. Only the semver-compatible part of the \
+                     version should be given, e.g. `rand@1` or `rand@0.8`"
+                )?,
+                Problem::VersionInSubSelector(perm_sel) => write!(
+                    f,
+                    "  `version` can only be set in `pkg.{}`, not `pkg.{perm_sel}`",
+                    perm_sel.package_name
+                )?,
+                Problem::InvalidConditional(sel) => write!(
+                    f,
+                    "  `pkg.{sel}` can only contain permissions, not `version`, selectors or \
+                     further conditions"
+                )?,
             }
         }
         Ok(())
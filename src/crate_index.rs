@@ -10,13 +10,16 @@ use anyhow::Context;
 use anyhow::Result;
 use cargo_metadata::camino::Utf8PathBuf;
 use cargo_metadata::semver::Version;
+use cargo_metadata::CargoOpt;
 use cargo_metadata::DependencyKind;
 use fxhash::FxHashMap;
 use fxhash::FxHashSet;
 use serde::Deserialize;
 use serde::Serialize;
 use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::fmt::Display;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -27,10 +30,20 @@ pub(crate) mod lib_tree;
 pub(crate) struct CrateIndex {
     pub(crate) manifest_path: PathBuf,
     pub(crate) package_infos: FxHashMap<PackageId, PackageInfo>,
+    /// Maps the directory of each package to its ID. Directories are present both as reported by
+    /// cargo metadata and with symlinks resolved, if that differs.
     dir_to_pkg_id: FxHashMap<PathBuf, PackageId>,
+    /// The target directory, both as reported by cargo metadata and with symlinks resolved.
+    target_dirs: Vec<PathBuf>,
+    /// Directories containing vendored packages, both as configured and with symlinks resolved.
+    vendor_dirs: Vec<PathBuf>,
     pkg_name_to_ids: FxHashMap<Arc<str>, Vec<PackageId>>,
+    /// Maps names that dependencies were given in Cargo.toml via `package = "..."` to the packages
+    /// that they refer to. Doesn't include names that are also the names of actual packages.
+    renames: FxHashMap<Arc<str>, Vec<PackageId>>,
     lib_tree: LibTree,
     pub(crate) permission_selectors: FxHashSet<PermSel>,
+    workspace_member_dirs: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
@@ -55,6 +68,8 @@ pub(crate) enum CrateKind {
     Primary,
     BuildScript,
     Test,
+    /// An example binary. Like tests, these can link dev-dependencies.
+    Example,
 }
 
 #[derive(Debug)]
@@ -62,7 +77,36 @@ pub(crate) struct PackageInfo {
     pub(crate) directory: Utf8PathBuf,
     pub(crate) description: Option<String>,
     pub(crate) documentation: Option<String>,
+    pub(crate) repository: Option<String>,
     is_proc_macro: bool,
+    /// Proc macros that might generate code in this package. See `find_proc_macro_deps`.
+    proc_macro_deps: Vec<PackageId>,
+    /// The package's features that cargo resolved as enabled.
+    features: FxHashSet<String>,
+    /// The package's direct dependencies with the current configuration, sorted by package.
+    dependencies: Vec<Dependency>,
+    /// How the package is used by the workspace. e.g. if it's reached through any build
+    /// dependency, this contains `Build`.
+    dep_kinds: BTreeSet<DepKind>,
+}
+
+/// A resolved dependency of one package on another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Dependency {
+    pub(crate) pkg_id: PackageId,
+    /// The kinds of dependency. A package can for example be both a normal and a build dependency
+    /// of the same package.
+    pub(crate) kinds: BTreeSet<DepKind>,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum DepKind {
+    /// Code that ends up in the workspace's non-test outputs.
+    Normal,
+    /// Code that's only used by build scripts.
+    Build,
+    /// Code that's only used by tests, examples and benchmarks.
+    Dev,
 }
 
 /// The name of the environment variable that we use to pass a list of non-unique package names to
@@ -72,27 +116,54 @@ pub(crate) struct PackageInfo {
 /// there are multiple versions of that package.
 pub(crate) const MULTIPLE_VERSION_PKG_NAMES_ENV: &str = "CACKLE_MULTIPLE_VERSION_PKG_NAMES";
 
+/// The name of the package to which the standard library is attributed when it's being audited.
+pub(crate) const RUST_STD_PKG_NAME: &str = "std";
+
 impl CrateIndex {
-    pub(crate) fn new(dir: &Path) -> Result<Self> {
+    /// Builds an index of the workspace in `dir`. `features` are the features to enable on
+    /// workspace members, which affects which features are resolved as enabled on dependencies.
+    pub(crate) fn new(dir: &Path, features: &[String]) -> Result<Self> {
         let manifest_path = dir.join("Cargo.toml");
-        let metadata = cargo_metadata::MetadataCommand::new()
-            .manifest_path(&manifest_path)
-            .exec()?;
+        let mut command = cargo_metadata::MetadataCommand::new();
+        // Cargo looks for its config, which may for example replace crates.io with vendored
+        // sources, starting from the current directory. So run from the workspace, as we do when
+        // building.
+        command.current_dir(dir);
+        command.manifest_path(&manifest_path);
+        if !features.is_empty() {
+            command.features(CargoOpt::SomeFeatures(features.to_vec()));
+        }
+        let metadata = command.exec()?;
+        let resolved_features: FxHashMap<&cargo_metadata::PackageId, &[String]> = metadata
+            .resolve
+            .iter()
+            .flat_map(|resolve| &resolve.nodes)
+            .map(|node| (&node.id, node.features.as_slice()))
+            .collect();
         let mut mapping = CrateIndex {
             manifest_path,
             ..Self::default()
         };
+        mapping.target_dirs = with_canonical(metadata.target_directory.as_std_path());
+        mapping.vendor_dirs = crate::proxy::cargo::vendored_source_dirs(dir)
+            .iter()
+            .flat_map(|vendor_dir| with_canonical(vendor_dir))
+            .collect();
         let mut name_counts = FxHashMap::default();
         for package in &metadata.packages {
             *name_counts.entry(&package.name).or_default() += 1;
         }
         let mut direct_deps: FxHashMap<PackageId, Vec<Arc<str>>> = FxHashMap::default();
+        let mut pkg_ids: FxHashMap<&cargo_metadata::PackageId, PackageId> = FxHashMap::default();
+        let packages_by_id: FxHashMap<&cargo_metadata::PackageId, &cargo_metadata::Package> =
+            metadata.packages.iter().map(|p| (&p.id, p)).collect();
         for package in &metadata.packages {
             let pkg_id = PackageId {
                 name: Arc::from(package.name.as_str()),
                 version: package.version.clone(),
                 name_is_unique: name_counts.get(&package.name) == Some(&1),
             };
+            pkg_ids.insert(&package.id, pkg_id.clone());
             let mut is_proc_macro = false;
             let mut has_build_script = false;
             let mut has_test = false;
@@ -119,29 +190,111 @@ impl CrateIndex {
                         directory: dir.to_path_buf(),
                         description: package.description.clone(),
                         documentation: package.documentation.clone(),
+                        repository: package.repository.clone(),
                         is_proc_macro,
+                        proc_macro_deps: Vec::new(),
+                        features: resolved_features
+                            .get(&package.id)
+                            .map(|features| features.iter().cloned().collect())
+                            .unwrap_or_default(),
+                        dependencies: Vec::new(),
+                        dep_kinds: BTreeSet::new(),
                     },
                 );
-                add_permission_selectors(
-                    &mut mapping.permission_selectors,
-                    package.name.as_str(),
-                    has_build_script,
-                    has_test,
-                );
+                // When there are multiple versions of a package, each version gets its own
+                // selectors. Selectors without the version are still valid and apply to all
+                // versions.
+                let perm_name = pkg_id.perm_name();
+                for pkg_name in [package.name.as_str(), perm_name.as_ref()] {
+                    add_permission_selectors(
+                        &mut mapping.permission_selectors,
+                        pkg_name,
+                        has_build_script,
+                        has_test,
+                    );
+                }
                 mapping
                     .pkg_name_to_ids
                     .entry(Arc::from(package.name.as_str()))
                     .or_default()
                     .push(pkg_id.clone());
-                mapping
-                    .dir_to_pkg_id
-                    .insert(dir.as_std_path().to_owned(), pkg_id.clone());
+                // Registry, git, path, patched and vendored packages all have their source under
+                // the directory containing their manifest. Source paths that we get from rustc and
+                // from debug info may however have had symlinks resolved, so record the canonical
+                // directory too.
+                for pkg_dir in with_canonical(dir.as_std_path()) {
+                    mapping.dir_to_pkg_id.insert(pkg_dir, pkg_id.clone());
+                }
+                if metadata.workspace_members.contains(&package.id) {
+                    mapping
+                        .workspace_member_dirs
+                        .push(dir.as_std_path().to_owned());
+                }
+            }
+        }
+        for node in metadata.resolve.iter().flat_map(|resolve| &resolve.nodes) {
+            let Some(info) = pkg_ids
+                .get(&node.id)
+                .and_then(|pkg_id| mapping.package_infos.get_mut(pkg_id))
+            else {
+                continue;
+            };
+            info.dependencies = node
+                .deps
+                .iter()
+                .filter_map(|dep| {
+                    Some(Dependency {
+                        pkg_id: pkg_ids.get(&dep.pkg)?.clone(),
+                        kinds: dep
+                            .dep_kinds
+                            .iter()
+                            .filter_map(|info| DepKind::from_cargo(info.kind))
+                            .collect(),
+                    })
+                })
+                .collect();
+            info.dependencies.sort_by(|a, b| {
+                (&a.pkg_id.name, &a.pkg_id.version).cmp(&(&b.pkg_id.name, &b.pkg_id.version))
+            });
+            let renamed_deps = packages_by_id
+                .get(&node.id)
+                .into_iter()
+                .flat_map(|package| &package.dependencies)
+                .filter_map(|dep| dep.rename.as_deref());
+            for rename in renamed_deps {
+                // The resolve graph only knows the dependency by the name of its extern crate.
+                let extern_name = rename.replace('-', "_");
+                if let Some(pkg_id) = node
+                    .deps
+                    .iter()
+                    .find(|dep| dep.name == extern_name)
+                    .and_then(|dep| pkg_ids.get(&dep.pkg))
+                {
+                    let renamed_to = mapping.renames.entry(Arc::from(rename)).or_default();
+                    if !renamed_to.contains(pkg_id) {
+                        renamed_to.push(pkg_id.clone());
+                    }
+                }
+            }
+        }
+        mapping.add_renames();
+        let members: Vec<PackageId> = metadata
+            .workspace_members
+            .iter()
+            .filter_map(|id| pkg_ids.get(id).cloned())
+            .collect();
+        mapping.compute_dep_kinds(&members);
+        for (pkg_id, deps) in &direct_deps {
+            let proc_macro_deps = mapping.find_proc_macro_deps(deps, &direct_deps);
+            if let Some(info) = mapping.package_infos.get_mut(pkg_id) {
+                info.proc_macro_deps = proc_macro_deps;
             }
         }
         mapping.lib_tree = LibTree::from_workspace(dir, &mapping.pkg_name_to_ids)?;
         for package_ids in mapping.pkg_name_to_ids.values_mut() {
             package_ids.sort_by_key(|pkg_id| pkg_id.version.clone());
         }
+        mapping.workspace_member_dirs.sort();
         Ok(mapping)
     }
 
@@ -161,10 +314,85 @@ impl CrateIndex {
         command.env(MULTIPLE_VERSION_PKG_NAMES_ENV, non_unique_names.join(","));
     }
 
+    /// Returns the newest package that `pkg_name` selects. If `pkg_name` includes a version, then
+    /// only packages with that version are considered.
     pub(crate) fn newest_package_id_with_name(&self, pkg_name: &PackageName) -> Option<&PackageId> {
-        self.pkg_name_to_ids
-            .get(pkg_name.as_ref())
-            .and_then(|pkg_ids| pkg_ids.last())
+        self.packages_selected_by(pkg_name)
+            .max_by_key(|pkg_id| &pkg_id.version)
+    }
+
+    /// Returns the packages that `pkg_name` refers to. `pkg_name` may include a version and may be
+    /// a name that a dependency was renamed to in Cargo.toml.
+    fn packages_selected_by<'a, 'b: 'a>(
+        &'b self,
+        pkg_name: &'a PackageName,
+    ) -> impl Iterator<Item = &'b PackageId> + 'a {
+        let by_name = self
+            .pkg_name_to_ids
+            .get(pkg_name.name())
+            .into_iter()
+            .flatten()
+            .filter(|pkg_id| pkg_id.is_selected_by(pkg_name));
+        by_name.chain(self.renames.get(pkg_name.as_ref()).into_iter().flatten())
+    }
+
+    /// Returns the names that dependents gave the package selected by `pkg_name` by renaming it in
+    /// their Cargo.toml.
+    pub(crate) fn renamed_as<'a>(
+        &'a self,
+        pkg_name: &'a PackageName,
+    ) -> impl Iterator<Item = &'a Arc<str>> {
+        self.renames
+            .iter()
+            .filter(|(_, pkg_ids)| {
+                pkg_ids
+                    .iter()
+                    .any(|pkg_id| *pkg_id.perm_name() == *pkg_name.as_ref())
+            })
+            .map(|(rename, _)| rename)
+    }
+
+    /// Drops renames that clash with the names of actual packages, then adds permission selectors
+    /// for the remaining ones, so that config written against them isn't reported as unused.
+    fn add_renames(&mut self) {
+        let pkg_name_to_ids = &self.pkg_name_to_ids;
+        self.renames
+            .retain(|rename, _| !pkg_name_to_ids.contains_key(rename));
+        for (rename, pkg_ids) in &self.renames {
+            let has_scope = |scope| {
+                pkg_ids.iter().any(|pkg_id| {
+                    self.permission_selectors
+                        .contains(&PermSel::with_scope(pkg_id, scope))
+                })
+            };
+            let has_build_script = has_scope(PermissionScope::Build);
+            let has_test = has_scope(PermissionScope::Test);
+            let mut selectors = FxHashSet::default();
+            add_permission_selectors(&mut selectors, rename, has_build_script, has_test);
+            self.permission_selectors.extend(selectors);
+        }
+    }
+
+    /// Returns the directories of the packages that are members of the workspace, sorted by path.
+    pub(crate) fn workspace_member_dirs(&self) -> &[PathBuf] {
+        &self.workspace_member_dirs
+    }
+
+    /// Returns whether `pkg_id` is a member of the workspace.
+    pub(crate) fn is_workspace_member(&self, pkg_id: &PackageId) -> bool {
+        self.pkg_dir(pkg_id).is_some_and(|dir| {
+            self.workspace_member_dirs
+                .binary_search_by(|d| d.as_path().cmp(dir))
+                .is_ok()
+        })
+    }
+
+    /// Returns whether `feature` is enabled on any version of the named package, or on the named
+    /// version if `pkg_name` includes one.
+    pub(crate) fn is_feature_enabled(&self, pkg_name: &PackageName, feature: &str) -> bool {
+        self.packages_selected_by(pkg_name)
+            .filter_map(|pkg_id| self.package_infos.get(pkg_id))
+            .any(|info| info.features.contains(feature))
     }
 
     pub(crate) fn package_info(&self, pkg_id: &PackageId) -> Option<&PackageInfo> {
@@ -181,6 +409,105 @@ impl CrateIndex {
         self.package_infos.keys()
     }
 
+    /// Returns the direct dependencies of `pkg_id` with the current configuration.
+    pub(crate) fn dependencies(&self, pkg_id: &PackageId) -> &[Dependency] {
+        self.package_infos
+            .get(pkg_id)
+            .map(|info| info.dependencies.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns how `pkg_id` is used by the workspace. Workspace members are `Normal`. Anything
+    /// reached from a package via a normal dependency is used in the same ways as that package.
+    /// Anything reached via a build or dev dependency is used by build scripts or tests
+    /// respectively, as is everything that it depends on.
+    pub(crate) fn dep_kinds(&self, pkg_id: &PackageId) -> Option<&BTreeSet<DepKind>> {
+        self.package_infos.get(pkg_id).map(|info| &info.dep_kinds)
+    }
+
+    /// Populates `dep_kinds` for each package by propagating from the workspace `members` along
+    /// dependency edges until nothing changes.
+    fn compute_dep_kinds(&mut self, members: &[PackageId]) {
+        let mut pending: Vec<(PackageId, DepKind)> = members
+            .iter()
+            .map(|pkg_id| (pkg_id.clone(), DepKind::Normal))
+            .collect();
+        while let Some((pkg_id, kind)) = pending.pop() {
+            let Some(info) = self.package_infos.get_mut(&pkg_id) else {
+                continue;
+            };
+            if !info.dep_kinds.insert(kind) {
+                continue;
+            }
+            for dep in &info.dependencies {
+                for dep_kind in &dep.kinds {
+                    let propagated = match dep_kind {
+                        DepKind::Normal => kind,
+                        other => *other,
+                    };
+                    pending.push((dep.pkg_id.clone(), propagated));
+                }
+            }
+        }
+    }
+
+    /// Returns proc macros that might generate code within `pkg_id`.
+    pub(crate) fn proc_macro_deps(&self, pkg_id: &PackageId) -> &[PackageId] {
+        self.package_infos
+            .get(pkg_id)
+            .map(|info| info.proc_macro_deps.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns proc macros that are in `deps` and also proc macros that appear to be the
+    /// implementation of a package in `deps`. e.g. if `deps` contains `serde`, then we'd include
+    /// `serde_derive`, since `serde` reexports its macros.
+    fn find_proc_macro_deps(
+        &self,
+        deps: &[Arc<str>],
+        direct_deps: &FxHashMap<PackageId, Vec<Arc<str>>>,
+    ) -> Vec<PackageId> {
+        let mut out = Vec::new();
+        for dep_name in deps {
+            for dep_id in self.pkg_name_to_ids.get(dep_name).into_iter().flatten() {
+                if self
+                    .package_infos
+                    .get(dep_id)
+                    .is_some_and(|i| i.is_proc_macro)
+                {
+                    out.push(dep_id.clone());
+                    continue;
+                }
+                let dep_crate_name = dep_name.replace('-', "_");
+                for indirect_name in direct_deps.get(dep_id).into_iter().flatten() {
+                    if !indirect_name
+                        .replace('-', "_")
+                        .starts_with(&format!("{dep_crate_name}_"))
+                    {
+                        continue;
+                    }
+                    for indirect_id in self
+                        .pkg_name_to_ids
+                        .get(indirect_name)
+                        .into_iter()
+                        .flatten()
+                    {
+                        if self
+                            .package_infos
+                            .get(indirect_id)
+                            .is_some_and(|i| i.is_proc_macro)
+                        {
+                            out.push(indirect_id.clone());
+                        }
+                    }
+                }
+            }
+        }
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        out.dedup();
+        out
+    }
+
     pub(crate) fn proc_macros(&self) -> impl Iterator<Item = &PackageId> {
         self.package_infos.iter().filter_map(|(pkg_id, info)| {
             if info.is_proc_macro {
@@ -196,11 +523,35 @@ impl CrateIndex {
     /// example in the case of crates that compile C code, since the C code won't be in the deps
     /// file. This function however doesn't differentiate between the build script for a package and
     /// the other source files in that package, so should only be used as a fallback.
-    pub(crate) fn package_id_for_path(&self, mut path: &Path) -> Option<&PackageId> {
+    pub(crate) fn package_id_for_path(&self, path: &Path) -> Option<&PackageId> {
+        let path = normalise_path(path);
+        self.package_id_for_normalised_path(&path).or_else(|| {
+            let canonical = path.canonicalize().ok()?;
+            if canonical == path {
+                return None;
+            }
+            self.package_id_for_normalised_path(&canonical)
+        })
+    }
+
+    fn package_id_for_normalised_path(&self, mut path: &Path) -> Option<&PackageId> {
+        if let Some(relative) = self
+            .target_dirs
+            .iter()
+            .find_map(|target_dir| path.strip_prefix(target_dir).ok())
+        {
+            return self.package_id_for_build_output(relative);
+        }
         loop {
             if let Some(pkg_id) = self.dir_to_pkg_id.get(path) {
                 return Some(pkg_id);
             }
+            // The vendor directory is usually within the workspace. Vendored sources of packages
+            // that aren't in the dependency tree shouldn't be attributed to the workspace's root
+            // package.
+            if self.vendor_dirs.iter().any(|vendor_dir| vendor_dir == path) {
+                return None;
+            }
             if let Some(parent) = path.parent() {
                 path = parent;
             } else {
@@ -209,12 +560,51 @@ impl CrateIndex {
         }
     }
 
+    /// Returns the ID of the package whose build script produced `relative_path`, which is relative
+    /// to the target directory. Build script output goes in
+    /// `[{triple}/]{profile}/build/{package name}-{hash}/out`. Without this, generated files would
+    /// be attributed to whichever package contains the target directory, if any. Returns None if
+    /// there are multiple versions of the package, since we can't tell them apart.
+    fn package_id_for_build_output(&self, relative_path: &Path) -> Option<&PackageId> {
+        let mut components = relative_path.components().map(|c| c.as_os_str().to_str());
+        components.find(|c| *c == Some("build"))?;
+        let (pkg_name, _hash) = components.next()??.rsplit_once('-')?;
+        match self.pkg_name_to_ids.get(pkg_name)?.as_slice() {
+            [pkg_id] => Some(pkg_id),
+            _ => None,
+        }
+    }
+
     /// Returns the transitive deps for `pkg_id`. All deps will be in "crate form", i.e. with '-'
     /// replaced with '_'.
     pub(crate) fn transitive_deps(&self, pkg_id: &PackageId) -> Option<&FxHashSet<Arc<str>>> {
         self.lib_tree.pkg_transitive_deps.get(pkg_id)
     }
 
+    /// Returns the packages that depend on `pkg_id`, either directly or indirectly, with the
+    /// current configuration. Dependencies are tracked by lib name, so if there are multiple
+    /// versions of `pkg_id`'s package, then dependents of the other versions are included too.
+    pub(crate) fn transitive_dependents(&self, pkg_id: &PackageId) -> Vec<&PackageId> {
+        let lib_names: Vec<&Arc<str>> = self
+            .lib_tree
+            .lib_name_to_pkg_id
+            .iter()
+            .filter(|(_, id)| *id == pkg_id)
+            .map(|(lib_name, _)| lib_name)
+            .collect();
+        let mut dependents: Vec<&PackageId> = self
+            .lib_tree
+            .pkg_transitive_deps
+            .iter()
+            .filter(|(id, deps)| {
+                *id != pkg_id && lib_names.iter().any(|lib_name| deps.contains(*lib_name))
+            })
+            .map(|(id, _)| id)
+            .collect();
+        dependents.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        dependents
+    }
+
     /// Returns a map from "crate form" names to package names.
     pub(crate) fn name_prefix_to_pkg_id(&self) -> &FxHashMap<Arc<str>, PackageId> {
         &self.lib_tree.lib_name_to_pkg_id
@@ -231,6 +621,8 @@ fn add_permission_selectors(
     permission_selectors.insert(perm_sel.clone());
     permission_selectors.insert(perm_sel.clone_with_scope(PermissionScope::FromBuild));
     permission_selectors.insert(perm_sel.clone_with_scope(PermissionScope::FromTest));
+    permission_selectors.insert(perm_sel.clone_with_scope(PermissionScope::FromHost));
+    permission_selectors.insert(perm_sel.clone_with_scope(PermissionScope::Dev));
     if has_build_script {
         permission_selectors.insert(perm_sel.clone_with_scope(PermissionScope::Build));
     }
@@ -240,10 +632,39 @@ fn add_permission_selectors(
 }
 
 impl PackageId {
+    /// Returns the ID of the pseudo-package to which the standard library is attributed when
+    /// `common.audit_std` is set.
+    pub(crate) fn rust_std() -> Self {
+        PackageId {
+            name: Arc::from(RUST_STD_PKG_NAME),
+            version: Version::new(0, 0, 0),
+            name_is_unique: true,
+        }
+    }
+
     pub(crate) fn pkg_name(&self) -> Arc<str> {
         self.name.clone()
     }
 
+    /// Returns the name used to select this package in the `pkg` section of the config. If there
+    /// are multiple versions of the package, then this includes the semver-compatible part of the
+    /// version, e.g. `rand@0.8`, so that each version can be granted permissions independently.
+    pub(crate) fn perm_name(&self) -> Arc<str> {
+        if self.name_is_unique {
+            self.name.clone()
+        } else {
+            Arc::from(format!("{}@{}", self.name, compatible_version(&self.version)).as_str())
+        }
+    }
+
+    /// Returns whether `pkg_name` refers to this package, either with or without a version.
+    pub(crate) fn is_selected_by(&self, pkg_name: &PackageName) -> bool {
+        *self.name == *pkg_name.name()
+            && pkg_name
+                .version()
+                .map_or(true, |version| version == compatible_version(&self.version))
+    }
+
     pub(crate) fn from_env() -> Result<Self> {
         let name = get_env("CARGO_PKG_NAME")?;
         let version_string = get_env("CARGO_PKG_VERSION")?;
@@ -267,7 +688,7 @@ impl PackageId {
         &self.version
     }
 
-    pub(crate) fn crate_name(&self) -> Cow<str> {
+    pub(crate) fn crate_name(&self) -> Cow<'_, str> {
         if self.name.contains('-') {
             self.name.replace('-', "_").into()
         } else {
@@ -281,10 +702,6 @@ fn get_env(key: &str) -> Result<String> {
 }
 
 impl CrateSel {
-    pub(crate) fn pkg_name(&self) -> Arc<str> {
-        self.pkg_id.name.clone()
-    }
-
     pub(crate) fn primary(pkg_id: PackageId) -> Self {
         Self {
             pkg_id,
@@ -335,6 +752,7 @@ impl CrateKind {
             CrateKind::Primary => "primary",
             CrateKind::BuildScript => "build-script",
             CrateKind::Test => "test",
+            CrateKind::Example => "example",
         }
     }
 
@@ -343,6 +761,7 @@ impl CrateKind {
             "primary" => CrateKind::Primary,
             "build-script" => CrateKind::BuildScript,
             "test" => CrateKind::Test,
+            "example" => CrateKind::Example,
             other => bail!("Invalid crate selector token `{other}`"),
         })
     }
@@ -354,6 +773,36 @@ impl From<&PackageId> for PackageName {
     }
 }
 
+impl PackageInfo {
+    /// Returns the package's features that cargo resolved as enabled, in no particular order.
+    pub(crate) fn features(&self) -> impl Iterator<Item = &str> {
+        self.features.iter().map(String::as_str)
+    }
+}
+
+impl DepKind {
+    /// Returns the equivalent of a dependency kind from cargo metadata, or None if cargo reported
+    /// a kind that we don't know about.
+    fn from_cargo(kind: DependencyKind) -> Option<Self> {
+        match kind {
+            DependencyKind::Normal => Some(DepKind::Normal),
+            DependencyKind::Build => Some(DepKind::Build),
+            DependencyKind::Development => Some(DepKind::Dev),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DepKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DepKind::Normal => write!(f, "normal"),
+            DepKind::Build => write!(f, "build"),
+            DepKind::Dev => write!(f, "dev"),
+        }
+    }
+}
+
 impl Display for CrateSel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.pkg_id.name)?;
@@ -361,6 +810,7 @@ impl Display for CrateSel {
             CrateKind::BuildScript => write!(f, ".build")?,
             CrateKind::Primary => {}
             CrateKind::Test => write!(f, ".test")?,
+            CrateKind::Example => write!(f, ".example")?,
         }
         if !self.pkg_id.name_is_unique {
             write!(f, "[{}]", self.pkg_id.version)?;
@@ -381,13 +831,55 @@ impl PackageId {
     }
 }
 
+/// Returns the part of `version` that semver-compatible versions have in common. e.g. `1` for
+/// `1.2.3`, `0.8` for `0.8.5` and `0.0.3` for `0.0.3`.
+pub(crate) fn compatible_version(version: &Version) -> String {
+    if version.major > 0 {
+        version.major.to_string()
+    } else if version.minor > 0 {
+        format!("0.{}", version.minor)
+    } else {
+        format!("0.0.{}", version.patch)
+    }
+}
+
+/// Returns `path`, followed by its canonical form if that's different.
+fn with_canonical(path: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![path.to_owned()];
+    if let Ok(canonical) = path.canonicalize() {
+        if canonical != path {
+            paths.push(canonical);
+        }
+    }
+    paths
+}
+
+/// Removes `.` and `..` components from `path` without touching the filesystem. Paths in debug
+/// info sometimes contain these, e.g. when a path dependency is specified relative to its
+/// dependent.
+fn normalise_path(path: &Path) -> PathBuf {
+    let mut normalised = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalised.file_name().is_some() => {
+                normalised.pop();
+            }
+            other => normalised.push(other),
+        }
+    }
+    normalised
+}
+
 #[cfg(test)]
 pub(crate) mod testing {
     use super::CrateIndex;
     use super::PackageId;
     use super::PackageInfo;
+    use cargo_metadata::camino::Utf8PathBuf;
     use cargo_metadata::semver::Version;
     use fxhash::FxHashSet;
+    use std::path::Path;
     use std::sync::Arc;
 
     pub(crate) fn pkg_id(name: &str) -> PackageId {
@@ -398,6 +890,15 @@ pub(crate) mod testing {
         }
     }
 
+    /// Returns the ID of a package that has multiple versions in the dependency tree.
+    pub(crate) fn pkg_id_with_version(name: &str, version: &str) -> PackageId {
+        PackageId {
+            name: Arc::from(name),
+            version: Version::parse(version).unwrap(),
+            name_is_unique: false,
+        }
+    }
+
     pub(crate) fn index_with_package_names(package_names: &[&str]) -> Arc<CrateIndex> {
         let package_infos = package_names
             .iter()
@@ -408,7 +909,12 @@ pub(crate) mod testing {
                         directory: Default::default(),
                         description: Default::default(),
                         documentation: Default::default(),
+                        repository: Default::default(),
                         is_proc_macro: Default::default(),
+                        proc_macro_deps: Default::default(),
+                        features: Default::default(),
+                        dependencies: Default::default(),
+                        dep_kinds: Default::default(),
                     },
                 )
             })
@@ -423,6 +929,31 @@ pub(crate) mod testing {
             ..CrateIndex::default()
         })
     }
+
+    /// Returns an index with the specified packages, each with its own directory within `dir`.
+    pub(crate) fn index_with_package_dirs(package_names: &[&str], dir: &Path) -> Arc<CrateIndex> {
+        let mut index = Arc::into_inner(index_with_package_names(package_names)).unwrap();
+        for (pkg_id, info) in &mut index.package_infos {
+            let pkg_dir = dir.join(pkg_id.name_str());
+            std::fs::create_dir_all(&pkg_dir).unwrap();
+            info.directory = Utf8PathBuf::try_from(pkg_dir).unwrap();
+        }
+        Arc::new(index)
+    }
+
+    /// Returns an index with the specified packages, each with the specified features enabled.
+    pub(crate) fn index_with_features(packages: &[(&str, &[&str])]) -> Arc<CrateIndex> {
+        let names: Vec<&str> = packages.iter().map(|(name, _)| *name).collect();
+        let mut index = Arc::into_inner(index_with_package_names(&names)).unwrap();
+        for (name, features) in packages {
+            let pkg_id = pkg_id(name);
+            if let Some(info) = index.package_infos.get_mut(&pkg_id) {
+                info.features = features.iter().map(|f| f.to_string()).collect();
+            }
+            index.pkg_name_to_ids.insert(Arc::from(*name), vec![pkg_id]);
+        }
+        Arc::new(index)
+    }
 }
 
 #[test]
@@ -442,7 +973,7 @@ fn test_crate_index() {
 
     let crate_root = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
     let test_crates_dir = crate_root.join("test_crates");
-    let index = CrateIndex::new(&test_crates_dir).unwrap();
+    let index = CrateIndex::new(&test_crates_dir, &[]).unwrap();
 
     check(&index, "crab_2", &["crab_1", "crab_3"]);
     check(&index, "crab_4", &[]);
@@ -453,4 +984,325 @@ fn test_crate_index() {
             "crab_1", "crab_2", "crab_3", "crab_4", "crab_5", "crab_6", "crab_7", "crab_8", "res_1",
         ],
     );
+    let crab_bin = &index.name_prefix_to_pkg_id()["crab_bin"];
+    let proc_macro_deps: Vec<_> = index
+        .proc_macro_deps(crab_bin)
+        .iter()
+        .map(|pkg_id| pkg_id.name_str())
+        .collect();
+    assert_eq!(proc_macro_deps, vec!["pmacro-1"]);
+    let dependents: Vec<_> = index
+        .transitive_dependents(&index.name_prefix_to_pkg_id()["crab_3"])
+        .iter()
+        .map(|pkg_id| pkg_id.name_str())
+        .collect();
+    assert_eq!(dependents, vec!["crab-2", "crab-bin", "shared-1"]);
+
+    // crab-8 has crab-1 as a build dependency, while crab-bin depends on it normally.
+    let crab_8 = &index.name_prefix_to_pkg_id()["crab_8"];
+    let deps: Vec<(&str, Vec<DepKind>)> = index
+        .dependencies(crab_8)
+        .iter()
+        .map(|dep| (dep.pkg_id.name_str(), dep.kinds.iter().copied().collect()))
+        .collect();
+    assert_eq!(
+        deps,
+        vec![
+            ("crab-1", vec![DepKind::Build]),
+            ("crab-6", vec![DepKind::Normal])
+        ]
+    );
+    let kinds = |name: &str| -> Vec<DepKind> {
+        index
+            .dep_kinds(&index.name_prefix_to_pkg_id()[name])
+            .unwrap()
+            .iter()
+            .copied()
+            .collect()
+    };
+    assert_eq!(kinds("crab_bin"), vec![DepKind::Normal]);
+    assert_eq!(kinds("crab_1"), vec![DepKind::Normal, DepKind::Build]);
+    assert_eq!(kinds("crab_4"), vec![DepKind::Normal]);
+
+    // There are two versions of crab-3, so each gets its own permission selectors.
+    for name in ["crab-3", "crab-3@0.1", "crab-3@2"] {
+        assert!(index
+            .permission_selectors
+            .contains(&PermSel::for_primary(name)));
+    }
+    let crab_3_v1 = index
+        .newest_package_id_with_name(&PackageName("crab-3@0.1".into()))
+        .unwrap();
+    assert_eq!(crab_3_v1.version(), &Version::new(0, 1, 0));
+    assert_eq!(&*crab_3_v1.perm_name(), "crab-3@0.1");
+    assert!(crab_3_v1.is_selected_by(&PackageName("crab-3".into())));
+    assert!(!crab_3_v1.is_selected_by(&PackageName("crab-3@2".into())));
+    assert_eq!(
+        index
+            .newest_package_id_with_name(&PackageName("crab-3".into()))
+            .map(|pkg_id| pkg_id.perm_name()),
+        Some("crab-3@2".into())
+    );
+
+    let path_pkg = |path: PathBuf| index.package_id_for_path(&path).map(|id| id.name_str());
+    assert_eq!(
+        path_pkg(test_crates_dir.join("crab-1/../crab-2/./src/lib.rs")),
+        Some("crab-2")
+    );
+    let target_dir = &index.target_dirs[0];
+    assert_eq!(
+        path_pkg(target_dir.join("debug/build/crab-5-0123456789abcdef/out/generated.rs")),
+        Some("crab-5")
+    );
+    // There are two versions of crab-3, so we don't know which one a build directory belongs to.
+    assert_eq!(
+        path_pkg(target_dir.join("debug/build/crab-3-0123456789abcdef/out/generated.rs")),
+        None
+    );
+    assert_eq!(path_pkg(target_dir.join("debug/deps/generated.rs")), None);
+
+    let crab_3 = PackageName("crab-3".into());
+    assert!(!index.is_feature_enabled(&crab_3, "crash-if-not-sandboxed"));
+    let features = ["crab-bin/crash-if-not-sandboxed".to_owned()];
+    let index = CrateIndex::new(&test_crates_dir, &features).unwrap();
+    assert!(index.is_feature_enabled(&crab_3, "crash-if-not-sandboxed"));
+}
+
+#[test]
+fn test_vendored_source_paths() {
+    let mut index = CrateIndex::default();
+    index
+        .dir_to_pkg_id
+        .insert(PathBuf::from("/ws"), testing::pkg_id("root"));
+    index
+        .dir_to_pkg_id
+        .insert(PathBuf::from("/ws/vendor/foo"), testing::pkg_id("foo"));
+    index.vendor_dirs = vec![PathBuf::from("/ws/vendor")];
+    let path_pkg = |path: &str| {
+        index
+            .package_id_for_path(Path::new(path))
+            .map(|pkg_id| pkg_id.name_str())
+    };
+    assert_eq!(path_pkg("/ws/src/main.rs"), Some("root"));
+    assert_eq!(path_pkg("/ws/vendor/foo/src/lib.rs"), Some("foo"));
+    assert_eq!(path_pkg("/ws/vendor/windows-sys/src/lib.rs"), None);
+}
+
+#[test]
+fn test_renamed_dependency() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let write = |path: &str, contents: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    };
+    write(
+        "Cargo.toml",
+        r#"
+        [package]
+        name = "app"
+        version = "0.1.0"
+        edition = "2021"
+
+        [dependencies]
+        short = { path = "lib", package = "long-name", features = ["tls"] }
+        "#,
+    );
+    write("src/main.rs", "fn main() {}");
+    write(
+        "lib/Cargo.toml",
+        r#"
+        [package]
+        name = "long-name"
+        version = "0.1.0"
+        edition = "2021"
+
+        [features]
+        tls = []
+        "#,
+    );
+    write("lib/src/lib.rs", "");
+    write(
+        "cackle.toml",
+        r#"
+        [common]
+        version = 1
+        import_std = ["fs", "net"]
+
+        [pkg.short]
+        allow_apis = ["fs"]
+
+        [pkg.short.'cfg(feature = "tls")']
+        allow_apis = ["net"]
+        "#,
+    );
+    let index = CrateIndex::new(dir, &[]).unwrap();
+    let short = PackageName("short".into());
+    assert_eq!(
+        index
+            .newest_package_id_with_name(&short)
+            .map(|pkg_id| pkg_id.name_str()),
+        Some("long-name")
+    );
+    assert!(index.is_feature_enabled(&short, "tls"));
+    assert!(index
+        .permission_selectors
+        .contains(&PermSel::for_primary("short")));
+
+    let config = crate::config::parse_file(&dir.join("cackle.toml"), &index).unwrap();
+    let allowed: Vec<String> = config
+        .permissions
+        .get(&PermSel::for_primary("long-name"))
+        .unwrap()
+        .allow_apis
+        .iter()
+        .map(|api| api.to_string())
+        .collect();
+    assert_eq!(allowed, ["fs", "net"]);
+}
+
+#[test]
+fn test_dependency_edges() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let write = |path: &str, contents: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    };
+    let write_lib = |name: &str, extra: &str| {
+        write(
+            &format!("{name}/Cargo.toml"),
+            &format!(
+                "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n{extra}"
+            ),
+        );
+        write(&format!("{name}/src/lib.rs"), "");
+    };
+    write(
+        "Cargo.toml",
+        r#"
+        [package]
+        name = "app"
+        version = "0.1.0"
+        edition = "2021"
+
+        [dependencies]
+        short = { path = "long-name", package = "long-name", features = ["tls"] }
+
+        [build-dependencies]
+        codegen = { path = "codegen" }
+        short = { path = "long-name", package = "long-name" }
+
+        [dev-dependencies]
+        testutil = { path = "testutil" }
+        "#,
+    );
+    write("src/main.rs", "fn main() {}");
+    write("build.rs", "fn main() {}");
+    write_lib("long-name", "[features]\ntls = []\nunused = []\n");
+    write_lib("codegen", "");
+    write_lib(
+        "testutil",
+        "[dependencies]\nhelper = { path = \"../helper\" }\n",
+    );
+    write_lib("helper", "");
+
+    let index = CrateIndex::new(dir, &[]).unwrap();
+    let pkg_id = |name: &str| {
+        index
+            .newest_package_id_with_name(&PackageName(name.into()))
+            .unwrap()
+            .clone()
+    };
+    let deps: Vec<(&str, Vec<DepKind>)> = index
+        .dependencies(&pkg_id("app"))
+        .iter()
+        .map(|dep| (dep.pkg_id.name_str(), dep.kinds.iter().copied().collect()))
+        .collect();
+    assert_eq!(
+        deps,
+        vec![
+            ("codegen", vec![DepKind::Build]),
+            ("long-name", vec![DepKind::Normal, DepKind::Build]),
+            ("testutil", vec![DepKind::Dev]),
+        ]
+    );
+    assert!(index.dependencies(&pkg_id("long-name")).is_empty());
+
+    let kinds = |name: &str| -> Vec<DepKind> {
+        index
+            .dep_kinds(&pkg_id(name))
+            .unwrap()
+            .iter()
+            .copied()
+            .collect()
+    };
+    assert_eq!(kinds("app"), vec![DepKind::Normal]);
+    assert_eq!(kinds("long-name"), vec![DepKind::Normal, DepKind::Build]);
+    assert_eq!(kinds("codegen"), vec![DepKind::Build]);
+    // helper is only reached via a dev-dependency, so is only used by tests.
+    assert_eq!(kinds("helper"), vec![DepKind::Dev]);
+
+    let features: Vec<&str> = index.package_infos[&pkg_id("long-name")]
+        .features()
+        .collect();
+    assert_eq!(features, vec!["tls"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinked_source_paths() {
+    use std::os::unix::fs::symlink;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let write = |path: &str, contents: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    };
+    write(
+        "real/Cargo.toml",
+        r#"
+        [package]
+        name = "app"
+        version = "0.1.0"
+        edition = "2021"
+
+        [dependencies]
+        lib = { path = "lib" }
+        "#,
+    );
+    write("real/src/main.rs", "fn main() {}");
+    write(
+        "shared/lib/Cargo.toml",
+        r#"
+        [package]
+        name = "lib"
+        version = "0.1.0"
+        edition = "2021"
+        "#,
+    );
+    write("shared/lib/src/lib.rs", "");
+    // The workspace is reached via a symlink and the path dependency's directory is itself a
+    // symlink, so cargo metadata reports paths that differ from what rustc may report.
+    symlink(dir.join("shared/lib"), dir.join("real/lib")).unwrap();
+    symlink(dir.join("real"), dir.join("link")).unwrap();
+    symlink(dir.join("shared"), dir.join("other")).unwrap();
+
+    let index = CrateIndex::new(&dir.join("link"), &[]).unwrap();
+    let path_pkg = |path: &str| {
+        index
+            .package_id_for_path(&dir.join(path))
+            .map(|pkg_id| pkg_id.name_str())
+    };
+    assert_eq!(path_pkg("link/src/main.rs"), Some("app"));
+    assert_eq!(path_pkg("real/src/main.rs"), Some("app"));
+    assert_eq!(path_pkg("link/lib/src/lib.rs"), Some("lib"));
+    assert_eq!(path_pkg("shared/lib/src/lib.rs"), Some("lib"));
+    // A symlink that cargo didn't tell us about is only resolved for files that exist.
+    assert_eq!(path_pkg("other/lib/src/lib.rs"), Some("lib"));
+    assert_eq!(path_pkg("other/lib/src/missing.rs"), None);
 }
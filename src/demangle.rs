@@ -3,6 +3,10 @@
 //! mangled symbol and obtain the parts of symbol as references into the original string, thus
 //! avoiding heap allocation. This demangler was built experimentally based on observed mangled
 //! symbols. We almost certainly get stuff wrong.
+//!
+//! Symbols that use the v0 mangling scheme can't be split in place, since they can contain
+//! back-references and punycode. For these, we demangle with rustc-demangle, then tokenise the
+//! result in the same way as we do for names from debug info.
 
 use anyhow::anyhow;
 use anyhow::bail;
@@ -20,6 +24,8 @@ pub(crate) enum DemangleToken<'data> {
 pub(crate) struct DemangleIterator<'data> {
     outer: &'data str,
     inner: Option<&'data str>,
+    /// Set if we're tokenising a symbol that has already been demangled.
+    demangled: Option<NonMangledIterator<'data>>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -35,14 +41,81 @@ impl<'data> DemangleIterator<'data> {
             Self {
                 outer: rest,
                 inner: None,
+                demangled: None,
             }
         } else {
             Self {
                 outer: "",
                 inner: None,
+                demangled: None,
             }
         }
     }
+
+    /// Returns an iterator that provides tokens for `demangled`, which should be the output of
+    /// `demangle_v0`.
+    pub(crate) fn from_demangled(demangled: &'data str) -> Self {
+        Self {
+            outer: "",
+            inner: None,
+            demangled: Some(NonMangledIterator::new(&[], demangled)),
+        }
+    }
+}
+
+/// If `data` is a symbol that uses the v0 mangling scheme, returns it demangled, without crate
+/// disambiguators. Inherent impls are written as `<crate::Foo<u8>>::bar` by rustc-demangle. We
+/// drop the angle brackets so that we get `crate::Foo<u8>::bar`, which is what we'd get from a
+/// legacy symbol.
+pub(crate) fn demangle_v0(data: &str) -> Option<String> {
+    // On some platforms, e.g. MacOS, symbols have an extra leading underscore.
+    if !data.starts_with("_R") && !data.starts_with("__R") {
+        return None;
+    }
+    let demangled = rustc_demangle::try_demangle(data).ok()?;
+    Some(remove_inherent_impl_brackets(&format!("{demangled:#}")))
+}
+
+/// Converts `<a::B>::c` to `a::B::c`. Leaves `<a::B as c::D>::e` alone.
+fn remove_inherent_impl_brackets(demangled: &str) -> String {
+    let bytes = demangled.as_bytes();
+    let mut to_remove = Vec::new();
+    // For each open angle bracket: the offset of the bracket, whether it starts a qualified path
+    // (as opposed to generic arguments) and whether we've seen " as " at this level.
+    let mut stack: Vec<(usize, bool, bool)> = Vec::new();
+    for (i, byte) in bytes.iter().enumerate() {
+        let previous = i.checked_sub(1).map(|p| bytes[p]);
+        match byte {
+            b'<' => {
+                let qualified = !previous.is_some_and(|p| {
+                    p.is_ascii_alphanumeric() || p == b'_' || p == b':' || p == b'>'
+                });
+                stack.push((i, qualified, false));
+            }
+            b'>' if previous != Some(b'-') => {
+                if let Some((start, qualified, has_as)) = stack.pop() {
+                    if qualified && !has_as && demangled[i + 1..].starts_with("::") {
+                        to_remove.push(start);
+                        to_remove.push(i);
+                    }
+                }
+            }
+            b' ' if demangled[i..].starts_with(" as ") => {
+                if let Some(top) = stack.last_mut() {
+                    top.2 = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    if to_remove.is_empty() {
+        return demangled.to_owned();
+    }
+    demangled
+        .char_indices()
+        .filter(|(i, _)| !to_remove.contains(i))
+        .map(|(_, ch)| ch)
+        .collect()
 }
 
 /// An iterator that processes a non-mangled string and provides the same tokens as
@@ -76,6 +149,9 @@ impl<'data> Iterator for DemangleIterator<'data> {
     type Item = DemangleToken<'data>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(demangled) = self.demangled.as_mut() {
+            return demangled.next();
+        }
         if self.inner == Some("") {
             self.inner = None;
         }
@@ -279,6 +355,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_remove_inherent_impl_brackets() {
+        assert_eq!(
+            remove_inherent_impl_brackets("<lib::Foo<3>>::get::{closure#0}"),
+            "lib::Foo<3>::get::{closure#0}"
+        );
+        assert_eq!(
+            remove_inherent_impl_brackets("<alloc::string::String as core::fmt::Debug>::fmt"),
+            "<alloc::string::String as core::fmt::Debug>::fmt"
+        );
+        assert_eq!(
+            remove_inherent_impl_brackets("std::fs::read::<&str>"),
+            "std::fs::read::<&str>"
+        );
+        assert_eq!(
+            remove_inherent_impl_brackets(
+                "<<a::B as c::D>::E as f::G<fn() -> u8>>::h::<<i::J>::K>"
+            ),
+            "<<a::B as c::D>::E as f::G<fn() -> u8>>::h::<i::J::K>"
+        );
+    }
+
+    #[test]
+    fn test_v0() {
+        assert_eq!(demangle_v0("_ZN3std2fs5write17h0f72782372833d23E"), None);
+        assert_eq!(
+            demangle_v0("_RNvMs0_Csh537bOAIRKx_3libNtB5_3Bar3new").as_deref(),
+            Some("lib::Bar::new")
+        );
+        let tokens: Vec<_> = DemangleIterator::from_demangled("<lib::Foo<3>>::get").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                DemangleToken::Char('<'),
+                DemangleToken::Text("lib"),
+                DemangleToken::Text("Foo"),
+                DemangleToken::Char('<'),
+                DemangleToken::Text("3"),
+                DemangleToken::Char('>'),
+                DemangleToken::Char('>'),
+                DemangleToken::Text("get"),
+            ]
+        );
+    }
+
     #[test]
     fn test_other() {
         check(
@@ -33,47 +33,92 @@ fn parse_deps(deps_text: &str) -> Result<Vec<PathBuf>> {
     Ok(deps)
 }
 
-fn deps_path_from_rustc_args(mut args: impl Iterator<Item = String>) -> Result<Option<PathBuf>> {
-    let mut crate_name = None;
-    let mut extra = String::new();
-    let mut out_dir = None;
-    let mut emit_dep_info = false;
-    while let Some(arg) = args.next() {
-        if arg == "-C" {
-            let Some(arg) = args.next() else {
-                bail!("Missing argument to -C");
-            };
-            if let Some(rest) = arg.strip_prefix("extra-filename=") {
-                extra = rest.to_owned();
+/// Uses the supplied rustc arguments to determine where the rlib will be written. Returns None if
+/// rustc isn't producing an rlib, e.g. because it's compiling a binary.
+pub(crate) fn rlib_path_from_rustc_args(
+    args: impl Iterator<Item = String>,
+) -> Result<Option<PathBuf>> {
+    let args = OutputArgs::parse(args)?;
+    if !args.emit.split(',').any(|kind| kind == "link")
+        || !args
+            .crate_types
+            .iter()
+            .any(|crate_type| crate_type == "lib" || crate_type == "rlib")
+    {
+        return Ok(None);
+    }
+    Ok(Some(args.output_path("lib", ".rlib")?))
+}
+
+fn deps_path_from_rustc_args(args: impl Iterator<Item = String>) -> Result<Option<PathBuf>> {
+    let args = OutputArgs::parse(args)?;
+    if !args.emit.split(',').any(|kind| kind == "dep-info") {
+        return Ok(None);
+    }
+    Ok(Some(args.output_path("", ".d")?))
+}
+
+/// The rustc arguments that determine what gets written where.
+#[derive(Default)]
+struct OutputArgs {
+    crate_name: Option<String>,
+    crate_types: Vec<String>,
+    extra: String,
+    out_dir: Option<String>,
+    emit: String,
+}
+
+impl OutputArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self::default();
+        while let Some(arg) = args.next() {
+            if arg == "-C" {
+                let Some(arg) = args.next() else {
+                    bail!("Missing argument to -C");
+                };
+                if let Some(rest) = arg.strip_prefix("extra-filename=") {
+                    parsed.extra = rest.to_owned();
+                }
+            } else if arg == "--out-dir" {
+                let Some(arg) = args.next() else {
+                    bail!("Missing argument to --out-dir");
+                };
+                parsed.out_dir = Some(arg);
+            } else if arg == "--crate-name" {
+                let Some(arg) = args.next() else {
+                    bail!("Missing argument to --crate-name");
+                };
+                parsed.crate_name = Some(arg);
+            } else if arg == "--crate-type" {
+                let Some(arg) = args.next() else {
+                    bail!("Missing argument to --crate-type");
+                };
+                parsed.crate_types.extend(arg.split(',').map(str::to_owned));
+            } else if let Some(rest) = arg.strip_prefix("--emit=") {
+                parsed.emit = rest.to_owned();
             }
-        } else if arg == "--out-dir" {
-            let Some(arg) = args.next() else {
-                bail!("Missing argument to --out-dir");
-            };
-            out_dir = Some(arg);
-        } else if arg == "--crate-name" {
-            let Some(arg) = args.next() else {
-                bail!("Missing argument to --crate-name");
-            };
-            crate_name = Some(arg);
-        } else if arg.starts_with("--emit=") {
-            emit_dep_info = arg.contains("dep-info");
         }
+        Ok(parsed)
     }
-    if !emit_dep_info {
-        return Ok(None);
+
+    fn output_path(&self, prefix: &str, suffix: &str) -> Result<PathBuf> {
+        let crate_name = self
+            .crate_name
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing --crate-name"))?;
+        let out_dir = self
+            .out_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing --out-dir"))?;
+        Ok(Path::new(out_dir).join(format!("{prefix}{crate_name}{}{suffix}", self.extra)))
     }
-    let crate_name = crate_name.ok_or_else(|| anyhow!("Missing --crate-name"))?;
-    let out_dir = out_dir.ok_or_else(|| anyhow!("Missing --out-dir"))?;
-    Ok(Some(
-        Path::new(&out_dir).join(format!("{crate_name}{extra}.d")),
-    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::deps_path_from_rustc_args;
     use super::parse_deps;
+    use super::rlib_path_from_rustc_args;
     use anyhow::Result;
     use std::path::PathBuf;
 
@@ -133,6 +178,36 @@ mod tests {
         assert_eq!(deps_path(&[]).unwrap(), None);
     }
 
+    #[test]
+    fn test_rlib_path_from_rustc_args() {
+        let rlib_path = |args: &[&str]| {
+            rlib_path_from_rustc_args(
+                [
+                    "rustc",
+                    "--crate-name",
+                    "foo",
+                    "-C",
+                    "extra-filename=-0188200cb614ae3d",
+                    "--out-dir",
+                    "/t/debug/deps",
+                ]
+                .iter()
+                .chain(args)
+                .map(|s| s.to_string()),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            rlib_path(&["--crate-type", "lib", "--emit=dep-info,metadata,link"]),
+            Some(PathBuf::from("/t/debug/deps/libfoo-0188200cb614ae3d.rlib"))
+        );
+        assert_eq!(
+            rlib_path(&["--crate-type", "lib", "--emit=dep-info,metadata"]),
+            None
+        );
+        assert_eq!(rlib_path(&["--crate-type", "bin", "--emit=link"]), None);
+    }
+
     fn path_strings(input: &[PathBuf]) -> Vec<&str> {
         input.iter().filter_map(|path| path.to_str()).collect()
     }
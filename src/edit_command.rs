@@ -0,0 +1,175 @@
+//! Non-interactive edits to cackle.toml requested from the command line. These go through the same
+//! config editing code as the interactive UI, so the resulting file is formatted the same way as it
+//! would be had the user accepted the equivalent fix in the UI.
+
+use crate::config::permissions::PermSel;
+use crate::config::permissions::PermissionScope;
+use crate::config::ApiName;
+use crate::config::PackageName;
+use crate::config::SandboxKind;
+use crate::config_editor::AllowApis;
+use crate::config_editor::AllowUnsafe;
+use crate::config_editor::ConfigEditor;
+use crate::config_editor::Edit;
+use crate::config_editor::EditOpts;
+use crate::config_editor::SandboxAllowNetwork;
+use crate::config_editor::SetPkgSandboxKind;
+use crate::crate_index::CrateIndex;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use clap::Subcommand;
+use std::path::Path;
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum EditCommand {
+    /// Allow a package to use one or more APIs.
+    AllowApi(AllowApiOptions),
+
+    /// Allow a package to use unsafe code.
+    AllowUnsafe(AllowUnsafeOptions),
+
+    /// Configure the sandbox used when running a package's build script.
+    Sandbox(SandboxOptions),
+}
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct AllowApiOptions {
+    /// The package to grant permissions to, optionally with a selector. e.g. `foo`, `foo.build` or
+    /// `foo.from.test`.
+    selector: PermSel,
+
+    /// The APIs to allow.
+    #[clap(required = true)]
+    apis: Vec<String>,
+
+    /// A comment to attach to the added APIs.
+    #[clap(long)]
+    comment: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct AllowUnsafeOptions {
+    /// The package to allow unsafe code in, optionally with a selector. e.g. `foo` or `foo.test`.
+    selector: PermSel,
+
+    /// A comment to attach to the edit.
+    #[clap(long)]
+    comment: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct SandboxOptions {
+    /// The package whose build script should be configured.
+    package: String,
+
+    /// What kind of sandbox to run the build script in.
+    #[clap(long, value_enum)]
+    kind: Option<SandboxKind>,
+
+    /// Allow the build script to access the network.
+    #[clap(long)]
+    allow_network: bool,
+
+    /// A comment to attach to the edit.
+    #[clap(long)]
+    comment: Option<String>,
+}
+
+impl EditCommand {
+    /// Applies the requested edit to the config file at `config_path`. The config file must already
+    /// exist. The edit is only written if the resulting config is valid.
+    pub(crate) fn apply(&self, config_path: &Path, crate_index: &CrateIndex) -> Result<()> {
+        if !config_path.exists() {
+            bail!(
+                "{} doesn't exist. Run `cargo acl init` to create it",
+                config_path.display()
+            );
+        }
+        let config = crate::config::parse_file(config_path, crate_index)?;
+        let perm_sel = self.perm_sel();
+        let edits = self.edits()?;
+        if crate_index
+            .newest_package_id_with_name(&perm_sel.package_name)
+            .is_none()
+        {
+            bail!(
+                "Package `{}` isn't in the dependency tree",
+                perm_sel.package_name
+            );
+        }
+        if let EditCommand::AllowApi(options) = self {
+            for api in &options.apis {
+                config.get_api_config(&ApiName::new(api))?;
+            }
+        }
+
+        let original = std::fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        let mut editor = ConfigEditor::from_toml_string(&original)?;
+        let opts = EditOpts {
+            comment: self.comment(),
+        };
+        for edit in &edits {
+            edit.apply(&mut editor, &opts)?;
+        }
+        crate::fs::write_atomic(config_path, &editor.to_toml())?;
+        // Make sure that the edit didn't leave us with an invalid config. e.g. if the user requested
+        // a selector that isn't permitted in combination with the edit.
+        if let Err(error) = crate::config::parse_file(config_path, crate_index) {
+            crate::fs::write_atomic(config_path, &original)?;
+            return Err(error.context("Edit would produce an invalid config"));
+        }
+        for edit in &edits {
+            println!("{}", edit.title());
+        }
+        Ok(())
+    }
+
+    fn perm_sel(&self) -> PermSel {
+        match self {
+            EditCommand::AllowApi(options) => options.selector.clone(),
+            EditCommand::AllowUnsafe(options) => options.selector.clone(),
+            EditCommand::Sandbox(options) => PermSel {
+                package_name: PackageName(options.package.as_str().into()),
+                scope: PermissionScope::Build,
+            },
+        }
+    }
+
+    fn comment(&self) -> Option<String> {
+        match self {
+            EditCommand::AllowApi(options) => options.comment.clone(),
+            EditCommand::AllowUnsafe(options) => options.comment.clone(),
+            EditCommand::Sandbox(options) => options.comment.clone(),
+        }
+    }
+
+    fn edits(&self) -> Result<Vec<Box<dyn Edit>>> {
+        let perm_sel = self.perm_sel();
+        let mut edits: Vec<Box<dyn Edit>> = Vec::new();
+        match self {
+            EditCommand::AllowApi(options) => edits.push(Box::new(AllowApis {
+                perm_sel,
+                apis: options.apis.iter().map(|api| ApiName::new(api)).collect(),
+            })),
+            EditCommand::AllowUnsafe(_) => edits.push(Box::new(AllowUnsafe { perm_sel })),
+            EditCommand::Sandbox(options) => {
+                if let Some(kind) = options.kind {
+                    edits.push(Box::new(SetPkgSandboxKind {
+                        perm_sel: perm_sel.clone(),
+                        kind,
+                    }));
+                }
+                if options.allow_network {
+                    edits.push(Box::new(SandboxAllowNetwork { perm_sel }));
+                }
+                if edits.is_empty() {
+                    bail!("Nothing to do. Specify --kind and/or --allow-network");
+                }
+            }
+        }
+        Ok(edits)
+    }
+}
@@ -1,7 +1,12 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum AppEvent {
     /// Shutdown in progress. The UI should close.
     Shutdown,
     /// New problems have been added to the problem store.
     ProblemsAdded,
+    /// The build progress or output has changed. Only sent if the UI asked to be notified.
+    ProgressChanged,
+    /// An event from the terminal, such as a key press. Only sent to the full terminal UI.
+    #[cfg(feature = "ui")]
+    Input(crossterm::event::Event),
 }
@@ -1,5 +1,7 @@
 use anyhow::Context;
 use anyhow::Result;
+use sha2::Digest;
+use sha2::Sha256;
 use std::path::Path;
 
 /// Writes `contents` to `path`. The write is first done to a temporary filename then renamed to
@@ -23,6 +25,16 @@ pub(crate) fn read_to_string(path: &Path) -> Result<String> {
     std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
 }
 
+/// Returns the SHA-256 of the file at `path`, as lowercase hex.
+pub(crate) fn sha256(path: &Path) -> Result<String> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    Ok(Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
 pub(crate) fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
     let path = path.as_ref();
     std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
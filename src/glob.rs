@@ -0,0 +1,62 @@
+//! Minimal glob matching for paths in config. `*` matches any sequence of characters within a
+//! single path component and `**` matches any number of whole components, including none.
+
+use std::path::Path;
+
+/// Returns whether `path` matches `pattern`. Both are treated as `/`-separated relative paths.
+pub(crate) fn matches(pattern: &str, path: &Path) -> bool {
+    let Some(path) = path.to_str() else {
+        return false;
+    };
+    let pattern: Vec<&str> = pattern.split('/').filter(|p| !p.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    matches_components(&pattern, &path)
+}
+
+fn matches_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_components(rest, &path[skip..])),
+        Some((first, rest)) => path.split_first().is_some_and(|(component, path_rest)| {
+            matches_component(first.as_bytes(), component.as_bytes())
+                && matches_components(rest, path_rest)
+        }),
+    }
+}
+
+fn matches_component(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| matches_component(rest, &text[skip..])),
+        Some((ch, rest)) => text.split_first().is_some_and(|(text_ch, text_rest)| {
+            ch == text_ch && matches_component(rest, text_rest)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[track_caller]
+    fn check(pattern: &str, path: &str, expected: bool) {
+        assert_eq!(super::matches(pattern, Path::new(path)), expected);
+    }
+
+    #[test]
+    fn glob_matching() {
+        check("src/lib.rs", "src/lib.rs", true);
+        check("src/lib.rs", "src/main.rs", false);
+        check("src/*.rs", "src/main.rs", true);
+        check("src/*.rs", "src/a/main.rs", false);
+        check("src/testing/**", "src/testing/helpers.rs", true);
+        check("src/testing/**", "src/testing/a/b/helpers.rs", true);
+        check("src/testing/**", "src/other.rs", false);
+        check("**/tests.rs", "tests.rs", true);
+        check("**/tests.rs", "src/a/tests.rs", true);
+        check("src/**/mod.rs", "src/mod.rs", true);
+        check("src/**/mod.rs", "src/a/b/mod.rs", true);
+        check("src/**/mod.rs", "src/a/b/lib.rs", false);
+        check("src/t*g/*.rs", "src/testing/a.rs", true);
+    }
+}
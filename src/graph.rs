@@ -0,0 +1,315 @@
+//! Support for dumping the graph of references between symbols and between crates that we observed
+//! while analysing the build.
+
+use clap::Parser;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct GraphOptions {
+    /// The format of the output.
+    #[clap(long, value_enum, default_value_t = GraphFormat::Dot)]
+    format: GraphFormat,
+
+    /// Output references between individual symbols rather than between crates.
+    #[clap(long)]
+    symbols: bool,
+
+    /// Only include references to or from the specified package. Symbols that weren't attributed
+    /// to a package, such as those from the standard library, are matched by crate name. May be
+    /// specified multiple times.
+    #[clap(long = "crate")]
+    crates: Vec<String>,
+
+    /// Only include references to code that uses the specified permission (API). May be specified
+    /// multiple times.
+    #[clap(long = "permission")]
+    permissions: Vec<String>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum GraphFormat {
+    /// Graphviz DOT format.
+    #[default]
+    Dot,
+    /// A JSON object with `nodes` and `edges`.
+    Json,
+}
+
+/// A graph of references between symbols, annotated with what package each symbol came from and
+/// what APIs each referenced symbol matched.
+#[derive(Default, Debug)]
+pub(crate) struct Graph {
+    nodes: BTreeMap<String, GraphNode>,
+    edges: BTreeMap<(String, String), BTreeSet<String>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GraphNode {
+    /// The package that the symbol was attributed to, if any. Symbols from the standard library
+    /// won't have a package.
+    pub(crate) package: Option<String>,
+
+    /// The name of the crate that the symbol was attributed to. This is the package name where
+    /// there is one, otherwise it's derived from the symbol.
+    pub(crate) crate_name: Option<String>,
+}
+
+impl GraphNode {
+    /// Returns the name of the package that the symbol was attributed to, or if there isn't one,
+    /// the name of its crate.
+    fn name(&self) -> Option<&str> {
+        self.package.as_deref().or(self.crate_name.as_deref())
+    }
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    nodes: Vec<JsonNode<'a>>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonNode<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonEdge<'a> {
+    from: &'a str,
+    to: &'a str,
+    apis: &'a BTreeSet<String>,
+}
+
+impl Graph {
+    /// Records a reference from `from` to `to`. `apis` are the APIs that matched `to`.
+    pub(crate) fn add_reference(
+        &mut self,
+        from: (String, GraphNode),
+        to: (String, GraphNode),
+        apis: impl Iterator<Item = String>,
+    ) {
+        if from.0 == to.0 {
+            return;
+        }
+        let key = (from.0.clone(), to.0.clone());
+        self.nodes.entry(from.0).or_insert(from.1);
+        self.nodes.entry(to.0).or_insert(to.1);
+        self.edges.entry(key).or_default().extend(apis);
+    }
+
+    pub(crate) fn merge(&mut self, other: Graph) {
+        for (name, node) in other.nodes {
+            self.nodes.entry(name).or_insert(node);
+        }
+        for (key, apis) in other.edges {
+            self.edges.entry(key).or_default().extend(apis);
+        }
+    }
+
+    /// Returns a graph with a node per crate rather than per symbol.
+    fn by_crate(&self) -> Graph {
+        let mut out = Graph::default();
+        for ((from, to), apis) in &self.edges {
+            let (Some(from), Some(to)) = (self.crate_node(from), self.crate_node(to)) else {
+                continue;
+            };
+            out.add_reference(from, to, apis.iter().cloned());
+        }
+        out
+    }
+
+    fn crate_node(&self, symbol_name: &str) -> Option<(String, GraphNode)> {
+        let node = self.nodes.get(symbol_name)?;
+        Some((node.name()?.to_owned(), node.clone()))
+    }
+
+    /// Returns a copy of this graph, retaining only those edges that match `options`.
+    fn filtered(&self, options: &GraphOptions) -> Graph {
+        let mut out = Graph::default();
+        for ((from, to), apis) in &self.edges {
+            if !options.permissions.is_empty()
+                && !apis.iter().any(|api| options.permissions.contains(api))
+            {
+                continue;
+            }
+            let from_node = &self.nodes[from];
+            let to_node = &self.nodes[to];
+            if !options.crates.is_empty()
+                && !options.crates.iter().any(|c| {
+                    from_node.name() == Some(c.as_str()) || to_node.name() == Some(c.as_str())
+                })
+            {
+                continue;
+            }
+            out.add_reference(
+                (from.clone(), from_node.clone()),
+                (to.clone(), to_node.clone()),
+                apis.iter().cloned(),
+            );
+        }
+        out
+    }
+
+    pub(crate) fn print(&self, options: &GraphOptions) {
+        let mut graph = self.filtered(options);
+        if !options.symbols {
+            graph = graph.by_crate();
+        }
+        match options.format {
+            GraphFormat::Dot => print!("{}", graph.to_dot()),
+            GraphFormat::Json => println!("{}", graph.to_json()),
+        }
+    }
+
+    fn to_dot(&self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph references {\n");
+        for (name, node) in &self.nodes {
+            let _ = write!(out, "    {}", dot_quote(name));
+            if let Some(package) = node.package.as_ref().filter(|p| *p != name) {
+                let _ = write!(out, " [tooltip={}]", dot_quote(package));
+            }
+            out.push_str(";\n");
+        }
+        for ((from, to), apis) in &self.edges {
+            let _ = write!(out, "    {} -> {}", dot_quote(from), dot_quote(to));
+            if !apis.is_empty() {
+                let label = apis.iter().cloned().collect::<Vec<_>>().join(", ");
+                let _ = write!(out, " [label={}, color=red]", dot_quote(&label));
+            }
+            out.push_str(";\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let graph = JsonGraph {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|(name, node)| JsonNode {
+                    name,
+                    package: node.package.as_deref(),
+                })
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .map(|((from, to), apis)| JsonEdge { from, to, apis })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&graph).unwrap()
+    }
+}
+
+fn dot_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Graph;
+    use super::GraphNode;
+    use super::GraphOptions;
+
+    fn node(name: &str, crate_name: Option<&str>) -> (String, GraphNode) {
+        (
+            name.to_owned(),
+            GraphNode {
+                package: crate_name.map(str::to_owned),
+                crate_name: crate_name.map(str::to_owned),
+            },
+        )
+    }
+
+    fn test_graph() -> Graph {
+        let mut graph = Graph::default();
+        graph.add_reference(
+            node("a::main", Some("a")),
+            node("b::read_config", Some("b")),
+            std::iter::empty(),
+        );
+        graph.add_reference(
+            node("b::read_config", Some("b")),
+            node("std::fs::read", None),
+            std::iter::once("fs".to_owned()),
+        );
+        graph.add_reference(
+            node("a::main", Some("a")),
+            node("a::helper", Some("a")),
+            std::iter::empty(),
+        );
+        graph
+    }
+
+    #[test]
+    fn by_crate() {
+        let graph = test_graph().by_crate();
+        let edges: Vec<_> = graph.edges.keys().cloned().collect();
+        assert_eq!(edges, vec![("a".to_owned(), "b".to_owned())]);
+    }
+
+    #[test]
+    fn filter_by_permission() {
+        let options = GraphOptions {
+            symbols: true,
+            permissions: vec!["fs".to_owned()],
+            ..GraphOptions::default()
+        };
+        let graph = test_graph().filtered(&options);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph references {\n    \"b::read_config\" [tooltip=\"b\"];\n    \
+             \"std::fs::read\";\n    \"b::read_config\" -> \"std::fs::read\" \
+             [label=\"fs\", color=red];\n}\n"
+        );
+    }
+
+    #[test]
+    fn filter_by_crate() {
+        let options = GraphOptions {
+            crates: vec!["a".to_owned()],
+            ..GraphOptions::default()
+        };
+        let graph = test_graph().filtered(&options);
+        assert_eq!(graph.edges.len(), 2);
+    }
+
+    #[test]
+    fn filter_by_package_name() {
+        let mut graph = Graph::default();
+        let node = |name: &str, package: Option<&str>, crate_name: &str| {
+            (
+                name.to_owned(),
+                GraphNode {
+                    package: package.map(str::to_owned),
+                    crate_name: Some(crate_name.to_owned()),
+                },
+            )
+        };
+        graph.add_reference(
+            node("my_app::main", Some("my-app"), "my_app"),
+            node("std::fs::read", None, "std"),
+            std::iter::once("fs".to_owned()),
+        );
+        let filtered = |name: &str| {
+            graph
+                .filtered(&GraphOptions {
+                    crates: vec![name.to_owned()],
+                    ..GraphOptions::default()
+                })
+                .edges
+                .len()
+        };
+        assert_eq!(filtered("my-app"), 1);
+        assert_eq!(filtered("std"), 1);
+        assert_eq!(filtered("my_app"), 0);
+    }
+}
@@ -0,0 +1,69 @@
+//! Analyses rust crates and their dependent crates to see what categories of APIs and language
+//! features are used.
+
+// Unsafe code is only permitted where we need to make syscalls that aren't otherwise available.
+// Currently that's:
+// * The Landlock and namespace sandboxes.
+// * Resource limits for sandboxed processes, which need `setrlimit`, `setpgid` and `kill`.
+// * Watching for changed files with inotify.
+// * Pointing stdout at stderr in LSP mode, so that stray output can't corrupt the protocol.
+// * Getting random bytes on Windows, for authenticating connections from subprocesses.
+#![deny(unsafe_code)]
+#![cfg_attr(not(feature = "ui"), allow(dead_code, unused_variables))]
+#![allow(unknown_lints)]
+#![allow(clippy::assigning_clones)]
+#![allow(clippy::needless_borrows_for_generic_args)]
+
+pub mod analysis;
+mod attestation;
+mod build_output;
+mod build_progress;
+mod build_script_checker;
+mod checker;
+mod ci;
+mod cli;
+mod colour;
+mod config;
+mod config_editor;
+mod config_validation;
+mod cowarc;
+mod crate_index;
+mod demangle;
+mod deps;
+mod edit_command;
+pub(crate) mod events;
+pub(crate) mod fs;
+mod glob;
+mod graph;
+pub(crate) mod link_info;
+mod lint_config;
+pub(crate) mod location;
+mod logging;
+mod lsp;
+mod names;
+mod outcome;
+pub(crate) mod problem;
+pub(crate) mod problem_store;
+mod proxy;
+mod replay;
+mod report;
+mod rust_std;
+mod sandbox;
+mod sarif;
+mod summary;
+pub(crate) mod symbol;
+mod symbol_graph;
+mod timing;
+mod tmpdir;
+mod ui;
+mod unsafe_checker;
+mod watch;
+
+pub use cli::cli_main;
+
+const _CHECK_OS: () = if cfg!(all(
+    not(target_os = "linux"),
+    not(feature = "unsupported-os")
+)) {
+    panic!("Sorry, only Linux is currently supported. See PORTING.md");
+};
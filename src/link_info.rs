@@ -1,3 +1,4 @@
+use crate::crate_index::CrateKind;
 use crate::crate_index::CrateSel;
 use anyhow::bail;
 use anyhow::Result;
@@ -7,6 +8,14 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+/// The name of the environment variable via which our rustc wrapper tells our linker wrapper which
+/// platform the output is being built for.
+pub(crate) const PLATFORM_ENV: &str = "CACKLE_PLATFORM";
+
+/// The name of the environment variable via which our rustc wrapper tells our linker wrapper which
+/// rustc invocation it was run from.
+pub(crate) const RUSTC_INVOCATION_ENV: &str = "CACKLE_RUSTC_INVOCATION";
+
 /// Information about a linker invocation.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub(crate) struct LinkInfo {
@@ -14,6 +23,80 @@ pub(crate) struct LinkInfo {
     pub(crate) object_paths: Vec<PathBuf>,
     pub(crate) output_file: Arc<Path>,
     is_shared: bool,
+    #[serde(default)]
+    pub(crate) platform: Platform,
+    /// The rustc invocation that ran the linker. A single rustc invocation may link several
+    /// outputs, e.g. if a package has multiple crate types.
+    #[serde(default)]
+    pub(crate) rustc_invocation: RustcInvocationId,
+}
+
+/// Identifies a run of our rustc wrapper. Used to match linker invocations with the rustc
+/// completion that follows them, since several crates with the same `CrateSel`, such as the
+/// binaries of a package, may be compiled concurrently.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Hash, Default)]
+pub(crate) struct RustcInvocationId(u64);
+
+impl RustcInvocationId {
+    /// Returns a new ID. Process IDs can be reused, so we include the current time.
+    pub(crate) fn new() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or_default();
+        Self((u64::from(std::process::id()) << 32) | u64::from(nanos))
+    }
+
+    pub(crate) fn to_env_value(self) -> String {
+        self.0.to_string()
+    }
+
+    fn from_env() -> Self {
+        Self(
+            std::env::var(RUSTC_INVOCATION_ENV)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Whether a crate is being built to run on the host, as is the case for build scripts and proc
+/// macros, or on the target. When cross-compiling, the same package may be built for both, so
+/// it's not enough to identify outputs by their `CrateSel`.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Hash, Default)]
+pub(crate) enum Platform {
+    Host,
+    #[default]
+    Target,
+}
+
+impl Platform {
+    /// Determines the platform for a rustc invocation from its arguments.
+    pub(crate) fn from_rustc_args<S: AsRef<str>>(crate_sel: &CrateSel, args: &[S]) -> Self {
+        let is_proc_macro = args
+            .windows(2)
+            .any(|pair| pair[0].as_ref() == "--crate-type" && pair[1].as_ref() == "proc-macro");
+        if is_proc_macro || crate_sel.kind == CrateKind::BuildScript {
+            Platform::Host
+        } else {
+            Platform::Target
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Platform::Host => "host",
+            Platform::Target => "target",
+        }
+    }
+
+    fn from_env() -> Self {
+        match std::env::var(PLATFORM_ENV).as_deref() {
+            Ok("host") => Platform::Host,
+            _ => Platform::Target,
+        }
+    }
 }
 
 impl LinkInfo {
@@ -29,6 +112,8 @@ impl LinkInfo {
             object_paths,
             output_file: get_output_file()?,
             is_shared: get_is_shared(),
+            platform: Platform::from_env(),
+            rustc_invocation: RustcInvocationId::from_env(),
         })
     }
 
@@ -64,7 +149,7 @@ fn get_is_shared() -> bool {
 }
 
 fn has_supported_extension(path: &Path) -> bool {
-    const EXTENSIONS: &[&str] = &["rlib", "o"];
+    const EXTENSIONS: &[&str] = &["rlib", "o", "a"];
     path.extension()
         .and_then(|ext| ext.to_str())
         .is_some_and(|ext| EXTENSIONS.contains(&ext))
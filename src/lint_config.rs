@@ -0,0 +1,114 @@
+//! Support for the `lint-config` subcommand, which reports (and optionally removes) parts of the
+//! config that are no longer needed. e.g. APIs that are allowed but not used and config for
+//! packages that are no longer in the dependency tree.
+
+use crate::config::Config;
+use crate::config_editor;
+use crate::config_editor::ConfigEditor;
+use crate::problem::Problem;
+use crate::problem::ProblemList;
+use anyhow::Result;
+use clap::Parser;
+use std::path::Path;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct LintConfigOptions {
+    /// Remove unused config rather than just reporting it.
+    #[clap(long)]
+    pub(crate) autofix: bool,
+}
+
+/// Returns whether `problem` indicates that some part of the config isn't needed.
+pub(crate) fn is_lint(problem: &Problem) -> bool {
+    matches!(
+        problem,
+        Problem::UnusedAllowApi(..)
+            | Problem::UnusedPackageConfig(..)
+            | Problem::UnusedSandboxConfiguration(..)
+    )
+}
+
+/// Applies the first available fix for each lint in `problems` to the config file at
+/// `config_path`. Returns the titles of the edits that were applied.
+pub(crate) fn autofix(
+    config_path: &Path,
+    config: &Config,
+    problems: &ProblemList,
+) -> Result<Vec<String>> {
+    let mut editor = ConfigEditor::from_file(config_path)?;
+    let mut applied = Vec::new();
+    for problem in problems {
+        if !is_lint(problem) {
+            continue;
+        }
+        let fixes = config_editor::fixes_for_problem(problem, config);
+        let Some(fix) = fixes.first() else {
+            continue;
+        };
+        fix.apply(&mut editor, &Default::default())?;
+        applied.push(format!("{}: {problem}", fix.title()));
+    }
+    if !applied.is_empty() {
+        editor.write(config_path)?;
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::permissions::PermSel;
+    use crate::config::ApiName;
+    use crate::problem::Problem;
+    use crate::problem::ProblemList;
+    use crate::problem::UnusedAllowApi;
+    use indoc::indoc;
+
+    #[test]
+    fn autofix_removes_unused() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("cackle.toml");
+        let initial = indoc! {r#"
+            [api.fs]
+            include = ["std::fs"]
+
+            [api.net]
+            include = ["std::net"]
+
+            [pkg.crab1]
+            allow_apis = [
+                "fs",
+                "net",
+            ]
+
+            [pkg.crab2]
+            allow_unsafe = true
+        "#};
+        std::fs::write(&config_path, initial).unwrap();
+        let config = crate::config::testing::parse(initial).unwrap();
+        let mut problems = ProblemList::default();
+        problems.push(Problem::UnusedAllowApi(UnusedAllowApi {
+            perm_sel: PermSel::for_primary("crab1"),
+            apis: vec![ApiName::from("net")],
+        }));
+        problems.push(Problem::UnusedPackageConfig(PermSel::for_primary("crab2")));
+        problems.push(Problem::new("Not a lint"));
+
+        let applied = super::autofix(&config_path, &config, &problems).unwrap();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(
+            std::fs::read_to_string(&config_path).unwrap(),
+            indoc! {r#"
+                [api.fs]
+                include = ["std::fs"]
+
+                [api.net]
+                include = ["std::net"]
+
+                [pkg.crab1]
+                allow_apis = [
+                    "fs",
+                ]
+            "#}
+        );
+    }
+}
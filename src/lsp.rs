@@ -0,0 +1,438 @@
+//! Support for the `lsp` subcommand, which runs a language server on stdin and stdout so that
+//! editors can show where crates use APIs that they aren't permitted to use. We recheck whenever the
+//! editor saves a file. Cargo only rebuilds what changed, so only affected crates get rechecked.
+
+use crate::crate_index::CrateIndex;
+use crate::problem::Problem;
+use crate::problem::Severity;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+mod transport;
+
+/// How long to wait after a file is saved for further saves before rechecking.
+pub(crate) const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// JSON-RPC error code for requests that we don't support.
+const METHOD_NOT_FOUND: i32 = -32601;
+
+pub(crate) struct Server {
+    output: Arc<Mutex<File>>,
+    events: Receiver<Event>,
+    /// The diagnostics that the editor is currently showing, keyed by file.
+    published: BTreeMap<PathBuf, Vec<Diagnostic>>,
+}
+
+enum Event {
+    Saved(PathBuf),
+    Exit,
+}
+
+/// What a build will have rechecked. Diagnostics for anything else are kept from previous builds.
+pub(crate) enum Rechecked {
+    Everything,
+    Saved {
+        files: Vec<PathBuf>,
+        /// The packages containing `files`.
+        packages: BTreeSet<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Diagnostic {
+    line: u32,
+    column: Option<u32>,
+    severity: Severity,
+    message: String,
+    /// The package whose code the diagnostic is for.
+    package: String,
+}
+
+impl Server {
+    /// Takes over stdin and stdout and starts responding to requests from the editor.
+    pub(crate) fn start() -> Result<Self> {
+        let output = Arc::new(Mutex::new(transport::take_stdout()?));
+        let (event_sender, events) = std::sync::mpsc::channel();
+        std::thread::Builder::new().name("LSP".to_owned()).spawn({
+            let output = output.clone();
+            move || {
+                if let Err(error) = read_messages(&output, &event_sender) {
+                    eprintln!("LSP error: {error:#}");
+                }
+                let _ = event_sender.send(Event::Exit);
+            }
+        })?;
+        Ok(Self {
+            output,
+            events,
+            published: BTreeMap::new(),
+        })
+    }
+
+    /// Blocks until the editor saves at least one file, then continues to wait until no further
+    /// files have been saved for `debounce`. Returns the paths that were saved, or `None` if the
+    /// editor asked us to exit.
+    pub(crate) fn wait_for_saves(&self, debounce: Duration) -> Option<Vec<PathBuf>> {
+        let mut saved = Vec::new();
+        let mut event = self.events.recv().ok()?;
+        loop {
+            match event {
+                Event::Saved(path) => saved.push(path),
+                Event::Exit => return None,
+            }
+            event = match self.events.recv_timeout(debounce) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return None,
+            };
+        }
+        saved.sort();
+        saved.dedup();
+        Some(saved)
+    }
+
+    /// Publishes diagnostics for `problems`, which were found by a build. Problems that don't
+    /// relate to a location in the source are shown as messages instead.
+    pub(crate) fn publish(
+        &mut self,
+        problems: &[(Severity, &Problem)],
+        root: &Path,
+        rechecked: &Rechecked,
+    ) -> Result<()> {
+        let mut found = Vec::new();
+        for &(severity, problem) in problems {
+            let diagnostics = diagnostics(problem, severity, root);
+            if diagnostics.is_empty() {
+                self.show_message(severity, &problem.to_string())?;
+            }
+            found.extend(diagnostics);
+        }
+        let merged = merge(&self.published, found, rechecked);
+        let paths: BTreeSet<&PathBuf> = self.published.keys().chain(merged.keys()).collect();
+        for path in paths {
+            let diagnostics = merged.get(path);
+            if self.published.get(path) == diagnostics {
+                continue;
+            }
+            let diagnostics: Vec<Value> = diagnostics
+                .into_iter()
+                .flatten()
+                .map(Diagnostic::to_json)
+                .collect();
+            self.send(&json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": {"uri": uri_from_path(path), "diagnostics": diagnostics},
+            }))?;
+        }
+        self.published = merged;
+        Ok(())
+    }
+
+    pub(crate) fn show_message(&self, severity: Severity, message: &str) -> Result<()> {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "method": "window/showMessage",
+            "params": {"type": lsp_severity(severity), "message": message},
+        }))
+    }
+
+    fn send(&self, message: &Value) -> Result<()> {
+        transport::write_message(&mut *self.output.lock().unwrap(), message)
+    }
+}
+
+impl Rechecked {
+    /// Returns what will be rechecked after `saved` were saved. Saving the config means that all
+    /// crates will be rechecked.
+    pub(crate) fn new(saved: &[PathBuf], config_path: &Path, crate_index: &CrateIndex) -> Self {
+        if saved.iter().any(|path| path == config_path) {
+            return Rechecked::Everything;
+        }
+        Rechecked::Saved {
+            files: saved.to_owned(),
+            packages: saved
+                .iter()
+                .filter_map(|path| crate_index.package_id_for_path(path))
+                .map(|pkg_id| pkg_id.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl Diagnostic {
+    fn to_json(&self) -> Value {
+        // LSP positions are zero-based, whereas ours are one-based. If we don't know the column, we
+        // cover the whole line.
+        let line = self.line.saturating_sub(1);
+        let range = match self.column {
+            Some(column) => {
+                let position = json!({"line": line, "character": column.saturating_sub(1)});
+                json!({"start": position, "end": position})
+            }
+            None => json!({
+                "start": {"line": line, "character": 0},
+                "end": {"line": line + 1, "character": 0},
+            }),
+        };
+        json!({
+            "range": range,
+            "severity": lsp_severity(self.severity),
+            "source": "cackle",
+            "message": self.message,
+        })
+    }
+}
+
+/// Reads messages from the editor until it asks us to exit or closes stdin.
+fn read_messages(output: &Mutex<File>, events: &Sender<Event>) -> Result<()> {
+    let send = |message: Value| transport::write_message(&mut *output.lock().unwrap(), &message);
+    let mut input = std::io::stdin().lock();
+    while let Some(message) = transport::read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        match (method, message.get("id")) {
+            ("initialize", Some(id)) => send(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": {
+                    "capabilities": {"textDocumentSync": {"save": true}},
+                    "serverInfo": {"name": "cackle", "version": env!("CARGO_PKG_VERSION")},
+                },
+            }))?,
+            ("shutdown", Some(id)) => send(json!({"jsonrpc": "2.0", "id": id, "result": null}))?,
+            ("exit", _) => return Ok(()),
+            ("textDocument/didSave", None) => {
+                if let Some(path) = message["params"]["textDocument"]["uri"]
+                    .as_str()
+                    .and_then(path_from_uri)
+                {
+                    let _ = events.send(Event::Saved(path));
+                }
+            }
+            ("", _) => {
+                // A response to a request. We don't send any requests, so we don't expect these.
+            }
+            (_, Some(id)) => send(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {
+                    "code": METHOD_NOT_FOUND,
+                    "message": format!("Unsupported method `{method}`"),
+                },
+            }))?,
+            // Other notifications, such as files being opened, don't affect us.
+            (_, None) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Returns a diagnostic for each place where `problem` says that an API was used.
+fn diagnostics(problem: &Problem, severity: Severity, root: &Path) -> Vec<(PathBuf, Diagnostic)> {
+    let (api_usages, referenced_pkg_id) = match problem {
+        Problem::DisallowedApiUsage(api_usages) => (api_usages, None),
+        Problem::OffTreeApiUsage(off_tree) => (&off_tree.usages, Some(&off_tree.referenced_pkg_id)),
+        _ => return Vec::new(),
+    };
+    api_usages
+        .usages
+        .iter()
+        .map(|usage| {
+            let location = &usage.source_location;
+            let message = match referenced_pkg_id {
+                None => format!(
+                    "`{}` is in the `{}` API, which `{}` isn't permitted to use",
+                    usage.to_name,
+                    api_usages.api_name,
+                    api_usages.perm_sel()
+                ),
+                Some(referenced_pkg_id) => format!(
+                    "`{}` is in the `{}` API and comes from `{referenced_pkg_id}`, which isn't a \
+                     dependency of `{}`",
+                    usage.to_name,
+                    api_usages.api_name,
+                    api_usages.perm_sel()
+                ),
+            };
+            let diagnostic = Diagnostic {
+                line: location.line(),
+                column: location.column(),
+                severity,
+                message,
+                package: api_usages.pkg_id.to_string(),
+            };
+            (root.join(location.filename()), diagnostic)
+        })
+        .collect()
+}
+
+/// Combines newly `found` diagnostics with those `previous` published. Previous diagnostics are
+/// dropped if they were rechecked, since if they still applied, they'd have been found again. A
+/// package is rechecked if it was saved, or if we found diagnostics for it, since then whatever it
+/// was linked into was rechecked.
+fn merge(
+    previous: &BTreeMap<PathBuf, Vec<Diagnostic>>,
+    found: Vec<(PathBuf, Diagnostic)>,
+    rechecked: &Rechecked,
+) -> BTreeMap<PathBuf, Vec<Diagnostic>> {
+    let mut merged: BTreeMap<PathBuf, Vec<Diagnostic>> = BTreeMap::new();
+    if let Rechecked::Saved { files, packages } = rechecked {
+        let found_packages: BTreeSet<&str> = found
+            .iter()
+            .map(|(_, diagnostic)| diagnostic.package.as_str())
+            .collect();
+        for (path, diagnostics) in previous {
+            if files.contains(path) {
+                continue;
+            }
+            for diagnostic in diagnostics {
+                if !packages.contains(&diagnostic.package)
+                    && !found_packages.contains(diagnostic.package.as_str())
+                {
+                    merged
+                        .entry(path.clone())
+                        .or_default()
+                        .push(diagnostic.clone());
+                }
+            }
+        }
+    }
+    for (path, diagnostic) in found {
+        merged.entry(path).or_default().push(diagnostic);
+    }
+    for diagnostics in merged.values_mut() {
+        diagnostics.sort();
+        diagnostics.dedup();
+    }
+    merged
+}
+
+fn lsp_severity(severity: Severity) -> u32 {
+    match severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Info => 3,
+    }
+}
+
+fn uri_from_path(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            let _ = write!(uri, "%{byte:02X}");
+        }
+    }
+    uri
+}
+
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // Skip the authority, which is normally empty, but may be e.g. `localhost`.
+    let mut encoded = &rest.as_bytes()[rest.find('/')?..];
+    let mut bytes = Vec::new();
+    while let Some((&byte, tail)) = encoded.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            encoded = &tail[2..];
+        } else {
+            bytes.push(byte);
+            encoded = tail;
+        }
+    }
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostic;
+    use super::Rechecked;
+    use crate::problem::Severity;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::path::PathBuf;
+
+    #[test]
+    fn uris() {
+        let path = Path::new("/home/foo/my crate/src/100%.rs");
+        let uri = super::uri_from_path(path);
+        assert_eq!(uri, "file:///home/foo/my%20crate/src/100%25.rs");
+        assert_eq!(super::path_from_uri(&uri).as_deref(), Some(path));
+        assert_eq!(
+            super::path_from_uri("file://localhost/a/b.rs"),
+            Some(PathBuf::from("/a/b.rs"))
+        );
+        assert_eq!(super::path_from_uri("https://example.com/a.rs"), None);
+        assert_eq!(super::path_from_uri("file:///a%2"), None);
+    }
+
+    fn diagnostic(package: &str, line: u32) -> Diagnostic {
+        Diagnostic {
+            line,
+            column: Some(5),
+            severity: Severity::Error,
+            message: format!("{package} uses net"),
+            package: package.to_owned(),
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut previous = BTreeMap::new();
+        previous.insert(PathBuf::from("/a/src/lib.rs"), vec![diagnostic("a", 1)]);
+        previous.insert(PathBuf::from("/b/src/lib.rs"), vec![diagnostic("b", 1)]);
+        previous.insert(PathBuf::from("/c/src/lib.rs"), vec![diagnostic("c", 1)]);
+        previous.insert(PathBuf::from("/d/src/lib.rs"), vec![diagnostic("d", 1)]);
+
+        // `a` was saved, so its diagnostic was fixed. `b` was found again, so its previous
+        // diagnostics get replaced. `c` wasn't rechecked, so is retained. `d` is in the same package
+        // as a file that was saved.
+        let rechecked = Rechecked::Saved {
+            files: vec![PathBuf::from("/a/src/lib.rs"), PathBuf::from("/d/src/x.rs")],
+            packages: ["a", "d"].into_iter().map(str::to_owned).collect(),
+        };
+        let found = vec![(PathBuf::from("/b/src/lib.rs"), diagnostic("b", 2))];
+        let merged = super::merge(&previous, found.clone(), &rechecked);
+        assert_eq!(
+            merged.into_iter().collect::<Vec<_>>(),
+            vec![
+                (PathBuf::from("/b/src/lib.rs"), vec![diagnostic("b", 2)]),
+                (PathBuf::from("/c/src/lib.rs"), vec![diagnostic("c", 1)]),
+            ]
+        );
+
+        let merged = super::merge(&previous, found, &Rechecked::Everything);
+        assert_eq!(merged.len(), 1);
+    }
+
+    #[test]
+    fn diagnostic_json() {
+        let json = diagnostic("a", 10).to_json();
+        assert_eq!(json["range"]["start"]["line"], 9);
+        assert_eq!(json["range"]["start"]["character"], 4);
+        assert_eq!(json["severity"], 1);
+
+        let mut without_column = diagnostic("a", 10);
+        without_column.column = None;
+        let json = without_column.to_json();
+        assert_eq!(json["range"]["end"]["line"], 10);
+    }
+}
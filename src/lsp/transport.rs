@@ -0,0 +1,93 @@
+//! The base protocol of LSP. Each message is a JSON-RPC object preceded by a `Content-Length`
+//! header.
+
+// We need unsafe in order to redirect stdout via libc.
+#![allow(unsafe_code)]
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::Value;
+use std::io::BufRead;
+use std::io::Write;
+use std::os::fd::AsFd;
+
+/// Reads the next message from `input`. Returns `None` once the input has been closed.
+pub(super) fn read_message(input: &mut dyn BufRead) -> Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                let value = value.trim();
+                content_length = Some(
+                    value
+                        .parse::<usize>()
+                        .with_context(|| format!("Invalid Content-Length `{value}`"))?,
+                );
+            }
+        }
+    }
+    let Some(content_length) = content_length else {
+        bail!("Received message without a Content-Length");
+    };
+    let mut content = vec![0; content_length];
+    input.read_exact(&mut content)?;
+    Ok(Some(serde_json::from_slice(&content)?))
+}
+
+pub(super) fn write_message(output: &mut dyn Write, message: &Value) -> Result<()> {
+    let content = serde_json::to_string(message)?;
+    write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Returns a file that writes to what was stdout, then points stdout at stderr. That way, anything
+/// else that gets written to stdout, whether by us or by cargo, ends up in the editor's log rather
+/// than corrupting the protocol.
+pub(super) fn take_stdout() -> Result<std::fs::File> {
+    std::io::stdout().flush()?;
+    let stdout = std::io::stdout().as_fd().try_clone_to_owned()?;
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        bail!(std::io::Error::last_os_error());
+    }
+    Ok(stdout.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let mut out = Vec::new();
+        super::write_message(&mut out, &json!({"id": 1, "method": "initialize"})).unwrap();
+        super::write_message(&mut out, &json!({"method": "exit"})).unwrap();
+        assert!(out.starts_with(b"Content-Length: 30\r\n\r\n{"));
+
+        let mut input = out.as_slice();
+        let message = super::read_message(&mut input).unwrap().unwrap();
+        assert_eq!(message["method"], "initialize");
+        let message = super::read_message(&mut input).unwrap().unwrap();
+        assert_eq!(message["method"], "exit");
+        assert!(super::read_message(&mut input).unwrap().is_none());
+    }
+
+    #[test]
+    fn other_headers() {
+        let mut input: &[u8] = b"Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n\
+            content-length: 2\r\n\r\n{}";
+        assert_eq!(super::read_message(&mut input).unwrap(), Some(json!({})));
+
+        let mut input: &[u8] = b"Content-Type: foo\r\n\r\n{}";
+        assert!(super::read_message(&mut input).is_err());
+    }
+}
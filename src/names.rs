@@ -50,6 +50,18 @@ impl Name {
             .first()
             .is_some_and(|name_start| prefix == &**name_start)
     }
+
+    /// Returns whether all the parts of `prefix` match the corresponding leading parts of this
+    /// name. e.g. `std::fs::read` is within `std::fs`, but not within `std::f`. A part of `*` in
+    /// `prefix` matches any part, so `std::os::unix::net` is within `std::os::*::net`.
+    pub(crate) fn is_within(&self, prefix: &Name) -> bool {
+        prefix.parts.len() <= self.parts.len()
+            && prefix
+                .parts
+                .iter()
+                .zip(self.parts.iter())
+                .all(|(a, b)| a == b || &**a == WILDCARD)
+    }
 }
 
 impl Namespace {
@@ -82,7 +94,7 @@ impl Namespace {
 }
 
 impl<'input> DebugName<'input> {
-    pub(crate) fn names_iterator(&self) -> NamesIterator<NonMangledIterator> {
+    pub(crate) fn names_iterator(&self) -> NamesIterator<'_, NonMangledIterator<'_>> {
         NamesIterator::new(NonMangledIterator::new(
             &self.namespace.parts,
             self.name.as_ref(),
@@ -230,6 +242,10 @@ impl<'data, I: Clone + Iterator<Item = DemangleToken<'data>>> Iterator
     type Item = NameToken<'data>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let NamesIteratorState::EndAfterAsFinal = &self.state {
+            self.state = NamesIteratorState::Inactive;
+            return Some(NameToken::EndName);
+        }
         while let Some(token) = self.it.next() {
             match token {
                 DemangleToken::Text(text) => {
@@ -254,7 +270,7 @@ impl<'data, I: Clone + Iterator<Item = DemangleToken<'data>>> Iterator
                     if self.as_final == Some(text)
                         && self
                             .as_final
-                            .is_some_and(|t| t.as_ptr() as usize == text.as_ptr() as usize)
+                            .is_some_and(|t| std::ptr::eq(t.as_ptr(), text.as_ptr()))
                     {
                         // This text was already output as the final part of an as-name. Ignore it.
                         continue;
@@ -283,6 +299,7 @@ impl<'data, I: Clone + Iterator<Item = DemangleToken<'data>>> Iterator
                         self.state = NamesIteratorState::AsSkip {
                             gt_depth: 1,
                             return_point: self.it.clone(),
+                            trait_has_generics: ch == '<',
                         };
                     }
                     match ch {
@@ -308,24 +325,27 @@ impl<'data, I: Clone + Iterator<Item = DemangleToken<'data>>> Iterator
                         NamesIteratorState::AsSkip {
                             gt_depth,
                             return_point,
-                        } => {
-                            if *gt_depth == 0 {
-                                match self.it.next() {
-                                    Some(DemangleToken::Text(text)) => {
-                                        self.it = return_point.clone();
-                                        self.as_final = Some(text);
-                                        self.state = NamesIteratorState::OutputtingName;
-                                        return Some(NameToken::Part(text));
-                                    }
-                                    _ => {
-                                        self.it = return_point.clone();
-                                        self.as_final = None;
-                                        self.state = NamesIteratorState::Inactive;
-                                        return Some(NameToken::EndName);
-                                    }
-                                }
+                            trait_has_generics,
+                        } if *gt_depth == 0 => match self.it.next() {
+                            Some(DemangleToken::Text(text)) => {
+                                self.it = return_point.clone();
+                                self.as_final = Some(text);
+                                // If the trait had generics, then we're about to go back and
+                                // process them, so the name needs to end here.
+                                self.state = if *trait_has_generics {
+                                    NamesIteratorState::EndAfterAsFinal
+                                } else {
+                                    NamesIteratorState::OutputtingName
+                                };
+                                return Some(NameToken::Part(text));
                             }
-                        }
+                            _ => {
+                                self.it = return_point.clone();
+                                self.as_final = None;
+                                self.state = NamesIteratorState::Inactive;
+                                return Some(NameToken::EndName);
+                            }
+                        },
                         _ => {}
                     }
                 }
@@ -351,7 +371,7 @@ impl<'input> DebugName<'input> {
         }
     }
 
-    pub(crate) fn new(namespace: Namespace, name: &str) -> DebugName {
+    pub(crate) fn new(namespace: Namespace, name: &str) -> DebugName<'_> {
         DebugName {
             namespace,
             name: Utf8Bytes::Borrowed(name),
@@ -374,7 +394,11 @@ enum NamesIteratorState<I> {
         gt_depth: i32,
         /// An iterator pointing to where we'll come back to once we've finished with the as-name.
         return_point: I,
+        /// Whether the trait has generic arguments. e.g. `<Foo as bar::Baz<T>>::baz`.
+        trait_has_generics: bool,
     },
+    /// We've just output the final part of an as-name and the name should now end.
+    EndAfterAsFinal,
 }
 
 impl<'input> SymbolAndName<'input> {
@@ -453,6 +477,9 @@ impl Debug for Name {
     }
 }
 
+/// A path part that, in path prefixes from the config, matches any single part.
+pub(crate) const WILDCARD: &str = "*";
+
 pub(crate) fn split_simple(value: &str) -> Name {
     Name {
         parts: value.split("::").map(Arc::from).collect(),
@@ -463,6 +490,15 @@ pub(crate) fn split_simple(value: &str) -> Name {
 mod tests {
     use super::*;
 
+    #[test]
+    fn is_within_wildcard() {
+        let name = split_simple("std::os::unix::net::UnixStream");
+        assert!(name.is_within(&split_simple("std::os::*::net")));
+        assert!(name.is_within(&split_simple("std::*")));
+        assert!(!name.is_within(&split_simple("std::os::*::fs")));
+        assert!(!split_simple("std::os").is_within(&split_simple("std::os::*")));
+    }
+
     #[track_caller]
     fn check(namespace: &[&str], input: &str, expected: &[&[&str]]) {
         let mut out = Vec::new();
@@ -524,6 +560,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_split_as_with_generic_trait() {
+        check(
+            &[],
+            "<&str as core::convert::AsRef<std::path::Path>>::as_ref",
+            &[
+                &["str"],
+                &["core", "convert", "AsRef", "as_ref"],
+                &["std", "path", "Path"],
+            ],
+        );
+    }
+
     #[test]
     fn test_split_vtable() {
         check(
@@ -2,18 +2,24 @@
 //! multiple problems and report them all, although in the case of errors, we usually stop.
 
 use crate::checker::ApiUsage;
+use crate::config::advisories::Advisory;
 use crate::config::permissions::PermSel;
 use crate::config::permissions::PermissionScope;
 use crate::config::ApiConfig;
 use crate::config::ApiName;
 use crate::config::ApiPath;
+use crate::config::SandboxKind;
 use crate::crate_index::CrateKind;
 use crate::crate_index::CrateSel;
 use crate::crate_index::PackageId;
+use crate::names::Name;
 use crate::names::SymbolOrDebugName;
 use crate::proxy::rpc::BinExecutionOutput;
 use crate::proxy::rpc::UnsafeUsage;
+use crate::sandbox::Protection;
 use crate::symbol::Symbol;
+use cargo_metadata::semver::VersionReq;
+use fxhash::FxHashMap;
 use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::path::Path;
@@ -33,10 +39,16 @@ pub(crate) enum Problem {
     UsesBuildScript(PackageId),
     DisallowedUnsafe(UnsafeUsage),
     IsProcMacro(PackageId),
+    DisallowedFfiExport(FfiExport),
+    VersionMismatch(VersionMismatch),
     DisallowedApiUsage(ApiUsages),
     OffTreeApiUsage(OffTreeApiUsage),
     ExecutionFailed(BinExecutionFailed),
+    ResourceLimitExceeded(BinExecutionFailed),
+    UnexpectedWrites(BinExecutionFailed),
     DisallowedBuildInstruction(DisallowedBuildInstruction),
+    DisallowedLinkLib(DisallowedLinkLib),
+    DisallowedRustcDirective(DisallowedRustcDirective),
     UnusedPackageConfig(PermSel),
     UnusedAllowApi(UnusedAllowApi),
     SelectSandbox,
@@ -45,12 +57,16 @@ pub(crate) enum Problem {
     PossibleExportedApi(PossibleExportedApi),
     UnusedSandboxConfiguration(PermSel),
     NewConfigVersionAvailable(i64),
+    SandboxDegraded(SandboxDegraded),
+    AdvisoryWithSensitiveApis(AdvisoryWithSensitiveApis),
+    StaleAttestation(StaleAttestation),
+    UndeclaredApis(UndeclaredApis),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) struct BinExecutionFailed {
     pub(crate) crate_sel: CrateSel,
-    pub(crate) output: BinExecutionOutput,
+    pub(crate) output: Box<BinExecutionOutput>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -59,6 +75,8 @@ pub(crate) struct ApiUsages {
     pub(crate) scope: PermissionScope,
     pub(crate) api_name: ApiName,
     pub(crate) usages: Vec<ApiUsage>,
+    /// How severe it is that the API was used, as determined by the API's config.
+    pub(crate) severity: Severity,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -79,6 +97,79 @@ pub(crate) struct DisallowedBuildInstruction {
     pub(crate) instruction: String,
 }
 
+/// A build script asked cargo to link a native library that the package hasn't been permitted to
+/// link.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct DisallowedLinkLib {
+    pub(crate) pkg_id: PackageId,
+    /// The name of the library, without any kind or modifiers. e.g. `ssl`.
+    pub(crate) lib: String,
+    pub(crate) instruction: String,
+}
+
+/// A build script emitted a cfg or environment variable for the compilation of its package that the
+/// package hasn't been permitted to set.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct DisallowedRustcDirective {
+    pub(crate) pkg_id: PackageId,
+    pub(crate) kind: RustcDirectiveKind,
+    /// The name of the environment variable or cfg, without any value.
+    pub(crate) name: String,
+    pub(crate) instruction: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum RustcDirectiveKind {
+    /// `cargo:rustc-env=VAR=VALUE`
+    Env,
+    /// `cargo:rustc-cfg=KEY[="VALUE"]`
+    Cfg,
+}
+
+/// The config asked for the strongest available sandbox, but what's available on this system
+/// doesn't provide some protections.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct SandboxDegraded {
+    pub(crate) kind: SandboxKind,
+    pub(crate) skipped: Vec<Protection>,
+}
+
+/// A package that has outstanding advisories in the RustSec advisory database and which is granted
+/// APIs that are configured as sensitive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct AdvisoryWithSensitiveApis {
+    pub(crate) pkg_id: PackageId,
+    pub(crate) advisories: Vec<Advisory>,
+    pub(crate) apis: Vec<ApiName>,
+}
+
+/// A package whose attestation no longer holds.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct StaleAttestation {
+    pub(crate) pkg_id: PackageId,
+    pub(crate) reviewer: String,
+    pub(crate) reason: StaleAttestationReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum StaleAttestationReason {
+    /// The package's rlib is different to the one that was attested.
+    ContentsChanged,
+    /// The package is now granted different permissions to those that were attested.
+    PermissionsChanged,
+    /// The attestation's signature couldn't be verified.
+    InvalidSignature(String),
+}
+
+/// A package that was observed to use APIs that it doesn't declare in its `cackle-manifest.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct UndeclaredApis {
+    pub(crate) perm_sel: PermSel,
+    pub(crate) undeclared: Vec<ApiName>,
+    /// APIs that are declared, but weren't observed to be used.
+    pub(crate) unobserved: Vec<ApiName>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) struct AvailableApi {
     pub(crate) pkg_id: PackageId,
@@ -86,6 +177,23 @@ pub(crate) struct AvailableApi {
     pub(crate) config: ApiConfig,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct VersionMismatch {
+    pub(crate) pkg_id: PackageId,
+    pub(crate) version_req: VersionReq,
+}
+
+/// Unmangled symbols, e.g. from `#[no_mangle]` functions, that are defined by a package. Such
+/// symbols can be called from outside of Rust without going through any of the package's APIs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct FfiExport {
+    pub(crate) pkg_id: PackageId,
+    /// The scope of the binary that the symbols were exported from. e.g. if the package's build
+    /// script exports a symbol, then this is `Build`.
+    pub(crate) scope: PermissionScope,
+    pub(crate) symbols: Vec<String>,
+}
+
 /// The name of a top-level module in a crate that matches the name of a restricted API. For
 /// example, if there's an API named "fs" and we find a crate with a module named "fs".
 #[derive(Clone, Hash, PartialEq, Eq, Debug)]
@@ -149,8 +257,10 @@ impl<'a> IntoIterator for &'a ProblemList {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How serious a problem is. Variants are ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum Severity {
+    Info,
     Warning,
     Error,
 }
@@ -166,7 +276,13 @@ impl Problem {
             | Problem::UnusedPackageConfig(..)
             | Problem::PossibleExportedApi(..)
             | Problem::NewConfigVersionAvailable(..)
+            | Problem::SandboxDegraded(..)
+            | Problem::AdvisoryWithSensitiveApis(..)
+            | Problem::StaleAttestation(..)
+            | Problem::UndeclaredApis(..)
             | Problem::AvailableApi(..) => Severity::Warning,
+            Problem::DisallowedApiUsage(usages) => usages.severity,
+            Problem::OffTreeApiUsage(off_tree) => off_tree.usages.severity,
             _ => Severity::Error,
         }
     }
@@ -175,7 +291,10 @@ impl Problem {
     fn should_send_retry_to_subprocess(&self) -> bool {
         matches!(
             self,
-            &Problem::ExecutionFailed(..) | &Problem::DisallowedUnsafe(..)
+            &Problem::ExecutionFailed(..)
+                | &Problem::ResourceLimitExceeded(..)
+                | &Problem::UnexpectedWrites(..)
+                | &Problem::DisallowedUnsafe(..)
         )
     }
 
@@ -188,6 +307,12 @@ impl Problem {
                 scope: api_usage.scope,
                 api_name: api_usage.api_name.clone(),
                 usages: Default::default(),
+                severity: api_usage.severity,
+            }),
+            Problem::DisallowedFfiExport(export) => Problem::DisallowedFfiExport(FfiExport {
+                pkg_id: export.pkg_id.clone(),
+                scope: export.scope,
+                symbols: Default::default(),
             }),
             Problem::PossibleExportedApi(info) => {
                 Problem::PossibleExportedApi(PossibleExportedApi {
@@ -202,8 +327,14 @@ impl Problem {
     /// Merges `other` into `self`. Should only be called with two problems that are not equal, but
     /// which have equal deduplication_keys.
     pub(crate) fn merge(&mut self, other: Problem) {
-        if let (Problem::DisallowedApiUsage(a), Problem::DisallowedApiUsage(b)) = (self, other) {
-            a.merge(b);
+        match (self, other) {
+            (Problem::DisallowedApiUsage(a), Problem::DisallowedApiUsage(b)) => a.merge(b),
+            (Problem::DisallowedFfiExport(a), Problem::DisallowedFfiExport(b)) => {
+                a.symbols.extend(b.symbols);
+                a.symbols.sort();
+                a.symbols.dedup();
+            }
+            _ => {}
         }
     }
 
@@ -214,10 +345,16 @@ impl Problem {
             Problem::UsesBuildScript(pkg_id) => Some(pkg_id),
             Problem::DisallowedUnsafe(d) => Some(d.crate_sel.pkg_id()),
             Problem::IsProcMacro(pkg_id) => Some(pkg_id),
+            Problem::DisallowedFfiExport(d) => Some(&d.pkg_id),
+            Problem::VersionMismatch(d) => Some(&d.pkg_id),
             Problem::DisallowedApiUsage(d) => Some(&d.pkg_id),
             Problem::OffTreeApiUsage(d) => Some(&d.usages.pkg_id),
             Problem::ExecutionFailed(d) => Some(d.crate_sel.pkg_id()),
+            Problem::ResourceLimitExceeded(d) => Some(d.crate_sel.pkg_id()),
+            Problem::UnexpectedWrites(d) => Some(d.crate_sel.pkg_id()),
             Problem::DisallowedBuildInstruction(d) => Some(&d.pkg_id),
+            Problem::DisallowedLinkLib(d) => Some(&d.pkg_id),
+            Problem::DisallowedRustcDirective(d) => Some(&d.pkg_id),
             Problem::UnusedPackageConfig(_) => None,
             Problem::UnusedAllowApi(_) => None,
             Problem::SelectSandbox => None,
@@ -226,6 +363,10 @@ impl Problem {
             Problem::PossibleExportedApi(d) => Some(&d.pkg_id),
             Problem::UnusedSandboxConfiguration(_) => None,
             Problem::NewConfigVersionAvailable(_) => None,
+            Problem::SandboxDegraded(_) => None,
+            Problem::AdvisoryWithSensitiveApis(d) => Some(&d.pkg_id),
+            Problem::StaleAttestation(d) => Some(&d.pkg_id),
+            Problem::UndeclaredApis(_) => None,
         }
     }
 }
@@ -264,6 +405,22 @@ impl Display for Problem {
                 "`{}` is a proc macro",
                 CrateSel::primary(pkg_name.clone())
             )?,
+            Problem::VersionMismatch(info) => write!(
+                f,
+                "`{}` has permissions for version `{}`, but version {} is in use",
+                info.pkg_id.pkg_name(),
+                info.version_req,
+                info.pkg_id.version()
+            )?,
+            Problem::DisallowedFfiExport(export) => {
+                write!(f, "`{}` exports unmangled symbols", export.perm_sel())?;
+                if f.alternate() {
+                    writeln!(f)?;
+                    for symbol in &export.symbols {
+                        writeln!(f, "  {symbol}")?;
+                    }
+                }
+            }
             Problem::DisallowedApiUsage(info) => info.fmt(f)?,
             Problem::OffTreeApiUsage(info) => {
                 write!(
@@ -276,7 +433,21 @@ impl Display for Problem {
                     display_usages(f, &info.usages.usages)?;
                 }
             }
-            Problem::ExecutionFailed(info) => info.fmt(f)?,
+            Problem::ExecutionFailed(info) | Problem::ResourceLimitExceeded(info) => info.fmt(f)?,
+            Problem::UnexpectedWrites(info) => {
+                write!(
+                    f,
+                    "`{}` wrote to files that it isn't permitted to write to",
+                    info.crate_sel
+                )?;
+                if f.alternate() {
+                    writeln!(f)?;
+                    writeln!(f, "The following writes were discarded:")?;
+                    for write in &info.output.captured_writes {
+                        writeln!(f, "  {write}")?;
+                    }
+                }
+            }
             Problem::DisallowedBuildInstruction(info) => {
                 write!(
                     f,
@@ -285,6 +456,34 @@ impl Display for Problem {
                     info.instruction
                 )?;
             }
+            Problem::DisallowedLinkLib(info) => {
+                write!(
+                    f,
+                    "{}'s build script links native library `{}`",
+                    CrateSel::primary(info.pkg_id.clone()),
+                    info.lib
+                )?;
+                if f.alternate() {
+                    writeln!(f)?;
+                    writeln!(f, "Instruction: {}", info.instruction)?;
+                }
+            }
+            Problem::DisallowedRustcDirective(info) => {
+                let what = match info.kind {
+                    RustcDirectiveKind::Env => "environment variable",
+                    RustcDirectiveKind::Cfg => "cfg",
+                };
+                write!(
+                    f,
+                    "{}'s build script sets {what} `{}`",
+                    CrateSel::primary(info.pkg_id.clone()),
+                    info.name
+                )?;
+                if f.alternate() {
+                    writeln!(f)?;
+                    writeln!(f, "Instruction: {}", info.instruction)?;
+                }
+            }
             Problem::UnusedPackageConfig(pkg_name) => {
                 write!(
                     f,
@@ -329,6 +528,93 @@ impl Display for Problem {
                      Perhaps you meant to configure `{crate_name}.build.sandbox`"
                 )?;
             }
+            Problem::SandboxDegraded(info) => {
+                write!(
+                    f,
+                    "Sandbox kind `Auto` selected `{:?}`, which doesn't provide all protections",
+                    info.kind
+                )?;
+                if f.alternate() {
+                    writeln!(f)?;
+                    writeln!(f, "Skipped protections:")?;
+                    for protection in &info.skipped {
+                        writeln!(f, "  {protection}")?;
+                    }
+                    writeln!(f, "Run `cargo acl sandbox-info` for details")?;
+                }
+            }
+            Problem::AdvisoryWithSensitiveApis(info) => {
+                let apis: Vec<String> = info.apis.iter().map(|api| format!("`{api}`")).collect();
+                write!(
+                    f,
+                    "`{}` has {} outstanding {} and is granted {}",
+                    info.pkg_id,
+                    info.advisories.len(),
+                    if info.advisories.len() == 1 {
+                        "advisory"
+                    } else {
+                        "advisories"
+                    },
+                    apis.join(", ")
+                )?;
+                if f.alternate() {
+                    writeln!(f)?;
+                    for advisory in &info.advisories {
+                        writeln!(f, "  {advisory}")?;
+                        writeln!(f, "    https://rustsec.org/advisories/{}.html", advisory.id)?;
+                    }
+                }
+            }
+            Problem::StaleAttestation(info) => {
+                match &info.reason {
+                    StaleAttestationReason::ContentsChanged => write!(
+                        f,
+                        "`{}` has changed since it was attested by {}",
+                        info.pkg_id, info.reviewer
+                    )?,
+                    StaleAttestationReason::PermissionsChanged => write!(
+                        f,
+                        "`{}` has been granted different permissions since it was attested by {}",
+                        info.pkg_id, info.reviewer
+                    )?,
+                    StaleAttestationReason::InvalidSignature(error) => write!(
+                        f,
+                        "Attestation of `{}` by {} couldn't be verified: {error}",
+                        info.pkg_id, info.reviewer
+                    )?,
+                }
+                if f.alternate() {
+                    writeln!(f)?;
+                    writeln!(
+                        f,
+                        "Once it's been reviewed, run `cargo acl attest {}` to attest it again",
+                        info.pkg_id.pkg_name()
+                    )?;
+                }
+            }
+            Problem::UndeclaredApis(info) => {
+                let undeclared: Vec<String> = info
+                    .undeclared
+                    .iter()
+                    .map(|api| format!("`{api}`"))
+                    .collect();
+                write!(
+                    f,
+                    "`{}` uses APIs that its {} doesn't declare: {}",
+                    info.perm_sel,
+                    crate::config::declarations::MANIFEST_FILENAME,
+                    undeclared.join(", ")
+                )?;
+                if f.alternate() && !info.unobserved.is_empty() {
+                    let unobserved: Vec<String> = info
+                        .unobserved
+                        .iter()
+                        .map(|api| format!("`{api}`"))
+                        .collect();
+                    writeln!(f)?;
+                    writeln!(f, "Declared, but not observed: {}", unobserved.join(", "))?;
+                }
+            }
         }
         Ok(())
     }
@@ -355,6 +641,12 @@ impl Display for ApiUsages {
                 PermissionScope::FromTest => {
                     " in code included in a test from another package".fmt(f)?
                 }
+                PermissionScope::FromHost => {
+                    " in code included in a proc macro from another package".fmt(f)?
+                }
+                PermissionScope::Dev => {
+                    " in code included in a test, benchmark or example".fmt(f)?
+                }
             }
         }
         Ok(())
@@ -378,15 +670,22 @@ impl Display for UnusedAllowApi {
 impl Display for BinExecutionFailed {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let pkg_id = &self.output.crate_sel.pkg_id;
+        let outcome = match &self.output.limit_exceeded {
+            Some(limit) => format!("exceeded its {limit}"),
+            None => "failed".to_owned(),
+        };
         match self.output.crate_sel.kind {
             CrateKind::Primary => {
-                write!(f, "Execution of binary for package `{pkg_id}` failed")?;
+                write!(f, "Execution of binary for package `{pkg_id}` {outcome}")?;
             }
             CrateKind::BuildScript => {
-                write!(f, "Build script for package `{pkg_id}` failed")?;
+                write!(f, "Build script for package `{pkg_id}` {outcome}")?;
             }
             CrateKind::Test => {
-                write!(f, "Execution of test for package `{pkg_id}` failed")?;
+                write!(f, "Execution of test for package `{pkg_id}` {outcome}")?;
+            }
+            CrateKind::Example => {
+                write!(f, "Execution of example for package `{pkg_id}` {outcome}")?;
             }
         }
         if f.alternate() {
@@ -396,6 +695,16 @@ impl Display for BinExecutionFailed {
                 String::from_utf8_lossy(&self.output.stderr),
                 String::from_utf8_lossy(&self.output.stdout)
             )?;
+            if !self.output.denied_accesses.is_empty() {
+                writeln!(f, "Accesses denied by the sandbox:")?;
+                for access in &self.output.denied_accesses {
+                    writeln!(f, "  {access}")?;
+                }
+            }
+            let denied_hosts = self.output.denied_hosts();
+            if !denied_hosts.is_empty() {
+                writeln!(f, "Network access denied to: {}", denied_hosts.join(", "))?;
+            }
             if let Some(sandbox_display) = self.output.sandbox_config_display.as_ref() {
                 writeln!(f, "Sandbox config:\n{sandbox_display}",)?;
             }
@@ -415,6 +724,8 @@ fn display_usages(
             .or_default()
             .push(u);
     }
+    // If the usages were found in more than one binary, say which binary each is from.
+    let show_bin = usages.iter().any(|u| u.bin_path != usages[0].bin_path);
     let mut by_from: BTreeMap<&SymbolOrDebugName, Vec<&ApiUsage>> = BTreeMap::new();
     for (filename, usages_for_location) in by_source_filename {
         writeln!(f, "  {}", filename.display())?;
@@ -429,13 +740,37 @@ fn display_usages(
                 if let Some(column) = u.source_location.column() {
                     write!(f, ":{}", column)?;
                 }
-                writeln!(f, "]")?;
+                write!(f, "]")?;
+                if u.similar_usages > 0 {
+                    write!(f, " (+{} similar)", u.similar_usages)?;
+                }
+                if show_bin {
+                    let bin_name = u.bin_path.file_name().unwrap_or(u.bin_path.as_os_str());
+                    write!(f, " in `{}`", bin_name.to_string_lossy())?;
+                }
+                writeln!(f)?;
+                if u.call_chain.len() > 1 {
+                    writeln!(
+                        f,
+                        "         call chain: {}",
+                        display_call_chain(&u.call_chain)
+                    )?;
+                }
             }
         }
     }
     Ok(())
 }
 
+/// Formats `call_chain` as a single line, e.g. `a::entry -> a::helper -> a::uses_api`.
+pub(crate) fn display_call_chain(call_chain: &[SymbolOrDebugName]) -> String {
+    call_chain
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
 impl From<Problem> for ProblemList {
     fn from(value: Problem) -> Self {
         Self {
@@ -461,12 +796,37 @@ impl ApiUsages {
         self.usages.append(&mut b.usages);
     }
 
+    /// Retains at most `max_usages` usages, except that a usage is always retained if no other
+    /// usage of the same name in the same file has been retained, so that `allow_fn` and suggested
+    /// edits behave the same as if we'd retained everything. Usages that are dropped are counted in
+    /// the `similar_usages` of the retained usage that they match.
+    pub(crate) fn aggregate(&mut self, max_usages: usize) {
+        let mut usages = std::mem::take(&mut self.usages);
+        usages.sort_by(|a, b| a.source_location.cmp(&b.source_location));
+        let mut retained_by_name: FxHashMap<(PathBuf, Name), usize> = FxHashMap::default();
+        for usage in usages {
+            let key = (
+                usage.source_location.filename().to_owned(),
+                usage.to_name.clone(),
+            );
+            if self.usages.len() >= max_usages {
+                if let Some(index) = retained_by_name.get(&key) {
+                    self.usages[*index].similar_usages += 1 + usage.similar_usages;
+                    continue;
+                }
+            }
+            retained_by_name.entry(key).or_insert(self.usages.len());
+            self.usages.push(usage);
+        }
+    }
+
     pub(crate) fn with_usages(&self, usages: Vec<ApiUsage>) -> Self {
         Self {
             pkg_id: self.pkg_id.clone(),
             scope: self.scope,
             api_name: self.api_name.clone(),
             usages,
+            severity: self.severity,
         }
     }
 
@@ -474,3 +834,89 @@ impl ApiUsages {
         PermSel::with_scope(&self.pkg_id, self.scope)
     }
 }
+
+impl FfiExport {
+    pub(crate) fn perm_sel(&self) -> PermSel {
+        PermSel::with_scope(&self.pkg_id, self.scope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::BinLocation;
+    use crate::crate_index::testing::pkg_id;
+    use crate::location::SourceLocation;
+    use crate::symbol_graph::NameSource;
+
+    fn usage(filename: &str, line: u32, to: &str) -> ApiUsage {
+        ApiUsage {
+            bin_location: BinLocation {
+                address: 0,
+                symbol_start: 0,
+            },
+            bin_path: Arc::from(Path::new("bin")),
+            permission_scope: PermissionScope::All,
+            source_location: SourceLocation::new(Path::new(filename), line, None),
+            outer_location: None,
+            from: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+            to: SymbolOrDebugName::Symbol(Symbol::borrowed(&[])),
+            to_name: crate::names::split_simple(to),
+            to_source: NameSource::Symbol(Symbol::borrowed(to.as_bytes()).to_heap()),
+            debug_data: None,
+            call_chain: Vec::new(),
+            similar_usages: 0,
+        }
+    }
+
+    #[test]
+    fn usages_from_multiple_binaries() {
+        let mut in_other_bin = usage("a.rs", 2, "std::fs::write");
+        in_other_bin.bin_path = Arc::from(Path::new("target/debug/other"));
+        let mut api_usages = ApiUsages {
+            pkg_id: pkg_id("foo"),
+            scope: PermissionScope::All,
+            api_name: ApiName::from("fs"),
+            usages: vec![usage("a.rs", 1, "std::fs::read")],
+            severity: Severity::Error,
+        };
+        assert!(!format!("{api_usages:#}").contains(" in `"));
+        api_usages.usages.push(in_other_bin);
+        let display = format!("{api_usages:#}");
+        assert!(display.contains("std::fs::read [1] in `bin`"));
+        assert!(display.contains("std::fs::write [2] in `other`"));
+    }
+
+    #[test]
+    fn aggregate_usages() {
+        let mut api_usages = ApiUsages {
+            pkg_id: pkg_id("foo"),
+            scope: PermissionScope::All,
+            api_name: ApiName::from("fs"),
+            usages: vec![
+                usage("b.rs", 1, "std::fs::read"),
+                usage("a.rs", 3, "std::fs::read"),
+                usage("a.rs", 1, "std::fs::read"),
+                usage("a.rs", 2, "std::fs::read"),
+                usage("a.rs", 4, "std::fs::write"),
+            ],
+            severity: Severity::Error,
+        };
+        api_usages.aggregate(1);
+        let retained: Vec<_> = api_usages
+            .usages
+            .iter()
+            .map(|u| {
+                (
+                    u.source_location.filename().to_str().unwrap(),
+                    u.source_location.line(),
+                    u.similar_usages,
+                )
+            })
+            .collect();
+        assert_eq!(
+            retained,
+            vec![("a.rs", 1, 2), ("a.rs", 4, 0), ("b.rs", 1, 0)]
+        );
+    }
+}
@@ -2,6 +2,7 @@ use crate::events::AppEvent;
 use crate::outcome::Outcome;
 use crate::problem::Problem;
 use crate::problem::ProblemList;
+use crate::problem::Severity;
 use fxhash::FxHashMap;
 use fxhash::FxHashSet;
 use log::info;
@@ -28,11 +29,26 @@ pub(crate) struct ProblemStore {
     id_by_deduplication_key: FxHashMap<Problem, ProblemId>,
     event_sender: Sender<AppEvent>,
     pub(crate) has_aborted: bool,
+    /// Problems that the UI reported, together with the severity that they were reported with.
+    /// Only recorded when a report is to be written with `--output-format`, or when running as a
+    /// language server.
+    pub(crate) reported: Vec<(Severity, Problem)>,
+    /// Receivers for problems that were reinstated after whatever reported them had already
+    /// carried on. See `reinstate`.
+    reinstated: Vec<Receiver<Outcome>>,
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub(crate) struct ProblemId(usize);
 
+impl ProblemId {
+    /// Returns a number that identifies this problem, for when it needs to be referred to from
+    /// outside the process. e.g. by the web UI.
+    pub(crate) fn index(self) -> usize {
+        self.0
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct ProblemStoreRef {
     inner: Arc<Mutex<ProblemStore>>,
@@ -48,7 +64,23 @@ impl ProblemStoreRef {
         outcome.recv().unwrap_or(Outcome::GiveUp)
     }
 
-    pub(crate) fn lock(&self) -> MutexGuard<ProblemStore> {
+    /// Waits until all problems that were reinstated by `ProblemStore::reinstate`, without anything
+    /// waiting for them, have been resolved, or we abort.
+    pub(crate) fn wait_for_reinstated(&self) -> Outcome {
+        loop {
+            let receivers = std::mem::take(&mut self.lock().reinstated);
+            if receivers.is_empty() {
+                return Outcome::Continue;
+            }
+            for receiver in receivers {
+                if receiver.recv().unwrap_or(Outcome::GiveUp) == Outcome::GiveUp {
+                    return Outcome::GiveUp;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, ProblemStore> {
         self.inner.lock().unwrap()
     }
 }
@@ -61,6 +93,8 @@ impl ProblemStore {
             id_by_deduplication_key: Default::default(),
             event_sender,
             has_aborted: false,
+            reported: Vec::new(),
+            reinstated: Vec::new(),
         }
     }
 
@@ -121,11 +155,17 @@ impl ProblemStore {
         }
     }
 
+    /// Returns the problems that haven't yet been resolved, most severe first. Problems of equal
+    /// severity are returned in the order in which they were added.
     pub(crate) fn deduplicated_into_iter(&self) -> impl Iterator<Item = (ProblemId, &Problem)> {
-        ProblemStoreIterator {
-            store: self,
-            id: ProblemId(0),
-        }
+        let mut problems: Vec<(ProblemId, &Problem)> = self
+            .problems
+            .iter()
+            .enumerate()
+            .filter_map(|(index, problem)| Some((ProblemId(index), problem.as_ref()?)))
+            .collect();
+        problems.sort_by_key(|(_, problem)| std::cmp::Reverse(problem.severity()));
+        problems.into_iter()
     }
 
     pub(crate) fn is_empty(&self) -> bool {
@@ -136,6 +176,12 @@ impl ProblemStore {
         self.problems.iter().filter(|p| p.is_some()).count()
     }
 
+    /// Returns the number of problems that have been resolved or rejected since the store was
+    /// created or last reset.
+    pub(crate) fn num_resolved(&self) -> usize {
+        self.problems.iter().filter(|p| p.is_none()).count()
+    }
+
     pub(crate) fn resolve(&mut self, id: ProblemId) {
         self.replace(id, ProblemList::default());
     }
@@ -163,6 +209,77 @@ impl ProblemStore {
             .remove(&problem.deduplication_key());
     }
 
+    /// Adds back `problems`, which were previously resolved, and removes `replacements`, which were
+    /// added when they were resolved. Used when the edit that resolved the problems is undone.
+    /// Anything still waiting for `replacements` to be resolved will wait for `problems` instead.
+    /// If nothing is waiting, because whatever reported `problems` has already carried on, then
+    /// `problems` are waited for by `ProblemStoreRef::wait_for_reinstated`.
+    pub(crate) fn reinstate(&mut self, problems: Vec<Problem>, replacements: &[ProblemId]) {
+        if problems.is_empty() {
+            return;
+        }
+        let ids: Vec<ProblemId> = problems
+            .into_iter()
+            .map(|problem| self.add_problem(problem))
+            .collect();
+        let mut waited_for = false;
+        for entry in &mut self.notification_entries {
+            if entry.sender.is_some()
+                && replacements
+                    .iter()
+                    .any(|replacement| entry.problem_ids.contains(replacement))
+            {
+                entry.problem_ids.extend(ids.iter());
+                waited_for = true;
+            }
+        }
+        for replacement in replacements {
+            if self.problems[replacement.0].is_some() {
+                self.resolve(*replacement);
+            }
+        }
+        if !waited_for {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            self.notification_entries.push(NotificationEntry {
+                problem_ids: ids.into_iter().collect(),
+                sender: Some(sender),
+            });
+            self.reinstated.push(receiver);
+        }
+        let _ = self.event_sender.send(AppEvent::ProblemsAdded);
+    }
+
+    /// Removes a problem that the user has decided shouldn't be permitted. Whatever was waiting for
+    /// the problem to be resolved gives up, which fails the build. Other problems are unaffected,
+    /// so can still be reviewed.
+    pub(crate) fn reject(&mut self, id: ProblemId) {
+        let problem = self
+            .problems
+            .get_mut(id.0)
+            .expect("Called ProblemStore::reject with invalid ID")
+            .take()
+            .expect("Called ProblemStore::reject with ID that was already resolved");
+        for entry in &mut self.notification_entries {
+            if entry.problem_ids.remove(&id) {
+                if let Some(sender) = entry.sender.take() {
+                    let _ = sender.send(Outcome::GiveUp);
+                }
+            }
+        }
+        info!("Rejected problem: {problem}");
+        self.id_by_deduplication_key
+            .remove(&problem.deduplication_key());
+    }
+
+    /// Discards all problems and clears any abort, ready for another build. Used by `watch`.
+    pub(crate) fn reset(&mut self) {
+        self.abort();
+        self.problems.clear();
+        self.id_by_deduplication_key.clear();
+        self.reinstated.clear();
+        self.has_aborted = false;
+    }
+
     pub(crate) fn abort(&mut self) {
         for mut entry in &mut self.notification_entries.drain(..) {
             if let Some(sender) = entry.sender.take() {
@@ -219,31 +336,10 @@ impl NotificationEntry {
     }
 }
 
-struct ProblemStoreIterator<'a> {
-    store: &'a ProblemStore,
-    id: ProblemId,
-}
-
-impl<'a> Iterator for ProblemStoreIterator<'a> {
-    type Item = (ProblemId, &'a Problem);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if self.id.0 >= self.store.problems.len() {
-                return None;
-            }
-            let id = self.id;
-            self.id = ProblemId(id.0 + 1);
-            if let Some(problem) = self.store.problems[id.0].as_ref() {
-                return Some((id, problem));
-            }
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::ProblemStore;
+    use crate::config::permissions::PermSel;
     use crate::crate_index::testing::pkg_id;
     use crate::problem::Problem;
     use crate::problem::ProblemList;
@@ -296,6 +392,7 @@ mod tests {
         assert_eq!(done1.try_recv(), Err(TryRecvError::Empty));
         assert_eq!(done2.try_recv(), Err(TryRecvError::Empty));
         store.resolve(first_problem_index(&store).unwrap());
+        assert_eq!(store.num_resolved(), 2);
         assert_eq!(done1.try_recv(), Ok(crate::outcome::Outcome::Continue));
         assert_eq!(done2.try_recv(), Ok(crate::outcome::Outcome::Continue));
     }
@@ -323,6 +420,52 @@ mod tests {
         assert_eq!(done2.try_recv(), Ok(crate::outcome::Outcome::GiveUp));
     }
 
+    #[test]
+    fn reject() {
+        let mut store = ProblemStore::new(channel().0);
+        let done1 = store.add(create_problems());
+        let mut problems = ProblemList::default();
+        problems.push(Problem::UsesBuildScript(pkg_id("crab3")));
+        let done2 = store.add(problems);
+        let (id, _) = store.deduplicated_into_iter().next().unwrap();
+        store.reject(id);
+        assert_eq!(done1.try_recv(), Ok(crate::outcome::Outcome::GiveUp));
+        assert_eq!(done2.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(store.len(), 2);
+        assert!(!store.has_aborted);
+    }
+
+    #[test]
+    fn reinstate() {
+        let mut store = ProblemStore::new(channel().0);
+        let done = store.add(Problem::MissingConfiguration("cackle.toml".into()).into());
+        let (missing_id, _) = store.deduplicated_into_iter().next().unwrap();
+        store.replace(missing_id, create_problems());
+        let replacements: Vec<ProblemId> =
+            store.deduplicated_into_iter().map(|(id, _)| id).collect();
+
+        // Undoing the edit that replaced the first problem means that we're still waiting.
+        store.reinstate(
+            vec![Problem::MissingConfiguration("cackle.toml".into())],
+            &replacements,
+        );
+        assert_eq!(store.len(), 1);
+        assert!(store.reinstated.is_empty());
+        let (missing_id, _) = store.deduplicated_into_iter().next().unwrap();
+        store.resolve(missing_id);
+        assert_eq!(done.try_recv(), Ok(crate::outcome::Outcome::Continue));
+
+        // Now nothing is waiting, so reinstating gets waited for separately.
+        store.reinstate(vec![Problem::UsesBuildScript(pkg_id("crab1"))], &[]);
+        assert_eq!(store.reinstated.len(), 1);
+        let (id, _) = store.deduplicated_into_iter().next().unwrap();
+        store.resolve(id);
+        assert_eq!(
+            store.reinstated[0].try_recv(),
+            Ok(crate::outcome::Outcome::Continue)
+        );
+    }
+
     #[test]
     fn deduplicated_iteration() {
         let mut store = ProblemStore::new(channel().0);
@@ -330,4 +473,23 @@ mod tests {
         store.add(create_problems());
         assert_eq!(store.deduplicated_into_iter().count(), 2);
     }
+
+    #[test]
+    fn most_severe_first() {
+        let mut store = ProblemStore::new(channel().0);
+        let mut problems = ProblemList::default();
+        problems.push(Problem::UnusedPackageConfig(PermSel::for_primary("crab1")));
+        problems.push(Problem::UsesBuildScript(pkg_id("crab2")));
+        problems.push(Problem::UsesBuildScript(pkg_id("crab3")));
+        store.add(problems);
+        let problems: Vec<&Problem> = store.deduplicated_into_iter().map(|(_, p)| p).collect();
+        assert_eq!(
+            problems,
+            vec![
+                &Problem::UsesBuildScript(pkg_id("crab2")),
+                &Problem::UsesBuildScript(pkg_id("crab3")),
+                &Problem::UnusedPackageConfig(PermSel::for_primary("crab1")),
+            ]
+        );
+    }
 }
@@ -19,21 +19,25 @@
 //!
 //! * We can run them inside a sandbox if the config says to do so.
 //! * We can capture their output and check for any directives to cargo that haven't been permitted.
+//!
+//! When running tests, we also wrap rustdoc so that doctests can be run inside the sandbox
+//! configured for the package's tests.
 
 use self::rpc::Request;
+use crate::build_output::BuildOutput;
+use crate::build_output::StreamCapture;
+use crate::cli::Args;
+use crate::cli::RequestHandler;
 use crate::config::CommonConfig;
 use crate::config::Config;
 use crate::crate_index::CrateIndex;
 use crate::outcome::ExitCode;
 use crate::outcome::Outcome;
-use crate::Args;
-use crate::RequestHandler;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use std::io::Write;
-use std::os::unix::net::UnixListener;
-use std::os::unix::net::UnixStream;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
@@ -41,6 +45,9 @@ use std::process::Stdio;
 use std::sync::mpsc::channel;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
 use std::thread::JoinHandle;
 use std::time::Duration;
 
@@ -48,13 +55,17 @@ pub(crate) mod cargo;
 pub(crate) mod errors;
 pub(crate) mod rpc;
 pub(crate) mod subprocess;
+pub(crate) mod transport;
 
 pub(crate) const SOCKET_ENV: &str = "CACKLE_SOCKET_PATH";
 const CONFIG_PATH_ENV: &str = "CACKLE_CONFIG_PATH";
 const ORIG_LINKER_ENV: &str = "CACKLE_ORIG_LINKER";
+const ORIG_RUSTC_WRAPPER_ENV: &str = "CACKLE_ORIG_RUSTC_WRAPPER";
 pub(crate) const TARGET_DIR: &str = "CACKLE_TARGET_DIR";
 pub(crate) const MANIFEST_DIR: &str = "CACKLE_MANIFEST_DIR";
 const RUSTC_PATH: &str = "CACKLE_RUSTC_PATH";
+/// Set when sandboxed binaries should be traced to find out what accesses were denied.
+pub(crate) const TRACE_SANDBOX_ENV: &str = "CACKLE_TRACE_SANDBOX";
 
 /// Environment variables that we need to allow through to rustc when we run rustc in a sandbox.
 pub(crate) const RUSTC_ENV_VARS: &[&str] = &[
@@ -70,6 +81,20 @@ pub(crate) struct CargoRunner<'a> {
     pub(crate) args: &'a Args,
     pub(crate) crate_index: &'a CrateIndex,
     pub(crate) target_dir: &'a Path,
+    /// Where captured output from cargo is kept, in addition to being passed through.
+    pub(crate) build_output: &'a Arc<Mutex<BuildOutput>>,
+}
+
+/// An error indicating that cargo failed, as opposed to us failing or finding problems.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BuildFailed;
+
+impl std::error::Error for BuildFailed {}
+
+impl std::fmt::Display for BuildFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`cargo` exited with non-zero exit status")
+    }
 }
 
 #[derive(Default)]
@@ -96,7 +121,7 @@ impl<'a> CargoRunner<'a> {
     /// main.
     pub(crate) fn invoke_cargo_build(
         &self,
-        abort_recv: Receiver<()>,
+        abort_recv: &Receiver<()>,
         abort_sender: Sender<()>,
         request_creator: impl Fn(Request) -> RequestHandler,
     ) -> Result<CargoOutputWaiter> {
@@ -104,16 +129,13 @@ impl<'a> CargoRunner<'a> {
             panic!("{SOCKET_ENV} is already set. Missing call to handle_wrapped_binaries?");
         }
 
-        // We put `cackle.socket` into a directory by itself. This lets our rustc sandbox have write
-        // permission on this directory without also gaining write access to other files that we put
-        // in our temporary directory.
+        // If we're using a Unix domain socket, we put `cackle.socket` into a directory by itself. This
+        // lets our rustc sandbox have write permission on this directory without also gaining write
+        // access to other files that we put in our temporary directory.
         let ipc_dir = self.tmpdir.join("comms");
         std::fs::create_dir_all(&ipc_dir)
             .with_context(|| format!("Failed to crate directory `{}`", ipc_dir.display()))?;
-        let ipc_path = ipc_dir.join("cackle.socket");
-        let _ = std::fs::remove_file(&ipc_path);
-        let listener = UnixListener::bind(&ipc_path)
-            .with_context(|| format!("Failed to create Unix socket `{}`", ipc_path.display()))?;
+        let listener = transport::bind(self.args.ipc_transport.unwrap_or_default(), &ipc_dir)?;
 
         let mut command = cargo::command(
             "build",
@@ -124,7 +146,7 @@ impl<'a> CargoRunner<'a> {
         for pkg in &self.args.package {
             command.arg("-p").arg(pkg);
         }
-        if self.args.command.is_none() {
+        if self.args.is_full_build() {
             let default_build_flags = ["--all-targets".to_owned()];
             for flag in self
                 .config
@@ -150,14 +172,24 @@ impl<'a> CargoRunner<'a> {
             command.arg("--features");
             command.arg(features);
         }
+        command.args(cargo::passthrough_args(self.args));
         let config_path = crate::config::flattened_config_path(self.tmpdir);
         command
-            .env(SOCKET_ENV, &ipc_path)
+            .env(SOCKET_ENV, listener.endpoint())
             .env(CONFIG_PATH_ENV, config_path)
             .env(TARGET_DIR, self.target_dir)
             .env(MANIFEST_DIR, self.manifest_dir)
-            .env(RUSTC_PATH, rustc_path)
+            .env(RUSTC_PATH, &rustc_path)
             .env("RUSTC_WRAPPER", cackle_exe()?);
+        if let Some(wrapper) = self.rustc_wrapper()? {
+            command.env(ORIG_RUSTC_WRAPPER_ENV, wrapper);
+        }
+        if matches!(self.args.command, Some(crate::cli::Command::Test(..))) {
+            command.env("RUSTDOC", self.write_rustdoc_wrapper(&rustc_path)?);
+        }
+        if self.args.trace_sandbox {
+            command.env(TRACE_SANDBOX_ENV, "1");
+        }
 
         self.crate_index.add_internal_env(&mut command);
 
@@ -178,27 +210,26 @@ impl<'a> CargoRunner<'a> {
             output_waiter.stdout_thread = Some(start_output_pass_through_thread(
                 "cargo-stdout-pass-through",
                 cargo_process.stdout.take().unwrap(),
+                StreamCapture::new(self.build_output.clone()),
             )?);
             output_waiter.stderr_thread = Some(start_output_pass_through_thread(
                 "cargo-stderr-pass-through",
                 cargo_process.stderr.take().unwrap(),
+                StreamCapture::new(self.build_output.clone()),
             )?);
         }
 
-        listener
-            .set_nonblocking(true)
-            .context("Failed to set socket to non-blocking")?;
         let (error_send, error_recv) = channel();
+        let (request_send, request_recv) = channel();
+        let subscribers = Subscribers::default();
         loop {
             if let Some(status) = cargo_process.try_wait()? {
                 drop(listener);
-                // Deleting the socket is best-effort only, so we don't report an error if we can't.
-                let _ = std::fs::remove_file(&ipc_path);
                 if let Ok(error) = error_recv.try_recv() {
                     return Err(error);
                 }
                 if status.code() != Some(0) {
-                    bail!("`cargo` exited with non-zero exit status");
+                    return Err(BuildFailed.into());
                 }
                 break;
             }
@@ -206,25 +237,51 @@ impl<'a> CargoRunner<'a> {
                 log::info!("Killing cargo process");
                 let _ = cargo_process.kill();
             }
-            // We need to concurrently accept connections from our proxy subprocesses and also check to
-            // see if our main subprocess has terminated. It should be possible to do this without
-            // polling... but it's so much simpler to just poll.
-            if let Ok((mut connection, _)) = listener.accept() {
-                let request: rpc::Request = rpc::read_from_stream(&mut connection)
-                    .context("Malformed request from subprocess")?;
-                let request_handler = (request_creator)(request);
+            // We need to concurrently accept connections from our proxy subprocesses, handle requests
+            // that arrive on those connections and also check to see if our main subprocess has
+            // terminated. It should be possible to do this without polling... but it's so much
+            // simpler to just poll.
+            let mut idle = true;
+            if let Some(mut connection) = listener.accept()? {
+                idle = false;
+                if let Err(error) = rpc::accept(connection.as_mut()) {
+                    let _ = cargo_process.kill();
+                    let _ = cargo_process.wait();
+                    if error.is::<rpc::VersionMismatch>() {
+                        return Err(error);
+                    }
+                    return Err(error.context("Malformed handshake from subprocess"));
+                }
+                let writer = Arc::new(Mutex::new(connection.try_clone()?));
+                subscribers.add(&writer);
+                start_connection_reader(
+                    connection,
+                    writer,
+                    request_send.clone(),
+                    error_send.clone(),
+                )?;
+            }
+            while let Ok((envelope, writer)) = request_recv.try_recv() {
+                idle = false;
+                let request_handler = (request_creator)(envelope.request);
                 let error_send = error_send.clone();
                 let abort_sender = abort_sender.clone();
+                let subscribers = subscribers.clone();
                 std::thread::Builder::new()
                     .name("Request handler".to_owned())
                     .spawn(move || {
-                        if let Err(error) =
-                            process_request(request_handler, connection, abort_sender)
-                        {
+                        if let Err(error) = process_request(
+                            request_handler,
+                            envelope.id,
+                            &writer,
+                            abort_sender,
+                            &subscribers,
+                        ) {
                             let _ = error_send.send(error);
                         }
                     })?;
-            } else {
+            }
+            if idle {
                 // Avoid using too much CPU with our polling.
                 std::thread::sleep(Duration::from_millis(10));
             }
@@ -232,6 +289,64 @@ impl<'a> CargoRunner<'a> {
 
         Ok(output_waiter)
     }
+
+    /// Returns the rustc wrapper that the user had configured, if any and if we're configured to
+    /// chain to it. Our subprocesses then invoke rustc via this wrapper, so that tools like sccache
+    /// keep working even though we've taken over `RUSTC_WRAPPER`.
+    fn rustc_wrapper(&self) -> Result<Option<PathBuf>> {
+        let Some(mut wrapper) = cargo::configured_rustc_wrapper(self.manifest_dir) else {
+            return Ok(None);
+        };
+        // Resolve bare names now, so that our subprocesses know which file to make available
+        // inside the rustc sandbox.
+        if wrapper.components().count() == 1 {
+            if let Some(resolved) = wrapper.to_str().and_then(crate::sandbox::find_in_path) {
+                wrapper = resolved;
+            }
+        }
+        // If we're somehow already the configured wrapper, chaining to ourselves would recurse.
+        if wrapper == cackle_exe()? {
+            return Ok(None);
+        }
+        let rustc_sandboxed = crate::sandbox::rustc_is_sandboxed(&self.config.raw.rustc);
+        if !self.config.raw.wrapper.passthrough(rustc_sandboxed) {
+            log::info!(
+                "Not using rustc wrapper `{}` since wrapper.passthrough is {}",
+                wrapper.display(),
+                match self.config.raw.wrapper.passthrough {
+                    Some(_) => "false",
+                    None => "off by default when rustc is sandboxed",
+                }
+            );
+            return Ok(None);
+        }
+        log::info!("Invoking rustc via wrapper `{}`", wrapper.display());
+        Ok(Some(wrapper))
+    }
+
+    /// Writes a script that cargo can invoke in place of rustdoc, which calls back into us so that
+    /// we can run doctests in a sandbox. Returns the path to the script.
+    fn write_rustdoc_wrapper(&self, rustc_path: &Path) -> Result<PathBuf> {
+        let rustdoc_path = if rustc_path.is_absolute() {
+            rustc_path.with_file_name("rustdoc")
+        } else {
+            PathBuf::from("rustdoc")
+        };
+        let wrapper_path = self.tmpdir.join("rustdoc");
+        std::fs::write(
+            &wrapper_path,
+            format!(
+                "#!/usr/bin/env bash\n\
+                 exec \"{}\" {} \"{}\" \"$@\"\n",
+                cackle_exe()?.display(),
+                subprocess::PROXY_RUSTDOC_ARG,
+                rustdoc_path.display()
+            ),
+        )
+        .with_context(|| format!("Failed to write `{}`", wrapper_path.display()))?;
+        std::fs::set_permissions(&wrapper_path, std::fs::Permissions::from_mode(0o755))?;
+        Ok(wrapper_path)
+    }
 }
 
 /// Returns the path to rustc as provided by rustup. If rustup is available, then we bypass it when
@@ -272,6 +387,7 @@ impl CargoOutputWaiter {
 fn start_output_pass_through_thread(
     thread_name: &str,
     mut reader: impl std::io::Read + Send + 'static,
+    mut capture: StreamCapture,
 ) -> Result<JoinHandle<()>> {
     Ok(std::thread::Builder::new()
         .name(thread_name.to_owned())
@@ -281,6 +397,8 @@ fn start_output_pass_through_thread(
                 if size == 0 {
                     break;
                 }
+                // Keep a copy, so that the UI can show output that it's holding back.
+                capture.append(&output[..size]);
                 // For now, we just send all output to stderr regardless of whether it was
                 // originally on stdout or stderr. We lock stderr when the UI (full_term.rs) is
                 // active, so the following lock will block the whole time the UI is active. Once
@@ -290,19 +408,100 @@ fn start_output_pass_through_thread(
         })?)
 }
 
+/// The sending half of a connection to a proxy subprocess. Shared by the threads that are handling
+/// requests received on that connection.
+type SharedWriter = Arc<Writer>;
+
+type Writer = Mutex<Box<dyn transport::Connection>>;
+
+/// The connections to our proxy subprocesses, so that we can send them notifications.
+#[derive(Default, Clone)]
+struct Subscribers {
+    writers: Arc<Mutex<Vec<Weak<Writer>>>>,
+}
+
+impl Subscribers {
+    fn add(&self, writer: &SharedWriter) {
+        let mut writers = self.writers.lock().unwrap();
+        writers.retain(|writer| writer.strong_count() > 0);
+        writers.push(Arc::downgrade(writer));
+    }
+
+    fn notify(&self, notification: rpc::Notification) {
+        let writers: Vec<SharedWriter> = self
+            .writers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for writer in writers {
+            // The subprocess may have already exited, in which case there's nobody to notify.
+            let _ = rpc::write_to_stream(
+                &rpc::ServerMessage::Notification(notification),
+                writer.lock().unwrap().as_mut(),
+            );
+        }
+    }
+}
+
+/// Starts a thread that reads requests from `connection` and passes them, together with `writer`,
+/// which should be used to send the responses, to `request_send`.
+fn start_connection_reader(
+    mut connection: Box<dyn transport::Connection>,
+    writer: SharedWriter,
+    request_send: Sender<(rpc::RequestEnvelope, SharedWriter)>,
+    error_send: Sender<anyhow::Error>,
+) -> Result<()> {
+    std::thread::Builder::new()
+        .name("Connection reader".to_owned())
+        .spawn(move || loop {
+            match rpc::read_from_stream(connection.as_mut()) {
+                Ok(envelope) => {
+                    if request_send.send((envelope, writer.clone())).is_err() {
+                        return;
+                    }
+                }
+                Err(error) => {
+                    let closed = error
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(|e| e.kind() == std::io::ErrorKind::UnexpectedEof);
+                    if !closed {
+                        let _ = error_send.send(error.context("Malformed request from subprocess"));
+                    }
+                    return;
+                }
+            }
+        })?;
+    Ok(())
+}
+
 fn process_request(
     mut request_handler: RequestHandler,
-    mut connection: UnixStream,
+    id: u64,
+    writer: &SharedWriter,
     abort_sender: Sender<()>,
+    subscribers: &Subscribers,
 ) -> Result<()> {
     let response = request_handler.handle_request();
-    let can_continue = response.as_ref().unwrap_or(&Outcome::GiveUp);
-    if can_continue == &Outcome::GiveUp {
+    let can_continue = *response.as_ref().unwrap_or(&Outcome::GiveUp);
+    if can_continue == Outcome::GiveUp {
         // Send an abort signal to cargo, otherwise if we're not capturing the output from cargo,
         // we'll see errors not related to the problem encountered.
         let _ = abort_sender.send(());
     }
-    rpc::write_to_stream(&can_continue, &mut connection)?;
+    if request_handler.config_changed() {
+        // We notify before responding, so that the subprocess that sent this request will know that
+        // the config changed by the time it gets its response.
+        subscribers.notify(rpc::Notification::ConfigChanged);
+    }
+    rpc::write_to_stream(
+        &rpc::ServerMessage::Response {
+            id,
+            outcome: can_continue,
+        },
+        writer.lock().unwrap().as_mut(),
+    )?;
     response?;
     Ok(())
 }
@@ -1,7 +1,11 @@
+use crate::cli::Args;
 use crate::config::CommonConfig;
-use crate::Args;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
 use clap::Parser;
 use std::path::Path;
+use std::path::PathBuf;
 use std::process::Command;
 
 /// The name of the default cargo profile that we use.
@@ -34,19 +38,11 @@ pub(crate) fn command(
     if args.colour.should_use_colour() {
         command.arg("--color=always");
     }
-    let extra_args = match &args.command {
-        Some(crate::Command::Test(cargo_options)) => {
-            command.arg("test");
-            cargo_options.remaining.as_slice()
-        }
-        Some(crate::Command::Run(cargo_options)) => {
-            command.arg("run");
-            cargo_options.remaining.as_slice()
-        }
-        _ => {
-            command.arg(base_command);
-            &[]
-        }
+    match &args.command {
+        Some(crate::cli::Command::Test(..)) => command.arg("test"),
+        Some(crate::cli::Command::Run(..)) => command.arg("run"),
+        Some(crate::cli::Command::Cargo(cargo_args)) => command.arg(&cargo_args[0]),
+        _ => command.arg(base_command),
     };
     command
         .arg("--config")
@@ -64,6 +60,355 @@ pub(crate) fn command(
     let profile = profile_name(args, config);
     command.arg("--profile").arg(profile);
     command.env(PROFILE_NAME_ENV, profile);
-    command.args(extra_args);
     command
 }
+
+/// Returns the arguments that were given after the cargo subcommand and which should be passed
+/// through to cargo. These should be added after any arguments that we add ourselves, since they may
+/// include `--` followed by arguments for the binary being run.
+pub(crate) fn passthrough_args(args: &Args) -> &[String] {
+    match &args.command {
+        Some(crate::cli::Command::Test(cargo_options))
+        | Some(crate::cli::Command::Run(cargo_options)) => cargo_options.remaining.as_slice(),
+        Some(crate::cli::Command::Cargo(cargo_args)) => &cargo_args[1..],
+        _ => &[],
+    }
+}
+
+/// Flags that cargo accepts, but which we also need to know about.
+const TAKEN_FLAGS: &[&str] = &[
+    "--target",
+    "--profile",
+    "--features",
+    "-F",
+    "--package",
+    "-p",
+];
+
+#[derive(Default, Debug, PartialEq, Eq)]
+struct CargoFlags {
+    target: Option<String>,
+    profile: Option<String>,
+    features: Vec<String>,
+    packages: Vec<String>,
+}
+
+impl Args {
+    /// Moves flags that we need to know about, such as `--target` and `--features`, out of the
+    /// arguments that get passed through to cargo and into our own options. This lets them be given
+    /// either before or after the cargo subcommand, while only being handled in one place.
+    pub(crate) fn take_cargo_flags(&mut self) -> Result<()> {
+        let flags = match &mut self.command {
+            Some(crate::cli::Command::Test(cargo_options))
+            | Some(crate::cli::Command::Run(cargo_options)) => {
+                take_flags(&mut cargo_options.remaining, 0)?
+            }
+            Some(crate::cli::Command::Cargo(cargo_args)) => take_flags(cargo_args, 1)?,
+            _ => return Ok(()),
+        };
+        if flags.target.is_some() {
+            if self.target.is_some() {
+                bail!("`--target` was specified both before and after the cargo subcommand");
+            }
+            self.target = flags.target;
+        }
+        if flags.profile.is_some() {
+            if self.profile.is_some() {
+                bail!("`--profile` was specified both before and after the cargo subcommand");
+            }
+            self.profile = flags.profile;
+        }
+        if !flags.features.is_empty() {
+            let features = self.features.iter().chain(flags.features.iter());
+            self.features = Some(features.cloned().collect::<Vec<_>>().join(","));
+        }
+        self.package.extend(flags.packages);
+        Ok(())
+    }
+}
+
+/// Removes flags in `TAKEN_FLAGS` from `args`, ignoring the first `start` arguments and anything
+/// after `--`.
+fn take_flags(args: &mut Vec<String>, start: usize) -> Result<CargoFlags> {
+    let mut flags = CargoFlags::default();
+    let mut remaining = std::mem::take(args).into_iter();
+    args.extend(remaining.by_ref().take(start));
+    while let Some(arg) = remaining.next() {
+        if arg == "--" {
+            args.push(arg);
+            args.extend(remaining);
+            break;
+        }
+        // `--release` is equivalent to `--profile release`. We always pass `--profile` to cargo,
+        // which cargo doesn't permit in combination with `--release`.
+        if arg == "--release" {
+            set_once(&mut flags.profile, "--profile", "release".to_owned())?;
+            continue;
+        }
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        if !TAKEN_FLAGS.contains(&name) {
+            args.push(arg);
+            continue;
+        }
+        let value = match value {
+            Some(value) => value.to_owned(),
+            None => remaining
+                .next()
+                .ok_or_else(|| anyhow!("`{name}` requires a value"))?,
+        };
+        match name {
+            "--target" => set_once(&mut flags.target, name, value)?,
+            "--profile" => set_once(&mut flags.profile, name, value)?,
+            "--features" | "-F" => flags.features.push(value),
+            _ => flags.packages.push(value),
+        }
+    }
+    Ok(flags)
+}
+
+fn set_once(dest: &mut Option<String>, name: &str, value: String) -> Result<()> {
+    if dest.is_some() {
+        bail!("`{name}` was specified more than once");
+    }
+    *dest = Some(value);
+    Ok(())
+}
+
+/// Returns the rustc wrapper, such as sccache, that cargo would use if we didn't set
+/// `RUSTC_WRAPPER` ourselves. Like cargo, we check the environment first, then the cargo config
+/// files that apply to `dir`.
+pub(crate) fn configured_rustc_wrapper(dir: &Path) -> Option<PathBuf> {
+    for var in ["RUSTC_WRAPPER", "CARGO_BUILD_RUSTC_WRAPPER"] {
+        if let Some(value) = std::env::var_os(var) {
+            // Cargo treats an empty value as meaning no wrapper.
+            return (!value.is_empty()).then(|| PathBuf::from(value));
+        }
+    }
+    rustc_wrapper_from_config_files(dir, cargo_home().as_deref())
+}
+
+fn rustc_wrapper_from_config_files(dir: &Path, cargo_home: Option<&Path>) -> Option<PathBuf> {
+    for (base, table) in config_files(dir, cargo_home) {
+        let Some(wrapper) = table
+            .get("build")
+            .and_then(|build| build.get("rustc-wrapper"))
+            .and_then(|wrapper| wrapper.as_str())
+        else {
+            continue;
+        };
+        if wrapper.is_empty() {
+            return None;
+        }
+        // Values containing a path separator are relative to `base`. Other values are looked up
+        // on the PATH.
+        if !wrapper.contains('/') {
+            return Some(PathBuf::from(wrapper));
+        }
+        return Some(base.join(wrapper));
+    }
+    None
+}
+
+/// Returns the directories that `cargo vendor` populated and that the cargo config files that
+/// apply to `dir` use as replacements for other sources, e.g. crates.io. Packages from these
+/// sources have their source in a subdirectory of the vendor directory rather than in the cargo
+/// registry.
+pub(crate) fn vendored_source_dirs(dir: &Path) -> Vec<PathBuf> {
+    vendored_source_dirs_from_config_files(dir, cargo_home().as_deref())
+}
+
+fn vendored_source_dirs_from_config_files(dir: &Path, cargo_home: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for (base, table) in config_files(dir, cargo_home) {
+        let Some(sources) = table.get("source").and_then(|s| s.as_table()) else {
+            continue;
+        };
+        let replacements: Vec<&str> = sources
+            .values()
+            .filter_map(|source| source.get("replace-with")?.as_str())
+            .collect();
+        for (name, source) in sources {
+            let Some(directory) = source.get("directory").and_then(|d| d.as_str()) else {
+                continue;
+            };
+            if replacements.contains(&name.as_str()) {
+                dirs.push(base.join(directory));
+            }
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+pub(crate) fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")))
+}
+
+/// Returns the contents of the cargo config files that apply to `dir`, most specific first, each
+/// with the directory that relative paths within it are relative to. As per cargo, that's the
+/// parent of the `.cargo` directory containing the config file.
+fn config_files(dir: &Path, cargo_home: Option<&Path>) -> Vec<(PathBuf, toml::Table)> {
+    let project_dirs = dir.ancestors().map(|ancestor| ancestor.join(".cargo"));
+    let mut files = Vec::new();
+    for cargo_dir in project_dirs.chain(cargo_home.map(Path::to_owned)) {
+        for filename in ["config.toml", "config"] {
+            let path = cargo_dir.join(filename);
+            let Ok(contents) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let Ok(table) = contents.parse::<toml::Table>() else {
+                log::warn!("Failed to parse `{}`", path.display());
+                continue;
+            };
+            let base = cargo_dir.parent().unwrap_or(&cargo_dir).to_owned();
+            files.push((base, table));
+            // Cargo only reads `config` if there's no `config.toml`.
+            break;
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::OuterArgs;
+    use crate::cli::OuterCommand;
+    use clap::Parser;
+    use std::path::Path;
+
+    #[test]
+    fn take_flags() {
+        let mut args = [
+            "check",
+            "--features=a,b",
+            "-F",
+            "c",
+            "--bin",
+            "x",
+            "--release",
+        ]
+        .map(str::to_owned)
+        .to_vec();
+        let args_after = ["--", "--target", "y"].map(str::to_owned);
+        args.extend(args_after.iter().cloned());
+        let flags = super::take_flags(&mut args, 1).unwrap();
+        assert_eq!(
+            flags,
+            super::CargoFlags {
+                target: None,
+                profile: Some("release".to_owned()),
+                features: vec!["a,b".to_owned(), "c".to_owned()],
+                packages: vec![],
+            }
+        );
+        assert_eq!(args, ["check", "--bin", "x", "--", "--target", "y"]);
+
+        let mut args = vec!["--target".to_owned()];
+        assert!(super::take_flags(&mut args, 0).is_err());
+        let mut args = ["--profile", "a", "--profile=b"]
+            .map(str::to_owned)
+            .to_vec();
+        assert!(super::take_flags(&mut args, 0).is_err());
+    }
+
+    #[test]
+    fn cargo_cackle_passthrough() {
+        let parse = |args: &[&str]| {
+            let outer = OuterArgs::try_parse_from(args).unwrap();
+            let OuterCommand::Acl(mut args) = outer.command;
+            args.take_cargo_flags().map(|_| args)
+        };
+        let args = parse(&[
+            "cargo-cackle",
+            "cackle",
+            "--features",
+            "a",
+            "check",
+            "--features",
+            "b",
+            "-p",
+            "foo",
+            "--target=x86_64-unknown-linux-gnu",
+            "--lib",
+        ])
+        .unwrap();
+        assert_eq!(args.features.as_deref(), Some("a,b"));
+        assert_eq!(args.package, ["foo"]);
+        assert_eq!(args.target.as_deref(), Some("x86_64-unknown-linux-gnu"));
+        assert_eq!(super::passthrough_args(&args), ["--lib"]);
+        assert!(matches!(&args.command, Some(crate::cli::Command::Cargo(c)) if c[0] == "check"));
+
+        assert!(parse(&["cargo-acl", "acl", "--target", "a", "test", "--target", "b"]).is_err());
+    }
+
+    #[test]
+    fn rustc_wrapper_from_config_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let home = root.join("home");
+        let project = root.join("project");
+        let member = project.join("member");
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::create_dir_all(member.join(".cargo")).unwrap();
+        std::fs::create_dir_all(project.join(".cargo")).unwrap();
+
+        let get = || super::rustc_wrapper_from_config_files(&member, Some(&home));
+        assert_eq!(get(), None);
+
+        std::fs::write(
+            home.join("config.toml"),
+            "[build]\nrustc-wrapper = \"sccache\"\n",
+        )
+        .unwrap();
+        assert_eq!(get(), Some(Path::new("sccache").to_owned()));
+
+        // Config closer to the project takes precedence and relative paths are resolved relative
+        // to the directory containing `.cargo`.
+        std::fs::write(
+            project.join(".cargo/config.toml"),
+            "[build]\nrustc-wrapper = \"tools/wrap\"\n",
+        )
+        .unwrap();
+        assert_eq!(get(), Some(project.join("tools/wrap")));
+
+        // An empty value disables any wrapper configured further out.
+        std::fs::write(
+            member.join(".cargo/config"),
+            "[build]\nrustc-wrapper = \"\"\n",
+        )
+        .unwrap();
+        assert_eq!(get(), None);
+    }
+
+    #[test]
+    fn vendored_source_dirs_from_config_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let project = tmp.path().join("project");
+        std::fs::create_dir_all(project.join(".cargo")).unwrap();
+        let get = || super::vendored_source_dirs_from_config_files(&project, None);
+        assert!(get().is_empty());
+
+        // This is what `cargo vendor` tells you to add to your config.
+        std::fs::write(
+            project.join(".cargo/config.toml"),
+            indoc::indoc! {r#"
+                [source.crates-io]
+                replace-with = "vendored-sources"
+
+                [source.vendored-sources]
+                directory = "vendor"
+
+                [source.unused]
+                directory = "/elsewhere"
+            "#},
+        )
+        .unwrap();
+        assert_eq!(get(), vec![project.join("vendor")]);
+    }
+}
@@ -1,28 +1,69 @@
 //! Defines the communication protocol between the proxy subprocesses and the parent process.
 
+use super::transport::Connection;
 use crate::config::SandboxConfig;
 use crate::crate_index::CrateSel;
 use crate::link_info::LinkInfo;
+use crate::link_info::Platform;
+use crate::link_info::RustcInvocationId;
 use crate::location::SourceLocation;
 use crate::outcome::Outcome;
+use crate::sandbox::AccessKind;
+use crate::sandbox::CapturedWrite;
+use crate::sandbox::DeniedAccess;
+use crate::sandbox::NetworkRequest;
+use crate::sandbox::ResourceLimit;
 use anyhow::Context;
 use anyhow::Result;
+use fxhash::FxHashMap;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
+use std::fmt::Display;
 use std::io::Read;
 use std::io::Write;
-use std::os::unix::net::UnixStream;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
 
-/// A communication channel to the main Cackle process.
+/// The version of the protocol spoken between the proxy subprocesses and the parent process. This
+/// must be incremented whenever a change is made to `Request`, `Outcome` or any of the types that
+/// they contain that would cause an older or newer binary to misinterpret messages. Additions that
+/// can be safely ignored should instead be advertised via `CAPABILITIES`.
+pub(crate) const PROTOCOL_VERSION: u32 = 6;
+
+/// Optional protocol features supported by this binary.
+pub(crate) const CAPABILITIES: &[&str] = &["proxy-rustdoc", "captured-writes"];
+
+/// A communication channel to the main Cackle process. A connection is opened when the first
+/// request is sent and is then shared by all clones of the client, with requests from different
+/// threads being multiplexed over it.
+#[derive(Clone)]
 pub(crate) struct RpcClient {
-    socket_path: PathBuf,
+    endpoint: String,
+    connection: Arc<Mutex<Option<Arc<ClientConnection>>>>,
+    config_changed: Arc<AtomicBool>,
+}
+
+struct ClientConnection {
+    writer: Mutex<Box<dyn Connection>>,
+    /// Requests that we're awaiting responses to, keyed by request ID.
+    pending: Arc<Mutex<FxHashMap<u64, Sender<Outcome>>>>,
+    next_id: AtomicU64,
 }
 
 impl RpcClient {
-    pub(crate) fn new(socket_path: PathBuf) -> Self {
-        RpcClient { socket_path }
+    pub(crate) fn new(endpoint: String) -> Self {
+        RpcClient {
+            endpoint,
+            connection: Default::default(),
+            config_changed: Default::default(),
+        }
     }
 
     /// Advises the parent process that the specified crate uses unsafe.
@@ -31,61 +72,255 @@ impl RpcClient {
         crate_sel: &CrateSel,
         locations: Vec<SourceLocation>,
     ) -> Result<Outcome> {
-        let mut ipc = self.connect()?;
-        let request = Request::CrateUsesUnsafe(UnsafeUsage {
+        self.send(Request::CrateUsesUnsafe(UnsafeUsage {
             crate_sel: crate_sel.clone(),
             locations,
-        });
-        write_to_stream(&request, &mut ipc)?;
-        read_from_stream(&mut ipc)
+        }))
     }
 
     pub(crate) fn rustc_started(&self, crate_sel: &CrateSel) -> Result<Outcome> {
-        let mut ipc = self.connect()?;
-        let request = Request::RustcStarted(crate_sel.clone());
-        write_to_stream(&request, &mut ipc)?;
-        read_from_stream(&mut ipc)
+        self.send(Request::RustcStarted(crate_sel.clone()))
     }
 
     pub(crate) fn linker_invoked(&self, info: LinkInfo) -> Result<Outcome> {
-        let mut ipc = self.connect()?;
-        write_to_stream(&Request::LinkerInvoked(info), &mut ipc)?;
-        read_from_stream(&mut ipc)
+        self.send(Request::LinkerInvoked(info))
     }
 
     pub(crate) fn bin_execution_complete(&self, info: BinExecutionOutput) -> Result<Outcome> {
-        let mut ipc = self.connect()?;
-        write_to_stream(&Request::BinExecutionComplete(info), &mut ipc)?;
-        read_from_stream(&mut ipc)
+        self.send(Request::BinExecutionComplete(Box::new(info)))
     }
 
     pub(crate) fn rustc_complete(&self, info: RustcOutput) -> Result<Outcome> {
-        let mut ipc = self.connect()?;
-        write_to_stream(&Request::RustcComplete(info), &mut ipc)?;
-        read_from_stream(&mut ipc)
-    }
-
-    /// Creates a new connection to the socket. We only send a single request/response on each
-    /// connection because it makes things simpler. In general a single request/response is all we
-    /// need anyway.
-    fn connect(&self) -> Result<UnixStream> {
-        UnixStream::connect(&self.socket_path).with_context(|| {
-            format!(
-                "Failed to connect to socket `{}`",
-                self.socket_path.display()
-            )
+        self.send(Request::RustcComplete(info))
+    }
+
+    /// Advises the parent process of what we're currently doing.
+    pub(crate) fn progress(&self, update: ProgressUpdate) -> Result<Outcome> {
+        self.send(Request::Progress(update))
+    }
+
+    /// Returns whether the parent process has advised us that the config has changed since the
+    /// last time this was called.
+    pub(crate) fn take_config_changed(&self) -> bool {
+        self.config_changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Sends `request` and waits for the response to it.
+    fn send(&self, request: Request) -> Result<Outcome> {
+        let connection = self.connection()?;
+        let id = connection.next_id.fetch_add(1, Ordering::Relaxed);
+        let (response_send, response_recv) = std::sync::mpsc::channel();
+        connection.pending.lock().unwrap().insert(id, response_send);
+        write_to_stream(
+            &RequestEnvelope { id, request },
+            connection.writer.lock().unwrap().as_mut(),
+        )?;
+        response_recv
+            .recv()
+            .context("Connection to parent process closed before response was received")
+    }
+
+    /// Returns our connection to the parent process, creating it if it doesn't already exist.
+    fn connection(&self) -> Result<Arc<ClientConnection>> {
+        let mut connection = self.connection.lock().unwrap();
+        if let Some(connection) = connection.as_ref() {
+            return Ok(connection.clone());
+        }
+        let new_connection = Arc::new(self.connect()?);
+        *connection = Some(new_connection.clone());
+        Ok(new_connection)
+    }
+
+    /// Creates a new connection to the socket, performs the handshake and starts a thread to read
+    /// responses and notifications from the parent process.
+    fn connect(&self) -> Result<ClientConnection> {
+        let mut stream = super::transport::connect(&self.endpoint)?;
+        write_to_stream(&Hello::new(), stream.as_mut())?;
+        match read_from_stream(stream.as_mut()).context("Failed to read handshake response")? {
+            HelloResponse::Accepted { .. } => {}
+            HelloResponse::VersionMismatch { expected } => {
+                return Err(VersionMismatch {
+                    proxy_version: PROTOCOL_VERSION,
+                    parent_version: expected,
+                }
+                .into())
+            }
+        }
+        let pending: Arc<Mutex<FxHashMap<u64, Sender<Outcome>>>> = Default::default();
+        let mut reader = stream.try_clone()?;
+        let reader_pending = pending.clone();
+        let config_changed = self.config_changed.clone();
+        std::thread::Builder::new()
+            .name("RPC reader".to_owned())
+            .spawn(move || {
+                while let Ok(message) = read_from_stream(reader.as_mut()) {
+                    match message {
+                        ServerMessage::Response { id, outcome } => {
+                            if let Some(sender) = reader_pending.lock().unwrap().remove(&id) {
+                                let _ = sender.send(outcome);
+                            }
+                        }
+                        ServerMessage::Notification(Notification::ConfigChanged) => {
+                            config_changed.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+                // Dropping the senders for any outstanding requests will cause them to fail.
+                reader_pending.lock().unwrap().clear();
+            })?;
+        Ok(ClientConnection {
+            writer: Mutex::new(stream),
+            pending,
+            next_id: AtomicU64::new(0),
         })
     }
 }
 
+/// A request from a proxy subprocess, together with an ID that the parent process includes in its
+/// response.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub(crate) struct RequestEnvelope {
+    pub(crate) id: u64,
+    pub(crate) request: Request,
+}
+
+/// A message sent by the parent process to a proxy subprocess.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub(crate) enum ServerMessage {
+    /// The response to the request with the specified ID.
+    Response { id: u64, outcome: Outcome },
+    /// Something the parent process is advising us of, not in response to any particular request.
+    Notification(Notification),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub(crate) enum Notification {
+    /// The config has been changed, e.g. because the user accepted a fix for a problem.
+    ConfigChanged,
+}
+
+/// The first message sent on each connection by the proxy subprocess.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub(crate) struct Hello {
+    pub(crate) version: u32,
+    pub(crate) capabilities: Vec<String>,
+}
+
+/// The parent process's reply to `Hello`.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub(crate) enum HelloResponse {
+    Accepted { capabilities: Vec<String> },
+    VersionMismatch { expected: u32 },
+}
+
+impl Hello {
+    fn new() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+/// An error indicating that a proxy subprocess and the parent process were built from different
+/// versions of Cackle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct VersionMismatch {
+    /// The protocol version of the proxy subprocess. Proxies from before the handshake was added
+    /// are reported as version 0.
+    pub(crate) proxy_version: u32,
+    pub(crate) parent_version: u32,
+}
+
+impl std::error::Error for VersionMismatch {}
+
+impl Display for VersionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "A proxy subprocess uses RPC protocol version {}, but the parent process uses version {}. \
+             This usually means that a binary in the target directory was wrapped by a different \
+             version of cackle",
+            self.proxy_version, self.parent_version
+        )
+    }
+}
+
+/// Performs the server side of the handshake on a newly accepted connection. Requests can then be
+/// read as `RequestEnvelope`s. If the proxy subprocess speaks a different protocol version, then the
+/// returned error will be a `VersionMismatch`.
+pub(crate) fn accept(stream: &mut dyn Connection) -> Result<()> {
+    let legacy_proxy = || -> anyhow::Error {
+        VersionMismatch {
+            proxy_version: 0,
+            parent_version: PROTOCOL_VERSION,
+        }
+        .into()
+    };
+    let first: serde_json::Value = match read_from_stream(stream) {
+        Ok(first) => first,
+        // Proxies from before the handshake was added used a different frame format.
+        Err(error) if matches!(error.downcast_ref(), Some(FrameError::BadMagic(_))) => {
+            return Err(legacy_proxy());
+        }
+        Err(error) => return Err(error),
+    };
+    let Ok(hello) = serde_json::from_value::<Hello>(first) else {
+        return Err(legacy_proxy());
+    };
+    if hello.version != PROTOCOL_VERSION {
+        let _ = write_to_stream(
+            &HelloResponse::VersionMismatch {
+                expected: PROTOCOL_VERSION,
+            },
+            stream,
+        );
+        return Err(VersionMismatch {
+            proxy_version: hello.version,
+            parent_version: PROTOCOL_VERSION,
+        }
+        .into());
+    }
+    for capability in CAPABILITIES {
+        if !hello.capabilities.iter().any(|c| c == capability) {
+            log::info!("Proxy subprocess doesn't support `{capability}`");
+        }
+    }
+    write_to_stream(
+        &HelloResponse::Accepted {
+            capabilities: Hello::new().capabilities,
+        },
+        stream,
+    )
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub(crate) enum Request {
     /// Advises that the specified crate failed to compile because it uses unsafe.
     CrateUsesUnsafe(UnsafeUsage),
     LinkerInvoked(LinkInfo),
-    BinExecutionComplete(BinExecutionOutput),
+    BinExecutionComplete(Box<BinExecutionOutput>),
     RustcStarted(CrateSel),
     RustcComplete(RustcOutput),
+    Progress(ProgressUpdate),
+}
+
+/// Sent periodically by proxy subprocesses so that the UI can show what the build is doing.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
+pub(crate) struct ProgressUpdate {
+    pub(crate) crate_sel: CrateSel,
+    pub(crate) phase: BuildPhase,
+    /// How far through `phase` the subprocess is, if known.
+    pub(crate) percent: Option<u8>,
+}
+
+/// What a proxy subprocess is doing. Phases are ordered in the sequence in which they happen.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub(crate) enum BuildPhase {
+    Compiling,
+    Linking,
+    Running,
+    Finished,
 }
 
 /// The output from running a binary such as a build script or a test.
@@ -100,12 +335,72 @@ pub(crate) struct BinExecutionOutput {
     /// A display string for how the sandbox was configured (e.g. the command line). Only present if
     /// the exit code is non-zero.
     pub(crate) sandbox_config_display: Option<String>,
+    /// Requests made via the sandbox's filtering network proxy, if any.
+    pub(crate) network_requests: Vec<NetworkRequest>,
+    /// File and network accesses that were denied by the sandbox. Only populated when tracing is
+    /// enabled.
+    pub(crate) denied_accesses: Vec<DeniedAccess>,
+    /// The resource limit, if any, that the binary was terminated for exceeding.
+    pub(crate) limit_exceeded: Option<ResourceLimit>,
+    /// Writes that the binary made outside of the directories it's permitted to write to. Only
+    /// populated when `capture_writes` is enabled. These writes were discarded.
+    pub(crate) captured_writes: Vec<CapturedWrite>,
+}
+
+impl BinExecutionOutput {
+    /// Returns the hosts that the binary tried to access via the network proxy but wasn't
+    /// permitted to.
+    pub(crate) fn denied_hosts(&self) -> Vec<&str> {
+        let mut hosts: Vec<&str> = self
+            .network_requests
+            .iter()
+            .filter(|request| !request.allowed)
+            .map(|request| request.host.as_str())
+            .collect();
+        hosts.sort();
+        hosts.dedup();
+        hosts
+    }
+
+    /// Returns the directories containing paths that the binary was denied write access to.
+    pub(crate) fn denied_write_dirs(&self) -> Vec<&Path> {
+        let mut dirs: Vec<&Path> = self
+            .denied_accesses
+            .iter()
+            .filter(|access| access.kind == AccessKind::Write)
+            .map(|access| Path::new(&access.target))
+            .filter(|path| path.is_absolute())
+            .filter_map(|path| path.parent())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    /// Returns the existing directories in which the binary tried to write something outside of
+    /// the directories it's permitted to write to.
+    pub(crate) fn captured_write_dirs(&self) -> Vec<&Path> {
+        let mut dirs: Vec<&Path> = self
+            .captured_writes
+            .iter()
+            .filter_map(|write| write.path.parent())
+            .filter(|dir| dir.is_dir())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
 pub(crate) struct RustcOutput {
     pub(crate) crate_sel: CrateSel,
     pub(crate) source_paths: Vec<PathBuf>,
+    pub(crate) platform: Platform,
+    pub(crate) rustc_invocation: RustcInvocationId,
+    /// The rlib that was written, if any.
+    #[serde(default)]
+    pub(crate) rlib_path: Option<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
@@ -114,22 +409,82 @@ pub(crate) struct UnsafeUsage {
     pub(crate) locations: Vec<SourceLocation>,
 }
 
-/// Writes `value` to `stream`. The format used is the length followed by `value` serialised as
-/// JSON.
-pub(crate) fn write_to_stream<T: Serialize>(value: &T, stream: &mut impl Write) -> Result<()> {
+/// The first byte of every frame. This lets us detect a peer that uses a different framing, such as
+/// proxies from before frames were checksummed, which started with a native-width length.
+const FRAME_MAGIC: u8 = 0xCA;
+
+/// The size of a frame header: the magic byte, the payload length and the payload's CRC-32.
+const FRAME_HEADER_LEN: usize = 1 + 4 + 4;
+
+/// The largest payload that we'll accept. Anything larger indicates a corrupt length, which we don't
+/// want to try to allocate.
+const MAX_FRAME_LEN: u32 = 256 * 1024 * 1024;
+
+/// An error indicating that a frame read from a stream was invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FrameError {
+    BadMagic(u8),
+    TooLong(u32),
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+impl std::error::Error for FrameError {}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::BadMagic(byte) => write!(f, "Invalid frame magic byte 0x{byte:02x}"),
+            FrameError::TooLong(len) => write!(f, "Frame length {len} exceeds maximum"),
+            FrameError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Frame checksum mismatch: expected 0x{expected:08x}, got 0x{actual:08x}"
+            ),
+        }
+    }
+}
+
+/// Writes `value` to `stream`. The format used is a frame header consisting of `FRAME_MAGIC`, the
+/// length of the payload as a little-endian u32 and the CRC-32 of the payload, followed by the
+/// payload, which is `value` serialised as JSON.
+pub(crate) fn write_to_stream<T: Serialize>(
+    value: &T,
+    stream: &mut (impl Write + ?Sized),
+) -> Result<()> {
     let serialized = serde_json::to_string(value)?;
-    stream.write_all(&serialized.len().to_le_bytes())?;
-    stream.write_all(serialized.as_bytes())?;
+    let len = u32::try_from(serialized.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .with_context(|| format!("Message of {} bytes is too large", serialized.len()))?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + serialized.len());
+    frame.push(FRAME_MAGIC);
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(serialized.as_bytes()).to_le_bytes());
+    frame.extend_from_slice(serialized.as_bytes());
+    stream.write_all(&frame)?;
     Ok(())
 }
 
-/// Reads a value of type `T` from `stream`. Format is the same as for `write_to_stream`.
-pub(crate) fn read_from_stream<T: DeserializeOwned>(stream: &mut impl Read) -> Result<T> {
-    let mut len_bytes = [0u8; std::mem::size_of::<usize>()];
-    stream.read_exact(&mut len_bytes)?;
-    let len = usize::from_le_bytes(len_bytes);
-    let mut buf = vec![0u8; len];
+/// Reads a value of type `T` from `stream`. Format is the same as for `write_to_stream`. If the
+/// frame is invalid, the returned error will be a `FrameError`.
+pub(crate) fn read_from_stream<T: DeserializeOwned>(
+    stream: &mut (impl Read + ?Sized),
+) -> Result<T> {
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    stream.read_exact(&mut header)?;
+    if header[0] != FRAME_MAGIC {
+        return Err(FrameError::BadMagic(header[0]).into());
+    }
+    let len = u32::from_le_bytes(header[1..5].try_into().unwrap());
+    if len > MAX_FRAME_LEN {
+        return Err(FrameError::TooLong(len).into());
+    }
+    let expected = u32::from_le_bytes(header[5..9].try_into().unwrap());
+    let mut buf = vec![0u8; len as usize];
     stream.read_exact(&mut buf)?;
+    let actual = crc32fast::hash(&buf);
+    if actual != expected {
+        return Err(FrameError::ChecksumMismatch { expected, actual }.into());
+    }
     let serialized = std::str::from_utf8(&buf)?;
     serde_json::from_str(serialized).with_context(|| format!("Invalid message `{serialized}`"))
 }
@@ -137,6 +492,7 @@ pub(crate) fn read_from_stream<T: DeserializeOwned>(stream: &mut impl Read) -> R
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::os::unix::net::UnixStream;
     use std::path::Path;
 
     #[test]
@@ -152,4 +508,215 @@ mod tests {
 
         assert_eq!(req, req2);
     }
+
+    fn crate_sel(name: &str) -> CrateSel {
+        CrateSel::primary(crate::crate_index::testing::pkg_id(name))
+    }
+
+    fn rustc_started() -> Request {
+        Request::RustcStarted(crate_sel("foo"))
+    }
+
+    #[test]
+    fn handshake() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        write_to_stream(&Hello::new(), &mut client).unwrap();
+        accept(&mut server).unwrap();
+        assert!(matches!(
+            read_from_stream(&mut client).unwrap(),
+            HelloResponse::Accepted { .. }
+        ));
+    }
+
+    #[test]
+    fn multiplexed_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener =
+            super::super::transport::bind(super::super::transport::TransportKind::Unix, dir.path())
+                .unwrap();
+        let client = RpcClient::new(listener.endpoint());
+        let client2 = client.clone();
+        let requests = std::thread::spawn(move || {
+            let other = std::thread::spawn(move || client2.rustc_started(&crate_sel("bar")));
+            let outcome = client.rustc_started(&crate_sel("foo")).unwrap();
+            (outcome, other.join().unwrap().unwrap(), client)
+        });
+        let mut server = loop {
+            if let Some(connection) = listener.accept().unwrap() {
+                break connection;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        accept(server.as_mut()).unwrap();
+        let first: RequestEnvelope = read_from_stream(server.as_mut()).unwrap();
+        let second: RequestEnvelope = read_from_stream(server.as_mut()).unwrap();
+        assert_ne!(first.id, second.id);
+        // Respond out of order, telling the client for "foo" to give up.
+        let mut envelopes = [first, second];
+        envelopes
+            .sort_by_key(|envelope| envelope.request == Request::RustcStarted(crate_sel("foo")));
+        write_to_stream(
+            &ServerMessage::Notification(Notification::ConfigChanged),
+            server.as_mut(),
+        )
+        .unwrap();
+        for envelope in envelopes {
+            let outcome = if envelope.request == Request::RustcStarted(crate_sel("foo")) {
+                Outcome::GiveUp
+            } else {
+                Outcome::Continue
+            };
+            write_to_stream(
+                &ServerMessage::Response {
+                    id: envelope.id,
+                    outcome,
+                },
+                server.as_mut(),
+            )
+            .unwrap();
+        }
+        let (foo, bar, client) = requests.join().unwrap();
+        assert_eq!(foo, Outcome::GiveUp);
+        assert_eq!(bar, Outcome::Continue);
+        assert!(client.take_config_changed());
+        assert!(!client.take_config_changed());
+    }
+
+    #[test]
+    fn handshake_version_mismatch() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        let hello = Hello {
+            version: PROTOCOL_VERSION + 1,
+            capabilities: Vec::new(),
+        };
+        write_to_stream(&hello, &mut client).unwrap();
+        let error = accept(&mut server).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<VersionMismatch>(),
+            Some(&VersionMismatch {
+                proxy_version: PROTOCOL_VERSION + 1,
+                parent_version: PROTOCOL_VERSION,
+            })
+        );
+        assert_eq!(
+            read_from_stream::<HelloResponse>(&mut client).unwrap(),
+            HelloResponse::VersionMismatch {
+                expected: PROTOCOL_VERSION
+            }
+        );
+    }
+
+    #[test]
+    fn request_without_handshake() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        write_to_stream(&rustc_started(), &mut client).unwrap();
+        let error = accept(&mut server).unwrap_err();
+        assert_eq!(
+            error
+                .downcast_ref::<VersionMismatch>()
+                .map(|m| m.proxy_version),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn legacy_frame() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        let serialized = serde_json::to_string(&rustc_started()).unwrap();
+        client.write_all(&serialized.len().to_le_bytes()).unwrap();
+        client.write_all(serialized.as_bytes()).unwrap();
+        let error = accept(&mut server).unwrap_err();
+        assert_eq!(
+            error
+                .downcast_ref::<VersionMismatch>()
+                .map(|m| m.proxy_version),
+            Some(0)
+        );
+    }
+
+    /// A small deterministic PRNG so that the fuzz tests are reproducible without extra
+    /// dependencies.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn encoded_request() -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_to_stream(&rustc_started(), &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn fuzz_random_input() {
+        let mut rng = XorShift(0x1234_5678_9abc_def0);
+        for _ in 0..10_000 {
+            let len = rng.below(64);
+            let mut input: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            if !input.is_empty() && rng.below(2) == 0 {
+                input[0] = FRAME_MAGIC;
+            }
+            assert!(read_from_stream::<Request>(&mut input.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn fuzz_corrupted_frames() {
+        let valid = encoded_request();
+        let mut rng = XorShift(0x0fed_cba9_8765_4321);
+        for _ in 0..10_000 {
+            let mut input = valid.clone();
+            match rng.below(3) {
+                0 => {
+                    // Flip one or more bits.
+                    for _ in 0..=rng.below(4) {
+                        let index = rng.below(input.len());
+                        input[index] ^= 1 << rng.below(8);
+                    }
+                }
+                1 => input.truncate(rng.below(input.len())),
+                _ => {
+                    let index = rng.below(input.len());
+                    input.insert(index, rng.next() as u8);
+                }
+            }
+            if input == valid {
+                continue;
+            }
+            assert!(read_from_stream::<Request>(&mut input.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn frame_errors() {
+        let valid = encoded_request();
+
+        let mut input = valid.clone();
+        input[0] = 0;
+        let error = read_from_stream::<Request>(&mut input.as_slice()).unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&FrameError::BadMagic(0)));
+
+        let mut input = valid.clone();
+        input[1..5].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = read_from_stream::<Request>(&mut input.as_slice()).unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&FrameError::TooLong(u32::MAX)));
+
+        let mut input = valid;
+        *input.last_mut().unwrap() ^= 1;
+        let error = read_from_stream::<Request>(&mut input.as_slice()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FrameError::ChecksumMismatch { .. })
+        ));
+    }
 }
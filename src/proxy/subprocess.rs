@@ -4,6 +4,8 @@
 use super::cackle_exe;
 use super::errors::get_disallowed_unsafe_locations;
 use super::rpc::BinExecutionOutput;
+use super::rpc::BuildPhase;
+use super::rpc::ProgressUpdate;
 use super::rpc::RustcOutput;
 use super::run_command;
 use super::ExitCode;
@@ -12,9 +14,15 @@ use crate::config::permissions::PermSel;
 use crate::config::permissions::Permissions;
 use crate::config::Config;
 use crate::config::RustcConfig;
+use crate::config::SandboxConfig;
 use crate::crate_index::CrateKind;
 use crate::crate_index::CrateSel;
+use crate::crate_index::PackageId;
 use crate::link_info::LinkInfo;
+use crate::link_info::Platform;
+use crate::link_info::RustcInvocationId;
+use crate::link_info::PLATFORM_ENV;
+use crate::link_info::RUSTC_INVOCATION_ENV;
 use crate::location::SourceLocation;
 use crate::outcome::Outcome;
 use crate::proxy::rpc::RpcClient;
@@ -31,11 +39,20 @@ use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
+use std::process::Output;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::Sender;
 use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
 
 pub(crate) const PROXY_BIN_ARG: &str = "proxy-bin";
+pub(crate) const PROXY_RUSTDOC_ARG: &str = "proxy-rustdoc";
 pub(crate) const ENV_CRATE_KIND: &str = "CACKLE_CRATE_KIND";
 
+/// How often we advise the parent process of our progress while running something.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Checks if we're acting as a wrapper for rustc or the linker. If we are, then we do whatever work
 /// we need to do, then invoke the binary that we're wrapping and then exit - i.e. we don't return.
 /// If we're not wrapping a binary, then we just return.
@@ -44,7 +61,7 @@ pub(crate) fn handle_wrapped_binaries() -> Result<()> {
     if socket_path.is_empty() {
         return Ok(());
     }
-    let rpc_client = RpcClient::new(socket_path.into());
+    let rpc_client = RpcClient::new(socket_path);
 
     let mut args = std::env::args().peekable();
     // Skip binary name.
@@ -59,6 +76,14 @@ pub(crate) fn handle_wrapped_binaries() -> Result<()> {
         let crate_sel = CrateSel::from_env()?.with_selector_token(&selector_token)?;
         let bin_args: Vec<_> = args.collect();
         exit_status = proxy_binary(PathBuf::from(orig_bin), &crate_sel, &rpc_client, &bin_args)?;
+    } else if args.peek().is_some_and(|a| a == PROXY_RUSTDOC_ARG) {
+        // We're wrapping rustdoc.
+        args.next();
+        let Some(rustdoc) = args.next() else {
+            bail!("Missing proxy-rustdoc args");
+        };
+        let rustdoc_args: Vec<_> = args.collect();
+        exit_status = proxy_rustdoc(PathBuf::from(rustdoc), &rpc_client, &rustdoc_args)?;
     } else if is_path_to_rustc(args.peek()) {
         // We're wrapping rustc.
         exit_status = proxy_rustc(&rpc_client)?;
@@ -119,6 +144,35 @@ fn setup_bin_wrapper(link_info: &mut LinkInfo) -> Result<()> {
     Ok(())
 }
 
+/// Returns a command that runs rustc, via `wrapper` if there is one. Wrappers such as sccache take
+/// the path to rustc as their first argument.
+fn rustc_command(rustc_path: PathBuf, wrapper: Option<OsString>) -> Command {
+    match wrapper {
+        Some(wrapper) => {
+            let mut command = Command::new(wrapper);
+            command.arg(rustc_path);
+            command
+        }
+        None => Command::new(rustc_path),
+    }
+}
+
+/// Returns the command to run `orig_bin` with `args` when config says not to use a sandbox.
+fn command_without_sandbox(
+    orig_bin: &Path,
+    args: &[String],
+    sandbox_config: &SandboxConfig,
+) -> Command {
+    let mut command = Command::new(orig_bin);
+    command.args(args);
+    crate::sandbox::apply_env_without_sandbox(sandbox_config, &mut command);
+    // If the command is a build script and it runs rustc, we want it to invoke rustc directly, not
+    // to go via our wrapper. This is also consistent with what happens if the command were to be
+    // run in a sandbox.
+    command.env_remove("RUSTC_WRAPPER");
+    command
+}
+
 /// Returns the name of the real bin file after we've renamed it.
 fn orig_bin_path(path: &Path) -> Arc<Path> {
     Arc::from(
@@ -143,22 +197,36 @@ fn proxy_binary(
         let config = SubprocessConfig::from_env()?;
         let perm_sel = PermSel::for_non_build_output(crate_sel);
         let sandbox_config = config.permissions.sandbox_config_for_package(&perm_sel);
-        let mut command = Command::new(&orig_bin);
-        command.args(args);
-        let Some(sandbox) = crate::sandbox::for_perm_sel(&sandbox_config, &orig_bin, &perm_sel)?
+        let Some(mut sandbox) =
+            crate::sandbox::for_perm_sel(&sandbox_config, &orig_bin, &perm_sel)?
         else {
             // Config says to run without a sandbox.
-            return Ok(command
-                // If the command is a build script and it runs rustc, we want it to invoke rustc
-                // directly, not to go via our wrapper. This is also consistent with what happens if
-                // the command were to be run in a sandbox.
-                .env_remove("RUSTC_WRAPPER")
-                .args(args)
+            return Ok(command_without_sandbox(&orig_bin, args, &sandbox_config)
                 .status()?
                 .into());
         };
+        let mut command = Command::new(&orig_bin);
+        command.args(args);
 
+        let http_proxy =
+            crate::sandbox::start_http_proxy(&sandbox_config, sandbox.as_mut(), &mut command)?;
+        let trace = crate::sandbox::start_trace(sandbox.as_mut(), &mut command)?;
+        let capture = crate::sandbox::start_write_capture(&sandbox_config, sandbox.as_mut())?;
+        let start = std::time::Instant::now();
+        let cpu_time_before = crate::sandbox::children_cpu_time();
+        let progress = ProgressReporter::start(rpc_client, crate_sel, BuildPhase::Running);
         let output = sandbox.run(&command)?;
+        drop(progress);
+        let limit_exceeded = crate::sandbox::ResourceLimits::from_config(&sandbox_config).exceeded(
+            &output,
+            start.elapsed(),
+            crate::sandbox::children_cpu_time().saturating_sub(cpu_time_before),
+        );
+        let captured_writes = match &capture {
+            Some(capture) => capture.writes()?,
+            None => Vec::new(),
+        };
+        let wrote_outside_allowed_dirs = !captured_writes.is_empty();
         let rpc_response = rpc_client.bin_execution_complete({
             let exit_code = output.status.code().unwrap_or(-1);
             BinExecutionOutput {
@@ -166,15 +234,31 @@ fn proxy_binary(
                 stdout: output.stdout.clone(),
                 stderr: output.stderr.clone(),
                 crate_sel: crate_sel.clone(),
-                sandbox_config,
+                sandbox_config: sandbox_config.clone(),
                 binary_path: orig_bin.clone(),
                 sandbox_config_display: (exit_code != 0)
                     .then(|| sandbox.display_to_run(&command).to_string()),
+                network_requests: http_proxy.map(|proxy| proxy.requests()).unwrap_or_default(),
+                denied_accesses: match &trace {
+                    Some(trace) => trace.denied_accesses()?,
+                    None => Vec::new(),
+                },
+                limit_exceeded,
+                captured_writes,
             }
         })?;
         match rpc_response {
             Outcome::Continue => {
-                if output.status.code() == Some(0) {
+                // If writes were captured and the sandbox config has since changed, presumably to
+                // permit those writes, then we rerun so that the writes actually happen. Otherwise,
+                // the writes stay discarded.
+                let rerun_for_writes = wrote_outside_allowed_dirs
+                    && rpc_client.take_config_changed()
+                    && SubprocessConfig::from_env()?
+                        .permissions
+                        .sandbox_config_for_package(&perm_sel)
+                        != sandbox_config;
+                if output.status.code() == Some(0) && !rerun_for_writes {
                     std::io::stderr().lock().write_all(&output.stderr)?;
                     std::io::stdout().lock().write_all(&output.stdout)?;
                     return Ok(output.status.into());
@@ -187,6 +271,19 @@ fn proxy_binary(
     }
 }
 
+/// Runs rustdoc. If rustdoc has been asked to run doctests, then these get run in the sandbox for
+/// the package's tests, since rustdoc runs the doctests itself.
+fn proxy_rustdoc(rustdoc: PathBuf, rpc_client: &RpcClient, args: &[String]) -> Result<ExitCode> {
+    if !args.iter().any(|arg| arg == "--test") {
+        return run_command(Command::new(rustdoc).args(args));
+    }
+    let crate_sel = CrateSel {
+        pkg_id: PackageId::from_env()?,
+        kind: CrateKind::Test,
+    };
+    proxy_binary(rustdoc, &crate_sel, rpc_client, args)
+}
+
 fn proxy_rustc(rpc_client: &RpcClient) -> Result<ExitCode> {
     if std::env::var("CARGO_PKG_NAME").is_err() {
         // If CARGO_PKG_NAME isn't set, then cargo is probably just invoking rustc to query
@@ -199,9 +296,12 @@ fn proxy_rustc(rpc_client: &RpcClient) -> Result<ExitCode> {
     let mut crate_sel = CrateSel::from_env()?;
     if std::env::args().any(|arg| arg == "--test") {
         crate_sel.kind = CrateKind::Test;
+    } else if is_example() {
+        crate_sel.kind = CrateKind::Example;
     }
     let mut runner = RustcRunner::new(crate_sel);
     rpc_client.rustc_started(&runner.crate_sel)?;
+    let _progress = ProgressReporter::start(rpc_client, &runner.crate_sel, BuildPhase::Compiling);
     loop {
         match runner.run(rpc_client)? {
             RustcRunStatus::Retry => {}
@@ -215,6 +315,25 @@ fn proxy_rustc(rpc_client: &RpcClient) -> Result<ExitCode> {
     }
 }
 
+/// Returns whether rustc is being invoked to build an example. Cargo doesn't tell us the kind of
+/// target, so we check if the source file is in the package's `examples` directory.
+fn is_example() -> bool {
+    let (Ok(manifest_dir), Ok(current_dir)) =
+        (std::env::var("CARGO_MANIFEST_DIR"), std::env::current_dir())
+    else {
+        return false;
+    };
+    std::env::args()
+        .skip(2)
+        .filter(|arg| arg.ends_with(".rs"))
+        .any(|arg| {
+            current_dir
+                .join(arg)
+                .strip_prefix(&manifest_dir)
+                .is_ok_and(|path| path.starts_with("examples"))
+        })
+}
+
 fn rustc_path_from_env() -> Result<PathBuf> {
     path_from_env(super::RUSTC_PATH)
 }
@@ -227,6 +346,8 @@ fn path_from_env(var_name: &str) -> Result<PathBuf> {
 
 struct RustcRunner {
     crate_sel: CrateSel,
+    platform: Platform,
+    invocation: RustcInvocationId,
 }
 
 enum RustcRunStatus {
@@ -237,7 +358,13 @@ enum RustcRunStatus {
 
 impl RustcRunner {
     fn new(crate_sel: CrateSel) -> Self {
-        Self { crate_sel }
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        let platform = Platform::from_rustc_args(&crate_sel, &args);
+        Self {
+            crate_sel,
+            platform,
+            invocation: RustcInvocationId::new(),
+        }
     }
 
     fn run(&mut self, rpc_client: &RpcClient) -> Result<RustcRunStatus> {
@@ -247,17 +374,21 @@ impl RustcRunner {
         let unsafe_permitted = config
             .permissions
             .unsafe_permitted_for_crate(&self.crate_sel);
-        let mut command = self.get_command(unsafe_permitted)?;
-        let output = match crate::sandbox::for_rustc(
-            &config.rustc,
-            &RustcSandboxInputs::from_env(&self.crate_sel)?,
-        )? {
-            Some(mut sandbox) => {
-                sandbox.ro_bind(&cackle_exe()?);
-                sandbox.run(&command)?
+        let unsafe_permitted_paths = config.permissions.unsafe_permitted_paths(&self.crate_sel);
+        let mut output = self.run_rustc(&config, unsafe_permitted)?;
+        if !unsafe_permitted && !unsafe_permitted_paths.is_empty() && !output.status.success() {
+            let locations = get_disallowed_unsafe_locations(&output)?;
+            if !locations.is_empty()
+                && locations
+                    .iter()
+                    .all(|location| is_unsafe_permitted_at(location, unsafe_permitted_paths))
+            {
+                // All the unsafe that rustc found is in files where it's permitted, so compile
+                // again without forbidding unsafe. Unsafe in any other files will then be found
+                // when we scan the sources below.
+                output = self.run_rustc(&config, true)?;
             }
-            None => command.output()?,
-        };
+        }
         let mut unsafe_locations = Vec::new();
 
         if output.status.code() == Some(0) {
@@ -267,6 +398,9 @@ impl RustcRunner {
             let response = rpc_client.rustc_complete(RustcOutput {
                 crate_sel: self.crate_sel.clone(),
                 source_paths: source_paths.clone(),
+                platform: self.platform,
+                rustc_invocation: self.invocation,
+                rlib_path: crate::deps::rlib_path_from_rustc_args(std::env::args())?,
             })?;
             if response != Outcome::Continue {
                 return Ok(RustcRunStatus::GiveUp);
@@ -277,6 +411,8 @@ impl RustcRunner {
         } else {
             unsafe_locations.extend(get_disallowed_unsafe_locations(&output)?);
         }
+        unsafe_locations
+            .retain(|location| !is_unsafe_permitted_at(location, unsafe_permitted_paths));
         if !unsafe_locations.is_empty() {
             unsafe_locations.sort();
             unsafe_locations.dedup();
@@ -291,9 +427,31 @@ impl RustcRunner {
         Ok(RustcRunStatus::Done(output))
     }
 
+    fn run_rustc(&self, config: &SubprocessConfig, unsafe_permitted: bool) -> Result<Output> {
+        let mut command = self.get_command(unsafe_permitted)?;
+        let sandbox = crate::sandbox::for_rustc(
+            &config.rustc,
+            &RustcSandboxInputs::from_env(&self.crate_sel)?,
+        )?;
+        let output = match sandbox {
+            Some(mut sandbox) => {
+                sandbox.ro_bind(&cackle_exe()?);
+                if let Some(wrapper) = std::env::var_os(super::ORIG_RUSTC_WRAPPER_ENV) {
+                    sandbox.ro_bind(Path::new(&wrapper));
+                }
+                sandbox.run(&command)?
+            }
+            None => command.output()?,
+        };
+        Ok(output)
+    }
+
     fn get_command(&self, unsafe_permitted: bool) -> Result<Command> {
         let mut args = std::env::args().skip(2).peekable();
-        let mut command = Command::new(rustc_path_from_env()?);
+        let mut command = rustc_command(
+            rustc_path_from_env()?,
+            std::env::var_os(super::ORIG_RUSTC_WRAPPER_ENV),
+        );
         let mut linker_arg = OsString::new();
         let mut orig_linker_arg = None;
         while let Some(arg) = args.next() {
@@ -333,6 +491,8 @@ impl RustcRunner {
         command.arg("-C").arg("save-temps");
         command.arg("-Ccodegen-units=1");
         command.env(ENV_CRATE_KIND, self.crate_sel.selector_token());
+        command.env(PLATFORM_ENV, self.platform.as_str());
+        command.env(RUSTC_INVOCATION_ENV, self.invocation.to_env_value());
         if !unsafe_permitted {
             command.arg("-Funsafe-code");
         }
@@ -340,6 +500,35 @@ impl RustcRunner {
     }
 }
 
+/// Returns whether `location` is in a source file that matches one of `permitted_paths`, which are
+/// globs relative to the directory of the package being compiled.
+fn is_unsafe_permitted_at(location: &SourceLocation, permitted_paths: &[String]) -> bool {
+    if permitted_paths.is_empty() {
+        return false;
+    }
+    let (Ok(manifest_dir), Ok(current_dir)) =
+        (std::env::var("CARGO_MANIFEST_DIR"), std::env::current_dir())
+    else {
+        return false;
+    };
+    path_matches_any(
+        &current_dir.join(location.filename()),
+        Path::new(&manifest_dir),
+        permitted_paths,
+    )
+}
+
+fn path_matches_any(path: &Path, dir: &Path, globs: &[String]) -> bool {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_owned());
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    let Ok(relative_path) = path.strip_prefix(dir) else {
+        return false;
+    };
+    globs
+        .iter()
+        .any(|glob| crate::glob::matches(glob, relative_path))
+}
+
 /// Searches for the unsafe keyword in the specified paths.
 fn find_unsafe_in_sources(paths: &[PathBuf]) -> Result<Vec<SourceLocation>> {
     let mut locations = Vec::new();
@@ -349,6 +538,59 @@ fn find_unsafe_in_sources(paths: &[PathBuf]) -> Result<Vec<SourceLocation>> {
     Ok(locations)
 }
 
+/// Advises the parent process of our progress from a background thread until dropped, at which
+/// point we report that we've finished.
+struct ProgressReporter {
+    rpc_client: RpcClient,
+    crate_sel: CrateSel,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProgressReporter {
+    fn start(rpc_client: &RpcClient, crate_sel: &CrateSel, phase: BuildPhase) -> Self {
+        let (stop, stop_recv) = std::sync::mpsc::channel::<()>();
+        let thread = {
+            let rpc_client = rpc_client.clone();
+            let crate_sel = crate_sel.clone();
+            std::thread::Builder::new()
+                .name("progress".to_owned())
+                .spawn(move || loop {
+                    // Progress updates are best-effort, so we ignore any error.
+                    let _ = rpc_client.progress(ProgressUpdate {
+                        crate_sel: crate_sel.clone(),
+                        phase,
+                        percent: None,
+                    });
+                    if stop_recv.recv_timeout(PROGRESS_INTERVAL) != Err(RecvTimeoutError::Timeout) {
+                        return;
+                    }
+                })
+                .ok()
+        };
+        Self {
+            rpc_client: rpc_client.clone(),
+            crate_sel: crate_sel.clone(),
+            stop: Some(stop),
+            thread,
+        }
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = self.rpc_client.progress(ProgressUpdate {
+            crate_sel: self.crate_sel.clone(),
+            phase: BuildPhase::Finished,
+            percent: None,
+        });
+    }
+}
+
 /// Runs the real linker, then advises our parent process of all input files to the linker as well
 /// as the output file. If the parent process says that all checks have been satisfied, then we
 /// return, otherwise we exit.
@@ -357,6 +599,12 @@ fn proxy_linker(
     rpc_client: RpcClient,
     args: std::iter::Peekable<std::env::Args>,
 ) -> Result<ExitCode> {
+    // Progress updates are best-effort, so we ignore any error.
+    let _ = rpc_client.progress(ProgressUpdate {
+        crate_sel: link_info.crate_sel.clone(),
+        phase: BuildPhase::Linking,
+        percent: None,
+    });
     // Invoke the actual linker first, since the parent process uses the output file to aid with
     // analysis.
     let exit_status = invoke_real_linker(args)?;
@@ -436,11 +684,41 @@ fn test_orig_bin_path() {
     );
 }
 
+#[test]
+fn chained_rustc_command() {
+    let command = rustc_command(
+        PathBuf::from("/toolchain/bin/rustc"),
+        Some(OsString::from("/usr/bin/sccache")),
+    );
+    assert_eq!(command.get_program(), "/usr/bin/sccache");
+    assert_eq!(
+        command.get_args().collect::<Vec<_>>(),
+        ["/toolchain/bin/rustc"]
+    );
+
+    let command = rustc_command(PathBuf::from("/toolchain/bin/rustc"), None);
+    assert_eq!(command.get_program(), "/toolchain/bin/rustc");
+    assert_eq!(command.get_args().count(), 0);
+}
+
+#[test]
+fn command_without_sandbox_passes_args_once() {
+    let args = ["--test-threads".to_owned(), "1".to_owned()];
+    let command = command_without_sandbox(Path::new("foo"), &args, &SandboxConfig::default());
+    assert_eq!(
+        command.get_args().collect::<Vec<_>>(),
+        ["--test-threads", "1"]
+    );
+    assert!(command
+        .get_envs()
+        .any(|(var, value)| var == "RUSTC_WRAPPER" && value.is_none()));
+}
+
 #[test]
 fn config_roundtrips() {
     let crate_root = std::path::PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
     let test_crates_dir = crate_root.join("test_crates");
-    let crate_index = crate::crate_index::CrateIndex::new(&test_crates_dir).unwrap();
+    let crate_index = crate::crate_index::CrateIndex::new(&test_crates_dir, &[]).unwrap();
     let full_config =
         crate::config::parse_file(&test_crates_dir.join("cackle.toml"), &crate_index).unwrap();
     let subprocess_config = SubprocessConfig::from_full_config(&full_config);
@@ -449,3 +727,31 @@ fn config_roundtrips() {
         SubprocessConfig::deserialise(&subprocess_config.serialise().unwrap()).unwrap();
     assert_eq!(subprocess_config, roundtripped_config);
 }
+
+#[test]
+fn unsafe_permitted_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let pkg_dir = dir.path().join("pkg");
+    std::fs::create_dir_all(pkg_dir.join("src/ffi")).unwrap();
+    let globs = ["src/ffi/**".to_owned(), "src/sys.rs".to_owned()];
+    assert!(path_matches_any(
+        &pkg_dir.join("src/ffi/bindings.rs"),
+        &pkg_dir,
+        &globs
+    ));
+    assert!(path_matches_any(
+        &pkg_dir.join("src/sys.rs"),
+        &pkg_dir,
+        &globs
+    ));
+    assert!(!path_matches_any(
+        &pkg_dir.join("src/lib.rs"),
+        &pkg_dir,
+        &globs
+    ));
+    assert!(!path_matches_any(
+        &dir.path().join("other/src/sys.rs"),
+        &pkg_dir,
+        &globs
+    ));
+}
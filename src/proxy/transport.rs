@@ -0,0 +1,416 @@
+//! Transports over which the proxy subprocesses communicate with the parent process.
+//!
+//! The parent creates a `Listener` and passes its endpoint to subprocesses via an environment
+//! variable. Subprocesses then call `connect` with that endpoint. By default a Unix domain socket is
+//! used. Operating systems without Unix domain sockets can instead use TCP on the loopback
+//! interface. Since any local process can connect to a TCP port, each TCP connection must start with
+//! a token that's only known to the parent and the processes that it started.
+
+use anyhow::Context;
+use anyhow::Result;
+use clap::ValueEnum;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Prefix for TCP endpoints. Endpoints without this prefix are paths to Unix domain sockets.
+const TCP_PREFIX: &str = "tcp:";
+
+/// The length in bytes of the token sent at the start of each TCP connection.
+const TOKEN_LEN: usize = 32;
+
+/// How long we'll wait for a newly connected client to send its token.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum TransportKind {
+    /// A Unix domain socket in our temporary directory.
+    #[cfg_attr(unix, default)]
+    Unix,
+
+    /// TCP on the loopback interface, authenticated with a random token.
+    #[cfg_attr(not(unix), default)]
+    Tcp,
+}
+
+/// A connection between a proxy subprocess and the parent process.
+pub(crate) trait Connection: Read + Write + Send {
+    /// Returns another handle to the same connection. This allows one thread to read from the
+    /// connection while others write to it.
+    fn try_clone(&self) -> Result<Box<dyn Connection>>;
+}
+
+impl Connection for TcpStream {
+    fn try_clone(&self) -> Result<Box<dyn Connection>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+}
+
+/// Something that proxy subprocesses can connect to.
+pub(crate) trait Listener {
+    /// Returns the string that should be passed to `connect` in order to connect to this listener.
+    fn endpoint(&self) -> String;
+
+    /// Returns the next connection, if any is pending. Doesn't block.
+    fn accept(&self) -> Result<Option<Box<dyn Connection>>>;
+}
+
+/// Creates a listener of the specified kind. Any files needed are created in `dir`.
+pub(crate) fn bind(kind: TransportKind, dir: &Path) -> Result<Box<dyn Listener>> {
+    match kind {
+        #[cfg(unix)]
+        TransportKind::Unix => Ok(Box::new(unix::SocketListener::bind(dir)?)),
+        #[cfg(not(unix))]
+        TransportKind::Unix => {
+            anyhow::bail!("Unix domain sockets aren't supported on this platform")
+        }
+        TransportKind::Tcp => Ok(Box::new(TokenListener::bind()?)),
+    }
+}
+
+/// Connects to the listener with the specified endpoint.
+pub(crate) fn connect(endpoint: &str) -> Result<Box<dyn Connection>> {
+    if let Some((addr, token)) = parse_tcp_endpoint(endpoint) {
+        let mut stream =
+            TcpStream::connect(addr).with_context(|| format!("Failed to connect to `{addr}`"))?;
+        stream.write_all(token.as_bytes())?;
+        return Ok(Box::new(stream));
+    }
+    #[cfg(unix)]
+    {
+        unix::connect(Path::new(endpoint))
+    }
+    #[cfg(not(unix))]
+    {
+        anyhow::bail!("Unsupported endpoint `{endpoint}`")
+    }
+}
+
+/// Returns the directory containing the socket, if `endpoint` is for a Unix domain socket. Processes
+/// need write access to this directory in order to connect.
+pub(crate) fn socket_dir(endpoint: &str) -> Option<PathBuf> {
+    if parse_tcp_endpoint(endpoint).is_some() {
+        return None;
+    }
+    Path::new(endpoint).parent().map(Path::to_owned)
+}
+
+/// Returns whether `endpoint` requires network access, which sandboxes don't permit by default.
+pub(crate) fn uses_network(endpoint: &str) -> bool {
+    parse_tcp_endpoint(endpoint).is_some()
+}
+
+fn parse_tcp_endpoint(endpoint: &str) -> Option<(SocketAddr, &str)> {
+    let rest = endpoint.strip_prefix(TCP_PREFIX)?;
+    let (addr, token) = rest.rsplit_once('/')?;
+    Some((addr.parse().ok()?, token))
+}
+
+struct TokenListener {
+    listener: TcpListener,
+    token: String,
+    /// Connections that haven't yet sent their whole token. We read tokens without blocking, so
+    /// that a client that connects, but never sends anything, can't hold up other connections.
+    pending: Mutex<Vec<PendingConnection>>,
+}
+
+struct PendingConnection {
+    stream: TcpStream,
+    token: Vec<u8>,
+    deadline: Instant,
+}
+
+/// The outcome of trying to read the rest of a pending connection's token.
+enum TokenStatus {
+    Valid,
+    Invalid,
+    Incomplete,
+}
+
+impl TokenListener {
+    fn bind() -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .context("Failed to listen on loopback interface")?;
+        listener
+            .set_nonblocking(true)
+            .context("Failed to set socket to non-blocking")?;
+        Ok(Self {
+            listener,
+            token: new_token()?,
+            pending: Default::default(),
+        })
+    }
+}
+
+impl Listener for TokenListener {
+    fn endpoint(&self) -> String {
+        let addr = self
+            .listener
+            .local_addr()
+            .unwrap_or_else(|_| SocketAddr::from((Ipv4Addr::LOCALHOST, 0)));
+        format!("{TCP_PREFIX}{addr}/{}", self.token)
+    }
+
+    fn accept(&self) -> Result<Option<Box<dyn Connection>>> {
+        let mut pending = self.pending.lock().unwrap();
+        while let Ok((stream, _)) = self.listener.accept() {
+            stream.set_nonblocking(true)?;
+            pending.push(PendingConnection {
+                stream,
+                token: Vec::with_capacity(TOKEN_LEN),
+                deadline: Instant::now() + TOKEN_TIMEOUT,
+            });
+        }
+        let now = Instant::now();
+        let mut i = 0;
+        while i < pending.len() {
+            match pending[i].read_token(self.token.as_bytes()) {
+                TokenStatus::Incomplete if now < pending[i].deadline => i += 1,
+                TokenStatus::Valid => {
+                    let stream = pending.remove(i).stream;
+                    stream.set_nonblocking(false)?;
+                    return Ok(Some(Box::new(stream)));
+                }
+                _ => {
+                    // Something other than one of our subprocesses connected. Ignore it.
+                    log::warn!("Rejected connection without a valid token");
+                    pending.remove(i);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl PendingConnection {
+    /// Reads whatever is available of the token without blocking and checks it against `expected`
+    /// once it's complete.
+    fn read_token(&mut self, expected: &[u8]) -> TokenStatus {
+        let mut buf = [0u8; TOKEN_LEN];
+        while self.token.len() < TOKEN_LEN {
+            match self.stream.read(&mut buf[..TOKEN_LEN - self.token.len()]) {
+                Ok(0) => return TokenStatus::Invalid,
+                Ok(n) => self.token.extend_from_slice(&buf[..n]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    return TokenStatus::Incomplete
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return TokenStatus::Invalid,
+            }
+        }
+        if constant_time_eq(&self.token, expected) {
+            TokenStatus::Valid
+        } else {
+            TokenStatus::Invalid
+        }
+    }
+}
+
+/// Returns a random token of `TOKEN_LEN` hex digits, using the operating system's random number
+/// generator.
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; TOKEN_LEN / 2];
+    random_bytes(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+#[cfg(unix)]
+fn random_bytes(buf: &mut [u8]) -> Result<()> {
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(buf))
+        .context("Failed to read `/dev/urandom`")
+}
+
+// Windows has no `/dev/urandom`, so we need to ask for random bytes via a Win32 API.
+#[cfg(windows)]
+#[allow(unsafe_code)]
+fn random_bytes(buf: &mut [u8]) -> Result<()> {
+    use windows_sys::Win32::Security::Cryptography::BCryptGenRandom;
+    use windows_sys::Win32::Security::Cryptography::BCRYPT_USE_SYSTEM_PREFERRED_RNG;
+
+    let len = u32::try_from(buf.len())?;
+    let status =
+        unsafe { BCryptGenRandom(0, buf.as_mut_ptr(), len, BCRYPT_USE_SYSTEM_PREFERRED_RNG) };
+    if status < 0 {
+        anyhow::bail!("BCryptGenRandom failed with {status:#010x}");
+    }
+    Ok(())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::Connection;
+    use super::Listener;
+    use anyhow::Context;
+    use anyhow::Result;
+    use std::os::unix::net::UnixListener;
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
+    use std::path::PathBuf;
+
+    pub(super) struct SocketListener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl SocketListener {
+        pub(super) fn bind(dir: &Path) -> Result<Self> {
+            let path = dir.join("cackle.socket");
+            let _ = std::fs::remove_file(&path);
+            let listener = UnixListener::bind(&path)
+                .with_context(|| format!("Failed to create Unix socket `{}`", path.display()))?;
+            listener
+                .set_nonblocking(true)
+                .context("Failed to set socket to non-blocking")?;
+            Ok(Self { listener, path })
+        }
+    }
+
+    impl Listener for SocketListener {
+        fn endpoint(&self) -> String {
+            self.path.to_string_lossy().into_owned()
+        }
+
+        fn accept(&self) -> Result<Option<Box<dyn Connection>>> {
+            let Ok((stream, _)) = self.listener.accept() else {
+                return Ok(None);
+            };
+            stream.set_nonblocking(false)?;
+            Ok(Some(Box::new(stream)))
+        }
+    }
+
+    impl Drop for SocketListener {
+        fn drop(&mut self) {
+            // Deleting the socket is best-effort only, so we don't report an error if we can't.
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    impl Connection for UnixStream {
+        fn try_clone(&self) -> Result<Box<dyn Connection>> {
+            Ok(Box::new(UnixStream::try_clone(self)?))
+        }
+    }
+
+    pub(super) fn connect(path: &Path) -> Result<Box<dyn Connection>> {
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("Failed to connect to socket `{}`", path.display()))?;
+        Ok(Box::new(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TransportKind;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    fn round_trip(kind: TransportKind) {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = super::bind(kind, dir.path()).unwrap();
+        let mut client = super::connect(&listener.endpoint()).unwrap();
+        client.write_all(b"ping").unwrap();
+        let mut server = loop {
+            if let Some(connection) = listener.accept().unwrap() {
+                break connection;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[test]
+    fn unix_round_trip() {
+        round_trip(TransportKind::Unix);
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        round_trip(TransportKind::Tcp);
+    }
+
+    #[test]
+    fn tcp_rejects_bad_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = super::bind(TransportKind::Tcp, dir.path()).unwrap();
+        let endpoint = listener.endpoint();
+        let (addr, token) = super::parse_tcp_endpoint(&endpoint).unwrap();
+        let bad_token = "0".repeat(token.len());
+        assert_ne!(token, bad_token);
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(bad_token.as_bytes()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(listener.accept().unwrap().is_none());
+        // The connection should have been closed.
+        let mut buf = [0u8; 1];
+        assert_eq!(client.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn tcp_silent_client_does_not_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = super::bind(TransportKind::Tcp, dir.path()).unwrap();
+        let endpoint = listener.endpoint();
+        let (addr, _) = super::parse_tcp_endpoint(&endpoint).unwrap();
+        // A client that connects, but never sends its token, and one that only sends part of it.
+        let _silent = TcpStream::connect(addr).unwrap();
+        let mut partial = TcpStream::connect(addr).unwrap();
+        partial.write_all(&endpoint.as_bytes()[..4]).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let start = Instant::now();
+        assert!(listener.accept().unwrap().is_none());
+        let mut client = super::connect(&endpoint).unwrap();
+        client.write_all(b"ping").unwrap();
+        let mut server = loop {
+            if let Some(connection) = listener.accept().unwrap() {
+                break connection;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert!(start.elapsed() < super::TOKEN_TIMEOUT);
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[test]
+    fn tokens_are_random() {
+        let token = super::new_token().unwrap();
+        assert_eq!(token.len(), super::TOKEN_LEN);
+        assert!(token.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_ne!(token, super::new_token().unwrap());
+    }
+
+    #[test]
+    fn endpoints() {
+        assert!(super::uses_network("tcp:127.0.0.1:1234/abc"));
+        assert!(!super::uses_network("/tmp/comms/cackle.socket"));
+        assert_eq!(
+            super::socket_dir("/tmp/comms/cackle.socket"),
+            Some("/tmp/comms".into())
+        );
+        assert_eq!(super::socket_dir("tcp:127.0.0.1:1234/abc"), None);
+    }
+}
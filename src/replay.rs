@@ -0,0 +1,260 @@
+//! Recording of the requests that our proxy subprocesses make during a build, so that the checks
+//! can be rerun later, possibly with a modified config, without rebuilding.
+//!
+//! A recording is a file with one JSON value per line. The first line is a header, each subsequent
+//! line is a request together with checksums of the files that the checker will read when handling
+//! it. On replay, the checksums are verified so that we don't silently analyse object files that
+//! differ from the ones that were built.
+
+use crate::proxy::rpc::Request;
+use crate::proxy::rpc::PROTOCOL_VERSION;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug, Clone)]
+pub(crate) struct ReplayOptions {
+    /// A recording made by running with `--record`.
+    path: PathBuf,
+}
+
+impl ReplayOptions {
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+pub(crate) struct Recorder {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct Header {
+    protocol_version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct RecordedRequest {
+    request: Request,
+    checksums: Vec<FileChecksum>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+struct FileChecksum {
+    path: PathBuf,
+    len: u64,
+    crc32: u32,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording `{}`", path.display()))?;
+        let mut recorder = Self {
+            path: path.to_owned(),
+            out: BufWriter::new(file),
+        };
+        recorder.write_line(&Header {
+            protocol_version: PROTOCOL_VERSION,
+        })?;
+        Ok(recorder)
+    }
+
+    /// Appends `request` to the recording. Requests that aren't needed in order to rerun the checks
+    /// are skipped.
+    pub(crate) fn record(&mut self, request: &Request) -> Result<()> {
+        if matches!(request, Request::Progress(..) | Request::RustcStarted(..)) {
+            return Ok(());
+        }
+        let checksums = input_files(request)
+            .into_iter()
+            .filter(|path| path.exists())
+            .map(|path| FileChecksum::compute(&path))
+            .collect::<Result<Vec<_>>>()?;
+        self.write_line(&RecordedRequest {
+            request: request.clone(),
+            checksums,
+        })
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.out, value)?;
+        self.out.write_all(b"\n")?;
+        // Flush after each request, so that if the build is aborted, what we've recorded so far is
+        // still usable.
+        self.out
+            .flush()
+            .with_context(|| format!("Failed to write recording `{}`", self.path.display()))
+    }
+}
+
+/// Returns the files that the checker will read when handling `request`.
+fn input_files(request: &Request) -> Vec<PathBuf> {
+    match request {
+        Request::LinkerInvoked(link_info) => {
+            let mut paths = link_info.object_paths.clone();
+            paths.push(link_info.output_file.to_path_buf());
+            paths
+        }
+        _ => Vec::new(),
+    }
+}
+
+impl FileChecksum {
+    fn compute(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+        Ok(Self {
+            path: path.to_owned(),
+            len: bytes.len() as u64,
+            crc32: crc32fast::hash(&bytes),
+        })
+    }
+}
+
+/// Reads the requests from the recording at `path`. Fails if any of the files referenced by the
+/// recording have changed since it was made. A final line without a newline is ignored, since it's
+/// what's left if we were killed part way through recording a request.
+pub(crate) fn read(path: &Path) -> Result<Vec<Request>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to open recording `{}`", path.display()))?;
+    let mut lines = contents
+        .split_inclusive('\n')
+        .filter_map(|line| line.strip_suffix('\n'));
+    let Some(header) = lines.next() else {
+        bail!("Recording `{}` is empty", path.display());
+    };
+    let header: Header = serde_json::from_str(header)
+        .with_context(|| format!("`{}` is not a cackle recording", path.display()))?;
+    if header.protocol_version != PROTOCOL_VERSION {
+        bail!(
+            "Recording `{}` was made by a different version of cackle (protocol version {}, \
+             expected {PROTOCOL_VERSION})",
+            path.display(),
+            header.protocol_version
+        );
+    }
+    let mut requests = Vec::new();
+    let mut changed = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let recorded: RecordedRequest = serde_json::from_str(line).with_context(|| {
+            format!(
+                "Invalid request on line {} of `{}`",
+                index + 2,
+                path.display()
+            )
+        })?;
+        for checksum in &recorded.checksums {
+            let current = FileChecksum::compute(&checksum.path).ok();
+            if current.as_ref() != Some(checksum) {
+                changed.push(checksum.path.display().to_string());
+            }
+        }
+        requests.push(recorded.request);
+    }
+    if !changed.is_empty() {
+        changed.sort();
+        changed.dedup();
+        bail!(
+            "The following files have changed or been removed since the recording was made. \
+             Rerun the build with `--record` to make a new recording:\n{}",
+            changed.join("\n")
+        );
+    }
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::CrateSel;
+    use crate::link_info::LinkInfo;
+    use crate::link_info::Platform;
+    use crate::proxy::rpc::Request;
+    use crate::proxy::rpc::RustcOutput;
+    use std::io::Write;
+    use std::path::Path;
+
+    #[test]
+    fn record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let object = dir.path().join("foo.o");
+        std::fs::write(&object, "object").unwrap();
+        let link_info: LinkInfo = serde_json::from_value(serde_json::json!({
+            "crate_sel": CrateSel::primary(pkg_id("foo")),
+            "object_paths": [object],
+            "output_file": dir.path().join("foo"),
+            "is_shared": false,
+        }))
+        .unwrap();
+        let link = Request::LinkerInvoked(link_info);
+        let complete = Request::RustcComplete(RustcOutput {
+            crate_sel: CrateSel::primary(pkg_id("foo")),
+            source_paths: vec![Path::new("src/lib.rs").to_owned()],
+            platform: Platform::Target,
+            rustc_invocation: Default::default(),
+            rlib_path: None,
+        });
+        let recording = dir.path().join("recording.jsonl");
+        let mut recorder = super::Recorder::create(&recording).unwrap();
+        recorder.record(&link).unwrap();
+        recorder
+            .record(&Request::RustcStarted(CrateSel::primary(pkg_id("foo"))))
+            .unwrap();
+        recorder.record(&complete).unwrap();
+        drop(recorder);
+
+        assert_eq!(super::read(&recording).unwrap(), vec![link, complete]);
+
+        std::fs::write(&object, "changed").unwrap();
+        let error = super::read(&recording).unwrap_err();
+        assert!(format!("{error:#}").contains("foo.o"));
+    }
+
+    #[test]
+    fn read_interrupted_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let complete = |name| {
+            Request::RustcComplete(RustcOutput {
+                crate_sel: CrateSel::primary(pkg_id(name)),
+                source_paths: vec![Path::new("src/lib.rs").to_owned()],
+                platform: Platform::Target,
+                rustc_invocation: Default::default(),
+                rlib_path: None,
+            })
+        };
+        let recording = dir.path().join("review-session.jsonl");
+        let mut recorder = super::Recorder::create(&recording).unwrap();
+        recorder.record(&complete("foo")).unwrap();
+        recorder.record(&complete("bar")).unwrap();
+
+        // Simulate being killed part way through writing a request. We don't drop the recorder
+        // first, since an interrupted run wouldn't have.
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&recording)
+            .unwrap();
+        let partial = serde_json::to_string(&super::RecordedRequest {
+            request: complete("baz"),
+            checksums: Vec::new(),
+        })
+        .unwrap();
+        file.write_all(&partial.as_bytes()[..partial.len() / 2])
+            .unwrap();
+
+        assert_eq!(
+            super::read(&recording).unwrap(),
+            vec![complete("foo"), complete("bar")]
+        );
+        drop(recorder);
+    }
+}
@@ -0,0 +1,346 @@
+//! Support for `--output-format json` and the `report` subcommand, which write a report of which
+//! APIs each crate used and where, whether those APIs were granted by the config, where crates use
+//! unsafe and how sandboxed binaries fared. The JSON schema is documented in JSON_REPORT.md. Any
+//! incompatible change to the schema must increment `SCHEMA_VERSION`.
+
+use crate::analysis;
+use crate::config::permissions::PermSel;
+use crate::config::ApiName;
+use crate::config::SandboxKind;
+use crate::problem::ApiUsages;
+use crate::problem::Problem;
+use crate::problem::Severity;
+use crate::proxy::rpc::BinExecutionOutput;
+use crate::proxy::rpc::RustcOutput;
+use anyhow::Result;
+use clap::Parser;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub(crate) mod diff;
+pub(crate) mod export;
+mod html;
+pub(crate) mod table;
+
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct ReportOptions {
+    /// Directory in which to write a static HTML report. Created if it doesn't exist.
+    #[clap(long)]
+    html: PathBuf,
+}
+
+/// Information collected while checking, from which the report is written.
+#[derive(Default)]
+pub(crate) struct Report {
+    crates: BTreeMap<String, CrateReport>,
+    sandbox: Vec<SandboxOutcome>,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    schema_version: u32,
+    cackle_version: &'static str,
+    crates: Vec<&'a CrateReport>,
+    sandbox: &'a [SandboxOutcome],
+    problems: Vec<ProblemReport>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CrateReport {
+    selector: String,
+    package: String,
+    apis: BTreeMap<String, ApiReport>,
+    #[serde(default)]
+    unsafe_usages: BTreeSet<LocationReport>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ApiReport {
+    status: ApiStatus,
+    usages: BTreeSet<UsageReport>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum ApiStatus {
+    /// The config permits the usages.
+    Granted,
+    /// The usages aren't permitted by the config.
+    New,
+    /// The config permits the API, but no usages were found.
+    Unused,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+struct UsageReport {
+    file: PathBuf,
+    line: u32,
+    column: Option<u32>,
+    from: String,
+    to: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+struct LocationReport {
+    file: PathBuf,
+    line: u32,
+    column: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct SandboxOutcome {
+    #[serde(rename = "crate")]
+    crate_name: String,
+    binary: PathBuf,
+    sandbox: Option<SandboxKind>,
+    exit_code: i32,
+    limit_exceeded: Option<String>,
+    denied_hosts: Vec<String>,
+    denied_write_dirs: Vec<PathBuf>,
+    discarded_writes: usize,
+}
+
+#[derive(Serialize)]
+struct ProblemReport {
+    severity: &'static str,
+    package: Option<String>,
+    message: String,
+}
+
+impl ApiReport {
+    fn into_analysis(self) -> analysis::ApiReport {
+        analysis::ApiReport {
+            status: match self.status {
+                ApiStatus::Granted => analysis::ApiStatus::Granted,
+                ApiStatus::New => analysis::ApiStatus::New,
+                ApiStatus::Unused => analysis::ApiStatus::Unused,
+            },
+            usages: self
+                .usages
+                .into_iter()
+                .map(|usage| analysis::ApiUsage {
+                    file: usage.file,
+                    line: usage.line,
+                    column: usage.column,
+                    from: usage.from,
+                    to: usage.to,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Report {
+    /// Records usages of an API. `granted` should be whether the config permits the usages. If any
+    /// usages of an API weren't permitted, then the API is reported as new.
+    pub(crate) fn api_used(&mut self, api_usages: &ApiUsages, granted: bool) {
+        let perm_sel = api_usages.perm_sel();
+        let api = self.api_entry(&perm_sel, &api_usages.api_name);
+        if !granted {
+            api.status = ApiStatus::New;
+        }
+        for usage in &api_usages.usages {
+            api.usages.insert(UsageReport {
+                file: usage.source_location.filename().to_owned(),
+                line: usage.source_location.line(),
+                column: usage.source_location.column(),
+                from: usage.from.to_string(),
+                to: usage.to_name.to_string(),
+            });
+        }
+    }
+
+    /// Records an API that the config permits, but which wasn't used.
+    pub(crate) fn api_unused(&mut self, perm_sel: &PermSel, api: &ApiName) {
+        self.api_entry(perm_sel, api).status = ApiStatus::Unused;
+    }
+
+    /// Records where the crate that rustc just compiled uses unsafe. This is recorded regardless
+    /// of whether the crate is permitted to use unsafe.
+    pub(crate) fn crate_compiled(&mut self, info: &RustcOutput) -> Result<()> {
+        let perm_sel = PermSel::for_non_build_output(&info.crate_sel);
+        let crate_report = self.crate_entry(&perm_sel);
+        for path in &info.source_paths {
+            if path.extension().is_some_and(|ext| ext == "rs") {
+                for location in crate::unsafe_checker::scan_path(path)? {
+                    crate_report.unsafe_usages.insert(LocationReport {
+                        file: location.filename().to_owned(),
+                        line: location.line(),
+                        column: location.column(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn bin_executed(&mut self, output: &BinExecutionOutput) {
+        self.sandbox.push(SandboxOutcome {
+            crate_name: output.crate_sel.to_string(),
+            binary: output.binary_path.clone(),
+            sandbox: output.sandbox_config.kind,
+            exit_code: output.exit_code,
+            limit_exceeded: output.limit_exceeded.map(|limit| limit.to_string()),
+            denied_hosts: output
+                .denied_hosts()
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            denied_write_dirs: output
+                .denied_write_dirs()
+                .into_iter()
+                .map(Path::to_owned)
+                .collect(),
+            discarded_writes: output.captured_writes.len(),
+        });
+    }
+
+    fn crate_entry(&mut self, perm_sel: &PermSel) -> &mut CrateReport {
+        self.crates
+            .entry(perm_sel.to_string())
+            .or_insert_with(|| CrateReport {
+                selector: perm_sel.to_string(),
+                package: perm_sel.package_name.to_string(),
+                apis: BTreeMap::new(),
+                unsafe_usages: BTreeSet::new(),
+            })
+    }
+
+    fn api_entry(&mut self, perm_sel: &PermSel, api: &ApiName) -> &mut ApiReport {
+        self.crate_entry(perm_sel)
+            .apis
+            .entry(api.to_string())
+            .or_insert_with(|| ApiReport {
+                status: ApiStatus::Granted,
+                usages: BTreeSet::new(),
+            })
+    }
+
+    /// Writes the report as JSON to `out`, together with `problems` that were reported.
+    pub(crate) fn write(
+        &self,
+        problems: &[(Severity, &Problem)],
+        out: &mut dyn std::io::Write,
+    ) -> Result<()> {
+        let report = JsonReport {
+            schema_version: SCHEMA_VERSION,
+            cackle_version: env!("CARGO_PKG_VERSION"),
+            crates: self.crates.values().collect(),
+            sandbox: &self.sandbox,
+            problems: problems
+                .iter()
+                .map(|(severity, problem)| ProblemReport {
+                    severity: match severity {
+                        Severity::Info => "info",
+                        Severity::Warning => "warning",
+                        Severity::Error => "error",
+                    },
+                    package: problem.pkg_id().map(|pkg_id| pkg_id.to_string()),
+                    message: problem.to_string(),
+                })
+                .collect(),
+        };
+        serde_json::to_writer_pretty(&mut *out, &report)?;
+        writeln!(out)?;
+        Ok(())
+    }
+
+    /// Converts the report into the form exposed by the public `analysis` API.
+    pub(crate) fn into_analysis(self, problems: &[(Severity, &Problem)]) -> analysis::Report {
+        analysis::Report {
+            crates: self
+                .crates
+                .into_values()
+                .map(|crate_report| analysis::CrateReport {
+                    selector: crate_report.selector,
+                    package: crate_report.package,
+                    apis: crate_report
+                        .apis
+                        .into_iter()
+                        .map(|(api, api_report)| (api, api_report.into_analysis()))
+                        .collect(),
+                })
+                .collect(),
+            problems: problems
+                .iter()
+                .map(|(severity, problem)| analysis::Problem {
+                    severity: (*severity).into(),
+                    package: problem.pkg_id().map(|pkg_id| pkg_id.to_string()),
+                    message: problem.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the report as HTML to the directory specified in `options`. Returns the path of the
+    /// main page.
+    pub(crate) fn write_html(&self, options: &ReportOptions) -> Result<PathBuf> {
+        html::write(self, &options.html)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Report;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::config::SandboxConfig;
+    use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::CrateSel;
+    use crate::problem::ApiUsages;
+    use crate::problem::Problem;
+    use crate::problem::Severity;
+    use crate::proxy::rpc::BinExecutionOutput;
+    use std::path::PathBuf;
+
+    #[test]
+    fn json_report() {
+        let mut report = Report::default();
+        report.api_used(
+            &ApiUsages {
+                pkg_id: pkg_id("crab1"),
+                scope: PermissionScope::Build,
+                api_name: ApiName::from("fs"),
+                usages: Vec::new(),
+                severity: Severity::Error,
+            },
+            false,
+        );
+        report.api_unused(&PermSel::for_primary("crab2"), &ApiName::from("net"));
+        report.bin_executed(&BinExecutionOutput {
+            exit_code: 1,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            crate_sel: CrateSel::build_script(pkg_id("crab1")),
+            sandbox_config: SandboxConfig::default(),
+            binary_path: PathBuf::from("build-script-build"),
+            sandbox_config_display: None,
+            network_requests: Vec::new(),
+            denied_accesses: Vec::new(),
+            limit_exceeded: None,
+            captured_writes: Vec::new(),
+        });
+        let problem = Problem::new("Something went wrong");
+        let mut out = Vec::new();
+        report
+            .write(&[(Severity::Error, &problem)], &mut out)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["schema_version"], super::SCHEMA_VERSION);
+        assert_eq!(json["crates"][0]["selector"], "crab1.build");
+        assert_eq!(json["crates"][0]["package"], "crab1");
+        assert_eq!(json["crates"][0]["apis"]["fs"]["status"], "new");
+        assert_eq!(json["crates"][1]["apis"]["net"]["status"], "unused");
+        assert_eq!(json["sandbox"][0]["crate"], "crab1.build");
+        assert_eq!(json["sandbox"][0]["exit_code"], 1);
+        assert_eq!(json["problems"][0]["severity"], "error");
+        assert_eq!(json["problems"][0]["message"], "Something went wrong");
+    }
+}
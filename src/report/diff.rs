@@ -0,0 +1,167 @@
+//! Support for the `diff` subcommand, which compares the APIs used by each crate against those in a
+//! report written previously with `--output-format json`.
+
+use super::ApiStatus;
+use super::CrateReport;
+use super::Report;
+use super::SCHEMA_VERSION;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use colored::Colorize;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct DiffOptions {
+    /// A report written with `--output-format json` to compare against.
+    baseline: PathBuf,
+}
+
+/// The APIs used by each crate according to a previously written report.
+pub(crate) struct Baseline {
+    used_apis: BTreeSet<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct StoredReport {
+    crates: Vec<CrateReport>,
+}
+
+/// Differences between the APIs used by each crate in a baseline and in the current build. Each
+/// entry is a crate selector and an API name.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub(crate) struct PermissionDiff {
+    added: Vec<(String, String)>,
+    removed: Vec<(String, String)>,
+}
+
+impl Baseline {
+    pub(crate) fn load(options: &DiffOptions) -> Result<Self> {
+        let path = &options.baseline;
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read baseline `{}`", path.display()))?;
+        Self::parse(&bytes).with_context(|| format!("Invalid baseline `{}`", path.display()))
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_slice(bytes)?;
+        // Check the version before parsing the rest, since if it differs, then any parse error
+        // would likely be confusing.
+        match value.get("schema_version").and_then(|v| v.as_u64()) {
+            Some(version) if version == u64::from(SCHEMA_VERSION) => {}
+            Some(version) => bail!(
+                "Baseline has schema version {version}, but only version {SCHEMA_VERSION} is \
+                 supported. Please regenerate it with `--output-format json`"
+            ),
+            None => bail!("Missing `schema_version`. Was it written with `--output-format json`?"),
+        }
+        let report: StoredReport = serde_json::from_value(value)?;
+        Ok(Self {
+            used_apis: used_apis(report.crates.iter()),
+        })
+    }
+}
+
+impl Report {
+    /// Returns which crates have started or stopped using which APIs since `baseline`.
+    pub(crate) fn diff(&self, baseline: &Baseline) -> PermissionDiff {
+        let current = used_apis(self.crates.values());
+        PermissionDiff {
+            added: current.difference(&baseline.used_apis).cloned().collect(),
+            removed: baseline.used_apis.difference(&current).cloned().collect(),
+        }
+    }
+}
+
+impl PermissionDiff {
+    /// Returns whether any crate uses an API that it didn't use in the baseline.
+    pub(crate) fn has_additions(&self) -> bool {
+        !self.added.is_empty()
+    }
+
+    pub(crate) fn print(&self) {
+        if self.added.is_empty() && self.removed.is_empty() {
+            println!("No changes to APIs used since baseline");
+            return;
+        }
+        for (selector, api) in &self.added {
+            println!("{} {selector} uses `{api}`", "+".green());
+        }
+        for (selector, api) in &self.removed {
+            println!("{} {selector} no longer uses `{api}`", "-".red());
+        }
+    }
+}
+
+/// Returns pairs of crate selector and API name for each API that's actually used. APIs that are
+/// allowed, but unused are excluded.
+fn used_apis<'a>(crates: impl Iterator<Item = &'a CrateReport>) -> BTreeSet<(String, String)> {
+    crates
+        .flat_map(|crate_report| {
+            crate_report
+                .apis
+                .iter()
+                .filter(|(_, api_report)| api_report.status != ApiStatus::Unused)
+                .map(|(api, _)| (crate_report.selector.clone(), api.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Baseline;
+    use super::PermissionDiff;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::ApiUsages;
+    use crate::problem::Severity;
+    use crate::report::Report;
+
+    fn usages(pkg: &str, api: &'static str) -> ApiUsages {
+        ApiUsages {
+            pkg_id: pkg_id(pkg),
+            scope: PermissionScope::All,
+            api_name: ApiName::from(api),
+            usages: Vec::new(),
+            severity: Severity::Error,
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let mut baseline_report = Report::default();
+        baseline_report.api_used(&usages("crab1", "fs"), true);
+        baseline_report.api_used(&usages("crab2", "fs"), true);
+        baseline_report.api_unused(&PermSel::for_primary("crab1"), &ApiName::from("net"));
+        let mut json = Vec::new();
+        baseline_report.write(&[], &mut json).unwrap();
+        let baseline = Baseline::parse(&json).unwrap();
+
+        let mut report = Report::default();
+        report.api_used(&usages("crab1", "fs"), true);
+        report.api_used(&usages("crab1", "net"), false);
+        let diff = report.diff(&baseline);
+        assert_eq!(
+            diff,
+            PermissionDiff {
+                added: vec![("crab1".to_owned(), "net".to_owned())],
+                removed: vec![("crab2".to_owned(), "fs".to_owned())],
+            }
+        );
+        assert!(diff.has_additions());
+    }
+
+    #[test]
+    fn schema_version_mismatch() {
+        let error = Baseline::parse(br#"{"schema_version": 999, "crates": []}"#)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("schema version 999"));
+        assert!(Baseline::parse(br#"{"crates": []}"#).is_err());
+    }
+}
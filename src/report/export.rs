@@ -0,0 +1,387 @@
+//! Support for the `export` subcommand, which writes what we observed about each package in the
+//! format of another tool. For cargo-vet, we write an audit entry per package with notes saying
+//! which APIs it used and whether it contains unsafe code. For CycloneDX, we write an SBOM of the
+//! packages that were built, with the same information attached to each component as properties.
+
+use super::ApiStatus;
+use super::CrateReport;
+use super::Report;
+use crate::crate_index::CrateIndex;
+use crate::crate_index::PackageId;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use clap::ValueEnum;
+use fxhash::FxHashSet;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct ExportOptions {
+    /// The format to write.
+    #[clap(long, value_enum, default_value_t)]
+    format: ExportFormat,
+
+    /// The cargo-vet criteria that exported audits certify.
+    #[clap(long, default_value = "safe-to-deploy")]
+    criteria: String,
+
+    /// Who to record as having performed exported audits, e.g. "Name <email>".
+    #[clap(long)]
+    who: Option<String>,
+
+    /// File to write to. If not set, output is written to stdout.
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// Audit entries in the format of cargo-vet's `audits.toml`, to be reviewed and merged into it.
+    #[default]
+    CargoVet,
+    /// A CycloneDX SBOM in JSON format, with properties on each component for the number of call
+    /// sites of each API and the number of places with unsafe code.
+    Cyclonedx,
+}
+
+#[derive(Serialize)]
+struct AuditsFile<'a> {
+    audits: BTreeMap<&'a str, Vec<AuditEntry<'a>>>,
+}
+
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    who: Option<&'a str>,
+    criteria: &'a str,
+    version: String,
+    notes: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Bom {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: BomMetadata,
+    components: Vec<BomComponent>,
+    dependencies: Vec<BomDependency>,
+}
+
+#[derive(Serialize)]
+struct BomMetadata {
+    tools: BomTools,
+}
+
+#[derive(Serialize)]
+struct BomTools {
+    components: Vec<BomTool>,
+}
+
+#[derive(Serialize)]
+struct BomTool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+struct BomComponent {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "bom-ref")]
+    bom_ref: String,
+    name: String,
+    version: String,
+    purl: String,
+    properties: Vec<BomProperty>,
+}
+
+#[derive(Serialize)]
+struct BomProperty {
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BomDependency {
+    #[serde(rename = "ref")]
+    bom_ref: String,
+    depends_on: Vec<String>,
+}
+
+impl Report {
+    /// Writes the report in the format selected by `options`.
+    pub(crate) fn export(&self, options: &ExportOptions, crate_index: &CrateIndex) -> Result<()> {
+        let output = match options.format {
+            ExportFormat::CargoVet => self.cargo_vet_audits(options, crate_index)?,
+            ExportFormat::Cyclonedx => self.cyclonedx_bom(crate_index)?,
+        };
+        match &options.output {
+            Some(path) => crate::fs::write(path, output)
+                .with_context(|| format!("Failed to write {}", path.display())),
+            None => {
+                print!("{output}");
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns an audit entry for each package that isn't a workspace member and that was built.
+    fn cargo_vet_audits(
+        &self,
+        options: &ExportOptions,
+        crate_index: &CrateIndex,
+    ) -> Result<String> {
+        let mut audits: BTreeMap<&str, Vec<AuditEntry>> = BTreeMap::new();
+        for (pkg_id, crate_reports) in self.built_packages(crate_index) {
+            if crate_index.is_workspace_member(pkg_id) {
+                continue;
+            }
+            audits
+                .entry(pkg_id.name_str())
+                .or_default()
+                .push(AuditEntry {
+                    who: options.who.as_deref(),
+                    criteria: &options.criteria,
+                    version: pkg_id.version().to_string(),
+                    notes: notes(&crate_reports),
+                });
+        }
+        Ok(toml::to_string(&AuditsFile { audits })?)
+    }
+
+    /// Returns a CycloneDX SBOM with a component for each package that was built. Workspace members
+    /// are applications, everything else is a library.
+    fn cyclonedx_bom(&self, crate_index: &CrateIndex) -> Result<String> {
+        let packages = self.built_packages(crate_index);
+        let built: FxHashSet<&PackageId> = packages.iter().map(|(pkg_id, _)| *pkg_id).collect();
+        let mut components = Vec::new();
+        let mut dependencies = Vec::new();
+        for (pkg_id, crate_reports) in &packages {
+            let kind = if crate_index.is_workspace_member(pkg_id) {
+                "application"
+            } else {
+                "library"
+            };
+            components.push(BomComponent {
+                kind,
+                bom_ref: purl(pkg_id),
+                name: pkg_id.name_str().to_owned(),
+                version: pkg_id.version().to_string(),
+                purl: purl(pkg_id),
+                properties: properties(crate_reports),
+            });
+            dependencies.push(BomDependency {
+                bom_ref: purl(pkg_id),
+                depends_on: crate_index
+                    .dependencies(pkg_id)
+                    .iter()
+                    .filter(|dep| built.contains(&dep.pkg_id))
+                    .map(|dep| purl(&dep.pkg_id))
+                    .collect(),
+            });
+        }
+        let bom = Bom {
+            bom_format: "CycloneDX",
+            spec_version: "1.5",
+            version: 1,
+            metadata: BomMetadata {
+                tools: BomTools {
+                    components: vec![BomTool {
+                        kind: "application",
+                        name: "cackle",
+                        version: env!("CARGO_PKG_VERSION"),
+                    }],
+                },
+            },
+            components,
+            dependencies,
+        };
+        Ok(serde_json::to_string_pretty(&bom)? + "\n")
+    }
+
+    /// Returns the packages that were built, sorted by name and version, each with the reports of
+    /// the crates that were built from it, e.g. a library and a build script.
+    fn built_packages<'a>(
+        &'a self,
+        crate_index: &'a CrateIndex,
+    ) -> Vec<(&'a PackageId, Vec<&'a CrateReport>)> {
+        let mut pkg_ids: Vec<&PackageId> = crate_index.package_ids().collect();
+        pkg_ids.sort_by(|a, b| (a.name_str(), a.version()).cmp(&(b.name_str(), b.version())));
+        pkg_ids
+            .into_iter()
+            .filter_map(|pkg_id| {
+                let perm_name = pkg_id.perm_name();
+                let crate_reports: Vec<&CrateReport> = self
+                    .crates
+                    .values()
+                    .filter(|crate_report| *crate_report.package == *perm_name)
+                    .collect();
+                (!crate_reports.is_empty()).then_some((pkg_id, crate_reports))
+            })
+            .collect()
+    }
+}
+
+/// Returns the package URL of `pkg_id`, which we also use to refer to it within an SBOM.
+fn purl(pkg_id: &PackageId) -> String {
+    format!("pkg:cargo/{}@{}", pkg_id.name_str(), pkg_id.version())
+}
+
+/// Returns SBOM properties with the number of call sites of each API and the number of places with
+/// unsafe code, summed over all crates built from a package.
+fn properties(crate_reports: &[&CrateReport]) -> Vec<BomProperty> {
+    let mut call_sites: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut unsafe_usages = 0;
+    for crate_report in crate_reports {
+        for (api, api_report) in &crate_report.apis {
+            if api_report.status != ApiStatus::Unused {
+                *call_sites.entry(api).or_default() += api_report.usages.len();
+            }
+        }
+        unsafe_usages += crate_report.unsafe_usages.len();
+    }
+    call_sites
+        .into_iter()
+        .map(|(api, count)| BomProperty {
+            name: format!("cackle:api:{api}"),
+            value: count.to_string(),
+        })
+        .chain(std::iter::once(BomProperty {
+            name: "cackle:unsafe".to_owned(),
+            value: unsafe_usages.to_string(),
+        }))
+        .collect()
+}
+
+/// Describes the APIs used and unsafe code found in a package, which may have been built as
+/// several crates, e.g. a library and a build script.
+fn notes(crate_reports: &[&CrateReport]) -> String {
+    let mut parts = Vec::new();
+    for crate_report in crate_reports {
+        let apis: Vec<&str> = crate_report
+            .apis
+            .iter()
+            .filter(|(_, api)| api.status != ApiStatus::Unused)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if !apis.is_empty() {
+            parts.push(format!(
+                "{} uses {}",
+                crate_report.selector,
+                apis.join(", ")
+            ));
+        }
+        if !crate_report.unsafe_usages.is_empty() {
+            parts.push(format!(
+                "{} has unsafe code in {} places",
+                crate_report.selector,
+                crate_report.unsafe_usages.len()
+            ));
+        }
+    }
+    if parts.is_empty() {
+        "cackle observed no use of restricted APIs or unsafe code".to_owned()
+    } else {
+        format!("Observed by cackle: {}", parts.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExportOptions;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::crate_index::testing::index_with_package_names;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::ApiUsages;
+    use crate::problem::Severity;
+    use crate::report::Report;
+
+    fn report() -> Report {
+        let mut report = Report::default();
+        for (scope, api) in [
+            (PermissionScope::All, "fs"),
+            (PermissionScope::All, "net"),
+            (PermissionScope::Build, "process"),
+        ] {
+            report.api_used(
+                &ApiUsages {
+                    pkg_id: pkg_id("crab1"),
+                    scope,
+                    api_name: ApiName::from(api),
+                    usages: Vec::new(),
+                    severity: Severity::Error,
+                },
+                true,
+            );
+        }
+        report.api_unused(&PermSel::for_primary("crab2"), &ApiName::from("net"));
+        report
+    }
+
+    #[test]
+    fn cargo_vet_audits() {
+        let report = report();
+        let crate_index = index_with_package_names(&["crab1", "crab2", "crab3"]);
+        let options = ExportOptions {
+            who: Some("Someone <someone@example.com>".to_owned()),
+            criteria: "safe-to-run".to_owned(),
+            ..ExportOptions::default()
+        };
+        let audits: toml::Table =
+            toml::from_str(&report.cargo_vet_audits(&options, &crate_index).unwrap()).unwrap();
+        let crab1 = &audits["audits"]["crab1"][0];
+        assert_eq!(crab1["who"].as_str(), Some("Someone <someone@example.com>"));
+        assert_eq!(crab1["criteria"].as_str(), Some("safe-to-run"));
+        assert_eq!(crab1["version"].as_str(), Some("0.0.0"));
+        assert_eq!(
+            crab1["notes"].as_str(),
+            Some("Observed by cackle: crab1 uses fs, net; crab1.build uses process")
+        );
+        assert_eq!(
+            audits["audits"]["crab2"][0]["notes"].as_str(),
+            Some("cackle observed no use of restricted APIs or unsafe code")
+        );
+        // crab3 wasn't built, so we don't know anything about it.
+        assert!(audits["audits"].get("crab3").is_none());
+    }
+
+    #[test]
+    fn cyclonedx_bom() {
+        let crate_index = index_with_package_names(&["crab1", "crab2", "crab3"]);
+        let bom: serde_json::Value =
+            serde_json::from_str(&report().cyclonedx_bom(&crate_index).unwrap()).unwrap();
+        assert_eq!(bom["bomFormat"], "CycloneDX");
+        let components = bom["components"].as_array().unwrap();
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0]["name"], "crab1");
+        assert_eq!(components[0]["type"], "library");
+        assert_eq!(components[0]["purl"], "pkg:cargo/crab1@0.0.0");
+        let properties: Vec<&str> = components[0]["properties"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|property| property["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            properties,
+            [
+                "cackle:api:fs",
+                "cackle:api:net",
+                "cackle:api:process",
+                "cackle:unsafe"
+            ]
+        );
+        // Unused APIs aren't exercised, so aren't included.
+        assert_eq!(components[1]["properties"][0]["name"], "cackle:unsafe");
+        assert_eq!(bom["dependencies"][0]["ref"], "pkg:cargo/crab1@0.0.0");
+    }
+}
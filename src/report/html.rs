@@ -0,0 +1,273 @@
+//! Renders a `Report` as a static HTML page, for sharing with people reviewing dependencies.
+
+use super::ApiStatus;
+use super::CrateReport;
+use super::Report;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; vertical-align: top; }
+td.granted { background: #dfd; }
+td.new { background: #fcc; }
+td.unused { background: #eee; color: #666; }
+tr.failed { background: #fcc; }
+details { margin-left: 1em; }
+code { font-size: 0.9em; }
+";
+
+/// Writes `report` to `index.html` in `dir`, returning the path that was written.
+pub(super) fn write(report: &Report, dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    let path = dir.join("index.html");
+    std::fs::write(&path, render(report))
+        .with_context(|| format!("Failed to write `{}`", path.display()))?;
+    Ok(path)
+}
+
+fn render(report: &Report) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>Cackle report</title>\n");
+    let _ = writeln!(out, "<style>{STYLE}</style>\n</head>\n<body>");
+    let _ = writeln!(
+        out,
+        "<h1>Cackle report</h1>\n<p>Generated by cackle {}.</p>",
+        env!("CARGO_PKG_VERSION")
+    );
+    render_matrix(report, &mut out);
+    render_sandbox(report, &mut out);
+    out.push_str("<h2>Crates</h2>\n");
+    for (index, crate_report) in report.crates.values().enumerate() {
+        render_crate(index, crate_report, &mut out);
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Renders a table with a row per crate and a column per API, showing which APIs each crate uses.
+fn render_matrix(report: &Report, out: &mut String) {
+    let apis: BTreeSet<&str> = report
+        .crates
+        .values()
+        .flat_map(|crate_report| crate_report.apis.keys().map(String::as_str))
+        .collect();
+    out.push_str("<h2>Permissions</h2>\n<table>\n<tr><th>Crate</th>");
+    for api in &apis {
+        let _ = write!(out, "<th>{}</th>", escape(api));
+    }
+    out.push_str("<th>Unsafe</th></tr>\n");
+    for (index, crate_report) in report.crates.values().enumerate() {
+        let _ = write!(
+            out,
+            "<tr><td><a href=\"#{}\">{}</a></td>",
+            crate_anchor(index),
+            escape(&crate_report.selector)
+        );
+        for api in &apis {
+            match crate_report.apis.get(*api) {
+                Some(api_report) => {
+                    let (class, text) = status_text(api_report.status);
+                    let _ = write!(
+                        out,
+                        "<td class=\"{class}\"><a href=\"#{}\">{text}</a></td>",
+                        api_anchor(index, api)
+                    );
+                }
+                None => out.push_str("<td></td>"),
+            }
+        }
+        if crate_report.unsafe_usages.is_empty() {
+            out.push_str("<td>0</td>");
+        } else {
+            let _ = write!(
+                out,
+                "<td><a href=\"#{}-unsafe\">{}</a></td>",
+                crate_anchor(index),
+                crate_report.unsafe_usages.len()
+            );
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+fn render_sandbox(report: &Report, out: &mut String) {
+    out.push_str("<h2>Sandboxed binaries</h2>\n");
+    if report.sandbox.is_empty() {
+        out.push_str("<p>No build scripts or tests were run in a sandbox.</p>\n");
+        return;
+    }
+    out.push_str(
+        "<table>\n<tr><th>Crate</th><th>Binary</th><th>Sandbox</th><th>Exit code</th>\
+         <th>Limit exceeded</th><th>Denied hosts</th><th>Denied writes</th>\
+         <th>Discarded writes</th></tr>\n",
+    );
+    for outcome in &report.sandbox {
+        let failed = outcome.exit_code != 0 || outcome.limit_exceeded.is_some();
+        let sandbox = outcome
+            .sandbox
+            .map(|kind| format!("{kind:?}"))
+            .unwrap_or_else(|| "None".to_owned());
+        let denied_write_dirs: Vec<String> = outcome
+            .denied_write_dirs
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect();
+        let _ = writeln!(
+            out,
+            "<tr{}><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{}</td><td>{}</td></tr>",
+            if failed { " class=\"failed\"" } else { "" },
+            escape(&outcome.crate_name),
+            escape(&outcome.binary.display().to_string()),
+            escape(&sandbox),
+            outcome.exit_code,
+            escape(outcome.limit_exceeded.as_deref().unwrap_or("")),
+            escape(&outcome.denied_hosts.join(", ")),
+            escape(&denied_write_dirs.join(", ")),
+            outcome.discarded_writes,
+        );
+    }
+    out.push_str("</table>\n");
+}
+
+/// Renders the usage locations for each API used by a crate and its unsafe usages.
+fn render_crate(index: usize, crate_report: &CrateReport, out: &mut String) {
+    let _ = writeln!(
+        out,
+        "<h3 id=\"{}\">{}</h3>",
+        crate_anchor(index),
+        escape(&crate_report.selector)
+    );
+    for (api, api_report) in &crate_report.apis {
+        let (_, status) = status_text(api_report.status);
+        let _ = writeln!(
+            out,
+            "<details id=\"{}\"><summary>{} ({status}, {} usages)</summary>",
+            api_anchor(index, api),
+            escape(api),
+            api_report.usages.len()
+        );
+        if !api_report.usages.is_empty() {
+            out.push_str("<table>\n<tr><th>Location</th><th>From</th><th>To</th></tr>\n");
+            for usage in &api_report.usages {
+                let _ = writeln!(
+                    out,
+                    "<tr><td><code>{}</code></td><td><code>{}</code></td>\
+                     <td><code>{}</code></td></tr>",
+                    escape(&location_text(&usage.file, usage.line, usage.column)),
+                    escape(&usage.from),
+                    escape(&usage.to)
+                );
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</details>\n");
+    }
+    if !crate_report.unsafe_usages.is_empty() {
+        let _ = writeln!(
+            out,
+            "<details id=\"{}-unsafe\"><summary>unsafe ({} usages)</summary>\n<ul>",
+            crate_anchor(index),
+            crate_report.unsafe_usages.len()
+        );
+        for location in &crate_report.unsafe_usages {
+            let _ = writeln!(
+                out,
+                "<li><code>{}</code></li>",
+                escape(&location_text(
+                    &location.file,
+                    location.line,
+                    location.column
+                ))
+            );
+        }
+        out.push_str("</ul>\n</details>\n");
+    }
+}
+
+fn status_text(status: ApiStatus) -> (&'static str, &'static str) {
+    match status {
+        ApiStatus::Granted => ("granted", "granted"),
+        ApiStatus::New => ("new", "NEW"),
+        ApiStatus::Unused => ("unused", "unused"),
+    }
+}
+
+fn location_text(file: &Path, line: u32, column: Option<u32>) -> String {
+    match column {
+        Some(column) => format!("{}:{line}:{column}", file.display()),
+        None => format!("{}:{line}", file.display()),
+    }
+}
+
+fn crate_anchor(index: usize) -> String {
+    format!("crate-{index}")
+}
+
+/// Returns an anchor for an API of a crate. API names are restricted to characters that are valid
+/// in an ID, but we escape them anyway, since they end up in an attribute.
+fn api_anchor(index: usize, api: &str) -> String {
+    format!("crate-{index}-api-{}", escape(api))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::ApiUsages;
+    use crate::problem::Severity;
+    use crate::report::Report;
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            super::escape("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn render() {
+        let mut report = Report::default();
+        report.api_used(
+            &ApiUsages {
+                pkg_id: pkg_id("crab1"),
+                scope: PermissionScope::All,
+                api_name: ApiName::from("net"),
+                usages: Vec::new(),
+                severity: Severity::Error,
+            },
+            false,
+        );
+        let html = super::render(&report);
+        assert!(html.contains("<th>net</th>"));
+        assert!(html.contains("<td class=\"new\"><a href=\"#crate-0-api-net\">NEW</a></td>"));
+        assert!(html.contains("<details id=\"crate-0-api-net\">"));
+        assert!(html.contains("No build scripts or tests were run in a sandbox."));
+    }
+}
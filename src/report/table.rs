@@ -0,0 +1,260 @@
+//! Support for `summary --table`, which prints a table of the APIs used or granted for each crate,
+//! together with how many call sites use each API.
+
+use super::ApiStatus;
+use super::CrateReport;
+use super::Report;
+use crate::summary::OutputFormat;
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum TableSort {
+    /// Sort by crate name.
+    #[default]
+    Crate,
+    /// Crates with the most call sites of restricted APIs first.
+    Usages,
+    /// Crates that use or are granted the most APIs first.
+    Permissions,
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    #[serde(rename = "crate")]
+    crate_name: &'a str,
+    call_sites: usize,
+    apis: BTreeMap<&'a str, Cell>,
+}
+
+#[derive(Serialize, Clone, Copy)]
+struct Cell {
+    status: ApiStatus,
+    call_sites: usize,
+}
+
+impl Report {
+    /// Returns the table as a string in the requested format. Human-readable output is a table with
+    /// aligned columns and JSON output is an array with an object per crate.
+    pub(crate) fn table(&self, format: OutputFormat, sort: TableSort) -> Result<String> {
+        let rows = self.rows(sort);
+        let apis: BTreeSet<&str> = rows
+            .iter()
+            .flat_map(|row| row.apis.keys().copied())
+            .collect();
+        Ok(match format {
+            OutputFormat::Human => text(&rows, &apis),
+            OutputFormat::Csv => csv(&rows),
+            OutputFormat::Md => markdown(&rows, &apis),
+            OutputFormat::Json => serde_json::to_string_pretty(&rows)? + "\n",
+        })
+    }
+
+    fn rows(&self, sort: TableSort) -> Vec<Row<'_>> {
+        let mut rows: Vec<Row> = self
+            .crates
+            .values()
+            .filter(|crate_report| !crate_report.apis.is_empty())
+            .map(Row::new)
+            .collect();
+        match sort {
+            TableSort::Crate => {}
+            TableSort::Usages => rows.sort_by_key(|row| std::cmp::Reverse(row.call_sites)),
+            TableSort::Permissions => rows.sort_by_key(|row| std::cmp::Reverse(row.apis.len())),
+        }
+        rows
+    }
+}
+
+impl<'a> Row<'a> {
+    fn new(crate_report: &'a CrateReport) -> Self {
+        let apis: BTreeMap<&str, Cell> = crate_report
+            .apis
+            .iter()
+            .map(|(api, api_report)| {
+                (
+                    api.as_str(),
+                    Cell {
+                        status: api_report.status,
+                        call_sites: api_report.usages.len(),
+                    },
+                )
+            })
+            .collect();
+        Self {
+            crate_name: &crate_report.selector,
+            call_sites: apis.values().map(|cell| cell.call_sites).sum(),
+            apis,
+        }
+    }
+
+    /// Returns the text to display for `api` in a table cell.
+    fn cell_text(&self, api: &str) -> String {
+        match self.apis.get(api) {
+            Some(cell) => match cell.status {
+                ApiStatus::Granted => format!("granted ({})", cell.call_sites),
+                ApiStatus::New => format!("NEW ({})", cell.call_sites),
+                ApiStatus::Unused => "unused".to_owned(),
+            },
+            None => String::new(),
+        }
+    }
+
+    /// Returns the cells of the row, including the crate name and total call sites.
+    fn cells(&self, apis: &BTreeSet<&str>) -> Vec<String> {
+        let mut cells = vec![self.crate_name.to_owned()];
+        cells.extend(apis.iter().map(|api| self.cell_text(api)));
+        cells.push(self.call_sites.to_string());
+        cells
+    }
+}
+
+fn header(apis: &BTreeSet<&str>) -> Vec<String> {
+    let mut header = vec!["crate".to_owned()];
+    header.extend(apis.iter().map(|api| api.to_string()));
+    header.push("call sites".to_owned());
+    header
+}
+
+fn text(rows: &[Row], apis: &BTreeSet<&str>) -> String {
+    let lines: Vec<Vec<String>> = std::iter::once(header(apis))
+        .chain(rows.iter().map(|row| row.cells(apis)))
+        .collect();
+    let mut widths = vec![0; lines[0].len()];
+    for line in &lines {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for line in &lines {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        let _ = writeln!(out, "{}", cells.join("  ").trim_end());
+    }
+    out
+}
+
+fn csv(rows: &[Row]) -> String {
+    let mut out = "crate,api,status,call_sites\n".to_owned();
+    for row in rows {
+        for (api, cell) in &row.apis {
+            let status = match cell.status {
+                ApiStatus::Granted => "granted",
+                ApiStatus::New => "new",
+                ApiStatus::Unused => "unused",
+            };
+            let _ = writeln!(
+                out,
+                "{},{},{status},{}",
+                csv_field(row.crate_name),
+                csv_field(api),
+                cell.call_sites
+            );
+        }
+    }
+    out
+}
+
+/// Quotes `field` if it contains characters that would otherwise need escaping.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn markdown(rows: &[Row], apis: &BTreeSet<&str>) -> String {
+    let header = header(apis);
+    let mut out = String::new();
+    let _ = writeln!(out, "| {} |", header.join(" | "));
+    let _ = writeln!(out, "|{}", "---|".repeat(header.len()));
+    for row in rows {
+        let _ = writeln!(out, "| {} |", row.cells(apis).join(" | "));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TableSort;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::ApiUsages;
+    use crate::problem::Severity;
+    use crate::report::Report;
+    use crate::summary::OutputFormat;
+
+    fn report() -> Report {
+        let mut report = Report::default();
+        report.api_used(
+            &ApiUsages {
+                pkg_id: pkg_id("crab1"),
+                scope: PermissionScope::All,
+                api_name: ApiName::from("fs"),
+                usages: Vec::new(),
+                severity: Severity::Error,
+            },
+            false,
+        );
+        report.api_unused(&PermSel::for_primary("crab1"), &ApiName::from("net"));
+        report.api_unused(&PermSel::for_primary("crab2"), &ApiName::from("fs"));
+        report.api_unused(&PermSel::for_primary("crab2"), &ApiName::from("net"));
+        report
+    }
+
+    #[test]
+    fn formats() {
+        let report = report();
+        assert_eq!(
+            report.table(OutputFormat::Human, TableSort::Crate).unwrap(),
+            "crate  fs       net     call sites\n\
+             crab1  NEW (0)  unused  0\n\
+             crab2  unused   unused  0\n"
+        );
+        assert_eq!(
+            report.table(OutputFormat::Csv, TableSort::Crate).unwrap(),
+            "crate,api,status,call_sites\n\
+             crab1,fs,new,0\n\
+             crab1,net,unused,0\n\
+             crab2,fs,unused,0\n\
+             crab2,net,unused,0\n"
+        );
+        assert_eq!(
+            report.table(OutputFormat::Md, TableSort::Crate).unwrap(),
+            "| crate | fs | net | call sites |\n\
+             |---|---|---|---|\n\
+             | crab1 | NEW (0) | unused | 0 |\n\
+             | crab2 | unused | unused | 0 |\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&report.table(OutputFormat::Json, TableSort::Crate).unwrap())
+                .unwrap();
+        assert_eq!(json[0]["crate"], "crab1");
+        assert_eq!(json[0]["apis"]["fs"]["status"], "new");
+
+        // crab2 now has more APIs than crab1, so should be sorted first.
+        let mut report = report;
+        report.api_unused(&PermSel::for_primary("crab2"), &ApiName::from("process"));
+        let csv = report
+            .table(OutputFormat::Csv, TableSort::Permissions)
+            .unwrap();
+        assert!(csv.lines().nth(1).unwrap().starts_with("crab2,"));
+    }
+
+    #[test]
+    fn csv_field() {
+        assert_eq!(super::csv_field("foo"), "foo");
+        assert_eq!(super::csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}
@@ -0,0 +1,181 @@
+//! Determines which source paths belong to the Rust standard library, or to the crates that are
+//! precompiled along with it, e.g. hashbrown. Usages from such code are normally not attributed to
+//! any package.
+
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+
+/// The commit date of the first toolchains whose precompiled dependencies of the standard library
+/// have source paths under `/rust/deps` rather than `/cargo/registry`.
+const DEPS_REMAP_DATE: &str = "2023-10-26";
+
+/// Crates in the `library` directory of the rust repository. Used to recognise the standard library
+/// when its source paths weren't remapped, as is the case with some distribution-built toolchains.
+const LIBRARY_CRATES: &[&str] = &[
+    "alloc",
+    "backtrace",
+    "core",
+    "panic_abort",
+    "panic_unwind",
+    "portable-simd",
+    "proc_macro",
+    "profiler_builtins",
+    "std",
+    "std_detect",
+    "stdarch",
+    "test",
+    "unwind",
+];
+
+#[derive(Debug, Clone)]
+pub(crate) struct RustStd {
+    sysroot: Arc<Path>,
+    /// Prefixes that source paths in the debug info of the precompiled standard library and its
+    /// dependencies were remapped to when the toolchain was built.
+    remapped_prefixes: Vec<PathBuf>,
+}
+
+impl RustStd {
+    /// Queries the toolchain that will be used to build the workspace in `dir`.
+    pub(crate) fn detect(dir: &Path) -> Result<Self> {
+        let sysroot = rustc_output(dir, &["--print", "sysroot"])?;
+        // If we can't get version info, we still know the sysroot, so we fall back to recognising
+        // paths from any toolchain.
+        let version_info = rustc_output(dir, &["-vV"]).unwrap_or_default();
+        Ok(Self::new(Path::new(sysroot.trim()), &version_info))
+    }
+
+    /// Creates an instance for a toolchain with `sysroot` and the supplied output of `rustc -vV`.
+    fn new(sysroot: &Path, version_info: &str) -> Self {
+        let field = |name: &str| {
+            version_info
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+                .filter(|value| *value != "unknown")
+        };
+        let mut remapped_prefixes = vec![match field("commit-hash") {
+            Some(hash) => Path::new("/rustc").join(hash),
+            None => PathBuf::from("/rustc"),
+        }];
+        match field("commit-date") {
+            Some(date) if date >= DEPS_REMAP_DATE => {
+                remapped_prefixes.push(PathBuf::from("/rust/deps"));
+            }
+            Some(_) => remapped_prefixes.push(PathBuf::from("/cargo/registry")),
+            None => {
+                remapped_prefixes.push(PathBuf::from("/rust/deps"));
+                remapped_prefixes.push(PathBuf::from("/cargo/registry"));
+            }
+        }
+        Self {
+            sysroot: Arc::from(sysroot),
+            remapped_prefixes,
+        }
+    }
+
+    pub(crate) fn sysroot(&self) -> &Arc<Path> {
+        &self.sysroot
+    }
+
+    /// Returns whether `source_path` is within the sysroot. Packages never are, so this can be
+    /// checked before looking for the package containing a path.
+    pub(crate) fn is_in_sysroot(&self, source_path: &Path) -> bool {
+        !self.sysroot.as_os_str().is_empty() && source_path.starts_with(&self.sysroot)
+    }
+
+    /// Returns whether `source_path` is from the standard library or from one of the precompiled
+    /// crates that comes with it. Since `/cargo/registry` may also be where packages live, e.g. if
+    /// `CARGO_HOME` is `/cargo`, this should only be relied upon for paths that aren't in any
+    /// package.
+    pub(crate) fn contains(&self, source_path: &Path) -> bool {
+        self.is_in_sysroot(source_path)
+            || self
+                .remapped_prefixes
+                .iter()
+                .any(|prefix| source_path.starts_with(prefix))
+            || is_unmapped_library_path(source_path)
+    }
+}
+
+impl Default for RustStd {
+    fn default() -> Self {
+        Self::new(Path::new(""), "")
+    }
+}
+
+/// Returns whether `source_path` looks like a path within the standard library's source, i.e.
+/// `.../library/{crate}/...`.
+fn is_unmapped_library_path(source_path: &Path) -> bool {
+    let mut components = source_path.components().map(|c| c.as_os_str());
+    while components.any(|c| c == "library") {
+        if components
+            .next()
+            .and_then(|c| c.to_str())
+            .is_some_and(|c| LIBRARY_CRATES.contains(&c))
+        {
+            return true;
+        }
+    }
+    false
+}
+
+fn rustc_output(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("rustc")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run `rustc {}`", args.join(" ")))?;
+    String::from_utf8(output.stdout)
+        .with_context(|| format!("Output of `rustc {}` isn't UTF-8", args.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RustStd;
+    use std::path::Path;
+
+    const VERSION_INFO: &str = "rustc 1.79.0 (129f3b996 2024-06-10)\n\
+        binary: rustc\n\
+        commit-hash: 129f3b9964af4d4a709d1383930ade12dfe7c081\n\
+        commit-date: 2024-06-10\n\
+        host: x86_64-unknown-linux-gnu\n\
+        release: 1.79.0\n";
+
+    #[test]
+    fn classify_paths() {
+        let rust_std = RustStd::new(
+            Path::new("/home/me/.rustup/toolchains/stable"),
+            VERSION_INFO,
+        );
+        let contains = |path: &str| rust_std.contains(Path::new(path));
+        assert!(contains(
+            "/rustc/129f3b9964af4d4a709d1383930ade12dfe7c081/library/std/src/fs.rs"
+        ));
+        assert!(contains("/rust/deps/hashbrown-0.14.5/src/map.rs"));
+        assert!(contains(
+            "/home/me/.rustup/toolchains/stable/lib/rustlib/src/rust/library/core/src/fmt/mod.rs"
+        ));
+        assert!(contains(
+            "/builddir/build/BUILD/rustc-1.79.0-src/library/alloc/src/vec/mod.rs"
+        ));
+        // Source from a user's registry and a user's own `library` directory.
+        assert!(!contains(
+            "/cargo/registry/src/index.crates.io-6f17d22bba15001f/foo-1.0.0/src/lib.rs"
+        ));
+        assert!(!contains("/home/me/project/library/src/lib.rs"));
+    }
+
+    #[test]
+    fn old_toolchain() {
+        let version_info = VERSION_INFO.replace("2024-06-10", "2023-08-23");
+        let rust_std = RustStd::new(Path::new("/sysroot"), &version_info);
+        assert!(rust_std.contains(Path::new(
+            "/cargo/registry/src/index.crates.io-6f17d22bba15001f/hashbrown-0.14.0/src/map.rs"
+        )));
+        assert!(!rust_std.contains(Path::new("/rust/deps/hashbrown-0.14.5/src/map.rs")));
+    }
+}
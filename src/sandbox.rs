@@ -8,11 +8,40 @@ use anyhow::Context;
 use anyhow::Result;
 use std::ffi::OsStr;
 use std::fmt::Display;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 
 mod bubblewrap;
+mod capabilities;
+mod capture;
+mod http_proxy;
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod landlock;
+mod limits;
+#[cfg(target_os = "linux")]
+mod namespaces;
+mod seatbelt;
+mod trace;
+
+pub(crate) use capabilities::skipped_protections;
+pub(crate) use capabilities::Capabilities;
+pub(crate) use capabilities::Protection;
+pub(crate) use capture::CapturedWrite;
+pub(crate) use capture::WriteCapture;
+pub(crate) use http_proxy::HttpProxy;
+pub(crate) use http_proxy::NetworkRequest;
+pub(crate) use limits::children_cpu_time;
+pub(crate) use limits::ResourceLimit;
+pub(crate) use limits::ResourceLimits;
+pub(crate) use trace::find_in_path;
+pub(crate) use trace::AccessKind;
+pub(crate) use trace::DeniedAccess;
+pub(crate) use trace::Trace;
 
 pub(crate) trait Sandbox {
     /// Runs `command` inside the sandbox.
@@ -30,12 +59,25 @@ pub(crate) trait Sandbox {
     /// Bind `dir` into the sandbox writable.
     fn writable_bind(&mut self, dir: &Path);
 
+    /// Mount an overlay at `dir`, so that `dir` appears writable, but writes go to `upper` instead.
+    /// `work` must be an empty directory on the same filesystem as `upper`. Only called if
+    /// `supports_overlay` returns true.
+    fn overlay(&mut self, _dir: &Path, _upper: &Path, _work: &Path) {}
+
+    /// Returns whether this kind of sandbox supports `overlay`.
+    fn supports_overlay(&self) -> bool {
+        false
+    }
+
     /// Allow unrestricted network access.
     fn allow_network(&mut self);
 
     /// Append a sandbox-specific argument.
     fn raw_arg(&mut self, arg: &OsStr);
 
+    /// Limit the resources that may be consumed by the sandboxed process.
+    fn set_resource_limits(&mut self, limits: ResourceLimits);
+
     /// Pass through the value of `env_var_name`
     fn pass_env(&mut self, env_var_name: &str) {
         if let Ok(value) = std::env::var(env_var_name) {
@@ -43,11 +85,14 @@ pub(crate) trait Sandbox {
         }
     }
 
-    /// Pass through all cargo environment variables.
-    fn pass_cargo_env(&mut self) {
-        self.pass_env("OUT_DIR");
+    /// Pass through all cargo environment variables that `config` doesn't deny.
+    fn pass_cargo_env(&mut self, config: &SandboxConfig) {
+        pass_env_for_config(self, config, "OUT_DIR");
         for (var, value) in std::env::vars_os() {
-            if var.to_str().is_some_and(is_cargo_env) {
+            if var
+                .to_str()
+                .is_some_and(|var| is_cargo_env(var) && !is_env_denied(config, var))
+            {
                 self.set_env(OsStr::new(&var), OsStr::new(&value));
             }
         }
@@ -59,9 +104,13 @@ pub(crate) trait Sandbox {
 }
 
 pub(crate) fn from_config(config: &SandboxConfig) -> Result<Option<Box<dyn Sandbox>>> {
-    let mut sandbox = match &config.kind {
+    let mut sandbox = match resolved_kind(config) {
         None | Some(SandboxKind::Disabled) => return Ok(None),
         Some(SandboxKind::Bubblewrap) => Box::<bubblewrap::Bubblewrap>::default(),
+        Some(SandboxKind::Landlock) => landlock_sandbox()?,
+        Some(SandboxKind::Namespaces) => namespaces_sandbox()?,
+        Some(SandboxKind::Seatbelt) => Box::<seatbelt::Seatbelt>::default(),
+        Some(SandboxKind::Auto) => unreachable!("`Auto` should have been resolved"),
     };
 
     let home = PathBuf::from(std::env::var("HOME").context("Couldn't get HOME env var")?);
@@ -72,18 +121,14 @@ pub(crate) fn from_config(config: &SandboxConfig) -> Result<Option<Box<dyn Sandb
     sandbox.tmpfs(&home);
     sandbox.tmpfs(Path::new("/var"));
     sandbox.tmpfs(Path::new("/tmp"));
-    // We need access to some parts of ~/.cargo in order to be able to build, but we don't bind all
-    // of it because it might contain crates.io credentials, which we'd like to avoid exposing.
-    let cargo_home = &home.join(".cargo");
-    sandbox.ro_bind(&cargo_home.join("bin"));
-    sandbox.ro_bind(&cargo_home.join("git"));
-    sandbox.ro_bind(&cargo_home.join("registry"));
-    sandbox.ro_bind(&home.join(".rustup"));
+    for dir in visible_home_dirs(&home) {
+        sandbox.ro_bind(&dir);
+    }
     sandbox.set_env(OsStr::new("USER"), OsStr::new("user"));
-    sandbox.pass_env("PATH");
-    sandbox.pass_env("HOME");
+    pass_env_for_config(sandbox.as_mut(), config, "PATH");
+    pass_env_for_config(sandbox.as_mut(), config, "HOME");
     for env in &config.pass_env {
-        sandbox.pass_env(env);
+        pass_env_for_config(sandbox.as_mut(), config, env);
     }
 
     // Allow read access to the crate's root source directory.
@@ -92,8 +137,11 @@ pub(crate) fn from_config(config: &SandboxConfig) -> Result<Option<Box<dyn Sandb
     // LD_LIBRARY_PATH is set when running `cargo test` on crates that normally compile as
     // cdylibs - e.g. proc macros. If we don't pass it through, those tests will fail to find
     // runtime dependencies.
-    sandbox.pass_env("LD_LIBRARY_PATH");
-    sandbox.pass_cargo_env();
+    pass_env_for_config(sandbox.as_mut(), config, "LD_LIBRARY_PATH");
+    sandbox.pass_cargo_env(config);
+    for (var, value) in &config.set_env {
+        sandbox.set_env(OsStr::new(var), OsStr::new(value));
+    }
 
     for dir in &config.bind_writable {
         if !dir.exists() {
@@ -126,10 +174,73 @@ pub(crate) fn from_config(config: &SandboxConfig) -> Result<Option<Box<dyn Sandb
         // permitted prevents DNS lookups on some systems.
         sandbox.tmpfs(Path::new("/run"));
     }
+    sandbox.set_resource_limits(ResourceLimits::from_config(config));
 
     Ok(Some(sandbox))
 }
 
+/// Returns the parts of the user's home directory that are visible in the sandbox.
+fn visible_home_dirs(home: &Path) -> [PathBuf; 4] {
+    // We need access to some parts of ~/.cargo in order to be able to build, but we don't bind all
+    // of it because it might contain crates.io credentials, which we'd like to avoid exposing.
+    let cargo_home = home.join(".cargo");
+    [
+        cargo_home.join("bin"),
+        cargo_home.join("git"),
+        cargo_home.join("registry"),
+        home.join(".rustup"),
+    ]
+}
+
+/// If tracing of sandboxed processes was requested, updates `sandbox` and `command` so that
+/// `command` is traced.
+pub(crate) fn start_trace(
+    sandbox: &mut dyn Sandbox,
+    command: &mut Command,
+) -> Result<Option<Trace>> {
+    if std::env::var_os(crate::proxy::TRACE_SANDBOX_ENV).is_none() {
+        return Ok(None);
+    }
+    let home = PathBuf::from(std::env::var("HOME").context("Couldn't get HOME env var")?);
+    let visible_dirs = visible_home_dirs(&home).to_vec();
+    let (trace, traced) = Trace::start(sandbox, command, vec![home], visible_dirs)?;
+    *command = traced;
+    Ok(Some(trace))
+}
+
+/// If `config` asks for writes to be captured, overlays the package's source directory and the
+/// workspace, so that anything the sandboxed process writes there is captured rather than applied.
+pub(crate) fn start_write_capture(
+    config: &SandboxConfig,
+    sandbox: &mut dyn Sandbox,
+) -> Result<Option<WriteCapture>> {
+    if !config.capture_writes.unwrap_or(false) {
+        return Ok(None);
+    }
+    let mut targets = vec![PathBuf::from(get_env("CARGO_MANIFEST_DIR")?)];
+    if let Ok(workspace_dir) = std::env::var(crate::proxy::MANIFEST_DIR) {
+        targets.push(PathBuf::from(workspace_dir));
+    }
+    // An overlay would hide any overlay within it, so we only keep the outermost directories.
+    let all_targets = targets.clone();
+    targets.retain(|target| {
+        !all_targets
+            .iter()
+            .any(|other| other != target && target.starts_with(other))
+    });
+    targets.dedup();
+    let mut writable: Vec<PathBuf> = config
+        .bind_writable
+        .iter()
+        .chain(&config.make_writable)
+        .cloned()
+        .collect();
+    if let Ok(out_dir) = std::env::var("OUT_DIR") {
+        writable.push(PathBuf::from(out_dir));
+    }
+    WriteCapture::start(sandbox, targets, &writable).map(Some)
+}
+
 /// Information extracted from the rustc command line that's relevant to running it in a sandbox.
 #[derive(Default)]
 pub(crate) struct RustcSandboxInputs {
@@ -138,6 +249,11 @@ pub(crate) struct RustcSandboxInputs {
     /// The names of environment variables that were set by the build script by printing
     /// "cargo:rustc-env=...". These variables should be allowed through when running rustc.
     build_script_env_vars: Vec<String>,
+    /// Whether rustc will load any proc macros.
+    uses_proc_macros: bool,
+    /// Whether network access is needed in order to communicate with the parent process, which is
+    /// the case if it's listening on TCP rather than a Unix domain socket.
+    needs_network: bool,
 }
 
 impl RustcSandboxInputs {
@@ -159,25 +275,40 @@ impl RustcSandboxInputs {
                 result.output_directories.push(rest.into());
             }
         }
-        if let Ok(socket_path) = std::env::var(crate::proxy::SOCKET_ENV) {
-            if let Some(dir) = Path::new(&socket_path).parent() {
-                result.output_directories.push(dir.to_owned());
+        if let Ok(endpoint) = std::env::var(crate::proxy::SOCKET_ENV) {
+            if let Some(dir) = crate::proxy::transport::socket_dir(&endpoint) {
+                result.output_directories.push(dir);
             }
+            result.needs_network = crate::proxy::transport::uses_network(&endpoint);
         }
         result
             .output_directories
             .retain(|d| !d.starts_with(&target_dir));
+        result.uses_proc_macros = uses_proc_macros(std::env::args());
         result.build_script_env_vars = read_env_vars(&target_dir, &cargo_profile, crate_sel);
         result.output_directories.push(target_dir);
         Ok(result)
     }
 }
 
+/// Returns whether `config` will result in rustc being run in a sandbox, either always or for
+/// crates that use proc macros.
+pub(crate) fn rustc_is_sandboxed(config: &RustcConfig) -> bool {
+    [&config.sandbox, &config.proc_macro_sandbox]
+        .into_iter()
+        .any(|config| !matches!(resolved_kind(config), None | Some(SandboxKind::Disabled)))
+}
+
 pub(crate) fn for_rustc(
     config: &RustcConfig,
     inputs: &RustcSandboxInputs,
 ) -> Result<Option<Box<dyn Sandbox>>> {
-    let Some(mut sandbox) = from_config(&config.sandbox)? else {
+    let sandbox_config = if inputs.uses_proc_macros {
+        &config.proc_macro_sandbox
+    } else {
+        &config.sandbox
+    };
+    let Some(mut sandbox) = from_config(sandbox_config)? else {
         return Ok(None);
     };
     for dir in &inputs.input_directories {
@@ -186,11 +317,14 @@ pub(crate) fn for_rustc(
     for dir in &inputs.output_directories {
         sandbox.writable_bind(dir);
     }
+    if inputs.needs_network {
+        sandbox.allow_network();
+    }
     for env in crate::proxy::RUSTC_ENV_VARS {
-        sandbox.pass_env(env);
+        pass_env_for_config(sandbox.as_mut(), sandbox_config, env);
     }
     for env in &inputs.build_script_env_vars {
-        sandbox.pass_env(env);
+        pass_env_for_config(sandbox.as_mut(), sandbox_config, env);
     }
     Ok(Some(sandbox))
 }
@@ -219,14 +353,121 @@ pub(crate) fn for_perm_sel(
     Ok(Some(sandbox))
 }
 
-pub(crate) fn available_kind() -> SandboxKind {
-    if bubblewrap::has_bwrap() {
-        SandboxKind::Bubblewrap
-    } else {
-        SandboxKind::Disabled
+/// Applies the environment variable settings from `config` to `command`, which is going to be run
+/// without a sandbox. Since the command inherits our environment, we can't restrict it to
+/// particular variables, but we can still remove those that are denied.
+pub(crate) fn apply_env_without_sandbox(config: &SandboxConfig, command: &mut Command) {
+    for (var, _) in std::env::vars_os() {
+        if var.to_str().is_some_and(|var| is_env_denied(config, var)) {
+            command.env_remove(var);
+        }
+    }
+    command.envs(&config.set_env);
+}
+
+/// Passes through `var` unless `config` denies it or sets it to a fixed value.
+fn pass_env_for_config<S: Sandbox + ?Sized>(sandbox: &mut S, config: &SandboxConfig, var: &str) {
+    if !is_env_denied(config, var) && !config.set_env.contains_key(var) {
+        sandbox.pass_env(var);
     }
 }
 
+fn is_env_denied(config: &SandboxConfig, var: &str) -> bool {
+    config
+        .deny_env
+        .iter()
+        .any(|pattern| crate::glob::matches(pattern, Path::new(var)))
+}
+
+/// If `config` only permits network access to particular domains, starts a proxy that enforces that
+/// and updates `sandbox` and `command` to go via it. The proxy must be kept alive while `command`
+/// runs.
+pub(crate) fn start_http_proxy(
+    config: &SandboxConfig,
+    sandbox: &mut dyn Sandbox,
+    command: &mut Command,
+) -> Result<Option<HttpProxy>> {
+    if config.allow_domains.is_empty() || config.allow_network.unwrap_or(false) {
+        return Ok(None);
+    }
+    if resolved_kind(config) == Some(SandboxKind::Landlock) {
+        bail!("`allow_domains` isn't supported by the Landlock sandbox, since it can't isolate the network");
+    }
+    let proxy = HttpProxy::start(&config.allow_domains)?;
+    *command = proxy.wrap(sandbox, command)?;
+    Ok(Some(proxy))
+}
+
+pub(crate) fn available_kind() -> SandboxKind {
+    Capabilities::get().strongest_kind()
+}
+
+/// Returns the kind of sandbox that `config` will actually use, with `Auto` resolved to whatever is
+/// available.
+fn resolved_kind(config: &SandboxConfig) -> Option<SandboxKind> {
+    config.kind.map(|kind| Capabilities::get().resolve(kind))
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn landlock_sandbox() -> Result<Box<dyn Sandbox>> {
+    Ok(Box::<landlock::Landlock>::default())
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn landlock_sandbox() -> Result<Box<dyn Sandbox>> {
+    bail!("The Landlock sandbox is only supported on Linux on x86_64 and aarch64")
+}
+
+#[cfg(target_os = "linux")]
+fn namespaces_sandbox() -> Result<Box<dyn Sandbox>> {
+    Ok(Box::<namespaces::Namespaces>::default())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn namespaces_sandbox() -> Result<Box<dyn Sandbox>> {
+    bail!("The Namespaces sandbox is only supported on Linux")
+}
+
+#[cfg(target_os = "linux")]
+fn has_namespaces() -> bool {
+    namespaces::is_available()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_namespaces() -> bool {
+    false
+}
+
+/// If we've been invoked as a helper process to set up a sandbox, then does that and doesn't
+/// return.
+pub(crate) fn handle_helper_invocation() {
+    http_proxy::handle_forwarder_invocation();
+    #[cfg(target_os = "linux")]
+    namespaces::handle_helper_invocation();
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn has_landlock() -> bool {
+    landlock::abi_version() >= 1
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn has_landlock() -> bool {
+    false
+}
+
 pub(crate) fn verify_kind(kind: SandboxKind) -> Result<()> {
     if kind == SandboxKind::Bubblewrap
         && std::process::Command::new("bwrap")
@@ -236,6 +477,17 @@ pub(crate) fn verify_kind(kind: SandboxKind) -> Result<()> {
     {
         anyhow::bail!("Failed to run `bwrap`, perhaps it needs to be installed? On systems with apt you can `sudo apt install bubblewrap`");
     }
+    if kind == SandboxKind::Namespaces && !has_namespaces() {
+        anyhow::bail!("Failed to create a namespace sandbox. Unprivileged user namespaces may be disabled on this system");
+    }
+    if kind == SandboxKind::Seatbelt && !seatbelt::has_sandbox_exec() {
+        anyhow::bail!("The Seatbelt sandbox requires macOS with `sandbox-exec` available");
+    }
+    if kind == SandboxKind::Landlock && !has_landlock() {
+        anyhow::bail!(
+            "Landlock isn't available. It requires Linux 5.13 or later with Landlock enabled"
+        );
+    }
     Ok(())
 }
 
@@ -262,6 +514,39 @@ pub(crate) fn write_env_vars(
     )
 }
 
+/// Appends `requests`, made by `crate_sel` via the filtering network proxy, to the network audit
+/// log.
+pub(crate) fn write_network_audit_log(
+    target_dir: &Path,
+    profile: &str,
+    crate_sel: &CrateSel,
+    requests: &[NetworkRequest],
+) -> Result<()> {
+    if requests.is_empty() {
+        return Ok(());
+    }
+    let dir = target_dir.join(profile);
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory `{}`", dir.display()))?;
+    let path = dir.join("network-audit.log");
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open `{}`", path.display()))?;
+    for request in requests {
+        let verdict = if request.allowed { "allowed" } else { "denied" };
+        let line = format!(
+            "{}: {} {}:{} {verdict}",
+            crate_sel, request.method, request.host, request.port
+        );
+        log::info!("Network request from {line}");
+        writeln!(file, "{line}")
+            .with_context(|| format!("Failed to write `{}`", path.display()))?;
+    }
+    Ok(())
+}
+
 fn read_env_vars(target_dir: &Path, profile: &str, crate_sel: &CrateSel) -> Vec<String> {
     let filename = env_vars_file(target_dir, profile, crate_sel);
     // Env vars will only be written when there was a build script run, so we just ignore errors.
@@ -275,6 +560,32 @@ fn read_env_vars(target_dir: &Path, profile: &str, crate_sel: &CrateSel) -> Vec<
         .collect()
 }
 
+/// Returns whether the supplied rustc arguments pass any proc macros via `--extern`. Proc macros are
+/// dynamic libraries, whereas regular dependencies are rlibs.
+fn uses_proc_macros(args: impl Iterator<Item = String>) -> bool {
+    let mut next_is_extern = false;
+    for arg in args {
+        let value = if next_is_extern {
+            next_is_extern = false;
+            arg.as_str()
+        } else if let Some(value) = arg.strip_prefix("--extern=") {
+            value
+        } else {
+            next_is_extern = arg == "--extern";
+            continue;
+        };
+        if let Some((_, path)) = value.split_once('=') {
+            if Path::new(path)
+                .extension()
+                .is_some_and(|ext| ext == "so" || ext == "dylib" || ext == "dll")
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
 fn get_env(var_name: &str) -> Result<String> {
     std::env::var(var_name)
         .with_context(|| format!("Failed to get environment variable `{var_name}`"))
@@ -295,6 +606,13 @@ fn is_cargo_env(var: &str) -> bool {
     if var == "RUSTC_WRAPPER" {
         return false;
     }
+    // Registry tokens are only needed by cargo itself and would be valuable to a malicious build
+    // script.
+    if var == "CARGO_REGISTRY_TOKEN"
+        || (var.starts_with("CARGO_REGISTRIES_") && var.ends_with("_TOKEN"))
+    {
+        return false;
+    }
 
     const PREFIXES: &[&str] = &["CARGO", "RUSTC", "DEP_"];
     const ONE_OFFS: &[&str] = &[
@@ -307,3 +625,52 @@ fn is_cargo_env(var: &str) -> bool {
     ];
     PREFIXES.iter().any(|prefix| var.starts_with(prefix)) || ONE_OFFS.contains(&var)
 }
+
+#[cfg(test)]
+mod tests {
+    #[track_caller]
+    fn check_uses_proc_macros(args: &[&str], expected: bool) {
+        let args = args.iter().map(|arg| arg.to_string());
+        assert_eq!(super::uses_proc_macros(args), expected);
+    }
+
+    #[test]
+    fn uses_proc_macros() {
+        check_uses_proc_macros(&["--crate-name", "foo", "src/lib.rs"], false);
+        check_uses_proc_macros(&["--extern", "serde=/t/deps/libserde-1234.rlib"], false);
+        check_uses_proc_macros(
+            &[
+                "--extern",
+                "serde=/t/deps/libserde-1234.rlib",
+                "--extern",
+                "serde_derive=/t/deps/libserde_derive-5678.so",
+            ],
+            true,
+        );
+        check_uses_proc_macros(&["--extern=foo_macros=/t/deps/libfoo_macros.dylib"], true);
+        // A proc-macro crate itself is passed `--extern proc_macro`, but doesn't load any.
+        check_uses_proc_macros(&["--extern", "proc_macro"], false);
+    }
+
+    #[test]
+    fn cargo_env() {
+        assert!(super::is_cargo_env("CARGO_PKG_NAME"));
+        assert!(super::is_cargo_env("TARGET"));
+        assert!(!super::is_cargo_env("RUSTC_WRAPPER"));
+        assert!(!super::is_cargo_env("CARGO_REGISTRY_TOKEN"));
+        assert!(!super::is_cargo_env("CARGO_REGISTRIES_MY_REGISTRY_TOKEN"));
+        assert!(!super::is_cargo_env("AWS_SECRET_ACCESS_KEY"));
+    }
+
+    #[test]
+    fn env_denied() {
+        let config = crate::config::SandboxConfig {
+            deny_env: vec!["AWS_*".to_owned(), "NPM_TOKEN".to_owned()],
+            ..Default::default()
+        };
+        assert!(super::is_env_denied(&config, "AWS_SECRET_ACCESS_KEY"));
+        assert!(super::is_env_denied(&config, "NPM_TOKEN"));
+        assert!(!super::is_env_denied(&config, "NPM_TOKEN_2"));
+        assert!(!super::is_env_denied(&config, "PATH"));
+    }
+}
@@ -1,3 +1,5 @@
+use super::limits;
+use super::ResourceLimits;
 use super::Sandbox;
 use anyhow::Context;
 use anyhow::Result;
@@ -10,6 +12,7 @@ use std::process::Command;
 #[derive(Default)]
 pub(super) struct Bubblewrap {
     args: Vec<OsString>,
+    limits: ResourceLimits,
 }
 
 impl Bubblewrap {
@@ -69,6 +72,19 @@ impl Sandbox for Bubblewrap {
         self.arg(dir);
     }
 
+    fn overlay(&mut self, dir: &Path, upper: &Path, work: &Path) {
+        self.arg("--overlay-src");
+        self.arg(dir);
+        self.arg("--overlay");
+        self.arg(upper);
+        self.arg(work);
+        self.arg(dir);
+    }
+
+    fn supports_overlay(&self) -> bool {
+        true
+    }
+
     fn set_env(&mut self, var: &OsStr, value: &OsStr) {
         self.arg("--setenv");
         self.arg(var);
@@ -79,9 +95,13 @@ impl Sandbox for Bubblewrap {
         self.arg("--share-net");
     }
 
+    fn set_resource_limits(&mut self, limits: ResourceLimits) {
+        self.limits = limits;
+    }
+
     fn run(&self, command: &Command) -> Result<std::process::Output> {
         let mut command = self.command(command);
-        command.output().with_context(|| {
+        limits::output(&mut command, &self.limits).with_context(|| {
             format!(
                 "Failed to run sandbox command: {}",
                 Path::new(command.get_program()).display()
@@ -0,0 +1,229 @@
+//! Probing of which isolation primitives are available on this system. This lets us pick the
+//! strongest sandbox that will actually work when the config asks for `Auto` and tell the user which
+//! protections they are and aren't getting.
+
+use crate::config::SandboxKind;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Sandbox kinds, strongest first.
+const KINDS_BY_STRENGTH: &[SandboxKind] = &[
+    SandboxKind::Bubblewrap,
+    SandboxKind::Namespaces,
+    SandboxKind::Seatbelt,
+    SandboxKind::Landlock,
+    SandboxKind::Disabled,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Capabilities {
+    bwrap: bool,
+    namespaces: bool,
+    seatbelt: bool,
+    landlock: bool,
+    strace: bool,
+}
+
+/// Something that a sandbox might protect against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum Protection {
+    /// Restricting which files can be read and written.
+    Filesystem,
+    /// Preventing network access.
+    Network,
+    /// Permitting network access only to particular domains.
+    DomainFiltering,
+    /// Preventing the sandboxed process from seeing or signalling other processes.
+    ProcessIsolation,
+}
+
+const ALL_PROTECTIONS: &[Protection] = &[
+    Protection::Filesystem,
+    Protection::Network,
+    Protection::DomainFiltering,
+    Protection::ProcessIsolation,
+];
+
+impl Capabilities {
+    /// Returns the capabilities of this system. Probing is done once per process.
+    pub(crate) fn get() -> &'static Capabilities {
+        static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+        CAPABILITIES.get_or_init(Self::probe)
+    }
+
+    fn probe() -> Self {
+        Self {
+            bwrap: super::bubblewrap::has_bwrap(),
+            namespaces: super::has_namespaces(),
+            seatbelt: super::seatbelt::has_sandbox_exec(),
+            landlock: super::has_landlock(),
+            strace: super::trace::find_in_path("strace").is_some(),
+        }
+    }
+
+    pub(crate) fn is_available(&self, kind: SandboxKind) -> bool {
+        match kind {
+            SandboxKind::Disabled | SandboxKind::Auto => true,
+            SandboxKind::Bubblewrap => self.bwrap,
+            SandboxKind::Namespaces => self.namespaces,
+            SandboxKind::Seatbelt => self.seatbelt,
+            SandboxKind::Landlock => self.landlock,
+        }
+    }
+
+    pub(crate) fn strongest_kind(&self) -> SandboxKind {
+        KINDS_BY_STRENGTH
+            .iter()
+            .copied()
+            .find(|kind| self.is_available(*kind))
+            .unwrap_or(SandboxKind::Disabled)
+    }
+
+    /// Returns `kind`, unless it's `Auto`, in which case returns the strongest available kind.
+    pub(crate) fn resolve(&self, kind: SandboxKind) -> SandboxKind {
+        if kind == SandboxKind::Auto {
+            self.strongest_kind()
+        } else {
+            kind
+        }
+    }
+
+    /// Returns an object that describes the available sandbox kinds and the protections provided.
+    pub(crate) fn report(&self) -> CapabilitiesReport {
+        CapabilitiesReport { capabilities: *self }
+    }
+}
+
+impl Protection {
+    pub(crate) fn is_provided_by(self, kind: SandboxKind) -> bool {
+        match kind {
+            SandboxKind::Disabled | SandboxKind::Auto => false,
+            SandboxKind::Bubblewrap | SandboxKind::Namespaces => true,
+            SandboxKind::Seatbelt => self != Protection::ProcessIsolation,
+            SandboxKind::Landlock => self == Protection::Filesystem,
+        }
+    }
+}
+
+/// Returns the protections that a sandbox of `kind` doesn't provide.
+pub(crate) fn skipped_protections(kind: SandboxKind) -> Vec<Protection> {
+    ALL_PROTECTIONS
+        .iter()
+        .copied()
+        .filter(|protection| !protection.is_provided_by(kind))
+        .collect()
+}
+
+impl Display for Protection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Protection::Filesystem => write!(f, "filesystem isolation"),
+            Protection::Network => write!(f, "network isolation"),
+            Protection::DomainFiltering => write!(f, "domain filtering (`allow_domains`)"),
+            Protection::ProcessIsolation => write!(f, "process isolation"),
+        }
+    }
+}
+
+pub(crate) struct CapabilitiesReport {
+    capabilities: Capabilities,
+}
+
+impl Display for CapabilitiesReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let caps = &self.capabilities;
+        writeln!(f, "Sandbox kinds:")?;
+        for kind in KINDS_BY_STRENGTH {
+            if *kind == SandboxKind::Disabled {
+                continue;
+            }
+            let status = if caps.is_available(*kind) {
+                "available"
+            } else {
+                "unavailable"
+            };
+            writeln!(f, "  {kind:?}: {status}")?;
+        }
+        let strongest = caps.strongest_kind();
+        writeln!(f, "`Auto` selects: {strongest:?}")?;
+        writeln!(f, "Protections:")?;
+        for protection in ALL_PROTECTIONS {
+            let status = if protection.is_provided_by(strongest) {
+                "active"
+            } else {
+                "skipped"
+            };
+            writeln!(f, "  {protection}: {status}")?;
+        }
+        let strace = if caps.strace {
+            "available"
+        } else {
+            "unavailable (strace not found)"
+        };
+        writeln!(f, "Tracing (--trace-sandbox): {strace}")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Capabilities;
+    use super::Protection;
+    use crate::config::SandboxKind;
+
+    fn caps(bwrap: bool, namespaces: bool, landlock: bool) -> Capabilities {
+        Capabilities {
+            bwrap,
+            namespaces,
+            seatbelt: false,
+            landlock,
+            strace: false,
+        }
+    }
+
+    #[test]
+    fn strongest_kind() {
+        assert_eq!(
+            caps(true, true, true).strongest_kind(),
+            SandboxKind::Bubblewrap
+        );
+        assert_eq!(
+            caps(false, true, true).strongest_kind(),
+            SandboxKind::Namespaces
+        );
+        assert_eq!(
+            caps(false, false, true).strongest_kind(),
+            SandboxKind::Landlock
+        );
+        assert_eq!(
+            caps(false, false, false).strongest_kind(),
+            SandboxKind::Disabled
+        );
+        assert_eq!(
+            caps(false, false, true).resolve(SandboxKind::Auto),
+            SandboxKind::Landlock
+        );
+        assert_eq!(
+            caps(false, false, true).resolve(SandboxKind::Bubblewrap),
+            SandboxKind::Bubblewrap
+        );
+    }
+
+    #[test]
+    fn skipped_protections() {
+        assert!(super::skipped_protections(SandboxKind::Bubblewrap).is_empty());
+        assert!(super::skipped_protections(SandboxKind::Namespaces).is_empty());
+        assert_eq!(
+            super::skipped_protections(SandboxKind::Seatbelt),
+            vec![Protection::ProcessIsolation]
+        );
+        assert_eq!(
+            super::skipped_protections(SandboxKind::Landlock),
+            vec![
+                Protection::Network,
+                Protection::DomainFiltering,
+                Protection::ProcessIsolation,
+            ]
+        );
+    }
+}
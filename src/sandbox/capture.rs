@@ -0,0 +1,182 @@
+//! Capturing of writes that a sandboxed process makes outside of the directories that it's
+//! permitted to write to. Directories that would otherwise be read-only are mounted as overlays, so
+//! that the process can write to them, but the writes end up in a directory of ours rather than in
+//! the real filesystem. Once the process has finished, we inspect our directory to see what it
+//! wrote. The writes are then discarded.
+
+use super::Sandbox;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The most writes that we'll report. Anything beyond this is likely just more of the same.
+const MAX_WRITES: usize = 100;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash, PartialOrd, Ord)]
+pub(crate) struct CapturedWrite {
+    pub(crate) path: PathBuf,
+    pub(crate) kind: WriteKind,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub(crate) enum WriteKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+pub(crate) struct WriteCapture {
+    dir: tempfile::TempDir,
+    /// The directories that are overlaid, in the same order as their upper directories within
+    /// `dir`.
+    targets: Vec<PathBuf>,
+}
+
+impl WriteCapture {
+    /// Configures `sandbox` so that writes to `targets` are captured. Since the overlays cover
+    /// anything previously mounted within `targets`, `writable` lists directories that should
+    /// remain writable and are bound again afterwards.
+    pub(crate) fn start(
+        sandbox: &mut dyn Sandbox,
+        targets: Vec<PathBuf>,
+        writable: &[PathBuf],
+    ) -> Result<Self> {
+        if !sandbox.supports_overlay() {
+            bail!("`capture_writes` is only supported by the Bubblewrap and Namespaces sandboxes");
+        }
+        let dir = tempfile::tempdir().context("Failed to create directory for captured writes")?;
+        for (index, target) in targets.iter().enumerate() {
+            let upper = upper_dir(dir.path(), index);
+            let work = dir.path().join(format!("work-{index}"));
+            std::fs::create_dir_all(&upper)?;
+            std::fs::create_dir_all(&work)?;
+            sandbox.overlay(target, &upper, &work);
+        }
+        for dir in writable {
+            sandbox.writable_bind(dir);
+        }
+        Ok(Self { dir, targets })
+    }
+
+    /// Returns the writes that were made to the overlaid directories.
+    pub(crate) fn writes(&self) -> Result<Vec<CapturedWrite>> {
+        let mut writes = Vec::new();
+        for (index, target) in self.targets.iter().enumerate() {
+            let upper = upper_dir(self.dir.path(), index);
+            collect_writes(&upper, &upper, target, &mut writes)
+                .with_context(|| format!("Failed to read writes captured for `{}`", target.display()))?;
+        }
+        writes.sort();
+        writes.truncate(MAX_WRITES);
+        Ok(writes)
+    }
+}
+
+fn upper_dir(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("upper-{index}"))
+}
+
+fn collect_writes(
+    upper: &Path,
+    dir: &Path,
+    target: &Path,
+    out: &mut Vec<CapturedWrite>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        let real_path = target.join(path.strip_prefix(upper)?);
+        if is_whiteout(&metadata) {
+            out.push(CapturedWrite {
+                path: real_path,
+                kind: WriteKind::Deleted,
+            });
+            continue;
+        }
+        let exists = real_path.symlink_metadata().is_ok();
+        if metadata.is_dir() {
+            // A directory that already exists only appears in the upper directory because
+            // something within it was written, so we only report its contents.
+            if !exists {
+                out.push(CapturedWrite {
+                    path: real_path,
+                    kind: WriteKind::Created,
+                });
+            }
+            collect_writes(upper, &path, target, out)?;
+        } else {
+            out.push(CapturedWrite {
+                path: real_path,
+                kind: if exists {
+                    WriteKind::Modified
+                } else {
+                    WriteKind::Created
+                },
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Overlayfs records deletions as character devices with device number 0/0.
+fn is_whiteout(metadata: &std::fs::Metadata) -> bool {
+    metadata.file_type().is_char_device() && metadata.rdev() == 0
+}
+
+impl Display for CapturedWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            WriteKind::Created => "created",
+            WriteKind::Modified => "modified",
+            WriteKind::Deleted => "deleted",
+        };
+        write!(f, "{kind} {}", self.path.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CapturedWrite;
+    use super::WriteKind;
+
+    #[test]
+    fn collect_writes() {
+        let upper = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(target.path().join("src")).unwrap();
+        std::fs::write(target.path().join("src/lib.rs"), "").unwrap();
+        std::fs::create_dir_all(upper.path().join("src")).unwrap();
+        std::fs::write(upper.path().join("src/lib.rs"), "changed").unwrap();
+        std::fs::create_dir_all(upper.path().join("generated")).unwrap();
+        std::fs::write(upper.path().join("generated/out.rs"), "").unwrap();
+
+        let mut writes = Vec::new();
+        super::collect_writes(upper.path(), upper.path(), target.path(), &mut writes).unwrap();
+        writes.sort();
+        assert_eq!(
+            writes,
+            vec![
+                CapturedWrite {
+                    path: target.path().join("generated"),
+                    kind: WriteKind::Created,
+                },
+                CapturedWrite {
+                    path: target.path().join("generated/out.rs"),
+                    kind: WriteKind::Created,
+                },
+                CapturedWrite {
+                    path: target.path().join("src/lib.rs"),
+                    kind: WriteKind::Modified,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,443 @@
+//! A filtering HTTP(S) proxy that lets sandboxed processes reach only an allowlist of domains.
+//!
+//! The proxy runs outside the sandbox and listens on a Unix socket. Inside the sandbox, which has
+//! its own network namespace, we run a forwarder that listens on the loopback interface, relays
+//! connections to the Unix socket and runs the actual command with `HTTP_PROXY` etc. pointing at
+//! itself. Since the sandbox has no other network access, connections that don't go via the proxy
+//! fail. Every request that the proxy receives is recorded, whether or not it's permitted.
+
+use super::Sandbox;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::ffi::OsStr;
+use std::io::Read;
+use std::io::Write;
+use std::net::Shutdown;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// When set, we're running inside a sandbox as a forwarder for the proxy listening on the Unix
+/// socket at the path given by this variable.
+const FORWARD_SOCKET_ENV: &str = "CACKLE_HTTP_PROXY_SOCKET";
+
+/// Environment variables that we set to tell programs to use our proxy.
+const PROXY_ENV_VARS: &[&str] = &[
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "ALL_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "all_proxy",
+];
+
+/// An upper bound on the size of request headers, to stop a misbehaving client from making us
+/// buffer an unbounded amount of data.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// A request made via the proxy.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash)]
+pub(crate) struct NetworkRequest {
+    /// The HTTP method. `CONNECT` for HTTPS and other tunnelled connections.
+    pub(crate) method: String,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) allowed: bool,
+}
+
+pub(crate) struct HttpProxy {
+    dir: tempfile::TempDir,
+    requests: Arc<Mutex<Vec<NetworkRequest>>>,
+}
+
+impl HttpProxy {
+    /// Starts a proxy that permits connections to `allow_domains`. A domain starting with `*.`
+    /// permits all subdomains of what follows.
+    pub(crate) fn start(allow_domains: &[String]) -> Result<Self> {
+        let dir = tempfile::TempDir::new().context("Failed to create directory for proxy")?;
+        let listener = UnixListener::bind(dir.path().join("http-proxy.sock"))
+            .context("Failed to create proxy socket")?;
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let allow_domains: Arc<[String]> = allow_domains.into();
+        std::thread::spawn({
+            let requests = requests.clone();
+            move || {
+                for client in listener.incoming().flatten() {
+                    let allow_domains = allow_domains.clone();
+                    let requests = requests.clone();
+                    std::thread::spawn(move || {
+                        if let Err(error) = handle_client(client, &allow_domains, &requests) {
+                            log::debug!("HTTP proxy connection failed: {error:#}");
+                        }
+                    });
+                }
+            }
+        });
+        Ok(Self { dir, requests })
+    }
+
+    /// Returns all requests made so far.
+    pub(crate) fn requests(&self) -> Vec<NetworkRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn socket_path(&self) -> PathBuf {
+        self.dir.path().join("http-proxy.sock")
+    }
+
+    /// Configures `sandbox` so that the proxy is reachable from within it and returns a command
+    /// that runs `command` via our forwarder.
+    pub(crate) fn wrap(&self, sandbox: &mut dyn Sandbox, command: &Command) -> Result<Command> {
+        let cackle_exe = std::env::current_exe().context("Failed to get current exe")?;
+        sandbox.ro_bind(&cackle_exe);
+        sandbox.writable_bind(self.dir.path());
+        sandbox.set_env(
+            OsStr::new(FORWARD_SOCKET_ENV),
+            self.socket_path().as_os_str(),
+        );
+        let mut wrapped = Command::new(cackle_exe);
+        wrapped.arg(command.get_program()).args(command.get_args());
+        for (var, value) in command.get_envs() {
+            match value {
+                Some(value) => wrapped.env(var, value),
+                None => wrapped.env_remove(var),
+            };
+        }
+        if let Some(dir) = command.get_current_dir() {
+            wrapped.current_dir(dir);
+        }
+        Ok(wrapped)
+    }
+}
+
+fn handle_client(
+    mut client: UnixStream,
+    allow_domains: &[String],
+    requests: &Mutex<Vec<NetworkRequest>>,
+) -> Result<()> {
+    let (head, rest) = read_head(&mut client)?;
+    let request = match parse_request(&head) {
+        Ok(request) => request,
+        Err(error) => {
+            respond(&mut client, "400 Bad Request", &format!("{error:#}"))?;
+            return Ok(());
+        }
+    };
+    let allowed = is_allowed(&request.host, allow_domains);
+    requests.lock().unwrap().push(NetworkRequest {
+        method: request.method.clone(),
+        host: request.host.clone(),
+        port: request.port,
+        allowed,
+    });
+    if !allowed {
+        respond(
+            &mut client,
+            "403 Forbidden",
+            &format!("Cackle sandbox doesn't permit access to `{}`", request.host),
+        )?;
+        return Ok(());
+    }
+    let mut server = match TcpStream::connect((request.host.as_str(), request.port)) {
+        Ok(server) => server,
+        Err(error) => {
+            respond(&mut client, "502 Bad Gateway", &error.to_string())?;
+            return Ok(());
+        }
+    };
+    if request.method == "CONNECT" {
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+    } else {
+        server.write_all(&head)?;
+    }
+    server.write_all(&rest)?;
+    tunnel(client, server)
+}
+
+/// Reads from `stream` until the end of the request headers. Returns the headers and any bytes
+/// that were read beyond them.
+fn read_head(stream: &mut impl Read) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            let rest = buffer.split_off(end + 4);
+            return Ok((buffer, rest));
+        }
+        if buffer.len() > MAX_HEAD_SIZE {
+            bail!("Request headers too large");
+        }
+        let len = stream.read(&mut chunk)?;
+        if len == 0 {
+            bail!("Connection closed before end of request headers");
+        }
+        buffer.extend_from_slice(&chunk[..len]);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    method: String,
+    host: String,
+    port: u16,
+}
+
+fn parse_request(head: &[u8]) -> Result<Request> {
+    let head = std::str::from_utf8(head).context("Request isn't valid UTF-8")?;
+    let request_line = head.lines().next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        bail!("Invalid request line `{request_line}`");
+    };
+    let (authority, default_port) = if method == "CONNECT" {
+        (target, None)
+    } else if let Some(rest) = target.strip_prefix("http://") {
+        (rest.split('/').next().unwrap_or_default(), Some(80))
+    } else {
+        bail!("Unsupported request target `{target}`");
+    };
+    let (host, port) = split_host_port(authority, default_port)?;
+    Ok(Request {
+        method: method.to_owned(),
+        host,
+        port,
+    })
+}
+
+fn split_host_port(authority: &str, default_port: Option<u16>) -> Result<(String, u16)> {
+    // Strip any user info.
+    let authority = authority.rsplit('@').next().unwrap_or_default();
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        // IPv6 address.
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| anyhow!("Invalid host `{authority}`"))?;
+        (host, rest.strip_prefix(':'))
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("Invalid port in `{authority}`"))?,
+        None => default_port.ok_or_else(|| anyhow!("Missing port in `{authority}`"))?,
+    };
+    if host.is_empty() {
+        bail!("Missing host in `{authority}`");
+    }
+    Ok((host.to_ascii_lowercase(), port))
+}
+
+/// Returns whether `host` is permitted by `allow_domains`.
+fn is_allowed(host: &str, allow_domains: &[String]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allow_domains.iter().any(|domain| {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        if let Some(parent) = domain.strip_prefix("*.") {
+            host.strip_suffix(parent)
+                .and_then(|subdomain| subdomain.strip_suffix('.'))
+                .is_some_and(|subdomain| !subdomain.is_empty())
+        } else {
+            host == domain
+        }
+    })
+}
+
+fn respond(client: &mut UnixStream, status: &str, message: &str) -> Result<()> {
+    write!(
+        client,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+        message.len()
+    )?;
+    Ok(())
+}
+
+/// A stream that we can copy to and from in separate threads.
+trait Duplex: Read + Write + Send + Sized + 'static {
+    fn duplicate(&self) -> std::io::Result<Self>;
+    fn shutdown_write(&self);
+}
+
+impl Duplex for UnixStream {
+    fn duplicate(&self) -> std::io::Result<Self> {
+        self.try_clone()
+    }
+
+    fn shutdown_write(&self) {
+        let _ = self.shutdown(Shutdown::Write);
+    }
+}
+
+impl Duplex for TcpStream {
+    fn duplicate(&self) -> std::io::Result<Self> {
+        self.try_clone()
+    }
+
+    fn shutdown_write(&self) {
+        let _ = self.shutdown(Shutdown::Write);
+    }
+}
+
+/// Copies data in both directions between `a` and `b` until both sides have finished sending.
+fn tunnel(a: impl Duplex, b: impl Duplex) -> Result<()> {
+    let mut a_reader = a.duplicate()?;
+    let mut b_writer = b.duplicate()?;
+    let a_to_b = std::thread::spawn(move || {
+        let _ = std::io::copy(&mut a_reader, &mut b_writer);
+        b_writer.shutdown_write();
+    });
+    let (mut b_reader, mut a_writer) = (b, a);
+    let _ = std::io::copy(&mut b_reader, &mut a_writer);
+    a_writer.shutdown_write();
+    let _ = a_to_b.join();
+    Ok(())
+}
+
+/// If we've been invoked inside a sandbox as a forwarder for the proxy, then runs the command from
+/// our arguments and doesn't return.
+pub(super) fn handle_forwarder_invocation() {
+    let Some(socket_path) = std::env::var_os(FORWARD_SOCKET_ENV) else {
+        return;
+    };
+    match run_forwarder(PathBuf::from(socket_path)) {
+        Ok(code) => std::process::exit(code),
+        Err(error) => {
+            eprintln!("Failed to run HTTP proxy forwarder: {error:#}");
+            std::process::exit(125);
+        }
+    }
+}
+
+fn run_forwarder(socket_path: PathBuf) -> Result<i32> {
+    let listener =
+        TcpListener::bind("127.0.0.1:0").context("Failed to listen on loopback interface")?;
+    let proxy_url = format!("http://{}", listener.local_addr()?);
+    let mut args = std::env::args_os().skip(1);
+    let program = args
+        .next()
+        .ok_or_else(|| anyhow!("No command supplied to forwarder"))?;
+    let mut command = Command::new(&program);
+    command.args(args).env_remove(FORWARD_SOCKET_ENV);
+    for var in PROXY_ENV_VARS {
+        command.env(var, &proxy_url);
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to run `{}`", Path::new(&program).display()))?;
+    std::thread::spawn(move || {
+        for client in listener.incoming().flatten() {
+            let socket_path = socket_path.clone();
+            std::thread::spawn(move || {
+                if let Ok(proxy) = UnixStream::connect(socket_path) {
+                    let _ = tunnel(client, proxy);
+                }
+            });
+        }
+    });
+    let status = child.wait()?;
+    Ok(status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpProxy;
+    use super::NetworkRequest;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn domain_matching() {
+        let allow = vec!["example.com".to_owned(), "*.github.com".to_owned()];
+        assert!(super::is_allowed("example.com", &allow));
+        assert!(super::is_allowed("example.com.", &allow));
+        assert!(!super::is_allowed("www.example.com", &allow));
+        assert!(super::is_allowed("api.github.com", &allow));
+        assert!(super::is_allowed("a.b.github.com", &allow));
+        assert!(!super::is_allowed("github.com", &allow));
+        assert!(!super::is_allowed("evilgithub.com", &allow));
+        assert!(!super::is_allowed("example.org", &allow));
+    }
+
+    #[test]
+    fn parse_request() {
+        let request = super::parse_request(b"CONNECT Example.com:443 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.host, "example.com");
+        assert_eq!(request.port, 443);
+        let request =
+            super::parse_request(b"GET http://example.com/foo HTTP/1.1\r\nHost: x\r\n\r\n")
+                .unwrap();
+        assert_eq!((request.method.as_str(), request.port), ("GET", 80));
+        let request = super::parse_request(b"CONNECT [::1]:8080 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!((request.host.as_str(), request.port), ("::1", 8080));
+        assert!(super::parse_request(b"GET /foo HTTP/1.1\r\n\r\n").is_err());
+        assert!(super::parse_request(b"CONNECT example.com HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn proxy_filters_and_records() {
+        // A server that echoes back whatever it's sent.
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for mut stream in server.incoming().flatten() {
+                let mut buf = [0; 5];
+                stream.read_exact(&mut buf).unwrap();
+                stream.write_all(&buf).unwrap();
+            }
+        });
+
+        let proxy = HttpProxy::start(&["127.0.0.1".to_owned()]).unwrap();
+        let mut client = UnixStream::connect(proxy.socket_path()).unwrap();
+        write!(client, "CONNECT 127.0.0.1:{port} HTTP/1.1\r\n\r\nhello").unwrap();
+        let (head, mut rest) = super::read_head(&mut client).unwrap();
+        assert!(head.starts_with(b"HTTP/1.1 200"));
+        while rest.len() < 5 {
+            let mut buf = [0; 5];
+            let len = client.read(&mut buf).unwrap();
+            rest.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(rest, b"hello");
+
+        let mut client = UnixStream::connect(proxy.socket_path()).unwrap();
+        write!(client, "CONNECT localhost:{port} HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 403"));
+
+        assert_eq!(
+            proxy.requests(),
+            vec![
+                NetworkRequest {
+                    method: "CONNECT".to_owned(),
+                    host: "127.0.0.1".to_owned(),
+                    port,
+                    allowed: true,
+                },
+                NetworkRequest {
+                    method: "CONNECT".to_owned(),
+                    host: "localhost".to_owned(),
+                    port,
+                    allowed: false,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! A sandbox that uses Landlock to restrict filesystem access and a seccomp filter to restrict
+//! network access. Unlike Bubblewrap, this doesn't need user namespaces or an external binary, so it
+//! works in containers where Bubblewrap can't run. It requires Linux 5.13 or later.
+//!
+//! Landlock can only grant access to parts of the filesystem, it can't mount anything. So where
+//! Bubblewrap would mount a tmpfs, we instead deny access to the directory. The one exception is
+//! the system temporary directory, for which we provide a private, empty replacement via `TMPDIR`.
+
+#![allow(unsafe_code)]
+
+use super::limits;
+use super::ResourceLimits;
+use super::Sandbox;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+/// All access rights up to and including `LANDLOCK_ACCESS_FS_MAKE_SYM`, which is what ABI version 1
+/// supports.
+const ACCESS_FS_ABI_1: u64 = (1 << 13) - 1;
+/// `LANDLOCK_ACCESS_FS_TRUNCATE`, added in ABI version 3.
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+/// Access rights that are applicable to files as opposed to directories.
+const ACCESS_FS_FILE: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+const ACCESS_FS_READ: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc00000b7;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+#[derive(Default)]
+pub(super) struct Landlock {
+    readable: Vec<PathBuf>,
+    writable: Vec<PathBuf>,
+    /// Directories that would have had a tmpfs mounted over them. Access to these is denied.
+    hidden: Vec<PathBuf>,
+    env: Vec<(OsString, OsString)>,
+    allow_network: bool,
+    limits: ResourceLimits,
+    /// Arguments from `extra_args`, which we don't support, but record so that we can report an
+    /// error when we try to run.
+    unsupported_args: Vec<OsString>,
+}
+
+impl Sandbox for Landlock {
+    fn raw_arg(&mut self, arg: &OsStr) {
+        self.unsupported_args.push(arg.to_owned());
+    }
+
+    fn tmpfs(&mut self, dir: &Path) {
+        self.hidden.push(dir.to_owned());
+    }
+
+    fn ro_bind(&mut self, dir: &Path) {
+        self.readable.push(dir.to_owned());
+    }
+
+    fn writable_bind(&mut self, dir: &Path) {
+        self.writable.push(dir.to_owned());
+    }
+
+    fn set_env(&mut self, var: &OsStr, value: &OsStr) {
+        self.env.push((var.to_owned(), value.to_owned()));
+    }
+
+    fn allow_network(&mut self) {
+        self.allow_network = true;
+    }
+
+    fn set_resource_limits(&mut self, limits: ResourceLimits) {
+        self.limits = limits;
+    }
+
+    fn run(&self, command: &Command) -> Result<std::process::Output> {
+        if !self.unsupported_args.is_empty() {
+            bail!("The Landlock sandbox doesn't support `extra_args`");
+        }
+        let abi = abi_version();
+        if abi < 1 {
+            bail!("Landlock isn't supported by this kernel. Linux 5.13 or later is required");
+        }
+        let tmpdir = tempfile::tempdir().context("Failed to create temporary directory")?;
+        let mut writable = self.writable.clone();
+        writable.push(tmpdir.path().to_owned());
+        writable.push(PathBuf::from("/dev/null"));
+        let ruleset = create_ruleset(abi, &self.readable_paths(), &writable)?;
+        let filter = (!self.allow_network).then(network_filter);
+
+        let mut sandboxed = self.command(command);
+        sandboxed.env("TMPDIR", tmpdir.path());
+        // Safety: The closure only makes syscalls, it doesn't allocate, which would be unsafe in
+        // the forked child.
+        unsafe {
+            sandboxed.pre_exec(move || restrict_self(&ruleset, filter.as_deref()));
+        }
+        limits::output(&mut sandboxed, &self.limits).with_context(|| {
+            format!(
+                "Failed to run `{}` in Landlock sandbox",
+                Path::new(command.get_program()).display()
+            )
+        })
+    }
+
+    fn display_to_run(&self, command: &Command) -> Box<dyn Display> {
+        Box::new(LandlockDisplay {
+            readable: self.readable_paths(),
+            writable: self.writable.clone(),
+            allow_network: self.allow_network,
+            command: self.command(command),
+        })
+    }
+}
+
+impl Landlock {
+    /// Returns a command that runs `command` with only the environment variables that have been
+    /// explicitly passed through.
+    fn command(&self, command: &Command) -> Command {
+        let mut sandboxed = Command::new(command.get_program());
+        sandboxed.args(command.get_args()).env_clear();
+        if let Some(dir) = command.get_current_dir() {
+            sandboxed.current_dir(dir);
+        }
+        for (var, value) in &self.env {
+            sandboxed.env(var, value);
+        }
+        for (var, value) in command.get_envs() {
+            if let Some(value) = value {
+                sandboxed.env(var, value);
+            } else {
+                sandboxed.env_remove(var);
+            }
+        }
+        sandboxed
+    }
+
+    /// Returns the paths to grant read access to. Since Landlock grants access to everything
+    /// beneath a path, readable directories that contain hidden directories are replaced by their
+    /// children, excluding the hidden directories. Readable directories within hidden directories,
+    /// e.g. `~/.cargo/registry`, are still granted, as they would be by Bubblewrap.
+    fn readable_paths(&self) -> Vec<PathBuf> {
+        let mut out = Vec::new();
+        for dir in &self.readable {
+            let hidden: Vec<&Path> = self
+                .hidden
+                .iter()
+                .map(PathBuf::as_path)
+                .filter(|h| h.starts_with(dir) && h != dir)
+                .collect();
+            expand_excluding(dir, &hidden, &mut out);
+        }
+        out
+    }
+}
+
+fn expand_excluding(dir: &Path, hidden: &[&Path], out: &mut Vec<PathBuf>) {
+    if hidden.contains(&dir) {
+        return;
+    }
+    if !hidden.iter().any(|h| h.starts_with(dir)) {
+        out.push(dir.to_owned());
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        expand_excluding(&entry.path(), hidden, out);
+    }
+}
+
+/// Returns the Landlock ABI version supported by the kernel, or a value less than 1 if Landlock
+/// isn't available.
+pub(super) fn abi_version() -> i64 {
+    unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    }
+}
+
+fn create_ruleset(abi: i64, readable: &[PathBuf], writable: &[PathBuf]) -> Result<OwnedFd> {
+    let handled = if abi >= 3 {
+        ACCESS_FS_ABI_1 | ACCESS_FS_TRUNCATE
+    } else {
+        ACCESS_FS_ABI_1
+    };
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0u32,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create Landlock ruleset");
+    }
+    let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+    for path in readable {
+        add_path_rule(&ruleset, path, ACCESS_FS_READ & handled)?;
+    }
+    for path in writable {
+        add_path_rule(&ruleset, path, handled)?;
+    }
+    Ok(ruleset)
+}
+
+fn add_path_rule(ruleset: &OwnedFd, path: &Path, access: u64) -> Result<()> {
+    // Like `--bind-try` and `--ro-bind` for Bubblewrap, paths that don't exist are skipped.
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return Ok(());
+    };
+    let access = if metadata.is_dir() {
+        access
+    } else {
+        access & ACCESS_FS_FILE
+    };
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to open `{}`", path.display()));
+    }
+    let path_fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let attr = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: path_fd.as_raw_fd(),
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0u32,
+        )
+    };
+    if result < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to add Landlock rule for `{}`", path.display()));
+    }
+    Ok(())
+}
+
+/// Returns a seccomp filter that prevents creation of any sockets other than Unix domain sockets.
+fn network_filter() -> Vec<libc::sock_filter> {
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+    const ARG0_OFFSET: u32 = 16;
+    const LOAD: u16 = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+    const JUMP_EQ: u16 = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+    const RET: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
+    let stmt = |code, k| libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump_eq = |k, jt, jf| libc::sock_filter {
+        code: JUMP_EQ,
+        jt,
+        jf,
+        k,
+    };
+    let mut filter = vec![
+        stmt(LOAD, ARCH_OFFSET),
+        jump_eq(AUDIT_ARCH, 1, 0),
+        stmt(RET, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(LOAD, NR_OFFSET),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    {
+        // Syscalls via the x32 ABI have a different number, so could be used to bypass the filter.
+        const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+        filter.push(libc::sock_filter {
+            code: (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16,
+            jt: 0,
+            jf: 1,
+            k: X32_SYSCALL_BIT,
+        });
+        filter.push(stmt(RET, libc::SECCOMP_RET_KILL_PROCESS));
+    }
+    filter.extend([
+        jump_eq(libc::SYS_socket as u32, 0, 3),
+        stmt(LOAD, ARG0_OFFSET),
+        jump_eq(libc::AF_UNIX as u32, 1, 0),
+        stmt(
+            RET,
+            libc::SECCOMP_RET_ERRNO | (libc::EACCES as u32 & libc::SECCOMP_RET_DATA),
+        ),
+        stmt(RET, libc::SECCOMP_RET_ALLOW),
+    ]);
+    filter
+}
+
+/// Applies the ruleset and seccomp filter to the current process. Called in the child process
+/// between fork and exec, so must not allocate.
+fn restrict_self(ruleset: &OwnedFd, filter: Option<&[libc::sock_filter]>) -> std::io::Result<()> {
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if let Some(filter) = filter {
+            let program = libc::sock_fprog {
+                len: filter.len() as u16,
+                filter: filter.as_ptr() as *mut libc::sock_filter,
+            };
+            if libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &program as *const libc::sock_fprog,
+            ) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+struct LandlockDisplay {
+    readable: Vec<PathBuf>,
+    writable: Vec<PathBuf>,
+    allow_network: bool,
+    command: Command,
+}
+
+impl Display for LandlockDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Landlock sandbox")?;
+        for path in &self.readable {
+            writeln!(f, "  read: {}", path.display())?;
+        }
+        for path in &self.writable {
+            writeln!(f, "  write: {}", path.display())?;
+        }
+        writeln!(
+            f,
+            "  network: {}",
+            if self.allow_network {
+                "allowed"
+            } else {
+                "denied"
+            }
+        )?;
+        write!(f, "  run: {}", self.command.get_program().to_string_lossy())?;
+        for arg in self.command.get_args() {
+            write!(f, " {}", arg.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Landlock;
+    use crate::sandbox::Sandbox;
+
+    #[test]
+    fn readable_paths_exclude_hidden() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for sub in ["home/user/.cargo", "home/other", "usr/lib"] {
+            std::fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        let mut sandbox = Landlock::default();
+        sandbox.ro_bind(root);
+        sandbox.tmpfs(&root.join("home/user"));
+        sandbox.ro_bind(&root.join("home/user/.cargo"));
+        let mut readable = sandbox.readable_paths();
+        readable.sort();
+        assert_eq!(
+            readable,
+            vec![
+                root.join("home/other"),
+                root.join("home/user/.cargo"),
+                root.join("usr"),
+            ]
+        );
+    }
+}
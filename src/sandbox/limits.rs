@@ -0,0 +1,382 @@
+//! Resource limits for sandboxed processes. CPU time, memory and process count are limited via
+//! rlimits, which are inherited by all descendants of the sandbox. Wall-clock time is limited by
+//! killing the process group of the sandbox once the limit is reached.
+
+// We need unsafe in order to set rlimits and send signals via libc.
+#![allow(unsafe_code)]
+
+use crate::config::SandboxConfig;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::Read;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Output;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often we check whether a process with a wall-clock limit has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Messages that indicate that a process failed to start another process. Under `RLIMIT_NPROC`,
+/// `fork` fails with `EAGAIN`.
+const FORK_FAILED_MESSAGES: &[&str] = &["Resource temporarily unavailable"];
+
+/// Messages that indicate that a process ran out of memory. Under `RLIMIT_AS`, allocations fail
+/// with `ENOMEM`, which Rust programs report as a failed memory allocation.
+const OUT_OF_MEMORY_MESSAGES: &[&str] = &[
+    "Cannot allocate memory",
+    "memory allocation of",
+    "out of memory",
+];
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ResourceLimits {
+    cpu_seconds: Option<u64>,
+    wall_seconds: Option<u64>,
+    memory_mb: Option<u64>,
+    processes: Option<u64>,
+}
+
+/// A limit that a process was terminated for exceeding.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub(crate) enum ResourceLimit {
+    CpuTime(u64),
+    WallTime(u64),
+    /// A memory limit in MiB.
+    Memory(u64),
+    Processes(u64),
+}
+
+impl ResourceLimits {
+    pub(crate) fn from_config(config: &SandboxConfig) -> Self {
+        Self {
+            cpu_seconds: config.max_cpu_seconds,
+            wall_seconds: config.max_wall_seconds,
+            memory_mb: config.max_memory_mb,
+            processes: config.max_processes,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns which limit, if any, caused a process that produced `output` after running for
+    /// `elapsed` and using `cpu_time` to fail. `output.status` may be either that of the process
+    /// itself, or that of a sandbox that reports the process being killed by a signal as 128 + the
+    /// signal number.
+    pub(crate) fn exceeded(
+        &self,
+        output: &Output,
+        elapsed: Duration,
+        cpu_time: Duration,
+    ) -> Option<ResourceLimit> {
+        let status = &output.status;
+        if status.success() {
+            return None;
+        }
+        if let Some(wall_seconds) = self.wall_seconds {
+            if elapsed >= Duration::from_secs(wall_seconds) {
+                return Some(ResourceLimit::WallTime(wall_seconds));
+            }
+        }
+        if let Some(cpu_seconds) = self.cpu_seconds {
+            if terminated_for_cpu_time(status, cpu_time, Duration::from_secs(cpu_seconds)) {
+                return Some(ResourceLimit::CpuTime(cpu_seconds));
+            }
+        }
+        if let Some(processes) = self.processes {
+            if stderr_contains(output, FORK_FAILED_MESSAGES) {
+                return Some(ResourceLimit::Processes(processes));
+            }
+        }
+        if let Some(memory_mb) = self.memory_mb {
+            if terminated_for_memory(status) || stderr_contains(output, OUT_OF_MEMORY_MESSAGES) {
+                return Some(ResourceLimit::Memory(memory_mb));
+            }
+        }
+        None
+    }
+}
+
+fn stderr_contains(output: &Output, messages: &[&str]) -> bool {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    messages.iter().any(|message| stderr.contains(message))
+}
+
+fn terminated_for_cpu_time(status: &ExitStatus, cpu_time: Duration, limit: Duration) -> bool {
+    // SIGXCPU is sent when the soft limit is reached. If the process ignores it, or is the init
+    // process of a PID namespace, for which it has no effect, then it gets SIGKILL when it reaches
+    // the hard limit. SIGKILL is also sent by other things, such as the kernel's OOM killer, so we
+    // only attribute it to the CPU limit if the process actually used that much CPU time.
+    terminated_by_signal(status, libc::SIGXCPU)
+        || (terminated_by_signal(status, libc::SIGKILL) && cpu_time >= limit)
+}
+
+/// Returns whether `status` indicates a crash that's typical of running out of address space. A
+/// failed allocation in Rust aborts and failing to grow the stack results in SIGSEGV.
+fn terminated_for_memory(status: &ExitStatus) -> bool {
+    terminated_by_signal(status, libc::SIGABRT) || terminated_by_signal(status, libc::SIGSEGV)
+}
+
+/// Returns the CPU time used by all child processes of the current process that have terminated
+/// and been waited for, including their descendants that were waited for.
+pub(crate) fn children_cpu_time() -> Duration {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } != 0 {
+        return Duration::ZERO;
+    }
+    let to_duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
+
+fn terminated_by_signal(status: &ExitStatus, signal: i32) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal() == Some(signal) || status.code() == Some(128 + signal)
+}
+
+/// Runs `command`, applying `limits`, and collects its output.
+pub(crate) fn output(command: &mut Command, limits: &ResourceLimits) -> std::io::Result<Output> {
+    if limits.is_empty() {
+        return command.output();
+    }
+    let limits = *limits;
+    // Safety: The closure only makes syscalls, it doesn't allocate, which would be unsafe in the
+    // forked child.
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(command, move || limits.apply());
+    }
+    let Some(wall_seconds) = limits.wall_seconds else {
+        return command.output();
+    };
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let deadline = Instant::now() + Duration::from_secs(wall_seconds);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            // Kill the whole process group, which we created in `apply`, so that we get any
+            // processes that were started by the sandboxed process.
+            unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+            let _ = child.kill();
+            break child.wait()?;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn read_in_background(
+    stream: Option<impl Read + Send + 'static>,
+) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+impl ResourceLimits {
+    /// Applies the limits to the current process. Called in the child after forking.
+    fn apply(&self) -> std::io::Result<()> {
+        if self.wall_seconds.is_some() && unsafe { libc::setpgid(0, 0) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if let Some(cpu_seconds) = self.cpu_seconds {
+            // The soft limit results in SIGXCPU, which terminates the process unless it's handled.
+            // The hard limit, a second later, results in SIGKILL.
+            set_rlimit(libc::RLIMIT_CPU, cpu_seconds, cpu_seconds.saturating_add(1))?;
+        }
+        if let Some(memory_mb) = self.memory_mb {
+            let bytes = memory_mb.saturating_mul(1024 * 1024);
+            set_rlimit(libc::RLIMIT_AS, bytes, bytes)?;
+        }
+        if let Some(processes) = self.processes {
+            set_rlimit(libc::RLIMIT_NPROC, processes, processes)?;
+        }
+        Ok(())
+    }
+}
+
+/// The type that `setrlimit` takes for the resource to limit, which differs between platforms.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type RlimitResource = libc::c_int;
+
+fn set_rlimit(resource: RlimitResource, soft: u64, hard: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl Display for ResourceLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceLimit::CpuTime(seconds) => write!(f, "CPU time limit of {seconds}s"),
+            ResourceLimit::WallTime(seconds) => write!(f, "wall-clock time limit of {seconds}s"),
+            ResourceLimit::Memory(mb) => write!(f, "memory limit of {mb} MiB"),
+            ResourceLimit::Processes(count) => write!(f, "limit of {count} processes"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResourceLimit;
+    use super::ResourceLimits;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+    use std::process::ExitStatus;
+    use std::process::Output;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn wall_clock_limit() {
+        let limits = ResourceLimits {
+            wall_seconds: Some(1),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let output = super::output(
+            Command::new("sh").args(["-c", "echo started; sleep 30"]),
+            &limits,
+        )
+        .unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed < Duration::from_secs(10));
+        assert_eq!(output.stdout, b"started\n");
+        assert_eq!(
+            limits.exceeded(&output, elapsed, Duration::ZERO),
+            Some(ResourceLimit::WallTime(1))
+        );
+    }
+
+    #[test]
+    fn cpu_limit() {
+        let limits = ResourceLimits {
+            cpu_seconds: Some(1),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let output = super::output(
+            Command::new("sh").args(["-c", "while true; do :; done"]),
+            &limits,
+        )
+        .unwrap();
+        assert_eq!(
+            limits.exceeded(&output, start.elapsed(), Duration::from_secs(1)),
+            Some(ResourceLimit::CpuTime(1))
+        );
+    }
+
+    #[test]
+    fn no_limit_exceeded() {
+        let limits = ResourceLimits {
+            cpu_seconds: Some(10),
+            wall_seconds: Some(10),
+            memory_mb: Some(1024),
+            processes: Some(1000),
+        };
+        let start = Instant::now();
+        let output = super::output(Command::new("true").arg("x"), &limits).unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            limits.exceeded(&output, start.elapsed(), Duration::ZERO),
+            None
+        );
+    }
+
+    fn failed_output(signal: i32, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(signal),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn killed_without_using_cpu_limit() {
+        let limits = ResourceLimits {
+            cpu_seconds: Some(10),
+            ..Default::default()
+        };
+        let killed = failed_output(libc::SIGKILL, "");
+        let exceeded = |cpu_seconds| {
+            limits.exceeded(&killed, Duration::ZERO, Duration::from_secs(cpu_seconds))
+        };
+        // e.g. killed by the OOM killer.
+        assert_eq!(exceeded(2), None);
+        assert_eq!(exceeded(11), Some(ResourceLimit::CpuTime(10)));
+    }
+
+    #[test]
+    fn memory_and_process_limits() {
+        let limits = ResourceLimits {
+            memory_mb: Some(512),
+            processes: Some(100),
+            ..Default::default()
+        };
+        let exceeded = |output: &Output| limits.exceeded(output, Duration::ZERO, Duration::ZERO);
+        assert_eq!(
+            exceeded(&failed_output(
+                libc::SIGABRT,
+                "memory allocation of 1048576 bytes failed"
+            )),
+            Some(ResourceLimit::Memory(512))
+        );
+        assert_eq!(
+            exceeded(&failed_output(libc::SIGSEGV, "")),
+            Some(ResourceLimit::Memory(512))
+        );
+        assert_eq!(
+            exceeded(&Output {
+                status: ExitStatus::from_raw(1 << 8),
+                stdout: Vec::new(),
+                stderr: b"sh: fork: retry: Resource temporarily unavailable".to_vec(),
+            }),
+            Some(ResourceLimit::Processes(100))
+        );
+        assert_eq!(
+            exceeded(&Output {
+                status: ExitStatus::from_raw(1 << 8),
+                stdout: Vec::new(),
+                stderr: b"error: file not found".to_vec(),
+            }),
+            None
+        );
+        // Without a memory limit, a crash is just a crash.
+        assert_eq!(
+            ResourceLimits::default().exceeded(
+                &failed_output(libc::SIGSEGV, ""),
+                Duration::ZERO,
+                Duration::ZERO
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,653 @@
+//! A sandbox built directly on Linux namespaces, without needing Bubblewrap to be installed. It
+//! works in much the same way as Bubblewrap: we create new user, mount, PID, IPC, UTS and
+//! (optionally) network namespaces, then construct a new root filesystem from bind mounts of
+//! selected parts of the real filesystem.
+//!
+//! Setting up the namespaces needs to happen in a process that hasn't yet started any threads, so
+//! rather than doing it between fork and exec, we run our own binary as a helper, passing it a
+//! description of the sandbox via an environment variable. The helper sets up the sandbox, then
+//! execs the sandboxed command.
+
+#![allow(unsafe_code)]
+
+use super::limits;
+use super::ResourceLimits;
+use super::Sandbox;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The environment variable via which we pass a serialised `Plan` to the helper process.
+const PLAN_ENV: &str = "CACKLE_NAMESPACE_SANDBOX_PLAN";
+
+/// The exit code used by the helper process if setting up the sandbox fails.
+const SETUP_FAILED_EXIT_CODE: i32 = 125;
+
+/// The user and group IDs that the sandboxed process runs as. Same as what we ask Bubblewrap for.
+const SANDBOX_ID: u32 = 1000;
+
+/// Where the base tmpfs, in which we construct the new root, is mounted. After pivoting into the
+/// base, the old root is at `/oldroot` and the new root is at `/newroot`.
+const BASE_DIR: &str = "/tmp";
+
+/// Device nodes that are bound from the real `/dev`.
+const DEVICES: &[&str] = &["null", "zero", "full", "random", "urandom", "tty"];
+
+#[derive(Default)]
+pub(super) struct Namespaces {
+    plan: Plan,
+    limits: ResourceLimits,
+    /// Arguments from `extra_args`, which we don't support, but record so that we can report an
+    /// error when we try to run.
+    unsupported_args: Vec<OsString>,
+}
+
+/// Everything that the helper process needs to know in order to set up the sandbox and run the
+/// sandboxed command.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+struct Plan {
+    /// Mounts, applied in order, so later mounts may cover earlier ones.
+    mounts: Vec<Mount>,
+    env: Vec<(OsString, OsString)>,
+    allow_network: bool,
+    program: OsString,
+    args: Vec<OsString>,
+    current_dir: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum Mount {
+    ReadOnly(PathBuf),
+    Writable(PathBuf),
+    Tmpfs(PathBuf),
+    Overlay {
+        dir: PathBuf,
+        upper: PathBuf,
+        work: PathBuf,
+    },
+}
+
+impl Sandbox for Namespaces {
+    fn raw_arg(&mut self, arg: &OsStr) {
+        self.unsupported_args.push(arg.to_owned());
+    }
+
+    fn tmpfs(&mut self, dir: &Path) {
+        self.plan.mounts.push(Mount::Tmpfs(dir.to_owned()));
+    }
+
+    fn ro_bind(&mut self, dir: &Path) {
+        self.plan.mounts.push(Mount::ReadOnly(dir.to_owned()));
+    }
+
+    fn writable_bind(&mut self, dir: &Path) {
+        self.plan.mounts.push(Mount::Writable(dir.to_owned()));
+    }
+
+    fn overlay(&mut self, dir: &Path, upper: &Path, work: &Path) {
+        self.plan.mounts.push(Mount::Overlay {
+            dir: dir.to_owned(),
+            upper: upper.to_owned(),
+            work: work.to_owned(),
+        });
+    }
+
+    fn supports_overlay(&self) -> bool {
+        true
+    }
+
+    fn set_env(&mut self, var: &OsStr, value: &OsStr) {
+        self.plan.env.push((var.to_owned(), value.to_owned()));
+    }
+
+    fn allow_network(&mut self) {
+        self.plan.allow_network = true;
+    }
+
+    fn set_resource_limits(&mut self, limits: ResourceLimits) {
+        self.limits = limits;
+    }
+
+    fn run(&self, command: &Command) -> Result<std::process::Output> {
+        if !self.unsupported_args.is_empty() {
+            bail!("The Namespaces sandbox doesn't support `extra_args`");
+        }
+        let plan = self.plan_for(command)?;
+        let exe = std::env::current_exe().context("Failed to determine path to cargo-acl")?;
+        let mut helper = Command::new(exe);
+        helper
+            .env_clear()
+            .env(PLAN_ENV, serde_json::to_string(&plan)?);
+        let output = limits::output(&mut helper, &self.limits)
+            .context("Failed to run namespace sandbox helper")?;
+        Ok(output)
+    }
+
+    fn display_to_run(&self, command: &Command) -> Box<dyn Display> {
+        let plan = self.plan_for(command).unwrap_or_else(|_| self.plan.clone());
+        Box::new(plan)
+    }
+}
+
+impl Namespaces {
+    fn plan_for(&self, command: &Command) -> Result<Plan> {
+        let mut plan = self.plan.clone();
+        for (var, value) in command.get_envs() {
+            plan.env.retain(|(existing, _)| existing != var);
+            if let Some(value) = value {
+                plan.env.push((var.to_owned(), value.to_owned()));
+            }
+        }
+        plan.program = command.get_program().to_owned();
+        plan.args = command.get_args().map(|arg| arg.to_owned()).collect();
+        plan.current_dir = match command.get_current_dir() {
+            Some(dir) => Some(dir.to_owned()),
+            None => Some(std::env::current_dir().context("Failed to get current directory")?),
+        };
+        Ok(plan)
+    }
+}
+
+/// If we've been invoked as the helper process for the namespace sandbox, then sets up the sandbox
+/// and runs the sandboxed command. Doesn't return in that case.
+pub(crate) fn handle_helper_invocation() {
+    let Some(serialised) = std::env::var_os(PLAN_ENV) else {
+        return;
+    };
+    let result = serde_json::from_slice::<Plan>(serialised.as_bytes())
+        .context("Invalid sandbox plan")
+        .and_then(|plan| run_helper(&plan));
+    if let Err(error) = result {
+        eprintln!("Failed to set up namespace sandbox: {error:#}");
+    }
+    std::process::exit(SETUP_FAILED_EXIT_CODE);
+}
+
+/// Returns whether we're able to create the namespaces that we need.
+pub(super) fn is_available() -> bool {
+    let mut sandbox = Namespaces::default();
+    sandbox.ro_bind(Path::new("/"));
+    sandbox
+        .run(&Command::new("true"))
+        .is_ok_and(|output| output.status.success())
+}
+
+fn run_helper(plan: &Plan) -> Result<()> {
+    let uid = unsafe { libc::getuid() };
+    let gid = unsafe { libc::getgid() };
+    let mut flags = libc::CLONE_NEWUSER
+        | libc::CLONE_NEWNS
+        | libc::CLONE_NEWPID
+        | libc::CLONE_NEWIPC
+        | libc::CLONE_NEWUTS;
+    if !plan.allow_network {
+        flags |= libc::CLONE_NEWNET;
+    }
+    if unsafe { libc::unshare(flags) } != 0 {
+        return Err(std::io::Error::last_os_error()).context(
+            "Failed to create namespaces. Unprivileged user namespaces may be disabled on this \
+             system, e.g. by the sysctl `kernel.unprivileged_userns_clone` or \
+             `kernel.apparmor_restrict_unprivileged_userns`. If so, consider using the Landlock \
+             sandbox instead",
+        );
+    }
+    write_file("/proc/self/setgroups", "deny")?;
+    write_file("/proc/self/uid_map", &format!("{SANDBOX_ID} {uid} 1"))?;
+    write_file("/proc/self/gid_map", &format!("{SANDBOX_ID} {gid} 1"))?;
+    if !plan.allow_network {
+        // As with Bubblewrap, bring up the loopback interface in the new network namespace.
+        loopback_up()?;
+    }
+
+    // We need to fork so that the child is the first process in the new PID namespace, which it
+    // needs to be in order to mount /proc.
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(std::io::Error::last_os_error()).context("fork failed");
+    }
+    if pid == 0 {
+        // If the helper gets killed, e.g. due to a wall-clock time limit, take the sandbox with it.
+        unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) };
+        let error = match setup_child(plan) {
+            Ok(()) => exec(plan),
+            Err(error) => error,
+        };
+        eprintln!("Failed to set up namespace sandbox: {error:#}");
+        std::process::exit(SETUP_FAILED_EXIT_CODE);
+    }
+    let mut status = 0;
+    if unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+        return Err(std::io::Error::last_os_error()).context("waitpid failed");
+    }
+    let code = if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else {
+        128 + libc::WTERMSIG(status)
+    };
+    std::process::exit(code);
+}
+
+fn setup_child(plan: &Plan) -> Result<()> {
+    // Make sure that none of our mounts propagate back to the parent namespace.
+    mount(None, "/", None, libc::MS_REC | libc::MS_PRIVATE)?;
+
+    // Create a tmpfs in which to build the new root, then pivot into it, keeping the old root
+    // accessible at /oldroot so that we can bind from it.
+    mount(
+        Some(OsStr::new("tmpfs")),
+        BASE_DIR,
+        Some("tmpfs"),
+        libc::MS_NODEV | libc::MS_NOSUID,
+    )?;
+    std::fs::create_dir(Path::new(BASE_DIR).join("oldroot"))?;
+    std::fs::create_dir(Path::new(BASE_DIR).join("newroot"))?;
+    pivot_root(BASE_DIR, &format!("{BASE_DIR}/oldroot"))?;
+    std::env::set_current_dir("/")?;
+    mount(
+        Some(OsStr::new("tmpfs")),
+        "/newroot",
+        Some("tmpfs"),
+        libc::MS_NODEV | libc::MS_NOSUID,
+    )?;
+
+    for m in &plan.mounts {
+        match m {
+            Mount::ReadOnly(path) => bind(path, true)?,
+            Mount::Writable(path) => bind(path, false)?,
+            Mount::Tmpfs(path) => {
+                let dest = new_root_path(path);
+                std::fs::create_dir_all(&dest)
+                    .with_context(|| format!("Failed to create `{}`", path.display()))?;
+                mount(
+                    Some(OsStr::new("tmpfs")),
+                    &dest,
+                    Some("tmpfs"),
+                    libc::MS_NODEV | libc::MS_NOSUID,
+                )?;
+            }
+            Mount::Overlay { dir, upper, work } => overlay(dir, upper, work)?,
+        }
+    }
+    setup_dev()?;
+    let proc_dir = new_root_path(Path::new("/proc"));
+    std::fs::create_dir_all(&proc_dir)?;
+    mount(
+        Some(OsStr::new("proc")),
+        &proc_dir,
+        Some("proc"),
+        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+    )?;
+
+    // Switch to the new root and detach the old one.
+    std::env::set_current_dir("/newroot")?;
+    pivot_root(".", ".")?;
+    let dot = CString::new(".")?;
+    if unsafe { libc::umount2(dot.as_ptr(), libc::MNT_DETACH) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to unmount old root");
+    }
+    std::env::set_current_dir("/")?;
+    if let Some(dir) = &plan.current_dir {
+        // As with Bubblewrap, if the directory isn't available in the sandbox, we stay at the root.
+        let _ = std::env::set_current_dir(dir);
+    }
+
+    let hostname = "none";
+    unsafe {
+        libc::sethostname(hostname.as_ptr().cast(), hostname.len());
+        libc::setsid();
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to set no_new_privs");
+        }
+    }
+    Ok(())
+}
+
+fn exec(plan: &Plan) -> anyhow::Error {
+    let error = Command::new(&plan.program)
+        .args(&plan.args)
+        .env_clear()
+        .envs(plan.env.iter().map(|(var, value)| (var, value)))
+        .exec();
+    anyhow!(error).context(format!(
+        "Failed to run `{}` in sandbox",
+        Path::new(&plan.program).display()
+    ))
+}
+
+/// Binds `path` from the old root to the same location in the new root. Like `--ro-bind` and
+/// `--bind-try` in Bubblewrap, if `path` doesn't exist, it's skipped.
+fn bind(path: &Path, read_only: bool) -> Result<()> {
+    let source = old_root_path(path);
+    let Ok(metadata) = std::fs::metadata(&source) else {
+        return Ok(());
+    };
+    let dest = new_root_path(path);
+    create_mount_point(&dest, metadata.is_dir())
+        .with_context(|| format!("Failed to create mount point for `{}`", path.display()))?;
+    mount(
+        Some(source.as_os_str()),
+        &dest,
+        None,
+        libc::MS_BIND | libc::MS_REC,
+    )?;
+    if read_only {
+        // A recursive bind mount doesn't let us make all the mounts read-only in one go, so we
+        // remount each of them.
+        for mount_point in mount_points_under(&dest)? {
+            match remount_read_only(&mount_point) {
+                Ok(()) => {}
+                // Some mounts, e.g. those inaccessible to our user, can't be remounted. We can't
+                // write to those anyway.
+                Err(error) if error.raw_os_error() == Some(libc::EACCES) => {}
+                Err(error) => {
+                    return Err(error).with_context(|| {
+                        format!("Failed to make `{}` read-only", mount_point.display())
+                    })
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Mounts an overlay at `dir` in the new root with the lower layer being `dir` from the old root.
+fn overlay(dir: &Path, upper: &Path, work: &Path) -> Result<()> {
+    let dest = new_root_path(dir);
+    std::fs::create_dir_all(&dest)
+        .with_context(|| format!("Failed to create mount point for `{}`", dir.display()))?;
+    let mut options = Vec::new();
+    for (name, path) in [("lowerdir", dir), ("upperdir", upper), ("workdir", work)] {
+        let path = old_root_path(path);
+        let bytes = path.as_os_str().as_bytes();
+        if bytes.contains(&b',') || bytes.contains(&b':') {
+            bail!(
+                "Can't mount an overlay using `{}`, since it contains `,` or `:`",
+                path.display()
+            );
+        }
+        options.push(format!("{name}={}", path.display()));
+    }
+    // Unprivileged overlay mounts need to store their metadata in user rather than trusted xattrs.
+    options.push("userxattr".to_owned());
+    let source = CString::new("overlay")?;
+    let dest_c = CString::new(dest.as_os_str().as_bytes())?;
+    let data = CString::new(options.join(","))?;
+    let result = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            dest_c.as_ptr(),
+            source.as_ptr(),
+            libc::MS_NODEV | libc::MS_NOSUID,
+            data.as_ptr().cast(),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| {
+            format!(
+                "Failed to mount overlay on `{}`. Linux 5.11 or later is required",
+                dir.display()
+            )
+        });
+    }
+    Ok(())
+}
+
+fn setup_dev() -> Result<()> {
+    let dev = new_root_path(Path::new("/dev"));
+    std::fs::create_dir_all(&dev)?;
+    mount(
+        Some(OsStr::new("tmpfs")),
+        &dev,
+        Some("tmpfs"),
+        libc::MS_NOSUID | libc::MS_NOEXEC,
+    )?;
+    for device in DEVICES {
+        let source = Path::new("/oldroot/dev").join(device);
+        if !source.exists() {
+            continue;
+        }
+        let dest = dev.join(device);
+        create_mount_point(&dest, false)?;
+        mount(Some(source.as_os_str()), &dest, None, libc::MS_BIND)?;
+    }
+    for (name, target) in [
+        ("fd", "/proc/self/fd"),
+        ("stdin", "/proc/self/fd/0"),
+        ("stdout", "/proc/self/fd/1"),
+        ("stderr", "/proc/self/fd/2"),
+    ] {
+        std::os::unix::fs::symlink(target, dev.join(name))?;
+    }
+    let shm = dev.join("shm");
+    std::fs::create_dir(&shm)?;
+    mount(
+        Some(OsStr::new("tmpfs")),
+        &shm,
+        Some("tmpfs"),
+        libc::MS_NOSUID | libc::MS_NODEV,
+    )?;
+    Ok(())
+}
+
+fn create_mount_point(dest: &Path, is_dir: bool) -> Result<()> {
+    if is_dir {
+        std::fs::create_dir_all(dest)?;
+    } else {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if !dest.exists() {
+            std::fs::write(dest, "")?;
+        }
+    }
+    Ok(())
+}
+
+fn old_root_path(path: &Path) -> PathBuf {
+    Path::new("/oldroot").join(path.strip_prefix("/").unwrap_or(path))
+}
+
+fn new_root_path(path: &Path) -> PathBuf {
+    Path::new("/newroot").join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// Returns `dir` and all mount points beneath it. Must be called while the old root is still at
+/// /oldroot, since that's where /proc is.
+fn mount_points_under(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mount_info = std::fs::read_to_string("/oldroot/proc/self/mountinfo")
+        .context("Failed to read /proc/self/mountinfo")?;
+    Ok(parse_mount_points(&mount_info)
+        .into_iter()
+        .filter(|mount_point| mount_point.starts_with(dir))
+        .collect())
+}
+
+/// Extracts the mount points from the contents of /proc/self/mountinfo.
+fn parse_mount_points(mount_info: &str) -> Vec<PathBuf> {
+    mount_info
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(|escaped| PathBuf::from(unescape_mount_point(escaped)))
+        .collect()
+}
+
+/// Mount points in mountinfo have spaces, tabs, newlines and backslashes escaped as octal.
+fn unescape_mount_point(escaped: &str) -> String {
+    escaped
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+fn remount_read_only(mount_point: &Path) -> std::io::Result<()> {
+    let c_path = CString::new(mount_point.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // When remounting in a user namespace, we must retain any flags that were locked by the
+    // parent namespace, otherwise the remount fails.
+    let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+    for (st_flag, ms_flag) in [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ] {
+        if stat.f_flag & st_flag != 0 {
+            flags |= ms_flag;
+        }
+    }
+    let result = unsafe {
+        libc::mount(
+            std::ptr::null(),
+            c_path.as_ptr(),
+            std::ptr::null(),
+            flags,
+            std::ptr::null(),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn mount(
+    source: Option<&OsStr>,
+    dest: impl AsRef<Path>,
+    fs_type: Option<&str>,
+    flags: libc::c_ulong,
+) -> Result<()> {
+    let dest = dest.as_ref();
+    let source = source.map(|s| CString::new(s.as_bytes())).transpose()?;
+    let dest_c = CString::new(dest.as_os_str().as_bytes())?;
+    let fs_type = fs_type.map(CString::new).transpose()?;
+    let result = unsafe {
+        libc::mount(
+            source.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            dest_c.as_ptr(),
+            fs_type.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            flags,
+            std::ptr::null(),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to mount `{}`", dest.display()));
+    }
+    Ok(())
+}
+
+fn pivot_root(new_root: &str, put_old: &str) -> Result<()> {
+    let new_root_c = CString::new(new_root)?;
+    let put_old_c = CString::new(put_old)?;
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_pivot_root,
+            new_root_c.as_ptr(),
+            put_old_c.as_ptr(),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to pivot root to `{new_root}`"));
+    }
+    Ok(())
+}
+
+fn loopback_up() -> Result<()> {
+    let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if socket < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create socket");
+    }
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dest, src) in request.ifr_name.iter_mut().zip(b"lo") {
+        *dest = *src as libc::c_char;
+    }
+    request.ifr_ifru.ifru_flags = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+    let result = unsafe { libc::ioctl(socket, libc::SIOCSIFFLAGS, &request) };
+    let error = std::io::Error::last_os_error();
+    unsafe { libc::close(socket) };
+    if result != 0 {
+        return Err(error).context("Failed to bring up loopback interface");
+    }
+    Ok(())
+}
+
+fn write_file(path: &str, contents: &str) -> Result<()> {
+    std::fs::write(path, contents).with_context(|| format!("Failed to write `{path}`"))
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "namespace-sandbox")?;
+        if self.allow_network {
+            write!(f, " --share-net")?;
+        }
+        for m in &self.mounts {
+            match m {
+                Mount::ReadOnly(path) => write!(f, " --ro-bind {}", path.display())?,
+                Mount::Writable(path) => write!(f, " --bind {}", path.display())?,
+                Mount::Tmpfs(path) => write!(f, " --tmpfs {}", path.display())?,
+                Mount::Overlay { dir, upper, work } => write!(
+                    f,
+                    " --overlay-src {} --overlay {} {} {}",
+                    dir.display(),
+                    upper.display(),
+                    work.display(),
+                    dir.display()
+                )?,
+            }
+        }
+        for (var, value) in &self.env {
+            write!(
+                f,
+                " --setenv {} {:?}",
+                var.to_string_lossy(),
+                value.to_string_lossy()
+            )?;
+        }
+        write!(f, " -- {}", self.program.to_string_lossy())?;
+        for arg in &self.args {
+            write!(f, " {}", arg.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    #[test]
+    fn parse_mount_points() {
+        let mount_info = "\
+            22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+            35 22 0:31 / /tmp rw,nosuid,nodev shared:15 - tmpfs tmpfs rw\n\
+            36 22 0:32 / /mnt/with\\040space rw - tmpfs tmpfs rw\n";
+        assert_eq!(
+            super::parse_mount_points(mount_info),
+            vec![
+                PathBuf::from("/"),
+                PathBuf::from("/tmp"),
+                PathBuf::from("/mnt/with space"),
+            ]
+        );
+    }
+}
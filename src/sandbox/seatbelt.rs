@@ -0,0 +1,248 @@
+//! A sandbox for macOS that runs the sandboxed process via `sandbox-exec` with a Seatbelt profile
+//! that we generate from the sandbox config.
+//!
+//! Seatbelt can't mount anything, so as with Landlock, directories that Bubblewrap would mount a
+//! tmpfs over are instead made inaccessible and the system temporary directory is replaced by a
+//! private, empty directory via `TMPDIR`. In Seatbelt profiles, later rules take precedence over
+//! earlier ones, so we emit rules in the order that they were added, which gives the same result as
+//! Bubblewrap's later mounts covering earlier ones.
+
+use super::limits;
+use super::ResourceLimits;
+use super::Sandbox;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+const SANDBOX_EXEC: &str = "/usr/bin/sandbox-exec";
+
+#[derive(Default)]
+pub(super) struct Seatbelt {
+    rules: Vec<Rule>,
+    env: Vec<(OsString, OsString)>,
+    allow_network: bool,
+    limits: ResourceLimits,
+    /// Arguments from `extra_args`, which we don't support, but record so that we can report an
+    /// error when we try to run.
+    unsupported_args: Vec<OsString>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Rule {
+    ReadOnly(PathBuf),
+    Writable(PathBuf),
+    Hidden(PathBuf),
+}
+
+impl Sandbox for Seatbelt {
+    fn raw_arg(&mut self, arg: &OsStr) {
+        self.unsupported_args.push(arg.to_owned());
+    }
+
+    fn tmpfs(&mut self, dir: &Path) {
+        self.rules.push(Rule::Hidden(dir.to_owned()));
+    }
+
+    fn ro_bind(&mut self, dir: &Path) {
+        self.rules.push(Rule::ReadOnly(dir.to_owned()));
+    }
+
+    fn writable_bind(&mut self, dir: &Path) {
+        self.rules.push(Rule::Writable(dir.to_owned()));
+    }
+
+    fn set_env(&mut self, var: &OsStr, value: &OsStr) {
+        self.env.push((var.to_owned(), value.to_owned()));
+    }
+
+    fn allow_network(&mut self) {
+        self.allow_network = true;
+    }
+
+    fn set_resource_limits(&mut self, limits: ResourceLimits) {
+        self.limits = limits;
+    }
+
+    fn run(&self, command: &Command) -> Result<std::process::Output> {
+        if !self.unsupported_args.is_empty() {
+            bail!("The Seatbelt sandbox doesn't support `extra_args`");
+        }
+        let tmpdir = tempfile::tempdir().context("Failed to create temporary directory")?;
+        let mut sandboxed = self.command(command, Some(tmpdir.path()))?;
+        limits::output(&mut sandboxed, &self.limits).with_context(|| {
+            format!(
+                "Failed to run `{}` in Seatbelt sandbox",
+                Path::new(command.get_program()).display()
+            )
+        })
+    }
+
+    fn display_to_run(&self, command: &Command) -> Box<dyn Display> {
+        match self.command(command, None) {
+            Ok(command) => Box::new(CommandDisplay { command }),
+            Err(error) => Box::new(format!("{error:#}")),
+        }
+    }
+}
+
+impl Seatbelt {
+    /// Returns a command that runs `command` under `sandbox-exec` with only the environment
+    /// variables that have been explicitly passed through. If supplied, `tmpdir` is made writable
+    /// and used as `TMPDIR`.
+    fn command(&self, command: &Command, tmpdir: Option<&Path>) -> Result<Command> {
+        let mut sandboxed = Command::new(SANDBOX_EXEC);
+        sandboxed
+            .arg("-p")
+            .arg(self.profile(tmpdir)?)
+            .arg(command.get_program())
+            .args(command.get_args())
+            .env_clear();
+        if let Some(dir) = command.get_current_dir() {
+            sandboxed.current_dir(dir);
+        }
+        for (var, value) in &self.env {
+            sandboxed.env(var, value);
+        }
+        if let Some(tmpdir) = tmpdir {
+            sandboxed.env("TMPDIR", tmpdir);
+        }
+        for (var, value) in command.get_envs() {
+            if let Some(value) = value {
+                sandboxed.env(var, value);
+            } else {
+                sandboxed.env_remove(var);
+            }
+        }
+        Ok(sandboxed)
+    }
+
+    /// Returns the Seatbelt profile for this sandbox.
+    fn profile(&self, tmpdir: Option<&Path>) -> Result<String> {
+        let mut out = String::new();
+        out.push_str("(version 1)\n");
+        out.push_str("(deny default)\n");
+        out.push_str("(allow process-fork process-exec)\n");
+        out.push_str("(allow signal (target same-sandbox))\n");
+        out.push_str("(allow sysctl-read mach-lookup ipc-posix-shm)\n");
+        out.push_str("(allow file-read-metadata)\n");
+        out.push_str("(allow file-write-data (literal \"/dev/null\"))\n");
+        for rule in &self.rules {
+            let (action, path) = match rule {
+                Rule::ReadOnly(path) => ("(allow file-read*", path),
+                Rule::Writable(path) => ("(allow file-read* file-write*", path),
+                Rule::Hidden(path) => ("(deny file-read* file-write*", path),
+            };
+            writeln!(out, "{action} (subpath {}))", quoted(&resolve(path))?)?;
+        }
+        if let Some(tmpdir) = tmpdir {
+            writeln!(
+                out,
+                "(allow file-read* file-write* (subpath {}))",
+                quoted(&resolve(tmpdir))?
+            )?;
+        }
+        if self.allow_network {
+            out.push_str("(allow network*)\n");
+        } else {
+            // Unix domain sockets are still permitted. These are used to reach our HTTP proxy if
+            // access to particular domains has been allowed.
+            out.push_str("(allow network* (remote unix-socket))\n");
+        }
+        Ok(out)
+    }
+}
+
+/// Returns `path` with any symlinks resolved. Seatbelt matches against the real path, so a rule for
+/// e.g. `/tmp` would otherwise not match anything, since on macOS that's a symlink to `/private/tmp`.
+fn resolve(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+/// Returns `path` as a Seatbelt string literal.
+fn quoted(path: &Path) -> Result<String> {
+    let Some(path) = path.to_str() else {
+        bail!(
+            "Path `{}` is not valid UTF-8, so can't be used in a Seatbelt profile",
+            path.display()
+        );
+    };
+    let mut out = String::with_capacity(path.len() + 2);
+    out.push('"');
+    for ch in path.chars() {
+        if ch == '"' || ch == '\\' {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out.push('"');
+    Ok(out)
+}
+
+pub(crate) fn has_sandbox_exec() -> bool {
+    cfg!(target_os = "macos") && Path::new(SANDBOX_EXEC).exists()
+}
+
+struct CommandDisplay {
+    command: Command,
+}
+
+impl Display for CommandDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut args = self.command.get_args();
+        write!(f, "{}", self.command.get_program().to_string_lossy())?;
+        if let (Some(flag), Some(profile)) = (args.next(), args.next()) {
+            writeln!(f, " {} '", flag.to_string_lossy())?;
+            write!(f, "{}'", profile.to_string_lossy())?;
+        }
+        for arg in args {
+            write!(f, " {}", arg.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Seatbelt;
+    use crate::sandbox::Sandbox;
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    #[test]
+    fn profile() {
+        let mut sandbox = Seatbelt::default();
+        sandbox.ro_bind(Path::new("/"));
+        sandbox.tmpfs(Path::new("/nonexistent/home"));
+        sandbox.ro_bind(Path::new("/nonexistent/home/.cargo"));
+        sandbox.writable_bind(Path::new("/nonexistent/out \"dir\""));
+        sandbox.set_env(OsStr::new("USER"), OsStr::new("user"));
+        let profile = sandbox.profile(None).unwrap();
+        let rules: Vec<&str> = profile
+            .lines()
+            .filter(|line| line.contains("subpath") || line.contains("network"))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                r#"(allow file-read* (subpath "/"))"#,
+                r#"(deny file-read* file-write* (subpath "/nonexistent/home"))"#,
+                r#"(allow file-read* (subpath "/nonexistent/home/.cargo"))"#,
+                r#"(allow file-read* file-write* (subpath "/nonexistent/out \"dir\""))"#,
+                r#"(allow network* (remote unix-socket))"#,
+            ]
+        );
+
+        sandbox.allow_network();
+        assert!(sandbox
+            .profile(None)
+            .unwrap()
+            .ends_with("(allow network*)\n"));
+    }
+}
@@ -0,0 +1,327 @@
+//! Tracing of the file and network accesses that a sandboxed process attempted but was denied.
+//! This is done by running the process under strace, which helps users figure out why something
+//! failed in the sandbox and what they'd need to permit for it to work.
+
+use super::Sandbox;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The most denied accesses that we'll report. Anything beyond this is likely just noise.
+const MAX_DENIED_ACCESSES: usize = 100;
+
+/// Syscalls that modify the filesystem. `open` and friends are classified based on their flags.
+const WRITE_SYSCALLS: &[&str] = &[
+    "creat",
+    "mkdir",
+    "mkdirat",
+    "mknod",
+    "mknodat",
+    "unlink",
+    "unlinkat",
+    "rmdir",
+    "rename",
+    "renameat",
+    "renameat2",
+    "link",
+    "linkat",
+    "symlink",
+    "symlinkat",
+    "chmod",
+    "fchmodat",
+    "chown",
+    "lchown",
+    "fchownat",
+    "truncate",
+    "utime",
+    "utimes",
+    "utimensat",
+];
+
+const OPEN_WRITE_FLAGS: &[&str] = &["O_WRONLY", "O_RDWR", "O_CREAT", "O_TRUNC"];
+
+const NETWORK_SYSCALLS: &[&str] = &["connect", "bind", "sendto", "sendmsg"];
+
+/// An access that a sandboxed process attempted, but which failed, most likely because of the
+/// sandbox.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Hash, PartialOrd, Ord)]
+pub(crate) struct DeniedAccess {
+    pub(crate) kind: AccessKind,
+    /// A path for file accesses, or an address for network accesses.
+    pub(crate) target: String,
+    /// The error name, e.g. `EACCES`.
+    pub(crate) error: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub(crate) enum AccessKind {
+    Read,
+    Write,
+    Network,
+}
+
+pub(crate) struct Trace {
+    dir: tempfile::TempDir,
+    /// Directories that are hidden by the sandbox. A file not being found in one of these is
+    /// considered a denied access.
+    hidden_dirs: Vec<PathBuf>,
+    /// Directories within `hidden_dirs` that are nonetheless visible in the sandbox.
+    visible_dirs: Vec<PathBuf>,
+}
+
+impl Trace {
+    /// Configures `sandbox` so that `command` can be traced and returns a command that runs
+    /// `command` under strace.
+    pub(crate) fn start(
+        sandbox: &mut dyn Sandbox,
+        command: &Command,
+        hidden_dirs: Vec<PathBuf>,
+        visible_dirs: Vec<PathBuf>,
+    ) -> Result<(Self, Command)> {
+        let Some(strace) = find_in_path("strace") else {
+            bail!("Tracing of sandboxed processes requires strace to be installed");
+        };
+        let dir = tempfile::TempDir::new().context("Failed to create directory for trace")?;
+        sandbox.writable_bind(dir.path());
+        let mut traced = Command::new(strace);
+        traced
+            // Follow forks, writing a separate file for each process so that output from
+            // concurrent syscalls doesn't get interleaved.
+            .arg("-ff")
+            .arg("-qq")
+            .args(["-s", "4096"])
+            .args(["-e", "trace=%file,%network"])
+            .args(["-e", "status=failed"])
+            .arg("-o")
+            .arg(dir.path().join("trace"))
+            .arg("--")
+            .arg(command.get_program())
+            .args(command.get_args());
+        for (var, value) in command.get_envs() {
+            match value {
+                Some(value) => traced.env(var, value),
+                None => traced.env_remove(var),
+            };
+        }
+        if let Some(dir) = command.get_current_dir() {
+            traced.current_dir(dir);
+        }
+        Ok((
+            Self {
+                dir,
+                hidden_dirs,
+                visible_dirs,
+            },
+            traced,
+        ))
+    }
+
+    /// Returns the accesses that were denied.
+    pub(crate) fn denied_accesses(&self) -> Result<Vec<DeniedAccess>> {
+        let mut denied = Vec::new();
+        for entry in std::fs::read_dir(self.dir.path())? {
+            let path = entry?.path();
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read trace `{}`", path.display()))?;
+            denied.extend(contents.lines().filter_map(|line| self.parse_line(line)));
+        }
+        denied.sort();
+        denied.dedup();
+        denied.truncate(MAX_DENIED_ACCESSES);
+        Ok(denied)
+    }
+
+    fn parse_line(&self, line: &str) -> Option<DeniedAccess> {
+        let (call, result) = line.rsplit_once(" = -1 ")?;
+        let error = result.split(' ').next()?;
+        let (syscall, args) = call.split_once('(')?;
+        if NETWORK_SYSCALLS.contains(&syscall) {
+            if matches!(error, "EINPROGRESS" | "EAGAIN" | "EALREADY") {
+                return None;
+            }
+            return Some(DeniedAccess {
+                kind: AccessKind::Network,
+                target: parse_inet_address(args)?,
+                error: error.to_owned(),
+            });
+        }
+        if syscall == "socket" {
+            // Our Landlock sandbox denies creation of sockets other than Unix domain sockets.
+            if !matches!(error, "EACCES" | "EPERM") {
+                return None;
+            }
+            let family = args.split(',').next()?;
+            return Some(DeniedAccess {
+                kind: AccessKind::Network,
+                target: family.to_owned(),
+                error: error.to_owned(),
+            });
+        }
+        let path = first_string(args)?;
+        let is_denied = match error {
+            "EACCES" | "EPERM" | "EROFS" => true,
+            "ENOENT" => self.is_hidden(Path::new(&path)),
+            _ => false,
+        };
+        if !is_denied {
+            return None;
+        }
+        let is_write = WRITE_SYSCALLS.contains(&syscall)
+            || (syscall.starts_with("open")
+                && OPEN_WRITE_FLAGS.iter().any(|flag| args.contains(flag)));
+        Some(DeniedAccess {
+            kind: if is_write {
+                AccessKind::Write
+            } else {
+                AccessKind::Read
+            },
+            target: path,
+            error: error.to_owned(),
+        })
+    }
+
+    fn is_hidden(&self, path: &Path) -> bool {
+        self.hidden_dirs.iter().any(|dir| path.starts_with(dir))
+            && !self.visible_dirs.iter().any(|dir| path.starts_with(dir))
+    }
+}
+
+pub(crate) fn find_in_path(binary: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Returns the first quoted string in some strace syscall arguments, with escapes processed.
+fn first_string(args: &str) -> Option<String> {
+    let (_, rest) = args.split_once('"')?;
+    let mut bytes = Vec::new();
+    let mut chars = rest.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => return Some(String::from_utf8_lossy(&bytes).into_owned()),
+            '\\' => {
+                let escaped = chars.next()?;
+                match escaped {
+                    'n' => bytes.push(b'\n'),
+                    't' => bytes.push(b'\t'),
+                    '0'..='7' => {
+                        let mut value = escaped.to_digit(8)?;
+                        for _ in 0..2 {
+                            let next = chars.clone().next().and_then(|ch| ch.to_digit(8));
+                            let Some(digit) = next else { break };
+                            chars.next();
+                            value = value * 8 + digit;
+                        }
+                        bytes.push(value as u8);
+                    }
+                    other => bytes.extend_from_slice(other.to_string().as_bytes()),
+                }
+            }
+            other => bytes.extend_from_slice(other.to_string().as_bytes()),
+        }
+    }
+    None
+}
+
+/// Extracts `address:port` from a sockaddr as formatted by strace. Returns `None` for non-IP
+/// addresses, e.g. Unix domain sockets, which our sandboxes don't restrict.
+fn parse_inet_address(args: &str) -> Option<String> {
+    let port = args
+        .split_once("_port=htons(")?
+        .1
+        .split(')')
+        .next()?
+        .to_owned();
+    if args.contains("sa_family=AF_INET6") {
+        let address = args.split_once("inet_pton(AF_INET6, ")?.1;
+        Some(format!("[{}]:{port}", first_string(address)?))
+    } else if args.contains("sa_family=AF_INET") {
+        let address = args.split_once("inet_addr(")?.1;
+        Some(format!("{}:{port}", first_string(address)?))
+    } else {
+        None
+    }
+}
+
+impl Display for DeniedAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+            AccessKind::Network => "network",
+        };
+        write!(f, "{kind} `{}` ({})", self.target, self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AccessKind;
+    use super::DeniedAccess;
+    use std::path::PathBuf;
+
+    #[track_caller]
+    fn check(line: &str, expected: Option<(AccessKind, &str, &str)>) {
+        let trace = super::Trace {
+            dir: tempfile::tempdir().unwrap(),
+            hidden_dirs: vec![PathBuf::from("/home/user")],
+            visible_dirs: vec![PathBuf::from("/home/user/.rustup")],
+        };
+        let expected = expected.map(|(kind, target, error)| DeniedAccess {
+            kind,
+            target: target.to_owned(),
+            error: error.to_owned(),
+        });
+        assert_eq!(trace.parse_line(line), expected);
+    }
+
+    #[test]
+    fn parse_trace() {
+        check(
+            r#"openat(AT_FDCWD, "/home/user/.ssh/id_rsa", O_RDONLY|O_CLOEXEC) = -1 ENOENT (No such file or directory)"#,
+            Some((AccessKind::Read, "/home/user/.ssh/id_rsa", "ENOENT")),
+        );
+        // Files that don't exist outside of hidden directories are of no interest.
+        check(
+            r#"openat(AT_FDCWD, "/usr/lib/libfoo.so", O_RDONLY|O_CLOEXEC) = -1 ENOENT (No such file or directory)"#,
+            None,
+        );
+        check(
+            r#"stat("/home/user/.rustup/settings.toml", 0x7ffd) = -1 ENOENT (No such file or directory)"#,
+            None,
+        );
+        check(
+            r#"openat(AT_FDCWD, "/src/gen.rs", O_WRONLY|O_CREAT|O_TRUNC|O_CLOEXEC, 0666) = -1 EROFS (Read-only file system)"#,
+            Some((AccessKind::Write, "/src/gen.rs", "EROFS")),
+        );
+        check(
+            r#"mkdir("/src/with \"quotes\"\303\251", 0777) = -1 EACCES (Permission denied)"#,
+            Some((AccessKind::Write, "/src/with \"quotes\"é", "EACCES")),
+        );
+        check(
+            r#"connect(3, {sa_family=AF_INET, sin_port=htons(443), sin_addr=inet_addr("140.82.112.3")}, 16) = -1 ENETUNREACH (Network is unreachable)"#,
+            Some((AccessKind::Network, "140.82.112.3:443", "ENETUNREACH")),
+        );
+        check(
+            r#"connect(3, {sa_family=AF_INET6, sin6_port=htons(80), sin6_flowinfo=htonl(0), inet_pton(AF_INET6, "::1", &sin6_addr), sin6_scope_id=0}, 28) = -1 ECONNREFUSED (Connection refused)"#,
+            Some((AccessKind::Network, "[::1]:80", "ECONNREFUSED")),
+        );
+        check(
+            r#"connect(3, {sa_family=AF_UNIX, sun_path="/var/run/nscd/socket"}, 110) = -1 ENOENT (No such file or directory)"#,
+            None,
+        );
+        check(
+            r#"socket(AF_INET, SOCK_STREAM|SOCK_CLOEXEC, IPPROTO_IP) = -1 EACCES (Permission denied)"#,
+            Some((AccessKind::Network, "AF_INET", "EACCES")),
+        );
+        check("+++ exited with 1 +++", None);
+    }
+}
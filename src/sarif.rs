@@ -0,0 +1,237 @@
+//! Writes problems in SARIF (Static Analysis Results Interchange Format), which is consumed by
+//! GitHub code scanning amongst other tools.
+
+use crate::location::SourceLocation;
+use crate::problem::Problem;
+use crate::problem::Severity;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::Value;
+use std::path::Path;
+
+/// The format in which problems are reported.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    /// Human readable text.
+    #[default]
+    Text,
+    /// SARIF 2.1.0 JSON.
+    Sarif,
+    /// A report of permissions used by each crate and sandbox outcomes. See JSON_REPORT.md.
+    Json,
+}
+
+/// Writes `problems` as a SARIF log to `out`. Paths within `root` are written relative to it, so
+/// that they can be matched against files in the repository.
+pub(crate) fn write(
+    problems: &[(Severity, &Problem)],
+    root: &Path,
+    out: &mut dyn std::io::Write,
+) -> Result<()> {
+    serde_json::to_writer_pretty(&mut *out, &sarif_log(problems, root))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn sarif_log(problems: &[(Severity, &Problem)], root: &Path) -> Value {
+    let mut rules: Vec<(&str, &str)> = Vec::new();
+    let mut results = Vec::new();
+    for &(severity, problem) in problems {
+        let rule = rule(problem);
+        if !rules.contains(&rule) {
+            rules.push(rule);
+        }
+        let level = match severity {
+            Severity::Info => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let message = json!({ "text": problem.to_string() });
+        let locations = source_locations(problem);
+        if locations.is_empty() {
+            results.push(json!({
+                "ruleId": rule.0,
+                "level": level,
+                "message": message,
+            }));
+        }
+        // Each location gets its own result, since tools generally only show the first location of
+        // a result.
+        for location in locations {
+            results.push(json!({
+                "ruleId": rule.0,
+                "level": level,
+                "message": message,
+                "locations": [physical_location(location, root)],
+            }));
+        }
+    }
+    let rules: Vec<Value> = rules
+        .into_iter()
+        .map(|(id, description)| json!({ "id": id, "shortDescription": { "text": description } }))
+        .collect();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "cackle",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/cackle-rs/cackle",
+                    "rules": rules,
+                }
+            },
+            "originalUriBaseIds": {
+                "%SRCROOT%": { "uri": format!("file://{}/", root.display()) }
+            },
+            "results": results,
+        }]
+    })
+}
+
+fn physical_location(location: &SourceLocation, root: &Path) -> Value {
+    let artifact_location = match location.filename().strip_prefix(root) {
+        Ok(relative) => json!({ "uri": relative.to_string_lossy(), "uriBaseId": "%SRCROOT%" }),
+        Err(_) => json!({ "uri": format!("file://{}", location.filename().display()) }),
+    };
+    let mut region = json!({ "startLine": location.line() });
+    if let Some(column) = location.column() {
+        region["startColumn"] = column.into();
+    }
+    json!({
+        "physicalLocation": {
+            "artifactLocation": artifact_location,
+            "region": region,
+        }
+    })
+}
+
+fn source_locations(problem: &Problem) -> Vec<&SourceLocation> {
+    match problem {
+        Problem::DisallowedApiUsage(usages) => usages
+            .usages
+            .iter()
+            .map(|usage| &usage.source_location)
+            .collect(),
+        Problem::OffTreeApiUsage(off_tree) => off_tree
+            .usages
+            .usages
+            .iter()
+            .map(|usage| &usage.source_location)
+            .collect(),
+        Problem::DisallowedUnsafe(unsafe_usage) => unsafe_usage.locations.iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the ID and description of the SARIF rule for `problem`.
+pub(crate) fn rule(problem: &Problem) -> (&'static str, &'static str) {
+    match problem {
+        Problem::Message(..) => ("message", "General problem"),
+        Problem::MissingConfiguration(..) => ("missing-config", "Configuration file not found"),
+        Problem::UsesBuildScript(..) => ("build-script", "Package has a build script"),
+        Problem::DisallowedUnsafe(..) => ("unsafe", "Disallowed unsafe code"),
+        Problem::IsProcMacro(..) => ("proc-macro", "Package is a proc macro"),
+        Problem::DisallowedFfiExport(..) => ("ffi-export", "Package exports unmangled symbols"),
+        Problem::VersionMismatch(..) => (
+            "version-mismatch",
+            "Package version doesn't match configured requirement",
+        ),
+        Problem::DisallowedApiUsage(..) => ("disallowed-api", "Use of disallowed API"),
+        Problem::OffTreeApiUsage(..) => (
+            "off-tree-api",
+            "Use of API via a package that isn't a dependency",
+        ),
+        Problem::ExecutionFailed(..) => ("execution-failed", "Sandboxed binary failed"),
+        Problem::ResourceLimitExceeded(..) => (
+            "resource-limit",
+            "Sandboxed binary exceeded a resource limit",
+        ),
+        Problem::UnexpectedWrites(..) => ("unexpected-writes", "Sandboxed binary wrote files"),
+        Problem::DisallowedBuildInstruction(..) => (
+            "build-instruction",
+            "Build script emitted disallowed instruction",
+        ),
+        Problem::DisallowedLinkLib(..) => ("link-lib", "Build script links native library"),
+        Problem::DisallowedRustcDirective(..) => (
+            "rustc-directive",
+            "Build script sets disallowed cfg or environment variable",
+        ),
+        Problem::UnusedPackageConfig(..) => ("unused-pkg-config", "Unused package configuration"),
+        Problem::UnusedAllowApi(..) => ("unused-allow-api", "Allowed API isn't used"),
+        Problem::SelectSandbox => ("select-sandbox", "No sandbox selected"),
+        Problem::ImportStdApi(..) => ("import-std-api", "Standard library API not imported"),
+        Problem::AvailableApi(..) => ("available-api", "Package exports API definitions"),
+        Problem::PossibleExportedApi(..) => (
+            "possible-exported-api",
+            "Package may export a restricted API",
+        ),
+        Problem::UnusedSandboxConfiguration(..) => {
+            ("unused-sandbox-config", "Unused sandbox configuration")
+        }
+        Problem::NewConfigVersionAvailable(..) => (
+            "new-config-version",
+            "Newer configuration version available",
+        ),
+        Problem::SandboxDegraded(..) => ("sandbox-degraded", "Sandbox protections unavailable"),
+        Problem::AdvisoryWithSensitiveApis(..) => (
+            "advisory",
+            "Package with security advisories is granted sensitive APIs",
+        ),
+        Problem::StaleAttestation(..) => ("stale-attestation", "Attestation no longer holds"),
+        Problem::UndeclaredApis(..) => (
+            "undeclared-apis",
+            "Package uses APIs that it doesn't declare",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::CrateSel;
+    use crate::location::SourceLocation;
+    use crate::problem::Problem;
+    use crate::problem::Severity;
+    use crate::proxy::rpc::UnsafeUsage;
+    use std::path::Path;
+
+    #[test]
+    fn sarif_log() {
+        let unsafe_usage = Problem::DisallowedUnsafe(UnsafeUsage {
+            crate_sel: CrateSel::primary(pkg_id("crab1")),
+            locations: vec![
+                SourceLocation::new(Path::new("/repo/src/lib.rs"), 10, Some(5)),
+                SourceLocation::new(Path::new("/registry/crab1/src/lib.rs"), 20, None),
+            ],
+        });
+        let message = Problem::new("Something went wrong");
+        let log = super::sarif_log(
+            &[
+                (Severity::Error, &unsafe_usage),
+                (Severity::Warning, &message),
+            ],
+            Path::new("/repo"),
+        );
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), 2);
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["ruleId"], "unsafe");
+        assert_eq!(results[0]["level"], "error");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/lib.rs");
+        assert_eq!(location["artifactLocation"]["uriBaseId"], "%SRCROOT%");
+        assert_eq!(location["region"]["startLine"], 10);
+        assert_eq!(location["region"]["startColumn"], 5);
+        let location = &results[1]["locations"][0]["physicalLocation"];
+        assert_eq!(
+            location["artifactLocation"]["uri"],
+            "file:///registry/crab1/src/lib.rs"
+        );
+        assert!(location["region"].get("startColumn").is_none());
+        assert_eq!(results[2]["level"], "warning");
+        assert!(results[2].get("locations").is_none());
+    }
+}
@@ -2,6 +2,9 @@ use crate::config::permissions::PermSel;
 use crate::config::Config;
 use crate::config::PackageConfig;
 use crate::crate_index::CrateIndex;
+use crate::report::table::TableSort;
+use anyhow::bail;
+use anyhow::Result;
 use clap::{Parser, ValueEnum};
 use fxhash::FxHashMap;
 use serde_json::Value;
@@ -22,6 +25,11 @@ pub enum OutputFormat {
     Human,
     /// Print output in a machine-readable form with minimal extra context.
     Json,
+    /// Comma separated values with a row per crate and API: crate,api,status,call_sites. Only
+    /// supported with `--table`.
+    Csv,
+    /// A markdown table. Only supported with `--table`.
+    Md,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -54,7 +62,16 @@ pub(crate) struct SummaryOptions {
     /// The format of the output
     #[clap(long, value_enum, action)]
     #[clap(default_value_t = OutputFormat::Human)]
-    output_format: OutputFormat,
+    pub(crate) output_format: OutputFormat,
+
+    /// Build and analyse, then print a table showing, for each crate, which APIs it uses and how
+    /// many call sites use each, as well as which granted APIs are unused.
+    #[clap(long)]
+    pub(crate) table: bool,
+
+    /// How to sort the rows of the table.
+    #[clap(long, value_enum, default_value_t = TableSort::Crate, requires = "table")]
+    pub(crate) sort: TableSort,
 }
 
 #[derive(serde::Serialize)]
@@ -88,6 +105,9 @@ impl Summary {
                         if pkg_config.allow_proc_macro {
                             permissions.push(format!("proc_macro{suffix}"));
                         }
+                        if pkg_config.allow_ffi_export {
+                            permissions.push(format!("ffi-export{suffix}"));
+                        }
                         if pkg_config.allow_unsafe {
                             permissions.push(format!("unsafe{suffix}"));
                         }
@@ -107,7 +127,10 @@ impl Summary {
         Self { packages }
     }
 
-    pub(crate) fn print(&self, options: &SummaryOptions) {
+    pub(crate) fn print(&self, options: &SummaryOptions) -> Result<()> {
+        if matches!(options.output_format, OutputFormat::Csv | OutputFormat::Md) {
+            bail!("`--output-format csv` and `--output-format md` require `--table`");
+        }
         let options = options.with_defaults();
         let mut json_map = HashMap::new();
 
@@ -155,6 +178,7 @@ impl Summary {
         if !json_map.is_empty() {
             println!("{}", serde_json::to_string_pretty(&json_map).unwrap());
         }
+        Ok(())
     }
 
     fn print_by_crate(&self) {
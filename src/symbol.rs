@@ -4,6 +4,7 @@ use crate::demangle::DemangleToken;
 use crate::names::NamesIterator;
 use anyhow::Result;
 use rustc_demangle::demangle;
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::str::Utf8Error;
@@ -16,7 +17,7 @@ pub(crate) struct Symbol<'data> {
 }
 
 impl<'data> Symbol<'data> {
-    pub(crate) fn borrowed(data: &[u8]) -> Symbol {
+    pub(crate) fn borrowed(data: &[u8]) -> Symbol<'_> {
         Symbol {
             bytes: Bytes::Borrowed(data),
         }
@@ -40,34 +41,97 @@ impl<'data> Symbol<'data> {
     }
 
     /// Splits the name of this symbol into names. See `crate::names::split_names` for details.
-    pub(crate) fn names(&self) -> Result<NamesIterator<DemangleIterator>> {
-        Ok(NamesIterator::new(DemangleIterator::new(self.to_str()?)))
+    /// `buffer` is used if the symbol needs to be demangled before it can be split. Symbols that
+    /// aren't mangled, e.g. `dlopen`, produce a single name with a single part.
+    pub(crate) fn names<'a>(
+        &'a self,
+        buffer: &'a mut String,
+    ) -> Result<NamesIterator<'a, DemangleIterator<'a>>> {
+        if let Some(name) = self.unmangled_name() {
+            return Ok(NamesIterator::new(DemangleIterator::from_demangled(name)));
+        }
+        Ok(NamesIterator::new(self.tokens(buffer)?))
+    }
+
+    /// If this symbol isn't mangled and looks like a C identifier, returns it without any symbol
+    /// version suffix. e.g. `dlopen@GLIBC_2.34` would give `dlopen`. WebAssembly imports are named
+    /// `module::field`, e.g. `wasi_snapshot_preview1::fd_write`, so we also accept identifiers
+    /// separated by `::`. The names we give to literals naming devices, e.g. `__path::dev::urandom`,
+    /// are formed the same way.
+    fn unmangled_name(&self) -> Option<&str> {
+        if self.is_mangled() {
+            return None;
+        }
+        let name = self.to_str().ok()?.split('@').next()?;
+        name.split("::")
+            .all(|part| {
+                let mut chars = part.chars();
+                chars
+                    .next()
+                    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            })
+            .then_some(name)
+    }
+
+    /// Returns an iterator over the tokens of this symbol. Legacy mangled symbols are tokenised in
+    /// place. Symbols using the v0 mangling scheme are first demangled into `buffer`.
+    fn tokens<'a>(&'a self, buffer: &'a mut String) -> Result<DemangleIterator<'a>, Utf8Error> {
+        let data = self.to_str()?;
+        if let Some(demangled) = crate::demangle::demangle_v0(data) {
+            *buffer = demangled;
+            return Ok(DemangleIterator::from_demangled(buffer));
+        }
+        Ok(DemangleIterator::new(data))
     }
 
     pub(crate) fn len(&self) -> usize {
         self.data().len()
     }
 
-    pub(crate) fn module_name(&self) -> Option<&str> {
-        let mut it = crate::demangle::DemangleIterator::new(self.to_str().ok()?);
-        if let (Some(DemangleToken::Text(..)), Some(DemangleToken::Text(text))) =
-            (it.next(), it.next())
-        {
-            Some(text)
-        } else {
-            None
-        }
+    pub(crate) fn module_name(&self) -> Option<Cow<'_, str>> {
+        self.find_in_tokens(|mut it| {
+            if let (Some(DemangleToken::Text(..)), Some(DemangleToken::Text(text))) =
+                (it.next(), it.next())
+            {
+                Some(text)
+            } else {
+                None
+            }
+        })
     }
 
-    pub(crate) fn crate_name(&self) -> Option<&str> {
-        let data_str = self.to_str().ok()?;
-        if let Some(DemangleToken::Text(text)) =
-            crate::demangle::DemangleIterator::new(data_str).next()
-        {
-            Some(text)
-        } else {
-            None
+    pub(crate) fn crate_name(&self) -> Option<Cow<'_, str>> {
+        self.find_in_tokens(|mut it| {
+            if let Some(DemangleToken::Text(text)) = it.next() {
+                Some(text)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Passes our tokens to `find` and returns whatever text it finds. The text is borrowed from
+    /// our data unless we needed to demangle in order to get tokens.
+    fn find_in_tokens(
+        &self,
+        find: impl for<'a> Fn(DemangleIterator<'a>) -> Option<&'a str>,
+    ) -> Option<Cow<'_, str>> {
+        let data = self.to_str().ok()?;
+        if let Some(demangled) = crate::demangle::demangle_v0(data) {
+            return find(DemangleIterator::from_demangled(&demangled))
+                .map(|text| Cow::Owned(text.to_owned()));
         }
+        find(DemangleIterator::new(data)).map(Cow::Borrowed)
+    }
+
+    /// Returns whether this symbol uses either the legacy or the v0 Rust mangling scheme.
+    pub(crate) fn is_mangled(&self) -> bool {
+        // On some platforms, e.g. MacOS, symbols have an extra leading underscore.
+        let data = self.data();
+        [data, data.strip_prefix(b"_").unwrap_or_default()]
+            .iter()
+            .any(|d| d.starts_with(b"_ZN") || d.starts_with(b"_R"))
     }
 
     /// Returns whether this symbol is one that we should "look through". Such symbols are ones
@@ -76,10 +140,10 @@ impl<'data> Symbol<'data> {
     /// foo->core::ops::function::Fn->std::env::var, then we'll consider `foo` as referencing
     /// `std::env::var`.
     pub(crate) fn is_look_through(&self) -> bool {
-        let Ok(data) = self.to_str() else {
+        let mut buffer = String::new();
+        let Ok(mut tokens) = self.tokens(&mut buffer) else {
             return false;
         };
-        let mut tokens = DemangleIterator::new(data);
         ["core", "ops", "function"]
             .iter()
             .all(|p| tokens.next() == Some(DemangleToken::Text(p)))
@@ -132,7 +196,7 @@ mod tests {
     fn test_names() {
         let symbol = Symbol::borrowed(b"_ZN4core3ptr85drop_in_place$LT$std..rt..lang_start$LT$$LP$$RP$$GT$..$u7b$$u7b$closure$u7d$$u7d$$GT$17h0bb7e9fe967fc41cE");
         assert_eq!(
-            get_name_vecs(symbol.names().unwrap()),
+            get_name_vecs(symbol.names(&mut String::new()).unwrap()),
             vec![
                 vec!["core", "ptr", "drop_in_place"],
                 vec!["std", "rt", "lang_start"],
@@ -143,7 +207,7 @@ mod tests {
         b"_ZN58_$LT$alloc..string..String$u20$as$u20$core..fmt..Debug$GT$3fmt17h3b29bd412ff2951fE",
     );
         assert_eq!(
-            get_name_vecs(symbol.names().unwrap()),
+            get_name_vecs(symbol.names(&mut String::new()).unwrap()),
             vec![
                 vec!["alloc", "string", "String"],
                 vec!["core", "fmt", "Debug", "fmt"]
@@ -158,7 +222,7 @@ mod tests {
     fn test_names_literal_number() {
         let symbol = Symbol::borrowed(b"_ZN104_$LT$proc_macro2..Span$u20$as$u20$syn..span..IntoSpans$LT$$u5b$proc_macro2..Span$u3b$$u20$1$u5d$$GT$$GT$10into_spans17h8cc941d826bfc6f7E");
         assert_eq!(
-            get_name_vecs(symbol.names().unwrap()),
+            get_name_vecs(symbol.names(&mut String::new()).unwrap()),
             vec![
                 vec!["proc_macro2", "Span"],
                 vec!["syn", "span", "IntoSpans", "into_spans"],
@@ -167,6 +231,70 @@ mod tests {
         );
     }
 
+    #[track_caller]
+    fn check_names(mangled: &str, expected: &[&[&str]]) {
+        let symbol = Symbol::borrowed(mangled.as_bytes());
+        let mut buffer = String::new();
+        let actual = get_name_vecs(symbol.names(&mut buffer).unwrap());
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_names_unmangled() {
+        check_names("dlopen", &[&["dlopen"]]);
+        check_names("dlsym@GLIBC_2.34", &[&["dlsym"]]);
+        check_names("__imp_LoadLibraryW", &[&["__imp_LoadLibraryW"]]);
+        check_names(".Lanon.123", &[]);
+        check_names(
+            "wasi_snapshot_preview1::fd_write",
+            &[&["wasi_snapshot_preview1", "fd_write"]],
+        );
+        check_names("__path::dev::urandom", &[&["__path", "dev", "urandom"]]);
+    }
+
+    #[test]
+    fn test_names_v0() {
+        check_names("_RNvCsh537bOAIRKx_3lib4uses", &[&["lib", "uses"]]);
+        check_names(
+            "_RINvNtCsjrHSEGnQ3l9_3std2fs4readReECsh537bOAIRKx_3lib",
+            &[&["std", "fs", "read"], &["str"]],
+        );
+    }
+
+    #[test]
+    fn test_names_v0_closure() {
+        check_names("_RNCNvCsh537bOAIRKx_3lib4uses0B3_", &[&["lib", "uses"]]);
+    }
+
+    #[test]
+    fn test_names_v0_trait_impl() {
+        check_names(
+            "_RNvXs1p_NtCsjrHSEGnQ3l9_3std4patheINtNtCsgEmfK2I1SDS_4core7convert5AsRefNtB6_4PathE6as_refCsh537bOAIRKx_3lib",
+            &[
+                &["str"],
+                &["core", "convert", "AsRef", "as_ref"],
+                &["std", "path", "Path"],
+            ],
+        );
+        let symbol = Symbol::borrowed(b"_RNvXs1_NtNtNtCsjrHSEGnQ3l9_3std2io5error14repr_bitpackedNtB5_4ReprNtNtNtCsgEmfK2I1SDS_4core3ops4drop4Drop4dropCsh537bOAIRKx_3lib");
+        assert_eq!(
+            symbol.to_string(),
+            "<std::io::error::repr_bitpacked::Repr as core::ops::drop::Drop>::drop"
+        );
+        assert_eq!(symbol.crate_name(), None);
+    }
+
+    #[test]
+    fn test_names_v0_inherent_impl_with_const_generic() {
+        check_names(
+            "_RNvMCsh537bOAIRKx_3libINtB2_3FooKj3_E3getB2_",
+            &[&["lib", "Foo"], &["get"]],
+        );
+        let symbol = Symbol::borrowed(b"_RNvMCsh537bOAIRKx_3libINtB2_3FooKj3_E3getB2_");
+        assert_eq!(symbol.crate_name().as_deref(), Some("lib"));
+        assert_eq!(symbol.module_name().as_deref(), Some("Foo"));
+    }
+
     #[test]
     fn test_display() {
         let symbol = Symbol::borrowed(b"_ZN4core3ptr85drop_in_place$LT$std..rt..lang_start$LT$$LP$$RP$$GT$..$u7b$$u7b$closure$u7d$$u7d$$GT$17h0bb7e9fe967fc41cE");
@@ -176,6 +304,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_is_mangled() {
+        assert!(Symbol::borrowed(b"_ZN4core3ptr13drop_in_place17h0bb7e9fe967fc41cE").is_mangled());
+        assert!(Symbol::borrowed(b"__ZN4core3ptr13drop_in_place17h0bb7e9fe967fc41cE").is_mangled());
+        assert!(Symbol::borrowed(b"_RNvCs1234_7mycrate3foo").is_mangled());
+        assert!(!Symbol::borrowed(b"main").is_mangled());
+        assert!(!Symbol::borrowed(b"__rg_alloc").is_mangled());
+    }
+
     #[test]
     fn comparison() {
         fn hash(sym: &Symbol) -> u64 {
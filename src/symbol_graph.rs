@@ -1,6 +1,8 @@
-//! This module builds a graph of relationships between symbols and linker sections. Provided code
-//! was compiled with one symbol per section, which it should have been, there should be a 1:1
-//! relationship between symbols and sections.
+//! This module builds a graph of relationships between symbols and linker sections. Code is
+//! normally compiled with one symbol per section, in which case there's a 1:1 relationship between
+//! symbols and sections. Sections can however contain multiple symbols, e.g. if code was compiled
+//! without function sections, so we attribute each relocation to the symbol whose address range
+//! contains it.
 //!
 //! We also parse the Dwarf debug information to determine what source file each linker section came
 //! from.
@@ -8,6 +10,8 @@
 use self::backtrace::Backtracer;
 use self::dwarf::SymbolDebugInfo;
 use self::object_file_path::ObjectFilePath;
+use self::reachability::ReferenceGraph;
+use crate::checker::proc_macro_attribution::ContainingFunction;
 use crate::checker::ApiUsage;
 use crate::checker::BinLocation;
 use crate::checker::Checker;
@@ -16,13 +20,17 @@ use crate::config::ApiConfig;
 use crate::config::ApiName;
 use crate::crate_index::CrateSel;
 use crate::crate_index::PackageId;
+use crate::graph::Graph;
+use crate::graph::GraphNode;
 use crate::link_info::LinkInfo;
+use crate::link_info::Platform;
 use crate::location::SourceLocation;
 use crate::names::DebugName;
 use crate::names::Name;
 use crate::names::SymbolAndName;
 use crate::names::SymbolOrDebugName;
 use crate::problem::ApiUsages;
+use crate::problem::FfiExport;
 use crate::problem::PossibleExportedApi;
 use crate::problem::ProblemList;
 use crate::symbol::Symbol;
@@ -35,16 +43,20 @@ use fxhash::FxHashMap;
 use fxhash::FxHashSet;
 use gimli::Dwarf;
 use gimli::EndianSlice;
-use gimli::LittleEndian;
+use gimli::RunTimeEndian;
 use log::debug;
 use log::trace;
 use object::Object;
 use object::ObjectSection;
 use object::ObjectSymbol;
+use object::RelocationKind;
 use object::RelocationTarget;
 use object::SectionIndex;
+use object::SectionKind;
+use object::SymbolKind;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fmt::Display;
 use std::fs::File;
 use std::io::Read;
@@ -54,8 +66,16 @@ use std::sync::Arc;
 use std::time::Instant;
 
 pub(crate) mod backtrace;
+mod bitcode;
 mod dwarf;
 pub(crate) mod object_file_path;
+mod reachability;
+mod thin_archive;
+mod wasm;
+
+/// The namespace of the names that we give to string literals naming devices. e.g. a reference to
+/// the literal `/dev/urandom` is treated as a reference to `__path::dev::urandom`.
+const DEVICE_PATH_NAMESPACE: &str = "__path::dev";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Filetype {
@@ -67,6 +87,16 @@ struct ApiUsageCollector<'input, 'backtracer> {
     outputs: ScanOutputs,
     backtracer: Option<&'backtracer mut Backtracer>,
 
+    /// References between symbols in the binary. Only populated if we're pruning API usages that
+    /// aren't reachable from the binary's entry points or reporting call chains.
+    reference_graph: Option<ReferenceGraph>,
+    prune_unreachable: bool,
+    report_call_chains: bool,
+
+    /// The graph of references that we'll report to the user. Only populated if the user asked for
+    /// it.
+    graph: Option<Graph>,
+
     bin: BinInfo<'input>,
     debug_enabled: bool,
     new_api_usages: FxHashMap<ApiUsageGroupKey, Vec<SingleApiUsage>>,
@@ -84,6 +114,7 @@ struct SingleApiUsage {
 struct BinInfo<'input> {
     filename: Arc<Path>,
     crate_sel: CrateSel,
+    platform: Platform,
     symbol_addresses: FxHashMap<Symbol<'input>, u64>,
     /// Symbols that we've already determined have no APIs. This is an optimisation that lets us
     /// skip these symbols when we see them again.
@@ -103,6 +134,8 @@ pub(crate) struct ScanOutputs {
 
     possible_exported_apis: Vec<PossibleExportedApi>,
 
+    ffi_exports: Vec<FfiExport>,
+
     /// The API definitions used to produce these outputs. Used to determine if we need to recompute
     /// API usages.
     pub(crate) apis: BTreeMap<ApiName, ApiConfig>,
@@ -116,12 +149,13 @@ struct ObjectIndex<'obj, 'data> {
 
 #[derive(Clone, Default)]
 struct SectionInfo<'data> {
-    first_symbol: Option<SymbolInfo<'data>>,
+    /// The symbols defined in the section, sorted by offset. Where multiple symbols have the same
+    /// offset, only the first is retained.
+    symbols: Vec<SymbolInfo<'data>>,
 }
 
 #[derive(Clone)]
 struct SymbolInfo<'data> {
-    /// The first symbol in the section.
     symbol: Symbol<'data>,
 
     /// The offset of the symbol.
@@ -142,7 +176,7 @@ pub(crate) fn scan_objects(
     // Backtraces require that we keep a bunch of stuff around, which uses up memory, so we only do
     // it if the UI is active and if we haven't explicitly disabled backtraces.
     let backtraces = !checker.args.no_backtrace && !checker.args.no_ui;
-    let mut backtracer = backtraces.then(|| Backtracer::new(checker.sysroot.clone()));
+    let mut backtracer = backtraces.then(|| Backtracer::new(checker.rust_std.sysroot().clone()));
     let outputs =
         scan_object_with_bin_bytes(&file_bytes, checker, backtracer.as_mut(), link_info, paths)?;
 
@@ -160,10 +194,11 @@ fn scan_object_with_bin_bytes(
     paths: &[PathBuf],
 ) -> Result<ScanOutputs> {
     let start = Instant::now();
-    let obj = object::File::parse(bin_file_bytes.as_slice())
+    let bin = BinFile::parse(bin_file_bytes.as_slice())
         .with_context(|| format!("Failed to parse {}", link_info.output_file.display()))?;
-    let owned_dwarf = Dwarf::load(|id| load_section(&obj, id))?;
-    let dwarf = owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, gimli::LittleEndian));
+    let owned_dwarf = Dwarf::load(|id| bin.load_section(id))?;
+    let endian = bin.endianness();
+    let dwarf = owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, endian));
     let start = checker.timings.add_timing(start, "Parse bin");
     let debug_artifacts =
         dwarf::DebugArtifacts::from_dwarf(&dwarf, checker).with_context(|| {
@@ -185,12 +220,24 @@ fn scan_object_with_bin_bytes(
         .keys()
         .map(|symbol| (symbol.clone(), false))
         .collect();
+    let prune_unreachable =
+        checker.config.raw.common.prune_unreachable && !checker.args.no_prune_unreachable;
+    let report_call_chains = checker.config.raw.common.report_call_chains;
     let mut collector = ApiUsageCollector {
         outputs: Default::default(),
         backtracer,
+        reference_graph: (prune_unreachable || report_call_chains).then(|| {
+            let mut graph = ReferenceGraph::default();
+            bin.add_roots(&mut graph);
+            graph
+        }),
+        prune_unreachable,
+        report_call_chains,
+        graph: checker.graph.is_some().then(Graph::default),
         bin: BinInfo {
             filename: link_info.output_file.clone(),
             crate_sel: link_info.crate_sel.clone(),
+            platform: link_info.platform,
             symbol_addresses: Default::default(),
             symbol_debug_info: debug_artifacts.symbol_debug_info,
             symbol_has_no_apis: no_api_symbol_hashes,
@@ -198,7 +245,9 @@ fn scan_object_with_bin_bytes(
         debug_enabled: checker.args.debug,
         new_api_usages: FxHashMap::default(),
     };
-    collector.bin.load_symbols(&obj)?;
+    collector
+        .bin
+        .load_symbols(&bin, &debug_artifacts.function_addresses)?;
     let start = checker.timings.add_timing(start, "Load symbols from bin");
     for f in debug_artifacts.inlined_functions {
         let from = Node {
@@ -226,13 +275,22 @@ fn scan_object_with_bin_bytes(
         .timings
         .add_timing(start, "Process inlined references");
     collector.find_possible_exports(checker);
+    collector.find_ffi_exports(&bin, &ctx, checker);
     let start = checker.timings.add_timing(start, "Find possible exports");
     for path in paths {
         collector
             .process_file(path, checker, &ctx)
             .with_context(|| format!("Failed to process `{}`", path.display()))?;
     }
-    collector.emit_shortest_api_usages();
+    collector.emit_shortest_api_usages(checker)?;
+    if let Some(max_usages) = checker.config.raw.common.max_usages_per_problem {
+        for api_usages in collector.outputs.api_usages.values_mut() {
+            api_usages.aggregate(max_usages);
+        }
+    }
+    if let (Some(graph), Some(checker_graph)) = (collector.graph.take(), checker.graph.as_mut()) {
+        checker_graph.merge(graph);
+    }
     checker.timings.add_timing(start, "Process object files");
     Ok(collector.outputs)
 }
@@ -244,6 +302,7 @@ impl ScanOutputs {
             checker.api_used(api_usages, &mut problems)?;
         }
         checker.possible_exported_api_problems(&self.possible_exported_apis, &mut problems);
+        checker.ffi_export_problems(&self.ffi_exports, &mut problems);
 
         Ok(problems)
     }
@@ -254,10 +313,24 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
         &mut self,
         filename: &Path,
         checker: &Checker,
-        ctx: &addr2line::Context<EndianSlice<'input, LittleEndian>>,
+        ctx: &addr2line::Context<EndianSlice<'input, RunTimeEndian>>,
     ) -> Result<()> {
         let mut buffer = Vec::new();
         match Filetype::from_filename(filename) {
+            Filetype::Archive if thin_archive::is_thin_archive(filename)? => {
+                for member_path in thin_archive::member_paths(filename)? {
+                    let file_bytes = std::fs::read(&member_path).with_context(|| {
+                        format!(
+                            "Failed to read `{}`, which is referenced by thin archive `{}`",
+                            member_path.display(),
+                            filename.display()
+                        )
+                    })?;
+                    let object_file_path = ObjectFilePath::in_thin_archive(filename, &member_path);
+                    self.process_object_file_bytes(&object_file_path, &file_bytes, checker, ctx)
+                        .with_context(|| format!("Failed to process {object_file_path}"))?;
+                }
+            }
             Filetype::Archive => {
                 let mut archive = Archive::new(File::open(filename)?);
                 while let Some(entry_result) = archive.next_entry() {
@@ -289,34 +362,35 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
         filename: &ObjectFilePath,
         file_bytes: &[u8],
         checker: &Checker,
-        ctx: &addr2line::Context<EndianSlice<'input, LittleEndian>>,
+        ctx: &addr2line::Context<EndianSlice<'input, RunTimeEndian>>,
     ) -> Result<()> {
         debug!("Processing object file {}", filename);
 
+        if bitcode::is_bitcode(file_bytes) {
+            if !checker.config.raw.common.scan_lto_bitcode {
+                bail!(
+                    "Object file contains LLVM bitcode rather than machine code. This happens \
+                     when building with linker-plugin LTO. Either disable LTO for the profile \
+                     being checked or set `common.scan_lto_bitcode = true`"
+                );
+            }
+            return self.process_bitcode(filename, file_bytes, checker);
+        }
+        if wasm::is_wasm(file_bytes) {
+            return self.process_wasm_object(filename, file_bytes, checker, ctx);
+        }
+
         let obj = object::File::parse(file_bytes).context("Failed to parse object file")?;
         let object_index = ObjectIndex::new(&obj);
         for section in obj.sections() {
             let section_name = section.name().unwrap_or("");
-            let Some(first_sym_info) = object_index.first_symbol(&section) else {
+            if object_index.section_symbols(&section).is_empty() {
+                if is_initialiser_section(section_name) {
+                    self.add_roots(&object_index, &section)?;
+                }
                 debug!("Skipping section `{section_name}` due to lack of debug info");
                 continue;
-            };
-            let Some(symbol_address_in_bin) = self
-                .bin
-                .symbol_addresses
-                .get(&first_sym_info.symbol)
-                .cloned()
-            else {
-                debug!(
-                    "Skipping section `{}` because symbol `{}` doesn't appear in exe/so",
-                    section_name, first_sym_info.symbol
-                );
-                continue;
-            };
-            let Some(debug_info) = self.bin.symbol_debug_info.get(&first_sym_info.symbol) else {
-                continue;
-            };
-            let fallback_source_location = debug_info.source_location();
+            }
             let debug_data = self.debug_enabled.then(|| {
                 UsageDebugData::Relocation(RelocationDebugData {
                     bin_path: self.bin.filename.clone(),
@@ -326,65 +400,268 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
             });
 
             for (offset, rel) in section.relocations() {
-                let mut target_symbols = Vec::new();
                 let rel = &rel;
+                let Some(sym_info) = object_index.symbol_containing(&section, offset) else {
+                    trace!("Skipping relocation at {offset} in `{section_name}` before any symbol");
+                    continue;
+                };
+                let Some(symbol_address_in_bin) =
+                    self.bin.symbol_addresses.get(&sym_info.symbol).cloned()
+                else {
+                    trace!(
+                        "Skipping relocation in `{}` because symbol `{}` doesn't appear in exe/so",
+                        section_name,
+                        sym_info.symbol
+                    );
+                    continue;
+                };
+                let mut target_symbols = Vec::new();
                 object_index.add_target_symbols(
                     rel,
                     &mut target_symbols,
                     &mut FxHashSet::default(),
                     &self.bin.symbol_addresses,
                 )?;
-
-                // Use debug info to determine the function that the reference originated from.
-                let offset_in_bin = symbol_address_in_bin + offset - first_sym_info.offset;
-                let mut frames = ctx.find_frames(offset_in_bin).skip_all_loads()?;
-                let (frame_fn_name, frame_location) = frames
-                    .next()?
-                    .map(|frame| (frame.function, frame.location))
-                    .unwrap_or((None, None));
-                let location_fetcher = LocationFetcher::FrameWithFallback {
-                    frame_location,
-                    fallback: &fallback_source_location,
-                };
-                let frame_symbol = frame_fn_name
-                    .as_ref()
-                    .map(|fn_name| Symbol::borrowed(&fn_name.name));
                 let bin_location = BinLocation {
-                    address: offset_in_bin,
+                    address: symbol_address_in_bin + offset - sym_info.offset,
                     symbol_start: symbol_address_in_bin,
                 };
+                self.process_symbol_references(
+                    &sym_info.symbol,
+                    bin_location,
+                    target_symbols,
+                    checker,
+                    ctx,
+                    debug_data.as_ref(),
+                )?;
+            }
+        }
+        Ok(())
+    }
 
-                let from_symbol = frame_symbol.as_ref().unwrap_or(&first_sym_info.symbol);
-                let from = Node {
-                    names: self.bin.get_symbol_and_name(from_symbol),
-                    location_fetcher,
-                };
-                let mut non_inlined_from = None;
-                if frame_symbol.as_ref() != Some(&first_sym_info.symbol) {
-                    non_inlined_from = Some(Node {
-                        names: self.bin.get_symbol_and_name(&first_sym_info.symbol),
-                        location_fetcher: LocationFetcher::AlreadyResolved(
-                            &fallback_source_location,
-                        ),
-                    });
-                }
-                for target_symbol in target_symbols {
-                    if let Some(target_address) = self.bin.symbol_addresses.get(&target_symbol) {
-                        if let Some(b) = self.backtracer.as_mut() {
-                            b.add_reference(bin_location, *target_address);
-                        }
+    /// Processes references from `from_symbol`, which is where `bin_location` is, to each of
+    /// `target_symbols`.
+    fn process_symbol_references(
+        &mut self,
+        from_symbol: &Symbol,
+        bin_location: BinLocation,
+        target_symbols: Vec<Symbol>,
+        checker: &Checker,
+        ctx: &addr2line::Context<EndianSlice<'input, RunTimeEndian>>,
+        debug_data: Option<&UsageDebugData>,
+    ) -> Result<()> {
+        let symbol_address_in_bin = bin_location.symbol_start;
+        let Some(debug_info) = self.bin.symbol_debug_info.get(from_symbol) else {
+            // Symbols without debug info, e.g. vtables, can't be the source of API usages, but they
+            // may still make other code reachable.
+            if let Some(graph) = self.reference_graph.as_mut() {
+                for symbol in &target_symbols {
+                    if let Some(address) = self.bin.symbol_addresses.get(symbol) {
+                        graph.add_reference(symbol_address_in_bin, *address);
                     }
-                    let target = self.bin.get_symbol_and_name(&target_symbol);
-                    self.process_reference(
-                        bin_location,
-                        non_inlined_from.as_ref(),
-                        &from,
-                        &target,
-                        checker,
-                        debug_data.as_ref(),
-                    )?;
                 }
             }
+            return Ok(());
+        };
+        let fallback_source_location = debug_info.source_location();
+
+        // Use debug info to determine the function that the reference originated from.
+        let mut frames = ctx.find_frames(bin_location.address).skip_all_loads()?;
+        let (frame_fn_name, frame_location) = frames
+            .next()?
+            .map(|frame| (frame.function, frame.location))
+            .unwrap_or((None, None));
+        let location_fetcher = LocationFetcher::FrameWithFallback {
+            frame_location,
+            fallback: &fallback_source_location,
+        };
+        let frame_symbol = frame_fn_name
+            .as_ref()
+            .map(|fn_name| Symbol::borrowed(&fn_name.name));
+
+        let from = Node {
+            names: self
+                .bin
+                .get_symbol_and_name(frame_symbol.as_ref().unwrap_or(from_symbol)),
+            location_fetcher,
+        };
+        let mut non_inlined_from = None;
+        if frame_symbol.as_ref() != Some(from_symbol) {
+            non_inlined_from = Some(Node {
+                names: self.bin.get_symbol_and_name(from_symbol),
+                location_fetcher: LocationFetcher::AlreadyResolved(&fallback_source_location),
+            });
+        }
+        for target_symbol in target_symbols {
+            if let Some(target_address) = self.bin.symbol_addresses.get(&target_symbol) {
+                if let Some(b) = self.backtracer.as_mut() {
+                    b.add_reference(bin_location, *target_address);
+                }
+                if let Some(graph) = self.reference_graph.as_mut() {
+                    graph.add_reference(symbol_address_in_bin, *target_address);
+                }
+            }
+            let target = self.bin.get_symbol_and_name(&target_symbol);
+            if self.graph.is_some() {
+                self.add_to_graph(&from, &target, checker)?;
+            }
+            self.process_reference(
+                bin_location,
+                non_inlined_from.as_ref(),
+                &from,
+                &target,
+                checker,
+                debug_data,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Processes a WebAssembly object file. Functions are located in the binary via their symbol,
+    /// then each reference is located by its offset within the function, which linking doesn't
+    /// change.
+    fn process_wasm_object(
+        &mut self,
+        filename: &ObjectFilePath,
+        file_bytes: &[u8],
+        checker: &Checker,
+        ctx: &addr2line::Context<EndianSlice<'input, RunTimeEndian>>,
+    ) -> Result<()> {
+        let module = wasm::WasmModule::parse(file_bytes)?;
+        let debug_data = self.debug_enabled.then(|| {
+            UsageDebugData::Relocation(RelocationDebugData {
+                bin_path: self.bin.filename.clone(),
+                object_file_path: filename.clone(),
+                section_name: "reloc.CODE".to_owned(),
+            })
+        });
+        for reference in module.references()? {
+            let from_symbol = Symbol::borrowed(reference.from.as_bytes());
+            let Some(symbol_address_in_bin) = self.bin.symbol_addresses.get(&from_symbol).cloned()
+            else {
+                trace!(
+                    "Skipping reference from `{from_symbol}` because it doesn't appear in the binary"
+                );
+                continue;
+            };
+            let bin_location = BinLocation {
+                address: symbol_address_in_bin + reference.offset,
+                symbol_start: symbol_address_in_bin,
+            };
+            let target_symbol = Symbol::borrowed(reference.target.as_bytes());
+            self.process_symbol_references(
+                &from_symbol,
+                bin_location,
+                vec![target_symbol],
+                checker,
+                ctx,
+                debug_data.as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Processes an object file that contains LLVM bitcode. Bitcode has no relocations, so we can't
+    /// tell which function references which symbol. Instead, we treat all symbols referenced by the
+    /// module as being referenced from the first defined symbol that made it into the binary with
+    /// debug info. This means that usages are attributed to the right package, but the function
+    /// reported as the source of the usage may be wrong.
+    fn process_bitcode(
+        &mut self,
+        filename: &ObjectFilePath,
+        file_bytes: &[u8],
+        checker: &Checker,
+    ) -> Result<()> {
+        let symbols =
+            bitcode::symbols(file_bytes).context("Failed to read bitcode symbol table")?;
+        let Some((from_symbol, from_address, location)) = symbols
+            .iter()
+            .filter(|bitcode_symbol| !bitcode_symbol.is_undefined)
+            .find_map(|bitcode_symbol| {
+                let symbol = Symbol::borrowed(bitcode_symbol.name);
+                let address = *self.bin.symbol_addresses.get(&symbol)?;
+                let location = self.bin.symbol_debug_info.get(&symbol)?.source_location();
+                Some((symbol, address, location))
+            })
+        else {
+            debug!("Skipping bitcode `{filename}` since none of its symbols have debug info");
+            return Ok(());
+        };
+        let debug_data = self.debug_enabled.then(|| {
+            UsageDebugData::Bitcode(BitcodeDebugData {
+                bin_path: self.bin.filename.clone(),
+                object_file_path: filename.clone(),
+            })
+        });
+        let from = Node {
+            names: self.bin.get_symbol_and_name(&from_symbol),
+            location_fetcher: LocationFetcher::AlreadyResolved(&location),
+        };
+        let bin_location = BinLocation {
+            address: from_address,
+            symbol_start: from_address,
+        };
+        for bitcode_symbol in symbols.iter().filter(|s| s.is_undefined) {
+            let target_symbol = Symbol::borrowed(bitcode_symbol.name);
+            if let Some(target_address) = self.bin.symbol_addresses.get(&target_symbol) {
+                if let Some(b) = self.backtracer.as_mut() {
+                    b.add_reference(bin_location, *target_address);
+                }
+                if let Some(graph) = self.reference_graph.as_mut() {
+                    graph.add_reference(from_address, *target_address);
+                }
+            }
+            let target = self.bin.get_symbol_and_name(&target_symbol);
+            if self.graph.is_some() {
+                self.add_to_graph(&from, &target, checker)?;
+            }
+            self.process_reference(
+                bin_location,
+                None,
+                &from,
+                &target,
+                checker,
+                debug_data.as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records a reference from `from` to `target` in the graph that we'll show to the user.
+    fn add_to_graph(
+        &mut self,
+        from: &Node,
+        target: &SymbolAndName,
+        checker: &Checker,
+    ) -> Result<()> {
+        let from_location = from.location_fetcher.location()?;
+        let from_node = graph_node(&from.names, Some(&from_location), checker)?;
+        let target_location = target
+            .symbol
+            .as_ref()
+            .and_then(|symbol| self.bin.symbol_debug_info.get(symbol))
+            .map(|debug_info| debug_info.source_location());
+        let target_node = graph_node(target, target_location.as_ref(), checker)?;
+        let mut apis = BTreeSet::new();
+        self.bin
+            .names_and_apis_do(target, checker, |_, _, matched_apis| {
+                apis.extend(matched_apis.iter().map(|api| api.to_string()));
+                Ok(())
+            })?;
+        if let Some(graph) = self.graph.as_mut() {
+            graph.add_reference(from_node, target_node, apis.into_iter());
+        }
+        Ok(())
+    }
+
+    /// Adds everything referenced by `section` as roots of our reference graph, if we're building
+    /// one.
+    fn add_roots(&mut self, object_index: &ObjectIndex, section: &object::Section) -> Result<()> {
+        let Some(graph) = self.reference_graph.as_mut() else {
+            return Ok(());
+        };
+        for address in object_index.target_addresses(section, &self.bin.symbol_addresses)? {
+            graph.add_root(address);
         }
         Ok(())
     }
@@ -406,10 +683,24 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
                 from_apis.extend(apis.iter());
                 Ok(())
             })?;
+        let containing_function = if checker.attributes_to_proc_macros() {
+            Some(ContainingFunction {
+                names: all_names(&from.names)?,
+                declared_at: from
+                    .names
+                    .symbol
+                    .as_ref()
+                    .and_then(|symbol| self.bin.symbol_debug_info.get(symbol))
+                    .map(|debug_info| debug_info.source_location()),
+            })
+        } else {
+            None
+        };
         let mut lazy_location = None;
         let mut lazy_crate_names = None;
         let bin_path = self.bin.filename.clone();
         let bin_sel = self.bin.crate_sel.clone();
+        let bin_platform = self.bin.platform;
         self.bin
             .names_and_apis_do(target, checker, |name, name_source, apis| {
                 // For the majority of references we expect no APIs to match. We defer computation
@@ -419,7 +710,8 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
                 }
                 let location = lazy_location.as_ref().unwrap();
                 if lazy_crate_names.is_none() {
-                    lazy_crate_names = Some(checker.pkg_ids_from_source_path(location.filename())?);
+                    lazy_crate_names =
+                        Some(checker.pkg_ids_for_usage(location, containing_function.as_ref())?);
                 }
                 let crate_names = lazy_crate_names.as_ref().unwrap();
 
@@ -440,12 +732,16 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
                             .transpose()?;
                         let api_usage = SingleApiUsage {
                             pkg_id: pkg_id.clone(),
-                            scope: PermissionScope::determine(pkg_id, &bin_sel),
+                            scope: PermissionScope::determine(pkg_id, &bin_sel, bin_platform),
                             api: api.clone(),
                             usage: ApiUsage {
                                 bin_location,
                                 bin_path: bin_path.clone(),
-                                permission_scope: PermissionScope::determine(pkg_id, &bin_sel),
+                                permission_scope: PermissionScope::determine(
+                                    pkg_id,
+                                    &bin_sel,
+                                    bin_platform,
+                                ),
                                 source_location: location.clone(),
                                 outer_location,
                                 from: from.names.symbol_or_debug_name()?,
@@ -453,6 +749,8 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
                                 to_name: name.clone(),
                                 to_source: name_source.to_owned(),
                                 debug_data: debug_data.cloned(),
+                                call_chain: Vec::new(),
+                                similar_usages: 0,
                             },
                         };
                         self.new_api_usages
@@ -466,18 +764,46 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
         Ok(())
     }
 
-    fn emit_shortest_api_usages(&mut self) {
+    fn emit_shortest_api_usages(&mut self, checker: &Checker) -> Result<()> {
         // New API usages are grouped by their deduplication key, which doesn't include the target
         // symbol. We then output only the API usage with the shortest target symbol.
-        for api_usages in std::mem::take(&mut self.new_api_usages).into_values() {
-            if let Some(shortest_target_usage) =
-                api_usages
-                    .into_iter()
-                    .min_by_key(|u| match &u.usage.to_source {
-                        NameSource::Symbol(sym) => sym.len(),
-                        NameSource::DebugName(debug_name) => debug_name.name.len(),
-                    })
+        let reachable = self
+            .reference_graph
+            .as_ref()
+            .filter(|_| self.prune_unreachable)
+            .map(|graph| graph.reachable());
+        let mut call_chain_finder = self
+            .reference_graph
+            .as_ref()
+            .filter(|_| self.report_call_chains)
+            .map(|graph| CallChainFinder::new(graph, &self.bin));
+        for mut api_usages in std::mem::take(&mut self.new_api_usages).into_values() {
+            if let Some(reachable) = reachable.as_ref() {
+                api_usages.retain(|u| {
+                    let keep = reachable.contains(&u.usage.bin_location.symbol_start);
+                    if !keep {
+                        debug!(
+                            "Pruning unreachable usage of `{}` from `{}`",
+                            u.api, u.usage.from
+                        );
+                    }
+                    keep
+                });
+            }
+            if let Some(mut shortest_target_usage) = api_usages.into_iter().min_by_key(|u| match &u
+                .usage
+                .to_source
             {
+                NameSource::Symbol(sym) => sym.len(),
+                NameSource::DebugName(debug_name) => debug_name.name.len(),
+            }) {
+                if let Some(finder) = call_chain_finder.as_mut() {
+                    shortest_target_usage.usage.call_chain = finder.call_chain(
+                        &shortest_target_usage.pkg_id,
+                        shortest_target_usage.usage.bin_location.symbol_start,
+                        checker,
+                    )?;
+                }
                 self.outputs
                     .api_usages
                     .entry((
@@ -489,11 +815,13 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
                         scope: shortest_target_usage.scope,
                         api_name: shortest_target_usage.api.clone(),
                         usages: Default::default(),
+                        severity: checker.api_severity(&shortest_target_usage.api),
                     })
                     .usages
                     .push(shortest_target_usage.usage);
             }
         }
+        Ok(())
     }
 
     fn find_possible_exports(&mut self, checker: &Checker) {
@@ -509,7 +837,7 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
             let Some(module_name) = symbol.module_name() else {
                 continue;
             };
-            let Some(api_name) = api_names.get(module_name) else {
+            let Some(api_name) = api_names.get(module_name.as_ref()) else {
                 continue;
             };
             let location = debug_info.source_location();
@@ -522,7 +850,7 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
                     // Macros can sometimes result in symbols being attributed to lower-level
                     // crates, so we only consider exported APIs that start with the crate name we
                     // expect for the package.
-                    if symbol.crate_name() != Some(pkg_id.crate_name().as_ref()) {
+                    if symbol.crate_name().as_deref() != Some(pkg_id.crate_name().as_ref()) {
                         continue;
                     }
                     self.outputs
@@ -536,6 +864,198 @@ impl<'input, 'backtracer> ApiUsageCollector<'input, 'backtracer> {
             }
         }
     }
+
+    /// Finds unmangled functions, e.g. from `#[no_mangle]`, that are defined by packages in the
+    /// dependency tree.
+    fn find_ffi_exports(
+        &mut self,
+        bin: &BinFile,
+        ctx: &addr2line::Context<EndianSlice<'input, RunTimeEndian>>,
+        checker: &Checker,
+    ) {
+        let exports = group_ffi_exports(
+            &bin.exported_functions(),
+            |address| {
+                let Some(location) = ctx.find_location(address)? else {
+                    return Ok(None);
+                };
+                Ok(SourceLocation::try_from(&location).ok())
+            },
+            |path| {
+                checker
+                    .opt_pkg_ids_from_source_path(path)
+                    .unwrap_or_default()
+                    .into_owned()
+            },
+            &self.bin.crate_sel,
+            self.bin.platform,
+        );
+        self.outputs.ffi_exports.extend(exports);
+    }
+}
+
+/// Groups `exported_functions` by the package and permission scope that they're exported from.
+/// Functions that aren't explicitly exported, or whose location can't be determined using
+/// `find_location`, are skipped. `bin_sel` and `platform` identify the binary being linked, which
+/// determines the scope, e.g. functions exported from a build script are checked against the
+/// build script's permissions.
+fn group_ffi_exports(
+    exported_functions: &[(&str, u64)],
+    find_location: impl Fn(u64) -> Result<Option<SourceLocation>>,
+    pkg_ids_for_path: impl Fn(&Path) -> Vec<PackageId>,
+    bin_sel: &CrateSel,
+    platform: Platform,
+) -> Vec<FfiExport> {
+    let mut by_package: FxHashMap<(PackageId, PermissionScope), BTreeSet<String>> =
+        FxHashMap::default();
+    for &(name, address) in exported_functions {
+        if is_compiler_generated_export(name) || Symbol::borrowed(name.as_bytes()).is_mangled() {
+            continue;
+        }
+        let Ok(Some(location)) = find_location(address) else {
+            continue;
+        };
+        for pkg_id in pkg_ids_for_path(location.filename()) {
+            let scope = PermissionScope::determine(&pkg_id, bin_sel, platform);
+            by_package
+                .entry((pkg_id, scope))
+                .or_default()
+                .insert(name.to_owned());
+        }
+    }
+    let mut exports: Vec<FfiExport> = by_package
+        .into_iter()
+        .map(|((pkg_id, scope), symbols)| FfiExport {
+            pkg_id,
+            scope,
+            symbols: symbols.into_iter().collect(),
+        })
+        .collect();
+    exports.sort_by_key(FfiExport::perm_sel);
+    exports
+}
+
+/// Works out the chain of calls by which API usages can be reached from an entry point to the
+/// package containing them.
+struct CallChainFinder<'a, 'input> {
+    graph: &'a ReferenceGraph,
+    callers: FxHashMap<u64, Vec<u64>>,
+    bin: &'a BinInfo<'input>,
+    /// The symbol at each address. Where there are several, we prefer one with debug info.
+    address_symbols: FxHashMap<u64, &'a Symbol<'input>>,
+    /// The packages that the symbol at each address has been attributed to.
+    address_packages: FxHashMap<u64, Vec<PackageId>>,
+}
+
+impl<'a, 'input> CallChainFinder<'a, 'input> {
+    fn new(graph: &'a ReferenceGraph, bin: &'a BinInfo<'input>) -> Self {
+        let mut address_symbols: FxHashMap<u64, &Symbol> = FxHashMap::default();
+        for (symbol, address) in &bin.symbol_addresses {
+            let existing = address_symbols.entry(*address).or_insert(symbol);
+            if !bin.symbol_debug_info.contains_key(existing)
+                && bin.symbol_debug_info.contains_key(symbol)
+            {
+                *existing = symbol;
+            }
+        }
+        Self {
+            graph,
+            callers: graph.callers(),
+            bin,
+            address_symbols,
+            address_packages: FxHashMap::default(),
+        }
+    }
+
+    /// Returns the call chain from an entry point of `pkg_id` to the symbol at `address`.
+    fn call_chain(
+        &mut self,
+        pkg_id: &PackageId,
+        address: u64,
+        checker: &Checker,
+    ) -> Result<Vec<SymbolOrDebugName>> {
+        let bin = self.bin;
+        let address_symbols = &self.address_symbols;
+        let address_packages = &mut self.address_packages;
+        let in_package = |address| {
+            address_packages
+                .entry(address)
+                .or_insert_with(|| {
+                    address_symbols
+                        .get(&address)
+                        .and_then(|symbol| bin.symbol_debug_info.get(*symbol))
+                        .and_then(|debug_info| {
+                            checker.opt_pkg_ids_from_source_path(
+                                debug_info.source_location().filename(),
+                            )
+                        })
+                        .map(|pkg_ids| pkg_ids.into_owned())
+                        .unwrap_or_default()
+                })
+                .contains(pkg_id)
+        };
+        let Some(path) = self.graph.entry_path(&self.callers, address, in_package) else {
+            return Ok(Vec::new());
+        };
+        path.iter()
+            .filter_map(|address| self.address_symbols.get(address))
+            .map(|symbol| self.bin.get_symbol_and_name(symbol).symbol_or_debug_name())
+            .collect()
+    }
+}
+
+/// Returns whether `name` is an unmangled symbol that's emitted by the compiler or linker rather
+/// than being explicitly exported by a package.
+fn is_compiler_generated_export(name: &str) -> bool {
+    matches!(name, "main" | "_start" | "_init" | "_fini") || name.starts_with("__rustc")
+}
+
+/// Returns the name and node details that we use to represent `names` in the graph that we show to
+/// the user. The package is determined from `location` if we have it.
+fn graph_node(
+    names: &SymbolAndName,
+    location: Option<&SourceLocation>,
+    checker: &Checker,
+) -> Result<(String, GraphNode)> {
+    let package = location
+        .and_then(|location| checker.opt_pkg_ids_from_source_path(location.filename()))
+        .and_then(|pkg_ids| pkg_ids.first().cloned());
+    let crate_name = match &package {
+        Some(pkg_id) => Some(pkg_id.name_str().to_owned()),
+        None => names
+            .symbol
+            .as_ref()
+            .and_then(|symbol| symbol.crate_name())
+            .map(|name| name.into_owned()),
+    };
+    Ok((
+        names.symbol_or_debug_name()?.to_string(),
+        GraphNode {
+            package: package.map(|pkg_id| pkg_id.to_string()),
+            crate_name,
+        },
+    ))
+}
+
+/// Returns all the names in `symbol_and_name`, from both its debug name and its symbol. Unlike
+/// the debug name, the symbol includes the trait for trait impls.
+fn all_names(symbol_and_name: &SymbolAndName) -> Result<Vec<Name>> {
+    let mut names = Vec::new();
+    if let Some(debug_name) = symbol_and_name.debug_name.as_ref() {
+        let mut it = debug_name.names_iterator();
+        while let Some((_, name)) = it.next_name()? {
+            names.push(name.create_name()?);
+        }
+    }
+    if let Some(symbol) = symbol_and_name.symbol.as_ref() {
+        let mut buffer = String::new();
+        let mut it = symbol.names(&mut buffer)?;
+        while let Some((_, name)) = it.next_name()? {
+            names.push(name.create_name()?);
+        }
+    }
+    names.retain(|name| !name.parts.is_empty());
+    Ok(names)
 }
 
 struct Node<'a> {
@@ -580,25 +1100,28 @@ impl<'obj, 'data> ObjectIndex<'obj, 'data> {
             let Some(section_index) = obj_symbol.section_index() else {
                 continue;
             };
-            let section_info = &mut section_infos[section_index.0];
-            let symbol_is_first_in_section = section_info
-                .first_symbol
-                .as_ref()
-                .map(|existing| obj_symbol.address() < existing.offset)
-                .unwrap_or(true);
-            if symbol_is_first_in_section {
-                section_info.first_symbol = Some(SymbolInfo {
-                    symbol: Symbol::borrowed(name),
-                    offset: obj_symbol.address(),
-                });
-            }
+            section_infos[section_index.0].symbols.push(SymbolInfo {
+                symbol: Symbol::borrowed(name),
+                offset: obj_symbol.address(),
+            });
+        }
+        for section_info in &mut section_infos {
+            section_info
+                .symbols
+                .sort_by_key(|symbol_info| symbol_info.offset);
+            section_info
+                .symbols
+                .dedup_by_key(|symbol_info| symbol_info.offset);
         }
         Self { obj, section_infos }
     }
 
     /// Adds the symbol or symbols that `rel` refers to into `symbols_out`. If `rel` refers to a
     /// section that doesn't define a non-local symbol at address 0, then all outgoing references
-    /// from that section will be included and so on recursively.
+    /// from that section will be included and so on recursively. If `rel` refers to a data symbol
+    /// defined in this object, e.g. a vtable or a static containing a function pointer, then we
+    /// include both the data symbol and whatever that data refers to. If `rel` refers to a string
+    /// literal naming a device, then we also include a name for that device.
     fn add_target_symbols(
         &self,
         rel: &object::Relocation,
@@ -606,29 +1129,117 @@ impl<'obj, 'data> ObjectIndex<'obj, 'data> {
         visited: &mut FxHashSet<SectionIndex>,
         bin_symbols: &FxHashMap<Symbol, u64>,
     ) -> Result<()> {
-        match self.get_symbol_or_section(rel.target(), bin_symbols)? {
-            SymbolOrSection::Symbol(symbol) => {
+        if let Some(symbol) = self.device_path_symbol(rel) {
+            symbols_out.push(symbol);
+        }
+        match self.get_symbol_or_section(rel.target(), target_offset(rel), bin_symbols)? {
+            SymbolOrSection::Symbol(symbol, data_section) => {
                 symbols_out.push(symbol);
+                if let Some(section_index) = data_section {
+                    self.add_section_target_symbols(
+                        section_index,
+                        symbols_out,
+                        visited,
+                        bin_symbols,
+                    )?;
+                }
             }
             SymbolOrSection::Section(section_index) => {
-                if !visited.insert(section_index) {
-                    // We've already visited this section.
-                    return Ok(());
-                }
-                let section = self.obj.section_by_index(section_index)?;
-                for (_, rel) in section.relocations() {
-                    self.add_target_symbols(&rel, symbols_out, visited, bin_symbols)?;
-                }
+                self.add_section_target_symbols(section_index, symbols_out, visited, bin_symbols)?;
             }
         }
         Ok(())
     }
 
+    /// Adds the targets of all relocations in the specified section, unless we've already visited
+    /// that section.
+    fn add_section_target_symbols(
+        &self,
+        section_index: SectionIndex,
+        symbols_out: &mut Vec<Symbol<'data>>,
+        visited: &mut FxHashSet<SectionIndex>,
+        bin_symbols: &FxHashMap<Symbol, u64>,
+    ) -> Result<()> {
+        if !visited.insert(section_index) {
+            // We've already visited this section.
+            return Ok(());
+        }
+        let section = self.obj.section_by_index(section_index)?;
+        for (_, rel) in section.relocations() {
+            self.add_target_symbols(&rel, symbols_out, visited, bin_symbols)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the addresses in the binary of all symbols referenced by `section`.
+    fn target_addresses(
+        &self,
+        section: &object::Section,
+        bin_symbols: &FxHashMap<Symbol, u64>,
+    ) -> Result<Vec<u64>> {
+        let mut target_symbols = Vec::new();
+        for (_, rel) in section.relocations() {
+            self.add_target_symbols(
+                &rel,
+                &mut target_symbols,
+                &mut FxHashSet::default(),
+                bin_symbols,
+            )?;
+        }
+        Ok(target_symbols
+            .iter()
+            .filter_map(|symbol| bin_symbols.get(symbol).cloned())
+            .collect())
+    }
+
+    /// If `rel` refers to the start of a string literal like `/dev/urandom` in read-only data,
+    /// returns a symbol like `__path::dev::urandom`, so that APIs can match code that opens the
+    /// device, which would otherwise be indistinguishable from opening any other file.
+    fn device_path_symbol(&self, rel: &object::Relocation) -> Option<Symbol<'data>> {
+        let RelocationTarget::Symbol(symbol_index) = rel.target() else {
+            return None;
+        };
+        let symbol = self.obj.symbol_by_index(symbol_index).ok()?;
+        let section = self.obj.section_by_index(symbol.section_index()?).ok()?;
+        if !matches!(
+            section.kind(),
+            SectionKind::ReadOnlyData | SectionKind::ReadOnlyString
+        ) {
+            return None;
+        }
+        let offset = symbol.address().checked_sub(section.address())? + target_offset(rel);
+        let data = section.data().ok()?.get(usize::try_from(offset).ok()?..)?;
+        let rest = data.strip_prefix(b"/dev/")?;
+        let len = rest
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+            .count();
+        let device = std::str::from_utf8(&rest[..len]).ok()?;
+        if !device.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+        let name = format!("{DEVICE_PATH_NAMESPACE}::{device}");
+        Some(Symbol::borrowed(name.as_bytes()).to_heap())
+    }
+
+    /// Returns `section_index` if it refers to a section in this object that contains data, as
+    /// opposed to code.
+    fn data_section(&self, section_index: SectionIndex) -> Option<SectionIndex> {
+        let section = self.obj.section_by_index(section_index).ok()?;
+        matches!(
+            section.kind(),
+            SectionKind::Data | SectionKind::ReadOnlyData | SectionKind::ReadOnlyDataWithRel
+        )
+        .then_some(section_index)
+    }
+
     /// Returns either symbol or the section index for a relocation target, giving preference to the
-    /// symbol.
+    /// symbol. If the target is a section, then `target_offset` is used to select the symbol within
+    /// that section.
     fn get_symbol_or_section(
         &self,
         target_in: RelocationTarget,
+        target_offset: u64,
         bin_symbols: &FxHashMap<Symbol, u64>,
     ) -> Result<SymbolOrSection<'data>> {
         let section_index = match target_in {
@@ -640,7 +1251,10 @@ impl<'obj, 'data> ObjectIndex<'obj, 'data> {
                 if !name.is_empty() {
                     let sym = Symbol::borrowed(name);
                     if bin_symbols.contains_key(&sym) || symbol.section_index().is_none() {
-                        return Ok(SymbolOrSection::Symbol(sym));
+                        let data_section = symbol
+                            .section_index()
+                            .and_then(|index| self.data_section(index));
+                        return Ok(SymbolOrSection::Symbol(sym, data_section));
                     }
                 }
                 symbol.section_index().ok_or_else(|| {
@@ -653,34 +1267,103 @@ impl<'obj, 'data> ObjectIndex<'obj, 'data> {
             .section_infos
             .get(section_index.0)
             .ok_or_else(|| anyhow!("Unnamed symbol has invalid section index"))?;
-        if let Some(first_symbol_info) = section_info.first_symbol.as_ref() {
-            if bin_symbols.contains_key(&first_symbol_info.symbol) {
-                return Ok(SymbolOrSection::Symbol(first_symbol_info.symbol.clone()));
+        if let Some(symbol_info) = section_info.symbol_containing(target_offset) {
+            if bin_symbols.contains_key(&symbol_info.symbol) {
+                return Ok(SymbolOrSection::Symbol(
+                    symbol_info.symbol.clone(),
+                    self.data_section(section_index),
+                ));
             }
         }
         Ok(SymbolOrSection::Section(section_index))
     }
 
-    /// Returns information about the first symbol in the section.
-    fn first_symbol(&self, section: &object::Section) -> Option<&SymbolInfo<'data>> {
+    /// Returns the symbols defined in `section`, sorted by offset.
+    fn section_symbols(&self, section: &object::Section) -> &[SymbolInfo<'data>] {
         self.section_infos
             .get(section.index().0)
-            .and_then(|section_info| section_info.first_symbol.as_ref())
+            .map(|section_info| section_info.symbols.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns the symbol whose address range within `section` contains `offset`.
+    fn symbol_containing(
+        &self,
+        section: &object::Section,
+        offset: u64,
+    ) -> Option<&SymbolInfo<'data>> {
+        self.section_infos
+            .get(section.index().0)?
+            .symbol_containing(offset)
+    }
+}
+
+impl<'data> SectionInfo<'data> {
+    /// Returns the last symbol that starts at or before `offset`. Symbol sizes aren't always
+    /// reliable, so we assume that each symbol extends until the next one starts.
+    fn symbol_containing(&self, offset: u64) -> Option<&SymbolInfo<'data>> {
+        let index = self
+            .symbols
+            .partition_point(|symbol_info| symbol_info.offset <= offset);
+        index.checked_sub(1).map(|index| &self.symbols[index])
     }
 }
 
+/// Returns the offset within the target section or symbol that `rel` refers to. For PC-relative
+/// relocations, the addend includes an adjustment for the size of the relocated field, which we
+/// undo.
+fn target_offset(rel: &object::Relocation) -> u64 {
+    let mut offset = rel.addend();
+    if matches!(
+        rel.kind(),
+        RelocationKind::Relative | RelocationKind::PltRelative
+    ) {
+        offset += i64::from(rel.size() / 8);
+    }
+    offset.max(0) as u64
+}
+
 enum SymbolOrSection<'data> {
-    Symbol(Symbol<'data>),
+    /// A symbol, together with the index of the section that defines it if that section is a data
+    /// section within the current object.
+    Symbol(Symbol<'data>, Option<SectionIndex>),
     Section(SectionIndex),
 }
 
 impl<'symbol, 'input: 'symbol> BinInfo<'input> {
-    fn load_symbols(&mut self, obj: &object::File) -> Result<()> {
-        for sym in obj.symbols() {
-            let symbol = &Symbol::borrowed(sym.name_bytes()?);
-            if !symbol.is_look_through() {
-                self.symbol_addresses
-                    .insert(symbol.to_heap(), sym.address());
+    /// Loads symbol addresses from `bin`. `function_addresses` come from the debug info and are
+    /// used for WebAssembly, which doesn't have a symbol table.
+    fn load_symbols(
+        &mut self,
+        bin: &BinFile,
+        function_addresses: &[(Symbol<'input>, u64)],
+    ) -> Result<()> {
+        match bin {
+            BinFile::Object(obj) => {
+                for sym in obj.symbols() {
+                    let symbol = &Symbol::borrowed(sym.name_bytes()?);
+                    if !symbol.is_look_through() {
+                        self.symbol_addresses
+                            .insert(symbol.to_heap(), sym.address());
+                    }
+                }
+            }
+            BinFile::Wasm(module) => {
+                for (symbol, low_pc) in function_addresses {
+                    if let Some(address) = module.function_containing(*low_pc) {
+                        if !symbol.is_look_through() {
+                            self.symbol_addresses.insert(symbol.to_heap(), address);
+                        }
+                    }
+                }
+                // The name section has demangled names, so is only useful for unmangled functions.
+                let names = module.function_names()?;
+                for (name, address) in names.into_iter().chain(module.exported_functions()) {
+                    if name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+                        self.symbol_addresses
+                            .insert(Symbol::borrowed(name.as_bytes()).to_heap(), address);
+                    }
+                }
             }
         }
         Ok(())
@@ -752,7 +1435,8 @@ impl<'input> BinInfo<'input> {
                 }
             }
         } else if let Some(symbol) = symbol_and_name.symbol.as_ref() {
-            let mut symbol_it = symbol.names()?;
+            let mut buffer = String::new();
+            let mut symbol_it = symbol.names(&mut buffer)?;
             while let Some((parts, name)) = symbol_it.next_name()? {
                 let apis = checker.apis_for_name_iterator(parts);
                 if !apis.is_empty() {
@@ -803,18 +1487,101 @@ impl<'symbol> Display for NameSource<'symbol> {
     }
 }
 
-/// Loads section `id` from `obj`.
+/// A linked binary. WebAssembly isn't supported by the object crate with the features that we
+/// enable, so we parse it ourselves.
+enum BinFile<'data> {
+    Object(object::File<'data>),
+    Wasm(wasm::WasmModule<'data>),
+}
+
+impl<'data> BinFile<'data> {
+    fn parse(bytes: &'data [u8]) -> Result<Self> {
+        if wasm::is_wasm(bytes) {
+            return Ok(BinFile::Wasm(wasm::WasmModule::parse(bytes)?));
+        }
+        Ok(BinFile::Object(object::File::parse(bytes)?))
+    }
+
+    fn endianness(&self) -> RunTimeEndian {
+        match self {
+            BinFile::Object(obj) => endianness(obj),
+            BinFile::Wasm(_) => RunTimeEndian::Little,
+        }
+    }
+
+    fn load_section(&self, id: gimli::SectionId) -> Result<Cow<'data, [u8]>> {
+        match self {
+            BinFile::Object(obj) => load_section(obj, id),
+            BinFile::Wasm(module) => Ok(Cow::Borrowed(
+                module.custom_section(id.name()).unwrap_or_default(),
+            )),
+        }
+    }
+
+    /// Adds the entry points of this binary as roots of `graph`.
+    fn add_roots(&self, graph: &mut ReferenceGraph) {
+        match self {
+            BinFile::Object(obj) => graph.add_roots_from_bin(obj),
+            BinFile::Wasm(module) => {
+                for (_, address) in module.exported_functions() {
+                    graph.add_root(address);
+                }
+            }
+        }
+    }
+
+    /// Returns the names and addresses of functions that are defined and visible to other code
+    /// that gets linked with this binary.
+    fn exported_functions(&self) -> Vec<(&'data str, u64)> {
+        match self {
+            BinFile::Object(obj) => obj
+                .symbols()
+                .filter(|sym| {
+                    sym.is_definition() && sym.is_global() && sym.kind() == SymbolKind::Text
+                })
+                .filter_map(|sym| Some((sym.name().ok()?, sym.address())))
+                .collect(),
+            BinFile::Wasm(module) => module.exported_functions().collect(),
+        }
+    }
+}
+
+/// Returns the byte order that should be used when reading debug info from `obj`.
+fn endianness(obj: &object::File) -> RunTimeEndian {
+    if obj.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    }
+}
+
+/// Loads section `id` from `obj`. Compressed sections (zlib or zstd) are decompressed. A missing
+/// section is treated as empty, however failure to decompress a section that is present is an
+/// error, since otherwise we'd silently lose source attribution.
 fn load_section<'data>(
     obj: &object::File<'data>,
     id: gimli::SectionId,
-) -> Result<Cow<'data, [u8]>, gimli::Error> {
+) -> Result<Cow<'data, [u8]>> {
     let Some(section) = obj.section_by_name(id.name()) else {
         return Ok(Cow::Borrowed([].as_slice()));
     };
-    let Ok(data) = section.uncompressed_data() else {
-        return Ok(Cow::Borrowed([].as_slice()));
-    };
-    Ok(data)
+    section
+        .uncompressed_data()
+        .with_context(|| format!("Failed to decompress section `{}`", id.name()))
+}
+
+/// Returns whether `section_name` is a section containing pointers to functions that get run at
+/// startup or shutdown. Such functions need to be treated as reachable.
+fn is_initialiser_section(section_name: &str) -> bool {
+    [
+        ".init_array",
+        ".fini_array",
+        ".preinit_array",
+        ".ctors",
+        ".dtors",
+    ]
+    .iter()
+    .any(|prefix| section_name.starts_with(prefix))
 }
 
 impl Filetype {
@@ -822,7 +1589,7 @@ impl Filetype {
         let Some(extension) = filename.extension() else {
             return Filetype::Other;
         };
-        if extension == "rlib" || extension == ".a" {
+        if extension == "rlib" || extension == "a" {
             Filetype::Archive
         } else {
             Filetype::Other
@@ -863,6 +1630,13 @@ impl SingleApiUsage {
 pub(crate) enum UsageDebugData {
     Relocation(RelocationDebugData),
     Inlined(InlinedDebugData),
+    Bitcode(BitcodeDebugData),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct BitcodeDebugData {
+    bin_path: Arc<Path>,
+    object_file_path: ObjectFilePath,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -881,7 +1655,7 @@ pub(crate) struct InlinedDebugData {
 impl InlinedDebugData {
     fn from_offset(
         low_pc: Option<u64>,
-        ctx: &addr2line::Context<EndianSlice<LittleEndian>>,
+        ctx: &addr2line::Context<EndianSlice<RunTimeEndian>>,
     ) -> Result<InlinedDebugData> {
         let mut frames = Vec::new();
         if let Some(offset) = low_pc {
@@ -895,3 +1669,450 @@ impl InlinedDebugData {
         Ok(InlinedDebugData { frames, low_pc })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ObjectIndex;
+    use super::SectionInfo;
+    use super::SymbolInfo;
+    use crate::symbol::Symbol;
+    use fxhash::FxHashMap;
+    use fxhash::FxHashSet;
+    use object::write;
+    use object::Object;
+    use object::ObjectSection;
+
+    #[test]
+    fn symbol_containing() {
+        let section_info = SectionInfo {
+            symbols: [("a", 0x10), ("b", 0x20)]
+                .into_iter()
+                .map(|(name, offset)| SymbolInfo {
+                    symbol: Symbol::borrowed(name.as_bytes()),
+                    offset,
+                })
+                .collect(),
+        };
+        let name_at = |offset| {
+            section_info
+                .symbol_containing(offset)
+                .map(|symbol_info| symbol_info.symbol.to_string())
+        };
+        assert_eq!(name_at(0x8), None);
+        assert_eq!(name_at(0x10).as_deref(), Some("a"));
+        assert_eq!(name_at(0x1f).as_deref(), Some("a"));
+        assert_eq!(name_at(0x20).as_deref(), Some("b"));
+        assert_eq!(name_at(0x100).as_deref(), Some("b"));
+    }
+
+    /// Builds an object in which `caller` references `VTABLE`, which in turn references `target`.
+    fn object_with_vtable() -> Vec<u8> {
+        let mut obj = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::X86_64,
+            object::Endianness::Little,
+        );
+        let mut add_symbol = |name: &str, section_name: &str, kind| {
+            let section = obj.add_section(Vec::new(), section_name.as_bytes().to_vec(), kind);
+            let symbol = obj.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: 8,
+                kind: object::SymbolKind::Data,
+                scope: object::SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Section(section),
+                flags: object::SymbolFlags::None,
+            });
+            obj.append_section_data(section, &[0; 8], 8);
+            (section, symbol)
+        };
+        let (caller_section, _) = add_symbol("caller", ".text.caller", object::SectionKind::Text);
+        let (vtable_section, vtable) = add_symbol(
+            "VTABLE",
+            ".data.rel.ro.VTABLE",
+            object::SectionKind::ReadOnlyDataWithRel,
+        );
+        let (_, target) = add_symbol("target", ".text.target", object::SectionKind::Text);
+        for (section, symbol) in [(caller_section, vtable), (vtable_section, target)] {
+            obj.add_relocation(
+                section,
+                write::Relocation {
+                    offset: 0,
+                    size: 64,
+                    kind: object::RelocationKind::Absolute,
+                    encoding: object::RelocationEncoding::Generic,
+                    symbol,
+                    addend: 0,
+                },
+            )
+            .unwrap();
+        }
+        obj.write().unwrap()
+    }
+
+    #[test]
+    fn ffi_exports() {
+        use crate::config::permissions::PermissionScope;
+        use crate::crate_index::testing::pkg_id;
+        use crate::crate_index::CrateSel;
+        use crate::link_info::Platform;
+        use crate::location::SourceLocation;
+        use anyhow::anyhow;
+        use std::path::Path;
+
+        let exported = [
+            ("crab1_init", 0x10),
+            ("crab2_init", 0x20),
+            ("main", 0x30),
+            ("_ZN5crab14main17h0123456789abcdefE", 0x40),
+            ("no_location", 0x50),
+            ("bad_debug_info", 0x60),
+        ];
+        let find_location = |address| match address {
+            0x10 | 0x30 | 0x40 => Ok(Some(SourceLocation::new(
+                Path::new("/crab1/build.rs"),
+                1,
+                None,
+            ))),
+            0x20 => Ok(Some(SourceLocation::new(
+                Path::new("/crab2/src/lib.rs"),
+                1,
+                None,
+            ))),
+            0x50 => Ok(None),
+            _ => Err(anyhow!("Invalid debug info")),
+        };
+        let pkg_ids_for_path = |path: &Path| {
+            if path.starts_with("/crab1") {
+                vec![pkg_id("crab1")]
+            } else {
+                vec![pkg_id("crab2")]
+            }
+        };
+        let exports = super::group_ffi_exports(
+            &exported,
+            find_location,
+            pkg_ids_for_path,
+            &CrateSel::build_script(pkg_id("crab1")),
+            Platform::Host,
+        );
+        let exports: Vec<(String, PermissionScope, Vec<String>)> = exports
+            .into_iter()
+            .map(|export| (export.pkg_id.to_string(), export.scope, export.symbols))
+            .collect();
+        assert_eq!(
+            exports,
+            vec![
+                (
+                    "crab1".to_owned(),
+                    PermissionScope::Build,
+                    vec!["crab1_init".to_owned()]
+                ),
+                (
+                    "crab2".to_owned(),
+                    PermissionScope::FromBuild,
+                    vec!["crab2_init".to_owned()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reference_via_data_section() {
+        let bytes = object_with_vtable();
+        let obj = object::File::parse(bytes.as_slice()).unwrap();
+        let object_index = ObjectIndex::new(&obj);
+        let bin_symbols: FxHashMap<Symbol, u64> = ["caller", "VTABLE", "target"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (Symbol::borrowed(name.as_bytes()), i as u64 * 8))
+            .collect();
+        let caller_section = obj.section_by_name(".text.caller").unwrap();
+        let mut targets = Vec::new();
+        for (_, rel) in caller_section.relocations() {
+            object_index
+                .add_target_symbols(&rel, &mut targets, &mut FxHashSet::default(), &bin_symbols)
+                .unwrap();
+        }
+        let targets: Vec<String> = targets.iter().map(|symbol| symbol.to_string()).collect();
+        assert_eq!(targets, ["VTABLE", "target"]);
+    }
+
+    #[test]
+    fn reference_to_device_path() {
+        let mut obj = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::X86_64,
+            object::Endianness::Little,
+        );
+        let caller_section = obj.add_section(
+            Vec::new(),
+            b".text.caller".to_vec(),
+            object::SectionKind::Text,
+        );
+        obj.append_section_data(caller_section, &[0; 32], 8);
+        let literals = obj.add_section(
+            Vec::new(),
+            b".rodata..Lanon.1".to_vec(),
+            object::SectionKind::ReadOnlyData,
+        );
+        obj.append_section_data(literals, b"/etc/hosts/dev/urandom/dev/0", 1);
+        let literals_symbol = obj.section_symbol(literals);
+        for (i, addend) in [0, 10, 22].into_iter().enumerate() {
+            obj.add_relocation(
+                caller_section,
+                write::Relocation {
+                    offset: i as u64 * 8,
+                    size: 64,
+                    kind: object::RelocationKind::Absolute,
+                    encoding: object::RelocationEncoding::Generic,
+                    symbol: literals_symbol,
+                    addend,
+                },
+            )
+            .unwrap();
+        }
+        let bytes = obj.write().unwrap();
+        let obj = object::File::parse(bytes.as_slice()).unwrap();
+        let object_index = ObjectIndex::new(&obj);
+        let caller_section = obj.section_by_name(".text.caller").unwrap();
+        let mut targets = Vec::new();
+        for (_, rel) in caller_section.relocations() {
+            object_index
+                .add_target_symbols(
+                    &rel,
+                    &mut targets,
+                    &mut FxHashSet::default(),
+                    &FxHashMap::default(),
+                )
+                .unwrap();
+        }
+        let targets: Vec<String> = targets.iter().map(|symbol| symbol.to_string()).collect();
+        assert_eq!(targets, ["__path::dev::urandom"]);
+    }
+
+    /// Builds an object with a `.debug_info` section that has `compressed` as its zlib-compressed
+    /// contents, which decompress to `size` bytes.
+    fn object_with_compressed_debug_info(compressed: &[u8], size: u64) -> Vec<u8> {
+        let mut obj = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::X86_64,
+            object::Endianness::Little,
+        );
+        let section = obj.add_section(
+            Vec::new(),
+            b".debug_info".to_vec(),
+            object::SectionKind::Debug,
+        );
+        let mut data = Vec::new();
+        data.extend(object::elf::ELFCOMPRESS_ZLIB.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(size.to_le_bytes());
+        data.extend(1u64.to_le_bytes());
+        data.extend(compressed);
+        obj.append_section_data(section, &data, 1);
+        obj.section_mut(section).flags = object::SectionFlags::Elf {
+            sh_flags: object::elf::SHF_COMPRESSED.into(),
+        };
+        obj.write().unwrap()
+    }
+
+    #[test]
+    fn load_compressed_section() {
+        let contents = b"debug info";
+        // A zlib stream containing a single uncompressed block.
+        let mut zlib = vec![0x78, 0x01, 0x01];
+        zlib.extend((contents.len() as u16).to_le_bytes());
+        zlib.extend((!(contents.len() as u16)).to_le_bytes());
+        zlib.extend(contents);
+        let (a, b) = contents.iter().fold((1u32, 0u32), |(a, b), byte| {
+            let a = (a + u32::from(*byte)) % 65521;
+            (a, (b + a) % 65521)
+        });
+        zlib.extend(((b << 16) | a).to_be_bytes());
+
+        let bytes = object_with_compressed_debug_info(&zlib, contents.len() as u64);
+        let obj = object::File::parse(bytes.as_slice()).unwrap();
+        assert_eq!(
+            super::load_section(&obj, gimli::SectionId::DebugInfo)
+                .unwrap()
+                .as_ref(),
+            contents
+        );
+        assert!(super::load_section(&obj, gimli::SectionId::DebugLine)
+            .unwrap()
+            .is_empty());
+
+        let bytes = object_with_compressed_debug_info(&[0x78, 0x9c, 0xff, 0xff, 0xff], 100);
+        let obj = object::File::parse(bytes.as_slice()).unwrap();
+        let error = super::load_section(&obj, gimli::SectionId::DebugInfo).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to decompress section `.debug_info`"
+        );
+    }
+
+    /// Builds an object with the specified byte order, containing a compilation unit for `crab.rs`.
+    fn object_with_compilation_unit(endianness: object::Endianness) -> Vec<u8> {
+        let big = endianness == object::Endianness::Big;
+        let u16_bytes = |value: u16| {
+            if big {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let u32_bytes = |value: u32| {
+            if big {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        // Abbreviation 1 is a compilation unit with no children and a DW_AT_name string.
+        let abbrev = [1, 0x11, 0, 0x03, 0x08, 0, 0, 0];
+        let mut unit = Vec::new();
+        unit.extend(u16_bytes(4));
+        unit.extend(u32_bytes(0));
+        unit.push(8);
+        unit.push(1);
+        unit.extend(b"crab.rs\0");
+        let mut info = u32_bytes(unit.len() as u32).to_vec();
+        info.extend(unit);
+
+        let mut obj = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::PowerPc64,
+            endianness,
+        );
+        for (name, data) in [(".debug_info", info.as_slice()), (".debug_abbrev", &abbrev)] {
+            let section = obj.add_section(
+                Vec::new(),
+                name.as_bytes().to_vec(),
+                object::SectionKind::Debug,
+            );
+            obj.append_section_data(section, data, 1);
+        }
+        obj.write().unwrap()
+    }
+
+    #[test]
+    fn dwarf_endianness() {
+        for endianness in [object::Endianness::Little, object::Endianness::Big] {
+            let bytes = object_with_compilation_unit(endianness);
+            let obj = object::File::parse(bytes.as_slice()).unwrap();
+            let owned_dwarf = gimli::Dwarf::load(|id| super::load_section(&obj, id)).unwrap();
+            let endian = super::endianness(&obj);
+            let dwarf = owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, endian));
+            let header = dwarf.units().next().unwrap().unwrap();
+            let unit = dwarf.unit(header).unwrap();
+            let mut entries = unit.entries();
+            let (_, entry) = entries.next_dfs().unwrap().unwrap();
+            let name = entry.attr_value(gimli::DW_AT_name).unwrap().unwrap();
+            assert_eq!(
+                dwarf.attr_string(&unit, name).unwrap().slice(),
+                b"crab.rs",
+                "{endianness:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn prune_with_multiple_functions_per_section() {
+        use super::reachability::ReferenceGraph;
+
+        // `a` and `b` share `.text`, as they would without `-ffunction-sections`. `main` calls `b`
+        // via the section symbol and `b` calls `open`.
+        let mut obj = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::X86_64,
+            object::Endianness::Little,
+        );
+        let text = obj.add_section(Vec::new(), b".text".to_vec(), object::SectionKind::Text);
+        obj.append_section_data(text, &[0; 32], 16);
+        let main = obj.add_section(
+            Vec::new(),
+            b".text.main".to_vec(),
+            object::SectionKind::Text,
+        );
+        obj.append_section_data(main, &[0; 16], 16);
+        for (name, section, value) in [("a", text, 0), ("b", text, 16), ("main", main, 0)] {
+            obj.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size: 16,
+                kind: object::SymbolKind::Text,
+                scope: object::SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Section(section),
+                flags: object::SymbolFlags::None,
+            });
+        }
+        let open = obj.add_symbol(write::Symbol {
+            name: b"open".to_vec(),
+            value: 0,
+            size: 0,
+            kind: object::SymbolKind::Text,
+            scope: object::SymbolScope::Dynamic,
+            weak: false,
+            section: write::SymbolSection::Undefined,
+            flags: object::SymbolFlags::None,
+        });
+        let text_symbol = obj.section_symbol(text);
+        for (section, offset, symbol, addend) in [(main, 4, text_symbol, 12), (text, 20, open, -4)]
+        {
+            obj.add_relocation(
+                section,
+                write::Relocation {
+                    offset,
+                    size: 32,
+                    kind: object::RelocationKind::PltRelative,
+                    encoding: object::RelocationEncoding::Generic,
+                    symbol,
+                    addend,
+                },
+            )
+            .unwrap();
+        }
+        let bytes = obj.write().unwrap();
+
+        let obj = object::File::parse(bytes.as_slice()).unwrap();
+        let object_index = ObjectIndex::new(&obj);
+        let bin_symbols: FxHashMap<Symbol, u64> = [
+            ("main", 0x1000),
+            ("a", 0x2000),
+            ("b", 0x2010),
+            ("open", 0x3000),
+        ]
+        .into_iter()
+        .map(|(name, address)| (Symbol::borrowed(name.as_bytes()), address))
+        .collect();
+
+        // Build the graph and work out where each reference is from in the same way as
+        // `process_object_file`.
+        let mut graph = ReferenceGraph::default();
+        graph.add_root(0x1000);
+        let mut usages = Vec::new();
+        for section in obj.sections() {
+            for (offset, rel) in section.relocations() {
+                let sym_info = object_index.symbol_containing(&section, offset).unwrap();
+                let symbol_start = bin_symbols[&sym_info.symbol];
+                let mut targets = Vec::new();
+                object_index
+                    .add_target_symbols(&rel, &mut targets, &mut FxHashSet::default(), &bin_symbols)
+                    .unwrap();
+                for target in targets {
+                    graph.add_reference(symbol_start, bin_symbols[&target]);
+                    usages.push((target.to_string(), symbol_start));
+                }
+            }
+        }
+        let reachable = graph.reachable();
+        assert!(reachable.contains(&0x2010));
+        assert!(!reachable.contains(&0x2000));
+        // The usage of `open` from `b` isn't pruned.
+        assert!(usages.contains(&("open".to_owned(), 0x2010)));
+        assert!(reachable.contains(&0x3000));
+    }
+}
@@ -55,15 +55,15 @@ impl Backtracer {
             &mut FxHashSet::default(),
         );
 
-        let obj = object::File::parse(self.bin_bytes.as_slice()).with_context(|| {
+        let bin = super::BinFile::parse(self.bin_bytes.as_slice()).with_context(|| {
             format!(
                 "Backtrace failed to parse bin file of size {}",
                 self.bin_bytes.len()
             )
         })?;
-        let owned_dwarf = Dwarf::load(|id| super::load_section(&obj, id))?;
-        let dwarf =
-            owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, gimli::LittleEndian));
+        let owned_dwarf = Dwarf::load(|id| bin.load_section(id))?;
+        let endian = bin.endianness();
+        let dwarf = owned_dwarf.borrow(|section| gimli::EndianSlice::new(section, endian));
         let ctx = addr2line::Context::from_dwarf(dwarf)
             .context("Failed in addr2line during backtrace")?;
 
@@ -0,0 +1,350 @@
+//! Support for reading the symbol table from LLVM bitcode files. These are what object files
+//! contain when building with linker-plugin LTO. Bitcode doesn't contain relocations, so the best
+//! we can do is find out which symbols a module defines and which it references.
+//!
+//! LLVM writes a symbol table (see `llvm/Object/IRSymtab.h`) into a top-level block of the bitcode
+//! file so that linkers don't need to parse the whole module. We read just that block and the
+//! string table that it refers to.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+const RAW_MAGIC: &[u8] = b"BC\xC0\xDE";
+const WRAPPER_MAGIC: &[u8] = b"\xDE\xC0\x17\x0B";
+
+const STRTAB_BLOCK_ID: u64 = 23;
+const SYMTAB_BLOCK_ID: u64 = 25;
+
+/// The record code for the blob in both the string table block and the symbol table block.
+const BLOB_RECORD_CODE: u64 = 1;
+
+const END_BLOCK: u64 = 0;
+const ENTER_SUBBLOCK: u64 = 1;
+const DEFINE_ABBREV: u64 = 2;
+const UNABBREV_RECORD: u64 = 3;
+
+/// The bit in a symbol's flags that indicates that it's undefined.
+const FLAG_UNDEFINED: u32 = 1 << 3;
+
+/// The index of the `Symbols` range within the symbol table header, in 32 bit words. It's
+/// preceded by `Version`, `Producer`, `Modules` and `Comdats`.
+const HEADER_SYMBOLS_WORD: usize = 7;
+
+/// The size of each symbol in the symbol table, in 32 bit words. Each symbol has a `Name`, an
+/// `IRName`, a `ComdatIndex` and `Flags`.
+const SYMBOL_WORDS: usize = 6;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct BitcodeSymbol<'data> {
+    pub(crate) name: &'data [u8],
+    pub(crate) is_undefined: bool,
+}
+
+/// Returns whether `bytes` is an LLVM bitcode file, either raw or with a wrapper header.
+pub(crate) fn is_bitcode(bytes: &[u8]) -> bool {
+    bytes.starts_with(RAW_MAGIC) || bytes.starts_with(WRAPPER_MAGIC)
+}
+
+/// Returns the symbols defined or referenced by the bitcode file `bytes`.
+pub(crate) fn symbols(bytes: &[u8]) -> Result<Vec<BitcodeSymbol<'_>>> {
+    let bytes = strip_wrapper(bytes)?;
+    let Some(bitstream) = bytes.strip_prefix(RAW_MAGIC) else {
+        bail!("Missing bitcode magic");
+    };
+    let mut reader = BitReader::new(bitstream);
+    let mut symtab = None;
+    let mut strtab = None;
+    // At the top level, the abbreviation width is 2 and we should only see blocks.
+    while reader.remaining_bits() >= 32 {
+        if reader.fixed(2)? != ENTER_SUBBLOCK {
+            break;
+        }
+        let block_id = reader.vbr(8)?;
+        let abbrev_width = reader.vbr(4)? as u32;
+        reader.align32();
+        let num_words = reader.fixed(32)? as usize;
+        match block_id {
+            SYMTAB_BLOCK_ID => symtab = Some(reader.read_blob_block(abbrev_width)?),
+            STRTAB_BLOCK_ID => strtab = Some(reader.read_blob_block(abbrev_width)?),
+            _ => reader.skip_bits(num_words * 32)?,
+        }
+    }
+    let (Some(symtab), Some(strtab)) = (symtab, strtab) else {
+        bail!("Bitcode file doesn't contain a symbol table");
+    };
+    parse_symtab(symtab, strtab)
+}
+
+fn strip_wrapper(bytes: &[u8]) -> Result<&[u8]> {
+    if !bytes.starts_with(WRAPPER_MAGIC) {
+        return Ok(bytes);
+    }
+    let offset = read_word(bytes, 2)? as usize;
+    let size = read_word(bytes, 3)? as usize;
+    bytes
+        .get(offset..offset.saturating_add(size))
+        .context("Bitcode wrapper refers to data outside of file")
+}
+
+fn parse_symtab<'data>(symtab: &[u8], strtab: &'data [u8]) -> Result<Vec<BitcodeSymbol<'data>>> {
+    let symbols_offset = read_word(symtab, HEADER_SYMBOLS_WORD)? as usize;
+    let num_symbols = read_word(symtab, HEADER_SYMBOLS_WORD + 1)? as usize;
+    let symbols = symtab
+        .get(symbols_offset..)
+        .context("Symbol table offset out of range")?;
+    (0..num_symbols)
+        .map(|i| {
+            let base = i * SYMBOL_WORDS;
+            let name_offset = read_word(symbols, base)? as usize;
+            let name_len = read_word(symbols, base + 1)? as usize;
+            let flags = read_word(symbols, base + 5)?;
+            let name = strtab
+                .get(name_offset..name_offset.saturating_add(name_len))
+                .context("Symbol name out of range")?;
+            Ok(BitcodeSymbol {
+                name,
+                is_undefined: flags & FLAG_UNDEFINED != 0,
+            })
+        })
+        .collect()
+}
+
+/// Reads the little-endian 32 bit word at `index`.
+fn read_word(bytes: &[u8], index: usize) -> Result<u32> {
+    let start = index * 4;
+    let word = bytes
+        .get(start..start + 4)
+        .context("Unexpected end of bitcode data")?;
+    Ok(u32::from_le_bytes(word.try_into().unwrap()))
+}
+
+/// An operand of an abbreviation definition.
+#[derive(Clone, Copy, Debug)]
+enum AbbrevOp {
+    Literal(u64),
+    Fixed(u32),
+    Vbr(u32),
+    Array,
+    Char6,
+    Blob,
+}
+
+struct BitReader<'data> {
+    data: &'data [u8],
+    bit_pos: usize,
+}
+
+impl<'data> BitReader<'data> {
+    fn new(data: &'data [u8]) -> Self {
+        Self { data, bit_pos: 0 }
+    }
+
+    fn remaining_bits(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.bit_pos)
+    }
+
+    fn fixed(&mut self, width: u32) -> Result<u64> {
+        if width as usize > self.remaining_bits() {
+            bail!("Unexpected end of bitcode");
+        }
+        let mut value = 0;
+        for i in 0..width {
+            let byte = self.data[self.bit_pos / 8];
+            let bit = (byte >> (self.bit_pos % 8)) & 1;
+            value |= u64::from(bit) << i;
+            self.bit_pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn vbr(&mut self, width: u32) -> Result<u64> {
+        let continuation = 1 << (width - 1);
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let chunk = self.fixed(width)?;
+            if shift >= 64 {
+                bail!("VBR value too large");
+            }
+            value |= (chunk & (continuation - 1)) << shift;
+            if chunk & continuation == 0 {
+                return Ok(value);
+            }
+            shift += width - 1;
+        }
+    }
+
+    fn align32(&mut self) {
+        self.bit_pos = self.bit_pos.next_multiple_of(32);
+    }
+
+    fn skip_bits(&mut self, bits: usize) -> Result<()> {
+        if bits > self.remaining_bits() {
+            bail!("Unexpected end of bitcode");
+        }
+        self.bit_pos += bits;
+        Ok(())
+    }
+
+    fn blob(&mut self) -> Result<&'data [u8]> {
+        let len = self.vbr(6)? as usize;
+        self.align32();
+        let start = self.bit_pos / 8;
+        let blob = self
+            .data
+            .get(start..start.saturating_add(len))
+            .context("Blob extends past end of bitcode")?;
+        self.bit_pos += len * 8;
+        self.align32();
+        Ok(blob)
+    }
+
+    /// Reads the contents of a block that we've just entered, returning the blob from the first
+    /// blob record.
+    fn read_blob_block(&mut self, abbrev_width: u32) -> Result<&'data [u8]> {
+        let mut abbrevs: Vec<Vec<AbbrevOp>> = Vec::new();
+        let mut result = None;
+        loop {
+            match self.fixed(abbrev_width)? {
+                END_BLOCK => {
+                    self.align32();
+                    return result.context("Block is missing blob record");
+                }
+                ENTER_SUBBLOCK => {
+                    self.vbr(8)?;
+                    self.vbr(4)?;
+                    self.align32();
+                    let num_words = self.fixed(32)? as usize;
+                    self.skip_bits(num_words * 32)?;
+                }
+                DEFINE_ABBREV => abbrevs.push(self.abbrev_definition()?),
+                UNABBREV_RECORD => {
+                    self.vbr(6)?;
+                    let num_ops = self.vbr(6)?;
+                    for _ in 0..num_ops {
+                        self.vbr(6)?;
+                    }
+                }
+                id => {
+                    let abbrev = abbrevs
+                        .get(id as usize - 4)
+                        .context("Reference to undefined abbreviation")?
+                        .clone();
+                    let (code, blob) = self.abbreviated_record(&abbrev)?;
+                    if code == Some(BLOB_RECORD_CODE) && result.is_none() {
+                        result = blob;
+                    }
+                }
+            }
+        }
+    }
+
+    fn abbrev_definition(&mut self) -> Result<Vec<AbbrevOp>> {
+        let num_ops = self.vbr(5)?;
+        let mut ops = Vec::new();
+        for _ in 0..num_ops {
+            if self.fixed(1)? == 1 {
+                ops.push(AbbrevOp::Literal(self.vbr(8)?));
+                continue;
+            }
+            ops.push(match self.fixed(3)? {
+                1 => AbbrevOp::Fixed(self.vbr(5)? as u32),
+                2 => AbbrevOp::Vbr(self.vbr(5)? as u32),
+                3 => AbbrevOp::Array,
+                4 => AbbrevOp::Char6,
+                5 => AbbrevOp::Blob,
+                other => bail!("Unknown abbreviation encoding {other}"),
+            });
+        }
+        Ok(ops)
+    }
+
+    /// Reads a record using `abbrev`, returning its code and blob, if it has one.
+    fn abbreviated_record(
+        &mut self,
+        abbrev: &[AbbrevOp],
+    ) -> Result<(Option<u64>, Option<&'data [u8]>)> {
+        let mut code = None;
+        let mut blob = None;
+        let mut ops = abbrev.iter();
+        while let Some(op) = ops.next() {
+            let value = match *op {
+                AbbrevOp::Array => {
+                    let element = *ops.next().context("Array abbreviation without element")?;
+                    let len = self.vbr(6)?;
+                    for _ in 0..len {
+                        self.scalar(element)?;
+                    }
+                    None
+                }
+                AbbrevOp::Blob => {
+                    blob = Some(self.blob()?);
+                    None
+                }
+                scalar => Some(self.scalar(scalar)?),
+            };
+            if code.is_none() {
+                code = value;
+            }
+        }
+        Ok((code, blob))
+    }
+
+    fn scalar(&mut self, op: AbbrevOp) -> Result<u64> {
+        match op {
+            AbbrevOp::Literal(value) => Ok(value),
+            AbbrevOp::Fixed(width) => self.fixed(width),
+            AbbrevOp::Vbr(width) => self.vbr(width),
+            AbbrevOp::Char6 => self.fixed(6),
+            AbbrevOp::Array | AbbrevOp::Blob => bail!("Unsupported nested abbreviation operand"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn parse_symbol_table() {
+        let strtab = b"mainputs";
+        let mut symtab = words(&[3, 0, 0, 0, 0, 0, 0, 40, 2]);
+        symtab.extend(&[0; 4]);
+        // main: defined.
+        symtab.extend(words(&[0, 4, 0, 0, u32::MAX, 1 << 9]));
+        // puts: undefined.
+        symtab.extend(words(&[4, 4, 0, 0, u32::MAX, FLAG_UNDEFINED]));
+        assert_eq!(
+            parse_symtab(&symtab, strtab).unwrap(),
+            vec![
+                BitcodeSymbol {
+                    name: b"main",
+                    is_undefined: false
+                },
+                BitcodeSymbol {
+                    name: b"puts",
+                    is_undefined: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn vbr() {
+        // A chunk with value 3 and the continuation bit set, then a final chunk with value 1.
+        let mut reader = BitReader::new(&[0b0000_1111]);
+        assert_eq!(reader.vbr(3).unwrap(), 7);
+    }
+
+    #[test]
+    fn detect_bitcode() {
+        assert!(!is_bitcode(b"\x7fELF"));
+        assert!(is_bitcode(b"BC\xC0\xDE\x35\x14"));
+        assert!(is_bitcode(b"\xDE\xC0\x17\x0B"));
+    }
+}
@@ -14,7 +14,7 @@ use gimli::Attribute;
 use gimli::AttributeValue;
 use gimli::Dwarf;
 use gimli::EndianSlice;
-use gimli::LittleEndian;
+use gimli::RunTimeEndian;
 use gimli::Unit;
 use gimli::UnitOffset;
 use std::ffi::OsStr;
@@ -25,6 +25,9 @@ use std::path::Path;
 pub(crate) struct DebugArtifacts<'input> {
     pub(crate) symbol_debug_info: FxHashMap<Symbol<'input>, SymbolDebugInfo<'input>>,
     pub(crate) inlined_functions: Vec<InlinedFunction<'input>>,
+    /// The start address of each function that has a linkage name. Only needed for binaries that
+    /// lack a symbol table, i.e. WebAssembly.
+    pub(crate) function_addresses: Vec<(Symbol<'input>, u64)>,
 }
 
 pub(crate) struct SymbolDebugInfo<'input> {
@@ -65,7 +68,7 @@ impl<'input> CallLocation<'input> {
 
 impl<'input> DebugArtifacts<'input> {
     pub(crate) fn from_dwarf(
-        dwarf: &Dwarf<EndianSlice<'input, LittleEndian>>,
+        dwarf: &Dwarf<EndianSlice<'input, RunTimeEndian>>,
         checker: &Checker,
     ) -> Result<Self> {
         let mut scanner = DwarfScanner::default();
@@ -76,7 +79,7 @@ impl<'input> DebugArtifacts<'input> {
 }
 
 impl<'input> DwarfScanner<'input> {
-    fn index_units(&mut self, dwarf: &Dwarf<EndianSlice<'input, LittleEndian>>) -> Result<()> {
+    fn index_units(&mut self, dwarf: &Dwarf<EndianSlice<'input, RunTimeEndian>>) -> Result<()> {
         let mut unit_headers = dwarf.units();
         while let Some(header) = unit_headers.next()? {
             let Some(debug_offset) = header.offset().as_debug_info_offset() else {
@@ -91,12 +94,12 @@ impl<'input> DwarfScanner<'input> {
 
     fn scan(
         &mut self,
-        dwarf: &Dwarf<EndianSlice<'input, LittleEndian>>,
+        dwarf: &Dwarf<EndianSlice<'input, RunTimeEndian>>,
         checker: &Checker,
     ) -> Result<()> {
         for unit in &self.units {
             let compdir = path_from_opt_slice(unit.comp_dir);
-            if checker.is_in_rust_std(compdir) {
+            if checker.should_skip_std_unit(compdir) {
                 continue;
             }
 
@@ -148,6 +151,13 @@ impl<'input> DwarfScanner<'input> {
                     {
                         self.out.inlined_functions.push(inlined_function);
                     }
+                    if tag == gimli::DW_TAG_subprogram {
+                        if let (Some(symbol), Some(low_pc)) =
+                            (&inline_scanner.names.symbol, inline_scanner.low_pc)
+                        {
+                            self.out.function_addresses.push((symbol.clone(), low_pc));
+                        }
+                    }
                     if tag == gimli::DW_TAG_subprogram || tag == gimli::DW_TAG_variable {
                         if let Some((symbol, debug_info)) =
                             symbol_scanner.get_debug_info(&unit_state)?
@@ -175,7 +185,7 @@ impl<'input> DwarfScanner<'input> {
     fn unit_containing(
         &self,
         offset: gimli::DebugInfoOffset,
-    ) -> Result<&Unit<EndianSlice<'input, LittleEndian>>> {
+    ) -> Result<&Unit<EndianSlice<'input, RunTimeEndian>>> {
         match self.unit_offsets.binary_search(&offset) {
             Ok(0) | Err(0) => {}
             Ok(index) | Err(index) => {
@@ -194,8 +204,8 @@ impl<'input> DwarfScanner<'input> {
 /// from the offset, we can determine information about the subprogram's attributes, but not about
 /// the namespace in which it's contained.
 fn get_subprogram_namespaces(
-    unit: &Unit<EndianSlice<LittleEndian>, usize>,
-    dwarf: &Dwarf<EndianSlice<LittleEndian>>,
+    unit: &Unit<EndianSlice<RunTimeEndian>, usize>,
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
 ) -> Result<FxHashMap<UnitOffset, Namespace>> {
     let mut subprogram_namespaces: FxHashMap<UnitOffset, Namespace> = Default::default();
     let mut stack: Vec<Option<Namespace>> = Vec::new();
@@ -247,9 +257,9 @@ fn get_subprogram_namespaces(
 }
 
 struct UnitState<'input, 'dwarf> {
-    dwarf: &'dwarf Dwarf<EndianSlice<'input, LittleEndian>>,
+    dwarf: &'dwarf Dwarf<EndianSlice<'input, RunTimeEndian>>,
     frames: Vec<FrameState<'input>>,
-    unit: &'dwarf Unit<EndianSlice<'input, LittleEndian>, usize>,
+    unit: &'dwarf Unit<EndianSlice<'input, RunTimeEndian>, usize>,
     compdir: &'input Path,
     subprogram_namespaces: FxHashMap<UnitOffset, Namespace>,
 }
@@ -257,14 +267,14 @@ struct UnitState<'input, 'dwarf> {
 impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
     fn attr_string(
         &self,
-        attr: AttributeValue<EndianSlice<'input, LittleEndian>, usize>,
-    ) -> Result<gimli::EndianSlice<'input, LittleEndian>> {
+        attr: AttributeValue<EndianSlice<'input, RunTimeEndian>, usize>,
+    ) -> Result<gimli::EndianSlice<'input, RunTimeEndian>> {
         Ok(self.dwarf.attr_string(self.unit, attr)?)
     }
 
     fn get_directory_and_filename(
         &self,
-        file_index: AttributeValue<EndianSlice<'input, LittleEndian>, usize>,
+        file_index: AttributeValue<EndianSlice<'input, RunTimeEndian>, usize>,
     ) -> Result<(Option<&'input OsStr>, &'input OsStr), anyhow::Error> {
         let header = self.line_program_header()?;
         let gimli::AttributeValue::FileIndex(file_index) = file_index else {
@@ -285,7 +295,7 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 
     fn line_program_header(
         &self,
-    ) -> Result<&gimli::LineProgramHeader<gimli::EndianSlice<'input, LittleEndian>>> {
+    ) -> Result<&gimli::LineProgramHeader<gimli::EndianSlice<'input, RunTimeEndian>>> {
         let line_program = self
             .unit
             .line_program
@@ -296,21 +306,21 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 
     fn get_symbol_and_name(
         &self,
-        attr: gimli::Attribute<EndianSlice<'input, LittleEndian>>,
+        attr: gimli::Attribute<EndianSlice<'input, RunTimeEndian>>,
         scanner: &DwarfScanner<'input>,
         max_depth: u32,
     ) -> Result<SymbolAndName<'input>> {
         match attr.value() {
             AttributeValue::UnitRef(unit_offset) => {
                 let unit = self.unit;
-                return self.get_symbol_and_name_in_unit(unit, unit_offset, max_depth, scanner);
+                self.get_symbol_and_name_in_unit(unit, unit_offset, max_depth, scanner)
             }
             AttributeValue::DebugInfoRef(offset) => {
                 let unit = scanner.unit_containing(offset)?;
                 let unit_offset = offset
                     .to_unit_offset(&unit.header)
                     .ok_or_else(|| anyhow!("Invalid unit offset"))?;
-                return self.get_symbol_and_name_in_unit(unit, unit_offset, max_depth, scanner);
+                self.get_symbol_and_name_in_unit(unit, unit_offset, max_depth, scanner)
             }
             _ => {
                 bail!("Unsupported abstract_origin type: {:?}", attr.value());
@@ -320,7 +330,7 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 
     fn get_symbol_and_name_in_unit(
         &self,
-        unit: &Unit<EndianSlice<'input, LittleEndian>, usize>,
+        unit: &Unit<EndianSlice<'input, RunTimeEndian>, usize>,
         unit_offset: UnitOffset,
         max_depth: u32,
         scanner: &DwarfScanner<'input>,
@@ -360,7 +370,7 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 
     fn scan_namespace(
         &self,
-        entries: &mut gimli::EntriesRaw<EndianSlice<'input, LittleEndian>>,
+        entries: &mut gimli::EntriesRaw<EndianSlice<'input, RunTimeEndian>>,
         attributes: &[gimli::AttributeSpecification],
     ) -> Result<Option<Namespace>> {
         // TODO: See if we can reduce duplication between this function and
@@ -400,7 +410,7 @@ impl<'input, 'dwarf> UnitState<'input, 'dwarf> {
 struct DwarfScanner<'input> {
     out: DebugArtifacts<'input>,
     unit_offsets: Vec<gimli::DebugInfoOffset>,
-    units: Vec<gimli::Unit<EndianSlice<'input, LittleEndian>>>,
+    units: Vec<gimli::Unit<EndianSlice<'input, RunTimeEndian>>>,
 }
 
 impl<'input> SymbolDebugInfo<'input> {
@@ -416,17 +426,17 @@ impl<'input> SymbolDebugInfo<'input> {
 
 #[derive(Default)]
 struct SymbolDebugInfoScanner<'input> {
-    name: Option<AttributeValue<EndianSlice<'input, LittleEndian>>>,
-    linkage_name: Option<AttributeValue<EndianSlice<'input, LittleEndian>>>,
+    name: Option<AttributeValue<EndianSlice<'input, RunTimeEndian>>>,
+    linkage_name: Option<AttributeValue<EndianSlice<'input, RunTimeEndian>>>,
     line: Option<u32>,
     column: Option<u32>,
-    file_index: Option<AttributeValue<EndianSlice<'input, LittleEndian>>>,
+    file_index: Option<AttributeValue<EndianSlice<'input, RunTimeEndian>>>,
 }
 
 impl<'input> SymbolDebugInfoScanner<'input> {
     fn handle_attribute(
         &mut self,
-        attr: Attribute<EndianSlice<'input, LittleEndian>>,
+        attr: Attribute<EndianSlice<'input, RunTimeEndian>>,
     ) -> Result<()> {
         match attr.name() {
             gimli::DW_AT_name => {
@@ -494,7 +504,7 @@ impl<'input> SymbolDebugInfoScanner<'input> {
     }
 }
 
-fn path_from_opt_slice(slice: Option<gimli::EndianSlice<gimli::LittleEndian>>) -> &Path {
+fn path_from_opt_slice(slice: Option<gimli::EndianSlice<'_, gimli::RunTimeEndian>>) -> &Path {
     slice
         .map(|dir| Path::new(OsStr::from_bytes(dir.slice())))
         .unwrap_or_else(|| Path::new(""))
@@ -527,7 +537,7 @@ pub(crate) struct CallLocation<'input> {
 impl<'input> InlinedFunctionScanner<'input> {
     fn handle_attribute<'dwarf>(
         &mut self,
-        attr: Attribute<EndianSlice<'input, LittleEndian>>,
+        attr: Attribute<EndianSlice<'input, RunTimeEndian>>,
         unit_state: &UnitState<'input, 'dwarf>,
         scanner: &DwarfScanner<'input>,
     ) -> Result<()> {
@@ -35,6 +35,14 @@ impl ObjectFilePath {
             inner: Some(inner),
         })
     }
+
+    /// Returns the path for `member`, which was referenced by the thin archive `archive`.
+    pub(crate) fn in_thin_archive(archive: &Path, member: &Path) -> Self {
+        Self {
+            outer: archive.to_owned(),
+            inner: Some(member.to_owned()),
+        }
+    }
 }
 
 impl Display for ObjectFilePath {
@@ -0,0 +1,186 @@
+//! Determines which parts of a linked binary are reachable from its entry points. This lets us skip
+//! reporting API usages in dead code that happens to have been retained by the linker.
+
+use crate::symbol::Symbol;
+use fxhash::FxHashMap;
+use fxhash::FxHashSet;
+use object::Object;
+use object::ObjectSymbol;
+use object::SymbolKind;
+
+#[derive(Default)]
+pub(crate) struct ReferenceGraph {
+    /// A map from the address of a symbol in the binary to the addresses of the symbols that it
+    /// references.
+    references: FxHashMap<u64, FxHashSet<u64>>,
+
+    /// Addresses from which traversal starts. e.g. the entry point, `main` and exported symbols.
+    roots: FxHashSet<u64>,
+}
+
+impl ReferenceGraph {
+    /// Declares that the symbol at `from_address` references the symbol at `to_address`.
+    pub(crate) fn add_reference(&mut self, from_address: u64, to_address: u64) {
+        if from_address != to_address {
+            self.references
+                .entry(from_address)
+                .or_default()
+                .insert(to_address);
+        }
+    }
+
+    pub(crate) fn add_root(&mut self, address: u64) {
+        self.roots.insert(address);
+    }
+
+    /// Adds roots for `bin`. We treat as roots the entry point, all exported symbols and all
+    /// defined, global symbols that aren't mangled. The latter is because such symbols might be
+    /// referenced from object files that we don't process, e.g. the standard library or the
+    /// allocator shim.
+    pub(crate) fn add_roots_from_bin(&mut self, bin: &object::File) {
+        if bin.entry() != 0 {
+            self.add_root(bin.entry());
+        }
+        if let Ok(exports) = bin.exports() {
+            for export in exports {
+                self.add_root(export.address());
+            }
+        }
+        for sym in bin.symbols() {
+            if !sym.is_definition() || !sym.is_global() || sym.kind() != SymbolKind::Text {
+                continue;
+            }
+            let Ok(name) = sym.name_bytes() else {
+                continue;
+            };
+            if !Symbol::borrowed(name).is_mangled() {
+                self.add_root(sym.address());
+            }
+        }
+    }
+
+    /// Returns the addresses of all symbols that are reachable from our roots.
+    pub(crate) fn reachable(&self) -> FxHashSet<u64> {
+        let mut reachable = FxHashSet::default();
+        let mut pending: Vec<u64> = self.roots.iter().cloned().collect();
+        while let Some(address) = pending.pop() {
+            if !reachable.insert(address) {
+                continue;
+            }
+            if let Some(targets) = self.references.get(&address) {
+                pending.extend(
+                    targets
+                        .iter()
+                        .filter(|target| !reachable.contains(target))
+                        .cloned(),
+                );
+            }
+        }
+        reachable
+    }
+
+    /// Returns a map from each address to the addresses that reference it.
+    pub(crate) fn callers(&self) -> FxHashMap<u64, Vec<u64>> {
+        let mut callers: FxHashMap<u64, Vec<u64>> = FxHashMap::default();
+        for (from, targets) in &self.references {
+            for to in targets {
+                callers.entry(*to).or_default().push(*from);
+            }
+        }
+        callers
+    }
+
+    /// Returns the shortest chain of references that ends at `to` and starts at an entry point to
+    /// the package containing `to`. `in_package` reports whether an address is in that package. An
+    /// entry point is an address in the package that's a root, isn't referenced by anything or is
+    /// referenced from outside the package. `callers` should be the result of calling `callers`.
+    pub(crate) fn entry_path(
+        &self,
+        callers: &FxHashMap<u64, Vec<u64>>,
+        to: u64,
+        mut in_package: impl FnMut(u64) -> bool,
+    ) -> Option<Vec<u64>> {
+        // A breadth-first search backwards from `to`, recording the next address along the path.
+        let mut next: FxHashMap<u64, u64> = FxHashMap::default();
+        let mut pending = std::collections::VecDeque::from([to]);
+        let mut seen = FxHashSet::from_iter([to]);
+        while let Some(address) = pending.pop_front() {
+            let address_callers = callers.get(&address).map(Vec::as_slice).unwrap_or_default();
+            let mut internal_callers = Vec::new();
+            let mut is_entry = self.roots.contains(&address) || address_callers.is_empty();
+            for caller in address_callers {
+                if in_package(*caller) {
+                    internal_callers.push(*caller);
+                } else {
+                    is_entry = true;
+                }
+            }
+            if is_entry {
+                let mut path = vec![address];
+                let mut current = address;
+                while let Some(n) = next.get(&current) {
+                    path.push(*n);
+                    current = *n;
+                }
+                return Some(path);
+            }
+            for caller in internal_callers {
+                if seen.insert(caller) {
+                    next.insert(caller, address);
+                    pending.push_back(caller);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReferenceGraph;
+
+    #[test]
+    fn reachable() {
+        let mut graph = ReferenceGraph::default();
+        graph.add_root(1);
+        graph.add_reference(1, 2);
+        graph.add_reference(2, 3);
+        graph.add_reference(3, 2);
+        graph.add_reference(4, 3);
+        graph.add_reference(5, 5);
+        let mut reachable: Vec<_> = graph.reachable().into_iter().collect();
+        reachable.sort();
+        assert_eq!(reachable, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn entry_path() {
+        // 1 and 2 are in another package. 10 is only referenced from within the package, so isn't
+        // an entry point.
+        let mut graph = ReferenceGraph::default();
+        graph.add_reference(1, 11);
+        graph.add_reference(2, 13);
+        graph.add_reference(10, 11);
+        graph.add_reference(11, 12);
+        graph.add_reference(12, 13);
+        graph.add_reference(13, 14);
+        graph.add_reference(14, 15);
+        let callers = graph.callers();
+        let in_package = |address| address >= 10;
+        assert_eq!(
+            graph.entry_path(&callers, 15, in_package),
+            Some(vec![13, 14, 15])
+        );
+        assert_eq!(
+            graph.entry_path(&callers, 12, in_package),
+            Some(vec![11, 12])
+        );
+        assert_eq!(graph.entry_path(&callers, 10, in_package), Some(vec![10]));
+
+        // A cycle that can't be reached from outside the package has no entry point.
+        graph.add_reference(20, 21);
+        graph.add_reference(21, 20);
+        let callers = graph.callers();
+        assert_eq!(graph.entry_path(&callers, 21, in_package), None);
+    }
+}
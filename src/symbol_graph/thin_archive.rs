@@ -0,0 +1,148 @@
+//! Support for reading thin archives. A thin archive, rather than embedding the data for each
+//! member, just stores a path to where the member can be found. Such archives are produced by `ar
+//! --thin` and by some build caching tools.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+const MAGIC: &[u8] = b"!<thin>\n";
+const HEADER_LEN: usize = 60;
+const HEADER_TERMINATOR: &[u8] = b"`\n";
+
+/// Returns whether the file at `path` is a thin archive.
+pub(crate) fn is_thin_archive(path: &Path) -> Result<bool> {
+    let mut magic = [0; MAGIC.len()];
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open `{}`", path.display()))?;
+    Ok(file.read_exact(&mut magic).is_ok() && magic == MAGIC)
+}
+
+/// Returns the paths of the members of the thin archive at `path`. Relative paths are resolved
+/// relative to the directory containing the archive.
+pub(crate) fn member_paths(path: &Path) -> Result<Vec<PathBuf>> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(parse_member_names(&bytes)
+        .with_context(|| format!("Failed to parse thin archive `{}`", path.display()))?
+        .into_iter()
+        .map(|name| dir.join(name))
+        .collect())
+}
+
+/// Returns the names of the members of a thin archive, given the bytes of the archive.
+fn parse_member_names(bytes: &[u8]) -> Result<Vec<PathBuf>> {
+    let Some(mut rest) = bytes.strip_prefix(MAGIC) else {
+        bail!("Missing thin archive header");
+    };
+    // The extended filename table. GNU ar stores names longer than 15 bytes here and refers to them
+    // by offset. For thin archives it always stores member names here.
+    let mut names_table: &[u8] = &[];
+    let mut names = Vec::new();
+    while !rest.is_empty() {
+        if rest.len() < HEADER_LEN {
+            bail!("Truncated member header");
+        }
+        let (header, after_header) = rest.split_at(HEADER_LEN);
+        if &header[58..] != HEADER_TERMINATOR {
+            bail!("Invalid member header");
+        }
+        let name = trim_spaces(&header[..16]);
+        let size: usize = std::str::from_utf8(trim_spaces(&header[48..58]))
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .context("Invalid member size")?;
+        rest = after_header;
+
+        // The symbol table and the filename table are stored within the archive. Other members
+        // are stored externally.
+        if name == b"/" || name == b"/SYM64/" || name == b"//" {
+            let padded_size = size + size % 2;
+            if rest.len() < size {
+                bail!("Truncated archive member");
+            }
+            if name == b"//" {
+                names_table = &rest[..size];
+            }
+            rest = &rest[padded_size.min(rest.len())..];
+            continue;
+        }
+        let name = if let Some(offset) = name.strip_prefix(b"/") {
+            let offset: usize = std::str::from_utf8(offset)
+                .ok()
+                .and_then(|offset| offset.parse().ok())
+                .context("Invalid offset into filename table")?;
+            let Some(entry) = names_table.get(offset..) else {
+                bail!("Offset {offset} is outside of filename table");
+            };
+            let end = entry
+                .windows(2)
+                .position(|w| w == b"/\n")
+                .unwrap_or(entry.len());
+            &entry[..end]
+        } else {
+            name.strip_suffix(b"/").unwrap_or(name)
+        };
+        let name = std::str::from_utf8(name).context("Member name is not valid UTF-8")?;
+        names.push(PathBuf::from(name));
+    }
+    Ok(names)
+}
+
+fn trim_spaces(bytes: &[u8]) -> &[u8] {
+    let end = bytes
+        .iter()
+        .rposition(|b| *b != b' ')
+        .map(|p| p + 1)
+        .unwrap_or(0);
+    &bytes[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(name: &str, size: usize) -> Vec<u8> {
+        let header = format!("{name:<16}{:<12}{:<6}{:<6}{:<8}{size:<10}`\n", 0, 0, 0, 644);
+        assert_eq!(header.len(), HEADER_LEN);
+        header.into_bytes()
+    }
+
+    #[test]
+    fn parse_thin_archive() {
+        let names_table = b"../objects/a-very-long-object-name.o/\nsub/other.o/\n";
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(header("/", 3));
+        bytes.extend(b"abc\n");
+        bytes.extend(header("//", names_table.len()));
+        bytes.extend(names_table);
+        // Padding, since the table has an odd length.
+        bytes.push(b'\n');
+        bytes.extend(header("/0", 1234));
+        bytes.extend(header("/38", 10));
+        bytes.extend(header("short.o/", 10));
+        assert_eq!(
+            parse_member_names(&bytes).unwrap(),
+            vec![
+                PathBuf::from("../objects/a-very-long-object-name.o"),
+                PathBuf::from("sub/other.o"),
+                PathBuf::from("short.o"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_thin_archive() {
+        assert!(parse_member_names(b"!<arch>\n").is_err());
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(header("/99", 10));
+        assert!(parse_member_names(&bytes).is_err());
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&header("a.o/", 10)[..20]);
+        assert!(parse_member_names(&bytes).is_err());
+    }
+}
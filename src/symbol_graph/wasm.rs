@@ -0,0 +1,602 @@
+//! A minimal parser for WebAssembly modules, sufficient to find out what functions in relocatable
+//! object files reference and to locate functions and debug info in linked modules.
+//!
+//! For object files, we use the `linking` custom section for the symbol table and `reloc.CODE` for
+//! relocations within function bodies. Functions imported from the host, rather than from other
+//! object files, are named `module::field`, e.g. `wasi_snapshot_preview1::fd_write`, so that they can
+//! be matched by API definitions like any other path. Relocations within data, e.g. vtables, aren't
+//! currently followed.
+//!
+//! Addresses within a module are offsets from the start of the code section's contents, which is
+//! what DWARF for WebAssembly uses. The address of a function is the start of its body.
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use std::borrow::Cow;
+use std::ops::Range;
+
+const MAGIC: &[u8] = b"\0asm";
+const VERSION: &[u8] = &[1, 0, 0, 0];
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_CODE: u8 = 10;
+
+const EXTERNAL_FUNCTION: u8 = 0;
+const EXTERNAL_TABLE: u8 = 1;
+const EXTERNAL_MEMORY: u8 = 2;
+const EXTERNAL_GLOBAL: u8 = 3;
+const EXTERNAL_TAG: u8 = 4;
+
+/// The module that undefined functions are imported from, unless they're given an explicit module.
+const DEFAULT_IMPORT_MODULE: &str = "env";
+
+const NAME_SUBSECTION_FUNCTIONS: u8 = 1;
+
+const LINKING_VERSION: u32 = 2;
+const LINKING_SYMBOL_TABLE: u8 = 8;
+
+const SYMBOL_KIND_FUNCTION: u8 = 0;
+const SYMBOL_KIND_DATA: u8 = 1;
+const SYMBOL_KIND_SECTION: u8 = 3;
+const SYMBOL_FLAG_UNDEFINED: u32 = 0x10;
+const SYMBOL_FLAG_EXPLICIT_NAME: u32 = 0x40;
+
+/// Relocation types whose index refers to a function symbol.
+const FUNCTION_RELOCATIONS: &[u8] = &[0, 1, 2, 12, 18, 19, 24, 26];
+
+/// Relocation types whose index refers to a data symbol.
+const DATA_RELOCATIONS: &[u8] = &[3, 4, 5, 11, 14, 15, 16, 17, 21, 23, 25];
+
+/// Relocation types that are followed by an addend.
+const RELOCATIONS_WITH_ADDEND: &[u8] = &[3, 4, 5, 8, 9, 11, 14, 15, 16, 17, 21, 22, 23, 25];
+
+/// Returns whether `bytes` is a WebAssembly module.
+pub(crate) fn is_wasm(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+#[derive(Default)]
+pub(crate) struct WasmModule<'data> {
+    function_imports: Vec<Import<'data>>,
+    exports: Vec<Export<'data>>,
+    /// The section index of the code section.
+    code_section_index: Option<u32>,
+    /// The range of each function body, in order of definition.
+    function_bodies: Vec<Range<u64>>,
+    custom_sections: Vec<(&'data str, &'data [u8])>,
+}
+
+struct Import<'data> {
+    module: &'data str,
+    field: &'data str,
+}
+
+struct Export<'data> {
+    name: &'data str,
+    kind: u8,
+    index: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct WasmReference<'data> {
+    /// The symbol for the function containing the reference.
+    pub(crate) from: &'data str,
+    /// The offset of the reference from the start of the function.
+    pub(crate) offset: u64,
+    /// The symbol that was referenced.
+    pub(crate) target: Cow<'data, str>,
+}
+
+struct LinkingSymbol<'data> {
+    kind: u8,
+    flags: u32,
+    index: Option<u32>,
+    name: Option<&'data str>,
+}
+
+impl<'data> WasmModule<'data> {
+    pub(crate) fn parse(bytes: &'data [u8]) -> Result<Self> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            bail!("Missing WebAssembly magic");
+        };
+        let Some(rest) = rest.strip_prefix(VERSION) else {
+            bail!("Unsupported WebAssembly version");
+        };
+        let mut module = WasmModule::default();
+        let mut reader = Reader::new(rest);
+        let mut section_index = 0;
+        while !reader.is_empty() {
+            let id = reader.u8()?;
+            let size = reader.u32()?;
+            let data = reader.bytes(size as usize)?;
+            match id {
+                SECTION_CUSTOM => {
+                    let mut custom = Reader::new(data);
+                    let name = custom.name()?;
+                    module.custom_sections.push((name, custom.rest()));
+                }
+                SECTION_IMPORT => module.parse_imports(data)?,
+                SECTION_EXPORT => module.parse_exports(data)?,
+                SECTION_CODE => {
+                    module.code_section_index = Some(section_index);
+                    module.parse_code(data)?;
+                }
+                _ => {}
+            }
+            section_index += 1;
+        }
+        Ok(module)
+    }
+
+    fn parse_imports(&mut self, data: &'data [u8]) -> Result<()> {
+        let mut reader = Reader::new(data);
+        for _ in 0..reader.u32()? {
+            let module = reader.name()?;
+            let field = reader.name()?;
+            match reader.u8()? {
+                EXTERNAL_FUNCTION => {
+                    reader.u32()?;
+                    self.function_imports.push(Import { module, field });
+                }
+                EXTERNAL_TABLE => {
+                    reader.u8()?;
+                    reader.limits()?;
+                }
+                EXTERNAL_MEMORY => reader.limits()?,
+                EXTERNAL_GLOBAL => {
+                    reader.u8()?;
+                    reader.u8()?;
+                }
+                EXTERNAL_TAG => {
+                    reader.u8()?;
+                    reader.u32()?;
+                }
+                other => bail!("Unsupported import kind {other}"),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_exports(&mut self, data: &'data [u8]) -> Result<()> {
+        let mut reader = Reader::new(data);
+        for _ in 0..reader.u32()? {
+            self.exports.push(Export {
+                name: reader.name()?,
+                kind: reader.u8()?,
+                index: reader.u32()?,
+            });
+        }
+        Ok(())
+    }
+
+    fn parse_code(&mut self, data: &'data [u8]) -> Result<()> {
+        let mut reader = Reader::new(data);
+        for _ in 0..reader.u32()? {
+            let size = reader.u32()? as u64;
+            let start = reader.position() as u64;
+            reader.bytes(size as usize)?;
+            self.function_bodies.push(start..start + size);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn custom_section(&self, name: &str) -> Option<&'data [u8]> {
+        self.custom_sections
+            .iter()
+            .find(|(section_name, _)| *section_name == name)
+            .map(|(_, data)| *data)
+    }
+
+    /// Returns the address of the function with index `function_index`, provided it's defined in
+    /// this module.
+    fn function_address(&self, function_index: u32) -> Option<u64> {
+        let defined_index = function_index.checked_sub(self.function_imports.len() as u32)?;
+        self.function_bodies
+            .get(defined_index as usize)
+            .map(|body| body.start)
+    }
+
+    /// Returns the address of the start of the function that contains `address`.
+    pub(crate) fn function_containing(&self, address: u64) -> Option<u64> {
+        let index = self
+            .function_bodies
+            .partition_point(|body| body.start <= address);
+        let body = self.function_bodies.get(index.checked_sub(1)?)?;
+        // DWARF sometimes gives the address of the function's size, which precedes the body.
+        Some(body.start).filter(|_| address < body.end)
+    }
+
+    /// Returns the names and addresses of exported functions.
+    pub(crate) fn exported_functions(&self) -> impl Iterator<Item = (&'data str, u64)> + '_ {
+        self.exports
+            .iter()
+            .filter(|export| export.kind == EXTERNAL_FUNCTION)
+            .filter_map(|export| Some((export.name, self.function_address(export.index)?)))
+    }
+
+    /// Returns names and addresses of functions from the `name` section, if present.
+    pub(crate) fn function_names(&self) -> Result<Vec<(&'data str, u64)>> {
+        let mut out = Vec::new();
+        let Some(data) = self.custom_section("name") else {
+            return Ok(out);
+        };
+        let mut reader = Reader::new(data);
+        while !reader.is_empty() {
+            let id = reader.u8()?;
+            let size = reader.u32()?;
+            let mut subsection = Reader::new(reader.bytes(size as usize)?);
+            if id != NAME_SUBSECTION_FUNCTIONS {
+                continue;
+            }
+            for _ in 0..subsection.u32()? {
+                let index = subsection.u32()?;
+                let name = subsection.name()?;
+                if let Some(address) = self.function_address(index) {
+                    out.push((name, address));
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns the references from functions in this module, which must be a relocatable object
+    /// file.
+    pub(crate) fn references(&self) -> Result<Vec<WasmReference<'data>>> {
+        let Some(linking) = self.custom_section("linking") else {
+            bail!("WebAssembly object file has no `linking` section");
+        };
+        let symbols = parse_symbol_table(linking)?;
+        // The first symbol that defines each function, indexed by the function's position in the
+        // code section.
+        let mut defining_symbols: Vec<Option<&LinkingSymbol>> =
+            vec![None; self.function_bodies.len()];
+        for symbol in &symbols {
+            if symbol.kind != SYMBOL_KIND_FUNCTION || symbol.flags & SYMBOL_FLAG_UNDEFINED != 0 {
+                continue;
+            }
+            let Some(defined_index) = symbol
+                .index
+                .and_then(|index| index.checked_sub(self.function_imports.len() as u32))
+            else {
+                continue;
+            };
+            if let Some(slot @ None) = defining_symbols.get_mut(defined_index as usize) {
+                *slot = Some(symbol);
+            }
+        }
+        let function_symbols: Vec<Option<&'data str>> = defining_symbols
+            .into_iter()
+            .map(|symbol| symbol.and_then(|symbol| symbol.name))
+            .collect();
+        let mut references = Vec::new();
+        for (name, data) in &self.custom_sections {
+            if !name.starts_with("reloc.") {
+                continue;
+            }
+            let mut reader = Reader::new(data);
+            if Some(reader.u32()?) != self.code_section_index {
+                continue;
+            }
+            for _ in 0..reader.u32()? {
+                let relocation_type = reader.u8()?;
+                let offset = reader.u64()?;
+                let index = reader.u32()?;
+                if RELOCATIONS_WITH_ADDEND.contains(&relocation_type) {
+                    reader.i64()?;
+                }
+                let Some(target) = self.relocation_target(relocation_type, index, &symbols) else {
+                    continue;
+                };
+                let body_index = self
+                    .function_bodies
+                    .partition_point(|body| body.start <= offset);
+                let Some(body_index) = body_index.checked_sub(1) else {
+                    continue;
+                };
+                let Some(from) = function_symbols[body_index] else {
+                    continue;
+                };
+                references.push(WasmReference {
+                    from,
+                    offset: offset - self.function_bodies[body_index].start,
+                    target,
+                });
+            }
+        }
+        Ok(references)
+    }
+
+    fn relocation_target(
+        &self,
+        relocation_type: u8,
+        index: u32,
+        symbols: &[LinkingSymbol<'data>],
+    ) -> Option<Cow<'data, str>> {
+        if !FUNCTION_RELOCATIONS.contains(&relocation_type)
+            && !DATA_RELOCATIONS.contains(&relocation_type)
+        {
+            return None;
+        }
+        let symbol = symbols.get(index as usize)?;
+        if symbol.kind == SYMBOL_KIND_FUNCTION && symbol.flags & SYMBOL_FLAG_UNDEFINED != 0 {
+            let import = self.function_imports.get(symbol.index? as usize)?;
+            // Functions that will be provided by other object files are imported from `env`.
+            if import.module != DEFAULT_IMPORT_MODULE {
+                return Some(Cow::Owned(format!("{}::{}", import.module, import.field)));
+            }
+            return Some(Cow::Borrowed(symbol.name.unwrap_or(import.field)));
+        }
+        symbol.name.map(Cow::Borrowed)
+    }
+}
+
+fn parse_symbol_table(linking: &[u8]) -> Result<Vec<LinkingSymbol<'_>>> {
+    let mut reader = Reader::new(linking);
+    let version = reader.u32()?;
+    if version != LINKING_VERSION {
+        bail!("Unsupported linking section version {version}");
+    }
+    while !reader.is_empty() {
+        let subsection_type = reader.u8()?;
+        let size = reader.u32()?;
+        let mut subsection = Reader::new(reader.bytes(size as usize)?);
+        if subsection_type != LINKING_SYMBOL_TABLE {
+            continue;
+        }
+        let mut symbols = Vec::new();
+        for _ in 0..subsection.u32()? {
+            let kind = subsection.u8()?;
+            let flags = subsection.u32()?;
+            let is_undefined = flags & SYMBOL_FLAG_UNDEFINED != 0;
+            let mut symbol = LinkingSymbol {
+                kind,
+                flags,
+                index: None,
+                name: None,
+            };
+            match kind {
+                SYMBOL_KIND_DATA => {
+                    symbol.name = Some(subsection.name()?);
+                    if !is_undefined {
+                        symbol.index = Some(subsection.u32()?);
+                        subsection.u64()?;
+                        subsection.u64()?;
+                    }
+                }
+                SYMBOL_KIND_SECTION => symbol.index = Some(subsection.u32()?),
+                _ => {
+                    symbol.index = Some(subsection.u32()?);
+                    if !is_undefined || flags & SYMBOL_FLAG_EXPLICIT_NAME != 0 {
+                        symbol.name = Some(subsection.name()?);
+                    }
+                }
+            }
+            symbols.push(symbol);
+        }
+        return Ok(symbols);
+    }
+    Ok(Vec::new())
+}
+
+struct Reader<'data> {
+    data: &'data [u8],
+    position: usize,
+}
+
+impl<'data> Reader<'data> {
+    fn new(data: &'data [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn rest(&self) -> &'data [u8] {
+        &self.data[self.position..]
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        let value = *self
+            .data
+            .get(self.position)
+            .context("Unexpected end of WebAssembly data")?;
+        self.position += 1;
+        Ok(value)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'data [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.saturating_add(len))
+            .context("Unexpected end of WebAssembly data")?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= 64 {
+                bail!("LEB128 value too large");
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        u32::try_from(self.u64()?).context("LEB128 value too large for u32")
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= 64 {
+                bail!("LEB128 value too large");
+            }
+            value |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'data str> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.bytes(len)?).context("WebAssembly name is not valid UTF-8")
+    }
+
+    fn limits(&mut self) -> Result<()> {
+        let flags = self.u8()?;
+        self.u64()?;
+        if flags & 1 != 0 {
+            self.u64()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leb(mut value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return out;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut out = leb(name.len() as u64);
+        out.extend(name.as_bytes());
+        out
+    }
+
+    fn section(id: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![id];
+        out.extend(leb(contents.len() as u64));
+        out.extend(contents);
+        out
+    }
+
+    fn custom_section(section_name: &str, contents: &[u8]) -> Vec<u8> {
+        let mut data = name(section_name);
+        data.extend(contents);
+        section(SECTION_CUSTOM, &data)
+    }
+
+    /// Builds an object file that imports `fd_write` from WASI and `helper` from another object
+    /// file. It defines `foo`, which calls `fd_write`, and `bar`, which calls `helper`.
+    fn test_object() -> Vec<u8> {
+        let mut imports = vec![2];
+        for (module, field) in [("wasi_snapshot_preview1", "fd_write"), ("env", "helper")] {
+            imports.extend(name(module));
+            imports.extend(name(field));
+            imports.extend([EXTERNAL_FUNCTION, 0]);
+        }
+
+        // Each body has no locals, then a call with a padded function index, then `end`.
+        let body = [0, 0x10, 0x80, 0x80, 0x80, 0x80, 0, 0x0b];
+        let mut code = vec![2];
+        for _ in 0..2 {
+            code.extend(leb(body.len() as u64));
+            code.extend(body);
+        }
+
+        let mut exports = vec![1];
+        exports.extend(name("foo"));
+        exports.extend([EXTERNAL_FUNCTION, 2]);
+
+        let mut symbol_table = vec![4];
+        symbol_table.extend([SYMBOL_KIND_FUNCTION, SYMBOL_FLAG_UNDEFINED as u8, 0]);
+        symbol_table.extend([SYMBOL_KIND_FUNCTION, SYMBOL_FLAG_UNDEFINED as u8, 1]);
+        for (index, symbol_name) in [(2, "foo"), (3, "bar")] {
+            symbol_table.extend([SYMBOL_KIND_FUNCTION, 0, index]);
+            symbol_table.extend(name(symbol_name));
+        }
+        let mut linking = leb(LINKING_VERSION.into());
+        linking.push(LINKING_SYMBOL_TABLE);
+        linking.extend(leb(symbol_table.len() as u64));
+        linking.extend(symbol_table);
+
+        // Relocations for the function indices in each call. The code section is section 1.
+        let reloc_code = [1, 2, 0, 4, 0, 0, 13, 1];
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION);
+        bytes.extend(section(SECTION_IMPORT, &imports));
+        bytes.extend(section(SECTION_CODE, &code));
+        bytes.extend(section(SECTION_EXPORT, &exports));
+        bytes.extend(custom_section("linking", &linking));
+        bytes.extend(custom_section("reloc.CODE", &reloc_code));
+        bytes
+    }
+
+    #[test]
+    fn references() {
+        let bytes = test_object();
+        let module = WasmModule::parse(&bytes).unwrap();
+        assert_eq!(
+            module.references().unwrap(),
+            vec![
+                WasmReference {
+                    from: "foo",
+                    offset: 2,
+                    target: Cow::Borrowed("wasi_snapshot_preview1::fd_write"),
+                },
+                WasmReference {
+                    from: "bar",
+                    offset: 2,
+                    target: Cow::Borrowed("helper"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn function_addresses() {
+        let bytes = test_object();
+        let module = WasmModule::parse(&bytes).unwrap();
+        assert_eq!(
+            module.exported_functions().collect::<Vec<_>>(),
+            vec![("foo", 2)]
+        );
+        assert_eq!(module.function_containing(5), Some(2));
+        assert_eq!(module.function_containing(10), None);
+        assert_eq!(module.function_containing(11), Some(11));
+        assert_eq!(module.function_containing(1), None);
+    }
+
+    #[test]
+    fn detect_wasm() {
+        assert!(is_wasm(b"\0asm\x01\0\0\0"));
+        assert!(!is_wasm(b"\x7fELF"));
+        assert!(WasmModule::parse(b"\0asm\x0d\0\x01\0").is_err());
+    }
+}
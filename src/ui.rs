@@ -2,10 +2,9 @@
 //! them.
 
 use crate::checker::Checker;
-use crate::crate_index::CrateIndex;
+use crate::cli::Args;
 use crate::events::AppEvent;
 use crate::problem_store::ProblemStoreRef;
-use crate::Args;
 use anyhow::Result;
 use clap::ValueEnum;
 use log::info;
@@ -19,8 +18,18 @@ use std::thread::JoinHandle;
 #[cfg(feature = "ui")]
 mod basic_term;
 #[cfg(feature = "ui")]
+mod editor;
+#[cfg(feature = "ui")]
 mod full_term;
+mod init_ui;
+#[cfg(feature = "ui")]
+mod notifications;
 mod null_ui;
+#[cfg(feature = "ui")]
+mod web_ui;
+
+#[cfg(feature = "ui")]
+pub(crate) use web_ui::ServeOptions;
 
 #[derive(ValueEnum, Debug, Clone, Copy, Default)]
 pub(crate) enum Kind {
@@ -30,6 +39,10 @@ pub(crate) enum Kind {
     Basic,
     #[cfg(feature = "ui")]
     Full,
+    /// Used by the `serve` subcommand, which needs a port, so can't be selected with `--ui`.
+    #[cfg(feature = "ui")]
+    #[value(skip)]
+    Web,
 }
 
 trait UserInterface: Send {
@@ -45,10 +58,21 @@ pub(crate) fn start_ui(
     config_path: &Path,
     checker: &Arc<Mutex<Checker>>,
     problem_store: ProblemStoreRef,
-    crate_index: Arc<CrateIndex>,
     event_receiver: Receiver<AppEvent>,
+    event_sender: &Sender<AppEvent>,
     abort_sender: Sender<()>,
 ) -> Result<JoinHandle<Result<()>>> {
+    if matches!(
+        args.command,
+        Some(crate::cli::Command::Init) | Some(crate::cli::Command::Baseline)
+    ) {
+        info!("Starting init UI");
+        let baseline = matches!(args.command, Some(crate::cli::Command::Baseline));
+        let mut ui = init_ui::InitUi::new(config_path.to_owned(), checker, abort_sender, baseline);
+        return Ok(std::thread::Builder::new()
+            .name("UI".to_owned())
+            .spawn(move || ui.run(problem_store, event_receiver))?);
+    }
     let mut ui: Box<dyn UserInterface> = match args.ui_kind() {
         Kind::None => {
             info!("Starting null UI");
@@ -60,6 +84,7 @@ pub(crate) fn start_ui(
             Box::new(basic_term::BasicTermUi::new(
                 config_path.to_owned(),
                 checker,
+                args.editor.clone(),
             ))
         }
         #[cfg(feature = "ui")]
@@ -68,30 +93,95 @@ pub(crate) fn start_ui(
             Box::new(full_term::FullTermUi::new(
                 config_path.to_owned(),
                 checker,
-                crate_index,
+                event_sender.clone(),
                 abort_sender,
+                args.editor.clone(),
             )?)
         }
+        #[cfg(feature = "ui")]
+        Kind::Web => {
+            let Some(crate::cli::Command::Serve(options)) = &args.command else {
+                anyhow::bail!("Web UI requires the `serve` subcommand");
+            };
+            info!("Starting web UI");
+            Box::new(web_ui::WebUi::new(config_path.to_owned(), checker, options))
+        }
     };
     Ok(std::thread::Builder::new()
         .name("UI".to_owned())
         .spawn(move || ui.run(problem_store, event_receiver))?)
 }
 
+/// Applies `edit` to the config at `config_path`, then resolves problem `id`, replacing it with
+/// whatever problems the edit says should follow. Other problems that no longer need an edit as a
+/// result are also resolved.
+#[cfg(feature = "ui")]
+fn apply_fix(
+    config_path: &Path,
+    config: &crate::config::Config,
+    pstore: &mut crate::problem_store::ProblemStore,
+    id: crate::problem_store::ProblemId,
+    edit: &dyn crate::config_editor::Edit,
+    opts: &crate::config_editor::EditOpts,
+) -> Result<()> {
+    let mut editor = crate::config_editor::ConfigEditor::from_file(config_path)?;
+    edit.apply(&mut editor, opts)?;
+    editor.write(config_path)?;
+    pstore.replace(id, edit.replacement_problems());
+    pstore.resolve_problems_with_empty_diff(&editor, config);
+    Ok(())
+}
+
 impl Args {
     pub(crate) fn should_capture_cargo_output(&self) -> bool {
+        self.has_interactive_ui()
+    }
+
+    /// Returns whether problems will be shown to the user for them to resolve.
+    pub(crate) fn has_interactive_ui(&self) -> bool {
         !matches!(self.ui_kind(), Kind::None)
     }
 
+    /// Returns whether we're running the `serve` subcommand.
+    pub(crate) fn is_serve(&self) -> bool {
+        #[cfg(feature = "ui")]
+        if matches!(self.command, Some(crate::cli::Command::Serve(..))) {
+            return true;
+        }
+        false
+    }
+
     fn ui_kind(&self) -> Kind {
-        if self.no_ui {
+        if self.no_ui
+            || self.ci
+            || self.output_format != crate::sarif::OutputFormat::Text
+            || matches!(
+                self.command,
+                Some(crate::cli::Command::Init)
+                    | Some(crate::cli::Command::Baseline)
+                    | Some(crate::cli::Command::Edit(..))
+                    | Some(crate::cli::Command::Lsp)
+                    | Some(crate::cli::Command::LintConfig(
+                        crate::lint_config::LintConfigOptions { autofix: true }
+                    ))
+            )
+        {
             return Kind::None;
         }
+        #[cfg(feature = "ui")]
+        if self.is_serve() {
+            return Kind::Web;
+        }
         if let Some(kind) = self.ui {
             return kind;
         }
         #[cfg(feature = "ui")]
         if std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+            // The full terminal UI holds on to the terminal until it exits, which in watch mode
+            // would be never, so output from cargo would be held back indefinitely.
+            if matches!(self.command, Some(crate::cli::Command::Watch(..))) {
+                return Kind::Basic;
+            }
             return Kind::Full;
         }
         Kind::None
@@ -1,6 +1,8 @@
 //! A basic text-based terminal UI. Doesn't use curses, just prints stuff and prompts for what to
-//! do.
+//! do. Since it only ever reads lines from stdin, it works in environments that can't show the full
+//! terminal UI, such as dumb terminals and editor shells, and can be driven by piping in answers.
 
+use super::notifications::Notifier;
 use crate::checker::Checker;
 use crate::config;
 use crate::config::ApiName;
@@ -10,8 +12,10 @@ use crate::config_editor;
 use crate::config_editor::ConfigEditor;
 use crate::config_editor::Edit;
 use crate::events::AppEvent;
+use crate::location::SourceLocation;
 use crate::outcome::Outcome;
 use crate::problem::Problem;
+use crate::problem_store::ProblemId;
 use crate::problem_store::ProblemStoreRef;
 use crate::sandbox;
 use anyhow::bail;
@@ -36,6 +40,9 @@ pub(crate) struct BasicTermUi {
     stdin_recv: Receiver<String>,
     config_last_modified: Option<SystemTime>,
     checker: Arc<Mutex<Checker>>,
+    /// The `--editor` command template.
+    editor: Option<String>,
+    notifier: Notifier,
 }
 
 impl super::UserInterface for BasicTermUi {
@@ -47,7 +54,12 @@ impl super::UserInterface for BasicTermUi {
         while let Ok(event) = event_receiver.recv() {
             match event {
                 AppEvent::Shutdown => return Ok(()),
-                AppEvent::ProblemsAdded => {}
+                AppEvent::ProblemsAdded => {
+                    let config = self.checker.lock().unwrap().config.clone();
+                    self.notifier
+                        .problems_added(&config, problem_store.lock().len());
+                }
+                AppEvent::ProgressChanged | AppEvent::Input(..) => continue,
             }
             loop {
                 let pstore_lock = problem_store.lock();
@@ -81,12 +93,14 @@ impl super::UserInterface for BasicTermUi {
                     println!("No automatic fixes available. Edit config manually to continue.");
                 } else {
                     println!("dN) Diff for fix N. e.g 'd1'");
+                    println!("y)  Apply fix 1");
                 }
-                match self.prompt_for_fix(&fixes)? {
-                    Outcome::Continue => {
-                        problem_store.lock().resolve(problem_index);
-                    }
-                    Outcome::GiveUp => {}
+                println!("n)  Reject. This will fail the build");
+                println!("e)  Edit cackle.toml");
+                println!("q)  Quit");
+                if self.prompt_for_fix(problem_index, &fixes, &problem_store)? == Outcome::GiveUp {
+                    problem_store.lock().abort();
+                    return Ok(());
                 }
             }
         }
@@ -95,12 +109,18 @@ impl super::UserInterface for BasicTermUi {
 }
 
 impl BasicTermUi {
-    pub(crate) fn new(config_path: PathBuf, checker: &Arc<Mutex<Checker>>) -> Self {
+    pub(crate) fn new(
+        config_path: PathBuf,
+        checker: &Arc<Mutex<Checker>>,
+        editor: Option<String>,
+    ) -> Self {
         Self {
             config_last_modified: config_modification_time(&config_path),
             config_path,
             stdin_recv: start_stdin_channel(),
             checker: checker.clone(),
+            editor,
+            notifier: Notifier::new(),
         }
     }
 
@@ -153,13 +173,25 @@ impl BasicTermUi {
         Ok(Outcome::Continue)
     }
 
-    fn prompt_for_fix(&mut self, fixes: &[Box<dyn Edit>]) -> Result<Outcome> {
+    /// Prompts for what to do about problem `id` until the user either resolves it or gives up.
+    fn prompt_for_fix(
+        &mut self,
+        id: ProblemId,
+        fixes: &[Box<dyn Edit>],
+        problem_store: &ProblemStoreRef,
+    ) -> Result<Outcome> {
         loop {
             match self.get_action(fixes.len()) {
                 Ok(Action::ApplyFix(n)) => {
-                    let mut editor = ConfigEditor::from_file(&self.config_path)?;
-                    fixes[n].apply(&mut editor, &Default::default())?;
-                    editor.write(&self.config_path)?;
+                    let config = self.checker.lock().unwrap().config.clone();
+                    super::apply_fix(
+                        &self.config_path,
+                        &config,
+                        &mut problem_store.lock(),
+                        id,
+                        fixes[n].as_ref(),
+                        &Default::default(),
+                    )?;
                     self.config_last_modified = config_modification_time(&self.config_path);
                     return Ok(Outcome::Continue);
                 }
@@ -175,8 +207,22 @@ impl BasicTermUi {
                         &editor.to_toml(),
                     );
                 }
+                Ok(Action::Reject) => {
+                    problem_store.lock().reject(id);
+                    return Ok(Outcome::Continue);
+                }
+                Ok(Action::Edit) => {
+                    // Once the editor exits, we'll notice if the config was changed and retry.
+                    let location = SourceLocation::new(self.config_path.as_path(), 1, None);
+                    if let Err(error) = super::editor::run(self.editor.as_deref(), &location) {
+                        println!("{error:#}");
+                    }
+                }
                 Ok(Action::GiveUp) => return Ok(Outcome::GiveUp),
-                Ok(Action::Retry) => return Ok(Outcome::Continue),
+                Ok(Action::Retry) => {
+                    problem_store.lock().resolve(id);
+                    return Ok(Outcome::Continue);
+                }
                 Err(error) => {
                     println!("{error}")
                 }
@@ -194,6 +240,7 @@ impl BasicTermUi {
         loop {
             match self.stdin_recv.recv_timeout(Duration::from_millis(250)) {
                 Ok(line) => {
+                    self.notifier.activity();
                     response = line.to_lowercase();
                     break;
                 }
@@ -208,12 +255,24 @@ impl BasicTermUi {
                 Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(Action::GiveUp),
             }
         }
-        let response = response.trim();
-        if let Some(rest) = response.strip_prefix('d') {
-            return Ok(Action::ShowDiff(fix_index(rest, num_fixes)?));
-        }
-        Ok(Action::ApplyFix(fix_index(response, num_fixes)?))
+        parse_response(&response, num_fixes)
+    }
+}
+
+/// Parses a line entered by the user in response to a problem that has `num_fixes` fixes.
+fn parse_response(response: &str, num_fixes: usize) -> Result<Action> {
+    let response = response.trim();
+    match response {
+        "y" => return Ok(Action::ApplyFix(fix_index("1", num_fixes)?)),
+        "n" => return Ok(Action::Reject),
+        "e" => return Ok(Action::Edit),
+        "q" => return Ok(Action::GiveUp),
+        _ => {}
+    }
+    if let Some(rest) = response.strip_prefix('d') {
+        return Ok(Action::ShowDiff(fix_index(rest, num_fixes)?));
     }
+    Ok(Action::ApplyFix(fix_index(response, num_fixes)?))
 }
 
 fn start_stdin_channel() -> Receiver<String> {
@@ -246,11 +305,14 @@ fn fix_index(n_str: &str, num_fixes: usize) -> Result<usize> {
     Ok(n - 1)
 }
 
+#[derive(Debug, PartialEq, Eq)]
 enum Action {
     Retry,
     GiveUp,
     ApplyFix(usize),
     ShowDiff(usize),
+    Reject,
+    Edit,
 }
 
 fn config_modification_time(config_path: &Path) -> Option<SystemTime> {
@@ -293,3 +355,22 @@ fn show_diff(original: &str, updated: &str) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_response;
+    use super::Action;
+
+    #[test]
+    fn responses() {
+        assert_eq!(parse_response("y\n", 2).unwrap(), Action::ApplyFix(0));
+        assert_eq!(parse_response("2", 2).unwrap(), Action::ApplyFix(1));
+        assert_eq!(parse_response("d1", 2).unwrap(), Action::ShowDiff(0));
+        assert_eq!(parse_response("n", 0).unwrap(), Action::Reject);
+        assert_eq!(parse_response("e", 0).unwrap(), Action::Edit);
+        assert_eq!(parse_response("q", 0).unwrap(), Action::GiveUp);
+        assert!(parse_response("y", 0).is_err());
+        assert!(parse_response("3", 2).is_err());
+        assert!(parse_response("maybe", 2).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+//! Opening source files in the user's editor.
+
+use crate::location::SourceLocation;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use std::process::Command;
+
+/// Used if the user doesn't supply a template with `--editor`. `{editor}` is replaced with
+/// `$VISUAL` or `$EDITOR`.
+const DEFAULT_TEMPLATE: &str = "{editor} +{line} {file}";
+
+/// Opens `location` in an editor, then waits for the editor to exit. `template` is as documented
+/// for `--editor`. The caller is responsible for making sure that the editor can use the terminal.
+pub(super) fn run(template: Option<&str>, location: &SourceLocation) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    let args = expand(template.unwrap_or(DEFAULT_TEMPLATE), &editor, location);
+    let Some((program, args)) = args.split_first() else {
+        bail!("Editor command is empty");
+    };
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run editor `{program}`"))?;
+    if !status.success() {
+        bail!("Editor `{program}` exited with {status}");
+    }
+    Ok(())
+}
+
+/// Splits `template` on whitespace, then replaces placeholders in each argument.
+fn expand(template: &str, editor: &str, location: &SourceLocation) -> Vec<String> {
+    let file = location.filename().display().to_string();
+    let line = location.line().to_string();
+    let column = location.column().unwrap_or(1).to_string();
+    template
+        .split_whitespace()
+        .flat_map(|arg| {
+            // `$EDITOR` may itself contain arguments. e.g. "code --wait".
+            if arg == "{editor}" {
+                editor.split_whitespace().map(str::to_owned).collect()
+            } else {
+                vec![arg
+                    .replace("{editor}", editor)
+                    .replace("{file}", &file)
+                    .replace("{line}", &line)
+                    .replace("{column}", &column)]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::location::SourceLocation;
+    use std::path::Path;
+
+    #[test]
+    fn expand() {
+        let location = SourceLocation::new(Path::new("/src/main.rs"), 12, Some(5));
+        assert_eq!(
+            super::expand(super::DEFAULT_TEMPLATE, "code --wait", &location),
+            vec!["code", "--wait", "+12", "/src/main.rs"]
+        );
+        assert_eq!(
+            super::expand("emacsclient +{line}:{column} {file}", "vi", &location),
+            vec!["emacsclient", "+12:5", "/src/main.rs"]
+        );
+        let location = SourceLocation::new(Path::new("/src/lib.rs"), 3, None);
+        assert_eq!(
+            super::expand("code --goto {file}:{line}:{column}", "vi", &location),
+            vec!["code", "--goto", "/src/lib.rs:3:1"]
+        );
+    }
+}
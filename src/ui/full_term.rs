@@ -1,18 +1,23 @@
 //! A fullscreen terminal user interface.
 
+use super::notifications::Notifier;
+use crate::build_progress::BuildProgress;
 use crate::checker::Checker;
 use crate::crate_index::CrateIndex;
 use crate::events::AppEvent;
+use crate::location::SourceLocation;
 use crate::problem_store::ProblemStoreRef;
 use anyhow::Result;
 use crossterm::event::Event;
-use crossterm::event::KeyCode;
+use crossterm::event::MouseEvent;
+use crossterm::event::MouseEventKind;
+use keys::Action;
+use keys::KeyMap;
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::Constraint;
 use ratatui::layout::Direction;
 use ratatui::layout::Layout;
 use ratatui::layout::Rect;
-use ratatui::style::Color;
 use ratatui::style::Modifier;
 use ratatui::style::Style;
 use ratatui::widgets::Block;
@@ -29,32 +34,41 @@ use std::io::Stdout;
 use std::path::PathBuf;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
-use std::sync::mpsc::TryRecvError;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
+use theme::Theme;
 
+mod keys;
 mod problems_ui;
+mod theme;
 
 pub(crate) struct FullTermUi {
     config_path: PathBuf,
+    /// Used to send ourselves input and progress events.
+    event_sender: Sender<AppEvent>,
     abort_sender: Sender<()>,
     crate_index: Arc<CrateIndex>,
     checker: Arc<Mutex<Checker>>,
+    /// The `--editor` command template.
+    editor: Option<String>,
 }
 
 impl FullTermUi {
     pub(crate) fn new(
         config_path: PathBuf,
         checker: &Arc<Mutex<Checker>>,
-        crate_index: Arc<CrateIndex>,
+        event_sender: Sender<AppEvent>,
         abort_sender: Sender<()>,
+        editor: Option<String>,
     ) -> Result<Self> {
         Ok(Self {
             config_path,
+            event_sender,
             abort_sender,
-            crate_index,
+            crate_index: checker.lock().unwrap().crate_index.clone(),
             checker: checker.clone(),
+            editor,
         })
     }
 }
@@ -70,7 +84,11 @@ impl Terminal {
     fn new() -> Result<Terminal> {
         crossterm::terminal::enable_raw_mode()?;
         let mut stdout = std::io::stdout();
-        crossterm::execute!(stdout, crossterm::terminal::EnterAlternateScreen)?;
+        crossterm::execute!(
+            stdout,
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::event::EnableMouseCapture
+        )?;
         let backend = ratatui::backend::CrosstermBackend::new(stdout);
         let term = ratatui::Terminal::new(backend)?;
         let output_lock = std::io::stderr().lock();
@@ -79,6 +97,28 @@ impl Terminal {
             _output_lock: output_lock,
         })
     }
+
+    /// Opens `location` in the user's editor, suspending the UI while the editor runs.
+    fn open_editor(&mut self, template: Option<&str>, location: &SourceLocation) -> Result<()> {
+        crossterm::terminal::disable_raw_mode()?;
+        crossterm::execute!(
+            self.term.backend_mut(),
+            crossterm::event::DisableMouseCapture,
+            crossterm::terminal::LeaveAlternateScreen,
+            crossterm::cursor::Show
+        )?;
+        let result = super::editor::run(template, location);
+        crossterm::execute!(
+            self.term.backend_mut(),
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::event::EnableMouseCapture
+        )?;
+        crossterm::terminal::enable_raw_mode()?;
+        // The editor will have drawn over whatever we last drew, so make sure everything gets
+        // redrawn.
+        self.term.clear()?;
+        result
+    }
 }
 
 impl super::UserInterface for FullTermUi {
@@ -87,19 +127,48 @@ impl super::UserInterface for FullTermUi {
         problem_store: ProblemStoreRef,
         event_receiver: Receiver<AppEvent>,
     ) -> Result<()> {
+        let mut needs_redraw = true;
+        let mut error = None;
+        match event_receiver.recv() {
+            Err(..) | Ok(AppEvent::Shutdown) => return Ok(()),
+            Ok(..) => {}
+        }
+        // A mistake in the key bindings or theme shouldn't stop the user from resolving problems,
+        // so we report it, then carry on with the defaults.
+        let ui_config = self.checker.lock().unwrap().config.raw.ui.clone();
+        let keys = KeyMap::from_config(&ui_config.keys).unwrap_or_else(|e| {
+            error = Some(e.context("Using default keys"));
+            KeyMap::default()
+        });
+        let theme = Theme::from_config(&ui_config.theme).unwrap_or_else(|e| {
+            error = Some(e.context("Using default theme"));
+            Theme::default()
+        });
         let mut screen = problems_ui::ProblemsUi::new(
             problem_store.clone(),
             self.crate_index.clone(),
             self.checker.clone(),
             self.config_path.clone(),
+            keys,
+            theme.clone(),
         );
-        let mut needs_redraw = true;
-        let mut error = None;
-        match event_receiver.recv() {
-            Ok(AppEvent::ProblemsAdded) => {}
-            Err(..) | Ok(AppEvent::Shutdown) => return Ok(()),
-        }
+        let mut notifier = Notifier::new();
         let mut terminal = Terminal::new()?;
+        let input_handled = spawn_input_thread(self.event_sender.clone(), crossterm::event::read)?;
+        {
+            let checker = self.checker.lock().unwrap();
+            let listener = &self.event_sender;
+            checker
+                .progress
+                .lock()
+                .unwrap()
+                .set_listener(listener.clone());
+            checker
+                .build_output
+                .lock()
+                .unwrap()
+                .set_listener(listener.clone());
+        }
         loop {
             if screen.quit_requested() {
                 let pstore = &mut problem_store.lock();
@@ -120,78 +189,137 @@ impl super::UserInterface for FullTermUi {
                 terminal.term.draw(|f| {
                     screen.render(f);
                     if let Some(e) = error.as_ref() {
-                        render_error(f, e);
+                        render_error(f, e, &theme);
                     }
                 })?;
-                needs_redraw = false;
             }
-            match event_receiver.try_recv() {
+            match event_receiver.recv() {
                 Ok(AppEvent::ProblemsAdded) => {
                     needs_redraw = true;
+                    let config = self.checker.lock().unwrap().config.clone();
+                    notifier.problems_added(&config, problem_store.lock().len());
                     if let Err(e) = screen.problems_added() {
                         error = Some(e);
                     }
                 }
-                Ok(AppEvent::Shutdown) => {
-                    return Ok(());
+                Ok(AppEvent::ProgressChanged) => {
+                    needs_redraw = screen.progress_changed();
                 }
-                Err(TryRecvError::Disconnected) => return Ok(()),
-                Err(TryRecvError::Empty) => {
-                    // TODO: Consider spawning a separate thread to read crossterm events, then feed
-                    // them into the main event channel. That way we can avoid polling.
-                    if crossterm::event::poll(Duration::from_millis(100))? {
-                        needs_redraw = true;
-                        let Ok(Event::Key(key)) = crossterm::event::read() else {
-                            continue;
-                        };
-                        // When we're displaying an error, any key will dismiss the error popup. The key
-                        // should then be ignored.
-                        if error.take().is_some() {
+                Ok(AppEvent::Input(event)) => {
+                    if matches!(
+                        event,
+                        Event::Key(..)
+                            | Event::Mouse(MouseEvent {
+                                kind: MouseEventKind::Down(..),
+                                ..
+                            })
+                    ) {
+                        notifier.activity();
+                    }
+                    // Mouse movement is reported, but we don't do anything with it.
+                    needs_redraw = !matches!(
+                        event,
+                        Event::Mouse(MouseEvent {
+                            kind: MouseEventKind::Moved,
+                            ..
+                        })
+                    );
+                    let result = match event {
+                        // When we're displaying an error, any key will dismiss the error popup. The
+                        // key should then be ignored.
+                        Event::Key(key) if error.take().is_some() => {
                             // But still process the quit key, since if the error came from
                             // rendering, we'd like a way to get out.
-                            if key.code == KeyCode::Char('q') {
+                            if screen.is_quit_key(key) {
                                 problem_store.lock().abort();
                             }
-                            continue;
+                            None
+                        }
+                        // Likewise, clicking anywhere dismisses the error.
+                        Event::Mouse(mouse) if error.is_some() => {
+                            if matches!(mouse.kind, MouseEventKind::Down(..)) {
+                                error = None;
+                            }
+                            None
                         }
-                        if let Err(e) = screen.handle_key(key) {
+                        Event::Key(key) => Some(screen.handle_key(key)),
+                        Event::Mouse(mouse) => Some(screen.handle_mouse(mouse)),
+                        _ => None,
+                    };
+                    if let Some(Err(e)) = result {
+                        error = Some(e);
+                    }
+                    if let Some(location) = screen.take_location_to_open() {
+                        if let Err(e) = terminal
+                            .open_editor(self.editor.as_deref(), &location)
+                            .and_then(|()| screen.editor_closed())
+                        {
                             error = Some(e);
                         }
                     }
+                    let _ = input_handled.send(());
                 }
+                Ok(AppEvent::Shutdown) | Err(..) => return Ok(()),
             }
         }
     }
 }
 
+/// Spawns a thread that reads events from the terminal using `read_event` and sends them to
+/// `event_sender`. After each event, the thread waits until it's told, via the returned sender, that
+/// the event has been handled. That way it doesn't read input that was intended for an editor that
+/// we're running.
+fn spawn_input_thread(
+    event_sender: Sender<AppEvent>,
+    mut read_event: impl FnMut() -> std::io::Result<Event> + Send + 'static,
+) -> Result<Sender<()>> {
+    let (handled_sender, handled_receiver) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("UI input".to_owned())
+        .spawn(move || {
+            // Once the UI has shut down, one of these will fail. The thread may be left blocked
+            // waiting for input until then, but it only ever reads input while the UI is waiting
+            // for it.
+            while let Ok(event) = read_event() {
+                if event_sender.send(AppEvent::Input(event)).is_err()
+                    || handled_receiver.recv().is_err()
+                {
+                    break;
+                }
+            }
+        })?;
+    Ok(handled_sender)
+}
+
 impl Drop for Terminal {
     fn drop(&mut self) {
         let _ = crossterm::terminal::disable_raw_mode();
         let _ = crossterm::execute!(
             self.term.backend_mut(),
+            crossterm::event::DisableMouseCapture,
             crossterm::terminal::LeaveAlternateScreen
         );
     }
 }
 
-fn render_build_progress(f: &mut Frame, area: Rect) {
+fn render_build_progress(f: &mut Frame, area: Rect, progress: &BuildProgress, theme: &Theme) {
     let block = Block::default()
         .title("Building")
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Yellow));
-    let paragraph = Paragraph::new("Build in progress...")
+        .border_style(Style::default().fg(theme.highlight));
+    let paragraph = Paragraph::new(progress.to_string())
         .block(block)
         .wrap(Wrap { trim: false });
     f.render_widget(Clear, area);
     f.render_widget(paragraph, area);
 }
 
-fn render_error(f: &mut Frame, error: &anyhow::Error) {
+fn render_error(f: &mut Frame, error: &anyhow::Error, theme: &Theme) {
     let area = message_area(f.size());
     let block = Block::default()
         .title("Error")
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Red));
+        .border_style(Style::default().fg(theme.error));
     let paragraph = Paragraph::new(format!("{error:#}"))
         .block(block)
         .wrap(Wrap { trim: false });
@@ -226,6 +354,7 @@ fn centre(target: u16, available: u16) -> Vec<Constraint> {
     ]
 }
 
+/// Renders a list with the item at `index` selected. Returns the index of the first item shown.
 fn render_list(
     f: &mut Frame,
     title: &str,
@@ -233,29 +362,80 @@ fn render_list(
     active: bool,
     area: Rect,
     index: usize,
-) {
+    theme: &Theme,
+) -> usize {
     let items: Vec<_> = items.collect();
     let mut block = Block::default().title(title).borders(Borders::ALL);
     if active {
         block = block
             .border_type(BorderType::Thick)
-            .border_style(Style::default().fg(Color::Yellow));
+            .border_style(Style::default().fg(theme.highlight));
     }
     let mut style = Style::default().add_modifier(Modifier::REVERSED);
     if active {
-        style = style.fg(Color::Yellow);
+        style = style.fg(theme.highlight);
     }
     let list = List::new(items).block(block).highlight_style(style);
     let mut list_state = ListState::default();
     list_state.select(Some(index));
     f.render_stateful_widget(list, area, &mut list_state);
+    list_state.offset()
 }
 
-/// Increment or decrement `counter`, wrapping at `len`. `keycode` must be Down or Up.
-fn update_counter(counter: &mut usize, key_code: KeyCode, len: usize) {
-    match key_code {
-        KeyCode::Up => *counter = (*counter + len - 1) % len,
-        KeyCode::Down => *counter = (*counter + len + 1) % len,
+/// Increment or decrement `counter`, wrapping at `len`. `action` must be Down or Up.
+fn update_counter(counter: &mut usize, action: Action, len: usize) {
+    if len == 0 {
+        return;
+    }
+    match action {
+        Action::Up => *counter = (*counter + len - 1) % len,
+        Action::Down => *counter = (*counter + len + 1) % len,
         _ => panic!("Invalid call to update_counter"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::events::AppEvent;
+    use crossterm::event::Event;
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::sync::mpsc::TryRecvError;
+    use std::time::Duration;
+
+    #[test]
+    fn input_thread_waits_for_events_to_be_handled() {
+        let (input_sender, input_receiver) = std::sync::mpsc::channel();
+        let (event_sender, event_receiver) = std::sync::mpsc::channel();
+        let read_event = move || {
+            input_receiver
+                .recv()
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+        };
+        let handled = super::spawn_input_thread(event_sender, read_event).unwrap();
+        input_sender.send(Event::FocusGained).unwrap();
+        input_sender.send(Event::FocusLost).unwrap();
+        let timeout = Duration::from_secs(10);
+
+        assert_eq!(
+            event_receiver.recv_timeout(timeout),
+            Ok(AppEvent::Input(Event::FocusGained))
+        );
+        // The second event is available, but shouldn't be read until we've handled the first.
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(event_receiver.try_recv(), Err(TryRecvError::Empty));
+
+        handled.send(()).unwrap();
+        assert_eq!(
+            event_receiver.recv_timeout(timeout),
+            Ok(AppEvent::Input(Event::FocusLost))
+        );
+
+        // Once reading fails, the thread should exit.
+        drop(input_sender);
+        handled.send(()).unwrap();
+        assert_eq!(
+            event_receiver.recv_timeout(timeout),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+}
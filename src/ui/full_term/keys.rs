@@ -0,0 +1,277 @@
+//! Mapping from keys to the actions that they perform. Each action has default keys, which can be
+//! overridden from the `[ui.keys]` section of cackle.toml.
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+use crossterm::event::KeyCode;
+use crossterm::event::KeyEvent;
+use crossterm::event::KeyModifiers;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+/// Something that the user can ask the UI to do. What an action does may depend on what's being
+/// shown. e.g. `Usages` both shows and hides the list of usages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Confirm,
+    Back,
+    Quit,
+    Help,
+    Fixes,
+    Reject,
+    Usages,
+    Mark,
+    Accept,
+    AcceptCrate,
+    AcceptApi,
+    Open,
+    Graph,
+    Undo,
+    Redo,
+    Search,
+    FilterCrate,
+    FilterApi,
+    FilterKind,
+    PackageTree,
+    Diagnostics,
+    Backtrace,
+    Comment,
+    Output,
+    PackageDetails,
+    Dashboard,
+}
+
+/// Each action with its name in `[ui.keys]` and its default keys. Where a key is bound to more
+/// than one action, earlier actions take precedence, provided that they do something in the
+/// current mode. For example `d` rejects marked problems if there are any, otherwise shows usages.
+const ACTIONS: &[(Action, &str, &[&str])] = &[
+    (Action::Up, "up", &["up"]),
+    (Action::Down, "down", &["down"]),
+    (Action::Left, "left", &["left"]),
+    (Action::Right, "right", &["right"]),
+    (Action::PageUp, "page_up", &["pgup"]),
+    (Action::PageDown, "page_down", &["pgdn"]),
+    (Action::Home, "home", &["home"]),
+    (Action::End, "end", &["end"]),
+    (Action::Confirm, "confirm", &["enter"]),
+    (Action::Back, "back", &["esc"]),
+    (Action::Quit, "quit", &["q"]),
+    (Action::Help, "help", &["h", "?"]),
+    (Action::Fixes, "fixes", &["f"]),
+    (Action::Reject, "reject", &["d"]),
+    (Action::Usages, "usages", &["d"]),
+    (Action::Mark, "mark", &["space"]),
+    (Action::Accept, "accept", &["a"]),
+    (Action::AcceptCrate, "accept_crate", &["C"]),
+    (Action::AcceptApi, "accept_api", &["N"]),
+    (Action::Open, "open", &["e"]),
+    (Action::Graph, "graph", &["g"]),
+    (Action::Undo, "undo", &["u"]),
+    (Action::Redo, "redo", &["r"]),
+    (Action::Search, "search", &["/"]),
+    (Action::FilterCrate, "filter_crate", &["c"]),
+    (Action::FilterApi, "filter_api", &["n"]),
+    (Action::FilterKind, "filter_kind", &["k"]),
+    (Action::PackageTree, "package_tree", &["t"]),
+    (Action::Diagnostics, "diagnostics", &["i"]),
+    (Action::Backtrace, "backtrace", &["b"]),
+    (Action::Comment, "comment", &["c"]),
+    (Action::Output, "output", &["o"]),
+    (Action::PackageDetails, "package_details", &["p"]),
+    (Action::Dashboard, "dashboard", &["s"]),
+];
+
+/// Names of keys other than printable characters.
+const KEY_NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::Char(' '), "space"),
+    (KeyCode::Enter, "enter"),
+    (KeyCode::Esc, "esc"),
+    (KeyCode::Tab, "tab"),
+    (KeyCode::Backspace, "backspace"),
+    (KeyCode::Delete, "delete"),
+    (KeyCode::Insert, "insert"),
+    (KeyCode::Up, "up"),
+    (KeyCode::Down, "down"),
+    (KeyCode::Left, "left"),
+    (KeyCode::Right, "right"),
+    (KeyCode::PageUp, "pgup"),
+    (KeyCode::PageDown, "pgdn"),
+    (KeyCode::Home, "home"),
+    (KeyCode::End, "end"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Key {
+    code: KeyCode,
+    ctrl: bool,
+}
+
+pub(super) struct KeyMap {
+    /// The keys bound to each action, in the same order as `ACTIONS`.
+    bindings: Vec<(Action, Vec<Key>)>,
+}
+
+impl KeyMap {
+    /// Returns the default bindings, overridden by `config`, which maps action names to keys.
+    pub(super) fn from_config(config: &BTreeMap<String, Vec<String>>) -> Result<Self> {
+        let mut bindings: Vec<(Action, Vec<Key>)> = ACTIONS
+            .iter()
+            .map(|(action, _, keys)| {
+                (
+                    *action,
+                    keys.iter().map(|k| parse_key(k).unwrap()).collect(),
+                )
+            })
+            .collect();
+        for (name, keys) in config {
+            let Some(index) = ACTIONS.iter().position(|(_, n, _)| n == name) else {
+                bail!("Unknown action `{name}` in ui.keys");
+            };
+            bindings[index].1 = keys
+                .iter()
+                .map(|key| parse_key(key))
+                .collect::<Result<_>>()?;
+        }
+        Ok(Self { bindings })
+    }
+
+    /// Returns the actions bound to `key`, highest precedence first.
+    pub(super) fn actions(&self, key: KeyEvent) -> Vec<Action> {
+        let key = Key {
+            code: key.code,
+            ctrl: key.modifiers.contains(KeyModifiers::CONTROL),
+        };
+        self.bindings
+            .iter()
+            .filter(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+
+    /// Returns the first key bound to `action`, for display. e.g. in a button.
+    pub(super) fn label(&self, action: Action) -> String {
+        self.keys(action)
+            .first()
+            .map(|key| key.to_string())
+            .unwrap_or_else(|| "none".to_owned())
+    }
+
+    /// Returns all the keys bound to `actions`, separated by slashes. e.g. "h/?".
+    pub(super) fn labels(&self, actions: &[Action]) -> String {
+        let labels: Vec<String> = actions
+            .iter()
+            .flat_map(|action| self.keys(*action))
+            .map(|key| key.to_string())
+            .collect();
+        if labels.is_empty() {
+            return "none".to_owned();
+        }
+        labels.join("/")
+    }
+
+    fn keys(&self, action: Action) -> &[Key] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, keys)| keys.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::from_config(&BTreeMap::new()).unwrap()
+    }
+}
+
+/// Parses a key such as "a", "?", "enter" or "ctrl-n".
+fn parse_key(text: &str) -> Result<Key> {
+    let (ctrl, name) = match text.strip_prefix("ctrl-") {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, text),
+    };
+    let mut chars = name.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(ch), None) => KeyCode::Char(ch),
+        _ => KEY_NAMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(code, _)| *code)
+            .ok_or_else(|| anyhow!("Unknown key `{text}` in ui.keys"))?,
+    };
+    Ok(Key { code, ctrl })
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ctrl {
+            write!(f, "ctrl-")?;
+        }
+        match (
+            self.code,
+            KEY_NAMES.iter().find(|(code, _)| *code == self.code),
+        ) {
+            (_, Some((_, name))) => write!(f, "{name}"),
+            (KeyCode::Char(ch), None) => write!(f, "{ch}"),
+            (code, None) => write!(f, "{code:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Action;
+    use super::KeyMap;
+    use crossterm::event::KeyCode;
+    use crossterm::event::KeyEvent;
+    use crossterm::event::KeyModifiers;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn overrides() {
+        let config: BTreeMap<String, Vec<String>> = [
+            ("up".to_owned(), vec!["k".to_owned(), "up".to_owned()]),
+            ("page_down".to_owned(), vec!["ctrl-d".to_owned()]),
+        ]
+        .into_iter()
+        .collect();
+        let keys = KeyMap::from_config(&config).unwrap();
+        assert_eq!(
+            keys.actions(KeyEvent::from(KeyCode::Char('k'))),
+            vec![Action::Up, Action::FilterKind]
+        );
+        assert_eq!(
+            keys.actions(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL)),
+            vec![Action::PageDown]
+        );
+        assert_eq!(
+            keys.actions(KeyEvent::from(KeyCode::Char('d'))),
+            vec![Action::Reject, Action::Usages]
+        );
+        assert_eq!(keys.label(Action::Up), "k");
+        assert_eq!(keys.labels(&[Action::Up, Action::Help]), "k/up/h/?");
+        assert_eq!(keys.label(Action::PageDown), "ctrl-d");
+        assert_eq!(keys.label(Action::Mark), "space");
+    }
+
+    #[test]
+    fn invalid_config() {
+        let config: BTreeMap<String, Vec<String>> = [("jump".to_owned(), vec!["j".to_owned()])]
+            .into_iter()
+            .collect();
+        assert!(KeyMap::from_config(&config).is_err());
+        let config: BTreeMap<String, Vec<String>> = [("up".to_owned(), vec!["uparrow".to_owned()])]
+            .into_iter()
+            .collect();
+        assert!(KeyMap::from_config(&config).is_err());
+    }
+}
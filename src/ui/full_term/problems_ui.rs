@@ -1,8 +1,13 @@
 //! Terminal user interface for showing and resolving detected problems.
 
 use super::centre_area;
+use super::keys::Action;
+use super::keys::KeyMap;
 use super::render_list;
+use super::theme::Theme;
 use super::update_counter;
+use crate::build_output::BuildOutput;
+use crate::build_progress::BuildProgress;
 use crate::checker::ApiUsage;
 use crate::checker::BinLocation;
 use crate::checker::Checker;
@@ -12,6 +17,7 @@ use crate::config_editor::ConfigEditor;
 use crate::config_editor::Edit;
 use crate::config_editor::EditOpts;
 use crate::crate_index::CrateIndex;
+use crate::crate_index::DepKind;
 use crate::crate_index::PackageId;
 use crate::location::SourceLocation;
 use crate::problem::OffTreeApiUsage;
@@ -26,11 +32,19 @@ use anyhow::Context;
 use anyhow::Result;
 use crossterm::event::KeyCode;
 use crossterm::event::KeyEvent;
+use crossterm::event::MouseButton;
+use crossterm::event::MouseEvent;
+use crossterm::event::MouseEventKind;
+use filter::Filter;
+use filter::Toggle;
+use fxhash::FxHashSet;
+use graph::Graph;
+use journal::Journal;
+use proposal::Proposal;
 use ratatui::layout::Constraint;
 use ratatui::layout::Direction;
 use ratatui::layout::Layout;
 use ratatui::layout::Rect;
-use ratatui::style::Color;
 use ratatui::style::Modifier;
 use ratatui::style::Style;
 use ratatui::text::Line;
@@ -44,6 +58,8 @@ use ratatui::widgets::Row;
 use ratatui::widgets::Table;
 use ratatui::widgets::Wrap;
 use ratatui::Frame;
+use std::cell::Cell;
+use std::cell::RefCell;
 use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
@@ -53,6 +69,10 @@ use std::sync::MutexGuard;
 use tui_input::backend::crossterm::EventHandler;
 
 mod diff;
+mod filter;
+mod graph;
+mod journal;
+mod proposal;
 mod syntax_styling;
 
 pub(super) struct ProblemsUi {
@@ -69,6 +89,45 @@ pub(super) struct ProblemsUi {
     checker: Arc<Mutex<Checker>>,
     comment: Option<String>,
     previous_comments: Vec<String>,
+    progress: Arc<Mutex<BuildProgress>>,
+    /// The generation of `progress` that was last rendered.
+    progress_generation: Cell<u64>,
+    build_output: Arc<Mutex<BuildOutput>>,
+    /// The value of `build_output.total_lines()` when build output was last rendered.
+    build_output_rendered: Cell<usize>,
+    /// Which problems are shown. `problem_index` is an index into the problems that are shown.
+    filter: Filter,
+    /// Problems that have been marked so that they can be accepted or rejected together. May
+    /// contain problems that have since been resolved.
+    marked: FxHashSet<ProblemId>,
+    journal: Journal,
+    /// A source location that the user has asked to open in their editor.
+    location_to_open: Option<SourceLocation>,
+    /// How many lines the details pane has been scrolled down by with the mouse wheel.
+    details_scroll: u16,
+    /// Where things were last rendered, so that we can tell what mouse events are for.
+    layout: RefCell<LastLayout>,
+    keys: KeyMap,
+    theme: Theme,
+}
+
+/// Where things were last rendered.
+#[derive(Default)]
+struct LastLayout {
+    list: Rect,
+    /// What each row of the list was for, starting from the first row shown.
+    list_rows: Vec<ListRow>,
+    details: Rect,
+    buttons: Rect,
+}
+
+/// What a row in the list was for.
+#[derive(Clone, Copy)]
+enum ListRow {
+    Problem(usize),
+    Edit(usize),
+    Usage(usize),
+    Frame(usize),
 }
 
 #[derive(Debug)]
@@ -76,11 +135,21 @@ enum Mode {
     SelectProblem,
     SelectEdit,
     SelectUsage,
+    /// A change to the config is shown so that the user can confirm it before it gets written.
+    ConfirmEdit(Box<Proposal>),
     SetComment(tui_input::Input),
+    Search(tui_input::Input),
     Backtrace(Vec<backtrace::Frame>),
+    /// The chain from the selected problem's crate to the API that it uses.
+    Graph(Graph),
     PromptAutoAccept,
     ShowPackageTree,
     ShowInternalDiagnostics,
+    /// Output from cargo is shown. Contains the number of the last line shown, counting from the
+    /// start of the build, or None if we're following new output as it arrives.
+    BuildOutput(Option<usize>),
+    /// Overall progress of the build and of reviewing problems.
+    Dashboard,
     Help,
 }
 
@@ -90,12 +159,22 @@ impl ProblemsUi {
     }
 
     pub(super) fn render(&self, f: &mut Frame) {
+        let outer = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(1)])
+            .split(f.size());
         let chunks = if self.show_package_details {
-            split_vertical(f.size(), &[30, 50, 20])
+            split_vertical(outer[0], &[30, 50, 20])
         } else {
-            split_vertical(f.size(), &[35, 65])
+            split_vertical(outer[0], &[35, 65])
         };
         let (top, middle) = (chunks[0], chunks[1]);
+        {
+            let mut layout = self.layout.borrow_mut();
+            layout.details = middle;
+            layout.buttons = outer[1];
+            layout.list_rows.clear();
+        }
 
         self.render_problems(f, top);
 
@@ -134,25 +213,174 @@ impl ProblemsUi {
                 Mode::Backtrace(frames) => {
                     self.render_backtrace_source(frames, f, middle);
                 }
-                Mode::PromptAutoAccept => render_auto_accept(f),
+                Mode::ConfirmEdit(proposal) => {
+                    render_proposal(proposal, &self.keys, &self.theme, f, middle)
+                }
+                Mode::Graph(graph) => render_graph(graph, &self.theme, f, middle),
+                Mode::PromptAutoAccept => render_auto_accept(&self.keys, &self.theme, f),
                 Mode::ShowPackageTree => self.render_package_tree(f),
                 Mode::ShowInternalDiagnostics => self.render_internal_diagnostics(f),
-                Mode::SetComment(input) => self.render_comment_input(input, f),
-                Mode::Help => render_help(f, previous_mode),
+                Mode::BuildOutput(last_line) => self.render_build_output(*last_line, f),
+                Mode::Dashboard => self.render_dashboard(f),
+                Mode::SetComment(input) => render_text_input("Set comment", input, &self.theme, f),
+                Mode::Search(input) => render_text_input("Search problems", input, &self.theme, f),
+                Mode::Help => render_help(f, previous_mode, &self.keys, &self.theme),
             }
             previous_mode = Some(mode);
         }
+        self.render_buttons(f, outer[1]);
     }
 
-    pub(super) fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
-        let Some(mode) = self.modes.last_mut() else {
-            return Ok(());
+    pub(super) fn handle_mouse(&mut self, mouse: MouseEvent) -> Result<()> {
+        let layout = self.layout.borrow();
+        let (column, row) = (mouse.column, mouse.row);
+        let in_list = contains(layout.list, column, row);
+        let in_details = contains(layout.details, column, row);
+        let button = contains(layout.buttons, column, row)
+            .then(|| button_at(&self.buttons(), column - layout.buttons.x))
+            .flatten();
+        let list_row = row
+            .checked_sub(layout.list.y + 1)
+            .and_then(|row| layout.list_rows.get(row as usize).copied());
+        drop(layout);
+        let in_list_mode = matches!(
+            self.modes.last(),
+            Some(Mode::SelectProblem | Mode::SelectEdit | Mode::SelectUsage | Mode::Backtrace(..))
+        );
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(action) = button {
+                    return self.handle_action(action).map(|_| ());
+                }
+                if let (Some(list_row), true) = (list_row.filter(|_| in_list), in_list_mode) {
+                    self.select_list_row(list_row);
+                }
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let action = if mouse.kind == MouseEventKind::ScrollUp {
+                    Action::Up
+                } else {
+                    Action::Down
+                };
+                if let Some(Mode::BuildOutput(last_line)) = self.modes.last_mut() {
+                    scroll_build_output(last_line, action, 3, &self.build_output.lock().unwrap());
+                } else if in_list && in_list_mode {
+                    return self.handle_action(action).map(|_| ());
+                } else if in_details {
+                    self.details_scroll = if action == Action::Up {
+                        self.details_scroll.saturating_sub(3)
+                    } else {
+                        self.details_scroll.saturating_add(3)
+                    };
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Selects whatever was shown in the list at `row` in response to it being clicked.
+    fn select_list_row(&mut self, row: ListRow) {
+        match row {
+            ListRow::Problem(index) => {
+                while !matches!(self.modes.last(), Some(Mode::SelectProblem)) {
+                    self.modes.pop();
+                }
+                if index != self.problem_index {
+                    self.select_problem(index);
+                }
+            }
+            ListRow::Edit(index) => self.edit_index = index,
+            ListRow::Usage(index) => {
+                if matches!(self.modes.last(), Some(Mode::Backtrace(..))) {
+                    self.modes.pop();
+                }
+                self.usage_index = index;
+            }
+            ListRow::Frame(index) => self.backtrace_index = index,
+        }
+    }
+
+    /// Returns the buttons to show for the current mode. Clicking a button performs its action.
+    fn buttons(&self) -> Vec<Button> {
+        let button = |action, label| Button::new(self.keys.label(action), label, action);
+        let mut buttons = match self.modes.last() {
+            Some(Mode::SelectProblem) => vec![
+                button(Action::Fixes, "Fixes"),
+                button(Action::Usages, "Usages"),
+                button(Action::Mark, "Mark"),
+                button(Action::Accept, "Accept"),
+                button(Action::Graph, "Graph"),
+                button(Action::Undo, "Undo"),
+                button(Action::Search, "Search"),
+            ],
+            Some(Mode::SelectEdit) => vec![
+                button(Action::Confirm, "Apply"),
+                button(Action::Open, "Edit first"),
+                button(Action::Comment, "Comment"),
+                button(Action::Back, "Back"),
+            ],
+            Some(Mode::SelectUsage) => vec![
+                button(Action::Open, "Open"),
+                button(Action::Backtrace, "Backtrace"),
+                button(Action::Fixes, "Fixes"),
+                button(Action::Back, "Back"),
+            ],
+            Some(Mode::Backtrace(..)) => {
+                vec![button(Action::Open, "Open"), button(Action::Back, "Back")]
+            }
+            Some(Mode::Graph(..)) => {
+                vec![button(Action::Open, "Open"), button(Action::Graph, "Close")]
+            }
+            Some(Mode::ConfirmEdit(..)) => vec![
+                button(Action::Confirm, "Write"),
+                button(Action::Open, "Edit"),
+                button(Action::Back, "Cancel"),
+            ],
+            Some(Mode::PromptAutoAccept) => vec![
+                button(Action::Confirm, "Accept"),
+                button(Action::Back, "Cancel"),
+            ],
+            // Keys typed while entering text go into the text, so we only offer ways out, which
+            // always use the same keys.
+            Some(Mode::SetComment(..) | Mode::Search(..)) => {
+                return vec![
+                    Button::new("enter".to_owned(), "Done", Action::Confirm),
+                    Button::new("esc".to_owned(), "Cancel", Action::Back),
+                ];
+            }
+            _ => vec![button(Action::Back, "Back")],
         };
-        match (mode, key.code) {
-            (Mode::SetComment(_), KeyCode::Esc) => {
-                self.modes.pop();
+        buttons.extend([
+            button(Action::Dashboard, "Progress"),
+            button(Action::Output, "Output"),
+            button(Action::Help, "Help"),
+            button(Action::Quit, "Quit"),
+        ]);
+        buttons
+    }
+
+    fn render_buttons(&self, f: &mut Frame, area: Rect) {
+        let key_style = Style::default().add_modifier(Modifier::REVERSED);
+        let mut spans = Vec::new();
+        for button in self.buttons() {
+            spans.push(Span::styled(format!(" {} ", button.key_label), key_style));
+            spans.push(Span::raw(format!(" {} ", button.label)));
+        }
+        f.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    pub(super) fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
+        // While text is being entered, keys go into the text, so only a few fixed keys have any
+        // other effect.
+        match (self.modes.last_mut(), key.code) {
+            (Some(Mode::SetComment(..) | Mode::Search(..)), KeyCode::Esc) => {
+                self.handle_action(Action::Back)?;
             }
-            (Mode::SetComment(input), KeyCode::Up | KeyCode::Down) => {
+            (Some(Mode::SetComment(..) | Mode::Search(..)), KeyCode::Enter) => {
+                self.handle_action(Action::Confirm)?;
+            }
+            (Some(Mode::SetComment(input)), KeyCode::Up | KeyCode::Down) => {
                 let value = input.value();
                 let mut index = self
                     .previous_comments
@@ -169,7 +397,41 @@ impl ProblemsUi {
                     None => *input = tui_input::Input::default(),
                 }
             }
-            (Mode::SetComment(input), KeyCode::Enter) => {
+            (Some(Mode::SetComment(input)), _) => {
+                input.handle_event(&crossterm::event::Event::Key(key));
+            }
+            (Some(Mode::Search(input)), _) => {
+                input.handle_event(&crossterm::event::Event::Key(key));
+                self.filter.set_search(input.value());
+                self.select_problem(0);
+            }
+            (Some(Mode::ShowPackageTree), _) => {
+                self.modes.pop();
+            }
+            _ => {
+                for action in self.keys.actions(key) {
+                    if self.handle_action(action)? {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Performs `action` in the current mode. Returns whether the action does anything in this
+    /// mode, so that if it doesn't, another action bound to the same key can be tried.
+    fn handle_action(&mut self, action: Action) -> Result<bool> {
+        let num_modes = self.modes.len();
+        let num_marked = self.num_marked();
+        let Some(mode) = self.modes.last_mut() else {
+            return Ok(false);
+        };
+        match (mode, action) {
+            (Mode::SetComment(_), Action::Back) => {
+                self.modes.pop();
+            }
+            (Mode::SetComment(input), Action::Confirm) => {
                 let value = input.value();
                 if value.is_empty() {
                     self.comment = None;
@@ -183,77 +445,195 @@ impl ProblemsUi {
                 }
                 self.modes.pop();
             }
-            (Mode::SetComment(input), _) => {
-                input.handle_event(&crossterm::event::Event::Key(key));
+            (Mode::Search(_), Action::Back) => {
+                self.filter.set_search("");
+                self.select_problem(0);
+                self.modes.pop();
             }
-            (_, KeyCode::Char('q')) => self.modes.clear(),
-            (Mode::SelectProblem, KeyCode::Up | KeyCode::Down) => {
-                update_counter(
-                    &mut self.problem_index,
-                    key.code,
-                    self.problem_store.lock().len(),
-                );
+            (Mode::Search(_), Action::Confirm) => {
+                self.modes.pop();
+            }
+            (_, Action::Quit) => self.modes.clear(),
+            (Mode::SelectProblem, Action::Up | Action::Down) => {
+                let num_problems = self.visible_problems(&self.problem_store.lock()).len();
+                let mut index = self.problem_index;
+                update_counter(&mut index, action, num_problems);
+                self.select_problem(index);
+            }
+            (Mode::SelectProblem, Action::Left | Action::Right) => {
+                let num_usages = self.usages().len();
+                let action = if action == Action::Left {
+                    Action::Up
+                } else {
+                    Action::Down
+                };
+                update_counter(&mut self.usage_index, action, num_usages);
+            }
+            (Mode::SelectProblem, Action::Mark) => {
+                if let Some((id, _)) = self.selected_problem(&self.problem_store.lock()) {
+                    if !self.marked.remove(&id) {
+                        self.marked.insert(id);
+                    }
+                }
+                let num_problems = self.visible_problems(&self.problem_store.lock()).len();
+                let mut index = self.problem_index;
+                update_counter(&mut index, Action::Down, num_problems);
+                self.select_problem(index);
+            }
+            (Mode::SelectProblem, Action::Accept) if num_marked > 0 => {
+                let marked: Vec<ProblemId> = self.marked.iter().copied().collect();
+                self.accept_problems(&marked)?;
+            }
+            (Mode::SelectProblem, Action::Reject) if num_marked > 0 => {
+                let mut pstore = self.problem_store.lock();
+                let marked: Vec<ProblemId> = pstore
+                    .deduplicated_into_iter()
+                    .map(|(id, _)| id)
+                    .filter(|id| self.marked.contains(id))
+                    .collect();
+                for id in marked {
+                    pstore.reject(id);
+                }
+                drop(pstore);
+                self.marked.clear();
+                self.select_problem(0);
+            }
+            (Mode::SelectProblem, Action::AcceptCrate) => {
+                self.accept_like_selected(Toggle::Crate)?
+            }
+            (Mode::SelectProblem, Action::AcceptApi) => self.accept_like_selected(Toggle::Api)?,
+            (Mode::SelectProblem | Mode::SelectUsage, Action::Open) => {
+                let usages = self.usages();
+                let Some(usage) = usages.get(self.usage_index).or(usages.first()) else {
+                    bail!("Sorry. This problem doesn't have a source location to open");
+                };
+                self.location_to_open = Some(usage.source_location().clone());
             }
-            (Mode::SelectEdit, KeyCode::Up | KeyCode::Down) => {
+            (Mode::Backtrace(frames), Action::Open) => {
+                let Some(location) = frames
+                    .get(self.backtrace_index)
+                    .and_then(|frame| frame.source_location.clone())
+                else {
+                    bail!("Debug info didn't have source location");
+                };
+                self.location_to_open = Some(location);
+            }
+            (Mode::SelectProblem, Action::Graph) => {
+                let pstore = self.problem_store.lock();
+                let Some((_, problem)) = self.selected_problem(&pstore) else {
+                    return Ok(true);
+                };
+                let graph = Graph::for_problem(problem)?;
+                drop(pstore);
+                self.modes.push(Mode::Graph(graph));
+            }
+            (Mode::Graph(graph), Action::Up | Action::Down | Action::Left | Action::Right) => {
+                graph.handle_action(action)
+            }
+            (Mode::Graph(graph), Action::Open | Action::Confirm) => {
+                let Some(location) = graph.selected_location() else {
+                    bail!("Sorry. The selected node doesn't have a source location to open");
+                };
+                self.location_to_open = Some(location.clone());
+            }
+            (Mode::Graph(..), Action::Graph) => {
+                self.modes.pop();
+            }
+            (Mode::SelectProblem, Action::Undo) => self.undo_or_redo(Journal::undo)?,
+            (Mode::SelectProblem, Action::Redo) => self.undo_or_redo(Journal::redo)?,
+            (Mode::SelectProblem, Action::Search) => {
+                self.modes.push(Mode::Search(self.filter.search().into()));
+            }
+            (Mode::SelectProblem, Action::FilterCrate) => self.toggle_filter(Toggle::Crate)?,
+            (Mode::SelectProblem, Action::FilterApi) => self.toggle_filter(Toggle::Api)?,
+            (Mode::SelectProblem, Action::FilterKind) => self.toggle_filter(Toggle::Kind)?,
+            (Mode::SelectProblem, Action::Back) if self.filter.is_active() => {
+                self.filter = Filter::default();
+                self.select_problem(0);
+            }
+            (Mode::SelectEdit, Action::Up | Action::Down) => {
                 let num_edits = self.edits().len();
-                update_counter(&mut self.edit_index, key.code, num_edits);
+                update_counter(&mut self.edit_index, action, num_edits);
             }
-            (Mode::SelectUsage, KeyCode::Up | KeyCode::Down) => {
+            (Mode::SelectUsage, Action::Up | Action::Down) => {
                 let num_usages = self.usages().len();
-                update_counter(&mut self.usage_index, key.code, num_usages);
+                update_counter(&mut self.usage_index, action, num_usages);
             }
-            (Mode::Backtrace(frames), KeyCode::Up | KeyCode::Down) => {
-                update_counter(&mut self.backtrace_index, key.code, frames.len());
+            (Mode::Backtrace(frames), Action::Up | Action::Down) => {
+                update_counter(&mut self.backtrace_index, action, frames.len());
             }
-            (Mode::SelectProblem, KeyCode::Char('f')) => {
+            (Mode::SelectProblem, Action::Fixes) => {
                 if self.edits().is_empty() {
                     bail!("Sorry. No automatic edits exist for this problem");
                 }
                 self.enter_edit_mode();
             }
-            (Mode::SelectProblem | Mode::SelectEdit, KeyCode::Char('d')) => {
+            (Mode::SelectProblem | Mode::SelectEdit, Action::Usages) => {
                 if self.usages().is_empty() {
                     bail!("Sorry. No additional details available for this problem");
                 }
                 self.enter_usage_mode();
             }
-            (Mode::SelectProblem, KeyCode::Char('t')) => {
+            (Mode::SelectProblem, Action::PackageTree) => {
                 self.modes.push(Mode::ShowPackageTree);
             }
-            (Mode::ShowPackageTree, _) => {
-                self.modes.pop();
-            }
-            (Mode::SelectUsage, KeyCode::Char('d')) => {
+            (Mode::SelectUsage, Action::Usages) => {
                 // We're already in details mode, drop back out to the problems list.
                 self.modes.pop();
             }
-            (Mode::SelectUsage, KeyCode::Char('i')) => {
+            (Mode::SelectUsage, Action::Diagnostics) => {
                 self.modes.push(Mode::ShowInternalDiagnostics);
             }
-            (Mode::ShowInternalDiagnostics, KeyCode::Char('i')) => {
+            (Mode::ShowInternalDiagnostics, Action::Diagnostics) => {
                 self.modes.pop();
             }
-            (Mode::SelectUsage, KeyCode::Char('f')) => {
+            (Mode::SelectUsage, Action::Fixes) => {
                 // We're showing details, jump over to showing edits.
                 self.modes.pop();
                 self.enter_edit_mode();
             }
-            (Mode::SelectUsage, KeyCode::Char('b')) => {
+            (Mode::SelectUsage, Action::Backtrace) => {
                 self.backtrace_index = 0;
                 self.modes.push(Mode::Backtrace(self.backtrace()?));
             }
-            (Mode::Backtrace(..), KeyCode::Char('b' | 'd')) => {
+            (Mode::Backtrace(..), Action::Backtrace | Action::Usages) => {
                 self.modes.pop();
             }
-            (Mode::SelectEdit, KeyCode::Char(' ' | 'f') | KeyCode::Enter) => {
+            (Mode::SelectEdit, Action::Mark | Action::Fixes | Action::Confirm) => {
                 self.apply_selected_edit()?;
                 self.comment = None;
-                if self.problem_index >= self.problem_store.lock().len() {
-                    self.problem_index = 0;
+                if self.problem_index >= self.visible_problems(&self.problem_store.lock()).len() {
+                    self.select_problem(0);
+                } else {
+                    // The problem that was selected has been resolved, so another has taken its
+                    // place.
+                    self.select_problem(self.problem_index);
                 }
                 self.modes.pop();
             }
-            (Mode::SelectEdit, KeyCode::Char('c')) => {
+            (Mode::SelectEdit, Action::Open) => {
+                let mut proposal = self.propose_selected_edit()?;
+                self.location_to_open = Some(proposal.start_editing()?);
+                self.modes.push(Mode::ConfirmEdit(Box::new(proposal)));
+            }
+            (Mode::ConfirmEdit(proposal), Action::Open) => {
+                self.location_to_open = Some(proposal.start_editing()?);
+            }
+            (Mode::ConfirmEdit(_), Action::Confirm) => {
+                let Some(Mode::ConfirmEdit(proposal)) = self.modes.pop() else {
+                    unreachable!();
+                };
+                if let Err(error) = self.apply_proposal(&proposal) {
+                    // Let the user fix whatever was wrong, or cancel.
+                    self.modes.push(Mode::ConfirmEdit(proposal));
+                    return Err(error);
+                }
+                self.comment = None;
+                while !matches!(self.modes.last(), Some(&Mode::SelectProblem)) {
+                    self.modes.pop();
+                }
+            }
+            (Mode::SelectEdit, Action::Comment) => {
                 if !self.current_edit_supports_comments() {
                     bail!("Sorry, this automatic edit doesn't support comments");
                 }
@@ -261,39 +641,224 @@ impl ProblemsUi {
                     self.comment.as_deref().unwrap_or_default().into(),
                 ));
             }
-            (Mode::SelectProblem, KeyCode::Char('a')) => {
-                if !self.accept_single_enabled {
-                    self.modes.push(Mode::PromptAutoAccept);
-                }
+            (Mode::SelectProblem, Action::Accept) if !self.accept_single_enabled => {
+                self.modes.push(Mode::PromptAutoAccept);
             }
-            (Mode::PromptAutoAccept, KeyCode::Enter) => {
+            (Mode::PromptAutoAccept, Action::Confirm) => {
                 self.accept_single_enabled = true;
                 self.accept_all_single_edits()?;
                 self.modes.pop();
             }
-            (_, KeyCode::Char('p')) => {
+            (Mode::BuildOutput(last_line), Action::Up | Action::Down) => {
+                scroll_build_output(last_line, action, 1, &self.build_output.lock().unwrap());
+            }
+            (Mode::BuildOutput(last_line), Action::PageUp | Action::PageDown) => {
+                scroll_build_output(last_line, action, 20, &self.build_output.lock().unwrap());
+            }
+            (Mode::BuildOutput(last_line), Action::Home) => {
+                let output = self.build_output.lock().unwrap();
+                *last_line = Some(output.total_lines() - output.lines().len());
+            }
+            (Mode::BuildOutput(last_line), Action::End) => *last_line = None,
+            (Mode::BuildOutput(..), Action::Output) => {
+                self.modes.pop();
+            }
+            (_, Action::Output) => self.modes.push(Mode::BuildOutput(None)),
+            (Mode::Dashboard, Action::Dashboard) => {
+                self.modes.pop();
+            }
+            (_, Action::Dashboard) => self.modes.push(Mode::Dashboard),
+            (_, Action::PackageDetails) => {
                 self.show_package_details = !self.show_package_details;
             }
-            (Mode::Help, KeyCode::Char('h' | '?')) => {
+            (Mode::Help, Action::Help) => {
                 self.modes.pop();
             }
-            (_, KeyCode::Char('h' | '?')) => self.modes.push(Mode::Help),
-            (_, KeyCode::Esc) => {
-                if self.modes.len() >= 2 {
-                    self.modes.pop();
-                }
+            (_, Action::Help) => self.modes.push(Mode::Help),
+            (_, Action::Back) if num_modes >= 2 => {
+                self.modes.pop();
             }
-            _ => {}
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn toggle_filter(&mut self, toggle: Toggle) -> Result<()> {
+        let pstore = self.problem_store.lock();
+        let problem = self.selected_problem(&pstore).map(|(_, problem)| problem);
+        self.filter.toggle(toggle, problem)?;
+        drop(pstore);
+        self.select_problem(0);
+        Ok(())
+    }
+
+    fn undo_or_redo(
+        &mut self,
+        action: fn(&mut Journal, &Path, &mut ProblemStore) -> Result<()>,
+    ) -> Result<()> {
+        let mut pstore = self.problem_store.lock();
+        action(&mut self.journal, &self.config_path, &mut pstore)?;
+        drop(pstore);
+        // Whatever is waiting for problems to be resolved will reload the config once they are, but
+        // any checks that happen in the meantime should use the restored config.
+        if self.config_path.exists() {
+            self.checker.lock().unwrap().load_config()?;
+        }
+        if self.problem_index >= self.visible_problems(&self.problem_store.lock()).len() {
+            self.select_problem(0);
+        }
+        Ok(())
+    }
+
+    /// Returns how many unresolved problems are marked.
+    fn num_marked(&self) -> usize {
+        self.problem_store
+            .lock()
+            .deduplicated_into_iter()
+            .filter(|(id, _)| self.marked.contains(id))
+            .count()
+    }
+
+    /// Accepts all problems that are like the selected problem in the respect given by `toggle`,
+    /// regardless of whether they're currently shown.
+    fn accept_like_selected(&mut self, toggle: Toggle) -> Result<()> {
+        let pstore = self.problem_store.lock();
+        let mut like_selected = Filter::default();
+        like_selected.toggle(toggle, self.selected_problem(&pstore).map(|(_, p)| p))?;
+        let ids: Vec<ProblemId> = pstore
+            .deduplicated_into_iter()
+            .filter(|(_, problem)| like_selected.matches(problem))
+            .map(|(id, _)| id)
+            .collect();
+        drop(pstore);
+        self.accept_problems(&ids)
+    }
+
+    /// Proposes applying the first available edit for each of the problems in `ids` that is still
+    /// unresolved. Problems without any edits are left out of the proposal.
+    fn accept_problems(&mut self, ids: &[ProblemId]) -> Result<()> {
+        let config = self.checker.lock().unwrap().config.clone();
+        let pstore = self.problem_store.lock();
+        let original = std::fs::read_to_string(&self.config_path).unwrap_or_default();
+        let mut editor = ConfigEditor::from_toml_string(&original)?;
+        let mut resolves = Vec::new();
+        let mut num_without_edits = 0;
+        for (id, problem) in pstore.deduplicated_into_iter() {
+            if !ids.contains(&id) {
+                continue;
+            }
+            let Some(edit) = config_editor::fixes_for_problem(problem, &config)
+                .into_iter()
+                .next()
+            else {
+                num_without_edits += 1;
+                continue;
+            };
+            edit.apply(&mut editor, &Default::default())
+                .with_context(|| format!("Failed to apply `{edit}` for problem: {problem}"))?;
+            resolves.push((id, edit.replacement_problems()));
+        }
+        drop(pstore);
+        if resolves.is_empty() {
+            bail!("None of the problems have automatic edits");
+        }
+        let mut proposal = Proposal::new(
+            format!("Accept {} problem(s)", resolves.len()),
+            original,
+            &editor,
+            resolves,
+        );
+        if num_without_edits > 0 {
+            proposal.note = Some(format!(
+                "{num_without_edits} problem(s) have no automatic edits, so will be left unresolved"
+            ));
         }
+        self.modes.push(Mode::ConfirmEdit(Box::new(proposal)));
         Ok(())
     }
 
+    /// Returns a proposal to apply the currently selected edit.
+    fn propose_selected_edit(&self) -> Result<Proposal> {
+        let config = self.checker.lock().unwrap().config.clone();
+        let pstore = self.problem_store.lock();
+        let Some((id, problem)) = self.selected_problem(&pstore) else {
+            bail!("No problem selected");
+        };
+        let edits = edits_for_problem(Some(problem), &config);
+        let Some(edit) = edits.get(self.edit_index) else {
+            bail!("No edit selected");
+        };
+        let original = std::fs::read_to_string(&self.config_path).unwrap_or_default();
+        let mut editor = ConfigEditor::from_toml_string(&original)?;
+        edit.apply(&mut editor, &self.edit_opts())?;
+        Ok(Proposal::new(
+            edit.title(),
+            original,
+            &editor,
+            vec![(id, edit.replacement_problems())],
+        ))
+    }
+
+    /// Writes the proposed config and resolves the problems that it was proposed for.
+    fn apply_proposal(&mut self, proposal: &Proposal) -> Result<()> {
+        let mut pstore = self.problem_store.lock();
+        let before = journal::Before::capture(&self.config_path, &pstore)?;
+        let editor = proposal.write(&self.config_path)?;
+        let unresolved: FxHashSet<ProblemId> =
+            pstore.deduplicated_into_iter().map(|(id, _)| id).collect();
+        for (id, replacements) in proposal.resolves() {
+            // Problems may have been resolved while the user was looking at the proposal.
+            if unresolved.contains(id) {
+                pstore.replace(*id, replacements.clone());
+            }
+            self.marked.remove(id);
+        }
+        let config = self.checker.lock().unwrap().config.clone();
+        pstore.resolve_problems_with_empty_diff(&editor, &config);
+        self.journal.record(before, &self.config_path, &pstore)?;
+        let num_visible = self.visible_problems(&pstore).len();
+        drop(pstore);
+        if self.problem_index >= num_visible {
+            self.select_problem(0);
+        } else {
+            self.select_problem(self.problem_index);
+        }
+        Ok(())
+    }
+
+    /// Returns the problems that the filter permits us to show, in the order in which we show
+    /// them.
+    fn visible_problems<'a>(&self, pstore: &'a ProblemStore) -> Vec<(ProblemId, &'a Problem)> {
+        pstore
+            .deduplicated_into_iter()
+            .filter(|(_, problem)| self.filter.matches(problem))
+            .collect()
+    }
+
+    fn selected_problem<'a>(&self, pstore: &'a ProblemStore) -> Option<(ProblemId, &'a Problem)> {
+        pstore
+            .deduplicated_into_iter()
+            .filter(|(_, problem)| self.filter.matches(problem))
+            .nth(self.problem_index)
+    }
+
+    /// Selects the problem at `index` within the shown problems. The usage preview goes back to
+    /// the first usage.
+    fn select_problem(&mut self, index: usize) {
+        self.problem_index = index;
+        self.usage_index = 0;
+        self.details_scroll = 0;
+    }
+
+    /// Enters usage mode with whatever usage was last previewed selected.
     fn enter_usage_mode(&mut self) {
         while !matches!(self.modes.last(), Some(&Mode::SelectProblem)) {
             self.modes.pop();
         }
         self.modes.push(Mode::SelectUsage);
-        self.usage_index = 0;
+        if self.usage_index >= self.usages().len() {
+            self.usage_index = 0;
+        }
     }
 
     fn enter_edit_mode(&mut self) {
@@ -306,7 +871,11 @@ impl ProblemsUi {
         crate_index: Arc<CrateIndex>,
         checker: Arc<Mutex<Checker>>,
         config_path: PathBuf,
+        keys: KeyMap,
+        theme: Theme,
     ) -> Self {
+        let progress = checker.lock().unwrap().progress.clone();
+        let build_output = checker.lock().unwrap().build_output.clone();
         Self {
             problem_store,
             crate_index,
@@ -321,9 +890,35 @@ impl ProblemsUi {
             checker,
             comment: None,
             previous_comments: Default::default(),
+            progress,
+            progress_generation: Cell::new(0),
+            build_output,
+            build_output_rendered: Cell::new(0),
+            filter: Filter::default(),
+            marked: FxHashSet::default(),
+            journal: Journal::default(),
+            location_to_open: None,
+            details_scroll: 0,
+            layout: Default::default(),
+            keys,
+            theme,
         }
     }
 
+    /// Returns whether the build progress, or build output if we're showing it, has changed since
+    /// we last rendered it. While the dashboard is shown, we always redraw, so that the elapsed
+    /// time stays current.
+    pub(super) fn progress_changed(&self) -> bool {
+        self.progress.lock().unwrap().generation() != self.progress_generation.get()
+            || matches!(self.modes.last(), Some(Mode::Dashboard))
+            || (self
+                .modes
+                .iter()
+                .any(|mode| matches!(mode, Mode::BuildOutput(..)))
+                && self.build_output.lock().unwrap().total_lines()
+                    != self.build_output_rendered.get())
+    }
+
     pub(super) fn problems_added(&mut self) -> Result<()> {
         if self.accept_single_enabled {
             self.accept_all_single_edits()?;
@@ -350,12 +945,14 @@ impl ProblemsUi {
 
         let config = self.checker.lock().unwrap().config.clone();
         let mut pstore = self.problem_store.lock();
+        let before = journal::Before::capture(&self.config_path, &pstore)?;
         let mut editor = ConfigEditor::from_file(&self.config_path)?;
         while let Some((index, edit)) = first_single_edit(&pstore, &config) {
             edit.apply(&mut editor, &Default::default())?;
             pstore.resolve(index);
         }
         self.write_config(&editor)?;
+        self.journal.record(before, &self.config_path, &pstore)?;
         Ok(())
     }
 
@@ -366,10 +963,34 @@ impl ProblemsUi {
     fn render_problems(&self, f: &mut Frame, area: Rect) {
         let pstore_lock = &self.problem_store.lock();
         if pstore_lock.is_empty() {
-            super::render_build_progress(f, area);
+            let progress = self.progress.lock().unwrap();
+            self.progress_generation.set(progress.generation());
+            super::render_build_progress(f, area, &progress, &self.theme);
             return;
         }
+        let problems = self.visible_problems(pstore_lock);
+        let area = if self.filter.is_active() {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(3), Constraint::Length(1)])
+                .split(area);
+            let hidden = pstore_lock.len() - problems.len();
+            let status = format!(
+                "{hidden} of {} problems hidden by filter on {}. Press {} to clear.",
+                pstore_lock.len(),
+                self.filter.description(),
+                self.keys.label(Action::Back)
+            );
+            f.render_widget(
+                Paragraph::new(status).style(Style::default().fg(self.theme.status)),
+                chunks[1],
+            );
+            chunks[0]
+        } else {
+            area
+        };
         let mut items = Vec::new();
+        let mut rows = Vec::new();
         let is_edit_mode = self.modes.iter().any(|m| matches!(m, &Mode::SelectEdit));
         let is_usage_mode = self.modes.iter().any(|m| matches!(m, &Mode::SelectUsage));
         let backtrace_frames = match self.modes.last() {
@@ -377,25 +998,35 @@ impl ProblemsUi {
             _ => None,
         };
         let config = self.checker.lock().unwrap().config.clone();
-        for (index, (_, problem)) in pstore_lock.deduplicated_into_iter().enumerate() {
-            items.push(ListItem::new(format!("{problem}")));
+        let any_marked = problems.iter().any(|(id, _)| self.marked.contains(id));
+        for (index, (id, problem)) in problems.iter().enumerate() {
+            let mut line = self.filter.highlight(&format!("{problem}"));
+            if any_marked {
+                let marker = if self.marked.contains(id) { "* " } else { "  " };
+                line.spans.insert(
+                    0,
+                    Span::styled(marker, Style::default().fg(self.theme.highlight)),
+                );
+            }
+            items.push(ListItem::new(line));
+            rows.push(ListRow::Problem(index));
             if index == self.problem_index {
                 if is_edit_mode {
-                    let edits = edits_for_problem(pstore_lock, self.problem_index, &config);
-                    items.extend(
-                        edits
-                            .iter()
-                            .map(|fix| ListItem::new(format!("  {}", fix.title()))),
-                    );
+                    let edits = edits_for_problem(Some(problem), &config);
+                    for (edit_index, fix) in edits.iter().enumerate() {
+                        items.push(ListItem::new(format!("  {}", fix.title())));
+                        rows.push(ListRow::Edit(edit_index));
+                    }
                 } else if is_usage_mode {
-                    let usages =
-                        usages_for_problem(pstore_lock, self.problem_index, &self.crate_index);
+                    let usages = usages_for_problem(Some(problem), &self.crate_index);
                     for (usage_index, usage) in usages.iter().enumerate() {
                         items.push(ListItem::new(format!("  {}", usage.list_display())));
+                        rows.push(ListRow::Usage(usage_index));
                         if let Some(frames) = backtrace_frames {
                             if usage_index == self.usage_index {
-                                for bt_frame in frames {
+                                for (frame_index, bt_frame) in frames.iter().enumerate() {
                                     items.push(ListItem::new(format!("    {bt_frame}")));
+                                    rows.push(ListRow::Frame(frame_index));
                                 }
                             }
                         }
@@ -420,7 +1051,7 @@ impl ProblemsUi {
             title = "Problems";
         }
 
-        render_list(
+        let offset = render_list(
             f,
             title,
             items.into_iter(),
@@ -435,17 +1066,28 @@ impl ProblemsUi {
             ),
             area,
             index,
+            &self.theme,
         );
+        let mut layout = self.layout.borrow_mut();
+        layout.list = area;
+        rows.drain(..offset.min(rows.len()));
+        layout.list_rows = rows;
     }
 
     fn render_details(&self, f: &mut Frame, area: Rect) {
+        let usages = self.usages();
+        if !usages.is_empty() {
+            self.render_usage_preview(&usages, f, area);
+            return;
+        }
         let block = Block::default().title("Details").borders(Borders::ALL);
         let pstore_lock = &self.problem_store.lock();
-        let problem = pstore_lock
-            .deduplicated_into_iter()
-            .nth(self.problem_index)
+        let problem = self
+            .selected_problem(pstore_lock)
             .map(|(_, problem)| problem);
-        let mut details = problem.map(problem_details).unwrap_or_default();
+        let mut details = problem
+            .map(|problem| problem_details(problem, &self.keys))
+            .unwrap_or_default();
         // If the details are the same as what we already displayed in the list then display
         // nothing. We don't want to needlessly repeat information.
         if problem
@@ -456,21 +1098,26 @@ impl ProblemsUi {
         }
         let paragraph = Paragraph::new(details)
             .block(block)
-            .wrap(Wrap { trim: false });
+            .wrap(Wrap { trim: false })
+            .scroll((self.details_scroll, 0));
         f.render_widget(paragraph, area);
     }
 
     fn edits(&self) -> Vec<Box<dyn Edit>> {
         let config = self.checker.lock().unwrap().config.clone();
-        edits_for_problem(&self.problem_store.lock(), self.problem_index, &config)
+        let pstore_lock = self.problem_store.lock();
+        let problem = self
+            .selected_problem(&pstore_lock)
+            .map(|(_, problem)| problem);
+        edits_for_problem(problem, &config)
     }
 
     fn usages(&self) -> Vec<Box<dyn DisplayUsage>> {
-        usages_for_problem(
-            &self.problem_store.lock(),
-            self.problem_index,
-            &self.crate_index,
-        )
+        let pstore_lock = self.problem_store.lock();
+        let problem = self
+            .selected_problem(&pstore_lock)
+            .map(|(_, problem)| problem);
+        usages_for_problem(problem, &self.crate_index)
     }
 
     fn render_edit_help_and_diff(&self, f: &mut Frame, area: Rect) {
@@ -485,12 +1132,13 @@ impl ProblemsUi {
             &self.edit_opts(),
             (area.height as usize).saturating_sub(4),
         )
-        .unwrap_or_else(error_lines);
+        .unwrap_or_else(|e| error_lines(e, &self.theme));
 
         let block = Block::default().title("Edit details").borders(Borders::ALL);
         let paragraph = Paragraph::new(lines)
             .block(block)
-            .wrap(Wrap { trim: false });
+            .wrap(Wrap { trim: false })
+            .scroll((self.details_scroll, 0));
         f.render_widget(paragraph, area);
     }
 
@@ -500,7 +1148,60 @@ impl ProblemsUi {
             return;
         };
 
-        render_source_location(usage.source_location(), area, f);
+        render_source_location(
+            usage.source_location(),
+            usage.highlighted_name(),
+            &self.theme,
+            area,
+            f,
+        );
+    }
+
+    /// Renders the source around one of `usages` so that usages can be reviewed without leaving
+    /// the problem list.
+    fn render_usage_preview(&self, usages: &[Box<dyn DisplayUsage>], f: &mut Frame, area: Rect) {
+        let index = if self.usage_index < usages.len() {
+            self.usage_index
+        } else {
+            0
+        };
+        let usage = &usages[index];
+        let mut lines = vec![Line::from(vec![
+            Span::styled(
+                format!("Usage {} of {}: ", index + 1, usages.len()),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(usage.list_display()),
+        ])];
+        let details_hint = format!("{}: usage details", self.keys.label(Action::Usages));
+        let hint = if usages.len() > 1 {
+            format!(
+                "{}: other usages, {details_hint}",
+                self.keys.labels(&[Action::Left, Action::Right])
+            )
+        } else {
+            details_hint
+        };
+        lines.push(Line::from(Span::styled(
+            hint,
+            Style::default().fg(self.theme.hint),
+        )));
+        lines.push(Line::from(""));
+        lines.extend(
+            usage_source_lines(
+                usage.source_location(),
+                usage.highlighted_name(),
+                (area.height as usize).saturating_sub(2 + lines.len()),
+            )
+            .unwrap_or_else(|e| error_lines(e, &self.theme)),
+        );
+        let block = Block::default()
+            .title(source_location_title(usage.source_location()))
+            .borders(Borders::ALL);
+        let paragraph = Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false });
+        f.render_widget(paragraph, area);
     }
 
     fn render_backtrace_source(&self, frames: &[backtrace::Frame], f: &mut Frame, area: Rect) {
@@ -509,7 +1210,7 @@ impl ProblemsUi {
         };
 
         if let Some(location) = frame.source_location.as_ref() {
-            render_source_location(location, area, f);
+            render_source_location(location, None, &self.theme, area, f);
         } else {
             let block = Block::default()
                 .title("Missing source location")
@@ -560,40 +1261,48 @@ impl ProblemsUi {
             .unwrap_or_else(|| "No debug data for this usage".to_owned());
 
         let lines: Vec<_> = debug_data.lines().collect();
-        render_message(f, Some("Internal diagnostics"), &lines);
+        render_message(f, &self.theme, Some("Internal diagnostics"), &lines);
+    }
+
+    fn render_dashboard(&self, f: &mut Frame) {
+        let progress = self.progress.lock().unwrap();
+        self.progress_generation.set(progress.generation());
+        let lines = dashboard_lines(
+            &progress,
+            &self.problem_store.lock(),
+            self.crate_index.package_ids().count(),
+            self.journal.num_applied(),
+        );
+        render_message(f, &self.theme, Some("Progress"), &lines);
     }
 
     /// Applies the currently selected edit and resolves the problem that produced that edit.
-    fn apply_selected_edit(&self) -> Result<()> {
+    fn apply_selected_edit(&mut self) -> Result<()> {
         let mut pstore_lock = self.problem_store.lock();
         let config = self.checker.lock().unwrap().config.clone();
-        let edits = edits_for_problem(&pstore_lock, self.problem_index, &config);
+        let Some((index, problem)) = self.selected_problem(&pstore_lock) else {
+            return Ok(());
+        };
+        let edits = edits_for_problem(Some(problem), &config);
         let Some(edit) = edits.get(self.edit_index) else {
             return Ok(());
         };
-        let mut editor = ConfigEditor::from_file(&self.config_path)?;
-        edit.apply(&mut editor, &self.edit_opts())?;
-        self.write_config(&editor)?;
-
-        // Resolve the currently selected problem.
-        let maybe_index = pstore_lock
-            .deduplicated_into_iter()
-            .nth(self.problem_index)
-            .map(|(index, _)| index);
-        if let Some(index) = maybe_index {
-            pstore_lock.replace(index, edit.replacement_problems());
-        }
-
-        // Resolve any other problems that now have no-op edits.
-        let config = self.checker.lock().unwrap().config.clone();
-        pstore_lock.resolve_problems_with_empty_diff(&editor, &config);
+        let before = journal::Before::capture(&self.config_path, &pstore_lock)?;
+        crate::ui::apply_fix(
+            &self.config_path,
+            &config,
+            &mut pstore_lock,
+            index,
+            edit.as_ref(),
+            &self.edit_opts(),
+        )?;
+        self.journal
+            .record(before, &self.config_path, &pstore_lock)?;
         Ok(())
     }
 
     fn current_edit_supports_comments(&self) -> bool {
-        let pstore_lock = self.problem_store.lock();
-        let config = self.checker.lock().unwrap().config.clone();
-        let edits = edits_for_problem(&pstore_lock, self.problem_index, &config);
+        let edits = self.edits();
         let Some(edit) = edits.get(self.edit_index) else {
             return false;
         };
@@ -625,7 +1334,50 @@ impl ProblemsUi {
             if let Some(documentation) = &crate_info.documentation {
                 writeln!(&mut text, "Documentation: {documentation}").unwrap();
             }
+            if let Some(repository) = &crate_info.repository {
+                writeln!(&mut text, "Repository: {repository}").unwrap();
+            }
             writeln!(&mut text, "Local path: {}", crate_info.directory).unwrap();
+            let mut features: Vec<&str> = crate_info.features().collect();
+            features.sort();
+            if !features.is_empty() {
+                writeln!(&mut text, "Enabled features: {}", features.join(", ")).unwrap();
+            }
+        }
+        if let Some(kinds) = self.crate_index.dep_kinds(&pkg_id) {
+            if !kinds.is_empty() {
+                let kinds: Vec<String> = kinds.iter().map(|kind| kind.to_string()).collect();
+                writeln!(&mut text, "Used as: {} dependency", kinds.join(", ")).unwrap();
+            }
+        }
+        let dependents = self.crate_index.transitive_dependents(&pkg_id);
+        write!(&mut text, "Dependents in workspace: {}", dependents.len()).unwrap();
+        write_some_names(
+            &mut text,
+            dependents.iter().map(|pkg_id| pkg_id.to_string()),
+        );
+        let dependencies = self.crate_index.dependencies(&pkg_id);
+        write!(&mut text, "Direct dependencies: {}", dependencies.len()).unwrap();
+        write_some_names(
+            &mut text,
+            dependencies.iter().map(|dep| {
+                if dep.kinds.iter().all(|kind| *kind == DepKind::Normal) {
+                    dep.pkg_id.to_string()
+                } else {
+                    let kinds: Vec<String> = dep.kinds.iter().map(|k| k.to_string()).collect();
+                    format!("{} [{}]", dep.pkg_id, kinds.join(", "))
+                }
+            }),
+        );
+        let config = self.checker.lock().unwrap().config.clone();
+        let granted = granted_permissions(&config, &pkg_id);
+        if granted.is_empty() {
+            writeln!(&mut text, "Granted permissions: none").unwrap();
+        } else {
+            writeln!(&mut text, "Granted permissions:").unwrap();
+            for line in granted {
+                writeln!(&mut text, "  {line}").unwrap();
+            }
         }
 
         let block = Block::default()
@@ -635,12 +1387,42 @@ impl ProblemsUi {
         f.render_widget(paragraph, area);
     }
 
+    fn render_build_output(&self, last_line: Option<usize>, f: &mut Frame) {
+        let output = self.build_output.lock().unwrap();
+        self.build_output_rendered.set(output.total_lines());
+        let area = f.size();
+        let first_kept = output.total_lines() - output.lines().len();
+        let end = last_line
+            .map_or(output.total_lines(), |last_line| last_line + 1)
+            .clamp(first_kept, output.total_lines());
+        let start = end
+            .saturating_sub((area.height as usize).saturating_sub(2))
+            .max(first_kept);
+        let lines: Vec<Line> = output
+            .lines()
+            .range(start - first_kept..end - first_kept)
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        let title = if output.total_lines() == 0 {
+            "Build output (none yet)".to_owned()
+        } else {
+            format!(
+                "Build output (lines {}-{end} of {})",
+                start + 1,
+                output.total_lines()
+            )
+        };
+        let paragraph = Paragraph::new(lines).block(active_block(&self.theme).title(title));
+        f.render_widget(Clear, area);
+        f.render_widget(paragraph, area);
+    }
+
     fn render_package_tree(&self, f: &mut Frame) {
         let text = self
             .package_tree_text()
             .unwrap_or_else(|error| error.to_string());
         let lines: Vec<_> = text.lines().collect();
-        render_message(f, None, &lines);
+        render_message(f, &self.theme, None, &lines);
     }
 
     fn package_tree_text(&self) -> Result<String> {
@@ -665,7 +1447,7 @@ impl ProblemsUi {
 
     fn current_package_id(&self) -> Option<PackageId> {
         let pstore = &self.problem_store.lock();
-        let (_, problem) = pstore.deduplicated_into_iter().nth(self.problem_index)?;
+        let (_, problem) = self.selected_problem(pstore)?;
         problem.pkg_id().cloned()
     }
 
@@ -685,16 +1467,28 @@ impl ProblemsUi {
         backtracer.backtrace(bin_location)
     }
 
-    fn render_comment_input(&self, input: &tui_input::Input, f: &mut Frame) {
-        let area = centre_area(f.size(), 80, 3);
-        let paragraph = Paragraph::new(input.value()).block(active_block().title("Set comment"));
-        f.render_widget(Clear, area);
-        f.render_widget(paragraph, area);
-        f.set_cursor(area.x + 1 + input.visual_cursor() as u16, area.y + 1);
+    /// Returns whether `key` is bound to quitting.
+    pub(super) fn is_quit_key(&self, key: KeyEvent) -> bool {
+        self.keys.actions(key).contains(&Action::Quit)
+    }
+
+    pub(super) fn take_location_to_open(&mut self) -> Option<SourceLocation> {
+        self.location_to_open.take()
+    }
+
+    /// Called once the editor opened for `location_to_open` has exited.
+    pub(super) fn editor_closed(&mut self) -> Result<()> {
+        if let Some(Mode::ConfirmEdit(proposal)) = self.modes.last_mut() {
+            proposal.finish_editing()?;
+        }
+        Ok(())
     }
 
     pub(crate) fn needs_cursor(&self) -> bool {
-        matches!(self.modes.last(), Some(Mode::SetComment(..)))
+        matches!(
+            self.modes.last(),
+            Some(Mode::SetComment(..) | Mode::Search(..))
+        )
     }
 
     fn edit_opts(&self) -> EditOpts {
@@ -704,12 +1498,68 @@ impl ProblemsUi {
     }
 }
 
-fn render_source_location(source_location: &SourceLocation, area: Rect, f: &mut Frame) {
-    let lines = usage_source_lines(source_location, (area.height as usize).saturating_sub(2))
-        .unwrap_or_else(error_lines);
+/// A clickable stand-in for a key.
+struct Button {
+    key_label: String,
+    label: &'static str,
+    action: Action,
+}
+
+impl Button {
+    fn new(key_label: String, label: &'static str, action: Action) -> Self {
+        Self {
+            key_label,
+            label,
+            action,
+        }
+    }
+
+    /// The number of columns that the button takes up when rendered.
+    fn width(&self) -> u16 {
+        (self.key_label.len() + self.label.len() + 4) as u16
+    }
+}
+
+/// Returns the action for the button at `column` within the row of buttons.
+fn button_at(buttons: &[Button], column: u16) -> Option<Action> {
+    let mut start = 0;
+    for button in buttons {
+        if column < start + button.width() {
+            return Some(button.action);
+        }
+        start += button.width();
+    }
+    None
+}
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    (area.x..area.x + area.width).contains(&column) && (area.y..area.y + area.height).contains(&row)
+}
+
+fn render_text_input(title: &str, input: &tui_input::Input, theme: &Theme, f: &mut Frame) {
+    let area = centre_area(f.size(), 80, 3);
+    let paragraph = Paragraph::new(input.value()).block(active_block(theme).title(title));
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+    f.set_cursor(area.x + 1 + input.visual_cursor() as u16, area.y + 1);
+}
+
+fn render_source_location(
+    source_location: &SourceLocation,
+    highlighted_name: Option<&str>,
+    theme: &Theme,
+    area: Rect,
+    f: &mut Frame,
+) {
+    let lines = usage_source_lines(
+        source_location,
+        highlighted_name,
+        (area.height as usize).saturating_sub(2),
+    )
+    .unwrap_or_else(|e| error_lines(e, theme));
 
     let block = Block::default()
-        .title(source_location.filename().display().to_string())
+        .title(source_location_title(source_location))
         .borders(Borders::ALL);
     let paragraph = Paragraph::new(lines)
         .block(block)
@@ -717,10 +1567,18 @@ fn render_source_location(source_location: &SourceLocation, area: Rect, f: &mut
     f.render_widget(paragraph, area);
 }
 
-fn error_lines(error: anyhow::Error) -> Vec<Line<'static>> {
+fn source_location_title(source_location: &SourceLocation) -> String {
+    format!(
+        "{}:{}",
+        source_location.filename().display(),
+        source_location.line()
+    )
+}
+
+fn error_lines(error: anyhow::Error, theme: &Theme) -> Vec<Line<'static>> {
     vec![Line::from(Span::styled(
         format!("{error:#}"),
-        Style::default().fg(Color::Red),
+        Style::default().fg(theme.error),
     ))]
 }
 
@@ -749,8 +1607,12 @@ fn config_diff_lines(
     Ok(lines)
 }
 
+/// Returns up to `max_lines` lines of source centred on `source_location`. On the line of the usage,
+/// the token at the usage's column is highlighted, or if we don't know the column, any tokens that
+/// match `highlighted_name`.
 fn usage_source_lines(
     source_location: &SourceLocation,
+    highlighted_name: Option<&str>,
     max_lines: usize,
 ) -> Result<Vec<Line<'static>>> {
     let before_context = (max_lines / 2) as i32;
@@ -774,18 +1636,33 @@ fn usage_source_lines(
             "{marker}{:gutter_width$}: ",
             line_number
         ))];
-        let column = (line_number == target_line)
-            .then(|| source_location.column())
-            .flatten();
-        format_line(&mut spans, column, line);
+        let highlight = if line_number != target_line {
+            Highlight::None
+        } else if let Some(column) = source_location.column() {
+            Highlight::Column(column)
+        } else if let Some(name) = highlighted_name {
+            Highlight::Name(name)
+        } else {
+            Highlight::None
+        };
+        format_line(&mut spans, highlight, line);
         lines.push(Line::from(spans));
     }
     Ok(lines)
 }
 
-fn format_line(out: &mut Vec<Span>, column: Option<u32>, line: &str) {
+/// Which tokens on a line of source should be highlighted.
+#[derive(Clone, Copy)]
+enum Highlight<'a> {
+    None,
+    /// The token containing this 1-based column.
+    Column(u32),
+    /// Tokens with this text.
+    Name(&'a str),
+}
+
+fn format_line(out: &mut Vec<Span>, highlight: Highlight, line: &str) {
     let mut offset = 0;
-    let column_offset = column.map(|c| (c as usize).saturating_sub(1));
     for token in rustc_ap_rustc_lexer::tokenize(line) {
         let new_offset = offset + token.len;
         let token_text = &line[offset..new_offset];
@@ -793,10 +1670,14 @@ fn format_line(out: &mut Vec<Span>, column: Option<u32>, line: &str) {
         if let Some(colour) = syntax_styling::colour_for_token_kind(token.kind, token_text) {
             style = style.fg(colour);
         }
-        if column_offset
-            .map(|c| (offset..new_offset).contains(&c))
-            .unwrap_or(false)
-        {
+        let highlighted = match highlight {
+            Highlight::None => false,
+            Highlight::Column(column) => {
+                (offset..new_offset).contains(&(column as usize).saturating_sub(1))
+            }
+            Highlight::Name(name) => token_text == name,
+        };
+        if highlighted {
             style = style.add_modifier(Modifier::REVERSED);
         }
         out.push(Span::styled(token_text.to_owned(), style));
@@ -804,59 +1685,224 @@ fn format_line(out: &mut Vec<Span>, column: Option<u32>, line: &str) {
     }
 }
 
-fn render_help(f: &mut Frame, mode: Option<&Mode>) {
-    let mut keys = vec![];
+fn render_help(f: &mut Frame, mode: Option<&Mode>, keys: &KeyMap, theme: &Theme) {
+    let k = |actions: &[Action]| keys.labels(actions);
+    let mut entries = vec![];
     let mut title = "Help";
     match mode {
         Some(Mode::SelectProblem) => {
             title = "Help for select-problem";
-            keys.extend([
-                ("f", "Show available automatic fixes for this problem"),
+            entries.extend([
                 (
-                    "d",
+                    k(&[Action::Fixes]),
+                    "Show available automatic fixes for this problem",
+                ),
+                (
+                    k(&[Action::Usages]),
                     "Select and show details of each usage (API/unsafe only)",
                 ),
-                ("t", "Show tree of crate dependencies to this crate"),
-                ("up", "Select previous problem"),
-                ("down", "Select next problem"),
-                ("a", "Enable auto-apply for problems with only one edit"),
+                (
+                    k(&[Action::PackageTree]),
+                    "Show tree of crate dependencies to this crate",
+                ),
+                (
+                    k(&[Action::Graph]),
+                    "Show how this crate reaches the API (API only)",
+                ),
+                (k(&[Action::Up]), "Select previous problem"),
+                (k(&[Action::Down]), "Select next problem"),
+                (
+                    k(&[Action::Left, Action::Right]),
+                    "Preview previous/next usage (API/unsafe only)",
+                ),
+                (
+                    k(&[Action::Accept]),
+                    "Enable auto-apply for problems with only one edit",
+                ),
+                (k(&[Action::Mark]), "Mark or unmark this problem"),
+                (
+                    k(&[Action::Accept]),
+                    "Accept marked problems by applying their first edit",
+                ),
+                (
+                    k(&[Action::Reject]),
+                    "Reject marked problems. This will fail the build",
+                ),
+                (
+                    k(&[Action::AcceptCrate]),
+                    "Accept all problems for this problem's crate",
+                ),
+                (
+                    k(&[Action::AcceptApi]),
+                    "Accept all problems for this problem's API",
+                ),
+                (
+                    k(&[Action::Open]),
+                    "Open the previewed usage in your editor (API/unsafe only)",
+                ),
+                (k(&[Action::Undo]), "Undo the last edit to cackle.toml"),
+                (k(&[Action::Redo]), "Redo the last undone edit"),
+                (
+                    k(&[Action::Search]),
+                    "Search for problems containing some text",
+                ),
+                (
+                    k(&[Action::FilterCrate]),
+                    "Toggle showing only problems for this problem's crate",
+                ),
+                (
+                    k(&[Action::FilterApi]),
+                    "Toggle showing only problems for this problem's API",
+                ),
+                (
+                    k(&[Action::FilterKind]),
+                    "Toggle showing only problems of this problem's kind",
+                ),
+                (k(&[Action::Back]), "Clear search and filters"),
             ]);
         }
         Some(Mode::SelectEdit) => {
             title = "Help for select-edit";
-            keys.extend([
-                ("space/enter/f", "Apply this edit"),
-                ("d", "Jump to usage details (API/unsafe only)"),
-                ("c", "Add comment to edit (supported edits only)"),
-                ("up", "Select previous edit"),
-                ("down", "Select next edit"),
-                ("esc", "Return to problem list"),
+            entries.extend([
+                (
+                    k(&[Action::Mark, Action::Confirm, Action::Fixes]),
+                    "Apply this edit",
+                ),
+                (
+                    k(&[Action::Usages]),
+                    "Jump to usage details (API/unsafe only)",
+                ),
+                (
+                    k(&[Action::Comment]),
+                    "Add comment to edit (supported edits only)",
+                ),
+                (
+                    k(&[Action::Open]),
+                    "Edit the resulting cackle.toml before applying it",
+                ),
+                (k(&[Action::Up]), "Select previous edit"),
+                (k(&[Action::Down]), "Select next edit"),
+                (k(&[Action::Back]), "Return to problem list"),
+            ]);
+        }
+        Some(Mode::Backtrace(..)) => {
+            title = "Help for backtrace";
+            entries.extend([
+                (k(&[Action::Up]), "Select previous frame"),
+                (k(&[Action::Down]), "Select next frame"),
+                (
+                    k(&[Action::Open]),
+                    "Open this frame's source in your editor",
+                ),
+                (
+                    k(&[Action::Backtrace, Action::Usages, Action::Back]),
+                    "Return to usage list",
+                ),
+            ]);
+        }
+        Some(Mode::Graph(..)) => {
+            title = "Help for usage graph";
+            entries.extend([
+                (k(&[Action::Up]), "Select previous node"),
+                (k(&[Action::Down]), "Select next node"),
+                (
+                    k(&[Action::Left]),
+                    "Collapse this node or select its parent",
+                ),
+                (
+                    k(&[Action::Right]),
+                    "Expand this node or select its first child",
+                ),
+                (
+                    k(&[Action::Open, Action::Confirm]),
+                    "Open this node's source in your editor",
+                ),
+                (k(&[Action::Graph, Action::Back]), "Return to problem list"),
+            ]);
+        }
+        Some(Mode::ConfirmEdit(..)) => {
+            title = "Help for confirm-edit";
+            entries.extend([
+                (k(&[Action::Confirm]), "Write the change to cackle.toml"),
+                (
+                    k(&[Action::Open]),
+                    "Edit the proposed cackle.toml in your editor",
+                ),
+                (k(&[Action::Back]), "Cancel"),
+            ]);
+        }
+        Some(Mode::BuildOutput(..)) => {
+            title = "Help for build output";
+            entries.extend([
+                (k(&[Action::Up, Action::Down]), "Scroll by one line"),
+                (k(&[Action::PageUp, Action::PageDown]), "Scroll by a page"),
+                (k(&[Action::Home]), "Go to the oldest output"),
+                (
+                    k(&[Action::End]),
+                    "Go to the newest output and follow new output",
+                ),
+                (k(&[Action::Output, Action::Back]), "Close build output"),
+            ]);
+        }
+        Some(Mode::Dashboard) => {
+            title = "Help for progress";
+            entries.push((k(&[Action::Dashboard, Action::Back]), "Close progress"));
+        }
+        Some(Mode::Search(..)) => {
+            title = "Help for search";
+            entries.extend([
+                (
+                    "enter".to_owned(),
+                    "Return to problem list, keeping the search",
+                ),
+                ("esc".to_owned(), "Clear the search"),
             ]);
         }
         Some(Mode::SelectUsage) => {
             title = "Help for select-usage";
-            keys.extend([
-                ("up", "Select previous usage"),
-                ("down", "Select next usage"),
-                ("b", "Show backtrace for this usage (API only)"),
-                ("f", "Jump to edits for the current problem"),
-                ("d/esc", "Return to problem list"),
-                ("i", "Show internal diagnostics (requires --debug)"),
+            entries.extend([
+                (k(&[Action::Up]), "Select previous usage"),
+                (k(&[Action::Down]), "Select next usage"),
+                (
+                    k(&[Action::Backtrace]),
+                    "Show backtrace for this usage (API only)",
+                ),
+                (k(&[Action::Open]), "Open this usage in your editor"),
+                (k(&[Action::Fixes]), "Jump to edits for the current problem"),
+                (k(&[Action::Usages, Action::Back]), "Return to problem list"),
+                (
+                    k(&[Action::Diagnostics]),
+                    "Show internal diagnostics (requires --debug)",
+                ),
             ]);
         }
         _ => {}
     }
-    keys.extend([
-        ("p", "Toggle display of package details"),
-        ("q", "Quit"),
-        ("h/?", "Show mode-specific help"),
+    entries.extend([
+        (
+            k(&[Action::Dashboard]),
+            "Show progress of the build and review",
+        ),
+        (k(&[Action::Output]), "Show output from cargo"),
+        (
+            k(&[Action::PackageDetails]),
+            "Toggle display of package details",
+        ),
+        (k(&[Action::Quit]), "Quit"),
+        (k(&[Action::Help]), "Show mode-specific help"),
     ]);
-    let left_col_width = 5;
-    let width = keys.iter().map(|(_, text)| text.len()).max().unwrap_or(0) + left_col_width + 3;
-    let height = keys.len() + 2;
-    let rows: Vec<Row> = keys
+    let left_col_width = entries.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    let width = entries
+        .iter()
+        .map(|(_, text)| text.len())
+        .max()
+        .unwrap_or(0)
+        + left_col_width
+        + 3;
+    let height = entries.len() + 2;
+    let rows: Vec<Row> = entries
         .into_iter()
-        .map(|(key, action)| Row::new(vec![key, action]))
+        .map(|(key, action)| Row::new(vec![key, action.to_owned()]))
         .collect();
     let area = centre_area(f.size(), width as u16, height as u16);
     let constraints = [
@@ -864,23 +1910,99 @@ fn render_help(f: &mut Frame, mode: Option<&Mode>) {
         Constraint::Max(area.width),
     ];
     let table = Table::new(rows)
-        .block(active_block().title(title))
+        .block(active_block(theme).title(title))
         .widths(&constraints);
     f.render_widget(Clear, area);
     f.render_widget(table, area);
 }
 
-fn render_auto_accept(f: &mut Frame) {
-    render_message(f, None, &[
-        "Auto-accept edits for all problems that only have a single edit?",
-        "",
-        "It's recommended that you look over the resulting cackle.toml afterwards to see if there are any crates with permissions that you don't think they should have.",
-        "",
-        "Press enter to accept, or escape to cancel.",
+fn render_proposal(proposal: &Proposal, keys: &KeyMap, theme: &Theme, f: &mut Frame, area: Rect) {
+    let mut lines = vec![Line::from(format!(
+        "Press {} to write this change to cackle.toml, {} to edit it first or {} to cancel.",
+        keys.label(Action::Confirm),
+        keys.label(Action::Open),
+        keys.label(Action::Back)
+    ))];
+    if let Some(note) = &proposal.note {
+        lines.push(Line::from(Span::styled(
+            note.clone(),
+            Style::default().fg(theme.highlight),
+        )));
+    }
+    lines.push(Line::from(""));
+    let max_lines = (area.height as usize).saturating_sub(2 + lines.len());
+    lines.append(&mut proposal.diff_lines(max_lines));
+    let paragraph = Paragraph::new(lines)
+        .block(active_block(theme).title(proposal.title.as_str()))
+        .wrap(Wrap { trim: false });
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+fn render_graph(graph: &Graph, theme: &Theme, f: &mut Frame, area: Rect) {
+    let rows = graph.rows();
+    // Keep the selected row in view.
+    let height = (area.height as usize).saturating_sub(2);
+    let skip = (graph.selected() + 1).saturating_sub(height);
+    let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+    let lines: Vec<Line> = rows
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .map(|(index, row)| {
+            let style = if index == graph.selected() {
+                selected_style
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::raw(row.prefix.as_str()),
+                Span::styled(row.label, style),
+            ])
+        })
+        .collect();
+    let paragraph = Paragraph::new(lines).block(active_block(theme).title("Usage graph"));
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// Moves `last_line`, the last line of build output shown, by `amount` lines in the direction
+/// given by `action`. Scrolling to the end resumes following new output.
+fn scroll_build_output(
+    last_line: &mut Option<usize>,
+    action: Action,
+    amount: usize,
+    output: &BuildOutput,
+) {
+    let total = output.total_lines();
+    let first_kept = total - output.lines().len();
+    let current = last_line.unwrap_or(total.saturating_sub(1));
+    *last_line = match action {
+        Action::Up | Action::PageUp => Some(current.saturating_sub(amount).max(first_kept)),
+        _ => Some(current + amount).filter(|line| line + 1 < total),
+    };
+}
+
+fn render_auto_accept(keys: &KeyMap, theme: &Theme, f: &mut Frame) {
+    render_message(f, theme, None, &[
+        "Auto-accept edits for all problems that only have a single edit?".to_owned(),
+        String::new(),
+        "It's recommended that you look over the resulting cackle.toml afterwards to see if there are any crates with permissions that you don't think they should have.".to_owned(),
+        String::new(),
+        format!(
+            "Press {} to accept, or {} to cancel.",
+            keys.label(Action::Confirm),
+            keys.label(Action::Back)
+        ),
     ]);
 }
 
-fn render_message<S: AsRef<str>>(f: &mut Frame, title: Option<&str>, raw_lines: &[S]) {
+fn render_message<S: AsRef<str>>(
+    f: &mut Frame,
+    theme: &Theme,
+    title: Option<&str>,
+    raw_lines: &[S],
+) {
     let width = raw_lines
         .iter()
         .map(|line| line.as_ref().len())
@@ -889,7 +2011,7 @@ fn render_message<S: AsRef<str>>(f: &mut Frame, title: Option<&str>, raw_lines:
         + 2;
     let height = raw_lines.len() + 2;
     let area = centre_area(f.size(), (width as u16).max(20), (height as u16).max(5));
-    let mut block = active_block();
+    let mut block = active_block(theme);
     if let Some(title) = title {
         block = block.title(title);
     }
@@ -901,37 +2023,32 @@ fn render_message<S: AsRef<str>>(f: &mut Frame, title: Option<&str>, raw_lines:
     f.render_widget(paragraph, area);
 }
 
-fn active_block() -> Block<'static> {
+fn active_block(theme: &Theme) -> Block<'static> {
     Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Yellow))
+        .border_style(Style::default().fg(theme.highlight))
 }
 
-fn edits_for_problem(
-    pstore_lock: &MutexGuard<ProblemStore>,
-    problem_index: usize,
-    config: &Config,
-) -> Vec<Box<dyn Edit>> {
-    let Some((_, problem)) = pstore_lock.deduplicated_into_iter().nth(problem_index) else {
+fn edits_for_problem(problem: Option<&Problem>, config: &Config) -> Vec<Box<dyn Edit>> {
+    let Some(problem) = problem else {
         return Vec::new();
     };
     config_editor::fixes_for_problem(problem, config)
 }
 
 fn usages_for_problem(
-    pstore_lock: &MutexGuard<ProblemStore>,
-    problem_index: usize,
+    problem: Option<&Problem>,
     crate_index: &CrateIndex,
 ) -> Vec<Box<dyn DisplayUsage>> {
     let mut usages_out: Vec<Box<dyn DisplayUsage>> = Vec::new();
-    match pstore_lock.deduplicated_into_iter().nth(problem_index) {
-        Some((_, Problem::DisallowedApiUsage(usages)))
-        | Some((_, Problem::OffTreeApiUsage(OffTreeApiUsage { usages, .. }))) => {
+    match problem {
+        Some(Problem::DisallowedApiUsage(usages))
+        | Some(Problem::OffTreeApiUsage(OffTreeApiUsage { usages, .. })) => {
             for usage in &usages.usages {
                 usages_out.push(Box::new(usage.clone()));
             }
         }
-        Some((_, Problem::DisallowedUnsafe(unsafe_usage))) => {
+        Some(Problem::DisallowedUnsafe(unsafe_usage)) => {
             for location in &unsafe_usage.locations {
                 let pkg_dir = crate_index
                     .pkg_dir(unsafe_usage.crate_sel.pkg_id())
@@ -972,6 +2089,11 @@ trait DisplayUsage {
     fn bin_location(&self) -> Option<(&Path, BinLocation)> {
         None
     }
+
+    /// The name to highlight in the source if we don't know which column the usage is at.
+    fn highlighted_name(&self) -> Option<&str> {
+        None
+    }
 }
 
 impl DisplayUsage for ApiUsage {
@@ -986,20 +2108,37 @@ impl DisplayUsage for ApiUsage {
     }
 
     fn list_display(&self) -> String {
+        if self.similar_usages > 0 {
+            return format!(
+                "{} -> {} (+{} similar)",
+                self.from, self.to_source, self.similar_usages
+            );
+        }
         format!("{} -> {}", self.from, self.to_source)
     }
 
     fn details(&self) -> Vec<(&'static str, String)> {
-        vec![
+        let mut details = vec![
             ("From", self.from.to_string()),
             ("To", self.to.to_string()),
             ("Matched name", self.to_name.to_string()),
-        ]
+        ];
+        if self.call_chain.len() > 1 {
+            details.push((
+                "Call chain",
+                crate::problem::display_call_chain(&self.call_chain),
+            ));
+        }
+        details
     }
 
     fn bin_location(&self) -> Option<(&Path, BinLocation)> {
         Some((&self.bin_path, self.bin_location))
     }
+
+    fn highlighted_name(&self) -> Option<&str> {
+        self.to_name.parts.last().map(|part| part.as_ref())
+    }
 }
 
 impl DisplayUsage for UnsafeLocation {
@@ -1021,16 +2160,19 @@ impl DisplayUsage for UnsafeLocation {
     }
 }
 
-fn problem_details(problem: &Problem) -> String {
+fn problem_details(problem: &Problem, keys: &KeyMap) -> String {
     match problem {
         Problem::DisallowedUnsafe(..) | Problem::DisallowedApiUsage(..) => {
-            "Press 'd' to see details of each usage".to_owned()
+            format!(
+                "Press '{}' to see details of each usage",
+                keys.label(Action::Usages)
+            )
         }
-        Problem::MissingConfiguration(..) => {
+        Problem::MissingConfiguration(..) => format!(
             "This user interface can guide you through creating an initial cackle.toml. \
-             Press 'h' at any time to see what keys are available."
-                .to_owned()
-        }
+             Press '{}' at any time to see what keys are available.",
+            keys.label(Action::Help)
+        ),
         Problem::OffTreeApiUsage(info) => {
             let pkg = &info.usages.pkg_id;
             let api = &info.usages.api_name;
@@ -1057,6 +2199,61 @@ fn problem_details(problem: &Problem) -> String {
     }
 }
 
+/// Appends up to a few of `names` in parentheses, followed by a newline.
+fn write_some_names(text: &mut String, names: impl ExactSizeIterator<Item = String>) {
+    const MAX_NAMES_SHOWN: usize = 5;
+    let total = names.len();
+    if total > 0 {
+        let names: Vec<String> = names.take(MAX_NAMES_SHOWN).collect();
+        let more = if total > MAX_NAMES_SHOWN { ", ..." } else { "" };
+        text.push_str(&format!(" ({}{more})", names.join(", ")));
+    }
+    text.push('\n');
+}
+
+/// Returns a line for each permission selector of `pkg_id` that has been granted anything,
+/// summarising what has been granted. This includes selectors for all versions of the package.
+fn granted_permissions(config: &Config, pkg_id: &PackageId) -> Vec<String> {
+    let mut granted: Vec<_> = config
+        .permissions_no_inheritance
+        .packages
+        .iter()
+        .filter(|(perm_sel, _)| pkg_id.is_selected_by(&perm_sel.package_name))
+        .filter_map(|(perm_sel, pkg_config)| {
+            let mut parts: Vec<String> = Vec::new();
+            if !pkg_config.allow_apis.is_empty() {
+                let apis: Vec<&str> = pkg_config
+                    .allow_apis
+                    .iter()
+                    .map(|api| api.name.as_ref())
+                    .collect();
+                parts.push(format!("APIs {}", apis.join(", ")));
+            }
+            if pkg_config.allow_unsafe {
+                parts.push("unsafe".to_owned());
+            }
+            if pkg_config.allow_proc_macro {
+                parts.push("proc macro".to_owned());
+            }
+            if pkg_config.allow_ffi_export {
+                parts.push("FFI export".to_owned());
+            }
+            if !pkg_config.allow_build_instructions.is_empty() {
+                parts.push(format!(
+                    "build instructions {}",
+                    pkg_config.allow_build_instructions.join(", ")
+                ));
+            }
+            (!parts.is_empty()).then(|| (perm_sel, parts.join("; ")))
+        })
+        .collect();
+    granted.sort();
+    granted
+        .into_iter()
+        .map(|(perm_sel, summary)| format!("{perm_sel}: {summary}"))
+        .collect()
+}
+
 fn split_vertical(area: Rect, percentages: &[u16]) -> Rc<[Rect]> {
     let constraints: Vec<_> = percentages
         .iter()
@@ -1068,3 +2265,165 @@ fn split_vertical(area: Rect, percentages: &[u16]) -> Rc<[Rect]> {
         .constraints(constraints)
         .split(area)
 }
+
+/// Returns the lines shown by the progress dashboard.
+fn dashboard_lines(
+    progress: &BuildProgress,
+    pstore: &ProblemStore,
+    num_packages: usize,
+    num_edits: usize,
+) -> Vec<String> {
+    let elapsed = progress
+        .elapsed()
+        .map(|elapsed| {
+            let secs = elapsed.as_secs();
+            format!("{}m {:02}s", secs / 60, secs % 60)
+        })
+        .unwrap_or_else(|| "not started".to_owned());
+    vec![
+        format!("Build time: {elapsed}"),
+        format!(
+            "Packages compiled: {} of {num_packages}",
+            progress.packages_compiled()
+        ),
+        format!("Crates in progress: {}", progress.num_active()),
+        format!("Problems outstanding: {}", pstore.len()),
+        format!("Problems resolved: {}", pstore.num_resolved()),
+        format!("Edits applied this session: {num_edits}"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::button_at;
+    use super::dashboard_lines;
+    use super::format_line;
+    use super::granted_permissions;
+    use super::Button;
+    use super::Highlight;
+    use crate::build_progress::BuildProgress;
+    use crate::crate_index::testing::pkg_id;
+    use crate::crate_index::CrateSel;
+    use crate::problem::Problem;
+    use crate::problem::ProblemList;
+    use crate::proxy::rpc::BuildPhase;
+    use crate::proxy::rpc::ProgressUpdate;
+    use crate::ui::full_term::keys::Action;
+    use ratatui::style::Modifier;
+
+    fn highlighted_tokens(highlight: Highlight, line: &str) -> Vec<String> {
+        let mut spans = Vec::new();
+        format_line(&mut spans, highlight, line);
+        spans
+            .into_iter()
+            .filter(|span| span.style.add_modifier.contains(Modifier::REVERSED))
+            .map(|span| span.content.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn highlighting() {
+        let line = "let f = File::open(path);";
+        assert_eq!(highlighted_tokens(Highlight::Column(15), line), ["open"]);
+        assert_eq!(highlighted_tokens(Highlight::Name("File"), line), ["File"]);
+        assert!(highlighted_tokens(Highlight::None, line).is_empty());
+    }
+
+    #[test]
+    fn granted() {
+        let config = crate::config::testing::parse(
+            r#"
+                [api.fs]
+                include = ["std::fs"]
+
+                [api.net]
+                include = ["std::net"]
+
+                [pkg.crab1]
+                allow_apis = ["fs", "net"]
+                allow_unsafe = true
+                build.allow_apis = ["fs"]
+
+                [pkg.crab2]
+                allow_unsafe = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            granted_permissions(&config, &pkg_id("crab1")),
+            vec!["crab1: APIs fs, net; unsafe", "crab1.build: APIs fs"]
+        );
+        assert!(granted_permissions(&config, &pkg_id("crab3")).is_empty());
+    }
+
+    #[test]
+    fn buttons() {
+        // Rendered as " f  Fixes  esc  Back ".
+        let buttons = [
+            Button::new("f".to_owned(), "Fixes", Action::Fixes),
+            Button::new("esc".to_owned(), "Back", Action::Back),
+        ];
+        assert_eq!(button_at(&buttons, 0), Some(Action::Fixes));
+        assert_eq!(button_at(&buttons, 9), Some(Action::Fixes));
+        assert_eq!(button_at(&buttons, 10), Some(Action::Back));
+        assert_eq!(button_at(&buttons, 20), Some(Action::Back));
+        assert_eq!(button_at(&buttons, 21), None);
+    }
+
+    #[test]
+    fn dashboard() {
+        let pstore_ref = crate::problem_store::create(std::sync::mpsc::channel().0);
+        let mut progress = BuildProgress::default();
+        assert_eq!(
+            dashboard_lines(&progress, &pstore_ref.lock(), 3, 0),
+            vec![
+                "Build time: not started",
+                "Packages compiled: 0 of 3",
+                "Crates in progress: 0",
+                "Problems outstanding: 0",
+                "Problems resolved: 0",
+                "Edits applied this session: 0",
+            ]
+        );
+
+        let mut problems = ProblemList::default();
+        problems.push(Problem::UsesBuildScript(pkg_id("crab1")));
+        problems.push(Problem::UsesBuildScript(pkg_id("crab2")));
+        let mut fixer = pstore_ref.clone();
+        let waiter = std::thread::spawn(move || fixer.fix_problems(problems));
+        while pstore_ref.lock().is_empty() {
+            std::thread::yield_now();
+        }
+        for (name, phase) in [
+            ("crab1", BuildPhase::Compiling),
+            ("crab2", BuildPhase::Compiling),
+            ("crab1", BuildPhase::Finished),
+        ] {
+            progress.update(&ProgressUpdate {
+                crate_sel: CrateSel::primary(pkg_id(name)),
+                phase,
+                percent: None,
+            });
+        }
+        let mut pstore = pstore_ref.lock();
+        let (id, _) = pstore.deduplicated_into_iter().next().unwrap();
+        pstore.resolve(id);
+        let lines = dashboard_lines(&progress, &pstore, 3, 1);
+        assert!(lines[0].starts_with("Build time: 0m 0"));
+        assert_eq!(
+            lines[1..],
+            [
+                "Packages compiled: 1 of 3",
+                "Crates in progress: 1",
+                "Problems outstanding: 1",
+                "Problems resolved: 1",
+                "Edits applied this session: 1",
+            ]
+        );
+
+        let (id, _) = pstore.deduplicated_into_iter().next().unwrap();
+        pstore.resolve(id);
+        drop(pstore);
+        waiter.join().unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+//! Filtering of the problem list, either by searching for text, or by only showing problems that
+//! are like a selected problem in some respect, such as being for the same crate.
+
+use crate::config::ApiName;
+use crate::crate_index::PackageId;
+use crate::problem::Problem;
+use anyhow::anyhow;
+use anyhow::Result;
+use ratatui::style::Color;
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::text::Span;
+
+#[derive(Default, Debug, Clone)]
+pub(super) struct Filter {
+    /// Only problems whose description contains this text, ignoring ASCII case, are shown.
+    search: String,
+    package: Option<PackageId>,
+    api: Option<ApiName>,
+    /// As returned by `kind`.
+    kind: Option<&'static str>,
+}
+
+/// A respect in which problems can be required to be like the selected problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Toggle {
+    Crate,
+    Api,
+    Kind,
+}
+
+impl Filter {
+    pub(super) fn matches(&self, problem: &Problem) -> bool {
+        self.package
+            .as_ref()
+            .map_or(true, |pkg_id| problem.pkg_id() == Some(pkg_id))
+            && self
+                .api
+                .as_ref()
+                .map_or(true, |api| api_names(problem).contains(&api))
+            && self.kind.map_or(true, |kind| self::kind(problem) == kind)
+            && (self.search.is_empty()
+                || !match_ranges(&problem.to_string(), &self.search).is_empty())
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        !self.search.is_empty()
+            || self.package.is_some()
+            || self.api.is_some()
+            || self.kind.is_some()
+    }
+
+    pub(super) fn search(&self) -> &str {
+        &self.search
+    }
+
+    pub(super) fn set_search(&mut self, search: &str) {
+        self.search = search.to_owned();
+    }
+
+    /// If we're already filtering in the respect given by `toggle`, then stops doing so. Otherwise
+    /// only shows problems that are like `problem` in that respect.
+    pub(super) fn toggle(&mut self, toggle: Toggle, problem: Option<&Problem>) -> Result<()> {
+        let was_set = match toggle {
+            Toggle::Crate => self.package.take().is_some(),
+            Toggle::Api => self.api.take().is_some(),
+            Toggle::Kind => self.kind.take().is_some(),
+        };
+        if was_set {
+            return Ok(());
+        }
+        let problem = problem.ok_or_else(|| anyhow!("No problem selected"))?;
+        match toggle {
+            Toggle::Crate => {
+                self.package = Some(
+                    problem
+                        .pkg_id()
+                        .ok_or_else(|| anyhow!("This problem isn't for a particular crate"))?
+                        .clone(),
+                );
+            }
+            Toggle::Api => {
+                self.api = Some(
+                    api_names(problem)
+                        .first()
+                        .map(|api| (*api).clone())
+                        .ok_or_else(|| anyhow!("This problem isn't for a particular API"))?,
+                );
+            }
+            Toggle::Kind => self.kind = Some(kind(problem)),
+        }
+        Ok(())
+    }
+
+    /// Returns a description of what problems are being shown. e.g. "crate `foo`, text "env"".
+    pub(super) fn description(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(pkg_id) = &self.package {
+            parts.push(format!("crate `{pkg_id}`"));
+        }
+        if let Some(api) = &self.api {
+            parts.push(format!("API `{api}`"));
+        }
+        if let Some(kind) = self.kind {
+            parts.push(format!("kind `{kind}`"));
+        }
+        if !self.search.is_empty() {
+            parts.push(format!("text \"{}\"", self.search));
+        }
+        parts.join(", ")
+    }
+
+    /// Returns `text` as a line in which matches of the search text are highlighted.
+    pub(super) fn highlight(&self, text: &str) -> Line<'static> {
+        let style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let mut spans = Vec::new();
+        let mut offset = 0;
+        for (start, end) in match_ranges(text, &self.search) {
+            spans.push(Span::raw(text[offset..start].to_owned()));
+            spans.push(Span::styled(text[start..end].to_owned(), style));
+            offset = end;
+        }
+        spans.push(Span::raw(text[offset..].to_owned()));
+        Line::from(spans)
+    }
+}
+
+/// Returns the byte ranges of non-overlapping occurrences of `search` within `text`, ignoring ASCII
+/// case. Since only ASCII characters are changed, the ranges are valid for `text`.
+fn match_ranges(text: &str, search: &str) -> Vec<(usize, usize)> {
+    if search.is_empty() {
+        return Vec::new();
+    }
+    let text = text.to_ascii_lowercase();
+    let search = search.to_ascii_lowercase();
+    text.match_indices(&search)
+        .map(|(start, matched)| (start, start + matched.len()))
+        .collect()
+}
+
+/// Returns the APIs that `problem` relates to.
+fn api_names(problem: &Problem) -> Vec<&ApiName> {
+    match problem {
+        Problem::DisallowedApiUsage(usages) => vec![&usages.api_name],
+        Problem::OffTreeApiUsage(off_tree) => vec![&off_tree.usages.api_name],
+        Problem::UnusedAllowApi(unused) => unused.apis.iter().collect(),
+        Problem::ImportStdApi(api) => vec![api],
+        Problem::AvailableApi(available) => vec![&available.api],
+        Problem::PossibleExportedApi(possible) => vec![&possible.api],
+        _ => Vec::new(),
+    }
+}
+
+/// Returns a name for the kind of problem. We use the same names as for SARIF rules.
+fn kind(problem: &Problem) -> &'static str {
+    crate::sarif::rule(problem).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+    use super::Toggle;
+    use crate::config::permissions::PermSel;
+    use crate::config::permissions::PermissionScope;
+    use crate::config::ApiName;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::ApiUsages;
+    use crate::problem::Problem;
+    use crate::problem::Severity;
+
+    fn api_usage(pkg: &str, api: &'static str) -> Problem {
+        Problem::DisallowedApiUsage(ApiUsages {
+            pkg_id: pkg_id(pkg),
+            scope: PermissionScope::All,
+            api_name: ApiName::from(api),
+            usages: Vec::new(),
+            severity: Severity::Error,
+        })
+    }
+
+    #[test]
+    fn toggles() {
+        let crab1_fs = api_usage("crab1", "fs");
+        let crab1_net = api_usage("crab1", "net");
+        let crab2_fs = api_usage("crab2", "fs");
+        let unused = Problem::UnusedPackageConfig(PermSel::for_primary("crab1"));
+        let message = Problem::new("Something went wrong");
+
+        let mut filter = Filter::default();
+        assert!(!filter.is_active());
+        filter.toggle(Toggle::Crate, Some(&crab1_fs)).unwrap();
+        assert!(filter.matches(&crab1_net));
+        assert!(!filter.matches(&crab2_fs));
+        assert!(!filter.matches(&message));
+
+        filter.toggle(Toggle::Api, Some(&crab1_fs)).unwrap();
+        assert!(!filter.matches(&crab1_net));
+        assert_eq!(filter.description(), "crate `crab1`, API `fs`");
+
+        // Toggling again removes the restriction, even if the selected problem couldn't have been
+        // used to set it.
+        filter.toggle(Toggle::Crate, Some(&message)).unwrap();
+        assert!(filter.matches(&crab2_fs));
+        assert!(filter.toggle(Toggle::Crate, Some(&message)).is_err());
+
+        filter.toggle(Toggle::Api, None).unwrap();
+        filter.toggle(Toggle::Kind, Some(&unused)).unwrap();
+        assert!(filter.matches(&unused));
+        assert!(!filter.matches(&crab1_fs));
+        filter.toggle(Toggle::Kind, None).unwrap();
+        assert!(!filter.is_active());
+    }
+
+    #[test]
+    fn search() {
+        let mut filter = Filter::default();
+        filter.set_search("CRAB1");
+        assert!(filter.matches(&api_usage("crab1", "fs")));
+        assert!(!filter.matches(&api_usage("crab2", "fs")));
+
+        filter.set_search("ab");
+        let line = filter.highlight("Crab is ABle");
+        let spans: Vec<&str> = line
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(spans, vec!["Cr", "ab", " is ", "AB", "le"]);
+    }
+}
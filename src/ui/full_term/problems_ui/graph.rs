@@ -0,0 +1,253 @@
+//! A tree showing why a crate needs permission to use an API. It goes from the crate, through the
+//! functions that make the usage, to the symbols that were referenced and finally the API that
+//! they belong to.
+
+use crate::config::permissions::PermSel;
+use crate::location::SourceLocation;
+use crate::problem::ApiUsages;
+use crate::problem::Problem;
+use crate::ui::full_term::keys::Action;
+use anyhow::bail;
+use anyhow::Result;
+
+#[derive(Debug)]
+pub(super) struct Graph {
+    root: Node,
+    /// The index of the selected row within `rows()`.
+    selected: usize,
+}
+
+#[derive(Debug)]
+struct Node {
+    label: String,
+    location: Option<SourceLocation>,
+    expanded: bool,
+    children: Vec<Node>,
+}
+
+/// A node of the graph that's currently visible.
+pub(super) struct Row<'a> {
+    /// Lines connecting the node to its parent and siblings.
+    pub(super) prefix: String,
+    pub(super) label: &'a str,
+    /// The indices of the children that lead from the root to this node.
+    path: Vec<usize>,
+    has_children: bool,
+    expanded: bool,
+}
+
+impl Graph {
+    pub(super) fn for_problem(problem: &Problem) -> Result<Self> {
+        let root = match problem {
+            Problem::DisallowedApiUsage(usages) => api_usages_tree(usages, None),
+            Problem::OffTreeApiUsage(off_tree) => api_usages_tree(
+                &off_tree.usages,
+                Some(&format!(
+                    ", provided by non-dependency `{}`",
+                    off_tree.referenced_pkg_id
+                )),
+            ),
+            _ => bail!("Graphs are only available for API usages"),
+        };
+        Ok(Self { root, selected: 0 })
+    }
+
+    pub(super) fn rows(&self) -> Vec<Row<'_>> {
+        let mut rows = vec![Row {
+            prefix: String::new(),
+            label: &self.root.label,
+            path: Vec::new(),
+            has_children: !self.root.children.is_empty(),
+            expanded: self.root.expanded,
+        }];
+        if self.root.expanded {
+            add_rows(&self.root, &mut Vec::new(), "", &mut rows);
+        }
+        rows
+    }
+
+    pub(super) fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Up and down move the selection. Right expands the selected node, or if it's already
+    /// expanded, moves to its first child. Left collapses the selected node, or if it's already
+    /// collapsed, moves to its parent.
+    pub(super) fn handle_action(&mut self, action: Action) {
+        let rows = self.rows();
+        let Some(row) = rows.get(self.selected) else {
+            return;
+        };
+        match action {
+            Action::Up => self.selected = self.selected.saturating_sub(1),
+            Action::Down => self.selected = (self.selected + 1).min(rows.len() - 1),
+            Action::Right if row.has_children && !row.expanded => {
+                let path = row.path.clone();
+                self.node_mut(&path).expanded = true;
+            }
+            Action::Right if row.has_children => self.selected += 1,
+            Action::Left if row.has_children && row.expanded => {
+                let path = row.path.clone();
+                self.node_mut(&path).expanded = false;
+            }
+            Action::Left => {
+                if let Some((_, parent_path)) = row.path.split_last() {
+                    if let Some(parent) = rows.iter().position(|r| r.path == parent_path) {
+                        self.selected = parent;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the source location of the selected node, if it has one.
+    pub(super) fn selected_location(&self) -> Option<&SourceLocation> {
+        let path = self.rows().get(self.selected)?.path.clone();
+        let mut node = &self.root;
+        for index in path {
+            node = &node.children[index];
+        }
+        node.location.as_ref()
+    }
+
+    fn node_mut(&mut self, path: &[usize]) -> &mut Node {
+        let mut node = &mut self.root;
+        for index in path {
+            node = &mut node.children[*index];
+        }
+        node
+    }
+}
+
+impl Node {
+    fn new(label: String, location: Option<SourceLocation>) -> Self {
+        Self {
+            label,
+            location,
+            expanded: true,
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns the child with the supplied label, adding it if there isn't one already.
+    fn child(&mut self, label: String, location: Option<SourceLocation>) -> &mut Node {
+        let index = match self.children.iter().position(|c| c.label == label) {
+            Some(index) => index,
+            None => {
+                self.children.push(Node::new(label, location));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+}
+
+fn api_usages_tree(usages: &ApiUsages, provided_by: Option<&str>) -> Node {
+    let perm_sel = PermSel::with_scope(&usages.pkg_id, usages.scope);
+    let mut root = Node::new(format!("`{perm_sel}`"), None);
+    for usage in &usages.usages {
+        // The call chain, if we have one, ends with the function containing the usage.
+        let mut node = &mut root;
+        if let Some((_, callers)) = usage.call_chain.split_last() {
+            for caller in callers {
+                node = node.child(caller.to_string(), None);
+            }
+        }
+        node = node.child(
+            usage.from.to_string(),
+            usage
+                .outer_location
+                .clone()
+                .or_else(|| Some(usage.source_location.clone())),
+        );
+        let location = &usage.source_location;
+        node = node.child(
+            format!(
+                "{} at {}:{}",
+                usage.to,
+                location.filename().display(),
+                location.line()
+            ),
+            Some(location.clone()),
+        );
+        node.child(
+            format!("API `{}`{}", usages.api_name, provided_by.unwrap_or("")),
+            None,
+        );
+    }
+    root
+}
+
+fn add_rows<'a>(node: &'a Node, path: &mut Vec<usize>, indent: &str, rows: &mut Vec<Row<'a>>) {
+    for (index, child) in node.children.iter().enumerate() {
+        let is_last = index + 1 == node.children.len();
+        path.push(index);
+        rows.push(Row {
+            prefix: format!("{indent}{}", if is_last { "└─ " } else { "├─ " }),
+            label: &child.label,
+            path: path.clone(),
+            has_children: !child.children.is_empty(),
+            expanded: child.expanded,
+        });
+        if child.expanded {
+            let indent = format!("{indent}{}", if is_last { "   " } else { "│  " });
+            add_rows(child, path, &indent, rows);
+        }
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Graph;
+    use super::Node;
+    use crate::ui::full_term::keys::Action;
+
+    fn rendered(graph: &Graph) -> Vec<String> {
+        graph
+            .rows()
+            .iter()
+            .map(|row| format!("{}{}", row.prefix, row.label))
+            .collect()
+    }
+
+    #[test]
+    fn navigation() {
+        let mut root = Node::new("crab".to_owned(), None);
+        root.child("main".to_owned(), None)
+            .child("read".to_owned(), None)
+            .child("fs".to_owned(), None);
+        root.child("helper".to_owned(), None)
+            .child("write".to_owned(), None);
+        let mut graph = Graph { root, selected: 0 };
+        assert_eq!(
+            rendered(&graph),
+            vec![
+                "crab",
+                "├─ main",
+                "│  └─ read",
+                "│     └─ fs",
+                "└─ helper",
+                "   └─ write",
+            ]
+        );
+
+        // Collapse `main`.
+        graph.handle_action(Action::Right);
+        assert_eq!(graph.selected(), 1);
+        graph.handle_action(Action::Left);
+        assert_eq!(
+            rendered(&graph),
+            vec!["crab", "├─ main", "└─ helper", "   └─ write"]
+        );
+
+        // Moving left from a leaf goes to its parent.
+        graph.handle_action(Action::Down);
+        graph.handle_action(Action::Down);
+        graph.handle_action(Action::Down);
+        assert_eq!(graph.selected(), 3);
+        graph.handle_action(Action::Left);
+        assert_eq!(graph.selected(), 2);
+    }
+}
@@ -0,0 +1,228 @@
+//! A journal of edits that have been made to the config from the UI, so that they can be undone and
+//! redone. Undoing an edit restores the config as it was before the edit and reinstates whatever
+//! problems the edit resolved.
+
+use crate::problem::Problem;
+use crate::problem::ProblemList;
+use crate::problem_store::ProblemId;
+use crate::problem_store::ProblemStore;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use fxhash::FxHashSet;
+use std::path::Path;
+
+#[derive(Default)]
+pub(super) struct Journal {
+    undo: Vec<Entry>,
+    redo: Vec<Entry>,
+}
+
+struct Entry {
+    /// The config before the edit, or None if it didn't exist.
+    config_before: Option<String>,
+    config_after: String,
+    /// Problems that the edit resolved.
+    resolved: Vec<Problem>,
+    /// Problems that were added in place of those that the edit resolved.
+    added: Vec<Problem>,
+}
+
+/// The state prior to an edit being applied.
+pub(super) struct Before {
+    config: Option<String>,
+    problems: Vec<(ProblemId, Problem)>,
+}
+
+impl Before {
+    pub(super) fn capture(config_path: &Path, pstore: &ProblemStore) -> Result<Self> {
+        Ok(Self {
+            config: read_config(config_path)?,
+            problems: pstore
+                .deduplicated_into_iter()
+                .map(|(id, problem)| (id, problem.clone()))
+                .collect(),
+        })
+    }
+}
+
+impl Journal {
+    /// Records an edit that has just been applied. `before` must have been captured prior to
+    /// applying the edit, without `pstore` having been unlocked since, otherwise problems that got
+    /// reported in the meantime would be considered part of the edit.
+    pub(super) fn record(
+        &mut self,
+        before: Before,
+        config_path: &Path,
+        pstore: &ProblemStore,
+    ) -> Result<()> {
+        let Some(config_after) = read_config(config_path)? else {
+            return Ok(());
+        };
+        let ids_after: FxHashSet<ProblemId> =
+            pstore.deduplicated_into_iter().map(|(id, _)| id).collect();
+        let ids_before: FxHashSet<ProblemId> = before.problems.iter().map(|(id, _)| *id).collect();
+        let resolved: Vec<Problem> = before
+            .problems
+            .into_iter()
+            .filter(|(id, _)| !ids_after.contains(id))
+            .map(|(_, problem)| problem)
+            .collect();
+        let added = pstore
+            .deduplicated_into_iter()
+            .filter(|(id, _)| !ids_before.contains(id))
+            .map(|(_, problem)| problem.clone())
+            .collect();
+        if resolved.is_empty() && before.config.as_ref() == Some(&config_after) {
+            return Ok(());
+        }
+        self.undo.push(Entry {
+            config_before: before.config,
+            config_after,
+            resolved,
+            added,
+        });
+        self.redo.clear();
+        Ok(())
+    }
+
+    /// Returns the number of edits that have been applied and not since undone.
+    pub(super) fn num_applied(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Reverts the config to how it was before the most recent edit and reinstates the problems
+    /// that the edit resolved.
+    pub(super) fn undo(&mut self, config_path: &Path, pstore: &mut ProblemStore) -> Result<()> {
+        let Some(entry) = self.undo.pop() else {
+            bail!("There are no edits to undo");
+        };
+        if let Err(error) = check_unchanged(config_path, Some(&entry.config_after)) {
+            self.undo.push(entry);
+            return Err(error);
+        }
+        write_config(config_path, entry.config_before.as_deref())?;
+        let added_ids = ids_of(pstore, &entry.added);
+        pstore.reinstate(entry.resolved.clone(), &added_ids);
+        self.redo.push(entry);
+        Ok(())
+    }
+
+    /// Reapplies the most recently undone edit.
+    pub(super) fn redo(&mut self, config_path: &Path, pstore: &mut ProblemStore) -> Result<()> {
+        let Some(entry) = self.redo.pop() else {
+            bail!("There are no undone edits to redo");
+        };
+        if let Err(error) = check_unchanged(config_path, entry.config_before.as_deref()) {
+            self.redo.push(entry);
+            return Err(error);
+        }
+        write_config(config_path, Some(&entry.config_after))?;
+        let resolved_ids = ids_of(pstore, &entry.resolved);
+        if let Some((first, rest)) = resolved_ids.split_first() {
+            let mut added = ProblemList::default();
+            for problem in &entry.added {
+                added.push(problem.clone());
+            }
+            pstore.replace(*first, added);
+            for id in rest {
+                pstore.resolve(*id);
+            }
+        }
+        self.undo.push(entry);
+        Ok(())
+    }
+}
+
+fn read_config(config_path: &Path) -> Result<Option<String>> {
+    if !config_path.exists() {
+        return Ok(None);
+    }
+    crate::fs::read_to_string(config_path).map(Some)
+}
+
+fn write_config(config_path: &Path, contents: Option<&str>) -> Result<()> {
+    match contents {
+        Some(contents) => crate::fs::write_atomic(config_path, contents),
+        None => std::fs::remove_file(config_path)
+            .with_context(|| format!("Failed to remove `{}`", config_path.display())),
+    }
+}
+
+/// Checks that the config is as we left it, so that we don't clobber changes made outside of the
+/// UI.
+fn check_unchanged(config_path: &Path, expected: Option<&str>) -> Result<()> {
+    if read_config(config_path)?.as_deref() != expected {
+        bail!(
+            "`{}` has been changed outside of cackle, so the edit can't be undone or redone",
+            config_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Returns the IDs of the unresolved problems in `pstore` that are in `problems`.
+fn ids_of(pstore: &ProblemStore, problems: &[Problem]) -> Vec<ProblemId> {
+    pstore
+        .deduplicated_into_iter()
+        .filter(|(_, problem)| problems.contains(problem))
+        .map(|(id, _)| id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Before;
+    use super::Journal;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::Problem;
+    use crate::problem::ProblemList;
+
+    #[test]
+    fn undo_redo() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("cackle.toml");
+        std::fs::write(&config_path, "before").unwrap();
+        let pstore_ref = crate::problem_store::create(std::sync::mpsc::channel().0);
+        let mut problems = ProblemList::default();
+        problems.push(Problem::UsesBuildScript(pkg_id("crab1")));
+        problems.push(Problem::UsesBuildScript(pkg_id("crab2")));
+        let mut fixer = pstore_ref.clone();
+        let waiter = std::thread::spawn(move || fixer.fix_problems(problems));
+        while pstore_ref.lock().is_empty() {
+            std::thread::yield_now();
+        }
+
+        let mut journal = Journal::default();
+        let mut pstore = pstore_ref.lock();
+        let before = Before::capture(&config_path, &pstore).unwrap();
+        let (id, _) = pstore.deduplicated_into_iter().next().unwrap();
+        std::fs::write(&config_path, "after").unwrap();
+        pstore.resolve(id);
+        journal.record(before, &config_path, &pstore).unwrap();
+        assert_eq!(pstore.len(), 1);
+        assert_eq!(journal.num_applied(), 1);
+
+        journal.undo(&config_path, &mut pstore).unwrap();
+        assert_eq!(std::fs::read_to_string(&config_path).unwrap(), "before");
+        assert_eq!(pstore.len(), 2);
+        assert_eq!(journal.num_applied(), 0);
+        assert!(journal.undo(&config_path, &mut pstore).is_err());
+
+        journal.redo(&config_path, &mut pstore).unwrap();
+        assert_eq!(std::fs::read_to_string(&config_path).unwrap(), "after");
+        assert_eq!(
+            pstore.deduplicated_into_iter().next().unwrap().1,
+            &Problem::UsesBuildScript(pkg_id("crab2"))
+        );
+
+        // Changes made outside of the UI prevent undo.
+        std::fs::write(&config_path, "external").unwrap();
+        assert!(journal.undo(&config_path, &mut pstore).is_err());
+
+        let (id, _) = pstore.deduplicated_into_iter().next().unwrap();
+        pstore.resolve(id);
+        drop(pstore);
+        assert_eq!(waiter.join().unwrap(), crate::outcome::Outcome::Continue);
+    }
+}
@@ -0,0 +1,153 @@
+//! A change to the config that's shown to the user as a diff, so that they can confirm it before
+//! it gets written. The user may also edit the proposed config before confirming it.
+
+use super::diff;
+use crate::config_editor::ConfigEditor;
+use crate::location::SourceLocation;
+use crate::problem::ProblemList;
+use crate::problem_store::ProblemId;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use ratatui::text::Line;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+#[derive(Debug)]
+pub(super) struct Proposal {
+    pub(super) title: String,
+    /// Something the user should know about the change. e.g. that some problems weren't included.
+    pub(super) note: Option<String>,
+    /// The config at the time the change was proposed.
+    original: String,
+    proposed: String,
+    /// The problems that the change resolves, each with the problems that should replace it.
+    resolves: Vec<(ProblemId, ProblemList)>,
+    /// A copy of the proposed config that the user is editing.
+    editing: Option<NamedTempFile>,
+}
+
+impl Proposal {
+    pub(super) fn new(
+        title: String,
+        original: String,
+        proposed: &ConfigEditor,
+        resolves: Vec<(ProblemId, ProblemList)>,
+    ) -> Self {
+        Self {
+            title,
+            note: None,
+            original,
+            proposed: proposed.to_toml(),
+            resolves,
+            editing: None,
+        }
+    }
+
+    pub(super) fn resolves(&self) -> &[(ProblemId, ProblemList)] {
+        &self.resolves
+    }
+
+    /// Writes the proposed config to a temporary file for the user to edit. Returns the location
+    /// in that file of the first change.
+    pub(super) fn start_editing(&mut self) -> Result<SourceLocation> {
+        let mut file = tempfile::Builder::new()
+            .prefix("cackle-")
+            .suffix(".toml")
+            .tempfile()?;
+        std::io::Write::write_all(&mut file, self.proposed.as_bytes())
+            .context("Failed to write proposed config")?;
+        let line = self
+            .original
+            .lines()
+            .zip(self.proposed.lines())
+            .take_while(|(original, proposed)| original == proposed)
+            .count()
+            + 1;
+        let location = SourceLocation::new(file.path(), line as u32, None);
+        self.editing = Some(file);
+        Ok(location)
+    }
+
+    /// Reads back the proposed config once the user has finished editing it. If what they wrote
+    /// isn't valid TOML, then it's kept anyway so that they can fix it, but an error is returned.
+    pub(super) fn finish_editing(&mut self) -> Result<()> {
+        let Some(file) = self.editing.take() else {
+            return Ok(());
+        };
+        self.proposed = crate::fs::read_to_string(file.path())?;
+        ConfigEditor::from_toml_string(&self.proposed).context("Edited config isn't valid TOML")?;
+        Ok(())
+    }
+
+    /// Writes the proposed config to `config_path`, provided that the config hasn't changed since
+    /// the proposal was made. Returns an editor for the config that was written.
+    pub(super) fn write(&self, config_path: &Path) -> Result<ConfigEditor> {
+        let current = std::fs::read_to_string(config_path).unwrap_or_default();
+        if current != self.original {
+            bail!(
+                "`{}` has changed since this edit was proposed",
+                config_path.display()
+            );
+        }
+        let editor = ConfigEditor::from_toml_string(&self.proposed)
+            .context("Proposed config isn't valid TOML")?;
+        crate::fs::write_atomic(config_path, &self.proposed)?;
+        Ok(editor)
+    }
+
+    /// Returns a diff of the change, trimmed, where possible, to `max_lines`.
+    pub(super) fn diff_lines(&self, max_lines: usize) -> Vec<Line<'static>> {
+        let mut lines = diff::diff_lines(&self.original, &self.proposed);
+        if lines.is_empty() {
+            lines.push(Line::from("The proposed config is unchanged"));
+        }
+        diff::remove_excess_context(&mut lines, max_lines);
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Proposal;
+    use crate::config_editor::ConfigEditor;
+
+    #[test]
+    fn edit_and_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("cackle.toml");
+        std::fs::write(&config_path, "[common]\nversion = 2\n").unwrap();
+        let editor =
+            ConfigEditor::from_toml_string("[common]\nversion = 2\n\n[pkg.crab1]\n").unwrap();
+        let mut proposal = Proposal::new(
+            "Test".to_owned(),
+            "[common]\nversion = 2\n".to_owned(),
+            &editor,
+            Vec::new(),
+        );
+
+        let location = proposal.start_editing().unwrap();
+        assert_eq!(location.line(), 3);
+        std::fs::write(
+            location.filename(),
+            "[common]\nversion = 2\n\n[pkg.crab2]\n",
+        )
+        .unwrap();
+        proposal.finish_editing().unwrap();
+        proposal.write(&config_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&config_path).unwrap(),
+            "[common]\nversion = 2\n\n[pkg.crab2]\n"
+        );
+
+        // The config has now changed, so the proposal is out of date.
+        assert!(proposal.write(&config_path).is_err());
+
+        // Invalid edits are reported, but kept so that they can be fixed.
+        let location = proposal.start_editing().unwrap();
+        std::fs::write(location.filename(), "[pkg.crab2").unwrap();
+        assert!(proposal.finish_editing().is_err());
+        std::fs::write(&config_path, "[common]\nversion = 2\n").unwrap();
+        assert!(proposal.write(&config_path).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! Colours used by the full-screen UI. A preset can be selected and individual colours overridden
+//! from the `[ui.theme]` section of cackle.toml.
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+use ratatui::style::Color;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Theme {
+    /// Borders of the active pane, the selected item in it and anything else that needs the user's
+    /// attention.
+    pub(super) highlight: Color,
+    /// Borders of error popups and inline errors.
+    pub(super) error: Color,
+    /// Status lines, such as the description of the current filter.
+    pub(super) status: Color,
+    /// Hints about what keys do, which are less important than what's around them.
+    pub(super) hint: Color,
+}
+
+const PRESETS: &[(&str, Theme)] = &[
+    (
+        "dark",
+        Theme {
+            highlight: Color::Yellow,
+            error: Color::Red,
+            status: Color::Cyan,
+            hint: Color::DarkGray,
+        },
+    ),
+    (
+        "light",
+        Theme {
+            highlight: Color::Blue,
+            error: Color::Red,
+            status: Color::Magenta,
+            hint: Color::DarkGray,
+        },
+    ),
+    (
+        // Uses the terminal's own foreground colour wherever we can, since that's presumably
+        // readable against its background.
+        "high-contrast",
+        Theme {
+            highlight: Color::Reset,
+            error: Color::LightRed,
+            status: Color::Reset,
+            hint: Color::Reset,
+        },
+    ),
+];
+
+impl Theme {
+    /// Returns the theme selected by `config`, which maps `preset` to the name of a preset and the
+    /// names of colours to their values. Colours that aren't set come from the preset.
+    pub(super) fn from_config(config: &BTreeMap<String, String>) -> Result<Self> {
+        let preset = config.get("preset").map(String::as_str).unwrap_or("dark");
+        let Some((_, mut theme)) = PRESETS.iter().find(|(name, _)| *name == preset).cloned() else {
+            bail!("Unknown preset `{preset}` in ui.theme");
+        };
+        for (name, value) in config {
+            let colour = match name.as_str() {
+                "preset" => continue,
+                "highlight" => &mut theme.highlight,
+                "error" => &mut theme.error,
+                "status" => &mut theme.status,
+                "hint" => &mut theme.hint,
+                _ => bail!("Unknown colour `{name}` in ui.theme"),
+            };
+            *colour = Color::from_str(value)
+                .map_err(|_| anyhow!("Invalid colour `{value}` for `{name}` in ui.theme"))?;
+        }
+        Ok(theme)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        PRESETS[0].1.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Theme;
+    use ratatui::style::Color;
+    use std::collections::BTreeMap;
+
+    fn config(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn presets_and_overrides() {
+        assert_eq!(Theme::from_config(&config(&[])).unwrap(), Theme::default());
+        let theme =
+            Theme::from_config(&config(&[("preset", "light"), ("hint", "#808080")])).unwrap();
+        assert_eq!(theme.highlight, Color::Blue);
+        assert_eq!(theme.hint, Color::Rgb(0x80, 0x80, 0x80));
+        let theme = Theme::from_config(&config(&[("highlight", "light-green")])).unwrap();
+        assert_eq!(theme.highlight, Color::LightGreen);
+        assert_eq!(theme.error, Color::Red);
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert!(Theme::from_config(&config(&[("preset", "solarized")])).is_err());
+        assert!(Theme::from_config(&config(&[("border", "red")])).is_err());
+        assert!(Theme::from_config(&config(&[("highlight", "reddish")])).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! A user-interface that never prompts, but instead applies the first available fix for each
+//! problem. This is used by the `init` subcommand to produce a draft configuration that grants
+//! whatever permissions are currently used and by the `baseline` subcommand to write a baseline
+//! that grandfathers all current problems.
+
+use crate::checker::Checker;
+use crate::config_editor;
+use crate::config_editor::ConfigEditor;
+use crate::config_editor::EditOpts;
+use crate::events::AppEvent;
+use crate::problem::Problem;
+use crate::problem::ProblemList;
+use crate::problem::Severity;
+use crate::problem_store::ProblemStoreRef;
+use anyhow::Result;
+use colored::Colorize;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Written at the top of the generated config file.
+const HEADER: &str = "\
+# Draft configuration generated by `cargo acl init`. Permissions were granted for everything that
+# the dependency tree currently uses. Review each entry marked TODO, then remove this comment.
+";
+
+/// Written at the top of the generated baseline.
+const BASELINE_HEADER: &str = "\
+# Written by `cargo acl baseline`. Permissions granted here are in addition to those in cackle.toml.
+# Rather than editing this file, fix problems, or grant permissions in cackle.toml, then rerun
+# `cargo acl baseline`.
+";
+
+/// Attached to each edit that supports comments.
+const REVIEW_COMMENT: &str = "TODO: review";
+
+pub(crate) struct InitUi {
+    config_path: PathBuf,
+    checker: Arc<Mutex<Checker>>,
+    abort_sender: Sender<()>,
+    /// Whether fixes should be written to the baseline rather than to the config.
+    baseline: bool,
+}
+
+impl InitUi {
+    pub(crate) fn new(
+        config_path: PathBuf,
+        checker: &Arc<Mutex<Checker>>,
+        abort_sender: Sender<()>,
+        baseline: bool,
+    ) -> Self {
+        Self {
+            config_path,
+            checker: checker.clone(),
+            abort_sender,
+            baseline,
+        }
+    }
+
+    /// Applies a fix for `problem`, returning the problems that replace it, or `None` if there's
+    /// no fix that we can apply.
+    fn fix_problem(&self, problem: &Problem) -> Result<Option<ProblemList>> {
+        if self.baseline {
+            return self.add_to_baseline(problem);
+        }
+        // Warnings don't cause failures, so we leave them for the user to decide on, rather than
+        // applying a fix such as extending an API to cover a possible export.
+        if problem.severity() < Severity::Error {
+            return Ok(Some(ProblemList::default()));
+        }
+        let mut editor = match problem {
+            Problem::MissingConfiguration(_) => ConfigEditor::initial(),
+            _ => ConfigEditor::from_file(&self.config_path)?,
+        };
+        let replacements = if let Problem::SelectSandbox = problem {
+            editor.set_sandbox_kind(crate::sandbox::available_kind())?;
+            ProblemList::default()
+        } else {
+            let config = self.checker.lock().unwrap().config.clone();
+            let fixes = config_editor::fixes_for_problem(problem, &config);
+            let Some(fix) = fixes.first() else {
+                return Ok(None);
+            };
+            fix.apply(
+                &mut editor,
+                &EditOpts {
+                    comment: Some(REVIEW_COMMENT.to_owned()),
+                },
+            )?;
+            println!("{}", fix.title());
+            fix.replacement_problems()
+        };
+        let mut toml = editor.to_toml();
+        if let Problem::MissingConfiguration(_) = problem {
+            toml = format!("{HEADER}{toml}");
+        }
+        crate::fs::write_atomic(&self.config_path, &toml)?;
+        Ok(Some(replacements))
+    }
+
+    /// Applies a fix for `problem` to the baseline, returning the problems that replace it, or
+    /// `None` if the problem can't be fixed by granting a permission in the baseline.
+    fn add_to_baseline(&self, problem: &Problem) -> Result<Option<ProblemList>> {
+        // Warnings and informational problems don't cause failures, so there's no need to grandfather them.
+        if problem.severity() < Severity::Error {
+            return Ok(Some(ProblemList::default()));
+        }
+        // The new baseline only replaces the old one once the run succeeds.
+        let baseline_path = crate::config::pending_baseline_path(&self.config_path);
+        let mut editor = ConfigEditor::from_file(&baseline_path)?;
+        let config = self.checker.lock().unwrap().config.clone();
+        let fixes = config_editor::fixes_for_problem(problem, &config);
+        let Some(fix) = fixes.first() else {
+            return Ok(None);
+        };
+        fix.apply(&mut editor, &EditOpts::default())?;
+        let mut toml = editor.to_toml();
+        let table: toml::Table = toml::from_str(&toml)?;
+        if table
+            .keys()
+            .any(|key| !crate::config::BASELINE_TABLES.contains(&key.as_str()))
+        {
+            return Ok(None);
+        }
+        if !baseline_path.exists() {
+            toml = format!("{BASELINE_HEADER}{toml}");
+        }
+        crate::fs::write_atomic(&baseline_path, &toml)?;
+        println!("{}", fix.title());
+        Ok(Some(fix.replacement_problems()))
+    }
+}
+
+impl super::UserInterface for InitUi {
+    fn run(
+        &mut self,
+        problem_store: ProblemStoreRef,
+        event_receiver: Receiver<AppEvent>,
+    ) -> Result<()> {
+        while let Ok(event) = event_receiver.recv() {
+            match event {
+                AppEvent::Shutdown => return Ok(()),
+                // Only the full terminal UI asks for progress and input events.
+                AppEvent::ProgressChanged => {}
+                #[cfg(feature = "ui")]
+                AppEvent::Input(..) => {}
+                AppEvent::ProblemsAdded => loop {
+                    let mut pstore = problem_store.lock();
+                    let Some((index, problem)) = pstore.deduplicated_into_iter().next() else {
+                        break;
+                    };
+                    match self.fix_problem(problem)? {
+                        Some(replacements) => pstore.replace(index, replacements),
+                        None => {
+                            let _ = self.abort_sender.send(());
+                            println!("{} {problem:#}", "ERROR:".red());
+                            if self.baseline {
+                                println!(
+                                    "This problem can't be added to the baseline. Fix it in \
+                                     cackle.toml, then rerun."
+                                );
+                            } else {
+                                println!("No automatic fix is available. Edit config manually.");
+                            }
+                            pstore.abort();
+                            break;
+                        }
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crate_index::testing::pkg_id;
+    use crate::problem::PossibleExportedApi;
+    use crate::symbol::Symbol;
+    use crate::tmpdir::TempDir;
+
+    #[test]
+    fn warnings_are_left_alone() {
+        let dir = TempDir::new(None).unwrap();
+        let config_path = dir.path().join("cackle.toml");
+        let config = "[common]\nversion = 2\n";
+        std::fs::write(&config_path, config).unwrap();
+        let checker = Arc::new(Mutex::new(Checker::new(
+            Arc::new(TempDir::new(None).unwrap()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )));
+        let (abort_sender, _abort_receiver) = std::sync::mpsc::channel();
+        let ui = InitUi::new(config_path.clone(), &checker, abort_sender, false);
+        let problem = Problem::PossibleExportedApi(PossibleExportedApi {
+            pkg_id: pkg_id("crab1"),
+            api: "fs".into(),
+            symbol: Symbol::borrowed(b"crab1::read_file").to_heap(),
+        });
+        assert!(ui.fix_problem(&problem).unwrap().unwrap().is_empty());
+        assert_eq!(std::fs::read_to_string(&config_path).unwrap(), config);
+    }
+}
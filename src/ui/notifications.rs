@@ -0,0 +1,97 @@
+//! Desktop notifications for when problems are found while the user isn't looking. Enabled by
+//! `ui.notifications` in cackle.toml.
+
+use crate::config::Config;
+use log::info;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long since the user last did anything before we consider them to have stepped away.
+const IDLE_TIME: Duration = Duration::from_secs(30);
+
+pub(super) struct Notifier {
+    last_activity: Instant,
+    /// Whether we've sent a notification since the user was last active. We only send one, since
+    /// several batches of problems often arrive in quick succession.
+    notified: bool,
+}
+
+impl Notifier {
+    pub(super) fn new() -> Self {
+        Self {
+            last_activity: Instant::now(),
+            notified: false,
+        }
+    }
+
+    /// Records that the user did something, such as pressing a key.
+    pub(super) fn activity(&mut self) {
+        self.last_activity = Instant::now();
+        self.notified = false;
+    }
+
+    /// Called when problems are added. Sends a notification if notifications are enabled and the
+    /// user has been idle. `num_problems` is the number of problems that now need attention.
+    pub(super) fn problems_added(&mut self, config: &Config, num_problems: usize) {
+        if !config.raw.ui.notifications || !self.should_notify(Instant::now()) {
+            return;
+        }
+        self.notified = true;
+        let body = if num_problems == 1 {
+            "1 problem needs review".to_owned()
+        } else {
+            format!("{num_problems} problems need review")
+        };
+        send("Cackle needs your input", &body);
+    }
+
+    fn should_notify(&self, now: Instant) -> bool {
+        !self.notified && now.saturating_duration_since(self.last_activity) >= IDLE_TIME
+    }
+}
+
+/// Sends a notification via `notify-send`, which talks to whatever notification daemon is running.
+/// Failures are logged, but otherwise ignored, since notifications are only a convenience.
+fn send(summary: &str, body: &str) {
+    let mut command = Command::new("notify-send");
+    command
+        .arg("--app-name=Cackle")
+        .arg(summary)
+        .arg(body)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Wait on a separate thread so that we neither block the UI nor leave a zombie process.
+    let result = std::thread::Builder::new()
+        .name("notify".to_owned())
+        .spawn(move || match command.status() {
+            Ok(status) if !status.success() => info!("notify-send failed with {status}"),
+            Ok(_) => {}
+            Err(error) => info!("Failed to run notify-send: {error}"),
+        });
+    if let Err(error) = result {
+        info!("Failed to start notification thread: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Notifier;
+    use super::IDLE_TIME;
+    use std::time::Duration;
+
+    #[test]
+    fn notifies_once_when_idle() {
+        let mut notifier = Notifier::new();
+        let start = notifier.last_activity;
+        assert!(!notifier.should_notify(start + Duration::from_secs(1)));
+        assert!(notifier.should_notify(start + IDLE_TIME));
+        notifier.notified = true;
+        assert!(!notifier.should_notify(start + IDLE_TIME * 2));
+        notifier.activity();
+        assert!(!notifier.notified);
+        assert!(notifier.should_notify(notifier.last_activity + IDLE_TIME));
+    }
+}
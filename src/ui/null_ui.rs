@@ -1,9 +1,11 @@
 //! A user-interface that never prompts. This is used when non-interactive mode is selected.
 
+use crate::cli::Args;
 use crate::events::AppEvent;
+use crate::lint_config;
 use crate::problem::Severity;
 use crate::problem_store::ProblemStoreRef;
-use crate::Args;
+use crate::sarif::OutputFormat;
 use anyhow::Result;
 use colored::Colorize;
 use std::sync::mpsc::Receiver;
@@ -33,37 +35,68 @@ impl super::UserInterface for NullUi {
         while let Ok(event) = event_receiver.recv() {
             match event {
                 AppEvent::Shutdown => return Ok(()),
+                // Only the full terminal UI asks for progress and input events.
+                AppEvent::ProgressChanged => {}
+                #[cfg(feature = "ui")]
+                AppEvent::Input(..) => {}
                 AppEvent::ProblemsAdded => {
                     let mut pstore = problem_store.lock();
-                    let mut has_errors = false;
+                    let mut reports = Vec::new();
                     for (_, problem) in pstore.deduplicated_into_iter() {
-                        let mut severity = problem.severity();
-                        if self.args.command.is_some() && severity == Severity::Warning {
+                        let mut severity = self.args.ci_severity(problem);
+                        if !self.args.is_full_build() && severity < Severity::Error {
                             // When running for example `cackle test`, not everything will be
                             // analysed, so unused warnings are expected. As such, we suppress all
                             // warnings.
                             continue;
                         }
-                        if self.args.fail_on_warnings {
+                        let is_lint_run =
+                            matches!(self.args.command, Some(crate::cli::Command::LintConfig(..)));
+                        if self.args.fail_on_warnings
+                            || (is_lint_run && lint_config::is_lint(problem))
+                        {
                             severity = Severity::Error
                         };
-                        match severity {
-                            Severity::Warning => {
-                                println!("{} {problem:#}", "WARNING:".yellow())
-                            }
-                            Severity::Error => {
-                                if !has_errors {
-                                    has_errors = true;
-                                    // Kill cargo process then wait a bit for any terminal output to
-                                    // settle before we start reporting errors.
-                                    let _ = self.abort_sender.send(());
-                                    std::thread::sleep(std::time::Duration::from_millis(20));
-                                    println!();
+                        reports.push((severity, problem));
+                    }
+                    let has_errors = reports
+                        .iter()
+                        .any(|(severity, _)| *severity == Severity::Error);
+                    if has_errors {
+                        // Kill cargo process then wait a bit for any terminal output to settle
+                        // before we start reporting errors.
+                        let _ = self.abort_sender.send(());
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                        if !self.args.writes_report_to_stdout() {
+                            println!();
+                        }
+                    }
+                    let reported: Vec<_> = if self.args.output_format != OutputFormat::Text
+                        || matches!(self.args.command, Some(crate::cli::Command::Lsp))
+                    {
+                        reports
+                            .iter()
+                            .map(|(severity, problem)| (*severity, (*problem).clone()))
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                    if self.args.writes_report_to_stdout() {
+                        // Problems will be written in the requested format once we're done.
+                    } else if self.args.ci {
+                        crate::ci::print_grouped(&reports);
+                    } else {
+                        for (severity, problem) in reports {
+                            match severity {
+                                Severity::Info => println!("{} {problem:#}", "INFO:".cyan()),
+                                Severity::Warning => {
+                                    println!("{} {problem:#}", "WARNING:".yellow())
                                 }
-                                println!("{} {problem:#}", "ERROR:".red())
+                                Severity::Error => println!("{} {problem:#}", "ERROR:".red()),
                             }
                         }
                     }
+                    pstore.reported.extend(reported);
                     if has_errors {
                         pstore.abort();
                     } else {
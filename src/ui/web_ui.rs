@@ -0,0 +1,461 @@
+//! A UI served over HTTP, for reviewing problems from a browser. Useful when building on a remote
+//! machine, since the port can be forwarded over SSH. Only listens on localhost.
+
+use crate::checker::Checker;
+use crate::config_editor;
+use crate::events::AppEvent;
+use crate::problem_store::ProblemStoreRef;
+use anyhow::Context;
+use anyhow::Result;
+use log::info;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(clap::Parser, Debug, Clone)]
+pub(crate) struct ServeOptions {
+    /// The port to listen on.
+    #[clap(long, default_value = "8080")]
+    port: u16,
+}
+
+/// How long to wait for events between checks for new connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Requests larger than this are rejected. We only ever expect small requests from our own page.
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+/// A header that must be present on requests that change anything. Browsers won't let other sites
+/// send it without our permission, which we never give.
+const ACTION_HEADER: &str = "x-cackle-action";
+
+pub(crate) struct WebUi {
+    config_path: PathBuf,
+    checker: Arc<Mutex<Checker>>,
+    port: u16,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    Page,
+    Problems,
+    Fix { id: usize, fix: usize },
+    Reject { id: usize },
+    Quit,
+    NotFound,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    host: Option<String>,
+    has_action_header: bool,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl super::UserInterface for WebUi {
+    fn run(
+        &mut self,
+        problem_store: ProblemStoreRef,
+        event_receiver: Receiver<AppEvent>,
+    ) -> Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", self.port))
+            .with_context(|| format!("Failed to listen on port {}", self.port))?;
+        listener.set_nonblocking(true)?;
+        println!("Serving UI at http://{}/", listener.local_addr()?);
+        loop {
+            match event_receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(AppEvent::Shutdown) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            }
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(error) = self.handle_connection(stream, &problem_store) {
+                            info!("Web UI request failed: {error:#}");
+                        }
+                    }
+                    Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(error) => return Err(error).context("Failed to accept connection"),
+                }
+            }
+        }
+    }
+}
+
+impl WebUi {
+    pub(crate) fn new(
+        config_path: PathBuf,
+        checker: &Arc<Mutex<Checker>>,
+        options: &ServeOptions,
+    ) -> Self {
+        Self {
+            config_path,
+            checker: checker.clone(),
+            port: options.port,
+        }
+    }
+
+    fn handle_connection(
+        &self,
+        mut stream: TcpStream,
+        problem_store: &ProblemStoreRef,
+    ) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let response = match read_request(&mut stream)? {
+            Some(request) => self.respond(&request, problem_store),
+            None => Response::error("400 Bad Request", "Malformed request"),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\n\
+             Connection: close\r\n\r\n",
+            response.status,
+            response.content_type,
+            response.body.len()
+        )?;
+        stream.write_all(response.body.as_bytes())?;
+        Ok(())
+    }
+
+    fn respond(&self, request: &Request, problem_store: &ProblemStoreRef) -> Response {
+        // Checking the host prevents other sites from reading problems via DNS rebinding.
+        if !request.host.as_deref().is_some_and(is_local_host) {
+            return Response::error("403 Forbidden", "Host not permitted");
+        }
+        let route = route(&request.method, &request.path);
+        if matches!(
+            route,
+            Route::Fix { .. } | Route::Reject { .. } | Route::Quit
+        ) && !request.has_action_header
+        {
+            return Response::error("403 Forbidden", "Missing action header");
+        }
+        let result = match route {
+            Route::Page => Ok(Response {
+                status: "200 OK",
+                content_type: "text/html; charset=utf-8",
+                body: PAGE.to_owned(),
+            }),
+            Route::Problems => Ok(self.problems_json(problem_store)),
+            Route::Fix { id, fix } => self.apply_fix(problem_store, id, fix),
+            Route::Reject { id } => {
+                let mut pstore = problem_store.lock();
+                let id = pstore
+                    .deduplicated_into_iter()
+                    .find(|(i, _)| i.index() == id)
+                    .map(|(id, _)| id);
+                match id {
+                    Some(id) => {
+                        pstore.reject(id);
+                        Ok(Response::ok())
+                    }
+                    None => Ok(Response::error("404 Not Found", "No such problem")),
+                }
+            }
+            Route::Quit => {
+                problem_store.lock().abort();
+                Ok(Response::ok())
+            }
+            Route::NotFound => Ok(Response::error("404 Not Found", "Not found")),
+        };
+        result.unwrap_or_else(|error| {
+            Response::error("500 Internal Server Error", &format!("{error:#}"))
+        })
+    }
+
+    fn problems_json(&self, problem_store: &ProblemStoreRef) -> Response {
+        let config = self.checker.lock().unwrap().config.clone();
+        let pstore = problem_store.lock();
+        let problems: Vec<serde_json::Value> = pstore
+            .deduplicated_into_iter()
+            .map(|(id, problem)| {
+                let fixes: Vec<String> = config_editor::fixes_for_problem(problem, &config)
+                    .iter()
+                    .map(|fix| fix.title())
+                    .collect();
+                serde_json::json!({
+                    "id": id.index(),
+                    "title": problem.to_string(),
+                    "details": format!("{problem:#}"),
+                    "fixes": fixes,
+                })
+            })
+            .collect();
+        Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: serde_json::Value::Array(problems).to_string(),
+        }
+    }
+
+    /// Applies fix number `fix` to problem `id` in the same way as the terminal UIs.
+    fn apply_fix(
+        &self,
+        problem_store: &ProblemStoreRef,
+        id: usize,
+        fix: usize,
+    ) -> Result<Response> {
+        let config = self.checker.lock().unwrap().config.clone();
+        let mut pstore = problem_store.lock();
+        let Some((id, problem)) = pstore
+            .deduplicated_into_iter()
+            .find(|(i, _)| i.index() == id)
+        else {
+            return Ok(Response::error("404 Not Found", "No such problem"));
+        };
+        let Some(edit) = config_editor::fixes_for_problem(problem, &config)
+            .into_iter()
+            .nth(fix)
+        else {
+            return Ok(Response::error("404 Not Found", "No such fix"));
+        };
+        super::apply_fix(
+            &self.config_path,
+            &config,
+            &mut pstore,
+            id,
+            edit.as_ref(),
+            &Default::default(),
+        )?;
+        Ok(Response::ok())
+    }
+}
+
+impl Response {
+    fn ok() -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "text/plain; charset=utf-8",
+            body: String::new(),
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: message.to_owned(),
+        }
+    }
+}
+
+/// Reads the request line and headers. Any body is ignored, since none of our routes need one.
+/// Returns None if the request couldn't be parsed.
+fn read_request(stream: &mut TcpStream) -> Result<Option<Request>> {
+    let mut data = Vec::new();
+    let mut buffer = [0; 1024];
+    while !data.windows(4).any(|w| w == b"\r\n\r\n") {
+        let bytes_read = stream.read(&mut buffer)?;
+        if bytes_read == 0 || data.len() > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        data.extend_from_slice(&buffer[..bytes_read]);
+    }
+    Ok(parse_request(&String::from_utf8_lossy(&data)))
+}
+
+fn parse_request(text: &str) -> Option<Request> {
+    let mut lines = text.split("\r\n");
+    let mut parts = lines.next()?.split(' ');
+    let method = parts.next()?.to_owned();
+    let path = parts.next()?.to_owned();
+    let mut request = Request {
+        method,
+        path,
+        host: None,
+        has_action_header: false,
+    };
+    for line in lines.take_while(|line| !line.is_empty()) {
+        let (name, value) = line.split_once(':')?;
+        let name = name.trim().to_ascii_lowercase();
+        if name == "host" {
+            request.host = Some(value.trim().to_owned());
+        } else if name == ACTION_HEADER {
+            request.has_action_header = true;
+        }
+    }
+    Some(request)
+}
+
+fn route(method: &str, path: &str) -> Route {
+    let path = path.split('?').next().unwrap_or_default();
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    match (method, parts.as_slice()) {
+        ("GET", []) => Route::Page,
+        ("GET", ["problems"]) => Route::Problems,
+        ("POST", ["problems", id, "fixes", fix]) => match (id.parse(), fix.parse()) {
+            (Ok(id), Ok(fix)) => Route::Fix { id, fix },
+            _ => Route::NotFound,
+        },
+        ("POST", ["problems", id, "reject"]) => match id.parse() {
+            Ok(id) => Route::Reject { id },
+            _ => Route::NotFound,
+        },
+        ("POST", ["quit"]) => Route::Quit,
+        _ => Route::NotFound,
+    }
+}
+
+/// Returns whether `host`, the value of a Host header, refers to this machine. The port isn't
+/// checked, since when forwarding over SSH, the local port may differ from the one we listen on.
+fn is_local_host(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    matches!(name, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Cackle</title>
+<style>
+body { font-family: sans-serif; margin: 0; display: flex; height: 100vh; }
+#list { width: 35%; overflow-y: auto; border-right: 1px solid #ccc; margin: 0; padding: 0; }
+#list li { list-style: none; padding: 0.4em 0.8em; cursor: pointer; }
+#list li.selected { background: #def; }
+#details { flex: 1; overflow-y: auto; padding: 0 1em; }
+pre { background: #f6f6f6; padding: 0.5em; white-space: pre-wrap; }
+button { margin: 0.2em 0.4em 0.2em 0; }
+button.reject { color: #a00; }
+#status { color: #666; }
+</style>
+</head>
+<body>
+<ul id="list"></ul>
+<div id="details">
+<h2>Cackle</h2>
+<p id="status">Loading problems...</p>
+<div id="problem"></div>
+<p><button id="quit">Quit</button></p>
+</div>
+<script>
+let problems = [];
+let selected = null;
+
+async function post(path) {
+  const response = await fetch(path, { method: "POST", headers: { "X-Cackle-Action": "1" } });
+  if (!response.ok) {
+    alert(await response.text());
+  }
+  refresh();
+}
+
+function render() {
+  const list = document.getElementById("list");
+  list.replaceChildren();
+  if (!problems.some(p => p.id === selected)) {
+    selected = problems.length > 0 ? problems[0].id : null;
+  }
+  for (const problem of problems) {
+    const item = document.createElement("li");
+    item.textContent = problem.title;
+    if (problem.id === selected) {
+      item.className = "selected";
+    }
+    item.onclick = () => { selected = problem.id; render(); };
+    list.appendChild(item);
+  }
+  const container = document.getElementById("problem");
+  container.replaceChildren();
+  const problem = problems.find(p => p.id === selected);
+  if (!problem) {
+    return;
+  }
+  const heading = document.createElement("h3");
+  heading.textContent = problem.title;
+  const details = document.createElement("pre");
+  details.textContent = problem.details;
+  container.append(heading, details);
+  problem.fixes.forEach((fix, index) => {
+    const button = document.createElement("button");
+    button.textContent = fix;
+    button.onclick = () => post(`/problems/${problem.id}/fixes/${index}`);
+    container.append(button, document.createElement("br"));
+  });
+  const reject = document.createElement("button");
+  reject.className = "reject";
+  reject.textContent = "Reject (fails the build)";
+  reject.onclick = () => post(`/problems/${problem.id}/reject`);
+  container.appendChild(reject);
+}
+
+async function refresh() {
+  const status = document.getElementById("status");
+  try {
+    const response = await fetch("/problems");
+    problems = await response.json();
+    status.textContent = problems.length === 0
+      ? "No problems to review. Waiting for the build..."
+      : `${problems.length} problem(s) to review`;
+  } catch (error) {
+    problems = [];
+    status.textContent = "Disconnected. The build may have finished.";
+  }
+  render();
+}
+
+document.getElementById("quit").onclick = () => post("/quit");
+refresh();
+setInterval(refresh, 2000);
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::is_local_host;
+    use super::parse_request;
+    use super::route;
+    use super::Route;
+
+    #[test]
+    fn routes() {
+        assert_eq!(route("GET", "/"), Route::Page);
+        assert_eq!(route("GET", "/problems?t=1"), Route::Problems);
+        assert_eq!(
+            route("POST", "/problems/3/fixes/1"),
+            Route::Fix { id: 3, fix: 1 }
+        );
+        assert_eq!(route("POST", "/problems/3/reject"), Route::Reject { id: 3 });
+        assert_eq!(route("GET", "/problems/3/reject"), Route::NotFound);
+        assert_eq!(route("POST", "/problems/x/fixes/1"), Route::NotFound);
+        assert_eq!(route("POST", "/quit"), Route::Quit);
+    }
+
+    #[test]
+    fn requests() {
+        let request = parse_request(
+            "POST /quit HTTP/1.1\r\nHost: localhost:9000\r\nX-Cackle-Action: 1\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/quit");
+        assert_eq!(request.host.as_deref(), Some("localhost:9000"));
+        assert!(request.has_action_header);
+        assert!(parse_request("GET\r\n\r\n").is_none());
+
+        assert!(is_local_host("localhost:9000"));
+        assert!(is_local_host("127.0.0.1"));
+        assert!(is_local_host("[::1]:8080"));
+        assert!(!is_local_host("evil.example.com:8080"));
+        assert!(!is_local_host("localhost.evil.example.com"));
+    }
+}
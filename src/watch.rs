@@ -0,0 +1,211 @@
+//! Support for the `watch` subcommand, which keeps the checker running and rebuilds whenever files
+//! in the workspace change. On Linux, changes are detected with inotify. Elsewhere, we poll for
+//! changes to modification times.
+
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+mod inotify;
+
+#[derive(Parser, Debug, Clone, Default)]
+pub(crate) struct WatchOptions {
+    /// How long to wait after a change for further changes before rebuilding, in milliseconds.
+    /// Avoids rebuilding multiple times when several files are saved at once.
+    #[clap(long, default_value = "200")]
+    debounce_ms: u64,
+}
+
+impl WatchOptions {
+    pub(crate) fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+}
+
+/// Watches a directory tree for changes to files.
+pub(crate) struct Watcher {
+    excluded: Vec<PathBuf>,
+    #[cfg(target_os = "linux")]
+    backend: inotify::Inotify,
+    #[cfg(not(target_os = "linux"))]
+    backend: poll::Poller,
+}
+
+impl Watcher {
+    /// Starts watching `root`, ignoring changes within any of `excluded`, such as the target
+    /// directory, since otherwise we'd keep triggering ourselves.
+    pub(crate) fn new(root: &Path, excluded: Vec<PathBuf>) -> Result<Self> {
+        let is_excluded = |path: &Path| is_excluded(&excluded, path);
+        #[cfg(target_os = "linux")]
+        let backend = inotify::Inotify::new(root, &is_excluded)
+            .with_context(|| format!("Failed to watch `{}`", root.display()))?;
+        #[cfg(not(target_os = "linux"))]
+        let backend = poll::Poller::new(root, &is_excluded)
+            .with_context(|| format!("Failed to watch `{}`", root.display()))?;
+        Ok(Self { excluded, backend })
+    }
+
+    /// Blocks until at least one file changes, then continues to wait until no further changes
+    /// have been seen for `debounce`. Returns the paths that changed, sorted and deduplicated.
+    pub(crate) fn wait_for_changes(&mut self, debounce: Duration) -> Result<Vec<PathBuf>> {
+        let mut changed = Vec::new();
+        while changed.is_empty() {
+            changed = self.changes(None)?;
+        }
+        loop {
+            let more = self.changes(Some(debounce))?;
+            if more.is_empty() {
+                break;
+            }
+            changed.extend(more);
+        }
+        changed.sort();
+        changed.dedup();
+        Ok(changed)
+    }
+
+    /// Returns paths that changed, waiting for up to `timeout`, or indefinitely if `timeout` is
+    /// `None`. May return an empty list even without a timeout if only excluded paths changed.
+    fn changes(&mut self, timeout: Option<Duration>) -> Result<Vec<PathBuf>> {
+        let excluded = &self.excluded;
+        let is_excluded = |path: &Path| is_excluded(excluded, path);
+        let mut changed = self.backend.changes(timeout, &is_excluded)?;
+        changed.retain(|path| !is_excluded(path));
+        Ok(changed)
+    }
+}
+
+fn is_excluded(excluded: &[PathBuf], path: &Path) -> bool {
+    excluded.iter().any(|dir| path.starts_with(dir))
+}
+
+/// Returns all directories within `root`, including `root` itself, that aren't excluded.
+fn directories(root: &Path, is_excluded: &dyn Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let mut pending = vec![root.to_owned()];
+    while let Some(dir) = pending.pop() {
+        if is_excluded(&dir) {
+            continue;
+        }
+        // Directories may be deleted while we're walking them, so we ignore errors.
+        if let Ok(entries) = dir.read_dir() {
+            for entry in entries.flatten() {
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    pending.push(entry.path());
+                }
+            }
+        }
+        dirs.push(dir);
+    }
+    dirs
+}
+
+#[cfg(not(target_os = "linux"))]
+mod poll {
+    use anyhow::Result;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use std::time::Instant;
+    use std::time::SystemTime;
+
+    /// How often we check for changes.
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub(super) struct Poller {
+        root: PathBuf,
+        modified: BTreeMap<PathBuf, Option<SystemTime>>,
+    }
+
+    impl Poller {
+        pub(super) fn new(root: &Path, is_excluded: &dyn Fn(&Path) -> bool) -> Result<Self> {
+            Ok(Self {
+                root: root.to_owned(),
+                modified: snapshot(root, is_excluded),
+            })
+        }
+
+        pub(super) fn changes(
+            &mut self,
+            timeout: Option<Duration>,
+            is_excluded: &dyn Fn(&Path) -> bool,
+        ) -> Result<Vec<PathBuf>> {
+            let start = Instant::now();
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let modified = snapshot(&self.root, is_excluded);
+                let mut changed: Vec<PathBuf> = modified
+                    .iter()
+                    .filter(|(path, time)| self.modified.get(*path) != Some(*time))
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                changed.extend(
+                    self.modified
+                        .keys()
+                        .filter(|path| !modified.contains_key(*path))
+                        .cloned(),
+                );
+                self.modified = modified;
+                if !changed.is_empty() || timeout.is_some_and(|timeout| start.elapsed() >= timeout)
+                {
+                    return Ok(changed);
+                }
+            }
+        }
+    }
+
+    fn snapshot(
+        root: &Path,
+        is_excluded: &dyn Fn(&Path) -> bool,
+    ) -> BTreeMap<PathBuf, Option<SystemTime>> {
+        let mut modified = BTreeMap::new();
+        for dir in super::directories(root, is_excluded) {
+            let Ok(entries) = dir.read_dir() else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let metadata = entry.metadata().ok();
+                if metadata.as_ref().is_some_and(|metadata| metadata.is_dir()) {
+                    continue;
+                }
+                let time = metadata.and_then(|metadata| metadata.modified().ok());
+                modified.insert(entry.path(), time);
+            }
+        }
+        modified
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Watcher;
+    use std::time::Duration;
+
+    #[test]
+    fn detects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        let mut watcher = Watcher::new(root, vec![root.join("target")]).unwrap();
+
+        // Changes in excluded directories shouldn't be reported, but should also not stop us from
+        // seeing later changes.
+        std::fs::write(root.join("target/out"), "x").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "fn foo() {}").unwrap();
+        let changed = watcher.wait_for_changes(Duration::from_millis(50)).unwrap();
+        assert_eq!(changed, vec![root.join("src/lib.rs")]);
+
+        // Files in directories created after we started watching should be seen too.
+        std::fs::create_dir_all(root.join("src/new")).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        std::fs::write(root.join("src/new/mod.rs"), "").unwrap();
+        let changed = watcher.wait_for_changes(Duration::from_millis(50)).unwrap();
+        assert!(changed.contains(&root.join("src/new/mod.rs")));
+    }
+}
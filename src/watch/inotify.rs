@@ -0,0 +1,147 @@
+//! Detection of file changes via inotify.
+
+// We need unsafe in order to use inotify via libc.
+#![allow(unsafe_code)]
+
+use anyhow::bail;
+use anyhow::Result;
+use fxhash::FxHashMap;
+use std::ffi::OsStr;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The events that we're interested in. Modifications are picked up when the file is closed, so
+/// that we don't see a separate event for each write.
+const MASK: u32 = libc::IN_CLOSE_WRITE
+    | libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO;
+
+pub(super) struct Inotify {
+    fd: OwnedFd,
+    /// The directory watched by each watch descriptor.
+    dirs: FxHashMap<i32, PathBuf>,
+    root: PathBuf,
+}
+
+impl Inotify {
+    pub(super) fn new(root: &Path, is_excluded: &dyn Fn(&Path) -> bool) -> Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            bail!(std::io::Error::last_os_error());
+        }
+        let mut inotify = Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            dirs: FxHashMap::default(),
+            root: root.to_owned(),
+        };
+        inotify.add_tree(root, is_excluded)?;
+        Ok(inotify)
+    }
+
+    /// Adds watches for `dir` and all directories within it. Returns the paths of everything found
+    /// within those directories, since if `dir` was only just created, they may have been written
+    /// before we started watching.
+    fn add_tree(
+        &mut self,
+        dir: &Path,
+        is_excluded: &dyn Fn(&Path) -> bool,
+    ) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for subdir in super::directories(dir, is_excluded) {
+            let c_path = std::ffi::CString::new(subdir.as_os_str().as_bytes())?;
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), c_path.as_ptr(), MASK) };
+            if wd < 0 {
+                let error = std::io::Error::last_os_error();
+                // The directory may have been deleted before we got to it.
+                if error.kind() == std::io::ErrorKind::NotFound {
+                    continue;
+                }
+                bail!("Failed to watch `{}`: {error}", subdir.display());
+            }
+            if let Ok(entries) = subdir.read_dir() {
+                files.extend(entries.flatten().map(|entry| entry.path()));
+            }
+            self.dirs.insert(wd, subdir);
+        }
+        Ok(files)
+    }
+
+    pub(super) fn changes(
+        &mut self,
+        timeout: Option<Duration>,
+        is_excluded: &dyn Fn(&Path) -> bool,
+    ) -> Result<Vec<PathBuf>> {
+        let timeout_ms = timeout.map_or(-1, |timeout| timeout.as_millis() as i32);
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+        if ready < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+            bail!(error);
+        }
+        if ready == 0 {
+            return Ok(Vec::new());
+        }
+        let mut buffer = vec![0u8; 64 * 1024];
+        let size = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+            )
+        };
+        if size < 0 {
+            bail!(std::io::Error::last_os_error());
+        }
+        let mut changed = Vec::new();
+        let mut new_dirs = Vec::new();
+        let header_size = std::mem::size_of::<libc::inotify_event>();
+        let mut offset = 0;
+        while offset + header_size <= size as usize {
+            let event: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr().cast()) };
+            let name_start = offset + header_size;
+            let name = &buffer[name_start..name_start + event.len as usize];
+            offset = name_start + event.len as usize;
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                // We missed some events, so we don't know what changed.
+                changed.push(self.root.clone());
+                continue;
+            }
+            if event.mask & libc::IN_IGNORED != 0 {
+                // The directory was deleted, so the kernel removed its watch.
+                self.dirs.remove(&event.wd);
+                continue;
+            }
+            let Some(dir) = self.dirs.get(&event.wd) else {
+                continue;
+            };
+            // Names are padded with nul bytes.
+            let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            let path = dir.join(OsStr::from_bytes(&name[..name_len]));
+            if event.mask & libc::IN_ISDIR != 0
+                && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0
+            {
+                new_dirs.push(path.clone());
+            }
+            changed.push(path);
+        }
+        for dir in new_dirs {
+            changed.extend(self.add_tree(&dir, is_excluded)?);
+        }
+        Ok(changed)
+    }
+}